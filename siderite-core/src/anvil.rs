@@ -0,0 +1,265 @@
+//! Anvils rename items and repair them with materials or a second item of the same kind, for experience levels.
+//! Enchantments of the second item aren't merged and anvils don't wear down.
+
+use siderite_nbt::Tag;
+
+use crate::items::{ItemStack, ItemType};
+use crate::window::WindowPropertySink;
+
+/// Slot of the item being renamed or repaired
+pub const INPUT_SLOT: usize = 0;
+
+/// Slot of the material or second item used for the repair
+pub const MATERIAL_SLOT: usize = 1;
+
+/// Slot showing the result, taking it uses up the other slots
+pub const OUTPUT_SLOT: usize = 2;
+
+/// Number of slots in an anvil
+pub const ANVIL_SIZE: usize = 3;
+
+/// Longest name an item can be given
+pub const MAX_NAME_LENGTH: usize = 30;
+
+/// Outside of creative anything costing this many levels is too expensive
+pub const MAX_COST: i32 = 40;
+
+/// The name typed into the anvil.
+/// When an item is put in the client sends the name it already has, only names differing from that one rename it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnvilName {
+    /// First name sent after the item was put in
+    shown: Option<String>,
+    typed: Option<String>
+}
+
+impl AnvilName {
+    pub fn set(&mut self, name: String) {
+        self.shown.get_or_insert_with(|| name.clone());
+        self.typed = Some(name);
+    }
+
+    /// Forgets the names, the next one sent is the name of a new item
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Returns the name the item gets, Some(None) removes its name and None keeps it
+    pub fn rename(&self) -> Option<Option<String>> {
+        let typed = self.typed.as_ref()?;
+        if typed.is_empty() {
+            Some(None)
+        } else if Some(typed) != self.shown.as_ref() {
+            Some(Some(typed.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The item an anvil makes out of the items in it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnvilResult {
+    pub output: Option<ItemStack>,
+    /// Experience levels taking the output costs
+    pub cost: i32,
+    /// Number of materials used up, 0 uses up the whole material slot
+    pub material_used: i8
+}
+
+impl AnvilResult {
+    /// Works out the result like vanilla does, the cost grows with the work done on the items before
+    pub fn new(input: Option<&ItemStack>, material: Option<&ItemStack>, rename: Option<Option<String>>, creative: bool) -> Self {
+        let input = match input {
+            Some(v) => v,
+            None => return Self::default()
+        };
+
+        let mut output = input.clone();
+        let mut cost = 0;
+        let mut material_used = 0;
+        if let Some(material) = material {
+            let max_damage = match input.item_type().and_then(ItemType::max_damage) {
+                Some(v) => v,
+                None => return Self::default()
+            };
+
+            if material.item_type().is_some() && material.item_type() == input.item_type().and_then(repair_material) {
+                // Every material repairs a quarter of the durability
+                let mut repaired = output.damage.min(max_damage / 4);
+                if repaired <= 0 {
+                    return Self::default();
+                }
+
+                while repaired > 0 && material_used < material.count {
+                    output.damage -= repaired;
+                    cost += 1;
+                    material_used += 1;
+                    repaired = output.damage.min(max_damage / 4);
+                }
+            } else if material.id == input.id {
+                // Worked out in i32, the damage values come from the client
+                let max_damage = i32::from(max_damage);
+                let durability = (max_damage - i32::from(input.damage)) + (max_damage - i32::from(material.damage)) + max_damage * 12 / 100;
+                let damage = (max_damage - durability).max(0);
+                if damage >= i32::from(input.damage) {
+                    return Self::default();
+                }
+
+                output.damage = damage as i16;
+                cost += 2;
+            } else {
+                return Self::default();
+            }
+        }
+
+        let mut rename_cost = 0;
+        match rename {
+            Some(None) if input.custom_name().is_some() => {
+                rename_cost = 1;
+                output.set_custom_name(None);
+            }
+            Some(Some(name)) if Some(name.as_str()) != input.custom_name() => {
+                rename_cost = 1;
+                output.set_custom_name(Some(name));
+            }
+            _ => ()
+        }
+        cost += rename_cost;
+        if cost == 0 {
+            return Self::default();
+        }
+
+        let mut total = cost.saturating_add(repair_cost(input)).saturating_add(material.map_or(0, repair_cost));
+        // Renaming alone is never too expensive
+        if rename_cost == cost && total >= MAX_COST {
+            total = MAX_COST - 1;
+        }
+        if total >= MAX_COST && !creative {
+            return Self { output: None, cost: total, material_used: 0 };
+        }
+
+        let prior_work = repair_cost(&output).max(material.map_or(0, repair_cost));
+        set_repair_cost(&mut output, prior_work.saturating_mul(2).saturating_add(1));
+        Self { output: Some(output), cost: total, material_used }
+    }
+}
+
+/// The level cost shown in the anvil window
+impl WindowPropertySink for AnvilResult {
+    fn window_properties(&self) -> Vec<(i16, i16)> {
+        vec![(0, self.cost as i16)]
+    }
+}
+
+/// Returns the material that repairs the item
+pub fn repair_material(item_type: ItemType) -> Option<ItemType> {
+    match item_type {
+        ItemType::IronShovel | ItemType::IronPickaxe | ItemType::IronAxe | ItemType::IronSword
+            | ItemType::IronHelmet | ItemType::IronChestplate | ItemType::IronLeggings | ItemType::IronBoots => Some(ItemType::IronIngot),
+        ItemType::DiamondShovel | ItemType::DiamondPickaxe | ItemType::DiamondAxe | ItemType::DiamondSword
+            | ItemType::DiamondHelmet | ItemType::DiamondChestplate | ItemType::DiamondLeggings | ItemType::DiamondBoots => Some(ItemType::Diamond),
+        _ => None
+    }
+}
+
+/// Returns the extra levels working on the item costs, it doubles every time the item goes through an anvil.
+/// Values set by the client are clamped, so doubling them can't overflow.
+pub fn repair_cost(item: &ItemStack) -> i32 {
+    item.nbt.as_ref().and_then(|v| v.get("RepairCost")).and_then(Tag::as_int).map_or(0, |v| v.clamp(0, i32::MAX / 2))
+}
+
+fn set_repair_cost(item: &mut ItemStack, cost: i32) {
+    item.nbt.get_or_insert_with(Default::default).insert("RepairCost", Tag::Int(cost));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(shown: &str, typed: &str) -> Option<Option<String>> {
+        let mut name = AnvilName::default();
+        name.set(shown.to_owned());
+        name.set(typed.to_owned());
+        name.rename()
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(AnvilName::default().rename(), None);
+        assert_eq!(typed("Iron Sword", "Iron Sword"), None);
+        assert_eq!(typed("Iron Sword", "Stabby"), Some(Some("Stabby".to_owned())));
+        assert_eq!(typed("Iron Sword", ""), Some(None));
+    }
+
+    #[test]
+    fn rename_cost() {
+        let sword = ItemStack::of(ItemType::IronSword, 1);
+        assert_eq!(AnvilResult::new(Some(&sword), None, None, false), AnvilResult::default());
+        // Removing a name the item doesn't have does nothing
+        assert_eq!(AnvilResult::new(Some(&sword), None, Some(None), false), AnvilResult::default());
+
+        let result = AnvilResult::new(Some(&sword), None, Some(Some("Stabby".to_owned())), false);
+        assert_eq!(result.cost, 1);
+        let named = result.output.unwrap();
+        assert_eq!(named.custom_name(), Some("Stabby"));
+        assert_eq!(repair_cost(&named), 1);
+
+        // Every rename costs more, but renaming alone is never too expensive
+        let result = AnvilResult::new(Some(&named), None, Some(Some("Pointy".to_owned())), false);
+        assert_eq!(result.cost, 2);
+        assert_eq!(repair_cost(result.output.as_ref().unwrap()), 3);
+        let mut worn = named.clone();
+        set_repair_cost(&mut worn, 63);
+        let result = AnvilResult::new(Some(&worn), None, Some(None), false);
+        assert_eq!(result.cost, MAX_COST - 1);
+        assert_eq!(result.output.unwrap().nbt.unwrap().get("display"), None);
+    }
+
+    #[test]
+    fn repair() {
+        let damaged = ItemStack::new(ItemType::IronPickaxe as i16, 1, 200);
+        let result = AnvilResult::new(Some(&damaged), Some(&ItemStack::of(ItemType::IronIngot, 5)), None, false);
+        // Each ingot repairs 62 uses, the fourth one fixes the rest
+        assert_eq!(result.output.as_ref().unwrap().damage, 0);
+        assert_eq!((result.cost, result.material_used), (4, 4));
+
+        let other = ItemStack::new(ItemType::IronPickaxe as i16, 1, 150);
+        let result = AnvilResult::new(Some(&damaged), Some(&other), None, false);
+        // The uses left of both add up, plus 12% of the durability
+        assert_eq!(result.output.as_ref().unwrap().damage, 70);
+        assert_eq!(result.cost, 2);
+
+        // Undamaged items and the wrong material can't be repaired
+        let ingots = ItemStack::of(ItemType::IronIngot, 5);
+        assert_eq!(AnvilResult::new(Some(&ItemStack::of(ItemType::IronPickaxe, 1)), Some(&ingots), None, false), AnvilResult::default());
+        assert_eq!(AnvilResult::new(Some(&damaged), Some(&ItemStack::of(ItemType::Diamond, 1)), None, false), AnvilResult::default());
+
+        // Too expensive outside of creative
+        let mut worn = damaged.clone();
+        set_repair_cost(&mut worn, 39);
+        let result = AnvilResult::new(Some(&worn), Some(&ingots), None, false);
+        assert_eq!((result.output, result.cost), (None, 43));
+        assert!(AnvilResult::new(Some(&worn), Some(&ingots), None, true).output.is_some());
+    }
+
+    #[test]
+    fn extreme_values() {
+        let mut worn = ItemStack::new(ItemType::IronPickaxe as i16, 1, i16::MAX);
+        set_repair_cost(&mut worn, i32::MAX);
+        let result = AnvilResult::new(Some(&worn), Some(&ItemStack::of(ItemType::IronIngot, 1)), None, true);
+        assert_eq!(result.cost, i32::MAX / 2 + 1);
+        assert_eq!(repair_cost(result.output.as_ref().unwrap()), i32::MAX);
+
+        let mut negative = worn.clone();
+        set_repair_cost(&mut negative, i32::MIN);
+        assert_eq!(repair_cost(&negative), 0);
+
+        // Damage values the client made up can't make the combined item overflow
+        let other = ItemStack::new(ItemType::IronPickaxe as i16, 1, i16::MIN);
+        let result = AnvilResult::new(Some(&worn), Some(&other), None, true);
+        assert_eq!(result.output.unwrap().damage, 0);
+        let result = AnvilResult::new(Some(&other), Some(&worn), None, true);
+        assert_eq!(result, AnvilResult::default());
+    }
+}
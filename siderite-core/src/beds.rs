@@ -0,0 +1,83 @@
+//! Beds let players sleep through the night, sleeping in one also sets where the player respawns
+
+use crate::coord::Coord;
+use crate::storage::world::{DAY_LENGTH, World};
+
+/// Meta bit of the head half of a bed, the lower two bits are the direction the bed points to
+pub const HEAD: u8 = 0x8;
+
+/// Meta bit set on both halves of a bed while a player is sleeping in it
+pub const OCCUPIED: u8 = 0x4;
+
+/// Ticks a player has to lie in bed before it counts as asleep
+pub const FULLY_ASLEEP_TICKS: i32 = 100;
+
+/// First time of day players can go to sleep at
+const NIGHT_START: i64 = 12541;
+
+/// Time of day the night ends at, players in bed wake up
+const NIGHT_END: i64 = 23458;
+
+/// Returns the direction the bed points to, from the foot to the head, as x and z offset
+fn direction(meta: u8) -> (i32, i32) {
+    // South, west, north, east
+    match meta & 0x3 {
+        0 => (0, 1),
+        1 => (-1, 0),
+        2 => (0, -1),
+        _ => (1, 0)
+    }
+}
+
+/// Returns the position of the head half of the bed, sleeping players lie with their head there
+pub fn head_pos(pos: Coord<i32>, meta: u8) -> Coord<i32> {
+    if meta & HEAD != 0 {
+        pos
+    } else {
+        other_half(pos, meta)
+    }
+}
+
+/// Returns the position of the other half of the bed
+pub fn other_half(pos: Coord<i32>, meta: u8) -> Coord<i32> {
+    let (x, z) = direction(meta);
+    if meta & HEAD != 0 {
+        Coord::new(pos.x - x, pos.y, pos.z - z)
+    } else {
+        Coord::new(pos.x + x, pos.y, pos.z + z)
+    }
+}
+
+/// Returns true if players can sleep, at night or during a thunderstorm like in vanilla
+pub fn can_sleep(world: &World) -> bool {
+    let time = world.time_of_day().rem_euclid(DAY_LENGTH);
+    (NIGHT_START..NIGHT_END).contains(&time) || world.weather().is_thundering()
+}
+
+/// Returns the time of day of the next morning
+pub fn next_morning(time_of_day: i64) -> i64 {
+    let time = time_of_day + DAY_LENGTH;
+    time - time.rem_euclid(DAY_LENGTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halves() {
+        let foot = Coord::new(4, 16, 4);
+        for (meta, head) in [(0, Coord::new(4, 16, 5)), (1, Coord::new(3, 16, 4)), (2, Coord::new(4, 16, 3)), (3, Coord::new(5, 16, 4))] {
+            assert_eq!(head_pos(foot, meta), head);
+            assert_eq!(head_pos(head, meta | HEAD | OCCUPIED), head);
+            assert_eq!(other_half(foot, meta), head);
+            assert_eq!(other_half(head, meta | HEAD), foot);
+        }
+    }
+
+    #[test]
+    fn morning() {
+        assert_eq!(next_morning(13000), DAY_LENGTH);
+        assert_eq!(next_morning(3 * DAY_LENGTH + 18000), 4 * DAY_LENGTH);
+    }
+}
@@ -1,3 +1,5 @@
+use num_derive::FromPrimitive;
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
 pub enum Biome {
@@ -26,3 +28,11 @@ pub enum Biome {
     JungleHills = 22
     // TODO: add more
 }
+
+impl Biome {
+    /// Returns true if water freezes and snow covers the ground in this biome
+    pub fn is_snowy(self) -> bool {
+        matches!(self, Biome::Taiga | Biome::TaigaHills | Biome::FrozenOcean | Biome::FrozenRiver
+            | Biome::IcePlains | Biome::IceMountains)
+    }
+}
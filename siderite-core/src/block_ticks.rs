@@ -0,0 +1,316 @@
+//! Random block ticks, which make grass spread, crops grow, leaves decay and ice melt
+
+use std::collections::{HashSet, VecDeque};
+
+use rand::{Rng, RngCore};
+
+use crate::blocks::BlockType;
+use crate::coord::{ChunkCoord, Coord};
+use crate::storage::chunk::WIDTH;
+use crate::storage::game_rules;
+use crate::storage::world::{Dimension, World};
+
+/// Handles a random tick of a block and returns the blocks to change
+pub type RandomTickHandler = fn(&World, Coord<i32>, BlockType, u8, &mut dyn RngCore) -> Vec<(Coord<i32>, BlockType, u8)>;
+
+/// Grass dies when the light above it is below this and the block above is opaque
+const GRASS_DIE_LIGHT: u8 = 4;
+
+/// Light above grass and crops needed for them to spread or grow
+const GROWTH_LIGHT: u8 = 9;
+
+/// Number of blocks grass tries to spread to every random tick
+const GRASS_SPREAD_ATTEMPTS: usize = 4;
+
+/// Growth meta of fully grown crops
+const CROP_MAX_AGE: u8 = 7;
+
+/// One in this many random ticks makes a crop grow, as for a crop on dry farmland
+const CROP_GROWTH_CHANCE: u32 = 13;
+
+/// Leaves further than this from a log decay
+const LEAVES_RANGE: usize = 4;
+
+/// Meta bit set for leaves placed by players, which never decay
+const LEAVES_NO_DECAY: u8 = 0x4;
+
+/// Ice melts when the block light reaching it is above this
+const ICE_MELT_LIGHT: u8 = 11;
+
+/// Returns the handler for random ticks of the block type, None if nothing happens to it
+pub fn random_tick_handler(block_type: BlockType) -> Option<RandomTickHandler> {
+    match block_type {
+        BlockType::Grass => Some(tick_grass),
+        BlockType::Wheat | BlockType::Carrots | BlockType::Potatoes => Some(tick_crop),
+        BlockType::Leaves => Some(tick_leaves),
+        BlockType::Ice => Some(tick_ice),
+        _ => None
+    }
+}
+
+/// Picks `randomTickSpeed` random blocks in every non-empty section of the chunks
+/// and returns the changes their handlers make
+pub fn random_ticks(world: &World, chunks: impl IntoIterator<Item = ChunkCoord>, rng: &mut impl Rng) -> Vec<(Coord<i32>, BlockType, u8)> {
+    let speed = world.game_rules().get_int(game_rules::RANDOM_TICK_SPEED);
+    let mut changes = Vec::new();
+    if speed <= 0 {
+        return changes;
+    }
+
+    let chunk_map = world.chunk_map();
+    for coord in chunks {
+        let mut ticked = Vec::new();
+        chunk_map.do_with_chunk(coord, |chunk| {
+            for (i, section) in chunk.data.sections.iter().enumerate() {
+                if section.is_none() {
+                    continue;
+                }
+
+                for _ in 0..speed {
+                    let rel_pos = Coord::new(rng.gen_range(0..WIDTH), i as i32 * WIDTH + rng.gen_range(0..WIDTH), rng.gen_range(0..WIDTH));
+                    let (block_type, meta) = chunk.data.get_block_type_meta(rel_pos);
+                    if let Some(handler) = random_tick_handler(block_type) {
+                        let pos = Coord::new(coord.x * WIDTH + rel_pos.x, rel_pos.y, coord.z * WIDTH + rel_pos.z);
+                        ticked.push((pos, block_type, meta, handler));
+                    }
+                }
+            }
+        });
+
+        // The handlers look at the blocks around, so they can't run while the chunk is locked
+        for (pos, block_type, meta, handler) in ticked {
+            changes.extend(handler(world, pos, block_type, meta, rng));
+        }
+    }
+
+    changes
+}
+
+fn above(pos: Coord<i32>) -> Coord<i32> {
+    Coord::new(pos.x, pos.y + 1, pos.z)
+}
+
+/// Grass turns to dirt in the dark and spreads to dirt nearby in the light
+fn tick_grass(world: &World, pos: Coord<i32>, _: BlockType, _: u8, rng: &mut dyn RngCore) -> Vec<(Coord<i32>, BlockType, u8)> {
+    let light = world.light(above(pos));
+    if light < GRASS_DIE_LIGHT && world.get_block_state(above(pos)).0.is_opaque() {
+        return vec![(pos, BlockType::Dirt, 0)];
+    }
+
+    let mut changes = Vec::new();
+    if light < GROWTH_LIGHT {
+        return changes;
+    }
+
+    for _ in 0..GRASS_SPREAD_ATTEMPTS {
+        let target = Coord::new(pos.x + rng.gen_range(-1..=1), pos.y + rng.gen_range(-3..=1), pos.z + rng.gen_range(-1..=1));
+        if world.get_block_state(target) == (BlockType::Dirt, 0)
+            && !world.get_block_state(above(target)).0.is_opaque()
+            && world.light(above(target)) >= GRASS_DIE_LIGHT {
+            changes.push((target, BlockType::Grass, 0));
+        }
+    }
+
+    changes
+}
+
+/// Crops in the light grow one stage at a time until they're fully grown
+fn tick_crop(world: &World, pos: Coord<i32>, block_type: BlockType, meta: u8, rng: &mut dyn RngCore) -> Vec<(Coord<i32>, BlockType, u8)> {
+    if meta >= CROP_MAX_AGE || world.light(above(pos)) < GROWTH_LIGHT || rng.gen_range(0..CROP_GROWTH_CHANCE) != 0 {
+        return Vec::new();
+    }
+
+    vec![(pos, block_type, meta + 1)]
+}
+
+/// Leaves disappear when they aren't connected to a log through other leaves
+fn tick_leaves(world: &World, pos: Coord<i32>, _: BlockType, meta: u8, _: &mut dyn RngCore) -> Vec<(Coord<i32>, BlockType, u8)> {
+    if meta & LEAVES_NO_DECAY != 0 || is_near_log(world, pos) {
+        return Vec::new();
+    }
+
+    vec![(pos, BlockType::Air, 0)]
+}
+
+/// Returns true if a log can be reached from the leaves within LEAVES_RANGE steps through leaves
+fn is_near_log(world: &World, start: Coord<i32>) -> bool {
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some((pos, distance)) = queue.pop_front() {
+        if distance == LEAVES_RANGE {
+            continue;
+        }
+
+        for (dx, dy, dz) in [(1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)] {
+            let next = Coord::new(pos.x + dx, pos.y + dy, pos.z + dz);
+            if !seen.insert(next) {
+                continue;
+            }
+
+            match world.get_block_state(next).0 {
+                BlockType::Log => return true,
+                BlockType::Leaves => queue.push_back((next, distance + 1)),
+                _ => {}
+            }
+        }
+    }
+
+    false
+}
+
+/// Ice melts near light sources, in the Nether it evaporates
+fn tick_ice(world: &World, pos: Coord<i32>, _: BlockType, _: u8, _: &mut dyn RngCore) -> Vec<(Coord<i32>, BlockType, u8)> {
+    if world.block_light(pos) <= ICE_MELT_LIGHT {
+        return Vec::new();
+    }
+
+    let melted = if world.dimension() == Dimension::Nether { BlockType::Air } else { BlockType::Water };
+    vec![(pos, melted, 0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::test_utils::test_world;
+
+    /// Runs the handler of the block at the position and applies the changes
+    fn tick(world: &World, pos: Coord<i32>, rng: &mut StdRng) -> Vec<(Coord<i32>, BlockType, u8)> {
+        let (block_type, meta) = world.get_block_state(pos);
+        let changes = random_tick_handler(block_type).map_or_else(Vec::new, |handler| handler(world, pos, block_type, meta, rng));
+        for &(pos, block_type, meta) in &changes {
+            world.set_block(pos, block_type, meta);
+        }
+
+        changes
+    }
+
+    #[test]
+    fn grass_spreads() {
+        let world = test_world(Dimension::Overworld);
+        let mut rng = StdRng::seed_from_u64(1);
+        let grass = Coord::new(0, 15, 0);
+        world.set_block(grass, BlockType::Grass, 0);
+        let mut spread = HashSet::new();
+        for _ in 0..100 {
+            for (pos, block_type, _) in tick(&world, grass, &mut rng) {
+                assert_eq!(block_type, BlockType::Grass);
+                // Covered dirt below the surface stays dirt
+                assert_eq!(pos.y, 15);
+                assert!(pos.x.abs() <= 1 && pos.z.abs() <= 1);
+                spread.insert(pos);
+            }
+        }
+
+        assert_eq!(spread.len(), 8);
+
+        // Grass under a solid block dies
+        world.set_block(above(grass), BlockType::Stone, 0);
+        assert_eq!(tick(&world, grass, &mut rng), vec![(grass, BlockType::Dirt, 0)]);
+    }
+
+    #[test]
+    fn crops_grow() {
+        let world = test_world(Dimension::Overworld);
+        let mut rng = StdRng::seed_from_u64(2);
+        let wheat = Coord::new(0, 16, 0);
+        world.set_block(wheat, BlockType::Wheat, 0);
+        for _ in 0..1000 {
+            tick(&world, wheat, &mut rng);
+        }
+
+        assert_eq!(world.get_block_state(wheat), (BlockType::Wheat, CROP_MAX_AGE));
+
+        // Crops don't grow in the dark
+        let potatoes = Coord::new(2, 16, 0);
+        world.set_block(potatoes, BlockType::Potatoes, 0);
+        world.set_block(Coord::new(2, 20, 0), BlockType::Stone, 0);
+        for _ in 0..1000 {
+            assert!(tick(&world, potatoes, &mut rng).is_empty());
+        }
+
+        // A torch next to them is bright enough
+        world.set_block(Coord::new(3, 17, 0), BlockType::Torch, 0);
+        for _ in 0..1000 {
+            tick(&world, potatoes, &mut rng);
+        }
+
+        assert_eq!(world.get_block_state(potatoes), (BlockType::Potatoes, CROP_MAX_AGE));
+    }
+
+    #[test]
+    fn leaves_decay() {
+        let world = test_world(Dimension::Overworld);
+        let mut rng = StdRng::seed_from_u64(3);
+
+        // Connected to a log through other leaves
+        world.set_block(Coord::new(0, 20, 0), BlockType::Log, 0);
+        for x in 1..=5 {
+            world.set_block(Coord::new(x, 20, 0), BlockType::Leaves, 0);
+        }
+
+        for x in 1..=4 {
+            assert!(tick(&world, Coord::new(x, 20, 0), &mut rng).is_empty());
+        }
+
+        assert_eq!(tick(&world, Coord::new(5, 20, 0), &mut rng), vec![(Coord::new(5, 20, 0), BlockType::Air, 0)]);
+
+        // Logs only count when they're connected through leaves
+        world.set_block(Coord::new(2, 20, 5), BlockType::Log, 0);
+        world.set_block(Coord::new(4, 20, 5), BlockType::Leaves, 0);
+        assert_eq!(tick(&world, Coord::new(4, 20, 5), &mut rng), vec![(Coord::new(4, 20, 5), BlockType::Air, 0)]);
+
+        // Leaves placed by players never decay
+        world.set_block(Coord::new(10, 20, 10), BlockType::Leaves, LEAVES_NO_DECAY);
+        assert!(tick(&world, Coord::new(10, 20, 10), &mut rng).is_empty());
+    }
+
+    #[test]
+    fn ice_melts() {
+        let world = test_world(Dimension::Overworld);
+        let mut rng = StdRng::seed_from_u64(4);
+        let ice = Coord::new(0, 20, 0);
+        world.set_block(ice, BlockType::Ice, 0);
+        assert!(tick(&world, ice, &mut rng).is_empty());
+
+        world.set_block(Coord::new(4, 20, 0), BlockType::Torch, 0);
+        assert!(tick(&world, ice, &mut rng).is_empty());
+
+        world.set_block(Coord::new(2, 20, 0), BlockType::Torch, 0);
+        assert_eq!(tick(&world, ice, &mut rng), vec![(ice, BlockType::Water, 0)]);
+
+        let nether = test_world(Dimension::Nether);
+        nether.set_block(ice, BlockType::Ice, 0);
+        nether.set_block(Coord::new(0, 21, 0), BlockType::Glowstone, 0);
+        assert_eq!(tick(&nether, ice, &mut rng), vec![(ice, BlockType::Air, 0)]);
+    }
+
+    #[test]
+    fn random_tick_speed() {
+        let mut world = test_world(Dimension::Overworld);
+        let mut rng = StdRng::seed_from_u64(5);
+        let chunk = ChunkCoord { x: 0, z: 0 };
+        world.chunk_map().do_with_chunk_mut(chunk, |chunk| {
+            chunk.data.fill(Coord::new(0, 16, 0), Coord::new(WIDTH - 1, 31, WIDTH - 1), BlockType::Wheat, 0);
+        });
+
+        world.game_rules_mut().set(game_rules::RANDOM_TICK_SPEED, "0");
+        assert!(random_ticks(&world, [chunk], &mut rng).is_empty());
+
+        world.game_rules_mut().set(game_rules::RANDOM_TICK_SPEED, "3");
+        let mut changes = Vec::new();
+        for _ in 0..100 {
+            changes.extend(random_ticks(&world, [chunk], &mut rng));
+        }
+
+        // Only the wheat section has blocks with a handler
+        assert!(!changes.is_empty());
+        for (pos, block_type, meta) in changes {
+            assert_eq!(block_type, BlockType::Wheat);
+            assert_eq!(meta, 1);
+            assert!((16..32).contains(&pos.y) && (0..WIDTH).contains(&pos.x) && (0..WIDTH).contains(&pos.z));
+        }
+    }
+}
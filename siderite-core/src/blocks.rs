@@ -1,5 +1,7 @@
 use num_derive::FromPrimitive;
 
+use crate::coord::Coord;
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
 pub enum BlockType {
@@ -8,9 +10,206 @@ pub enum BlockType {
     Grass = 2,
     Dirt = 3,
     CobbleStone = 4,
+    /// The meta picks the kind of wood, like the meta of logs
+    Planks = 5,
+    Bedrock = 7,
+    FlowingWater = 8,
+    Water = 9,
+    FlowingLava = 10,
+    Lava = 11,
+    Sand = 12,
+    Gravel = 13,
+    GoldOre = 14,
+    IronOre = 15,
+    CoalOre = 16,
+    Log = 17,
+    Leaves = 18,
+    Glass = 20,
+    Sandstone = 24,
+    Bed = 26,
+    Tnt = 46,
+    Bookshelf = 47,
+    Obsidian = 49,
+    Torch = 50,
+    Fire = 51,
+    Chest = 54,
+    RedstoneWire = 55,
+    DiamondOre = 56,
+    CraftingTable = 58,
+    Wheat = 59,
+    Furnace = 61,
+    LitFurnace = 62,
+    StandingSign = 63,
+    WoodenDoor = 64,
+    WallSign = 68,
+    Lever = 69,
+    IronDoor = 71,
+    RedstoneOre = 73,
+    RedstoneTorchOff = 75,
+    RedstoneTorchOn = 76,
+    StoneButton = 77,
+    SnowLayer = 78,
+    Ice = 79,
+    Glowstone = 89,
+    EnchantingTable = 116,
+    Carrots = 141,
+    EnderChest = 130,
+    Anvil = 145,
+    Potatoes = 142,
+    SlimeBlock = 165,
     // TODO: Add more
 }
 
+/// Vanilla names of the blocks, without the `minecraft:` namespace
+const BLOCK_NAMES: &[(BlockType, &str)] = &[
+    (BlockType::Air, "air"),
+    (BlockType::Stone, "stone"),
+    (BlockType::Grass, "grass"),
+    (BlockType::Dirt, "dirt"),
+    (BlockType::CobbleStone, "cobblestone"),
+    (BlockType::Planks, "planks"),
+    (BlockType::Bedrock, "bedrock"),
+    (BlockType::FlowingWater, "flowing_water"),
+    (BlockType::Water, "water"),
+    (BlockType::FlowingLava, "flowing_lava"),
+    (BlockType::Lava, "lava"),
+    (BlockType::Sand, "sand"),
+    (BlockType::Gravel, "gravel"),
+    (BlockType::GoldOre, "gold_ore"),
+    (BlockType::IronOre, "iron_ore"),
+    (BlockType::CoalOre, "coal_ore"),
+    (BlockType::Log, "log"),
+    (BlockType::Leaves, "leaves"),
+    (BlockType::Glass, "glass"),
+    (BlockType::Sandstone, "sandstone"),
+    (BlockType::Bed, "bed"),
+    (BlockType::Tnt, "tnt"),
+    (BlockType::Bookshelf, "bookshelf"),
+    (BlockType::Obsidian, "obsidian"),
+    (BlockType::Torch, "torch"),
+    (BlockType::Fire, "fire"),
+    (BlockType::Chest, "chest"),
+    (BlockType::RedstoneWire, "redstone_wire"),
+    (BlockType::DiamondOre, "diamond_ore"),
+    (BlockType::CraftingTable, "crafting_table"),
+    (BlockType::Wheat, "wheat"),
+    (BlockType::Furnace, "furnace"),
+    (BlockType::LitFurnace, "lit_furnace"),
+    (BlockType::StandingSign, "standing_sign"),
+    (BlockType::WoodenDoor, "wooden_door"),
+    (BlockType::WallSign, "wall_sign"),
+    (BlockType::Lever, "lever"),
+    (BlockType::IronDoor, "iron_door"),
+    (BlockType::RedstoneOre, "redstone_ore"),
+    (BlockType::RedstoneTorchOff, "unlit_redstone_torch"),
+    (BlockType::RedstoneTorchOn, "redstone_torch"),
+    (BlockType::StoneButton, "stone_button"),
+    (BlockType::SnowLayer, "snow_layer"),
+    (BlockType::Ice, "ice"),
+    (BlockType::Glowstone, "glowstone"),
+    (BlockType::EnchantingTable, "enchanting_table"),
+    (BlockType::EnderChest, "ender_chest"),
+    (BlockType::Anvil, "anvil"),
+    (BlockType::Carrots, "carrots"),
+    (BlockType::Potatoes, "potatoes"),
+    (BlockType::SlimeBlock, "slime")
+];
+
+impl BlockType {
+    /// Returns the block with the given vanilla name, without the `minecraft:` namespace
+    pub fn from_name(name: &str) -> Option<Self> {
+        BLOCK_NAMES.iter().find(|(_, v)| *v == name).map(|(block_type, _)| *block_type)
+    }
+
+    /// Returns the vanilla name of the block, without the `minecraft:` namespace
+    pub fn name(self) -> &'static str {
+        BLOCK_NAMES.iter().find(|(v, _)| *v == self).map(|(_, name)| *name).expect("block without a name")
+    }
+
+    /// Returns true for blocks that open a window when used
+    pub fn is_container(self) -> bool {
+        matches!(self, BlockType::Chest | BlockType::EnderChest | BlockType::EnchantingTable | BlockType::Anvil) || self.is_furnace()
+    }
+
+    pub fn is_furnace(self) -> bool {
+        matches!(self, BlockType::Furnace | BlockType::LitFurnace)
+    }
+
+    /// Returns true if the block can be replaced by placing a block or liquid in it
+    pub fn is_replaceable(self) -> bool {
+        matches!(self, BlockType::Air | BlockType::FlowingWater | BlockType::Water
+            | BlockType::FlowingLava | BlockType::Lava | BlockType::Fire | BlockType::SnowLayer)
+    }
+
+    /// Returns how well the block resists explosions
+    pub fn blast_resistance(self) -> f32 {
+        match self {
+            BlockType::Air | BlockType::Tnt | BlockType::Fire | BlockType::Torch
+                | BlockType::Wheat | BlockType::Carrots | BlockType::Potatoes
+                | BlockType::RedstoneWire | BlockType::RedstoneTorchOff | BlockType::RedstoneTorchOn => 0.0,
+            BlockType::Leaves => 0.2,
+            BlockType::Glowstone => 0.3,
+            BlockType::Glass => 1.5,
+            BlockType::Bookshelf => 7.5,
+            BlockType::Dirt | BlockType::Sand | BlockType::Gravel | BlockType::SnowLayer
+                | BlockType::Lever | BlockType::StoneButton => 0.5,
+            BlockType::StandingSign | BlockType::WallSign | BlockType::Bed => 1.0,
+            BlockType::Ice => 2.5,
+            BlockType::WoodenDoor => 3.0,
+            BlockType::GoldOre | BlockType::IronOre | BlockType::CoalOre
+                | BlockType::DiamondOre | BlockType::RedstoneOre => 3.0,
+            BlockType::Sandstone => 4.0,
+            BlockType::Chest | BlockType::CraftingTable => 12.5,
+            BlockType::Planks => 15.0,
+            BlockType::Furnace | BlockType::LitFurnace => 17.5,
+            BlockType::IronDoor => 5.0,
+            BlockType::Grass => 0.6,
+            BlockType::Log => 2.0,
+            BlockType::SlimeBlock => 0.0,
+            BlockType::Stone | BlockType::CobbleStone => 6.0,
+            BlockType::FlowingWater | BlockType::Water | BlockType::FlowingLava | BlockType::Lava => 100.0,
+            BlockType::Obsidian | BlockType::EnchantingTable => 1200.0,
+            BlockType::EnderChest => 3000.0,
+            BlockType::Anvil => 6000.0,
+            BlockType::Bedrock => 3_600_000.0
+        }
+    }
+
+    /// Returns true if entities can stand on the block
+    pub fn is_solid(self) -> bool {
+        !self.is_liquid() && !self.is_plant() && !matches!(self, BlockType::Air | BlockType::Fire | BlockType::Torch
+            | BlockType::RedstoneWire | BlockType::Lever | BlockType::RedstoneTorchOff | BlockType::RedstoneTorchOn | BlockType::StoneButton
+            | BlockType::StandingSign | BlockType::WallSign)
+    }
+
+    /// Returns true if the block doesn't let light through
+    pub fn is_opaque(self) -> bool {
+        self.is_solid() && !matches!(self, BlockType::Leaves | BlockType::Ice | BlockType::SlimeBlock | BlockType::Glass
+            | BlockType::WoodenDoor | BlockType::IronDoor | BlockType::Chest | BlockType::EnderChest | BlockType::EnchantingTable
+            | BlockType::Anvil | BlockType::Bed)
+    }
+
+    /// Returns true for crops
+    pub fn is_plant(self) -> bool {
+        matches!(self, BlockType::Wheat | BlockType::Carrots | BlockType::Potatoes)
+    }
+
+    /// Returns the light level the block gives off
+    pub fn light_emission(self) -> u8 {
+        match self {
+            BlockType::FlowingLava | BlockType::Lava | BlockType::Fire | BlockType::Glowstone => 15,
+            BlockType::Torch => 14,
+            BlockType::LitFurnace => 13,
+            BlockType::RedstoneTorchOn | BlockType::EnderChest => 7,
+            _ => 0
+        }
+    }
+
+    pub fn is_liquid(self) -> bool {
+        matches!(self, BlockType::FlowingWater | BlockType::Water | BlockType::FlowingLava | BlockType::Lava)
+    }
+}
+
 #[repr(i8)]
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
 pub enum BlockFace {
@@ -27,3 +226,81 @@ pub enum BlockFace {
     /// +X
     XP = 5
 }
+
+impl BlockFace {
+    /// Returns the position of the neighbouring block on this face, relative to the block
+    pub fn offset(self) -> Coord<i32> {
+        match self {
+            BlockFace::YM => Coord::new(0, -1, 0),
+            BlockFace::YP => Coord::new(0, 1, 0),
+            BlockFace::ZM => Coord::new(0, 0, -1),
+            BlockFace::ZP => Coord::new(0, 0, 1),
+            BlockFace::XM => Coord::new(-1, 0, 0),
+            BlockFace::XP => Coord::new(1, 0, 0)
+        }
+    }
+
+    /// Returns the position of the block touching this face of the block at `pos`
+    pub fn adjacent(self, pos: Coord<i32>) -> Coord<i32> {
+        let offset = self.offset();
+        Coord::new(pos.x + offset.x, pos.y + offset.y, pos.z + offset.z)
+    }
+
+    /// Returns the direction used for hanging entities: 0 south, 1 west, 2 north and 3 east.
+    /// None for the top and bottom faces.
+    pub fn horizontal_index(self) -> Option<u8> {
+        match self {
+            BlockFace::ZP => Some(0),
+            BlockFace::XM => Some(1),
+            BlockFace::ZM => Some(2),
+            BlockFace::XP => Some(3),
+            BlockFace::YM | BlockFace::YP => None
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            BlockFace::YM => BlockFace::YP,
+            BlockFace::YP => BlockFace::YM,
+            BlockFace::ZM => BlockFace::ZP,
+            BlockFace::ZP => BlockFace::ZM,
+            BlockFace::XM => BlockFace::XP,
+            BlockFace::XP => BlockFace::XM
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_traits::FromPrimitive;
+
+    use super::*;
+
+    #[test]
+    fn offsets() {
+        assert_eq!(BlockFace::YM.offset(), Coord::new(0, -1, 0));
+        assert_eq!(BlockFace::YP.offset(), Coord::new(0, 1, 0));
+        assert_eq!(BlockFace::ZM.offset(), Coord::new(0, 0, -1));
+        assert_eq!(BlockFace::ZP.offset(), Coord::new(0, 0, 1));
+        assert_eq!(BlockFace::XM.offset(), Coord::new(-1, 0, 0));
+        assert_eq!(BlockFace::XP.offset(), Coord::new(1, 0, 0));
+
+        for i in 0..6 {
+            let face = BlockFace::from_i8(i).unwrap();
+            let offset = face.offset();
+            let opposite = face.opposite().offset();
+            assert_eq!(offset.x.abs() + offset.y.abs() + offset.z.abs(), 1);
+            assert_eq!(Coord::new(-offset.x, -offset.y, -offset.z), opposite);
+            assert_eq!(face.adjacent(Coord::new(10, 64, -3)), Coord::new(10 + offset.x, 64 + offset.y, -3 + offset.z));
+        }
+    }
+
+    #[test]
+    fn opposite_is_involution() {
+        for i in 0..6 {
+            let face = BlockFace::from_i8(i).unwrap();
+            assert_ne!(face.opposite(), face);
+            assert_eq!(face.opposite().opposite(), face);
+        }
+    }
+}
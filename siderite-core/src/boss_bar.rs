@@ -0,0 +1,128 @@
+//! 1.8 has no boss bar packet, a bar is shown by keeping an invisible wither in front of the player.
+//! The client shows the name and health of the wither as the bar at the top of the screen.
+
+use crate::coord::{self, Coord};
+use crate::entities::metadata::{self, EntityFlags, Metadata, MetadataValue};
+
+/// Mob type of the wither in the Spawn Mob packet
+pub const WITHER_TYPE: u8 = 64;
+
+/// Health of a wither, shown as a full bar
+pub const WITHER_MAX_HEALTH: f32 = 300.0;
+
+/// Distance in front of the eyes of the player the wither is kept at,
+/// far enough to not be noticed and close enough to stay loaded on the client
+pub const DISTANCE: f64 = 30.0;
+
+/// The boss bar shown to a player
+#[derive(Clone, Debug, PartialEq)]
+pub struct BossBar {
+    /// ID of the wither carrying the bar
+    entity_id: u32,
+    /// Where the client last put the wither
+    pos: Coord<f64>,
+    text: String,
+    /// How full the bar is, from 0 to 1
+    progress: f32
+}
+
+impl BossBar {
+    /// Returns a full bar carried by a wither at the position
+    pub fn new(entity_id: u32, pos: Coord<f64>, text: String) -> Self {
+        Self {
+            entity_id,
+            pos,
+            text,
+            progress: 1.0
+        }
+    }
+
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    pub fn pos(&self) -> Coord<f64> {
+        self.pos
+    }
+
+    /// Moves the wither, returns false if the client wouldn't see a difference
+    /// at the 1/32 block precision of the protocol
+    pub fn move_to(&mut self, pos: Coord<f64>) -> bool {
+        let fixed = |v: Coord<f64>| ((v.x * 32.0).floor() as i32, (v.y * 32.0).floor() as i32, (v.z * 32.0).floor() as i32);
+        let moved = fixed(pos) != fixed(self.pos);
+        self.pos = pos;
+        moved
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    pub fn set_progress(&mut self, progress: f32) {
+        self.progress = progress.clamp(0.0, 1.0);
+    }
+
+    /// Returns the health of the wither for the progress.
+    /// The client removes withers without health after their death animation, so it never drops to 0.
+    pub fn health(&self) -> f32 {
+        (self.progress * WITHER_MAX_HEALTH).max(1.0)
+    }
+
+    /// Returns the metadata the wither is spawned with
+    pub fn metadata(&self) -> Metadata {
+        vec![
+            (metadata::FLAGS_INDEX, MetadataValue::Byte(EntityFlags::INVISIBLE.bits() as i8)),
+            (metadata::CUSTOM_NAME_INDEX, MetadataValue::String(self.text.clone())),
+            (metadata::HEALTH_INDEX, MetadataValue::Float(self.health()))
+        ]
+    }
+}
+
+/// Returns where the wither goes for a player looking from `eye`
+pub fn position(eye: Coord<f64>, yaw: f32, pitch: f32) -> Coord<f64> {
+    let dir = coord::look_direction(yaw, pitch);
+    Coord::new(eye.x + dir.x * DISTANCE, eye.y + dir.y * DISTANCE, eye.z + dir.z * DISTANCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_front_of_player() {
+        let pos = position(Coord::new(0.5, 65.62, 0.5), 90.0, 0.0);
+        assert!((pos.x - (0.5 - DISTANCE)).abs() < 1e-9);
+        assert!((pos.y - 65.62).abs() < 1e-9);
+        assert!((pos.z - 0.5).abs() < 1e-9);
+
+        let pos = position(Coord::new(0.0, 64.0, 0.0), 0.0, -90.0);
+        assert!((pos.y - (64.0 + DISTANCE)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn health_follows_progress() {
+        let mut bar = BossBar::new(1, Coord::new(0.0, 64.0, 0.0), "Boss".to_owned());
+        assert_eq!(bar.health(), WITHER_MAX_HEALTH);
+        bar.set_progress(0.5);
+        assert_eq!(bar.health(), 150.0);
+        bar.set_progress(-1.0);
+        assert_eq!(bar.progress(), 0.0);
+        assert_eq!(bar.health(), 1.0);
+    }
+
+    #[test]
+    fn moves_only_when_visible() {
+        let mut bar = BossBar::new(1, Coord::new(0.0, 64.0, 0.0), "Boss".to_owned());
+        assert!(!bar.move_to(Coord::new(0.01, 64.0, 0.0)));
+        assert!(bar.move_to(Coord::new(0.5, 64.0, 0.0)));
+        assert_eq!(bar.pos(), Coord::new(0.5, 64.0, 0.0));
+    }
+}
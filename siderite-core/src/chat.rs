@@ -0,0 +1,194 @@
+//! JSON chat components
+
+use serde_json::{self as json, json, Map, Value};
+
+/// Character that starts a legacy formatting code
+const FORMAT_CHAR: char = '§';
+
+/// Returns the name of the color of a legacy formatting code
+fn color_name(code: char) -> Option<&'static str> {
+    Some(match code {
+        '0' => "black",
+        '1' => "dark_blue",
+        '2' => "dark_green",
+        '3' => "dark_aqua",
+        '4' => "dark_red",
+        '5' => "dark_purple",
+        '6' => "gold",
+        '7' => "gray",
+        '8' => "dark_gray",
+        '9' => "blue",
+        'a' => "green",
+        'b' => "aqua",
+        'c' => "red",
+        'd' => "light_purple",
+        'e' => "yellow",
+        'f' => "white",
+        _ => return None
+    })
+}
+
+/// Returns a component with plain text
+pub fn text(text: &str) -> Value {
+    json!({ "text": text })
+}
+
+/// Returns a component the client translates to its language, the arguments replace the %s in the translation
+pub fn translate(key: &str, with: &[Value]) -> Value {
+    if with.is_empty() {
+        return json!({ "translate": key });
+    }
+
+    json!({ "translate": key, "with": with })
+}
+
+/// Returns the index of a color by its name, which is also the digit of its legacy formatting code
+pub fn color_index(name: &str) -> Option<u8> {
+    (0..16).find(|&i| color_name(char::from_digit(i, 16).unwrap()) == Some(name)).map(|i| i as u8)
+}
+
+/// Returns the component property set by a legacy formatting code
+fn format_name(code: char) -> Option<&'static str> {
+    Some(match code {
+        'k' => "obfuscated",
+        'l' => "bold",
+        'm' => "strikethrough",
+        'n' => "underlined",
+        'o' => "italic",
+        _ => return None
+    })
+}
+
+/// Converts text with § formatting codes to a chat component.
+/// Like in vanilla, a color code also resets the formatting.
+pub fn from_legacy(text: &str) -> Value {
+    if !text.contains(FORMAT_CHAR) {
+        return self::text(text);
+    }
+
+    let mut extra = Vec::new();
+    let mut style = Map::new();
+    let mut current = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != FORMAT_CHAR {
+            current.push(c);
+            continue;
+        }
+
+        let code = match chars.next() {
+            Some(v) => v.to_ascii_lowercase(),
+            None => break
+        };
+
+        if !current.is_empty() {
+            let mut part = style.clone();
+            part.insert("text".to_owned(), Value::String(std::mem::take(&mut current)));
+            extra.push(Value::Object(part));
+        }
+
+        if let Some(color) = color_name(code) {
+            style.clear();
+            style.insert("color".to_owned(), Value::String(color.to_owned()));
+        } else if let Some(format) = format_name(code) {
+            style.insert(format.to_owned(), Value::Bool(true));
+        } else if code == 'r' {
+            style.clear();
+        }
+    }
+
+    if !current.is_empty() {
+        let mut part = style;
+        part.insert("text".to_owned(), Value::String(current));
+        extra.push(Value::Object(part));
+    }
+
+    json!({ "text": "", "extra": extra })
+}
+
+/// Returns the chat component for the MOTD from server.properties,
+/// which is either a JSON component or text with § formatting codes
+pub fn motd_component(motd: &str) -> Value {
+    match json::from_str::<Value>(motd) {
+        Ok(v) if v.is_object() || v.is_array() => v,
+        _ => from_legacy(motd)
+    }
+}
+
+/// Returns the text of the component and its extra components, without any formatting
+pub fn to_plain_text(component: &Value) -> String {
+    match component {
+        Value::String(v) => v.clone(),
+        Value::Array(v) => v.iter().map(to_plain_text).collect(),
+        Value::Object(v) => {
+            let mut text = v.get("text").and_then(Value::as_str).unwrap_or_default().to_owned();
+            if let Some(extra) = v.get("extra") {
+                text.push_str(&to_plain_text(extra));
+            }
+
+            text
+        }
+        Value::Null => String::new(),
+        v => v.to_string()
+    }
+}
+
+/// Removes the § formatting codes from the text, the code after a § goes with it
+pub fn strip_formatting(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == FORMAT_CHAR {
+            chars.next();
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_motd() {
+        assert_eq!(motd_component("A Minecraft Server"), json!({ "text": "A Minecraft Server" }));
+        // Numbers are valid JSON, but not a component
+        assert_eq!(motd_component("1337"), json!({ "text": "1337" }));
+    }
+
+    #[test]
+    fn legacy_motd() {
+        assert_eq!(motd_component("§aGreen §lbold§r plain\n§Cred"), json!({
+            "text": "",
+            "extra": [
+                { "text": "Green ", "color": "green" },
+                { "text": "bold", "color": "green", "bold": true },
+                { "text": " plain\n" },
+                { "text": "red", "color": "red" }
+            ]
+        }));
+    }
+
+    #[test]
+    fn json_motd() {
+        let motd = r#"{"text": "Hello", "color": "gold", "extra": [{"text": " world"}]}"#;
+        assert_eq!(motd_component(motd), json!({ "text": "Hello", "color": "gold", "extra": [{ "text": " world" }] }));
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(to_plain_text(&json!("Hello")), "Hello");
+        assert_eq!(to_plain_text(&from_legacy("§aGreen §lbold§r plain")), "Green bold plain");
+        assert_eq!(to_plain_text(&json!([{ "text": "a", "clickEvent": { "action": "run_command", "value": "/op" } }, "b", 1])), "ab1");
+    }
+
+    #[test]
+    fn strip_formatting_codes() {
+        assert_eq!(strip_formatting("§aGreen §lbold§r plain"), "Green bold plain");
+        assert_eq!(strip_formatting("§§ab"), "ab");
+        assert_eq!(strip_formatting("end§"), "end");
+    }
+}
@@ -1,17 +1,71 @@
-use std::sync::{Arc, RwLock};
+use std::ops::Add;
+use std::sync::{Arc, Mutex, RwLock};
 
 use crossbeam_channel::Sender;
+use log::*;
+use rand::{thread_rng, Rng};
 use uuid::Uuid;
 use serde_json as json;
+use siderite_nbt::{Compound, Tag};
+use siderite_nbt::snbt;
 
+use crate::TPS;
+use crate::anvil::{self, AnvilName, AnvilResult};
 use crate::auth::AuthInfo;
-use crate::blocks::BlockFace;
-use crate::entities::player::Player;
-use crate::protocol::DigStatus;
+use crate::beds;
+use crate::blocks::{BlockFace, BlockType};
+use crate::boss_bar::{self, BossBar};
+use crate::chat;
+use crate::commands::{self, ArgError, ArgValue, CommandInfo, SelectorContext, Syntax};
+use crate::crafting;
+use crate::enchanting::{self, EnchantingOptions};
+use crate::entities::{self, Entity, EntityType, Motive, TNT_FUSE};
+use crate::entities::effects::{Effect, EffectType};
+use crate::entities::hunger::HUNGER;
+use crate::entities::metadata::{self, EntityFlags, MetadataValue};
+use crate::entities::player::{DamageCause, GameMode, Movement, Player, knockback};
+use crate::furnace::FurnaceUpdate;
+use crate::items::{self, INVENTORY_SIZE, ItemStack, ItemType, MAX_BOOK_TITLE_LENGTH};
+use crate::protocol::{Animation, BlockPlacement, DigStatus, EntityAction, EntityStatus, GameStateReason, TeleportFlags, UseEntityAction};
 use crate::protocol::packets::{Packet, PlayerListAction};
-use crate::server::Server;
+use crate::redstone;
+use crate::server::{self, Server};
+use crate::stats;
+use crate::storage::chunk::chunk_map::MAX_PREGEN_RADIUS;
+use crate::storage::game_rules;
+use crate::storage::world::{DAY_LENGTH, Difficulty, World};
+use crate::teams::{self, Team};
+use crate::tile_entities::TileEntity;
+use crate::weather::WeatherType;
+use crate::window::{Click, PLAYER_WINDOW_ID, SharedInventory, WindowManager, WindowType};
 use crate::coord::{ChunkCoord, Coord};
 
+/// Distance below the feet of a player that's checked for ground
+const GROUND_CHECK_DEPTH: f64 = 0.05;
+
+/// Horizontal distance a player can move in one tick before the move is rejected,
+/// vanilla uses the same limit for its "moved too quickly" check
+const MAX_MOVE_DISTANCE: f64 = 10.0;
+
+/// Flying players are allowed to move this many times further than walking ones
+const FLYING_MOVE_FACTOR: f64 = 2.0;
+
+/// Damage of a punch without a weapon
+const FIST_DAMAGE: f32 = 1.0;
+
+/// Maximum number of chunks sent in one Map Chunk Bulk packet
+const MAX_BULK_CHUNKS: usize = 10;
+
+/// Distance below the eyes of a player that dropped items appear at
+const DROP_HEIGHT_OFFSET: f64 = 0.3;
+
+/// Farthest distance from the eyes of a player to the center of a block they can use or place against,
+/// a bit more than the reach as the client only sends its position with the next move
+const BLOCK_USE_RANGE: f64 = 6.0;
+
+/// Sign lines are cut off after this many characters
+const MAX_SIGN_LINE_LENGTH: usize = 384;
+
 pub struct Client {
     id: u32,
     username: Option<String>,
@@ -19,6 +73,12 @@ pub struct Client {
     properties: json::Value,
 
     player: Option<Arc<RwLock<Player>>>,
+    windows: Mutex<WindowManager>,
+    /// Name typed into the open anvil
+    anvil_name: Mutex<AnvilName>,
+    /// Id of the held map, it was sent whole when the player started holding it
+    shown_map: Mutex<Option<i32>>,
+    boss_bar: Mutex<Option<BossBar>>,
 
     server: Arc<Server>,
     protocol: Sender<Packet>,
@@ -34,6 +94,10 @@ impl Client {
             properties: json::Value::Null,
 
             player: None,
+            windows: Mutex::new(WindowManager::new()),
+            anvil_name: Mutex::new(AnvilName::default()),
+            shown_map: Mutex::new(None),
+            boss_bar: Mutex::new(None),
 
             server,
             protocol,
@@ -64,8 +128,8 @@ impl Client {
         self.username = Some(username);
     }
 
-    pub fn kick(&self, reason: &str) {
-        self.protocol.send(Packet::Disconnect(reason.to_owned())).unwrap();
+    pub fn kick(&self, reason: json::Value) {
+        self.protocol.send(Packet::Disconnect(reason)).unwrap();
     }
 
     pub fn handle_login(&self, server_id: Option<String>) {
@@ -90,49 +154,2591 @@ impl Client {
         self.protocol.send(Packet::LoginSuccess()).unwrap();
     }
 
+    pub fn set_player(&mut self, player: Arc<RwLock<Player>>) {
+        self.player = Some(player);
+    }
+
     pub fn finish_auth(&mut self, player: Arc<RwLock<Player>>) {
-        self.player = Some(player.clone());
-        let world = player.read().unwrap().world();
-        let chunk_map = world.read().unwrap().chunk_map();
+        self.set_player(player.clone());
+        let (world, pos) = {
+            let p = player.read().unwrap();
+            (p.world(), p.pos())
+        };
 
         self.protocol.send(Packet::JoinGame(player.clone(), world.clone())).unwrap();
         self.protocol.send(Packet::SpawnPosition(world.clone())).unwrap();
-        self.protocol.send(Packet::ServerDifficulty(self.server.difficulty())).unwrap();
+        self.protocol.send(Packet::ServerDifficulty(world.read().unwrap().difficulty())).unwrap();
         self.protocol.send(Packet::PlayerAbilities(player.clone())).unwrap();
+        let attributes = player.read().unwrap().attributes().clone();
+        self.protocol.send(Packet::EntityProperties(self.id, attributes)).unwrap();
 
-        for x in -3..3 {
-            for z in -3..3 {
-                let coord = ChunkCoord {x, z};
-                let map = chunk_map.clone();
-                map.touch_chunk(coord);
-                self.protocol.send(Packet::ChunkData(
-                        coord,
-                        map)
-                    ).unwrap();
-            }
-        }
+        self.stream_chunks(&world, None, ChunkCoord::from_pos(pos));
 
-        self.protocol.send(Packet::TimeUpdate(world)).unwrap();
+        self.protocol.send(Packet::TimeUpdate(world.clone())).unwrap();
         self.protocol.send(Packet::PlayerPositionAndLook(player.clone())).unwrap();
+        let items = {
+            let p = player.read().unwrap();
+            self.windows.lock().unwrap().window_items(PLAYER_WINDOW_ID, p.inventory()).unwrap()
+        };
+        self.protocol.send(Packet::WindowItems(PLAYER_WINDOW_ID, items)).unwrap();
+
+        // Clients start with clear weather
+        let weather = world.read().unwrap().weather().clone();
+        if weather.is_raining() {
+            for packet in weather.packets() {
+                self.protocol.send(packet).unwrap();
+            }
+        }
 
         // Add ourself to the tab menu
         let packet = Packet::PlayerListItem(PlayerListAction::AddPlayer, Box::new([player]));
         self.protocol.send(packet.clone()).unwrap();
         self.server.broadcast(packet);
+
+        for packet in self.server.team_packets() {
+            self.protocol.send(packet).unwrap();
+        }
     }
 
-    pub fn handle_left_click(&self, _block_pos: Coord<i32>, _face: BlockFace, status: DigStatus) {
-        match status {
-            DigStatus::StartedDigging => (),
-            DigStatus::CancelledDigging => (),
-            DigStatus::FinishedDigging => (),
-            DigStatus::DropItemStack => (),
-            DigStatus::DropItem => (),
-            DigStatus::ShootArrowFinishEating => ()
+    /// Sends the chunks in view of `center` the client doesn't have yet
+    /// and unloads the ones in view of `old_center` that are now out of view
+    fn stream_chunks(&self, world: &Arc<RwLock<World>>, old_center: Option<ChunkCoord>, center: ChunkCoord) {
+        let (chunk_map, dimension) = {
+            let world = world.read().unwrap();
+            (world.chunk_map(), world.dimension())
+        };
+
+        let view_distance = self.server.view_distance() as i32;
+        let in_view = |coord: ChunkCoord, center: ChunkCoord|
+            (coord.x - center.x).abs() <= view_distance && (coord.z - center.z).abs() <= view_distance;
+
+        if let Some(old_center) = old_center {
+            for x in (old_center.x - view_distance)..=(old_center.x + view_distance) {
+                for z in (old_center.z - view_distance)..=(old_center.z + view_distance) {
+                    let coord = ChunkCoord {x, z};
+                    if !in_view(coord, center) {
+                        self.protocol.send(Packet::ChunkUnload(coord)).unwrap();
+                    }
+                }
+            }
+        }
+
+        let mut coords = Vec::new();
+        for x in (center.x - view_distance)..=(center.x + view_distance) {
+            for z in (center.z - view_distance)..=(center.z + view_distance) {
+                let coord = ChunkCoord {x, z};
+                if !old_center.is_some_and(|v| in_view(coord, v)) {
+                    chunk_map.touch_chunk(coord);
+                    coords.push(coord);
+                }
+            }
+        }
+
+        for batch in coords.chunks(MAX_BULK_CHUNKS) {
+            self.protocol.send(Packet::MapChunkBulk(batch.to_vec(), chunk_map.clone(), dimension)).unwrap();
+        }
+
+        // Tile entities can only be sent once the client has the chunks
+        for coord in coords {
+            chunk_map.do_with_chunk(coord, |chunk| {
+                for (pos, tile_entity) in &chunk.tile_entities {
+                    self.protocol.send(Packet::UpdateBlockEntity(*pos, tile_entity.clone())).unwrap();
+                }
+            });
+        }
+    }
+
+    pub fn handle_move(&self, pos: Option<Coord<f64>>, look: Option<(f32, f32)>, on_ground: bool) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (old, world, max_distance) = {
+            let p = player.read().unwrap();
+            (p.pos(), p.world(), Self::max_move_distance(&p))
+        };
+
+        if let (Some(pos), Some(max_distance)) = (pos, max_distance) {
+            let (dx, dz) = (pos.x - old.x, pos.z - old.z);
+            if dx * dx + dz * dz > max_distance * max_distance {
+                warn!("{} moved too quickly! {:.2}, {:.2}", self.username.as_deref().unwrap_or_default(), dx, dz);
+                // Put the client back where we think it is
+                self.protocol.send(Packet::PlayerPositionAndLook(player.clone())).unwrap();
+                return;
+            }
+        }
+
+        let new = pos.unwrap_or(old);
+        // Don't blindly trust the client, there has to be a block below the player
+        let (feet, below) = {
+            let w = world.read().unwrap();
+            let block_at = |y: f64| w.get_block_state(Coord::new(new.x.floor() as i32, y.floor() as i32, new.z.floor() as i32)).0;
+            (block_at(new.y), block_at(new.y - GROUND_CHECK_DEPTH))
+        };
+        let on_ground = on_ground && below.is_solid();
+        let landing_block = if feet.is_liquid() { feet } else { below };
+        let swimming = matches!(feet, BlockType::Water | BlockType::FlowingWater);
+
+        let damage = {
+            let mut player = player.write().unwrap();
+            let was_on_ground = player.on_ground();
+            let damage = player.update_fall(new.y, on_ground, landing_block);
+            if let Some(pos) = pos {
+                let (dx, dy, dz) = (pos.x - old.x, pos.y - old.y, pos.z - old.z);
+                if swimming {
+                    player.add_move_exhaustion((dx * dx + dy * dy + dz * dz).sqrt(), true);
+                } else if on_ground {
+                    let distance = (dx * dx + dz * dz).sqrt();
+                    let distance_cm = (distance * 100.0).round() as i32;
+                    if distance_cm > 0 {
+                        player.stats_mut().increment(stats::WALK_ONE_CM, distance_cm);
+                    }
+                    player.add_move_exhaustion(distance, false);
+                }
+
+                // Like vanilla, leaving the ground while moving up is a jump
+                if was_on_ground && !on_ground && dy > 0.0 {
+                    player.jump();
+                }
+
+                player.set_pos(pos);
+            }
+
+            if let Some((yaw, pitch)) = look {
+                player.set_rotation(yaw, pitch);
+            }
+
+            damage
         };
+
+        let (old_chunk, new_chunk) = (ChunkCoord::from_pos(old), ChunkCoord::from_pos(new));
+        if old_chunk != new_chunk {
+            self.stream_chunks(&world, Some(old_chunk), new_chunk);
+        }
+
+        if let Some(damage) = damage {
+            self.damage(damage, DamageCause::Fall);
+        }
     }
 
-    pub fn send(&self, packet: Packet) {
-        self.protocol.send(packet).unwrap();
+    /// Returns the horizontal distance the player can move in one tick,
+    /// None if it can move any distance, like creative and spectator players flying.
+    /// Speed lets players move further.
+    fn max_move_distance(player: &Player) -> Option<f64> {
+        let flying = player.is_flying();
+        let distance = match player.gamemode() {
+            GameMode::Creative | GameMode::Spectator if flying => return None,
+            _ if flying => MAX_MOVE_DISTANCE * FLYING_MOVE_FACTOR,
+            _ => MAX_MOVE_DISTANCE
+        };
+
+        Some(distance * player.speed_multiplier())
+    }
+
+    /// Hurts the player, when that kills it the death sequence runs.
+    /// Every kind of damage goes through here, players that get attacked are knocked back.
+    pub fn damage(&self, amount: f32, cause: DamageCause) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (health, food, saturation) = {
+            let mut p = player.write().unwrap();
+            // Dead players can't be hurt any further
+            if p.health() <= 0.0 {
+                return;
+            }
+
+            let health = p.health() - amount;
+            p.set_health(health);
+            (p.health(), p.food_level(), p.saturation())
+        };
+
+        self.protocol.send(Packet::UpdateHealth(health, food, saturation)).unwrap();
+        if health > 0.0 {
+            if let Some(velocity) = self.attack_knockback(player, &cause) {
+                self.protocol.send(Packet::EntityVelocity(self.id, velocity)).unwrap();
+            }
+
+            // The clients play the hurt animation and sound
+            self.send_to_tracking_and_self(player, Packet::EntityStatus(self.id, EntityStatus::Hurt));
+        } else {
+            self.die(player, cause);
+        }
+    }
+
+    /// Returns the velocity the attacker knocks the player back with, None if it wasn't attacked
+    fn attack_knockback(&self, player: &Arc<RwLock<Player>>, cause: &DamageCause) -> Option<Coord<f64>> {
+        let attacker = cause.killer()?;
+        let (world, pos) = {
+            let p = player.read().unwrap();
+            (p.world(), p.pos())
+        };
+
+        let attacker = world.read().unwrap().get_player(attacker)?;
+        let attacker_pos = attacker.read().unwrap().pos();
+        Some(knockback(pos, attacker_pos))
+    }
+
+    /// Opens the death screen, tells everyone how the player died and drops its inventory.
+    /// The health of the player has to be 0 already.
+    fn die(&self, player: &Arc<RwLock<Player>>, cause: DamageCause) {
+        let message = cause.death_message(self.username.as_deref().unwrap_or_default());
+        let (world, pos) = {
+            let p = player.read().unwrap();
+            (p.world(), p.pos())
+        };
+
+        // The crafting result isn't an item until it's taken, only the ingredients are kept or dropped
+        self.return_crafting_grid(player);
+
+        let keep_inventory = world.read().unwrap().game_rules().get_bool(game_rules::KEEP_INVENTORY);
+        let drops = {
+            let mut p = player.write().unwrap();
+            p.stats_mut().increment(stats::DEATHS, 1);
+            if keep_inventory { Vec::new() } else { p.inventory_mut().take_all() }
+        };
+
+        self.protocol.send(Packet::CombatEvent(self.id, cause.killer(), message.clone())).unwrap();
+        self.send_to_tracking_and_self(player, Packet::EntityStatus(self.id, EntityStatus::Dead));
+        info!("{}", message);
+        self.server.broadcast(Packet::ChatMessage(message));
+
+        for item in drops {
+            self.drop_item(&world, pos, item);
+        }
+
+        self.send_inventory_changes();
+    }
+
+    /// Respawns the player at the spawn of its world, only if it's dead
+    pub fn handle_respawn(&self) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (world, old, gamemode, spawn_point) = {
+            let p = player.read().unwrap();
+            if p.health() > 0.0 {
+                return;
+            }
+
+            (p.world(), p.pos(), p.gamemode(), (p.spawn_point(), p.spawn_forced()))
+        };
+
+        let spawn_point = match spawn_point {
+            (Some(bed), false) if world.read().unwrap().get_block_state(bed).0 != BlockType::Bed => {
+                player.write().unwrap().set_spawn_point(None, false);
+                self.protocol.send(Packet::ChangeGameState(GameStateReason::InvalidBed, 0.0)).unwrap();
+                None
+            }
+            // Players get up next to their bed
+            (Some(bed), false) => Some(world.read().unwrap().find_safe_spawn(bed)),
+            (spawn_point, _) => spawn_point
+        };
+        let spawn = match spawn_point {
+            Some(v) => v.bottom_center(),
+            None => {
+                let w = world.read().unwrap();
+                w.find_safe_spawn(w.spawn_pos()).bottom_center()
+            }
+        };
+        let (health, food, saturation) = {
+            let mut p = player.write().unwrap();
+            p.respawn(spawn);
+            (p.health(), p.food_level(), p.saturation())
+        };
+
+        self.protocol.send(Packet::Respawn(world.clone(), gamemode)).unwrap();
+        self.protocol.send(Packet::UpdateHealth(health, food, saturation)).unwrap();
+        self.teleport(player, &world, old, spawn);
+    }
+
+    /// Moves the player to `pos` and sends it the chunks around it
+    fn teleport(&self, player: &Arc<RwLock<Player>>, world: &Arc<RwLock<World>>, old: Coord<f64>, pos: Coord<f64>) {
+        {
+            let mut p = player.write().unwrap();
+            p.set_pos(pos);
+            p.reset_fall();
+        }
+        let (old_chunk, new_chunk) = (ChunkCoord::from_pos(old), ChunkCoord::from_pos(pos));
+        if old_chunk != new_chunk {
+            self.stream_chunks(world, Some(old_chunk), new_chunk);
+        }
+
+        self.protocol.send(Packet::PlayerPositionAndLook(player.clone())).unwrap();
+    }
+
+    /// Moves the player to `pos` in its world, facing the yaw and pitch
+    pub fn teleport_to(&self, pos: Coord<f64>, yaw: f32, pitch: f32) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (world, old) = {
+            let mut p = player.write().unwrap();
+            p.set_rotation(yaw, pitch);
+            (p.world(), p.pos())
+        };
+        self.teleport(player, &world, old, pos);
+    }
+
+    /// Moves the player to `pos` in `world`.
+    /// When that's another world the player leaves its current one,
+    /// and the client forgets everything around the old position before getting the chunks around the new one.
+    pub fn teleport_to_world(&self, world: &Arc<RwLock<World>>, pos: Coord<f64>) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (old_world, old, gamemode, vehicle) = {
+            let p = player.read().unwrap();
+            (p.world(), p.pos(), p.gamemode(), p.vehicle())
+        };
+        if Arc::ptr_eq(&old_world, world) {
+            self.teleport(player, world, old, pos);
+            return;
+        }
+
+        // Vehicles stay behind
+        if let Some(vehicle) = vehicle {
+            if let Some(entity) = old_world.read().unwrap().get_entity(vehicle) {
+                entity.write().unwrap().set_passenger(None);
+            }
+
+            player.write().unwrap().set_vehicle(None);
+            self.server.send_to_tracking(&old_world, self.id, Packet::AttachEntity(self.id, None, false));
+        }
+
+        let tracked = {
+            let mut w = old_world.write().unwrap();
+            w.remove_player(self.id);
+            w.tracker().tracked(self.id)
+        };
+        self.server.untrack_entities(&old_world, &[self.id]);
+
+        // The client only throws its world away when the dimension changes
+        if old_world.read().unwrap().dimension() == world.read().unwrap().dimension() {
+            if !tracked.is_empty() {
+                self.protocol.send(Packet::DestroyEntities(tracked)).unwrap();
+            }
+
+            let view_distance = self.server.view_distance() as i32;
+            let center = ChunkCoord::from_pos(old);
+            for x in (center.x - view_distance)..=(center.x + view_distance) {
+                for z in (center.z - view_distance)..=(center.z + view_distance) {
+                    self.protocol.send(Packet::ChunkUnload(ChunkCoord {x, z})).unwrap();
+                }
+            }
+        }
+
+        {
+            let mut p = player.write().unwrap();
+            p.set_world(world.clone());
+            p.set_pos(pos);
+            p.reset_fall();
+        }
+
+        self.protocol.send(Packet::Respawn(world.clone(), gamemode)).unwrap();
+        self.protocol.send(Packet::SpawnPosition(world.clone())).unwrap();
+        self.stream_chunks(world, None, ChunkCoord::from_pos(pos));
+        self.protocol.send(Packet::TimeUpdate(world.clone())).unwrap();
+        self.protocol.send(Packet::PlayerPositionAndLook(player.clone())).unwrap();
+
+        // The rain of the old world may still be falling
+        for packet in world.read().unwrap().weather().packets() {
+            self.protocol.send(packet).unwrap();
+        }
+
+        world.write().unwrap().add_player(self.id, player.clone());
+        self.server.update_tracking(world);
+    }
+
+    /// Moves the player, the fields with their flag set are added to the current position and rotation.
+    /// Unlike an absolute teleport this doesn't undo the movement the client made since the last position it sent,
+    /// so it's used to push players, like knockback.
+    pub fn teleport_relative(&self, pos: Coord<f64>, yaw: f32, pitch: f32, flags: TeleportFlags) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (world, old, new, new_yaw, new_pitch) = {
+            let mut p = player.write().unwrap();
+            let old = p.pos();
+            let new = Coord::new(
+                apply_relative(flags, TeleportFlags::X, pos.x, old.x),
+                apply_relative(flags, TeleportFlags::Y, pos.y, old.y),
+                apply_relative(flags, TeleportFlags::Z, pos.z, old.z));
+            let new_yaw = apply_relative(flags, TeleportFlags::YAW, yaw, p.yaw());
+            let new_pitch = apply_relative(flags, TeleportFlags::PITCH, pitch, p.pitch());
+            p.set_pos(new);
+            p.set_rotation(new_yaw, new_pitch);
+            (p.world(), old, new, p.yaw(), p.pitch())
+        };
+
+        let (old_chunk, new_chunk) = (ChunkCoord::from_pos(old), ChunkCoord::from_pos(new));
+        if old_chunk != new_chunk {
+            self.stream_chunks(&world, Some(old_chunk), new_chunk);
+        }
+
+        // 1.7 clients only get the absolute position
+        self.send(Packet::Teleport(pos, yaw, pitch, flags, (new, new_yaw, new_pitch)));
+    }
+
+    /// Runs a chat command, `command` is the message without the leading '/'
+    pub fn handle_command(&self, command: &str) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let args: Vec<&str> = command.split_whitespace().collect();
+        let (name, args) = match args.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return
+        };
+
+        let command = match commands::find_command(name) {
+            Some(v) => v,
+            None => {
+                self.send_chat(format!("Unknown command: /{}", name));
+                return;
+            }
+        };
+
+        if self.server.op_level(self.uuid) < command.level {
+            self.send_chat("You do not have permission to use this command".to_owned());
+            return;
+        }
+
+        let values = match command.syntax {
+            Syntax::Text(_) => Vec::new(),
+            _ => {
+                let context = {
+                    let p = player.read().unwrap();
+                    SelectorContext {
+                        origin: p.pos(),
+                        rotation: Some((p.yaw(), p.pitch())),
+                        players: self.server.player_positions()
+                    }
+                };
+                match command.parse_args(args, &context) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        self.send_chat(e.message(command));
+                        return;
+                    }
+                }
+            }
+        };
+
+        match command.name {
+            "help" => self.command_help(command, &values),
+            "pregen" => self.command_pregen(player, args),
+            "setblock" => self.command_setblock(player, args),
+            "fill" => self.command_fill(player, args),
+            "tp" => self.command_tp(player, &values),
+            "gamerule" => self.command_gamerule(player, args),
+            "bossbar" => self.command_bossbar(args),
+            "team" => self.command_team(args),
+            "summon" => self.command_summon(player, args),
+            "weather" => self.command_weather(player, &values),
+            "toggledownfall" => self.command_toggledownfall(player),
+            "time" => self.command_time(player, args),
+            "effect" => self.command_effect(args),
+            "tell" => self.command_tell(&values),
+            "give" => self.command_give(&values),
+            "kill" => self.command_kill(player, &values),
+            "clear" => self.command_clear(player, &values),
+            "spawnpoint" => self.command_spawnpoint(player, &values),
+            "save-all" => self.command_save_all(),
+            "difficulty" => self.command_difficulty(player, command, &values),
+            _ => unreachable!()
+        }
+    }
+
+    fn send_chat(&self, msg: String) {
+        self.send(Packet::ChatMessage(msg));
+    }
+
+    /// Tells the player how to use the command
+    fn send_usage(&self, name: &str) {
+        let command = commands::find_command(name).expect("unknown command");
+        self.send_chat(format!("Usage: {}", command.usage()));
+    }
+
+    fn command_help(&self, help: &CommandInfo, args: &[ArgValue]) {
+        let page = match args {
+            [ArgValue::Int(page)] => *page as usize,
+            _ => 1
+        };
+
+        let pages = commands::help_pages(self.server.op_level(self.uuid));
+        let commands = match pages.get(page - 1) {
+            Some(v) => v,
+            None => {
+                self.send_chat(ArgError::TooBig(page as i32, pages.len() as i32).message(help));
+                return;
+            }
+        };
+
+        self.send_chat(format!("§2--- Showing help page {} of {} (/help <page>) ---", page, pages.len()));
+        for command in commands {
+            self.send_chat(format!("{} - {}", command.usage(), command.description));
+        }
+    }
+
+    fn command_pregen(&self, player: &Arc<RwLock<Player>>, args: &[&str]) {
+        match args {
+            ["cancel"] => {
+                if !self.server.cancel_pregen() {
+                    self.send_chat("No pregeneration is running".to_owned());
+                }
+            }
+            [radius] => {
+                let radius = match radius.parse::<i32>() {
+                    Ok(v) if (0..=MAX_PREGEN_RADIUS).contains(&v) => v,
+                    _ => {
+                        self.send_chat(format!("Invalid radius: {}", radius));
+                        return;
+                    }
+                };
+
+                let (world, pos) = {
+                    let p = player.read().unwrap();
+                    (p.world(), p.pos())
+                };
+                if self.server.start_pregen(&world, ChunkCoord::from_pos(pos), radius, self.protocol.clone()) {
+                    self.send_chat(format!("Pregenerating chunks within {} chunks", radius));
+                } else {
+                    self.send_chat("A pregeneration is already running".to_owned());
+                }
+            }
+            _ => self.send_usage("pregen")
+        }
+    }
+
+    fn command_tp(&self, player: &Arc<RwLock<Player>>, args: &[ArgValue]) {
+        // The target players are optional, the destination is a player or a position with an optional rotation
+        let (targets, to) = match args {
+            [ArgValue::Players(names), to @ ..] if !to.is_empty() => {
+                (names.iter().filter_map(|v| self.server.find_player(v)).collect::<Vec<_>>(), to)
+            }
+            to => (vec![player.clone()], to)
+        };
+        for target in targets {
+            let (client, world, yaw, pitch) = {
+                let t = target.read().unwrap();
+                (t.client(), t.world(), t.yaw(), t.pitch())
+            };
+            let (pos, yaw, pitch, destination) = match to {
+                [ArgValue::Players(names)] => {
+                    let name = &names[0];
+                    let destination = match self.server.find_player(name) {
+                        Some(v) => v,
+                        None => continue
+                    };
+                    let d = destination.read().unwrap();
+                    if !Arc::ptr_eq(&d.world(), &world) {
+                        self.send_chat("Unable to teleport because players are not in the same dimension".to_owned());
+                        continue;
+                    }
+
+                    (d.pos(), d.yaw(), d.pitch(), name.clone())
+                }
+                [ArgValue::Position(pos), rotation @ ..] => {
+                    let (yaw, pitch) = match rotation {
+                        [ArgValue::Rotation(yaw, pitch)] => (*yaw, *pitch),
+                        _ => (yaw, pitch)
+                    };
+                    (*pos, yaw, pitch, format!("{}, {}, {}", pos.x, pos.y, pos.z))
+                }
+                _ => unreachable!()
+            };
+
+            // The client running the command is already locked
+            let name = if Arc::ptr_eq(&target, player) {
+                self.teleport_to(pos, yaw, pitch);
+                self.username.clone()
+            } else {
+                let client = client.read().unwrap();
+                client.teleport_to(pos, yaw, pitch);
+                client.get_username().map(str::to_owned)
+            };
+            self.send_chat(format!("Teleported {} to {}", name.unwrap_or_default(), destination));
+        }
+    }
+
+    fn command_summon(&self, player: &Arc<RwLock<Player>>, args: &[&str]) {
+        let (world, base) = {
+            let p = player.read().unwrap();
+            (p.world(), p.pos())
+        };
+
+        // The data tag can have spaces in it, so it's everything after the position
+        let (name, pos, nbt) = match args {
+            [name] => (*name, Some(base), None),
+            [name, rest @ ..] if rest.len() >= 3 => {
+                let (pos, nbt) = rest.split_at(3);
+                (*name, commands::parse_pos(pos, base).map(Coord::bottom_center), Some(nbt.join(" ")).filter(|v| !v.is_empty()))
+            }
+            _ => ("", None, None)
+        };
+        let pos = match pos {
+            Some(v) => v,
+            None => {
+                self.send_usage("summon");
+                return;
+            }
+        };
+        let nbt = match nbt.as_deref().map(|v| self.parse_data_tag(v)) {
+            Some(Some(v)) => Some(v),
+            Some(None) => return,
+            None => None
+        };
+
+        let mut entity = match EntityType::from_summon_name(name) {
+            Some(v) => Entity::summoned(server::get_next_entity_id(), v, pos),
+            None => {
+                self.send_chat(format!("Unable to summon {}", name));
+                return;
+            }
+        };
+        if let Some(nbt) = &nbt {
+            entity.apply_nbt(nbt);
+        }
+        // Vanilla removes items without a stack right away
+        if entity.entity_type() == EntityType::Item && entity.item().is_none() {
+            self.send_chat(format!("Unable to summon {}", name));
+            return;
+        }
+
+        let entity = world.write().unwrap().add_entity(entity);
+        self.server.track_entity(&world, &entity);
+        self.send_chat("Object successfully summoned".to_owned());
+    }
+
+    fn command_weather(&self, player: &Arc<RwLock<Player>>, args: &[ArgValue]) {
+        // Like vanilla, the weather lasts 5 to 15 minutes unless a duration in seconds is given
+        let (name, seconds) = match args {
+            [ArgValue::Enum(name)] => (*name, 300 + thread_rng().gen_range(0..600)),
+            [ArgValue::Enum(name), ArgValue::Int(seconds)] => (*name, *seconds),
+            _ => unreachable!()
+        };
+        let weather_type = WeatherType::from_name(name).expect("weather from the signature");
+        let duration = seconds * TPS;
+
+        let world = player.read().unwrap().world();
+        world.write().unwrap().weather_mut().set(weather_type, duration);
+        self.server.broadcast_weather(&world);
+        self.send_chat(match weather_type {
+            WeatherType::Clear => "Changing to clear weather",
+            WeatherType::Rain => "Changing to rainy weather",
+            WeatherType::Thunder => "Changing to rain and thunder"
+        }.to_owned());
+    }
+
+    fn command_toggledownfall(&self, player: &Arc<RwLock<Player>>) {
+        let world = player.read().unwrap().world();
+        world.write().unwrap().weather_mut().toggle_downfall();
+        self.server.broadcast_weather(&world);
+        self.send_chat("Toggled downfall".to_owned());
+    }
+
+    fn command_time(&self, player: &Arc<RwLock<Player>>, args: &[&str]) {
+        let world = player.read().unwrap().world();
+        let changed = match args {
+            ["set", value] => match commands::parse_time_of_day(value) {
+                Some(time) => {
+                    world.write().unwrap().set_time_of_day(time);
+                    Some(format!("Set the time to {}", time))
+                }
+                None => None
+            },
+            ["add", value] => match commands::parse_ticks(value) {
+                Some(time) => {
+                    // The moon goes through its phases in 8 days, whole cycles don't change what the players see
+                    // and leaving them out keeps huge values from overflowing
+                    let mut w = world.write().unwrap();
+                    let time_of_day = w.time_of_day() + time % (8 * DAY_LENGTH);
+                    w.set_time_of_day(time_of_day);
+                    Some(format!("Added {} to the time", time))
+                }
+                None => None
+            },
+            ["query", "daytime"] => {
+                self.send_chat(format!("Time is {}", world.read().unwrap().time_of_day() % DAY_LENGTH));
+                return;
+            }
+            ["query", "gametime"] => {
+                self.send_chat(format!("Time is {}", world.read().unwrap().age()));
+                return;
+            }
+            _ => None
+        };
+
+        match changed {
+            Some(msg) => {
+                self.server.broadcast_to_world(&world, Packet::TimeUpdate(world.clone()));
+                self.send_chat(msg);
+            }
+            None => self.send_usage("time")
+        }
+    }
+
+    fn command_effect(&self, args: &[&str]) {
+        let (name, args) = match args.split_first() {
+            Some((name, args)) if !args.is_empty() => (*name, args),
+            _ => {
+                self.send_usage("effect");
+                return;
+            }
+        };
+        let target = match self.server.find_player(name) {
+            Some(v) => v,
+            None => {
+                self.send_chat("That player cannot be found".to_owned());
+                return;
+            }
+        };
+
+        if args == ["clear"] {
+            let effect_types: Vec<_> = target.read().unwrap().effects().map(|v| v.effect_type).collect();
+            if self.server.remove_effects(&target, &effect_types).is_empty() {
+                self.send_chat(format!("Couldn't take any effects from {} as they do not have any", name));
+            } else {
+                self.send_chat(format!("Took all effects from {}", name));
+            }
+            return;
+        }
+
+        // Effects last 30 seconds unless a duration is given, like in vanilla
+        let effect_type = EffectType::from_name(args[0]);
+        let seconds = args.get(1).map_or(Some(30), |v| v.parse::<i32>().ok().filter(|v| (0..=1_000_000).contains(v)));
+        let amplifier = args.get(2).map_or(Some(0), |v| v.parse::<u8>().ok());
+        let hide_particles = args.get(3).map_or(Some(false), |v| v.parse::<bool>().ok());
+        let (effect_type, seconds, amplifier, hide_particles) = match (effect_type, seconds, amplifier, hide_particles) {
+            (Some(effect_type), Some(seconds), Some(amplifier), Some(hide_particles)) if args.len() <= 4 => (effect_type, seconds, amplifier, hide_particles),
+            _ => {
+                self.send_usage("effect");
+                return;
+            }
+        };
+
+        // A duration of 0 takes the effect away
+        if seconds == 0 {
+            if self.server.remove_effects(&target, &[effect_type]).is_empty() {
+                self.send_chat(format!("Couldn't take {} from {} as they do not have the effect", effect_type.name(), name));
+            } else {
+                self.send_chat(format!("Took {} from {}", effect_type.name(), name));
+            }
+            return;
+        }
+
+        let effect = Effect {
+            hide_particles,
+            ..Effect::new(effect_type, amplifier, seconds * TPS)
+        };
+        self.server.add_effect(&target, effect);
+        self.send_chat(format!("Given {} (ID {}) * {} to {} for {} seconds", effect_type.name(), effect_type as u8, amplifier, name, seconds));
+    }
+
+    fn command_tell(&self, args: &[ArgValue]) {
+        let (targets, msg) = match args {
+            [ArgValue::Players(targets), ArgValue::String(msg)] => (targets, msg),
+            _ => unreachable!()
+        };
+
+        for name in targets {
+            let target = match self.server.find_player(name) {
+                Some(v) => v.read().unwrap().client(),
+                None => continue
+            };
+            let target = target.read().unwrap();
+            if target.id() == self.id {
+                self.send_chat("You can't send a private message to yourself!".to_owned());
+                continue;
+            }
+
+            let (sender, receiver) = (self.username.as_deref().unwrap_or_default(), target.get_username().unwrap_or_default());
+            info!("{} whispers to {}: {}", sender, receiver, msg);
+            target.send(Packet::ChatMessage(format!("§7§o{} whispers to you: {}", sender, msg)));
+            self.send_chat(format!("§7§oYou whisper to {}: {}", receiver, msg));
+        }
+    }
+
+    fn command_give(&self, args: &[ArgValue]) {
+        let (targets, name, rest) = match args {
+            [ArgValue::Players(targets), ArgValue::String(name), rest @ ..] => (targets, name, rest),
+            _ => unreachable!()
+        };
+        let id = match items::id_from_name(name) {
+            Some(v) => v,
+            None => {
+                self.send_chat(format!("There is no such item with name {}", name));
+                return;
+            }
+        };
+        let (count, damage, data_tag) = match rest {
+            [] => (1, 0, None),
+            [ArgValue::Int(count)] => (*count, 0, None),
+            [ArgValue::Int(count), ArgValue::Int(damage)] => (*count, *damage, None),
+            [ArgValue::Int(count), ArgValue::Int(damage), ArgValue::String(data_tag)] => (*count, *damage, Some(data_tag)),
+            _ => unreachable!()
+        };
+
+        let mut item = ItemStack::new(id, count as i8, damage as i16);
+        if let Some(data_tag) = data_tag {
+            item.nbt = match self.parse_data_tag(data_tag) {
+                Some(v) => Some(v),
+                None => return
+            };
+        }
+        for target in targets.iter().filter_map(|v| self.server.find_player(v)) {
+            let client = target.read().unwrap().client();
+            let client = client.read().unwrap();
+            client.give_item(item.clone());
+            self.send_chat(format!("Given [{}] * {} to {}", items::name_from_id(id).unwrap_or_default(), count, client.get_username().unwrap_or_default()));
+        }
+    }
+
+    /// Parses the data tag argument of a command, the player gets told why if it isn't a valid compound
+    fn parse_data_tag(&self, data_tag: &str) -> Option<Compound> {
+        match snbt::parse(data_tag) {
+            Ok(Tag::Compound(v)) => Some(v),
+            Ok(_) => {
+                self.send_chat("Data tag parsing failed: Not a compound".to_owned());
+                None
+            }
+            Err(e) => {
+                self.send_chat(format!("Data tag parsing failed: {}", e));
+                None
+            }
+        }
     }
+
+    /// Returns the players the argument matched, or the player running the command if it wasn't given
+    fn targets_or_self(&self, player: &Arc<RwLock<Player>>, arg: Option<&ArgValue>) -> Vec<Arc<RwLock<Player>>> {
+        match arg {
+            Some(ArgValue::Players(names)) => names.iter().filter_map(|v| self.server.find_player(v)).collect(),
+            _ => vec![player.clone()]
+        }
+    }
+
+    fn command_kill(&self, player: &Arc<RwLock<Player>>, args: &[ArgValue]) {
+        for target in self.targets_or_self(player, args.first()) {
+            let client = target.read().unwrap().client();
+            let client = client.read().unwrap();
+            client.damage(f32::MAX, DamageCause::Generic);
+            self.send_chat(format!("Killed {}", client.get_username().unwrap_or_default()));
+        }
+    }
+
+    fn command_clear(&self, player: &Arc<RwLock<Player>>, args: &[ArgValue]) {
+        let id = match args.get(1) {
+            Some(ArgValue::String(name)) => match items::id_from_name(name) {
+                Some(v) => Some(v),
+                None => {
+                    self.send_chat(format!("There is no such item with name {}", name));
+                    return;
+                }
+            },
+            _ => None
+        };
+
+        for target in self.targets_or_self(player, args.first()) {
+            let client = target.read().unwrap().client();
+            let client = client.read().unwrap();
+            let removed = client.clear_inventory(id);
+            let name = client.get_username().unwrap_or_default();
+            self.send_chat(if removed > 0 {
+                format!("Cleared the inventory of {}, removing {} items", name, removed)
+            } else {
+                format!("Could not clear the inventory of {}, no items to remove", name)
+            });
+        }
+    }
+
+    fn command_spawnpoint(&self, player: &Arc<RwLock<Player>>, args: &[ArgValue]) {
+        // Both the player and the position are optional
+        let (targets, pos) = match args {
+            [ArgValue::Players(names), pos @ ..] => (names.iter().filter_map(|v| self.server.find_player(v)).collect::<Vec<_>>(), pos),
+            pos => (vec![player.clone()], pos)
+        };
+        let pos = match pos {
+            [ArgValue::Position(pos)] => Some(Coord::new(pos.x.floor() as i32, pos.y.floor() as i32, pos.z.floor() as i32)),
+            _ => None
+        };
+
+        for target in targets {
+            let (client, spawn) = {
+                let mut t = target.write().unwrap();
+                let spawn = pos.unwrap_or_else(|| {
+                    let pos = t.pos();
+                    Coord::new(pos.x.floor() as i32, pos.y.floor() as i32, pos.z.floor() as i32)
+                });
+                t.set_spawn_point(Some(spawn), true);
+                (t.client(), spawn)
+            };
+            let client = client.read().unwrap();
+            self.send_chat(format!("Set {}'s spawn point to ({}, {}, {})", client.get_username().unwrap_or_default(), spawn.x, spawn.y, spawn.z));
+        }
+    }
+
+    fn command_difficulty(&self, player: &Arc<RwLock<Player>>, difficulty: &CommandInfo, args: &[ArgValue]) {
+        // Names, their first letters and numbers all work, so it's a word instead of an enum
+        let name = match args {
+            [ArgValue::String(name)] => name,
+            _ => unreachable!()
+        };
+        let difficulty = match Difficulty::from_name(name) {
+            Some(v) => v,
+            None => {
+                self.send_chat(ArgError::Usage.message(difficulty));
+                return;
+            }
+        };
+
+        let world = player.read().unwrap().world();
+        self.server.set_difficulty(&world, difficulty);
+        self.send_chat(format!("Set game difficulty to {}", difficulty.name()));
+    }
+
+    fn command_save_all(&self) {
+        self.send_chat("Saving...".to_owned());
+        self.server.save_all();
+        self.send_chat("Saved the world".to_owned());
+    }
+
+    /// Removes the items with the id from the inventory of the player, or every item if there's no id,
+    /// and sends the inventory again. Returns the number of items removed.
+    fn clear_inventory(&self, id: Option<i16>) -> i32 {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return 0
+        };
+
+        let (removed, windows) = {
+            let mut p = player.write().unwrap();
+            let inventory = p.inventory_mut();
+            // The crafting result isn't an item, it's made again from what's left in the grid
+            inventory.set_slot(crafting::RESULT_SLOT, None);
+            let removed = inventory.clear(id);
+            crafting::update_result(inventory, self.server.recipes());
+            inventory.take_changed();
+
+            // The open window shows the inventory too
+            let windows = self.windows.lock().unwrap();
+            let ids = [Some(PLAYER_WINDOW_ID), windows.open_window().map(|v| v.id())];
+            let items: Vec<_> = ids.into_iter().flatten().filter_map(|id| Some((id, windows.window_items(id, p.inventory())?))).collect();
+            (removed, items)
+        };
+
+        for (id, items) in windows {
+            self.protocol.send(Packet::WindowItems(id, items)).unwrap();
+        }
+
+        removed
+    }
+
+    /// Adds the item to the inventory of the player, it's dropped at its feet if the inventory is full
+    fn give_item(&self, item: ItemStack) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let rest = player.write().unwrap().inventory_mut().add(item);
+        if let Some(rest) = rest {
+            let (world, pos) = {
+                let p = player.read().unwrap();
+                (p.world(), p.pos())
+            };
+            self.drop_item(&world, pos, rest);
+        }
+
+        self.send_inventory_changes();
+    }
+
+    fn command_gamerule(&self, player: &Arc<RwLock<Player>>, args: &[&str]) {
+        let world = player.read().unwrap().world();
+        match args {
+            [] => {
+                let w = world.read().unwrap();
+                let names: Vec<_> = w.game_rules().names().collect();
+                self.send_chat(names.join(", "));
+            }
+            [name] => match world.read().unwrap().game_rules().get(name) {
+                Some(value) => self.send_chat(format!("{} = {}", name, value)),
+                None => self.send_chat(format!("No game rule called '{}' is available", name))
+            },
+            [name, value] => {
+                let mut w = world.write().unwrap();
+                if w.game_rules().get(name).is_none() {
+                    self.send_chat(format!("No game rule called '{}' is available", name));
+                    return;
+                }
+
+                match w.game_rules_mut().set(name, value) {
+                    Some(value) => {
+                        if let Err(e) = w.save_level_dat() {
+                            error!("Failed to save level.dat: {}", e);
+                        }
+
+                        self.send_chat(format!("Game rule {} has been updated to {}", name, value));
+                    }
+                    None => self.send_chat(format!("'{}' is not a valid value for {}", value, name))
+                }
+            }
+            _ => self.send_usage("gamerule")
+        }
+    }
+
+    fn command_bossbar(&self, args: &[&str]) {
+        match args {
+            [] => self.send_usage("bossbar"),
+            ["remove"] => self.remove_boss_bar(),
+            ["progress", progress] => match progress.parse::<f32>() {
+                Ok(v) if (0.0..=1.0).contains(&v) => self.set_boss_bar_progress(v),
+                _ => self.send_chat(format!("Invalid progress: {}", progress))
+            },
+            text => self.set_boss_bar_text(text.join(" "))
+        }
+    }
+
+    fn command_team(&self, args: &[&str]) {
+        let username = self.username.clone().unwrap_or_default();
+        match args {
+            ["create", name, color @ ..] if color.len() <= 1 => {
+                if name.chars().count() > teams::MAX_NAME_LEN {
+                    self.send_chat(format!("Team names can't be longer than {} characters", teams::MAX_NAME_LEN));
+                    return;
+                }
+
+                let color = match color.first() {
+                    Some(color) => match chat::color_index(color) {
+                        Some(v) => Some(v),
+                        None => {
+                            self.send_chat(format!("Invalid color: {}", color));
+                            return;
+                        }
+                    },
+                    None => None
+                };
+
+                if self.server.create_team(Team::new(name.to_string(), color)) {
+                    self.send_chat(format!("Created team {}", name));
+                } else {
+                    self.send_chat(format!("Team {} already exists", name));
+                }
+            }
+            ["remove", name] => {
+                if self.server.remove_team(name) {
+                    self.send_chat(format!("Removed team {}", name));
+                } else {
+                    self.send_chat(format!("Unknown team: {}", name));
+                }
+            }
+            ["join", name] => {
+                if self.server.join_team(name, &username) {
+                    self.send_chat(format!("Joined team {}", name));
+                } else {
+                    self.send_chat(format!("Unknown team: {}", name));
+                }
+            }
+            ["leave"] => match self.server.leave_team(&username) {
+                Some(name) => self.send_chat(format!("Left team {}", name)),
+                None => self.send_chat("You aren't on a team".to_owned())
+            },
+            ["list"] => {
+                let teams = self.server.list_teams();
+                if teams.is_empty() {
+                    self.send_chat("There are no teams".to_owned());
+                }
+
+                for (name, players) in teams {
+                    self.send_chat(format!("{} ({}): {}", name, players.len(), players.join(", ")));
+                }
+            }
+            ["option", name, "friendlyfire", value] => {
+                let friendly_fire = match value.parse::<bool>() {
+                    Ok(v) => v,
+                    Err(_) => {
+                        self.send_chat(format!("Invalid value: {}", value));
+                        return;
+                    }
+                };
+
+                if self.server.update_team(name, |team| team.set_friendly_fire(friendly_fire)) {
+                    self.send_chat(format!("Set friendly fire of team {} to {}", name, friendly_fire));
+                } else {
+                    self.send_chat(format!("Unknown team: {}", name));
+                }
+            }
+            _ => self.send_usage("team")
+        }
+    }
+
+    fn command_setblock(&self, player: &Arc<RwLock<Player>>, args: &[&str]) {
+        let (world, base) = {
+            let p = player.read().unwrap();
+            (p.world(), p.pos())
+        };
+
+        let parsed = match args {
+            [x, y, z, block, meta @ ..] if meta.len() <= 1 =>
+                commands::parse_pos(&[x, y, z], base).zip(commands::parse_block(block)).zip(commands::parse_meta(meta.first())),
+            _ => None
+        };
+
+        match parsed {
+            Some(((pos, block_type), meta)) => {
+                if self.server.set_blocks(&world, &[(pos, block_type, meta)]) == 1 {
+                    self.send_chat("Block placed".to_owned());
+                } else {
+                    self.send_chat("Cannot place block outside of the world".to_owned());
+                }
+            }
+            None => self.send_usage("setblock")
+        }
+    }
+
+    fn command_fill(&self, player: &Arc<RwLock<Player>>, args: &[&str]) {
+        let (world, base) = {
+            let p = player.read().unwrap();
+            (p.world(), p.pos())
+        };
+
+        let parsed = match args {
+            [x1, y1, z1, x2, y2, z2, block, meta @ ..] if meta.len() <= 1 => commands::parse_pos(&[x1, y1, z1], base)
+                .zip(commands::parse_pos(&[x2, y2, z2], base))
+                .zip(commands::parse_block(block).zip(commands::parse_meta(meta.first()))),
+            _ => None
+        };
+
+        let ((from, to), (block_type, meta)) = match parsed {
+            Some(v) => v,
+            None => {
+                self.send_usage("fill");
+                return;
+            }
+        };
+
+        let min = Coord::new(from.x.min(to.x), from.y.min(to.y), from.z.min(to.z));
+        let max = Coord::new(from.x.max(to.x), from.y.max(to.y), from.z.max(to.z));
+        // Far apart positions don't fit in an i32 when subtracted
+        let size = |min: i32, max: i32| max as i64 - min as i64 + 1;
+        let count = size(min.x, max.x).saturating_mul(size(min.y, max.y)).saturating_mul(size(min.z, max.z));
+        if count > commands::MAX_FILL_BLOCKS as i64 {
+            self.send_chat(format!("Too many blocks in the specified area ({} > {})", count, commands::MAX_FILL_BLOCKS));
+            return;
+        }
+
+        let mut blocks = Vec::with_capacity(count as usize);
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    blocks.push((Coord::new(x, y, z), block_type, meta));
+                }
+            }
+        }
+
+        let changed = self.server.set_blocks(&world, &blocks);
+        self.send_chat(format!("{} blocks filled", changed));
+    }
+
+    pub fn handle_entity_action(&self, action: EntityAction) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let sprinting = match action {
+            EntityAction::StartSprinting => true,
+            EntityAction::StopSprinting => false,
+            EntityAction::LeaveBed => {
+                self.wake_up();
+                return;
+            }
+            // TODO: sneaking and horses
+            _ => return
+        };
+
+        let (world, attributes) = {
+            let mut p = player.write().unwrap();
+            p.set_sprinting(sprinting);
+            (p.world(), p.attributes().clone())
+        };
+        self.server.send_to_tracking(&world, self.id, self.flags_metadata());
+        self.server.send_to_tracking(&world, self.id, Packet::EntityProperties(self.id, attributes.clone()));
+        self.send(Packet::EntityProperties(self.id, attributes));
+    }
+
+    pub fn handle_player_abilities(&self, flying: bool) {
+        if let Some(player) = &self.player {
+            player.write().unwrap().set_flying(flying);
+        }
+    }
+
+    pub fn handle_request_stats(&self) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let stats = player.read().unwrap().stats().iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect();
+        self.protocol.send(Packet::Statistics(stats)).unwrap();
+    }
+
+    pub fn handle_left_click(&self, _block_pos: Coord<i32>, _face: BlockFace, status: DigStatus) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        match status {
+            DigStatus::StartedDigging => (),
+            DigStatus::CancelledDigging => (),
+            DigStatus::FinishedDigging => player.write().unwrap().add_exhaustion(HUNGER.dig),
+            DigStatus::DropItemStack => self.drop_held_item(player, true),
+            DigStatus::DropItem => self.drop_held_item(player, false),
+            DigStatus::ShootArrowFinishEating => self.release_item(player)
+        };
+    }
+
+    /// Throws one of the held items, or the whole stack, when the player presses the drop key
+    fn drop_held_item(&self, player: &Arc<RwLock<Player>>, whole_stack: bool) {
+        let item = player.write().unwrap().drop_held_item(whole_stack);
+        if let Some(item) = item {
+            self.send_inventory_changes();
+            self.throw_item(player, item);
+        }
+    }
+
+    /// The player stopped holding right click, which ends eating.
+    /// Food is only eaten once the timer runs out, like in vanilla.
+    fn release_item(&self, player: &Arc<RwLock<Player>>) {
+        let was_eating = {
+            let mut p = player.write().unwrap();
+            let was_eating = p.is_eating();
+            p.stop_eating();
+            was_eating
+        };
+
+        if was_eating {
+            self.send_to_tracking_and_self(player, self.flags_metadata());
+        }
+    }
+
+    pub fn handle_right_click(&self, placement: BlockPlacement) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (item_type, eye, yaw, pitch, world) = {
+            let p = player.read().unwrap();
+            let item_type = p.inventory().held_item().and_then(|v| v.item_type());
+            (item_type, p.eye_pos(), p.yaw(), p.pitch(), p.world())
+        };
+
+        // The client picks the block, it has to be within reach
+        if placement.face.is_some() {
+            let center = placement.pos.bottom_center();
+            let (dx, dy, dz) = (center.x - eye.x, center.y + 0.5 - eye.y, center.z - eye.z);
+            if dx * dx + dy * dy + dz * dz > BLOCK_USE_RANGE * BLOCK_USE_RANGE {
+                return;
+            }
+        }
+
+        // Chests, levers and buttons get used whatever the player is holding
+        if placement.face.is_some() {
+            let (block_type, meta) = world.read().unwrap().get_block_state(placement.pos);
+            if block_type.is_container() {
+                self.open_container(player, &world, placement.pos, block_type);
+                return;
+            }
+
+            if block_type == BlockType::Bed {
+                self.use_bed(player, &world, placement.pos, meta);
+                return;
+            }
+
+            if let Some(meta) = redstone::use_component(block_type, meta) {
+                if block_type == BlockType::StoneButton {
+                    world.write().unwrap().schedule_tick(placement.pos, redstone::STONE_BUTTON_DELAY);
+                }
+
+                self.server.set_blocks(&world, &[(placement.pos, block_type, meta)]);
+                return;
+            }
+        }
+
+        let item_type = match item_type {
+            Some(v) => v,
+            // TODO: place blocks
+            None => return
+        };
+
+        let changed_block = match (placement.face, item_type) {
+            (None, ItemType::Bucket | ItemType::WaterBucket | ItemType::LavaBucket) => {
+                let result = world.read().unwrap().use_bucket(eye, yaw, pitch, item_type);
+                match result {
+                    Some((pos, item)) => {
+                        let rest = player.write().unwrap().replace_held_item(ItemStack::of(item, 1));
+                        if let Some(rest) = rest {
+                            self.throw_item(player, rest);
+                        }
+                        pos
+                    }
+                    None => None
+                }
+            }
+            (None, ItemType::EmptyMap) => {
+                self.use_empty_map(player, &world);
+                None
+            }
+            (None, _) if item_type.food().is_some() => {
+                if player.write().unwrap().start_eating() {
+                    self.send_to_tracking_and_self(player, self.flags_metadata());
+                }
+
+                None
+            }
+            (Some(face), ItemType::FlintAndSteel) => {
+                let tnt = world.write().unwrap().prime_tnt(placement.pos, TNT_FUSE);
+                let pos = match tnt {
+                    Some(tnt) => {
+                        self.server.track_entity(&world, &tnt);
+                        Some(placement.pos)
+                    }
+                    None => world.read().unwrap().ignite(placement.pos, face)
+                };
+
+                if pos.is_some() {
+                    player.write().unwrap().damage_held_item();
+                }
+
+                pos
+            }
+            (Some(face), ItemType::Sign) => {
+                self.place_sign(player, &world, placement.pos, face, yaw);
+                None
+            }
+            (Some(face), ItemType::Painting | ItemType::ItemFrame) => {
+                self.place_hanging(player, &world, placement.pos, face, item_type);
+                None
+            }
+            _ => None
+        };
+
+        if let Some(pos) = changed_block {
+            let block = world.read().unwrap().get_block(pos);
+            if let Some((block_type, meta)) = block {
+                self.server.broadcast_to_world(&world, Packet::BlockChange(pos, block_type, meta));
+            }
+        }
+
+        self.send_inventory_changes();
+    }
+
+    /// Turns the held empty map into a map of the area the player is in
+    fn use_empty_map(&self, player: &Arc<RwLock<Player>>, world: &Arc<RwLock<World>>) {
+        let pos = player.read().unwrap().pos();
+        let dimension = world.read().unwrap().dimension();
+        let id = match self.server.create_map(0, dimension, pos.x.floor() as i32, pos.z.floor() as i32) {
+            Some(v) => v,
+            None => {
+                warn!("Out of map ids, {} can't create a new map", self.username.as_deref().unwrap_or_default());
+                return;
+            }
+        };
+        let map = ItemStack::new(ItemType::FilledMap as i16, 1, id as i16);
+
+        let rest = {
+            let mut p = player.write().unwrap();
+            // Creative players keep the empty map
+            if p.gamemode() == GameMode::Creative {
+                p.inventory_mut().add(map)
+            } else {
+                p.replace_held_item(map)
+            }
+        };
+        if let Some(rest) = rest {
+            self.throw_item(player, rest);
+        }
+    }
+
+    /// Lies down in the bed, which also becomes the spawn point of the player
+    fn use_bed(&self, player: &Arc<RwLock<Player>>, world: &Arc<RwLock<World>>, pos: Coord<i32>, meta: u8) {
+        let head = beds::head_pos(pos, meta);
+        let (can_sleep, (head_type, head_meta)) = {
+            let w = world.read().unwrap();
+            // TODO: vanilla blows up beds in worlds without a sky
+            if !w.has_sky_light() {
+                return;
+            }
+
+            (beds::can_sleep(&w), w.get_block_state(head))
+        };
+        if head_type != BlockType::Bed || player.read().unwrap().bed().is_some() {
+            return;
+        }
+
+        if head_meta & beds::OCCUPIED != 0 {
+            self.send_chat("This bed is occupied".to_owned());
+            return;
+        }
+        if !can_sleep {
+            self.send_chat("You can only sleep at night".to_owned());
+            return;
+        }
+
+        self.set_bed_occupied(world, head, true);
+        {
+            let mut p = player.write().unwrap();
+            p.set_spawn_point(Some(head), false);
+            p.sleep(head);
+            p.set_pos(Coord::new(head.x as f64 + 0.5, head.y as f64 + 0.6875, head.z as f64 + 0.5));
+        }
+        self.send_to_tracking_and_self(player, Packet::UseBed(self.id, head));
+    }
+
+    /// Gets the player out of bed, it stands up on top of the bed
+    pub fn wake_up(&self) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (world, bed, yaw, pitch) = {
+            let mut p = player.write().unwrap();
+            (p.world(), p.wake_up(), p.yaw(), p.pitch())
+        };
+        let bed = match bed {
+            Some(v) => v,
+            None => return
+        };
+
+        self.set_bed_occupied(&world, bed, false);
+        self.send_to_tracking_and_self(player, Packet::Animation(self.id, Animation::LeaveBed));
+        let pos = world.read().unwrap().find_safe_spawn(bed).bottom_center();
+        self.teleport_to(pos, yaw, pitch);
+    }
+
+    /// Marks both halves of the bed with its head at `head` as occupied or free
+    fn set_bed_occupied(&self, world: &Arc<RwLock<World>>, head: Coord<i32>, occupied: bool) {
+        let changes: Vec<_> = {
+            let w = world.read().unwrap();
+            let (block_type, meta) = w.get_block_state(head);
+            if block_type != BlockType::Bed {
+                return;
+            }
+
+            [head, beds::other_half(head, meta)].into_iter()
+                .map(|pos| (pos, w.get_block_state(pos)))
+                .filter(|(_, (block_type, _))| *block_type == BlockType::Bed)
+                .map(|(pos, (block_type, meta))| (pos, block_type, if occupied { meta | beds::OCCUPIED } else { meta & !beds::OCCUPIED }))
+                .collect()
+        };
+        self.server.set_blocks(world, &changes);
+    }
+
+    /// Opens the window of the container at `pos`
+    fn open_container(&self, player: &Arc<RwLock<Player>>, world: &Arc<RwLock<World>>, pos: Coord<i32>, block_type: BlockType) {
+        let (window_type, inventory, progress) = match block_type {
+            BlockType::EnderChest => (WindowType::EnderChest, player.read().unwrap().ender_chest().clone(), None),
+            // The items only stay in tables and anvils while the window is open
+            BlockType::EnchantingTable => (WindowType::EnchantingTable, SharedInventory::new(enchanting::ENCHANTING_TABLE_SIZE), None),
+            BlockType::Anvil => (WindowType::Anvil, SharedInventory::new(anvil::ANVIL_SIZE), None),
+            _ => {
+                let w = world.read().unwrap();
+                let tile_entity = match w.get_tile_entity(pos).filter(|v| v.items().is_some()) {
+                    Some(v) => v,
+                    // Containers set with commands don't have their tile entity yet
+                    None => {
+                        let tile_entity = match TileEntity::for_container(block_type) {
+                            Some(v) => v,
+                            None => return
+                        };
+                        w.set_tile_entity(pos, Some(tile_entity.clone()));
+                        tile_entity
+                    }
+                };
+
+                match tile_entity {
+                    TileEntity::Furnace { items, progress } => (WindowType::Furnace, items, Some(progress)),
+                    TileEntity::Chest { items } => (WindowType::Chest, items, None),
+                    _ => return
+                }
+            }
+        };
+
+        let slots = inventory.lock().size() as u8;
+        self.anvil_name.lock().unwrap().reset();
+        let (id, items, properties) = {
+            let p = player.read().unwrap();
+            let mut windows = self.windows.lock().unwrap();
+            let id = windows.open(window_type, inventory, Some(pos)).id();
+            let properties = progress.map(|v| windows.changed_properties(&v)).unwrap_or_default();
+            (id, windows.window_items(id, p.inventory()).unwrap(), properties)
+        };
+
+        self.protocol.send(Packet::OpenWindow(id, window_type, slots)).unwrap();
+        self.protocol.send(Packet::WindowItems(id, items)).unwrap();
+        for (property, value) in properties {
+            self.protocol.send(Packet::WindowProperty(id, property, value)).unwrap();
+        }
+
+        self.update_enchanting_window(player);
+        self.update_anvil_window(player);
+    }
+
+    /// Returns the id of the open enchanting table window, its items and the enchantments it offers
+    fn enchanting_options(&self, player: &Arc<RwLock<Player>>) -> Option<(u8, SharedInventory, EnchantingOptions)> {
+        let (id, pos, items) = {
+            let windows = self.windows.lock().unwrap();
+            let window = windows.open_window().filter(|v| v.window_type() == WindowType::EnchantingTable)?;
+            (window.id(), window.block()?, window.inventory().clone())
+        };
+
+        let (world, seed) = {
+            let p = player.read().unwrap();
+            (p.world(), p.enchant_seed())
+        };
+        let bookshelves = enchanting::count_bookshelves(&world.read().unwrap(), pos);
+        let options = EnchantingOptions::new(items.lock().get_slot(enchanting::ITEM_SLOT), bookshelves, seed);
+        Some((id, items, options))
+    }
+
+    /// Shows the enchantments the open enchanting table offers, only the properties that changed are sent
+    fn update_enchanting_window(&self, player: &Arc<RwLock<Player>>) {
+        let (id, _, options) = match self.enchanting_options(player) {
+            Some(v) => v,
+            None => return
+        };
+
+        let properties = self.windows.lock().unwrap().changed_properties(&options);
+        for (property, value) in properties {
+            self.protocol.send(Packet::WindowProperty(id, property, value)).unwrap();
+        }
+    }
+
+    /// Enchants the item in the open enchanting table with one of the offered options.
+    /// Outside of creative the option costs as many levels and lapis lazuli as its number, starting at 1.
+    pub fn handle_enchant_item(&self, window_id: u8, option: i8) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (items, options) = match self.enchanting_options(player) {
+            Some((id, items, options)) if id == window_id => (items, options),
+            _ => return
+        };
+        let (option, (enchantment, level)) = match usize::try_from(option).ok().and_then(|v| Some((v, options.get(v)?))) {
+            Some(v) => v,
+            None => return
+        };
+
+        let price = option as i32 + 1;
+        let xp_level = {
+            let mut p = player.write().unwrap();
+            let mut items = items.lock();
+            let creative = p.gamemode() == GameMode::Creative;
+            let lapis = items.get_slot(enchanting::LAPIS_SLOT).filter(|v| enchanting::is_lapis(v)).map_or(0, |v| v.count as i32);
+            if !creative && (p.xp_level() < options.costs[option] || lapis < price) {
+                return;
+            }
+
+            let mut item = items.get_slot(enchanting::ITEM_SLOT).cloned().unwrap();
+            item.set_enchantment(enchantment as i16, level);
+            items.set_slot(enchanting::ITEM_SLOT, Some(item));
+            if !creative {
+                let xp_level = p.xp_level();
+                p.set_xp_level(xp_level - price);
+                let mut lapis = items.get_slot(enchanting::LAPIS_SLOT).cloned().unwrap();
+                lapis.count -= price as i8;
+                items.set_slot(enchanting::LAPIS_SLOT, Some(lapis));
+            }
+
+            p.reroll_enchant_seed();
+            p.xp_level()
+        };
+
+        self.protocol.send(Packet::SetExperience(xp_level)).unwrap();
+        self.refresh_window(&items);
+        self.update_enchanting_window(player);
+    }
+
+    /// Returns the id of the open anvil window, its items and what the anvil makes out of them
+    fn anvil_result(&self, player: &Arc<RwLock<Player>>) -> Option<(u8, SharedInventory, AnvilResult)> {
+        let (id, items) = {
+            let windows = self.windows.lock().unwrap();
+            let window = windows.open_window().filter(|v| v.window_type() == WindowType::Anvil)?;
+            (window.id(), window.inventory().clone())
+        };
+
+        let creative = player.read().unwrap().gamemode() == GameMode::Creative;
+        let rename = self.anvil_name.lock().unwrap().rename();
+        let result = {
+            let items = items.lock();
+            AnvilResult::new(items.get_slot(anvil::INPUT_SLOT), items.get_slot(anvil::MATERIAL_SLOT), rename, creative)
+        };
+        Some((id, items, result))
+    }
+
+    /// Puts what the open anvil makes in its output slot and shows the cost, only the properties that changed are sent
+    fn update_anvil_window(&self, player: &Arc<RwLock<Player>>) {
+        let (id, items, result) = match self.anvil_result(player) {
+            Some(v) => v,
+            None => return
+        };
+
+        let output_changed = {
+            let mut items = items.lock();
+            let changed = items.get_slot(anvil::OUTPUT_SLOT) != result.output.as_ref();
+            items.set_slot(anvil::OUTPUT_SLOT, result.output.clone());
+            changed
+        };
+        if output_changed {
+            self.protocol.send(Packet::SetSlot(id as i8, anvil::OUTPUT_SLOT as i16, result.output.clone())).unwrap();
+        }
+
+        let properties = self.windows.lock().unwrap().changed_properties(&result);
+        for (property, value) in properties {
+            self.protocol.send(Packet::WindowProperty(id, property, value)).unwrap();
+        }
+    }
+
+    /// Sets the name typed into the open anvil
+    pub fn handle_item_name(&self, name: String) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let anvil_open = self.windows.lock().unwrap().open_window().is_some_and(|v| v.window_type() == WindowType::Anvil);
+        if !anvil_open || name.chars().count() > anvil::MAX_NAME_LENGTH {
+            return;
+        }
+
+        self.anvil_name.lock().unwrap().set(name);
+        self.update_anvil_window(player);
+    }
+
+    /// Takes the output of the open anvil, which uses up the items in it and the levels it costs outside of creative.
+    /// Only taking the output with a left click into an empty cursor is supported.
+    fn take_anvil_output(&self, player: &Arc<RwLock<Player>>, window_id: u8, button: i8, mode: u8) {
+        let (items, result) = match self.anvil_result(player) {
+            Some((_, items, result)) => (items, result),
+            None => return
+        };
+
+        let xp_level = {
+            let mut p = player.write().unwrap();
+            let mut windows = self.windows.lock().unwrap();
+            let creative = p.gamemode() == GameMode::Creative;
+            if result.output.is_none() || (!creative && p.xp_level() < result.cost) || windows.cursor().is_some() || (button, mode) != (0, 0) {
+                None
+            } else {
+                windows.click(window_id, anvil::OUTPUT_SLOT as i16, button, mode, p.inventory_mut());
+                let mut items = items.lock();
+                items.set_slot(anvil::INPUT_SLOT, None);
+                let material = items.get_slot(anvil::MATERIAL_SLOT).cloned()
+                    .filter(|v| result.material_used > 0 && v.count > result.material_used)
+                    .map(|v| ItemStack { count: v.count - result.material_used, ..v });
+                items.set_slot(anvil::MATERIAL_SLOT, material);
+                if !creative {
+                    let xp_level = p.xp_level();
+                    p.set_xp_level(xp_level - result.cost);
+                }
+
+                Some(p.xp_level())
+            }
+        };
+
+        match xp_level {
+            Some(xp_level) => {
+                self.anvil_name.lock().unwrap().reset();
+                self.protocol.send(Packet::SetExperience(xp_level)).unwrap();
+                self.refresh_window(&items);
+            }
+            None => self.resync_window(player, window_id)
+        }
+        self.update_anvil_window(player);
+    }
+
+    /// Takes the result of the crafting grid of the player inventory.
+    /// The client can't tell how often a shift click crafts, so it gets the whole inventory again.
+    fn take_crafting_result(&self, player: &Arc<RwLock<Player>>, button: i8, mode: u8) {
+        {
+            let mut p = player.write().unwrap();
+            let mut windows = self.windows.lock().unwrap();
+            windows.take_crafting_result(button, mode, p.inventory_mut(), self.server.recipes());
+            p.inventory_mut().take_changed();
+        }
+
+        self.resync_window(player, PLAYER_WINDOW_ID);
+    }
+
+    /// Moves the items in the crafting grid of the player inventory back into the inventory, the ones that don't fit get dropped
+    fn return_crafting_grid(&self, player: &Arc<RwLock<Player>>) {
+        let items: Vec<_> = {
+            let mut p = player.write().unwrap();
+            let inventory = p.inventory_mut();
+            inventory.set_slot(crafting::RESULT_SLOT, None);
+            crafting::grid_slots().filter_map(|slot| {
+                let item = inventory.get_slot(slot).cloned();
+                inventory.set_slot(slot, None);
+                item
+            }).collect()
+        };
+
+        for item in items {
+            let rest = player.write().unwrap().inventory_mut().add(item);
+            if let Some(rest) = rest {
+                self.throw_item(player, rest);
+            }
+        }
+        self.send_inventory_changes();
+    }
+
+    /// Applies a click in a window, the contents of the window get sent again if it can't be applied
+    pub fn handle_click_window(&self, window_id: u8, slot: i16, button: i8, mode: u8) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        if window_id == PLAYER_WINDOW_ID && slot == crafting::RESULT_SLOT as i16 {
+            self.take_crafting_result(player, button, mode);
+            return;
+        }
+
+        let anvil = self.windows.lock().unwrap().open_window().is_some_and(|v| v.id() == window_id && v.window_type() == WindowType::Anvil);
+        if anvil && slot == anvil::OUTPUT_SLOT as i16 {
+            self.take_anvil_output(player, window_id, button, mode);
+            return;
+        } else if anvil && slot == anvil::INPUT_SLOT as i16 {
+            // The client sends the name of the new item next
+            self.anvil_name.lock().unwrap().reset();
+        }
+
+        let (click, container, crafting_result) = {
+            let mut p = player.write().unwrap();
+            let mut windows = self.windows.lock().unwrap();
+            let click = windows.click(window_id, slot, button, mode, p.inventory_mut());
+            if window_id == PLAYER_WINDOW_ID {
+                crafting::update_result(p.inventory_mut(), self.server.recipes());
+            }
+            // The client already shows the result of the click, except for what the crafting grid makes now
+            let crafting_result = p.inventory_mut().take_changed().contains(&crafting::RESULT_SLOT)
+                .then(|| p.inventory().get_slot(crafting::RESULT_SLOT).cloned());
+            let container = windows.open_window().filter(|v| v.id() == window_id).map(|v| v.inventory().clone());
+            (click, container, crafting_result)
+        };
+
+        if let Some(result) = crafting_result {
+            self.protocol.send(Packet::SetSlot(PLAYER_WINDOW_ID as i8, crafting::RESULT_SLOT as i16, result)).unwrap();
+        }
+
+        match click {
+            // The client guesses where shift clicks and number keys move the items, it might have guessed differently
+            Click::Done if mode != 0 => self.resync_window(player, window_id),
+            Click::Done => (),
+            Click::Drop(item) => self.throw_item(player, item),
+            Click::Rejected => {
+                self.resync_window(player, window_id);
+                return;
+            }
+        }
+
+        self.update_enchanting_window(player);
+        self.update_anvil_window(player);
+
+        // Other players looking into the same chest need to see the change
+        if let Some(container) = container {
+            let world = player.read().unwrap().world();
+            let players = world.read().unwrap().players();
+            for other in players {
+                let client = other.read().unwrap().client();
+                let client = client.read().unwrap();
+                if client.id() != self.id {
+                    client.refresh_window(&container);
+                }
+            }
+        }
+    }
+
+    /// Sends the contents of the window and the item held by the cursor again
+    fn resync_window(&self, player: &Arc<RwLock<Player>>, window_id: u8) {
+        let (items, cursor) = {
+            let p = player.read().unwrap();
+            let windows = self.windows.lock().unwrap();
+            (windows.window_items(window_id, p.inventory()), windows.cursor().cloned())
+        };
+
+        if let Some(items) = items {
+            self.protocol.send(Packet::WindowItems(window_id, items)).unwrap();
+        }
+        self.protocol.send(Packet::SetSlot(-1, -1, cursor)).unwrap();
+    }
+
+    /// Sends the contents of the open window again if it shows the container
+    pub fn refresh_window(&self, container: &SharedInventory) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let p = player.read().unwrap();
+        let windows = self.windows.lock().unwrap();
+        if let Some(window) = windows.open_window().filter(|v| v.inventory() == container) {
+            let items = windows.window_items(window.id(), p.inventory()).unwrap();
+            self.protocol.send(Packet::WindowItems(window.id(), items)).unwrap();
+        }
+    }
+
+    /// Shows the progress of the furnace if the player has its window open, only the properties that changed are sent
+    pub fn update_furnace_window(&self, update: &FurnaceUpdate) {
+        let (id, properties) = {
+            let mut windows = self.windows.lock().unwrap();
+            let id = match windows.open_window().filter(|v| v.block() == Some(update.pos)) {
+                Some(v) => v.id(),
+                None => return
+            };
+            (id, windows.changed_properties(&update.progress))
+        };
+
+        for (property, value) in properties {
+            self.protocol.send(Packet::WindowProperty(id, property, value)).unwrap();
+        }
+
+        if update.items_changed {
+            self.refresh_window(&update.items);
+        }
+    }
+
+    /// Closes the window, the item held by the cursor goes back into the inventory
+    pub fn handle_close_window(&self, window_id: u8) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let returned = {
+            let mut windows = self.windows.lock().unwrap();
+            let open = windows.open_window().is_some_and(|v| v.id() == window_id);
+            let returned = open.then(|| Self::returned_items(&windows)).flatten();
+            windows.close(window_id);
+            returned
+        };
+
+        if window_id == PLAYER_WINDOW_ID {
+            self.return_crafting_grid(player);
+        }
+        self.return_cursor(player);
+        if let Some(items) = returned {
+            self.return_items(player, &items);
+        }
+    }
+
+    /// Closes the open window and the inventory when the player leaves,
+    /// so the items in the crafting grid and the cursor go back into the inventory
+    pub fn close_windows(&self) {
+        let open = self.windows.lock().unwrap().open_window().map(|v| v.id());
+        if let Some(id) = open {
+            self.handle_close_window(id);
+        }
+
+        self.handle_close_window(PLAYER_WINDOW_ID);
+    }
+
+    /// Closes the window of the block at `pos` if the player has it open, like when the block is destroyed
+    pub fn close_window_at(&self, pos: Coord<i32>) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (closed, returned) = {
+            let mut windows = self.windows.lock().unwrap();
+            let open = windows.open_window().is_some_and(|v| v.block() == Some(pos));
+            let returned = open.then(|| Self::returned_items(&windows)).flatten();
+            (windows.close_at(pos), returned)
+        };
+
+        if let Some(id) = closed {
+            self.protocol.send(Packet::CloseWindow(id)).unwrap();
+            self.return_cursor(player);
+            if let Some(items) = returned {
+                self.return_items(player, &items);
+            }
+        }
+    }
+
+    /// Returns the items of the open window if they go back to the player when it closes
+    fn returned_items(windows: &WindowManager) -> Option<SharedInventory> {
+        let window = windows.open_window().filter(|v| v.window_type().returns_items())?;
+        // The output of an anvil is made out of the other items
+        if window.window_type() == WindowType::Anvil {
+            window.inventory().lock().set_slot(anvil::OUTPUT_SLOT, None);
+        }
+
+        Some(window.inventory().clone())
+    }
+
+    /// Moves the items of the window into the inventory, the ones that don't fit get dropped
+    fn return_items(&self, player: &Arc<RwLock<Player>>, items: &SharedInventory) {
+        let items: Vec<_> = {
+            let mut items = items.lock();
+            (0..items.size()).filter_map(|slot| {
+                let item = items.get_slot(slot).cloned();
+                items.set_slot(slot, None);
+                item
+            }).collect()
+        };
+
+        for item in items {
+            let rest = player.write().unwrap().inventory_mut().add(item);
+            if let Some(rest) = rest {
+                self.throw_item(player, rest);
+            }
+        }
+
+        self.send_inventory_changes();
+    }
+
+    /// Puts the item held by the cursor back into the inventory, it gets dropped if the inventory is full
+    fn return_cursor(&self, player: &Arc<RwLock<Player>>) {
+        let item = match self.windows.lock().unwrap().take_cursor() {
+            Some(v) => v,
+            None => return
+        };
+
+        let rest = player.write().unwrap().inventory_mut().add(item);
+        if let Some(rest) = rest {
+            self.throw_item(player, rest);
+        }
+
+        self.send_inventory_changes();
+    }
+
+    /// Places a sign against the face of the block and opens the sign editor
+    fn place_sign(&self, player: &Arc<RwLock<Player>>, world: &Arc<RwLock<World>>, against: Coord<i32>, face: BlockFace, yaw: f32) {
+        let pos = face.adjacent(against);
+        let (block_type, meta) = match face {
+            // Signs can't hang from the ceiling
+            BlockFace::YM => return,
+            // Rotation in 1/16th of a full turn, facing the player
+            BlockFace::YP => (BlockType::StandingSign, (((yaw + 180.0) * 16.0 / 360.0 + 0.5).floor() as i32 & 15) as u8),
+            _ => (BlockType::WallSign, face as u8)
+        };
+
+        let replaceable = world.read().unwrap().get_block(pos).is_some_and(|(v, _)| v.is_replaceable());
+        if !replaceable || self.server.set_blocks(world, &[(pos, block_type, meta)]) == 0 {
+            return;
+        }
+
+        world.read().unwrap().set_tile_entity(pos, Some(TileEntity::empty_sign()));
+        {
+            let mut p = player.write().unwrap();
+            p.consume_held_item();
+            p.set_editing_sign(Some(pos));
+        }
+
+        self.protocol.send(Packet::OpenSignEditor(pos)).unwrap();
+    }
+
+    /// Hangs a painting or item frame on the side of the block
+    fn place_hanging(&self, player: &Arc<RwLock<Player>>, world: &Arc<RwLock<World>>, against: Coord<i32>, face: BlockFace, item_type: ItemType) {
+        // Only walls can be hung on
+        if face.horizontal_index().is_none() {
+            return;
+        }
+
+        let pos = face.adjacent(against);
+        let entity = {
+            let mut w = world.write().unwrap();
+            let wall = w.get_block(against).is_some_and(|(v, _)| v.is_solid());
+            let replaceable = w.get_block(pos).is_some_and(|(v, _)| v.is_replaceable());
+            if !wall || !replaceable || w.has_hanging_entity(pos, face) {
+                return;
+            }
+
+            let id = server::get_next_entity_id();
+            w.add_entity(match item_type {
+                ItemType::Painting => Entity::painting(id, pos, face, Motive::ALL[thread_rng().gen_range(0..Motive::ALL.len())]),
+                _ => Entity::item_frame(id, pos, face)
+            })
+        };
+
+        player.write().unwrap().consume_held_item();
+        self.server.track_entity(world, &entity);
+    }
+
+    /// Sets the text of the sign the player placed last.
+    /// Only the plain text of the lines is kept, like in vanilla.
+    pub fn handle_update_sign(&self, pos: Coord<i32>, lines: [String; 4]) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let world = {
+            let mut p = player.write().unwrap();
+            if p.editing_sign() != Some(pos) {
+                warn!("{} tried to change a sign they didn't place", self.username.as_deref().unwrap_or_default());
+                return;
+            }
+
+            p.set_editing_sign(None);
+            p.world()
+        };
+
+        if !matches!(world.read().unwrap().get_tile_entity(pos), Some(TileEntity::Sign { .. })) {
+            return;
+        }
+
+        let mut plain_lines: [String; 4] = Default::default();
+        for (line, plain_line) in lines.iter().zip(&mut plain_lines) {
+            let plain = match json::from_str(line) {
+                Ok(v) => chat::to_plain_text(&v),
+                Err(_) => {
+                    warn!("Invalid sign line: {}", line);
+                    return;
+                }
+            };
+
+            let plain: String = chat::strip_formatting(&plain).chars()
+                .filter(|&c| c >= ' ' && c != '\x7f')
+                .take(MAX_SIGN_LINE_LENGTH)
+                .collect();
+            *plain_line = json::Value::String(plain).to_string();
+        }
+
+        self.server.set_tile_entity(&world, pos, TileEntity::Sign { lines: plain_lines });
+    }
+
+    pub fn handle_held_item_change(&self, slot: usize) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let was_eating = {
+            let mut p = player.write().unwrap();
+            let was_eating = p.is_eating();
+            p.stop_eating();
+            p.inventory_mut().set_held_slot(slot);
+            was_eating
+        };
+
+        if was_eating {
+            self.send_to_tracking_and_self(player, self.flags_metadata());
+        }
+    }
+
+    /// Sets a slot of the inventory of a creative player, slot -1 drops the item
+    pub fn handle_creative_inventory_action(&self, slot: i16, item: Option<ItemStack>) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        if player.read().unwrap().gamemode() != GameMode::Creative {
+            warn!("{} sent a creative inventory action outside of creative mode", self.username.as_deref().unwrap_or_default());
+            self.kick(chat::text("Creative inventory actions are only allowed in creative mode"));
+            return;
+        }
+
+        let item = match item {
+            Some(mut item) if item.is_known() => {
+                item.count = item.count.min(item.max_stack_size());
+                Some(item)
+            }
+            Some(item) => {
+                warn!("Unknown item id in creative inventory action: {}", item.id);
+                return;
+            }
+            None => None
+        };
+
+        if slot == -1 {
+            if let Some(item) = item.filter(|v| v.count > 0) {
+                self.throw_item(player, item);
+            }
+
+            return;
+        }
+
+        // Slot 0 is the crafting output
+        if !(1..INVENTORY_SIZE as i16).contains(&slot) {
+            return;
+        }
+
+        let mut player = player.write().unwrap();
+        player.inventory_mut().set_slot(slot as usize, item);
+        // The client already knows about this change
+        player.inventory_mut().take_changed();
+    }
+
+    /// Saves the pages of the held book, signing it turns it into a written book.
+    /// `book` is the book as the client sees it after editing.
+    pub fn handle_edit_book(&self, book: Option<ItemStack>, sign: bool) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let book = match book {
+            Some(v) => v,
+            None => return
+        };
+
+        let pages = match book.book_pages() {
+            Some(v) => v,
+            None => {
+                warn!("{} sent an invalid book", self.username.as_deref().unwrap_or_default());
+                return;
+            }
+        };
+
+        {
+            let mut p = player.write().unwrap();
+            let mut held = match p.inventory().held_item() {
+                Some(v) if v.item_type() == Some(ItemType::WritableBook) => v.clone(),
+                _ => return
+            };
+
+            if sign {
+                let title = match book.book_title() {
+                    Some(v) if book.item_type() == Some(ItemType::WrittenBook) && v.chars().count() <= MAX_BOOK_TITLE_LENGTH => v,
+                    _ => return
+                };
+
+                let mut nbt = Compound::new();
+                nbt.insert("author", Tag::String(self.username.clone().unwrap_or_default()));
+                nbt.insert("title", Tag::String(title.to_owned()));
+                held = ItemStack { nbt: Some(nbt), ..ItemStack::of(ItemType::WrittenBook, held.count) };
+                // Written books store their pages as chat components, the client already made them into those
+                held.set_book_pages(pages);
+            } else {
+                if book.item_type() != Some(ItemType::WritableBook) {
+                    return;
+                }
+
+                held.set_book_pages(pages);
+            }
+
+            p.inventory_mut().set_held_item(Some(held));
+        }
+
+        self.send_inventory_changes();
+    }
+
+    /// Throws the item from just below the eyes of the player in the direction it's looking
+    fn throw_item(&self, player: &Arc<RwLock<Player>>, item: ItemStack) {
+        let (world, eye, yaw, pitch) = {
+            let p = player.read().unwrap();
+            (p.world(), p.eye_pos(), p.yaw(), p.pitch())
+        };
+
+        let mut entity = Entity::dropped_item(server::get_next_entity_id(), Coord::new(eye.x, eye.y - DROP_HEIGHT_OFFSET, eye.z), item);
+        entity.set_velocity(entities::throw_velocity(yaw, pitch));
+        let entity = world.write().unwrap().add_entity(entity);
+        self.server.track_entity(&world, &entity);
+    }
+
+    /// Spawns an item entity at the position
+    fn drop_item(&self, world: &Arc<RwLock<World>>, pos: Coord<f64>, item: ItemStack) {
+        let entity = world.write().unwrap().add_entity(Entity::dropped_item(server::get_next_entity_id(), pos, item));
+        self.server.track_entity(world, &entity);
+    }
+
+    /// Updates the eating timer and effects of the player
+    /// and sends its movement to the other players, called every tick
+    pub fn tick(&self) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let world = player.read().unwrap().world();
+        let in_bed = {
+            let mut p = player.write().unwrap();
+            p.tick_sleep();
+            p.bed().is_some()
+        };
+        // Players get out of bed once the night is over
+        if in_bed && !beds::can_sleep(&world.read().unwrap()) {
+            self.wake_up();
+        }
+
+        self.update_held_map(player, &world);
+
+        let difficulty = world.read().unwrap().difficulty();
+        let (finished_eating, expired, attributes, health_changed, food_changed, starvation) = {
+            let mut p = player.write().unwrap();
+            let (health, food) = (p.health(), (p.food_level(), p.saturation()));
+            let finished_eating = p.tick_eating();
+            let attributes = p.attributes().clone();
+            let expired = p.tick_effects();
+            let attributes = (*p.attributes() != attributes).then(|| p.attributes().clone());
+            let starvation = p.tick_hunger(difficulty);
+            (finished_eating, expired, attributes, p.health() != health, (p.food_level(), p.saturation()) != food, starvation)
+        };
+
+        for effect_type in expired {
+            self.send_to_tracking_and_self(player, Packet::RemoveEntityEffect(self.id, effect_type));
+        }
+
+        if let Some(attributes) = attributes {
+            self.send_to_tracking_and_self(player, Packet::EntityProperties(self.id, attributes));
+        }
+
+        if finished_eating || health_changed || food_changed {
+            let (health, food, saturation) = {
+                let p = player.read().unwrap();
+                (p.health(), p.food_level(), p.saturation())
+            };
+
+            self.protocol.send(Packet::UpdateHealth(health, food, saturation)).unwrap();
+            if health_changed && health <= 0.0 {
+                self.die(player, DamageCause::Generic);
+            }
+        }
+
+        if finished_eating {
+            self.send_to_tracking_and_self(player, self.flags_metadata());
+            self.send_inventory_changes();
+        }
+
+        if let Some(damage) = starvation {
+            self.damage(damage, DamageCause::Starvation);
+        }
+
+        self.send_movement(player);
+    }
+
+    /// Sends the held map whole when the player starts holding it, and draws the area around the player on it once a second
+    fn update_held_map(&self, player: &Arc<RwLock<Player>>, world: &Arc<RwLock<World>>) {
+        let (map_id, pos) = {
+            let p = player.read().unwrap();
+            (p.inventory().held_item().and_then(ItemStack::map_id), p.pos())
+        };
+
+        let id = match map_id {
+            Some(v) => v,
+            None => {
+                *self.shown_map.lock().unwrap() = None;
+                return;
+            }
+        };
+
+        let newly_held = self.shown_map.lock().unwrap().replace(id) != Some(id);
+        if newly_held {
+            if let Some(packet) = self.server.full_map(id) {
+                self.send(packet);
+            }
+        }
+
+        // The changed pixels get sent to everyone holding the map at the end of the tick
+        if world.read().unwrap().age() % TPS as i64 == 0 {
+            self.server.render_map(id, world, pos);
+        }
+    }
+
+    /// Sends how the player moved since the last tick to the other players
+    fn send_movement(&self, player: &Arc<RwLock<Player>>) {
+        let (movement, world, pos, eye, yaw, pitch, on_ground) = {
+            let mut p = player.write().unwrap();
+            (p.take_movement(), p.world(), p.pos(), p.eye_pos(), p.yaw(), p.pitch(), p.on_ground())
+        };
+
+        let packet = match movement {
+            Some(Movement::Look) => Packet::EntityLook(self.id, yaw, pitch, on_ground),
+            Some(Movement::RelativeMove(delta)) => Packet::EntityRelativeMove(self.id, delta, on_ground),
+            Some(Movement::LookAndRelativeMove(delta)) => Packet::EntityLookAndRelativeMove(self.id, delta, yaw, pitch, on_ground),
+            Some(Movement::Teleport) => Packet::EntityTeleport(self.id, pos, yaw, pitch, on_ground),
+            None => return
+        };
+        self.server.send_to_tracking(&world, self.id, packet);
+
+        // The wither carrying the boss bar has to stay in front of the player
+        if let Some(boss_bar) = self.boss_bar.lock().unwrap().as_mut() {
+            if boss_bar.move_to(boss_bar::position(eye, yaw, pitch)) {
+                self.send(Packet::EntityTeleport(boss_bar.entity_id(), boss_bar.pos(), 0.0, 0.0, false));
+            }
+        }
+
+        if matches!(movement, Some(Movement::Look | Movement::LookAndRelativeMove(_) | Movement::Teleport)) {
+            self.server.send_to_tracking(&world, self.id, Packet::EntityHeadLook(self.id, yaw));
+        }
+    }
+
+    /// Shows a boss bar with the text at the top of the screen, or changes the text of the one shown.
+    /// TODO: spawn the wither again after the player changes dimensions
+    pub fn set_boss_bar_text(&self, text: String) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let mut boss_bar = self.boss_bar.lock().unwrap();
+        match boss_bar.as_mut() {
+            Some(v) => {
+                v.set_text(text);
+                self.send(Packet::EntityMetadata(v.entity_id(), vec![(metadata::CUSTOM_NAME_INDEX, MetadataValue::String(v.text().to_owned()))]));
+            }
+            None => {
+                let pos = {
+                    let p = player.read().unwrap();
+                    boss_bar::position(p.eye_pos(), p.yaw(), p.pitch())
+                };
+                let bar = BossBar::new(server::get_next_entity_id(), pos, text);
+                self.send(Packet::SpawnMob(bar.entity_id(), boss_bar::WITHER_TYPE, pos, bar.metadata()));
+                *boss_bar = Some(bar);
+            }
+        }
+    }
+
+    /// Sets how full the boss bar is, from 0 to 1
+    pub fn set_boss_bar_progress(&self, progress: f32) {
+        if let Some(boss_bar) = self.boss_bar.lock().unwrap().as_mut() {
+            boss_bar.set_progress(progress);
+            self.send(Packet::EntityMetadata(boss_bar.entity_id(), vec![(metadata::HEALTH_INDEX, MetadataValue::Float(boss_bar.health()))]));
+        }
+    }
+
+    pub fn remove_boss_bar(&self) {
+        if let Some(boss_bar) = self.boss_bar.lock().unwrap().take() {
+            self.send(Packet::DestroyEntities(vec![boss_bar.entity_id()]));
+        }
+    }
+
+    /// Sends the inventory slots that changed to the client
+    fn send_inventory_changes(&self) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let mut player = player.write().unwrap();
+        for slot in player.inventory_mut().take_changed() {
+            let item = player.inventory().get_slot(slot).cloned();
+            self.protocol.send(Packet::SetSlot(0, slot as i16, item)).unwrap();
+        }
+    }
+
+    /// Returns the Entity Metadata packet with the current entity flags of the player
+    fn flags_metadata(&self) -> Packet {
+        let flags = self.player.as_ref().map_or(EntityFlags::default(), |v| v.read().unwrap().entity_flags());
+        Packet::EntityMetadata(self.id, vec![(metadata::FLAGS_INDEX, MetadataValue::Byte(flags.bits() as i8))])
+    }
+
+    pub fn handle_use_entity(&self, target: u32, action: UseEntityAction) {
+        if action == UseEntityAction::Attack {
+            self.attack(target);
+            return;
+        }
+
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        if player.read().unwrap().vehicle().is_some() {
+            return;
+        }
+
+        let world = player.read().unwrap().world();
+        let entity = match world.read().unwrap().get_entity(target) {
+            Some(v) => v,
+            None => return
+        };
+
+        if entity.read().unwrap().entity_type() == EntityType::ItemFrame {
+            // The client sends both actions for a click, using the frame twice would skip a rotation
+            if action == UseEntityAction::Interact {
+                self.use_item_frame(player, &entity);
+            }
+
+            return;
+        }
+
+        {
+            let mut entity = entity.write().unwrap();
+            if !entity.entity_type().is_rideable() || entity.passenger().is_some() {
+                return;
+            }
+
+            entity.set_passenger(Some(self.id));
+        }
+
+        player.write().unwrap().set_vehicle(Some(target));
+        self.send_to_tracking_and_self(player, Packet::AttachEntity(self.id, Some(target), false));
+    }
+
+    /// Puts one of the held items in the item frame, or rotates the item already in it
+    fn use_item_frame(&self, player: &Arc<RwLock<Player>>, frame: &Arc<RwLock<Entity>>) {
+        let metadata = {
+            let mut frame = frame.write().unwrap();
+            if frame.item().is_some() {
+                frame.rotate();
+                (metadata::ITEM_FRAME_ROTATION_INDEX, MetadataValue::Byte(frame.rotation() as i8))
+            } else {
+                let mut p = player.write().unwrap();
+                let item = match p.inventory().held_item() {
+                    Some(v) => ItemStack { count: 1, ..v.clone() },
+                    None => return
+                };
+
+                p.consume_held_item();
+                frame.set_item(Some(item.clone()));
+                (metadata::ITEM_FRAME_ITEM_INDEX, MetadataValue::Slot(Some(item)))
+            }
+        };
+
+        let id = frame.read().unwrap().id();
+        let world = player.read().unwrap().world();
+        self.server.send_to_tracking(&world, id, Packet::EntityMetadata(id, vec![metadata]));
+        self.send_inventory_changes();
+    }
+
+    /// Knocks the item out of an item frame, or the item frame or painting off the wall.
+    /// Creative players don't get the items dropped.
+    fn break_hanging(&self, world: &Arc<RwLock<World>>, entity: &Arc<RwLock<Entity>>, gamemode: GameMode) {
+        let (id, pos, item) = {
+            let mut e = entity.write().unwrap();
+            (e.id(), e.pos(), e.take_item())
+        };
+        // The item is dropped from the middle of the block the entity hangs in
+        let drop_pos = Coord::new(pos.x + 0.5, pos.y + 0.5, pos.z + 0.5);
+
+        let drop = match item {
+            Some(item) => {
+                self.server.send_to_tracking(world, id, Packet::EntityMetadata(id, vec![
+                    (metadata::ITEM_FRAME_ITEM_INDEX, MetadataValue::Slot(None)),
+                    (metadata::ITEM_FRAME_ROTATION_INDEX, MetadataValue::Byte(0))
+                ]));
+                item
+            }
+            None => {
+                let entity_type = entity.read().unwrap().entity_type();
+                world.write().unwrap().remove_entity(id);
+                self.server.untrack_entities(world, &[id]);
+                let item_type = match entity_type {
+                    EntityType::Painting => ItemType::Painting,
+                    _ => ItemType::ItemFrame
+                };
+                ItemStack::of(item_type, 1)
+            }
+        };
+
+        if gamemode != GameMode::Creative {
+            self.drop_item(world, drop_pos, drop);
+        }
+    }
+
+    /// Punches the target, only players and hanging entities can be hurt for now
+    fn attack(&self, target: u32) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (world, gamemode) = {
+            let p = player.read().unwrap();
+            (p.world(), p.gamemode())
+        };
+        if target == self.id || gamemode == GameMode::Spectator {
+            return;
+        }
+
+        let entity = world.read().unwrap().get_entity(target);
+        if let Some(entity) = entity {
+            if entity.read().unwrap().facing().is_some() {
+                self.break_hanging(&world, &entity, gamemode);
+            }
+
+            return;
+        }
+
+        let target = match world.read().unwrap().get_player(target) {
+            Some(v) => v,
+            None => return
+        };
+
+        let (target_client, target_gamemode) = {
+            let t = target.read().unwrap();
+            (t.client(), t.gamemode())
+        };
+        if target_gamemode != GameMode::Survival && target_gamemode != GameMode::Adventure {
+            return;
+        }
+
+        let name = self.username.clone().unwrap_or_default();
+        let target_name = target_client.read().unwrap().get_username().unwrap_or_default().to_owned();
+        if !self.server.can_hurt(&name, &target_name) {
+            return;
+        }
+
+        target_client.read().unwrap().damage(FIST_DAMAGE, DamageCause::Player(self.id, name));
+    }
+
+    pub fn handle_steer_vehicle(&self, _sideways: f32, forward: f32, _jump: bool, unmount: bool) {
+        let player = match &self.player {
+            Some(v) => v,
+            None => return
+        };
+
+        let (vehicle, world, yaw) = {
+            let p = player.read().unwrap();
+            match p.vehicle() {
+                Some(v) => (v, p.world(), p.yaw()),
+                None => return
+            }
+        };
+
+        let entity = world.read().unwrap().get_entity(vehicle);
+        if unmount {
+            if let Some(entity) = entity {
+                entity.write().unwrap().set_passenger(None);
+            }
+
+            player.write().unwrap().set_vehicle(None);
+            self.send_to_tracking_and_self(player, Packet::AttachEntity(self.id, None, false));
+            return;
+        }
+
+        if let Some(entity) = entity {
+            entity.write().unwrap().steer(yaw, forward);
+        }
+    }
+
+    pub fn send(&self, packet: Packet) {
+        self.protocol.send(packet).unwrap();
+    }
+
+    /// Sends the packet to the player and the players tracking it
+    fn send_to_tracking_and_self(&self, player: &Arc<RwLock<Player>>, packet: Packet) {
+        let world = player.read().unwrap().world();
+        self.server.send_to_tracking(&world, self.id, packet.clone());
+        self.send(packet);
+    }
+}
+
+/// Returns the value added to the current one if the flag is set
+fn apply_relative<T: Add<Output = T>>(flags: TeleportFlags, flag: TeleportFlags, value: T, current: T) -> T {
+    if flags.contains(flag) { current + value } else { value }
 }
@@ -0,0 +1,731 @@
+//! Parsing of chat command arguments
+
+use num_traits::FromPrimitive;
+use rand::thread_rng;
+use rand::seq::SliceRandom;
+
+use crate::blocks::BlockType;
+use crate::coord::Coord;
+
+/// Permission level needed for commands that change the world, like /setblock
+pub const CHEAT_LEVEL: u8 = 2;
+
+/// Permission level needed for commands that manage the server, like /save-all
+pub const ADMIN_LEVEL: u8 = 4;
+
+/// Maximum number of blocks /fill changes at once
+pub const MAX_FILL_BLOCKS: usize = 32768;
+
+/// Time of day `/time set day` sets, the same as vanilla
+pub const DAY_TIME: i64 = 1000;
+
+/// Time of day `/time set night` sets, the same as vanilla
+pub const NIGHT_TIME: i64 = 13000;
+
+/// Number of commands /help lists on each page, the same as vanilla
+pub const HELP_PAGE_SIZE: usize = 7;
+
+/// A command players can run, which /help lists
+#[derive(Debug)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// Other names the command can be run by
+    pub aliases: &'static [&'static str],
+    /// Permission level needed to run the command
+    pub level: u8,
+    pub syntax: Syntax,
+    pub description: &'static str
+}
+
+impl CommandInfo {
+    /// Returns how to use the command, like `/weather <clear|rain|thunder> [duration in seconds]`
+    pub fn usage(&self) -> String {
+        match self.syntax {
+            Syntax::Text(v) => v.to_owned(),
+            Syntax::Args(args) => usage(self.name, args),
+            Syntax::Overloads(signatures) => signatures.iter().map(|v| usage(self.name, v)).collect::<Vec<_>>().join(" OR ")
+        }
+    }
+
+    /// Parses the arguments with the signature of the command, commands with a text syntax parse their own
+    pub fn parse_args(&self, args: &[&str], context: &SelectorContext) -> Result<Vec<ArgValue>, ArgError> {
+        match self.syntax {
+            Syntax::Text(_) => Ok(Vec::new()),
+            Syntax::Args(signature) => parse_args(signature, args, context),
+            Syntax::Overloads(signatures) => match signatures.iter().find(|v| fits(v, args.len())) {
+                Some(signature) => parse_args(signature, args, context),
+                None => Err(ArgError::Usage)
+            }
+        }
+    }
+}
+
+/// The arguments a command takes
+#[derive(Debug)]
+pub enum Syntax {
+    /// Usage of a command that parses its own arguments
+    Text(&'static str),
+    /// Arguments the dispatcher parses before running the command
+    Args(&'static [Arg]),
+    /// Signatures the dispatcher picks from by the number of words given, like the player or position /tp takes
+    Overloads(&'static [&'static [Arg]])
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ArgType {
+    /// A whole number between the bounds
+    Int { min: i32, max: i32 },
+    /// The name of an online player or a target selector
+    Player,
+    /// One of the words
+    Enum(&'static [&'static str]),
+    /// Any single word
+    Word,
+    /// Three coordinates, `~` makes them relative to the position of the player running the command.
+    /// Whole x and z coordinates are moved to the center of the block when `center` is set.
+    Position { center: bool },
+    /// A yaw and a pitch, `~` makes them relative to the rotation of the player running the command
+    Rotation,
+    /// The rest of the arguments, spaces included
+    Greedy
+}
+
+impl ArgType {
+    /// Number of words the argument takes, the greedy argument takes at least one
+    const fn width(self) -> usize {
+        match self {
+            ArgType::Position { .. } => 3,
+            ArgType::Rotation => 2,
+            _ => 1
+        }
+    }
+}
+
+/// An argument in the signature of a command
+#[derive(Copy, Clone, Debug)]
+pub struct Arg {
+    pub name: &'static str,
+    pub arg_type: ArgType,
+    /// Optional arguments are only given when the arguments after them can take the rest of the words
+    pub optional: bool
+}
+
+impl Arg {
+    pub const fn required(name: &'static str, arg_type: ArgType) -> Self {
+        Self { name, arg_type, optional: false }
+    }
+
+    pub const fn optional(name: &'static str, arg_type: ArgType) -> Self {
+        Self { name, arg_type, optional: true }
+    }
+}
+
+/// A parsed argument, in the order of the signature. Optional arguments that weren't given are left out.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArgValue {
+    Int(i32),
+    /// Names of the players the name or selector matched
+    Players(Vec<String>),
+    Enum(&'static str),
+    String(String),
+    Position(Coord<f64>),
+    /// Yaw wrapped to -180 - 180 and pitch clamped to -90 - 90
+    Rotation(f32, f32)
+}
+
+/// Why the arguments of a command couldn't be parsed
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArgError {
+    /// The arguments don't fit the signature
+    Usage,
+    NotANumber(String),
+    TooSmall(i32, i32),
+    TooBig(i32, i32),
+    PlayerNotFound,
+    /// A relative coordinate was given without a position to be relative to
+    NoPosition
+}
+
+impl ArgError {
+    /// Returns the message telling the player what's wrong, the same as vanilla
+    pub fn message(&self, command: &CommandInfo) -> String {
+        match self {
+            ArgError::Usage => format!("Usage: {}", command.usage()),
+            ArgError::NotANumber(v) => format!("'{}' is not a valid number", v),
+            ArgError::TooSmall(v, min) => format!("The number you have entered ({}) is too small, it must be at least {}", v, min),
+            ArgError::TooBig(v, max) => format!("The number you have entered ({}) is too big, it must be at most {}", v, max),
+            ArgError::PlayerNotFound => "That player cannot be found".to_owned(),
+            ArgError::NoPosition => "Relative coordinates can only be used by players".to_owned()
+        }
+    }
+}
+
+/// Where a command is run from and the online players target selectors pick from
+pub struct SelectorContext {
+    /// Position of the player running the command, @p picks the player closest to it
+    pub origin: Coord<f64>,
+    /// Yaw and pitch of the player running the command, None for the console,
+    /// which has to use absolute coordinates and rotations
+    pub rotation: Option<(f32, f32)>,
+    /// Names and positions of the online players
+    pub players: Vec<(String, Coord<f64>)>
+}
+
+impl SelectorContext {
+    /// Returns the names of the players the name or selector matches, the case of names doesn't matter.
+    /// Supports @a for all players, @p for the closest player and @r for a random player.
+    pub fn resolve(&self, target: &str) -> Result<Vec<String>, ArgError> {
+        let distance = |pos: &Coord<f64>| (pos.x - self.origin.x).powi(2) + (pos.y - self.origin.y).powi(2) + (pos.z - self.origin.z).powi(2);
+        let found: Vec<_> = match target {
+            "@a" => self.players.iter().collect(),
+            "@p" => self.players.iter().min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b))).into_iter().collect(),
+            "@r" => self.players.choose(&mut thread_rng()).into_iter().collect(),
+            _ => self.players.iter().filter(|(name, _)| name.eq_ignore_ascii_case(target)).collect()
+        };
+
+        if found.is_empty() {
+            return Err(ArgError::PlayerNotFound);
+        }
+
+        Ok(found.into_iter().map(|(name, _)| name.clone()).collect())
+    }
+}
+
+/// Returns the usage of a command with the arguments, like `/tell <player> <private message ...>`
+pub fn usage(name: &str, args: &[Arg]) -> String {
+    let mut usage = format!("/{}", name);
+    for arg in args {
+        let text = match arg.arg_type {
+            ArgType::Enum(values) => values.join("|"),
+            ArgType::Greedy => format!("{} ...", arg.name),
+            _ => arg.name.to_owned()
+        };
+        // Every coordinate is a word of its own, like `[<x> <y> <z>]`
+        let words = || text.split(' ').map(|v| format!("<{}>", v)).collect::<Vec<_>>().join(" ");
+        usage += &match (arg.arg_type, arg.optional) {
+            (ArgType::Position { .. } | ArgType::Rotation, true) => format!(" [{}]", words()),
+            (ArgType::Position { .. } | ArgType::Rotation, false) => format!(" {}", words()),
+            (_, true) => format!(" [{}]", text),
+            (_, false) => format!(" <{}>", text)
+        };
+    }
+
+    usage
+}
+
+/// Parses the arguments of a command with the signature
+pub fn parse_args(signature: &[Arg], args: &[&str], context: &SelectorContext) -> Result<Vec<ArgValue>, ArgError> {
+    let mut values = Vec::new();
+    let mut words = args;
+    for (i, arg) in signature.iter().enumerate() {
+        let width = arg.arg_type.width();
+        let given = match arg.arg_type {
+            ArgType::Greedy => !words.is_empty(),
+            _ => words.len() >= width && (!arg.optional || fits(&signature[i + 1..], words.len() - width))
+        };
+        if !given {
+            if arg.optional {
+                continue;
+            }
+
+            return Err(ArgError::Usage);
+        }
+
+        let (taken, rest) = words.split_at(width);
+        let value = taken[0];
+        let value = match arg.arg_type {
+            ArgType::Int { min, max } => {
+                let value = value.parse::<i32>().map_err(|_| ArgError::NotANumber(value.to_owned()))?;
+                if value < min {
+                    return Err(ArgError::TooSmall(value, min));
+                }
+                if value > max {
+                    return Err(ArgError::TooBig(value, max));
+                }
+
+                ArgValue::Int(value)
+            }
+            ArgType::Player => ArgValue::Players(context.resolve(value)?),
+            ArgType::Enum(words) => ArgValue::Enum(words.iter().copied().find(|v| *v == value).ok_or(ArgError::Usage)?),
+            ArgType::Word => ArgValue::String(value.to_owned()),
+            ArgType::Position { center } => {
+                let base = context.rotation.map(|_| context.origin);
+                ArgValue::Position(Coord::new(
+                    parse_relative(taken[0], base.map(|v| v.x), center)?,
+                    parse_relative(taken[1], base.map(|v| v.y), false)?,
+                    parse_relative(taken[2], base.map(|v| v.z), center)?))
+            }
+            ArgType::Rotation => {
+                let yaw = parse_relative(taken[0], context.rotation.map(|v| v.0 as f64), false)? as f32;
+                let pitch = parse_relative(taken[1], context.rotation.map(|v| v.1 as f64), false)? as f32;
+                ArgValue::Rotation((yaw + 180.0).rem_euclid(360.0) - 180.0, pitch.clamp(-90.0, 90.0))
+            }
+            ArgType::Greedy => {
+                values.push(ArgValue::String(words.join(" ")));
+                return Ok(values);
+            }
+        };
+        values.push(value);
+        words = rest;
+    }
+
+    if !words.is_empty() {
+        return Err(ArgError::Usage);
+    }
+
+    Ok(values)
+}
+
+/// Returns true if the arguments can take exactly `words` words
+fn fits(signature: &[Arg], words: usize) -> bool {
+    let (arg, rest) = match signature.split_first() {
+        Some(v) => v,
+        None => return words == 0
+    };
+
+    let width = arg.arg_type.width();
+    let given = match arg.arg_type {
+        ArgType::Greedy => words > 0,
+        _ => words >= width && fits(rest, words - width)
+    };
+    given || (arg.optional && fits(rest, words))
+}
+
+/// Every command, sorted by name
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "bossbar",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/bossbar <text|progress <0-1>|remove>"),
+        description: "Shows a boss bar to everyone"
+    },
+    CommandInfo {
+        name: "clear",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Args(&[Arg::optional("player", ArgType::Player), Arg::optional("item", ArgType::Word)]),
+        description: "Removes items from the inventory of a player"
+    },
+    CommandInfo {
+        name: "difficulty",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Args(&[Arg::required("new difficulty", ArgType::Word)]),
+        description: "Changes the difficulty of your world"
+    },
+    CommandInfo {
+        name: "effect",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/effect <player> <effect> [seconds] [amplifier] [hideParticles]"),
+        description: "Gives a player an effect, or takes them away with clear"
+    },
+    CommandInfo {
+        name: "fill",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/fill <x1> <y1> <z1> <x2> <y2> <z2> <block> [meta]"),
+        description: "Fills an area with a block"
+    },
+    CommandInfo {
+        name: "gamerule",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/gamerule <rule name> [value]"),
+        description: "Shows or changes a game rule"
+    },
+    CommandInfo {
+        name: "give",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Args(&[
+            Arg::required("player", ArgType::Player),
+            Arg::required("item", ArgType::Word),
+            Arg::optional("amount", ArgType::Int { min: 1, max: 64 }),
+            Arg::optional("data", ArgType::Int { min: 0, max: i16::MAX as i32 }),
+            Arg::optional("dataTag", ArgType::Greedy)
+        ]),
+        description: "Gives a player items"
+    },
+    CommandInfo {
+        name: "help",
+        aliases: &["?"],
+        level: 0,
+        syntax: Syntax::Args(&[Arg::optional("page", ArgType::Int { min: 1, max: i32::MAX })]),
+        description: "Lists the commands you can use"
+    },
+    CommandInfo {
+        name: "kill",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Args(&[Arg::optional("player", ArgType::Player)]),
+        description: "Kills a player"
+    },
+    CommandInfo {
+        name: "pregen",
+        aliases: &[],
+        level: ADMIN_LEVEL,
+        syntax: Syntax::Text("/pregen <radius|cancel>"),
+        description: "Generates the chunks around you ahead of time"
+    },
+    CommandInfo {
+        name: "save-all",
+        aliases: &[],
+        level: ADMIN_LEVEL,
+        syntax: Syntax::Args(&[]),
+        description: "Saves the worlds and the data of the players"
+    },
+    CommandInfo {
+        name: "setblock",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/setblock <x> <y> <z> <block> [meta]"),
+        description: "Changes a block"
+    },
+    CommandInfo {
+        name: "spawnpoint",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Args(&[Arg::optional("player", ArgType::Player), Arg::optional("x y z", ArgType::Position { center: false })]),
+        description: "Sets where a player respawns"
+    },
+    CommandInfo {
+        name: "summon",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/summon <EntityName> [x] [y] [z] [dataTag]"),
+        description: "Spawns an entity"
+    },
+    CommandInfo {
+        name: "team",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/team <create <name> [color]|remove <name>|join <name>|leave|list|option <name> friendlyfire <true|false>>"),
+        description: "Manages the teams"
+    },
+    CommandInfo {
+        name: "tell",
+        aliases: &["msg", "w"],
+        level: 0,
+        syntax: Syntax::Args(&[Arg::required("player", ArgType::Player), Arg::required("private message", ArgType::Greedy)]),
+        description: "Sends a private message to a player"
+    },
+    CommandInfo {
+        name: "time",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/time <set|add|query> <value>"),
+        description: "Changes or shows the time of the world"
+    },
+    CommandInfo {
+        name: "toggledownfall",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Text("/toggledownfall"),
+        description: "Starts or stops the rain"
+    },
+    CommandInfo {
+        name: "tp",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Overloads(&[
+            &[Arg::optional("target player", ArgType::Player), Arg::required("destination player", ArgType::Player)],
+            &[
+                Arg::optional("target player", ArgType::Player),
+                Arg::required("x y z", ArgType::Position { center: true }),
+                Arg::optional("yaw pitch", ArgType::Rotation)
+            ]
+        ]),
+        description: "Teleports players to a player or a position"
+    },
+    CommandInfo {
+        name: "weather",
+        aliases: &[],
+        level: CHEAT_LEVEL,
+        syntax: Syntax::Args(&[
+            Arg::required("weather", ArgType::Enum(&["clear", "rain", "thunder"])),
+            Arg::optional("duration in seconds", ArgType::Int { min: 1, max: 1_000_000 })
+        ]),
+        description: "Changes the weather"
+    }
+];
+
+/// Returns the command with the name or alias
+pub fn find_command(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|v| v.name == name || v.aliases.contains(&name))
+}
+
+/// Returns the commands a player with the permission level can use, split into the pages /help shows
+pub fn help_pages(level: u8) -> Vec<Vec<&'static CommandInfo>> {
+    let commands: Vec<_> = COMMANDS.iter().filter(|v| v.level <= level).collect();
+    commands.chunks(HELP_PAGE_SIZE).map(<[_]>::to_vec).collect()
+}
+
+/// Parses a block coordinate, `~` prefixes make it relative to `base`
+pub fn parse_coord(arg: &str, base: f64) -> Option<i32> {
+    match arg.strip_prefix('~') {
+        Some("") => Some(base.floor() as i32),
+        Some(offset) => Some((base + offset.parse::<f64>().ok()?).floor() as i32),
+        None => arg.parse().ok()
+    }
+}
+
+/// Parses three block coordinates relative to `base`
+pub fn parse_pos(args: &[&str], base: Coord<f64>) -> Option<Coord<i32>> {
+    match args {
+        [x, y, z] => Some(Coord::new(parse_coord(x, base.x)?, parse_coord(y, base.y)?, parse_coord(z, base.z)?)),
+        _ => None
+    }
+}
+
+/// Parses a precise coordinate or angle, `~` prefixes make it relative to `base`, which the console doesn't have.
+/// Whole numbers are moved to the center of the block when `center` is set, like vanilla.
+pub fn parse_relative(arg: &str, base: Option<f64>, center: bool) -> Result<f64, ArgError> {
+    let number = |v: &str| v.parse::<f64>().ok().filter(|v| v.is_finite()).ok_or_else(|| ArgError::NotANumber(arg.to_owned()));
+    match (arg.strip_prefix('~'), base) {
+        (Some(_), None) => Err(ArgError::NoPosition),
+        (Some(""), Some(base)) => Ok(base),
+        (Some(offset), Some(base)) => Ok(base + number(offset)?),
+        (None, _) if center && !arg.contains('.') => Ok(number(arg)? + 0.5),
+        (None, _) => number(arg)
+    }
+}
+
+/// Parses a block by name, with or without the `minecraft:` namespace, or by numeric id
+pub fn parse_block(arg: &str) -> Option<BlockType> {
+    if let Ok(id) = arg.parse::<u8>() {
+        return BlockType::from_u8(id);
+    }
+
+    BlockType::from_name(arg.strip_prefix("minecraft:").unwrap_or(arg))
+}
+
+/// Parses an optional block meta, 0 - 15
+pub fn parse_meta(arg: Option<&&str>) -> Option<u8> {
+    match arg {
+        Some(v) => v.parse().ok().filter(|v| *v < 16),
+        None => Some(0)
+    }
+}
+
+/// Parses a number of ticks, which can't be negative
+pub fn parse_ticks(arg: &str) -> Option<i64> {
+    arg.parse().ok().filter(|v| *v >= 0)
+}
+
+/// Parses a time of day in ticks, or day or night
+pub fn parse_time_of_day(arg: &str) -> Option<i64> {
+    match arg {
+        "day" => Some(DAY_TIME),
+        "night" => Some(NIGHT_TIME),
+        _ => parse_ticks(arg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_coords() {
+        let base = Coord::new(10.7, 64.0, -3.2);
+        assert_eq!(parse_pos(&["~", "~-1", "~2.5"], base), Some(Coord::new(10, 63, -1)));
+        assert_eq!(parse_pos(&["5", "~", "-7"], base), Some(Coord::new(5, 64, -7)));
+        assert_eq!(parse_pos(&["5", "~x", "-7"], base), None);
+        assert_eq!(parse_pos(&["5", "6"], base), None);
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(parse_block("stone"), Some(BlockType::Stone));
+        assert_eq!(parse_block("minecraft:tnt"), Some(BlockType::Tnt));
+        assert_eq!(parse_block("49"), Some(BlockType::Obsidian));
+        assert_eq!(parse_block("6"), None);
+        assert_eq!(parse_block("cheese"), None);
+        assert_eq!(parse_meta(None), Some(0));
+        assert_eq!(parse_meta(Some(&"15")), Some(15));
+        assert_eq!(parse_meta(Some(&"16")), None);
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(parse_time_of_day("day"), Some(1000));
+        assert_eq!(parse_time_of_day("night"), Some(13000));
+        assert_eq!(parse_time_of_day("6000"), Some(6000));
+        assert_eq!(parse_time_of_day("-1"), None);
+        assert_eq!(parse_ticks("noon"), None);
+    }
+
+    #[test]
+    fn command_table() {
+        assert!(COMMANDS.windows(2).all(|v| v[0].name < v[1].name));
+        assert_eq!(find_command("msg").map(|v| v.name), Some("tell"));
+        assert!(find_command("give").is_none());
+
+        let pages = help_pages(0);
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].iter().map(|v| v.name).collect::<Vec<_>>(), ["help", "tell"]);
+        assert_eq!(help_pages(CHEAT_LEVEL).len(), COMMANDS.len().div_ceil(HELP_PAGE_SIZE));
+    }
+
+    fn context() -> SelectorContext {
+        SelectorContext {
+            origin: Coord::new(0.0, 64.0, 0.0),
+            rotation: Some((170.0, 10.0)),
+            players: vec![
+                ("Steve".to_owned(), Coord::new(0.0, 64.0, 0.0)),
+                ("Alex".to_owned(), Coord::new(3.0, 64.0, 4.0)),
+                ("Notch".to_owned(), Coord::new(-100.0, 70.0, 20.0))
+            ]
+        }
+    }
+
+    const SIGNATURE: &[Arg] = &[
+        Arg::required("mode", ArgType::Enum(&["survival", "creative"])),
+        Arg::required("player", ArgType::Player),
+        Arg::optional("level", ArgType::Int { min: 0, max: 10 }),
+        Arg::optional("reason", ArgType::Greedy)
+    ];
+
+    #[test]
+    fn generated_usage() {
+        assert_eq!(usage("test", SIGNATURE), "/test <survival|creative> <player> [level] [reason ...]");
+        assert_eq!(find_command("tell").unwrap().usage(), "/tell <player> <private message ...>");
+        assert_eq!(find_command("weather").unwrap().usage(), "/weather <clear|rain|thunder> [duration in seconds]");
+        assert_eq!(find_command("tp").unwrap().usage(), "/tp [target player] <destination player> OR /tp [target player] <x> <y> <z> [<yaw> <pitch>]");
+        assert_eq!(find_command("give").unwrap().usage(), "/give <player> <item> [amount] [data] [dataTag ...]");
+        assert_eq!(find_command("difficulty").unwrap().usage(), "/difficulty <new difficulty>");
+        assert_eq!(find_command("spawnpoint").unwrap().usage(), "/spawnpoint [player] [<x> <y> <z>]");
+    }
+
+    #[test]
+    fn parse_typed_args() {
+        let context = context();
+        assert_eq!(parse_args(SIGNATURE, &["creative", "alex"], &context), Ok(vec![
+            ArgValue::Enum("creative"),
+            ArgValue::Players(vec!["Alex".to_owned()])
+        ]));
+        assert_eq!(parse_args(SIGNATURE, &["survival", "Steve", "10", "because", "I", "can"], &context), Ok(vec![
+            ArgValue::Enum("survival"),
+            ArgValue::Players(vec!["Steve".to_owned()]),
+            ArgValue::Int(10),
+            ArgValue::String("because I can".to_owned())
+        ]));
+        assert_eq!(parse_args(&[], &[], &context), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let context = context();
+        assert_eq!(parse_args(SIGNATURE, &[], &context), Err(ArgError::Usage));
+        assert_eq!(parse_args(SIGNATURE, &["creative"], &context), Err(ArgError::Usage));
+        assert_eq!(parse_args(SIGNATURE, &["adventure", "Steve"], &context), Err(ArgError::Usage));
+        // Enums are case sensitive like in vanilla
+        assert_eq!(parse_args(SIGNATURE, &["Creative", "Steve"], &context), Err(ArgError::Usage));
+        assert_eq!(parse_args(SIGNATURE, &["creative", "Herobrine"], &context), Err(ArgError::PlayerNotFound));
+        assert_eq!(parse_args(SIGNATURE, &["creative", "Steve", "ten"], &context), Err(ArgError::NotANumber("ten".to_owned())));
+        assert_eq!(parse_args(SIGNATURE, &["creative", "Steve", "-1"], &context), Err(ArgError::TooSmall(-1, 0)));
+        assert_eq!(parse_args(SIGNATURE, &["creative", "Steve", "11"], &context), Err(ArgError::TooBig(11, 10)));
+        assert_eq!(parse_args(&SIGNATURE[..1], &["creative", "Steve"], &context), Err(ArgError::Usage));
+        assert_eq!(parse_args(&SIGNATURE[..2], &["creative", "@a", "5"], &context), Err(ArgError::Usage));
+    }
+
+    #[test]
+    fn error_messages() {
+        let help = find_command("help").unwrap();
+        assert_eq!(ArgError::Usage.message(help), "Usage: /help [page]");
+        assert_eq!(ArgError::NotANumber("x".to_owned()).message(help), "'x' is not a valid number");
+        assert_eq!(ArgError::TooSmall(0, 1).message(help), "The number you have entered (0) is too small, it must be at least 1");
+        assert_eq!(ArgError::TooBig(9, 2).message(help), "The number you have entered (9) is too big, it must be at most 2");
+        assert_eq!(ArgError::PlayerNotFound.message(help), "That player cannot be found");
+    }
+
+    #[test]
+    fn selectors() {
+        let context = context();
+        assert_eq!(context.resolve("@a").unwrap(), ["Steve", "Alex", "Notch"]);
+        assert_eq!(context.resolve("notch").unwrap(), ["Notch"]);
+        // The player running the command is the closest one
+        assert_eq!(context.resolve("@p").unwrap(), ["Steve"]);
+        let far = SelectorContext { origin: Coord::new(-90.0, 70.0, 20.0), ..context };
+        assert_eq!(far.resolve("@p").unwrap(), ["Notch"]);
+
+        for _ in 0..10 {
+            let random = far.resolve("@r").unwrap();
+            assert_eq!(random.len(), 1);
+            assert!(far.players.iter().any(|(name, _)| *name == random[0]));
+        }
+
+        let empty = SelectorContext { origin: Coord::new(0.0, 0.0, 0.0), rotation: None, players: Vec::new() };
+        for target in ["@a", "@p", "@r", "Steve"] {
+            assert_eq!(empty.resolve(target), Err(ArgError::PlayerNotFound));
+        }
+        assert_eq!(far.resolve("@e"), Err(ArgError::PlayerNotFound));
+    }
+
+    #[test]
+    fn relative_positions() {
+        assert_eq!(parse_relative("~-3.5", Some(10.25), true), Ok(6.75));
+        assert_eq!(parse_relative("~", Some(10.25), true), Ok(10.25));
+        assert_eq!(parse_relative("~2", Some(-1.0), true), Ok(1.0));
+        // Whole numbers are centered on the block, unless they're relative or centering is off
+        assert_eq!(parse_relative("-20", None, true), Ok(-19.5));
+        assert_eq!(parse_relative("-20.0", None, true), Ok(-20.0));
+        assert_eq!(parse_relative("64", None, false), Ok(64.0));
+        assert_eq!(parse_relative("~1", None, false), Err(ArgError::NoPosition));
+        assert_eq!(parse_relative("~up", Some(0.0), false), Err(ArgError::NotANumber("~up".to_owned())));
+        assert_eq!(parse_relative("NaN", None, false), Err(ArgError::NotANumber("NaN".to_owned())));
+    }
+
+    #[test]
+    fn tp_args() {
+        let context = SelectorContext { origin: Coord::new(0.5, 64.0, 0.5), ..context() };
+        let tp = find_command("tp").unwrap();
+        assert_eq!(tp.parse_args(&["alex"], &context), Ok(vec![ArgValue::Players(vec!["Alex".to_owned()])]));
+        assert_eq!(tp.parse_args(&["@a", "Notch"], &context), Ok(vec![
+            ArgValue::Players(vec!["Steve".to_owned(), "Alex".to_owned(), "Notch".to_owned()]),
+            ArgValue::Players(vec!["Notch".to_owned()])
+        ]));
+        assert_eq!(tp.parse_args(&["~-3.5", "~", "100"], &context), Ok(vec![ArgValue::Position(Coord::new(-3.0, 64.0, 100.5))]));
+        // Yaw wraps around and pitch stops at straight up or down
+        assert_eq!(tp.parse_args(&["Alex", "~", "~1", "~", "~20", "-100"], &context), Ok(vec![
+            ArgValue::Players(vec!["Alex".to_owned()]),
+            ArgValue::Position(Coord::new(0.5, 65.0, 0.5)),
+            ArgValue::Rotation(-170.0, -90.0)
+        ]));
+
+        assert_eq!(tp.parse_args(&[], &context), Err(ArgError::Usage));
+        assert_eq!(tp.parse_args(&["1", "2"], &context), Err(ArgError::PlayerNotFound));
+        assert_eq!(tp.parse_args(&["Herobrine"], &context), Err(ArgError::PlayerNotFound));
+        assert_eq!(tp.parse_args(&["1", "2", "3", "4", "5", "6", "7"], &context), Err(ArgError::Usage));
+        assert_eq!(tp.parse_args(&["~", "x", "~"], &context), Err(ArgError::NotANumber("x".to_owned())));
+
+        // The console can't use relative coordinates
+        let console = SelectorContext { rotation: None, ..context };
+        assert_eq!(tp.parse_args(&["Alex", "~", "64", "0"], &console), Err(ArgError::NoPosition));
+        assert_eq!(tp.parse_args(&["1", "64", "2", "~", "0"], &console), Err(ArgError::NoPosition));
+        assert_eq!(tp.parse_args(&["Alex", "1", "64", "2"], &console), Ok(vec![
+            ArgValue::Players(vec!["Alex".to_owned()]),
+            ArgValue::Position(Coord::new(1.5, 64.0, 2.5))
+        ]));
+    }
+
+    #[test]
+    fn optional_args_before_required_ones() {
+        const SIGNATURE: &[Arg] = &[
+            Arg::optional("player", ArgType::Player),
+            Arg::required("x y z", ArgType::Position { center: false }),
+            Arg::optional("count", ArgType::Int { min: 1, max: 64 })
+        ];
+        let context = context();
+        assert_eq!(usage("test", SIGNATURE), "/test [player] <x> <y> <z> [count]");
+        assert_eq!(parse_args(SIGNATURE, &["1", "2", "3"], &context), Ok(vec![ArgValue::Position(Coord::new(1.0, 2.0, 3.0))]));
+        assert_eq!(parse_args(SIGNATURE, &["Alex", "1", "2", "3"], &context), Ok(vec![
+            ArgValue::Players(vec!["Alex".to_owned()]),
+            ArgValue::Position(Coord::new(1.0, 2.0, 3.0))
+        ]));
+        assert_eq!(parse_args(SIGNATURE, &["Alex", "1", "2", "3", "4"], &context).unwrap().len(), 3);
+        assert_eq!(parse_args(SIGNATURE, &["1", "2"], &context), Err(ArgError::Usage));
+    }
+}
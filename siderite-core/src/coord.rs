@@ -1,12 +1,32 @@
 use num_traits::Num;
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+use crate::storage::chunk::WIDTH;
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub struct ChunkCoord {
     pub x: i32,
     pub z: i32
 }
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+impl ChunkCoord {
+    /// Returns the coordinate of the chunk containing the absolute position
+    pub fn from_pos(pos: Coord<f64>) -> Self {
+        ChunkCoord {
+            x: (pos.x.floor() as i32).div_euclid(WIDTH),
+            z: (pos.z.floor() as i32).div_euclid(WIDTH)
+        }
+    }
+
+    /// Returns the coordinate of the chunk containing the block
+    pub fn from_block_pos(pos: Coord<i32>) -> Self {
+        ChunkCoord {
+            x: pos.x.div_euclid(WIDTH),
+            z: pos.z.div_euclid(WIDTH)
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub struct Coord<T: Num + PartialOrd + Copy> {
     pub x: T,
     pub y: T,
@@ -19,6 +39,19 @@ impl<T: Num + PartialOrd + Copy> Coord<T> {
     }
 }
 
+impl Coord<i32> {
+    /// Returns the center of the bottom face of the block, where an entity standing in it is
+    pub fn bottom_center(self) -> Coord<f64> {
+        Coord::new(self.x as f64 + 0.5, self.y as f64, self.z as f64 + 0.5)
+    }
+}
+
+/// Returns the unit vector pointing where an entity with the yaw and pitch, in degrees, is looking
+pub fn look_direction(yaw: f32, pitch: f32) -> Coord<f64> {
+    let (yaw, pitch) = ((yaw as f64).to_radians(), (pitch as f64).to_radians());
+    Coord::new(-yaw.sin() * pitch.cos(), -pitch.sin(), yaw.cos() * pitch.cos())
+}
+
 // TODO: Make generic
 impl From<Coord<i32>> for Coord<f64> {
     fn from(other: Coord<i32>) -> Coord<f64> {
@@ -0,0 +1,231 @@
+//! Crafting turns the items in a crafting grid into a result, for now only the 2x2 grid of the player inventory.
+//! Taking the result uses up one of every item in the grid.
+
+use std::ops::Range;
+use std::sync::RwLock;
+
+use crate::blocks::BlockType;
+use crate::items::{Inventory, ItemStack, ItemType};
+
+/// Slot of the crafting result in the player inventory window
+pub const RESULT_SLOT: usize = 0;
+
+/// First slot of the crafting grid in the player inventory window, the grid is filled row by row
+pub const GRID_START: usize = 1;
+
+/// Width and height of the crafting grid of the player inventory
+pub const GRID_WIDTH: usize = 2;
+
+/// Returns the slots of the crafting grid of the player inventory
+pub fn grid_slots() -> Range<usize> {
+    GRID_START..GRID_START + GRID_WIDTH * GRID_WIDTH
+}
+
+/// An item a recipe needs
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ingredient {
+    pub id: i16,
+    /// None accepts any damage value, like planks of any wood
+    pub damage: Option<i16>
+}
+
+impl Ingredient {
+    pub fn block(block_type: BlockType, damage: Option<i16>) -> Self {
+        Self { id: block_type as i16, damage }
+    }
+
+    pub fn item(item_type: ItemType) -> Self {
+        Self { id: item_type as i16, damage: None }
+    }
+
+    pub fn matches(&self, item: &ItemStack) -> bool {
+        item.id == self.id && self.damage.map_or(true, |v| v == item.damage)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recipe {
+    /// The ingredients have to be laid out in the pattern, anywhere in the grid and mirrored or not.
+    /// The pattern is given row by row, with None for the slots that have to stay empty.
+    Shaped { width: usize, pattern: Vec<Option<Ingredient>>, result: ItemStack },
+    /// The ingredients can be anywhere in the grid, but nothing else can be in it
+    Shapeless { ingredients: Vec<Ingredient>, result: ItemStack }
+}
+
+impl Recipe {
+    /// Returns the result if the items in the grid match the recipe
+    pub fn craft(&self, grid: &[Option<ItemStack>], grid_width: usize) -> Option<ItemStack> {
+        let matches = match self {
+            Recipe::Shaped { width, pattern, .. } => Self::matches_shaped(*width, pattern, grid, grid_width),
+            Recipe::Shapeless { ingredients, .. } => Self::matches_shapeless(ingredients, grid)
+        };
+
+        match self {
+            Recipe::Shaped { result, .. } | Recipe::Shapeless { result, .. } if matches => Some(result.clone()),
+            _ => None
+        }
+    }
+
+    fn matches_shaped(width: usize, pattern: &[Option<Ingredient>], grid: &[Option<ItemStack>], grid_width: usize) -> bool {
+        let height = pattern.len() / width;
+        let filled: Vec<_> = (0..grid.len()).filter(|&i| grid[i].is_some()).map(|i| (i % grid_width, i / grid_width)).collect();
+        let (min_x, min_z) = match (filled.iter().map(|v| v.0).min(), filled.iter().map(|v| v.1).min()) {
+            (Some(x), Some(z)) => (x, z),
+            _ => return false
+        };
+        let max_x = filled.iter().map(|v| v.0).max().unwrap();
+        let max_z = filled.iter().map(|v| v.1).max().unwrap();
+        if (max_x - min_x + 1, max_z - min_z + 1) != (width, height) {
+            return false;
+        }
+
+        [false, true].into_iter().any(|mirrored| {
+            (0..height).all(|z| (0..width).all(|x| {
+                let ingredient = pattern[if mirrored { width - 1 - x } else { x } + z * width];
+                match (ingredient, &grid[min_x + x + (min_z + z) * grid_width]) {
+                    (Some(ingredient), Some(item)) => ingredient.matches(item),
+                    (None, None) => true,
+                    _ => false
+                }
+            }))
+        })
+    }
+
+    fn matches_shapeless(ingredients: &[Ingredient], grid: &[Option<ItemStack>]) -> bool {
+        let mut items: Vec<_> = grid.iter().flatten().collect();
+        if items.len() != ingredients.len() {
+            return false;
+        }
+
+        ingredients.iter().all(|ingredient| match items.iter().position(|v| ingredient.matches(v)) {
+            Some(i) => {
+                items.swap_remove(i);
+                true
+            }
+            None => false
+        })
+    }
+}
+
+/// The known recipes, the first one matching the grid gets crafted
+pub struct RecipeRegistry {
+    recipes: RwLock<Vec<Recipe>>
+}
+
+impl Default for RecipeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecipeRegistry {
+    /// Returns a registry with the built-in recipes
+    pub fn new() -> Self {
+        let planks = Some(Ingredient::block(BlockType::Planks, None));
+        let mut recipes = vec![
+            Recipe::Shaped { width: 1, pattern: vec![planks; 2], result: ItemStack::of(ItemType::Stick, 4) },
+            Recipe::Shaped { width: 2, pattern: vec![planks; 4], result: ItemStack::new(BlockType::CraftingTable as i16, 1, 0) }
+        ];
+        // Every kind of log makes planks of the same wood
+        for wood in 0..4 {
+            recipes.push(Recipe::Shapeless {
+                ingredients: vec![Ingredient::block(BlockType::Log, Some(wood))],
+                result: ItemStack::new(BlockType::Planks as i16, 4, wood)
+            });
+        }
+
+        Self { recipes: RwLock::new(recipes) }
+    }
+
+    pub fn register(&self, recipe: Recipe) {
+        self.recipes.write().unwrap().push(recipe);
+    }
+
+    /// Returns what the items in the grid make, None if they don't match any recipe
+    pub fn craft(&self, grid: &[Option<ItemStack>], grid_width: usize) -> Option<ItemStack> {
+        self.recipes.read().unwrap().iter().find_map(|v| v.craft(grid, grid_width))
+    }
+}
+
+/// Sets the result slot of the player inventory to what the items in its crafting grid make
+pub fn update_result(inventory: &mut Inventory, recipes: &RecipeRegistry) {
+    let grid: Vec<_> = grid_slots().map(|slot| inventory.get_slot(slot).cloned()).collect();
+    inventory.set_slot(RESULT_SLOT, recipes.craft(&grid, GRID_WIDTH));
+}
+
+/// Uses up one of every item in the crafting grid of the player inventory, after its result was taken
+pub fn consume_ingredients(inventory: &mut Inventory, recipes: &RecipeRegistry) {
+    for slot in grid_slots() {
+        if let Some(item) = inventory.get_slot(slot).cloned() {
+            inventory.set_slot(slot, Some(ItemStack { count: item.count - 1, ..item }));
+        }
+    }
+
+    update_result(inventory, recipes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planks(damage: i16) -> Option<ItemStack> {
+        Some(ItemStack::new(BlockType::Planks as i16, 1, damage))
+    }
+
+    #[test]
+    fn shaped() {
+        let recipes = RecipeRegistry::new();
+        let table = Some(ItemStack::new(BlockType::CraftingTable as i16, 1, 0));
+        // Any wood works, even mixed
+        assert_eq!(recipes.craft(&[planks(0), planks(1), planks(0), planks(3)], 2), table);
+        assert_eq!(recipes.craft(&[planks(0), planks(0), planks(0), None], 2), None);
+
+        // The sticks can be made in either column, but not across them
+        let sticks = Some(ItemStack::of(ItemType::Stick, 4));
+        assert_eq!(recipes.craft(&[planks(0), None, planks(2), None], 2), sticks);
+        assert_eq!(recipes.craft(&[None, planks(0), None, planks(0)], 2), sticks);
+        assert_eq!(recipes.craft(&[planks(0), None, None, planks(0)], 2), None);
+        assert_eq!(recipes.craft(&[planks(0), planks(0), None, None], 2), None);
+    }
+
+    #[test]
+    fn mirrored() {
+        let recipes = RecipeRegistry::new();
+        let apple = Some(Ingredient::item(ItemType::Apple));
+        recipes.register(Recipe::Shaped { width: 2, pattern: vec![apple, None, apple, apple], result: ItemStack::of(ItemType::Cookie, 1) });
+
+        let item = || Some(ItemStack::of(ItemType::Apple, 1));
+        assert_eq!(recipes.craft(&[item(), None, item(), item()], 2), Some(ItemStack::of(ItemType::Cookie, 1)));
+        assert_eq!(recipes.craft(&[None, item(), item(), item()], 2), Some(ItemStack::of(ItemType::Cookie, 1)));
+        assert_eq!(recipes.craft(&[item(), item(), None, item()], 2), None);
+    }
+
+    #[test]
+    fn shapeless() {
+        let recipes = RecipeRegistry::new();
+        let log = |damage| Some(ItemStack::new(BlockType::Log as i16, 1, damage));
+        for i in 0..4 {
+            let mut grid = vec![None; 4];
+            grid[i] = log(2);
+            assert_eq!(recipes.craft(&grid, 2), Some(ItemStack::new(BlockType::Planks as i16, 4, 2)));
+        }
+        assert_eq!(recipes.craft(&[log(0), log(0), None, None], 2), None);
+        assert_eq!(recipes.craft(&[None; 4], 2), None);
+    }
+
+    #[test]
+    fn consume() {
+        let recipes = RecipeRegistry::new();
+        let mut inventory = Inventory::new();
+        inventory.set_slot(GRID_START, Some(ItemStack::new(BlockType::Log as i16, 2, 0)));
+        update_result(&mut inventory, &recipes);
+        assert_eq!(inventory.get_slot(RESULT_SLOT), Some(&ItemStack::new(BlockType::Planks as i16, 4, 0)));
+
+        consume_ingredients(&mut inventory, &recipes);
+        assert_eq!(inventory.get_slot(GRID_START), Some(&ItemStack::new(BlockType::Log as i16, 1, 0)));
+        assert!(inventory.get_slot(RESULT_SLOT).is_some());
+        consume_ingredients(&mut inventory, &recipes);
+        assert_eq!(inventory.get_slot(GRID_START), None);
+        assert_eq!(inventory.get_slot(RESULT_SLOT), None);
+    }
+}
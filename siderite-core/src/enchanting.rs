@@ -0,0 +1,225 @@
+//! Enchanting tables enchant tools and armor for experience levels and lapis lazuli.
+//! The enchantments are simplified: armor gets Protection, swords Sharpness and digging tools Efficiency.
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::blocks::BlockType;
+use crate::coord::Coord;
+use crate::items::{ItemStack, ItemType};
+use crate::storage::world::World;
+use crate::window::WindowPropertySink;
+
+/// Slot of the item being enchanted
+pub const ITEM_SLOT: usize = 0;
+
+/// Slot of the lapis lazuli paying for the enchantment
+pub const LAPIS_SLOT: usize = 1;
+
+/// Number of slots in an enchanting table
+pub const ENCHANTING_TABLE_SIZE: usize = 2;
+
+/// Number of enchantments offered at once
+pub const OPTIONS: usize = 3;
+
+/// Most bookshelves around a table that count towards the costs
+pub const MAX_BOOKSHELVES: i32 = 15;
+
+/// Damage value of the dye that's lapis lazuli
+pub const LAPIS_DAMAGE: i16 = 4;
+
+#[repr(i16)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Enchantment {
+    Protection = 0,
+    Sharpness = 16,
+    Efficiency = 32
+}
+
+impl Enchantment {
+    pub fn max_level(self) -> i16 {
+        match self {
+            Enchantment::Protection => 4,
+            Enchantment::Sharpness | Enchantment::Efficiency => 5
+        }
+    }
+
+    /// Returns the enchantment a table puts on the item, None if the item can't be enchanted
+    pub fn for_item(item: &ItemStack) -> Option<Self> {
+        let item_type = item.item_type()?;
+        if item_type.is_armor() {
+            Some(Enchantment::Protection)
+        } else if item_type.is_sword() {
+            Some(Enchantment::Sharpness)
+        } else if item_type.is_digging_tool() {
+            Some(Enchantment::Efficiency)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the level an option costing `cost` levels gives, more expensive options give higher levels
+    pub fn level_for_cost(self, cost: i32) -> i16 {
+        (1 + cost / 8).min(self.max_level() as i32) as i16
+    }
+}
+
+/// The enchantments an enchanting table offers for the item in it
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EnchantingOptions {
+    /// Experience levels needed for each option, 0 for options that aren't offered
+    pub costs: [i32; OPTIONS],
+    pub seed: i32,
+    pub enchantment: Option<Enchantment>
+}
+
+impl EnchantingOptions {
+    /// Returns the options for the item, none are offered for items that can't be enchanted or already are
+    pub fn new(item: Option<&ItemStack>, bookshelves: i32, seed: i32) -> Self {
+        let enchantment = item
+            .filter(|v| v.count == 1 && !v.is_enchanted())
+            .and_then(Enchantment::for_item);
+        Self {
+            costs: enchantment.map_or([0; OPTIONS], |_| costs(bookshelves, seed)),
+            seed,
+            enchantment
+        }
+    }
+
+    /// Returns the enchantment and its level the option gives
+    pub fn get(&self, option: usize) -> Option<(Enchantment, i16)> {
+        let cost = *self.costs.get(option).filter(|v| **v > 0)?;
+        let enchantment = self.enchantment?;
+        Some((enchantment, enchantment.level_for_cost(cost)))
+    }
+}
+
+/// The costs, seed and enchantment hints of the enchanting table window
+impl WindowPropertySink for EnchantingOptions {
+    fn window_properties(&self) -> Vec<(i16, i16)> {
+        let mut properties: Vec<_> = self.costs.iter().enumerate().map(|(i, cost)| (i as i16, *cost as i16)).collect();
+        // The client only uses the seed to draw the enchanting script, the low bits are left out like vanilla does
+        properties.push((3, (self.seed & 0xFFF0) as i16));
+        for (i, cost) in self.costs.iter().enumerate() {
+            let hint = self.enchantment.filter(|_| *cost > 0).map_or(-1, |v| v as i16);
+            properties.push((4 + i as i16, hint));
+        }
+
+        properties
+    }
+}
+
+/// Returns the experience levels the three options cost, the same for the same bookshelves and seed
+pub fn costs(bookshelves: i32, seed: i32) -> [i32; OPTIONS] {
+    let bookshelves = bookshelves.clamp(0, MAX_BOOKSHELVES);
+    let mut rng = StdRng::seed_from_u64(seed as u32 as u64);
+    let mut costs = [0; OPTIONS];
+    for (i, cost) in costs.iter_mut().enumerate() {
+        let base = rng.gen_range(1..=8) + (bookshelves >> 1) + rng.gen_range(0..=bookshelves);
+        *cost = match i {
+            0 => (base / 3).max(1),
+            1 => base * 2 / 3 + 1,
+            _ => base.max(bookshelves * 2)
+        };
+    }
+
+    costs
+}
+
+/// Counts the bookshelves around the table, in the ring two blocks away from it at the height of the table and one above.
+/// Bookshelves are only counted if the block between them and the table is air.
+pub fn count_bookshelves(world: &World, table: Coord<i32>) -> i32 {
+    let is = |x: i32, y: i32, z: i32, block_type: BlockType|
+        world.get_block_state(Coord::new(table.x + x, table.y + y, table.z + z)).0 == block_type;
+    let shelves = |x: i32, z: i32| (0..=1).filter(|&y| is(x, y, z, BlockType::Bookshelf)).count() as i32;
+
+    let mut count = 0;
+    for z in -1..=1 {
+        for x in -1..=1 {
+            if (x, z) == (0, 0) || !is(x, 0, z, BlockType::Air) || !is(x, 1, z, BlockType::Air) {
+                continue;
+            }
+
+            count += shelves(x * 2, z * 2);
+            // The corners of the ring are reached through the diagonals
+            if x != 0 && z != 0 {
+                count += shelves(x * 2, z) + shelves(x, z * 2);
+            }
+        }
+    }
+
+    count
+}
+
+pub fn is_lapis(item: &ItemStack) -> bool {
+    item.item_type() == Some(ItemType::Dye) && item.damage == LAPIS_DAMAGE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::chunk::generator::GeneratorType;
+    use crate::storage::world::{Difficulty, Dimension, WorldConfig};
+
+    #[test]
+    fn costs_formula() {
+        for seed in [0, 1, -7, 123_456] {
+            let costs = costs(0, seed);
+            assert_eq!(costs, super::costs(0, seed));
+            // Without bookshelves the base is at most 8
+            assert!((1..=2).contains(&costs[0]));
+            assert!((1..=6).contains(&costs[1]));
+            assert!((1..=8).contains(&costs[2]));
+
+            // The last option always costs at least two levels per bookshelf
+            let full = super::costs(MAX_BOOKSHELVES, seed);
+            assert!(full[2] >= 2 * MAX_BOOKSHELVES && full[2] <= 30);
+            assert_eq!(super::costs(100, seed), full);
+        }
+    }
+
+    #[test]
+    fn options() {
+        let sword = ItemStack::of(ItemType::DiamondSword, 1);
+        let options = EnchantingOptions::new(Some(&sword), 15, 42);
+        assert_eq!(options.enchantment, Some(Enchantment::Sharpness));
+        assert_eq!(options.get(2), Some((Enchantment::Sharpness, Enchantment::Sharpness.level_for_cost(options.costs[2]))));
+        let properties = options.window_properties();
+        assert_eq!(properties.len(), 7);
+        assert_eq!(properties[4..], [(4, 16), (5, 16), (6, 16)]);
+
+        // Items that can't be enchanted or already are get no options
+        let mut enchanted = sword.clone();
+        enchanted.set_enchantment(Enchantment::Sharpness as i16, 1);
+        for item in [None, Some(&enchanted), Some(&ItemStack::of(ItemType::Apple, 1))] {
+            let options = EnchantingOptions::new(item, 15, 42);
+            assert_eq!(options.costs, [0; OPTIONS]);
+            assert_eq!(options.get(0), None);
+            assert_eq!(options.window_properties()[4..], [(4, -1), (5, -1), (6, -1)]);
+        }
+    }
+
+    #[test]
+    fn bookshelves() {
+        let world = World::new(WorldConfig {
+            name: "world".to_owned(),
+            dimension: Dimension::Overworld,
+            spawn_pos: Coord::new(0, 65, 0),
+            spawn_chunks_radius: 0,
+            difficulty: Difficulty::Easy,
+            generator: GeneratorType::Flat,
+            seed: 0
+        });
+        let table = Coord::new(8, 20, 8);
+        world.chunk_map().touch_chunk(crate::coord::ChunkCoord { x: 0, z: 0 });
+        world.set_block(table, BlockType::EnchantingTable, 0);
+        for x in 6..=10 {
+            world.set_block(Coord::new(x, 20, 6), BlockType::Bookshelf, 0);
+            world.set_block(Coord::new(x, 21, 6), BlockType::Bookshelf, 0);
+        }
+        assert_eq!(count_bookshelves(&world, table), 10);
+
+        // A torch in between blocks the shelves behind it
+        world.set_block(Coord::new(8, 21, 7), BlockType::Torch, 5);
+        assert_eq!(count_bookshelves(&world, table), 8);
+    }
+}
@@ -0,0 +1,192 @@
+//! Attributes of living entities, the client uses them to work out how fast players walk and sprint
+
+use uuid::Uuid;
+
+/// Modifier vanilla adds to the movement speed of sprinting entities
+pub const SPRINTING_SPEED_BOOST: Modifier = Modifier {
+    uuid: Uuid::from_u128(0x662A6B8D_DA3E_4C1C_8813_96EA6097278D),
+    amount: 0.30000001192092896,
+    operation: Operation::Multiply
+};
+
+/// Uuid of the movement speed modifier of the Speed effect
+pub const SPEED_UUID: Uuid = Uuid::from_u128(0x91AEAA56_376B_4498_935B_2F7F68070635);
+
+/// Uuid of the movement speed modifier of the Slowness effect
+pub const SLOWNESS_UUID: Uuid = Uuid::from_u128(0x7107DE5E_7CE8_4030_940E_514C1F160890);
+
+/// Change in movement speed for each level of Slowness
+pub const SLOWNESS_PER_LEVEL: f64 = -0.15;
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum AttributeType {
+    MaxHealth,
+    MovementSpeed
+}
+
+impl AttributeType {
+    /// Returns the key used in the Entity Properties packet
+    pub fn key(self) -> &'static str {
+        match self {
+            AttributeType::MaxHealth => "generic.maxHealth",
+            AttributeType::MovementSpeed => "generic.movementSpeed"
+        }
+    }
+
+    /// Returns the base value players have
+    pub fn default_value(self) -> f64 {
+        match self {
+            AttributeType::MaxHealth => 20.0,
+            AttributeType::MovementSpeed => 0.1
+        }
+    }
+
+    /// Returns the highest value the attribute can have, the lowest is 0
+    pub fn max_value(self) -> f64 {
+        1024.0
+    }
+}
+
+/// How a modifier changes the value of an attribute
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// Adds the amount to the base value
+    Add = 0,
+    /// Adds the amount times the base value
+    AddMultiplied = 1,
+    /// Multiplies the value by 1 + the amount
+    Multiply = 2
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Modifier {
+    pub uuid: Uuid,
+    pub amount: f64,
+    pub operation: Operation
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub attribute_type: AttributeType,
+    pub base: f64,
+    pub modifiers: Vec<Modifier>
+}
+
+impl Attribute {
+    pub fn new(attribute_type: AttributeType) -> Self {
+        Self {
+            attribute_type,
+            base: attribute_type.default_value(),
+            modifiers: Vec::new()
+        }
+    }
+
+    /// Returns the value with the modifiers applied in the same order as vanilla
+    pub fn value(&self) -> f64 {
+        let sum = |operation| self.modifiers.iter().filter(move |v| v.operation == operation).map(|v| v.amount);
+        let base = self.base + sum(Operation::Add).sum::<f64>();
+        let mut value = base + sum(Operation::AddMultiplied).map(|v| base * v).sum::<f64>();
+        for amount in sum(Operation::Multiply) {
+            value *= 1.0 + amount;
+        }
+
+        value.clamp(0.0, self.attribute_type.max_value())
+    }
+}
+
+/// The attributes of an entity, in the order they're sent
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attributes {
+    attributes: Vec<Attribute>
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            attributes: vec![Attribute::new(AttributeType::MaxHealth), Attribute::new(AttributeType::MovementSpeed)]
+        }
+    }
+}
+
+impl Attributes {
+    pub fn get(&self, attribute_type: AttributeType) -> &Attribute {
+        self.attributes.iter().find(|v| v.attribute_type == attribute_type).expect("missing attribute")
+    }
+
+    fn get_mut(&mut self, attribute_type: AttributeType) -> &mut Attribute {
+        self.attributes.iter_mut().find(|v| v.attribute_type == attribute_type).expect("missing attribute")
+    }
+
+    pub fn value(&self, attribute_type: AttributeType) -> f64 {
+        self.get(attribute_type).value()
+    }
+
+    /// Adds the modifier or replaces the one with the same uuid, returns true if anything changed
+    pub fn set_modifier(&mut self, attribute_type: AttributeType, modifier: Modifier) -> bool {
+        let modifiers = &mut self.get_mut(attribute_type).modifiers;
+        match modifiers.iter_mut().find(|v| v.uuid == modifier.uuid) {
+            Some(v) if *v == modifier => false,
+            Some(v) => {
+                *v = modifier;
+                true
+            }
+            None => {
+                modifiers.push(modifier);
+                true
+            }
+        }
+    }
+
+    /// Removes the modifier with the uuid, returns true if there was one
+    pub fn remove_modifier(&mut self, attribute_type: AttributeType, uuid: Uuid) -> bool {
+        let modifiers = &mut self.get_mut(attribute_type).modifiers;
+        let len = modifiers.len();
+        modifiers.retain(|v| v.uuid != uuid);
+        modifiers.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Attribute> {
+        self.attributes.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifiers() {
+        let mut attributes = Attributes::default();
+        assert_eq!(attributes.value(AttributeType::MovementSpeed), 0.1);
+
+        assert!(attributes.set_modifier(AttributeType::MovementSpeed, SPRINTING_SPEED_BOOST));
+        assert!(!attributes.set_modifier(AttributeType::MovementSpeed, SPRINTING_SPEED_BOOST));
+        assert!((attributes.value(AttributeType::MovementSpeed) - 0.13).abs() < 1e-6);
+
+        let speed = Modifier { uuid: SPEED_UUID, amount: 0.4, operation: Operation::Multiply };
+        attributes.set_modifier(AttributeType::MovementSpeed, speed);
+        assert!((attributes.value(AttributeType::MovementSpeed) - 0.1 * 1.3 * 1.4).abs() < 1e-6);
+
+        assert!(attributes.remove_modifier(AttributeType::MovementSpeed, SPRINTING_SPEED_BOOST.uuid));
+        assert!(!attributes.remove_modifier(AttributeType::MovementSpeed, SPRINTING_SPEED_BOOST.uuid));
+        assert!((attributes.value(AttributeType::MovementSpeed) - 0.14).abs() < 1e-6);
+        assert_eq!(attributes.value(AttributeType::MaxHealth), 20.0);
+    }
+
+    #[test]
+    fn operations_apply_in_order() {
+        let mut attribute = Attribute::new(AttributeType::MaxHealth);
+        let modifier = |n, amount, operation| Modifier { uuid: Uuid::from_u128(n), amount, operation };
+        attribute.modifiers = vec![
+            modifier(1, 1.0, Operation::Multiply),
+            modifier(2, 0.5, Operation::AddMultiplied),
+            modifier(3, 4.0, Operation::Add)
+        ];
+        // (20 + 4) * 1.5 * 2
+        assert_eq!(attribute.value(), 72.0);
+
+        attribute.modifiers.push(modifier(4, -100.0, Operation::Add));
+        assert_eq!(attribute.value(), 0.0);
+    }
+}
@@ -0,0 +1,138 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+/// How much faster each level of Speed makes an entity
+pub const SPEED_PER_LEVEL: f64 = 0.2;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Eq, FromPrimitive, Hash, PartialEq)]
+pub enum EffectType {
+    Speed = 1,
+    Slowness = 2,
+    Haste = 3,
+    MiningFatigue = 4,
+    Strength = 5,
+    InstantHealth = 6,
+    InstantDamage = 7,
+    JumpBoost = 8,
+    Nausea = 9,
+    Regeneration = 10,
+    Resistance = 11,
+    FireResistance = 12,
+    WaterBreathing = 13,
+    Invisibility = 14,
+    Blindness = 15,
+    NightVision = 16,
+    Hunger = 17,
+    Weakness = 18,
+    Poison = 19,
+    Wither = 20,
+    HealthBoost = 21,
+    Absorption = 22,
+    Saturation = 23
+}
+
+impl EffectType {
+    /// Returns the name used by /effect
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectType::Speed => "speed",
+            EffectType::Slowness => "slowness",
+            EffectType::Haste => "haste",
+            EffectType::MiningFatigue => "mining_fatigue",
+            EffectType::Strength => "strength",
+            EffectType::InstantHealth => "instant_health",
+            EffectType::InstantDamage => "instant_damage",
+            EffectType::JumpBoost => "jump_boost",
+            EffectType::Nausea => "nausea",
+            EffectType::Regeneration => "regeneration",
+            EffectType::Resistance => "resistance",
+            EffectType::FireResistance => "fire_resistance",
+            EffectType::WaterBreathing => "water_breathing",
+            EffectType::Invisibility => "invisibility",
+            EffectType::Blindness => "blindness",
+            EffectType::NightVision => "night_vision",
+            EffectType::Hunger => "hunger",
+            EffectType::Weakness => "weakness",
+            EffectType::Poison => "poison",
+            EffectType::Wither => "wither",
+            EffectType::HealthBoost => "health_boost",
+            EffectType::Absorption => "absorption",
+            EffectType::Saturation => "saturation"
+        }
+    }
+
+    /// Parses an effect by name, with or without the `minecraft:` namespace, or by numeric id
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Ok(id) = name.parse::<u8>() {
+            return Self::from_u8(id);
+        }
+
+        let name = name.strip_prefix("minecraft:").unwrap_or(name);
+        (1..=EffectType::Saturation as u8).filter_map(Self::from_u8).find(|v| v.name() == name)
+    }
+}
+
+/// A status effect applied to an entity
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Effect {
+    pub effect_type: EffectType,
+    /// 0 is level I
+    pub amplifier: u8,
+    /// Remaining duration in ticks
+    pub duration: i32,
+    pub hide_particles: bool
+}
+
+impl Effect {
+    pub fn new(effect_type: EffectType, amplifier: u8, duration: i32) -> Self {
+        Self {
+            effect_type,
+            amplifier,
+            duration,
+            hide_particles: false
+        }
+    }
+
+    /// Returns true if the effect should replace `current`, an effect of the same type.
+    /// Like vanilla, a stronger effect always does and one just as strong only if it lasts longer.
+    pub fn overrides(&self, current: &Effect) -> bool {
+        self.amplifier > current.amplifier || (self.amplifier == current.amplifier && self.duration > current.duration)
+    }
+
+    /// Returns true if the effect does something this tick, based on the remaining duration.
+    /// Like vanilla, higher amplifiers apply more often.
+    pub fn is_ready(&self) -> bool {
+        let interval = match self.effect_type {
+            EffectType::Regeneration => 50,
+            EffectType::Poison => 25,
+            EffectType::Wither => 40,
+            _ => return false
+        } >> self.amplifier.min(31);
+
+        interval == 0 || self.duration % interval == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(EffectType::from_name("speed"), Some(EffectType::Speed));
+        assert_eq!(EffectType::from_name("minecraft:fire_resistance"), Some(EffectType::FireResistance));
+        assert_eq!(EffectType::from_name("23"), Some(EffectType::Saturation));
+        assert_eq!(EffectType::from_name("0"), None);
+        assert_eq!(EffectType::from_name("flight"), None);
+    }
+
+    #[test]
+    fn stronger_or_longer_overrides() {
+        let current = Effect::new(EffectType::Speed, 1, 200);
+        assert!(Effect::new(EffectType::Speed, 1, 400).overrides(&current));
+        assert!(Effect::new(EffectType::Speed, 2, 20).overrides(&current));
+        assert!(!Effect::new(EffectType::Speed, 1, 100).overrides(&current));
+        assert!(!Effect::new(EffectType::Speed, 0, 1000).overrides(&current));
+    }
+}
@@ -0,0 +1,61 @@
+//! How fast players get hungry, and what happens when they're well fed or starving
+
+use crate::storage::world::Difficulty;
+
+/// The numbers of the hunger loop, all in one place so the tests can use them
+#[derive(Copy, Clone, Debug)]
+pub struct HungerTable {
+    /// Exhaustion added per meter walked on the ground
+    pub walk: f32,
+    /// Exhaustion added per meter sprinted on the ground
+    pub sprint: f32,
+    /// Exhaustion added per meter swum
+    pub swim: f32,
+    pub jump: f32,
+    pub sprint_jump: f32,
+    /// Exhaustion added for each block broken
+    pub dig: f32,
+    /// Exhaustion added for each half heart regenerated
+    pub regenerate: f32,
+
+    /// Exhaustion that costs a point of saturation, or a point of food once there's no saturation left
+    pub exhaustion_per_food: f32,
+    /// Exhaustion doesn't add up beyond this
+    pub max_exhaustion: f32,
+
+    /// Ticks between regenerating or starving half a heart
+    pub food_interval: u32,
+    /// Lowest food level players regenerate health at
+    pub regenerate_food_level: i32,
+    /// Ticks between the points of food players get back on peaceful difficulty
+    pub peaceful_food_interval: u32,
+    /// Health starving players are left with on each difficulty, by the value of the difficulty.
+    /// Peaceful players can't starve.
+    pub starvation_floor: [f32; 4]
+}
+
+impl HungerTable {
+    /// Returns the health starvation stops hurting players at
+    pub fn starvation_floor(&self, difficulty: Difficulty) -> f32 {
+        self.starvation_floor[difficulty as usize]
+    }
+}
+
+/// The same numbers as vanilla
+pub const HUNGER: HungerTable = HungerTable {
+    walk: 0.01,
+    sprint: 0.1,
+    swim: 0.015,
+    jump: 0.2,
+    sprint_jump: 0.8,
+    dig: 0.025,
+    regenerate: 3.0,
+
+    exhaustion_per_food: 4.0,
+    max_exhaustion: 40.0,
+
+    food_interval: 80,
+    regenerate_food_level: 18,
+    peaceful_food_interval: 10,
+    starvation_floor: [f32::INFINITY, 10.0, 1.0, 0.0]
+};
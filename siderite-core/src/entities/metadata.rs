@@ -0,0 +1,67 @@
+use bitflags::bitflags;
+
+use crate::coord::Coord;
+use crate::items::ItemStack;
+
+/// Index of the entity flags, shared by all entities
+pub const FLAGS_INDEX: u8 = 0;
+
+/// Index of the name shown above an entity, or on the boss bar of a boss
+pub const CUSTOM_NAME_INDEX: u8 = 2;
+
+/// Index of the health of a living entity
+pub const HEALTH_INDEX: u8 = 6;
+
+/// Index of the item stack of a dropped item
+pub const ITEM_INDEX: u8 = 10;
+
+/// Index of the item stack in an item frame
+pub const ITEM_FRAME_ITEM_INDEX: u8 = 8;
+
+/// Index of the rotation of the item in an item frame, in steps of 45 degrees
+pub const ITEM_FRAME_ROTATION_INDEX: u8 = 9;
+
+bitflags! {
+    #[derive(Default, Clone, Copy, Debug, PartialEq)]
+    pub struct EntityFlags: u8 {
+        const ON_FIRE = 0x01;
+        const CROUCHED = 0x02;
+        const SPRINTING = 0x08;
+        /// Eating, drinking, blocking or drawing a bow
+        const USING_ITEM = 0x10;
+        const INVISIBLE = 0x20;
+    }
+}
+
+/// A value in the Entity Metadata packet
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Float(f32),
+    String(String),
+    Slot(Option<ItemStack>),
+    Position(Coord<i32>),
+    /// Pitch, Yaw, Roll
+    Rotation(f32, f32, f32)
+}
+
+impl MetadataValue {
+    /// Returns the type id used in the Entity Metadata packet
+    pub fn type_id(&self) -> u8 {
+        match self {
+            MetadataValue::Byte(_) => 0,
+            MetadataValue::Short(_) => 1,
+            MetadataValue::Int(_) => 2,
+            MetadataValue::Float(_) => 3,
+            MetadataValue::String(_) => 4,
+            MetadataValue::Slot(_) => 5,
+            MetadataValue::Position(_) => 6,
+            MetadataValue::Rotation(..) => 7
+        }
+    }
+}
+
+/// Index and value of each entry
+pub type Metadata = Vec<(u8, MetadataValue)>;
@@ -1 +1,470 @@
+pub mod attributes;
+pub mod effects;
+pub mod hunger;
+pub mod metadata;
 pub mod player;
+pub mod tracker;
+
+use siderite_nbt::{Compound, Tag};
+
+use crate::blocks::BlockFace;
+use crate::coord::{self, Coord};
+use crate::items::ItemStack;
+
+/// Acceleration applied to a minecart when its rider pushes forward
+const MINECART_STEER_ACCELERATION: f64 = 0.1;
+
+/// Share of its speed a minecart keeps every tick
+const MINECART_DRAG: f64 = 0.96;
+
+/// Minecarts slower than this, in blocks per tick, come to a stop
+const MINECART_MIN_SPEED: f64 = 0.003;
+
+/// Number of ticks before primed TNT explodes
+pub const TNT_FUSE: i32 = 80;
+
+/// Number of rotations of the item in an item frame
+pub const ITEM_FRAME_ROTATIONS: u8 = 8;
+
+/// Speed of items thrown by players, in blocks per tick
+const THROW_SPEED: f64 = 0.3;
+
+/// Upwards speed added to thrown items, so they fly in an arc
+const THROW_LIFT: f64 = 0.1;
+
+/// Returns the velocity of an item thrown by a player looking in the direction, like vanilla without the random spread
+pub fn throw_velocity(yaw: f32, pitch: f32) -> Coord<f64> {
+    let direction = coord::look_direction(yaw, pitch);
+    Coord::new(direction.x * THROW_SPEED, direction.y * THROW_SPEED + THROW_LIFT, direction.z * THROW_SPEED)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EntityType {
+    /// A dropped item stack
+    Item,
+    Minecart,
+    PrimedTnt,
+    ItemFrame,
+    Painting,
+    Mob(MobType)
+}
+
+impl EntityType {
+    /// Returns true if a player can ride this entity
+    pub fn is_rideable(self) -> bool {
+        match self {
+            EntityType::Minecart => true,
+            EntityType::Item | EntityType::PrimedTnt | EntityType::ItemFrame | EntityType::Painting | EntityType::Mob(_) => false
+        }
+    }
+
+    /// Returns the type used in the Spawn Object packet, None for paintings and mobs which have their own packets
+    pub fn object_id(self) -> Option<u8> {
+        match self {
+            EntityType::Item => Some(2),
+            EntityType::Minecart => Some(10),
+            EntityType::PrimedTnt => Some(50),
+            EntityType::ItemFrame => Some(71),
+            EntityType::Painting | EntityType::Mob(_) => None
+        }
+    }
+
+    /// Returns the type with the name used by /summon, hanging entities can't be summoned
+    pub fn from_summon_name(name: &str) -> Option<Self> {
+        match name {
+            "Item" => Some(EntityType::Item),
+            "MinecartRideable" => Some(EntityType::Minecart),
+            "PrimedTnt" => Some(EntityType::PrimedTnt),
+            _ => MobType::from_name(name).map(EntityType::Mob)
+        }
+    }
+}
+
+/// Living entities other than players, the value is the type used in the Spawn Mob packet
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MobType {
+    Creeper = 50,
+    Skeleton = 51,
+    Spider = 52,
+    Zombie = 54,
+    Pig = 90,
+    Sheep = 91,
+    Cow = 92,
+    Chicken = 93
+}
+
+impl MobType {
+    pub const ALL: [MobType; 8] = [
+        MobType::Creeper, MobType::Skeleton, MobType::Spider, MobType::Zombie,
+        MobType::Pig, MobType::Sheep, MobType::Cow, MobType::Chicken
+    ];
+
+    /// Returns the name used by /summon
+    pub fn name(self) -> &'static str {
+        match self {
+            MobType::Creeper => "Creeper",
+            MobType::Skeleton => "Skeleton",
+            MobType::Spider => "Spider",
+            MobType::Zombie => "Zombie",
+            MobType::Pig => "Pig",
+            MobType::Sheep => "Sheep",
+            MobType::Cow => "Cow",
+            MobType::Chicken => "Chicken"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.name() == name)
+    }
+
+    /// Returns true for mobs that attack players, they can't exist on peaceful difficulty
+    pub fn is_hostile(self) -> bool {
+        match self {
+            MobType::Creeper | MobType::Skeleton | MobType::Spider | MobType::Zombie => true,
+            MobType::Pig | MobType::Sheep | MobType::Cow | MobType::Chicken => false
+        }
+    }
+
+    /// Returns the health the mob spawns with, the same as vanilla
+    pub fn max_health(self) -> f32 {
+        match self {
+            MobType::Creeper | MobType::Skeleton | MobType::Zombie => 20.0,
+            MobType::Spider => 16.0,
+            MobType::Pig | MobType::Cow => 10.0,
+            MobType::Sheep => 8.0,
+            MobType::Chicken => 4.0
+        }
+    }
+}
+
+/// The picture on a painting.
+/// TODO: paintings larger than one block
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Motive {
+    Kebab,
+    Aztec,
+    Alban,
+    Aztec2,
+    Bomb,
+    Plant,
+    Wasteland
+}
+
+impl Motive {
+    pub const ALL: [Motive; 7] = [Motive::Kebab, Motive::Aztec, Motive::Alban, Motive::Aztec2, Motive::Bomb, Motive::Plant, Motive::Wasteland];
+
+    /// Returns the name sent in Spawn Painting
+    pub fn name(self) -> &'static str {
+        match self {
+            Motive::Kebab => "Kebab",
+            Motive::Aztec => "Aztec",
+            Motive::Alban => "Alban",
+            Motive::Aztec2 => "Aztec2",
+            Motive::Bomb => "Bomb",
+            Motive::Plant => "Plant",
+            Motive::Wasteland => "Wasteland"
+        }
+    }
+}
+
+/// A non-player entity living in a world
+pub struct Entity {
+    id: u32,
+    entity_type: EntityType,
+
+    pos: Coord<f64>,
+    yaw: f32,
+    pitch: f32,
+    velocity: Coord<f64>,
+
+    /// Entity riding this entity
+    passenger: Option<u32>,
+
+    /// Health of a mob
+    health: Option<f32>,
+    /// Ticks left before the entity explodes
+    fuse: Option<i32>,
+    /// Item stack of a dropped item or the item in an item frame
+    item: Option<ItemStack>,
+
+    /// Direction item frames and paintings face, away from the wall they hang on.
+    /// Their position is the block they hang in.
+    facing: Option<BlockFace>,
+    motive: Option<Motive>,
+    /// Rotation of the item in an item frame
+    rotation: u8
+}
+
+impl Entity {
+    pub fn new(id: u32, entity_type: EntityType, pos: Coord<f64>) -> Self {
+        Self {
+            id,
+            entity_type,
+
+            pos,
+            yaw: 0f32,
+            pitch: 0f32,
+            velocity: Coord::new(0f64, 0f64, 0f64),
+
+            passenger: None,
+
+            health: None,
+            fuse: None,
+            item: None,
+
+            facing: None,
+            motive: None,
+            rotation: 0
+        }
+    }
+
+    /// Returns TNT that explodes after `fuse` ticks
+    pub fn primed_tnt(id: u32, pos: Coord<f64>, fuse: i32) -> Self {
+        let mut entity = Self::new(id, EntityType::PrimedTnt, pos);
+        entity.fuse = Some(fuse);
+        entity
+    }
+
+    /// Returns a mob with full health
+    pub fn mob(id: u32, mob_type: MobType, pos: Coord<f64>) -> Self {
+        let mut entity = Self::new(id, EntityType::Mob(mob_type), pos);
+        entity.health = Some(mob_type.max_health());
+        entity
+    }
+
+    /// Returns an entity of the type as summoned by /summon, TNT is primed and mobs have full health
+    pub fn summoned(id: u32, entity_type: EntityType, pos: Coord<f64>) -> Self {
+        match entity_type {
+            EntityType::PrimedTnt => Self::primed_tnt(id, pos, TNT_FUSE),
+            EntityType::Mob(v) => Self::mob(id, v, pos),
+            _ => Self::new(id, entity_type, pos)
+        }
+    }
+
+    /// Returns a dropped item stack
+    pub fn dropped_item(id: u32, pos: Coord<f64>, item: ItemStack) -> Self {
+        let mut entity = Self::new(id, EntityType::Item, pos);
+        entity.item = Some(item);
+        entity
+    }
+
+    /// Returns an empty item frame hanging in the block at `pos`
+    pub fn item_frame(id: u32, pos: Coord<i32>, facing: BlockFace) -> Self {
+        Self::hanging(id, EntityType::ItemFrame, pos, facing)
+    }
+
+    /// Returns a painting hanging in the block at `pos`
+    pub fn painting(id: u32, pos: Coord<i32>, facing: BlockFace, motive: Motive) -> Self {
+        let mut entity = Self::hanging(id, EntityType::Painting, pos, facing);
+        entity.motive = Some(motive);
+        entity
+    }
+
+    fn hanging(id: u32, entity_type: EntityType, pos: Coord<i32>, facing: BlockFace) -> Self {
+        debug_assert!(facing.horizontal_index().is_some());
+        let mut entity = Self::new(id, entity_type, Coord::new(pos.x as f64, pos.y as f64, pos.z as f64));
+        entity.yaw = facing.horizontal_index().unwrap_or_default() as f32 * 90.0;
+        entity.facing = Some(facing);
+        entity
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn entity_type(&self) -> EntityType {
+        self.entity_type
+    }
+
+    pub fn pos(&self) -> Coord<f64> {
+        self.pos
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch;
+    }
+
+    pub fn velocity(&self) -> Coord<f64> {
+        self.velocity
+    }
+
+    /// Sets the velocity in blocks per tick
+    pub fn set_velocity(&mut self, velocity: Coord<f64>) {
+        self.velocity = velocity;
+    }
+
+    pub fn passenger(&self) -> Option<u32> {
+        self.passenger
+    }
+
+    pub fn set_passenger(&mut self, passenger: Option<u32>) {
+        self.passenger = passenger;
+    }
+
+    pub fn item(&self) -> Option<&ItemStack> {
+        self.item.as_ref()
+    }
+
+    pub fn set_item(&mut self, item: Option<ItemStack>) {
+        self.item = item;
+    }
+
+    /// Takes the item out of an item frame, resetting its rotation
+    pub fn take_item(&mut self) -> Option<ItemStack> {
+        self.rotation = 0;
+        self.item.take()
+    }
+
+    /// Returns the data sent in the Spawn Object packet, the client ignores items without it
+    pub fn object_data(&self) -> i32 {
+        match self.entity_type {
+            EntityType::Item => 1,
+            EntityType::ItemFrame => self.facing.and_then(BlockFace::horizontal_index).unwrap_or_default() as i32,
+            EntityType::Minecart | EntityType::PrimedTnt | EntityType::Painting | EntityType::Mob(_) => 0
+        }
+    }
+
+    /// Returns the block a hanging entity hangs in, None for other entities
+    pub fn hanging_pos(&self) -> Option<Coord<i32>> {
+        self.facing?;
+        Some(Coord::new(self.pos.x.floor() as i32, self.pos.y.floor() as i32, self.pos.z.floor() as i32))
+    }
+
+    pub fn facing(&self) -> Option<BlockFace> {
+        self.facing
+    }
+
+    pub fn motive(&self) -> Option<Motive> {
+        self.motive
+    }
+
+    pub fn rotation(&self) -> u8 {
+        self.rotation
+    }
+
+    /// Turns the item in an item frame by 45 degrees
+    pub fn rotate(&mut self) {
+        self.rotation = (self.rotation + 1) % ITEM_FRAME_ROTATIONS;
+    }
+
+    pub fn health(&self) -> Option<f32> {
+        self.health
+    }
+
+    pub fn set_health(&mut self, health: f32) {
+        if self.health.is_some() {
+            self.health = Some(health);
+        }
+    }
+
+    pub fn fuse(&self) -> Option<i32> {
+        self.fuse
+    }
+
+    /// Applies the data tag of a /summon command.
+    /// Tags the entity doesn't have, or with values of the wrong type, are ignored like in vanilla.
+    pub fn apply_nbt(&mut self, nbt: &Compound) {
+        if let Some(health) = nbt.get("Health").and_then(number) {
+            self.set_health(health as f32);
+        }
+        if let Some(fuse) = nbt.get("Fuse").and_then(number) {
+            if self.fuse.is_some() {
+                self.fuse = Some(fuse as i32);
+            }
+        }
+        if let Some([x, y, z]) = nbt.get("Motion").and_then(numbers) {
+            self.velocity = Coord::new(x, y, z);
+        }
+        if let Some([yaw, pitch]) = nbt.get("Rotation").and_then(numbers) {
+            self.set_rotation(yaw as f32, pitch as f32);
+        }
+        if self.entity_type == EntityType::Item {
+            if let Some(item) = nbt.get("Item").and_then(Tag::as_compound).and_then(ItemStack::from_nbt) {
+                self.item = Some(item);
+            }
+        }
+    }
+
+    /// Counts down the fuse, returns true when it runs out
+    pub fn tick_fuse(&mut self) -> bool {
+        match self.fuse.as_mut() {
+            Some(v) => {
+                *v -= 1;
+                *v <= 0
+            }
+            None => false
+        }
+    }
+
+    /// Applies the movement input of the rider.
+    /// `yaw` is the look direction of the rider in degrees.
+    pub fn steer(&mut self, yaw: f32, forward: f32) {
+        if self.entity_type != EntityType::Minecart || forward <= 0f32 {
+            return;
+        }
+
+        // Like vanilla, riders can only give a minecart a push when it's (almost) standing still
+        let speed_sq = self.velocity.x * self.velocity.x + self.velocity.z * self.velocity.z;
+        if speed_sq < 0.01 {
+            let yaw = (yaw as f64).to_radians();
+            self.velocity.x -= yaw.sin() * MINECART_STEER_ACCELERATION;
+            self.velocity.z += yaw.cos() * MINECART_STEER_ACCELERATION;
+        }
+    }
+
+    /// Moves a minecart by its horizontal velocity and slows it down, returns true if it moved.
+    /// `blocked` tells if a position is inside a block the minecart can't pass, it stops in front of those.
+    /// Rails aren't followed yet.
+    pub fn tick_movement(&mut self, blocked: impl Fn(Coord<f64>) -> bool) -> bool {
+        if self.entity_type != EntityType::Minecart {
+            return false;
+        }
+
+        let (x, z) = (self.velocity.x, self.velocity.z);
+        let next = Coord::new(self.pos.x + x, self.pos.y, self.pos.z + z);
+        if x * x + z * z < MINECART_MIN_SPEED * MINECART_MIN_SPEED || blocked(next) {
+            self.velocity.x = 0.0;
+            self.velocity.z = 0.0;
+            return false;
+        }
+
+        self.pos = next;
+        self.velocity.x *= MINECART_DRAG;
+        self.velocity.z *= MINECART_DRAG;
+        true
+    }
+}
+
+/// Returns the value of a numeric tag of any type
+fn number(tag: &Tag) -> Option<f64> {
+    match tag {
+        Tag::Byte(v) => Some(*v as f64),
+        Tag::Short(v) => Some(*v as f64),
+        Tag::Int(v) => Some(*v as f64),
+        Tag::Long(v) => Some(*v as f64),
+        Tag::Float(v) => Some(*v as f64),
+        Tag::Double(v) => Some(*v),
+        _ => None
+    }
+}
+
+/// Returns the values of a list of exactly `N` numbers
+fn numbers<const N: usize>(tag: &Tag) -> Option<[f64; N]> {
+    let list = tag.as_list().filter(|v| v.len() == N)?;
+    let mut values = [0f64; N];
+    for (value, tag) in values.iter_mut().zip(list) {
+        *value = number(tag)?;
+    }
+
+    Some(values)
+}
@@ -1,11 +1,29 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, ErrorKind};
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 
 use bitflags::bitflags;
 use num_derive::FromPrimitive;
+use rand::{thread_rng, Rng};
+use serde_json::Value;
+use siderite_nbt::{Compound, Tag};
 
+use crate::beds;
+use crate::blocks::BlockType;
 use crate::client::Client;
 use crate::coord::Coord;
-use crate::storage::world::World;
+use crate::entities::attributes::{self, AttributeType, Attributes, Modifier, Operation};
+use crate::entities::effects::{self, Effect, EffectType};
+use crate::entities::hunger::HUNGER;
+use crate::entities::metadata::EntityFlags;
+use crate::items::{Inventory, ItemStack};
+use crate::stats::{self, Statistics};
+use crate::storage::versioning;
+use crate::storage::world::{Difficulty, World};
+use crate::tile_entities;
+use crate::window::{ENDER_CHEST_SIZE, SharedInventory};
 
 bitflags! {
     #[derive(Default, Clone, Copy)]
@@ -52,6 +70,80 @@ pub enum GameMode {
 /// The health value can be larger than this due to commands
 const DEFAULT_HEATH: f32 = 20.0;
 
+/// Maximum food level
+pub const MAX_FOOD_LEVEL: i32 = 20;
+
+/// Saturation of a player that just spawned
+const DEFAULT_SATURATION: f32 = 5.0;
+
+/// Number of ticks it takes to eat food
+pub const EATING_DURATION: u32 = 32;
+
+/// Height of the eyes above the feet of a standing player
+const EYE_HEIGHT: f64 = 1.62;
+
+/// Distance a player can fall without taking damage
+const SAFE_FALL_DISTANCE: f64 = 3.0;
+
+/// Speed an attack knocks a player back with, in blocks per tick
+const KNOCKBACK_STRENGTH: f64 = 0.4;
+
+/// What hurt a player, decides the death message
+#[derive(Clone, Debug, PartialEq)]
+pub enum DamageCause {
+    Generic,
+    Fall,
+    Explosion,
+    Starvation,
+    /// Entity ID and name of the attacker
+    Player(u32, String)
+}
+
+impl DamageCause {
+    /// Returns the entity ID of the killer, if the player was killed by an entity
+    pub fn killer(&self) -> Option<u32> {
+        match self {
+            DamageCause::Player(id, _) => Some(*id),
+            _ => None
+        }
+    }
+
+    pub fn death_message(&self, name: &str) -> String {
+        match self {
+            DamageCause::Generic => format!("{} died", name),
+            DamageCause::Fall => format!("{} fell from a high place", name),
+            DamageCause::Explosion => format!("{} blew up", name),
+            DamageCause::Starvation => format!("{} starved to death", name),
+            DamageCause::Player(_, killer) => format!("{} was slain by {}", name, killer)
+        }
+    }
+}
+
+/// Returns the velocity an attacker at `attacker` knocks a player at `pos` back with.
+/// Like vanilla the player gets pushed away from the attacker and up.
+pub fn knockback(pos: Coord<f64>, attacker: Coord<f64>) -> Coord<f64> {
+    let (dx, dz) = (pos.x - attacker.x, pos.z - attacker.z);
+    let distance = (dx * dx + dz * dz).sqrt();
+    if distance < 1.0e-4 {
+        // No direction to push the player in
+        return Coord::new(0.0, KNOCKBACK_STRENGTH, 0.0);
+    }
+
+    Coord::new(dx / distance * KNOCKBACK_STRENGTH, KNOCKBACK_STRENGTH, dz / distance * KNOCKBACK_STRENGTH)
+}
+
+/// How a player moved since its position was last sent to the other players
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Movement {
+    Look,
+    /// Change in position in 1/32 blocks
+    RelativeMove(Coord<i8>),
+    /// Change in position in 1/32 blocks
+    LookAndRelativeMove(Coord<i8>),
+    /// Moved 4 blocks or more along an axis, too far for a relative move
+    Teleport
+}
+
 pub struct Player {
     client: Arc<RwLock<Client>>,
     world: Arc<RwLock<World>>,
@@ -63,7 +155,54 @@ pub struct Player {
     pos: Coord<f64>,
     yaw: f32,
     pitch: f32,
-    skin_parts: SkinFlags
+    /// Position the other players last got sent
+    last_sent_pos: Coord<f64>,
+    /// Yaw and pitch the other players last got sent
+    last_sent_look: (f32, f32),
+    skin_parts: SkinFlags,
+    on_ground: bool,
+    is_sprinting: bool,
+    /// Highest Y since the player was last on the ground
+    fall_start: Option<f64>,
+
+    /// Entity the player is riding
+    vehicle: Option<u32>,
+
+    stats: Statistics,
+    inventory: Inventory,
+    /// Contents of the ender chest, the same in every ender chest the player opens
+    ender_chest: SharedInventory,
+
+    food_level: i32,
+    saturation: f32,
+    exhaustion: f32,
+    /// Ticks since the player last regenerated or starved
+    food_timer: u32,
+    /// Ticks until a peaceful player gets a point of food back
+    peaceful_food_timer: u32,
+    /// Number of ticks the player has been eating for
+    eating_ticks: Option<u32>,
+
+    effects: HashMap<EffectType, Effect>,
+    attributes: Attributes,
+
+    /// Sign the player placed and is allowed to write on
+    editing_sign: Option<Coord<i32>>,
+
+    /// Chat component shown in the tab list instead of the username
+    display_name: Option<Value>,
+    /// Where the player respawns instead of the spawn of the world, set by /spawnpoint or by sleeping in a bed
+    spawn_point: Option<Coord<i32>>,
+    /// True if the spawn point stays when there's no bed at it, like the ones set by /spawnpoint
+    spawn_forced: bool,
+    /// Head of the bed the player is sleeping in
+    bed: Option<Coord<i32>>,
+    /// Number of ticks the player has been in bed for
+    sleep_ticks: i32,
+
+    xp_level: i32,
+    /// Seed of the enchantments offered by enchanting tables, changes every time the player enchants something
+    enchant_seed: i32
 }
 
 impl Player {
@@ -84,10 +223,59 @@ impl Player {
             pos,
             yaw: 0f32,
             pitch: 0f32,
-            skin_parts: Default::default()
+            last_sent_pos: pos,
+            last_sent_look: (0f32, 0f32),
+            skin_parts: Default::default(),
+            on_ground: true,
+            is_sprinting: false,
+            fall_start: None,
+
+            vehicle: None,
+
+            stats: Statistics::new(),
+            inventory: Inventory::new(),
+            ender_chest: SharedInventory::new(ENDER_CHEST_SIZE),
+
+            food_level: MAX_FOOD_LEVEL,
+            saturation: DEFAULT_SATURATION,
+            exhaustion: 0.0,
+            food_timer: 0,
+            peaceful_food_timer: 0,
+            eating_ticks: None,
+
+            effects: HashMap::new(),
+            attributes: Attributes::default(),
+
+            editing_sign: None,
+
+            display_name: None,
+            spawn_point: None,
+            spawn_forced: false,
+            bed: None,
+            sleep_ticks: 0,
+
+            xp_level: 0,
+            enchant_seed: thread_rng().gen()
         }
     }
 
+    /// Brings the player back to life at `pos`, with full health and food and without effects
+    pub fn respawn(&mut self, pos: Coord<f64>) {
+        self.health = DEFAULT_HEATH;
+        self.food_level = MAX_FOOD_LEVEL;
+        self.saturation = DEFAULT_SATURATION;
+        self.exhaustion = 0.0;
+        self.food_timer = 0;
+        self.eating_ticks = None;
+        self.effects.clear();
+        self.fall_start = None;
+        self.on_ground = true;
+        self.is_flying = false;
+        self.is_sprinting = false;
+        self.attributes = Attributes::default();
+        self.pos = pos;
+    }
+
     /// Returns the current gamemode of the player.
     pub fn gamemode(&self) -> GameMode {
         self.gamemode
@@ -97,6 +285,10 @@ impl Player {
         self.world.clone()
     }
 
+    pub fn set_world(&mut self, world: Arc<RwLock<World>>) {
+        self.world = world;
+    }
+
     pub fn client(&self) -> Arc<RwLock<Client>> {
         self.client.clone()
     }
@@ -105,6 +297,10 @@ impl Player {
         self.health
     }
 
+    pub fn set_health(&mut self, health: f32) {
+        self.health = health.max(0.0);
+    }
+
     pub fn abilities(&self) -> Abilities {
         let mut abilities = Abilities::default();
         if self.gamemode == GameMode::Creative {
@@ -126,6 +322,10 @@ impl Player {
         self.pos
     }
 
+    pub fn set_pos(&mut self, pos: Coord<f64>) {
+        self.pos = pos;
+    }
+
     pub fn yaw(&self) -> f32 {
         self.yaw
     }
@@ -134,7 +334,2589 @@ impl Player {
         self.pitch
     }
 
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch;
+    }
+
+    /// Returns how the player moved since the last call, None if it didn't.
+    /// Positions are compared at the 1/32 block precision of the protocol,
+    /// so small moves add up instead of getting lost.
+    pub fn take_movement(&mut self) -> Option<Movement> {
+        let fixed = |v: f64| (v * 32.0).floor() as i32;
+        let delta = |new: f64, old: f64| i8::try_from(fixed(new) - fixed(old)).ok();
+        let looked = (self.yaw, self.pitch) != self.last_sent_look;
+        let (new, old) = (self.pos, self.last_sent_pos);
+        let movement = match (delta(new.x, old.x), delta(new.y, old.y), delta(new.z, old.z)) {
+            (Some(0), Some(0), Some(0)) => looked.then_some(Movement::Look),
+            (Some(x), Some(y), Some(z)) if looked => Some(Movement::LookAndRelativeMove(Coord::new(x, y, z))),
+            (Some(x), Some(y), Some(z)) => Some(Movement::RelativeMove(Coord::new(x, y, z))),
+            _ => Some(Movement::Teleport)
+        };
+
+        self.last_sent_pos = self.pos;
+        self.last_sent_look = (self.yaw, self.pitch);
+        movement
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.is_flying
+    }
+
+    /// Starts or stops flying, only if the player is allowed to fly
+    pub fn set_flying(&mut self, flying: bool) {
+        self.is_flying = flying && self.may_fly;
+    }
+
+    pub fn on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    pub fn is_sprinting(&self) -> bool {
+        self.is_sprinting
+    }
+
+    pub fn set_sprinting(&mut self, sprinting: bool) {
+        self.is_sprinting = sprinting;
+        if sprinting {
+            self.attributes.set_modifier(AttributeType::MovementSpeed, attributes::SPRINTING_SPEED_BOOST);
+        } else {
+            self.attributes.remove_modifier(AttributeType::MovementSpeed, attributes::SPRINTING_SPEED_BOOST.uuid);
+        }
+    }
+
+    pub fn attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+
+    /// Updates the movement speed modifiers of Speed and Slowness to the effects the player has
+    fn update_speed_modifiers(&mut self) {
+        let modifiers = [
+            (EffectType::Speed, attributes::SPEED_UUID, effects::SPEED_PER_LEVEL),
+            (EffectType::Slowness, attributes::SLOWNESS_UUID, attributes::SLOWNESS_PER_LEVEL)
+        ];
+        for (effect_type, uuid, per_level) in modifiers {
+            match self.effects.get(&effect_type) {
+                Some(effect) => self.attributes.set_modifier(AttributeType::MovementSpeed, Modifier {
+                    uuid,
+                    amount: per_level * (effect.amplifier as f64 + 1.0),
+                    operation: Operation::Multiply
+                }),
+                None => self.attributes.remove_modifier(AttributeType::MovementSpeed, uuid)
+            };
+        }
+    }
+
+    /// Forgets the height the player is falling from, so it doesn't take fall damage after being teleported
+    pub fn reset_fall(&mut self) {
+        self.fall_start = None;
+    }
+
+    /// Tracks the height the player is falling from, has to be called before the new position is set.
+    /// `landing_block` is the liquid the player is in or else the block below the player.
+    /// Returns the fall damage when the player lands.
+    pub fn update_fall(&mut self, y: f64, on_ground: bool, landing_block: BlockType) -> Option<f32> {
+        self.on_ground = on_ground;
+        let exempt = self.is_flying || self.gamemode == GameMode::Creative || self.gamemode == GameMode::Spectator;
+        // Water breaks any fall
+        if exempt || matches!(landing_block, BlockType::Water | BlockType::FlowingWater) {
+            self.fall_start = None;
+            return None;
+        }
+
+        if !on_ground {
+            let start = self.fall_start.unwrap_or(self.pos.y);
+            self.fall_start = Some(start.max(self.pos.y).max(y));
+            return None;
+        }
+
+        let distance = self.fall_start.take().map_or(0.0, |v| v - y);
+        if distance <= 0.0 {
+            return None;
+        }
+
+        self.stats.increment(stats::FALL_ONE_CM, (distance * 100.0).round() as i32);
+        // Slime blocks bounce the player back up
+        if landing_block == BlockType::SlimeBlock {
+            return None;
+        }
+
+        let damage = (distance - SAFE_FALL_DISTANCE).ceil();
+        if damage > 0.0 {
+            Some(damage as f32)
+        } else {
+            None
+        }
+    }
+
     pub fn skin_parts(&self) -> SkinFlags {
         self.skin_parts
     }
+
+    pub fn vehicle(&self) -> Option<u32> {
+        self.vehicle
+    }
+
+    pub fn set_vehicle(&mut self, vehicle: Option<u32>) {
+        self.vehicle = vehicle;
+    }
+
+    pub fn stats(&self) -> &Statistics {
+        &self.stats
+    }
+
+    pub fn stats_mut(&mut self) -> &mut Statistics {
+        &mut self.stats
+    }
+
+    pub fn set_stats(&mut self, stats: Statistics) {
+        self.stats = stats;
+    }
+
+    /// Saves the inventory, ender chest, position and spawn point of the player to a player.dat file
+    pub fn save_data(&self, path: &Path) -> io::Result<()> {
+        let mut nbt = Compound::new();
+        nbt.insert(versioning::DATA_VERSION_TAG, Tag::Int(versioning::DATA_VERSION));
+        nbt.insert("Inventory", tile_entities::items_nbt(&self.inventory));
+        nbt.insert("EnderItems", tile_entities::items_nbt(&self.ender_chest.lock()));
+        nbt.insert("Pos", Tag::List(vec![Tag::Double(self.pos.x), Tag::Double(self.pos.y), Tag::Double(self.pos.z)]));
+        nbt.insert("Rotation", Tag::List(vec![Tag::Float(self.yaw), Tag::Float(self.pitch)]));
+        if let Some(spawn) = self.spawn_point {
+            nbt.insert("SpawnX", Tag::Int(spawn.x));
+            nbt.insert("SpawnY", Tag::Int(spawn.y));
+            nbt.insert("SpawnZ", Tag::Int(spawn.z));
+            nbt.insert("SpawnForced", Tag::Byte(self.spawn_forced as i8));
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        siderite_nbt::write_gzip(BufWriter::new(File::create(path)?), "", &Tag::Compound(nbt))
+    }
+
+    /// Loads the data written by `save_data`, a missing file keeps the player as it is
+    pub fn load_data(&mut self, path: &Path) -> io::Result<()> {
+        let file = match File::open(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e)
+        };
+
+        let (_, mut root) = siderite_nbt::read_gzip(BufReader::new(file))?;
+        let nbt = root.as_compound_mut().ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "player data isn't a compound"))?;
+        versioning::migrate(&[], nbt)?;
+
+        if let Some(items) = nbt.get("Inventory") {
+            tile_entities::load_items(items, &mut self.inventory);
+        }
+        if let Some(items) = nbt.get("EnderItems") {
+            tile_entities::load_items(items, &mut self.ender_chest.lock());
+        }
+
+        if let Some([Tag::Double(x), Tag::Double(y), Tag::Double(z)]) = nbt.get("Pos").and_then(Tag::as_list) {
+            self.pos = Coord::new(*x, *y, *z);
+            self.last_sent_pos = self.pos;
+        }
+        if let Some([Tag::Float(yaw), Tag::Float(pitch)]) = nbt.get("Rotation").and_then(Tag::as_list) {
+            self.set_rotation(*yaw, *pitch);
+            self.last_sent_look = (*yaw, *pitch);
+        }
+
+        let int = |name: &str| nbt.get(name).and_then(Tag::as_int);
+        if let (Some(x), Some(y), Some(z)) = (int("SpawnX"), int("SpawnY"), int("SpawnZ")) {
+            self.set_spawn_point(Some(Coord::new(x, y, z)), nbt.get("SpawnForced").and_then(Tag::as_byte) == Some(1));
+        }
+
+        Ok(())
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    pub fn inventory_mut(&mut self) -> &mut Inventory {
+        &mut self.inventory
+    }
+
+    pub fn food_level(&self) -> i32 {
+        self.food_level
+    }
+
+    pub fn set_food_level(&mut self, food_level: i32) {
+        self.food_level = food_level.clamp(0, MAX_FOOD_LEVEL);
+    }
+
+    pub fn saturation(&self) -> f32 {
+        self.saturation
+    }
+
+    pub fn exhaustion(&self) -> f32 {
+        self.exhaustion
+    }
+
+    /// Makes the player hungrier, creative and spectator players can't get hungry
+    pub fn add_exhaustion(&mut self, exhaustion: f32) {
+        if self.gamemode == GameMode::Creative || self.gamemode == GameMode::Spectator {
+            return;
+        }
+
+        self.exhaustion = (self.exhaustion + exhaustion).min(HUNGER.max_exhaustion);
+    }
+
+    /// Adds the exhaustion of moving `distance` meters, in water if `swimming`
+    pub fn add_move_exhaustion(&mut self, distance: f64, swimming: bool) {
+        let per_meter = if swimming {
+            HUNGER.swim
+        } else if self.is_sprinting {
+            HUNGER.sprint
+        } else {
+            HUNGER.walk
+        };
+        self.add_exhaustion(distance as f32 * per_meter);
+    }
+
+    pub fn jump(&mut self) {
+        self.add_exhaustion(if self.is_sprinting { HUNGER.sprint_jump } else { HUNGER.jump });
+    }
+
+    /// Turns exhaustion into hunger, regenerates health when the player is well fed
+    /// and gives peaceful players their food back.
+    /// Returns the damage a starving player takes this tick.
+    pub fn tick_hunger(&mut self, difficulty: Difficulty) -> Option<f32> {
+        if self.health <= 0.0 {
+            return None;
+        }
+
+        if difficulty == Difficulty::Peaceful && self.food_level < MAX_FOOD_LEVEL {
+            self.peaceful_food_timer += 1;
+            if self.peaceful_food_timer >= HUNGER.peaceful_food_interval {
+                self.peaceful_food_timer = 0;
+                self.food_level += 1;
+            }
+        }
+
+        if self.exhaustion > HUNGER.exhaustion_per_food {
+            self.exhaustion -= HUNGER.exhaustion_per_food;
+            if self.saturation > 0.0 {
+                self.saturation = (self.saturation - 1.0).max(0.0);
+            } else if difficulty != Difficulty::Peaceful {
+                self.food_level = (self.food_level - 1).max(0);
+            }
+        }
+
+        if self.food_level >= HUNGER.regenerate_food_level && self.health < DEFAULT_HEATH {
+            self.food_timer += 1;
+            if self.food_timer >= HUNGER.food_interval {
+                self.food_timer = 0;
+                self.health = (self.health + 1.0).min(DEFAULT_HEATH);
+                self.add_exhaustion(HUNGER.regenerate);
+            }
+        } else if self.food_level <= 0 {
+            self.food_timer += 1;
+            if self.food_timer >= HUNGER.food_interval {
+                self.food_timer = 0;
+                if self.health > HUNGER.starvation_floor(difficulty) {
+                    return Some(1.0);
+                }
+            }
+        } else {
+            self.food_timer = 0;
+        }
+
+        None
+    }
+
+    /// Returns the position of the eyes of the player
+    pub fn eye_pos(&self) -> Coord<f64> {
+        Coord::new(self.pos.x, self.pos.y + EYE_HEIGHT, self.pos.z)
+    }
+
+    pub fn entity_flags(&self) -> EntityFlags {
+        let mut flags = EntityFlags::default();
+        if self.is_sprinting {
+            flags |= EntityFlags::SPRINTING;
+        }
+
+        if self.eating_ticks.is_some() {
+            flags |= EntityFlags::USING_ITEM;
+        }
+
+        flags
+    }
+
+    pub fn is_eating(&self) -> bool {
+        self.eating_ticks.is_some()
+    }
+
+    /// Starts eating the held item if it's food and the player is hungry.
+    /// Returns true if the player started eating.
+    pub fn start_eating(&mut self) -> bool {
+        let is_food = self.inventory.held_item()
+            .and_then(|v| v.item_type())
+            .and_then(|v| v.food())
+            .is_some();
+        // Creative players can't get hungry
+        if !is_food || self.gamemode == GameMode::Creative || self.food_level >= MAX_FOOD_LEVEL {
+            return false;
+        }
+
+        self.eating_ticks = Some(0);
+        true
+    }
+
+    pub fn stop_eating(&mut self) {
+        self.eating_ticks = None;
+    }
+
+    /// Advances the eating timer, consumes the held item once it finishes.
+    /// Returns true if the player finished eating this tick.
+    pub fn tick_eating(&mut self) -> bool {
+        let ticks = match self.eating_ticks.as_mut() {
+            Some(v) => v,
+            None => return false
+        };
+
+        *ticks += 1;
+        if *ticks < EATING_DURATION {
+            return false;
+        }
+
+        self.eating_ticks = None;
+        let mut item = match self.inventory.held_item() {
+            Some(v) => v.clone(),
+            None => return true
+        };
+
+        if let Some((hunger, saturation)) = item.item_type().and_then(|v| v.food()) {
+            self.food_level = (self.food_level + hunger).min(MAX_FOOD_LEVEL);
+            self.saturation = (self.saturation + hunger as f32 * saturation * 2.0).min(self.food_level as f32);
+            item.count -= 1;
+            self.inventory.set_held_item(Some(item));
+        }
+
+        true
+    }
+
+    pub fn effects(&self) -> impl Iterator<Item = &Effect> {
+        self.effects.values()
+    }
+
+    pub fn get_effect(&self, effect_type: EffectType) -> Option<&Effect> {
+        self.effects.get(&effect_type)
+    }
+
+    /// Adds the effect, an effect of the same type is only replaced by a stronger or longer one.
+    /// Returns the effect of the type the player has now.
+    pub fn add_effect(&mut self, effect: Effect) -> Effect {
+        let current = self.effects.entry(effect.effect_type).or_insert(effect);
+        if effect.overrides(current) {
+            *current = effect;
+        }
+
+        let effect = *current;
+        self.update_speed_modifiers();
+        effect
+    }
+
+    /// Returns how much faster than normal the player can move
+    pub fn speed_multiplier(&self) -> f64 {
+        match self.get_effect(EffectType::Speed) {
+            Some(v) => 1.0 + effects::SPEED_PER_LEVEL * (v.amplifier as f64 + 1.0),
+            None => 1.0
+        }
+    }
+
+    pub fn remove_effect(&mut self, effect_type: EffectType) -> Option<Effect> {
+        let effect = self.effects.remove(&effect_type);
+        self.update_speed_modifiers();
+        effect
+    }
+
+    /// Applies the effects and counts down their duration.
+    /// Returns the effects that expired this tick.
+    pub fn tick_effects(&mut self) -> Vec<EffectType> {
+        let mut expired = Vec::new();
+        for effect in self.effects.values_mut() {
+            if effect.is_ready() {
+                match effect.effect_type {
+                    EffectType::Regeneration if self.health < DEFAULT_HEATH => self.health += 1.0,
+                    // Poison can't kill
+                    EffectType::Poison if self.health > 1.0 => self.health -= 1.0,
+                    EffectType::Wither => self.health = (self.health - 1.0).max(0.0),
+                    // TODO: fire resistance needs fire and lava to hurt players
+                    _ => ()
+                }
+            }
+
+            effect.duration -= 1;
+            if effect.duration <= 0 {
+                expired.push(effect.effect_type);
+            }
+        }
+
+        for effect_type in &expired {
+            self.effects.remove(effect_type);
+        }
+        self.update_speed_modifiers();
+
+        expired
+    }
+
+    /// Replaces one of the held items with `item`, like a bucket getting filled.
+    /// Creative players keep the held item. Returns `item` if it doesn't fit in the inventory.
+    pub fn replace_held_item(&mut self, item: ItemStack) -> Option<ItemStack> {
+        if self.gamemode == GameMode::Creative {
+            return None;
+        }
+
+        let mut held = match self.inventory.held_item() {
+            Some(v) => v.clone(),
+            None => return None
+        };
+
+        if held.count <= 1 {
+            self.inventory.set_held_item(Some(item));
+            return None;
+        }
+
+        held.count -= 1;
+        self.inventory.set_held_item(Some(held));
+        self.inventory.add(item)
+    }
+
+    /// Takes one of the held items, or the whole stack, out of the inventory to drop it.
+    /// Unlike placing blocks this takes the items from creative players too.
+    pub fn drop_held_item(&mut self, whole_stack: bool) -> Option<ItemStack> {
+        let mut held = self.inventory.held_item()?.clone();
+        let count = if whole_stack { held.count } else { 1 };
+        held.count -= count;
+        self.inventory.set_held_item(Some(held.clone()));
+        Some(ItemStack { count, ..held })
+    }
+
+    /// Uses up one of the held items, like a placed block.
+    /// Creative players keep the held item.
+    pub fn consume_held_item(&mut self) {
+        if self.gamemode == GameMode::Creative {
+            return;
+        }
+
+        if let Some(mut held) = self.inventory.held_item().cloned() {
+            held.count -= 1;
+            self.inventory.set_held_item(Some(held));
+        }
+    }
+
+    pub fn ender_chest(&self) -> &SharedInventory {
+        &self.ender_chest
+    }
+
+    pub fn editing_sign(&self) -> Option<Coord<i32>> {
+        self.editing_sign
+    }
+
+    pub fn set_editing_sign(&mut self, pos: Option<Coord<i32>>) {
+        self.editing_sign = pos;
+    }
+
+    pub fn display_name(&self) -> Option<&Value> {
+        self.display_name.as_ref()
+    }
+
+    pub fn set_display_name(&mut self, display_name: Option<Value>) {
+        self.display_name = display_name;
+    }
+
+    pub fn spawn_point(&self) -> Option<Coord<i32>> {
+        self.spawn_point
+    }
+
+    pub fn spawn_forced(&self) -> bool {
+        self.spawn_forced
+    }
+
+    pub fn set_spawn_point(&mut self, spawn_point: Option<Coord<i32>>, forced: bool) {
+        self.spawn_point = spawn_point;
+        self.spawn_forced = forced;
+    }
+
+    /// Returns the head of the bed the player is sleeping in
+    pub fn bed(&self) -> Option<Coord<i32>> {
+        self.bed
+    }
+
+    /// Lies down in the bed with its head at `head`
+    pub fn sleep(&mut self, head: Coord<i32>) {
+        self.bed = Some(head);
+        self.sleep_ticks = 0;
+    }
+
+    /// Gets out of bed, returns the head of the bed the player was in
+    pub fn wake_up(&mut self) -> Option<Coord<i32>> {
+        self.sleep_ticks = 0;
+        self.bed.take()
+    }
+
+    /// Counts the ticks the player spent in bed
+    pub fn tick_sleep(&mut self) {
+        if self.bed.is_some() {
+            self.sleep_ticks = (self.sleep_ticks + 1).min(beds::FULLY_ASLEEP_TICKS);
+        }
+    }
+
+    /// Returns true once the player has been in bed long enough to skip the night
+    pub fn is_fully_asleep(&self) -> bool {
+        self.bed.is_some() && self.sleep_ticks >= beds::FULLY_ASLEEP_TICKS
+    }
+
+    pub fn xp_level(&self) -> i32 {
+        self.xp_level
+    }
+
+    pub fn set_xp_level(&mut self, xp_level: i32) {
+        self.xp_level = xp_level.max(0);
+    }
+
+    pub fn enchant_seed(&self) -> i32 {
+        self.enchant_seed
+    }
+
+    /// Picks a new enchant seed, so the enchanting tables offer other enchantments
+    pub fn reroll_enchant_seed(&mut self) {
+        self.enchant_seed = thread_rng().gen();
+    }
+
+    /// Uses up one durability point of the held item, breaking it if it has none left.
+    /// Creative players don't damage their items.
+    pub fn damage_held_item(&mut self) {
+        if self.gamemode == GameMode::Creative {
+            return;
+        }
+
+        let mut held = match self.inventory.held_item() {
+            Some(v) => v.clone(),
+            None => return
+        };
+
+        let max_damage = match held.item_type().and_then(|v| v.max_damage()) {
+            Some(v) => v,
+            None => return
+        };
+
+        held.damage += 1;
+        if held.damage > max_damage {
+            self.inventory.set_held_item(None);
+        } else {
+            self.inventory.set_held_item(Some(held));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::Receiver;
+    use siderite_nbt::Tag;
+
+    use super::*;
+    use crate::coord::ChunkCoord;
+    use crate::items::{HOTBAR_START, INVENTORY_SIZE, ItemType, MAIN_START};
+    use crate::blocks::BlockFace;
+    use crate::protocol::{Animation, BlockPlacement, DigStatus, EntityAction, EntityStatus, GameStateReason, TeleportFlags, UseEntityAction};
+    use crate::protocol::packets::{Packet, PlayerListAction};
+    use crate::redstone;
+    use crate::server::{Server, ServerConfig};
+    use crate::storage::chunk::chunk_map::MAX_PREGEN_RADIUS;
+    use crate::storage::chunk::generator::GeneratorType;
+    use crate::storage::game_rules;
+    use crate::storage::world::{DAY_LENGTH, Dimension, WorldConfig};
+    use crate::tile_entities::TileEntity;
+    use crate::test_utils::{test_config, test_player, test_server, test_server_with_config};
+    use crate::anvil;
+    use crate::beds;
+    use crate::enchanting::{self, Enchantment, EnchantingOptions};
+    use crate::crafting;
+    use crate::furnace;
+    use crate::map::{self, MAP_SIZE};
+    use crate::boss_bar;
+    use crate::commands;
+    use crate::entities::{self, EntityType, MobType};
+    use crate::entities::metadata::{self, MetadataValue};
+    use crate::window::{CHEST_SIZE, WindowType};
+    use crate::weather::WeatherType;
+
+    #[test]
+    fn eating_takes_32_ticks() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+        player.inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bread, 2)));
+        player.set_food_level(10);
+
+        assert!(player.start_eating());
+        assert!(player.entity_flags().contains(EntityFlags::USING_ITEM));
+        for _ in 1..EATING_DURATION {
+            assert!(!player.tick_eating());
+        }
+
+        assert!(player.tick_eating());
+        assert!(!player.is_eating());
+        assert_eq!(player.food_level(), 15);
+        assert_eq!(player.inventory().held_item(), Some(&ItemStack::of(ItemType::Bread, 1)));
+    }
+
+    #[test]
+    fn sprint_jumping_makes_hungry() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+        player.set_sprinting(true);
+        assert!(player.entity_flags().contains(EntityFlags::SPRINTING));
+
+        // A sprint jump every tick, covering a meter each
+        let mut curve = Vec::new();
+        for _ in 0..100 {
+            player.jump();
+            player.add_move_exhaustion(1.0, false);
+            assert_eq!(player.tick_hunger(Difficulty::Easy), None);
+            curve.push((player.food_level(), player.saturation()));
+        }
+
+        // Saturation runs out before the food level drops
+        let first_hungry = curve.iter().position(|v| v.0 < MAX_FOOD_LEVEL).unwrap();
+        assert!(curve[..first_hungry].windows(2).all(|v| v[1].1 <= v[0].1));
+        assert_eq!(curve[first_hungry].1, 0.0);
+        assert!(curve.windows(2).all(|v| v[0].0 - v[1].0 <= 1));
+
+        // Then every bit of exhaustion that costs a point of food does
+        let exhaustion = 100.0 * (HUNGER.sprint_jump + HUNGER.sprint);
+        let food_points = (exhaustion / HUNGER.exhaustion_per_food) as i32 - DEFAULT_SATURATION as i32;
+        assert!((player.food_level() - (MAX_FOOD_LEVEL - food_points)).abs() <= 1);
+    }
+
+    #[test]
+    fn well_fed_players_regenerate() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+        player.set_health(10.0);
+
+        for _ in 1..HUNGER.food_interval {
+            player.tick_hunger(Difficulty::Normal);
+        }
+        assert_eq!(player.health(), 10.0);
+        player.tick_hunger(Difficulty::Normal);
+        assert_eq!(player.health(), 11.0);
+        assert_eq!(player.exhaustion(), HUNGER.regenerate);
+
+        // Too hungry to regenerate
+        player.set_food_level(HUNGER.regenerate_food_level - 1);
+        for _ in 0..HUNGER.food_interval * 2 {
+            player.tick_hunger(Difficulty::Normal);
+        }
+        assert_eq!(player.health(), 11.0);
+    }
+
+    #[test]
+    fn starvation_stops_at_difficulty_floor() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+
+        for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+            player.set_health(20.0);
+            player.set_food_level(0);
+            let mut damaged_ticks = Vec::new();
+            for tick in 1..=HUNGER.food_interval * 30 {
+                if let Some(damage) = player.tick_hunger(difficulty) {
+                    damaged_ticks.push(tick);
+                    let health = player.health() - damage;
+                    player.set_health(health);
+                }
+            }
+
+            assert_eq!(player.health(), HUNGER.starvation_floor(difficulty));
+            assert!(damaged_ticks.iter().all(|v| v % HUNGER.food_interval == 0));
+        }
+    }
+
+    #[test]
+    fn peaceful_restores_food() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+        player.set_food_level(10);
+        player.add_exhaustion(HUNGER.exhaustion_per_food * 2.0);
+
+        for _ in 0..HUNGER.peaceful_food_interval * 10 {
+            player.tick_hunger(Difficulty::Peaceful);
+        }
+        assert_eq!(player.food_level(), MAX_FOOD_LEVEL);
+        // Exhaustion only costs saturation
+        assert!(player.saturation() < DEFAULT_SATURATION);
+    }
+
+    #[test]
+    fn hunger_sends_update_health() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let client = player.read().unwrap().client();
+
+        client.read().unwrap().tick();
+        assert!(!rx.try_iter().any(|v| matches!(v, Packet::UpdateHealth(..))));
+
+        player.write().unwrap().add_exhaustion(HUNGER.exhaustion_per_food + 0.5);
+        client.read().unwrap().tick();
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::UpdateHealth(_, MAX_FOOD_LEVEL, saturation) if saturation == DEFAULT_SATURATION - 1.0)));
+
+        // Creative players don't get hungry
+        let (creative, _) = test_player(&server, GameMode::Creative);
+        creative.write().unwrap().jump();
+        assert_eq!(creative.read().unwrap().exhaustion(), 0.0);
+    }
+
+    #[test]
+    fn drop_held_items() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Apple, 3)));
+        player.write().unwrap().inventory_mut().take_changed();
+        let client = player.read().unwrap().client();
+        let press = |status| client.read().unwrap().handle_left_click(Coord::new(0, 0, 0), BlockFace::YM, status);
+
+        press(DigStatus::DropItem);
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::of(ItemType::Apple, 2)));
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(matches!(&packets[0], Packet::SetSlot(0, slot, Some(item)) if *slot as usize == HOTBAR_START && item.count == 2));
+        assert_eq!(packet_names(packets), ["SetSlot", "SpawnObject", "EntityMetadata"]);
+
+        press(DigStatus::DropItemStack);
+        assert_eq!(player.read().unwrap().inventory().held_item(), None);
+        let counts: Vec<_> = server.default_world().read().unwrap().entities().iter()
+            .filter_map(|v| v.read().unwrap().item().map(|v| v.count))
+            .collect();
+        assert_eq!(counts.iter().sum::<i8>(), 3);
+        assert!(matches!(rx.try_recv(), Ok(Packet::SetSlot(0, _, None))));
+
+        // Nothing to drop
+        rx.try_iter().for_each(drop);
+        press(DigStatus::DropItem);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn items_are_thrown_where_the_player_looks() {
+        let forward = entities::throw_velocity(0.0, 0.0);
+        assert!(forward.z > 0.0 && forward.x.abs() < 1e-9 && forward.y > 0.0);
+
+        // Yaw 90 is looking west, towards negative x
+        let west = entities::throw_velocity(90.0, 0.0);
+        assert!(west.x < 0.0 && west.z.abs() < 1e-9);
+
+        let up = entities::throw_velocity(0.0, -90.0);
+        let down = entities::throw_velocity(0.0, 90.0);
+        assert!(up.y > forward.y && down.y < 0.0);
+    }
+
+    #[test]
+    fn releasing_right_click_stops_eating() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bread, 1)));
+        player.write().unwrap().set_food_level(10);
+        assert!(player.write().unwrap().start_eating());
+
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_left_click(Coord::new(0, 0, 0), BlockFace::YM, DigStatus::ShootArrowFinishEating);
+        assert!(!player.read().unwrap().is_eating());
+        assert!(matches!(rx.try_recv(), Ok(Packet::EntityMetadata(..))));
+        assert_eq!(player.read().unwrap().food_level(), 10);
+    }
+
+    #[test]
+    fn full_or_creative_players_dont_eat() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+        player.inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bread, 1)));
+        assert!(!player.start_eating());
+
+        let (player, _) = test_player(&server, GameMode::Creative);
+        let mut player = player.write().unwrap();
+        player.inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bread, 1)));
+        player.set_food_level(10);
+        assert!(!player.start_eating());
+    }
+
+    /// Lets the player fall from `from` to `to` and returns the fall damage
+    fn fall(player: &mut Player, from: f64, to: f64, landing_block: BlockType) -> Option<f32> {
+        player.set_pos(Coord::new(0.5, from, 0.5));
+        let mut y = from;
+        while y > to {
+            assert_eq!(player.update_fall(y, false, BlockType::Air), None);
+            player.set_pos(Coord::new(0.5, y, 0.5));
+            y -= 0.5;
+        }
+
+        player.update_fall(to, true, landing_block)
+    }
+
+    #[test]
+    fn fall_damage() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+        assert_eq!(fall(&mut player, 26.0, 16.0, BlockType::Dirt), Some(7.0));
+        assert_eq!(player.stats().get(stats::FALL_ONE_CM), 1000);
+        assert_eq!(fall(&mut player, 19.0, 16.0, BlockType::Dirt), None);
+        assert_eq!(fall(&mut player, 26.0, 16.0, BlockType::Water), None);
+        assert_eq!(fall(&mut player, 26.0, 16.0, BlockType::SlimeBlock), None);
+
+        let (player, _) = test_player(&server, GameMode::Creative);
+        let mut player = player.write().unwrap();
+        assert_eq!(fall(&mut player, 26.0, 16.0, BlockType::Dirt), None);
+    }
+
+    #[test]
+    fn fall_checks_ground() {
+        let server = test_server();
+        let (player, _rx) = test_player(&server, GameMode::Survival);
+        server.default_world().read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        let client = player.read().unwrap().client();
+        let client = client.read().unwrap();
+        // The ground is at y 16 and the player spawns at y 65, claiming to be on the ground midair doesn't count
+        client.handle_move(Some(Coord::new(0.5, 40.0, 0.5)), None, true);
+        assert!(!player.read().unwrap().on_ground());
+        client.handle_move(Some(Coord::new(0.5, 16.0, 0.5)), None, true);
+        assert!(player.read().unwrap().on_ground());
+        // Falling 49 blocks is deadly
+        assert_eq!(player.read().unwrap().health(), 0.0);
+    }
+
+    #[test]
+    fn moving_streams_chunks() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        player.write().unwrap().set_flying(true);
+        let client = player.read().unwrap().client();
+        let client = client.read().unwrap();
+        client.handle_move(Some(Coord::new(0.5, 65.0, 0.5)), None, false);
+        assert!(rx.try_recv().is_err());
+
+        // One chunk towards +X, a row of chunks comes into view and one leaves it
+        client.handle_move(Some(Coord::new(16.5, 65.0, 0.5)), None, false);
+        let view_distance = server.view_distance() as i32;
+        let (mut loaded, mut unloaded) = (Vec::new(), Vec::new());
+        for packet in rx.try_iter() {
+            match packet {
+                Packet::MapChunkBulk(coords, _, _) => loaded.extend(coords),
+                Packet::ChunkUnload(coord) => unloaded.push(coord),
+                _ => ()
+            }
+        }
+
+        assert_eq!(loaded.len(), unloaded.len());
+        assert_eq!(loaded.len(), 2 * view_distance as usize + 1);
+        assert!(loaded.iter().all(|v| v.x == 1 + view_distance));
+        assert!(unloaded.iter().all(|v| v.x == -view_distance));
+    }
+
+    #[test]
+    fn teleport_streams_chunks() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let client = player.read().unwrap().client();
+        let view_distance = server.view_distance() as i32;
+
+        // Far enough for none of the old chunks to stay in view
+        client.read().unwrap().teleport_to(Coord::new(1000.5, 65.0, 0.5), 0.0, 0.0);
+        let (mut loaded, mut unloaded) = (Vec::new(), Vec::new());
+        for packet in rx.try_iter() {
+            match packet {
+                Packet::MapChunkBulk(coords, _, _) => loaded.extend(coords),
+                Packet::ChunkUnload(coord) => unloaded.push(coord),
+                _ => ()
+            }
+        }
+
+        let side = 2 * view_distance as usize + 1;
+        assert_eq!(loaded.len(), side * side);
+        assert_eq!(unloaded.len(), side * side);
+        assert!(loaded.iter().all(|v| (v.x - 62).abs() <= view_distance && v.z.abs() <= view_distance));
+        assert!(unloaded.iter().all(|v| v.x.abs() <= view_distance && v.z.abs() <= view_distance));
+
+        // Within the same chunk nothing is streamed
+        client.read().unwrap().teleport_to(Coord::new(1001.5, 70.0, 3.5), 0.0, 0.0);
+        assert!(!rx.try_iter().any(|v| matches!(v, Packet::MapChunkBulk(..) | Packet::ChunkUnload(_))));
+    }
+
+    #[test]
+    fn teleport_to_world() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let (_observer, observer_rx) = test_player(&server, GameMode::Survival);
+        let id = player.read().unwrap().client().read().unwrap().id();
+        let overworld = server.default_world();
+        server.update_tracking(&overworld);
+        rx.try_iter().for_each(drop);
+        observer_rx.try_iter().for_each(drop);
+
+        let nether = Arc::new(RwLock::new(World::new(WorldConfig {
+            name: "nether".to_owned(),
+            dimension: Dimension::Nether,
+            spawn_pos: Coord::new(0, 65, 0),
+            spawn_chunks_radius: 0,
+            difficulty: Difficulty::Easy,
+            generator: GeneratorType::Flat,
+            seed: 0
+        })));
+        let client = player.read().unwrap().client();
+        client.read().unwrap().teleport_to_world(&nether, Coord::new(8.5, 70.0, 8.5));
+
+        assert!(Arc::ptr_eq(&player.read().unwrap().world(), &nether));
+        assert_eq!(player.read().unwrap().pos(), Coord::new(8.5, 70.0, 8.5));
+        assert!(overworld.read().unwrap().get_player(id).is_none());
+        assert!(nether.read().unwrap().get_player(id).is_some());
+        assert!(observer_rx.try_iter().any(|v| matches!(v, Packet::DestroyEntities(ids) if ids == [id])));
+
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(matches!(packets[0], Packet::Respawn(..)));
+        // Clients drop the chunks of the old dimension by themselves
+        assert!(!packets.iter().any(|v| matches!(v, Packet::ChunkUnload(_))));
+        let loaded: usize = packets.iter()
+            .map(|v| match v {
+                Packet::MapChunkBulk(coords, _, _) => coords.len(),
+                _ => 0
+            })
+            .sum();
+        let side = 2 * server.view_distance() as usize + 1;
+        assert_eq!(loaded, side * side);
+    }
+
+    #[test]
+    fn moving_too_fast() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let client = player.read().unwrap().client();
+        let client = client.read().unwrap();
+
+        client.handle_move(Some(Coord::new(3.0, 65.0, 4.0)), None, false);
+        assert_eq!(player.read().unwrap().pos(), Coord::new(3.0, 65.0, 4.0));
+        assert!(rx.try_recv().is_err());
+
+        // 12 blocks in a single tick gets snapped back
+        client.handle_move(Some(Coord::new(15.0, 65.0, 4.0)), None, false);
+        assert_eq!(player.read().unwrap().pos(), Coord::new(3.0, 65.0, 4.0));
+        assert!(matches!(rx.try_recv(), Ok(Packet::PlayerPositionAndLook(_))));
+
+        // Flying in creative is exempt
+        let (player, _rx) = test_player(&server, GameMode::Creative);
+        player.write().unwrap().set_flying(true);
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_move(Some(Coord::new(15.0, 65.0, 4.0)), None, false);
+        assert_eq!(player.read().unwrap().pos(), Coord::new(15.0, 65.0, 4.0));
+    }
+
+    #[test]
+    fn movement_deltas() {
+        let server = test_server();
+        let (player, _rx) = test_player(&server, GameMode::Creative);
+        let mut p = player.write().unwrap();
+        p.set_pos(Coord::new(0.5, 65.0, 0.5));
+        p.take_movement();
+        assert_eq!(p.take_movement(), None);
+
+        // Moves smaller than 1/32 blocks add up
+        p.set_pos(Coord::new(0.51, 65.0, 0.5));
+        assert_eq!(p.take_movement(), None);
+        p.set_pos(Coord::new(0.54, 65.0, 0.5));
+        assert_eq!(p.take_movement(), Some(Movement::RelativeMove(Coord::new(1, 0, 0))));
+
+        p.set_rotation(90.0, 0.0);
+        assert_eq!(p.take_movement(), Some(Movement::Look));
+        p.set_pos(Coord::new(0.54, 64.0, 2.5));
+        p.set_rotation(45.0, 10.0);
+        assert_eq!(p.take_movement(), Some(Movement::LookAndRelativeMove(Coord::new(0, -32, 64))));
+        p.set_pos(Coord::new(0.54, 64.0, 6.5));
+        assert_eq!(p.take_movement(), Some(Movement::Teleport));
+    }
+
+    #[test]
+    fn movement_is_sent_to_others() {
+        let server = test_server();
+        let (player, _rx) = test_player(&server, GameMode::Creative);
+        player.write().unwrap().set_flying(true);
+        let (_other, other_rx) = test_player(&server, GameMode::Creative);
+        server.update_tracking(&server.default_world());
+        assert!(matches!(other_rx.try_recv(), Ok(Packet::SpawnPlayer(_))));
+        let client = player.read().unwrap().client();
+        let client = client.read().unwrap();
+
+        // The player spawned at (0, 65, 0)
+        client.handle_move(Some(Coord::new(1.0, 65.0, 0.0)), None, false);
+        client.tick();
+        match other_rx.try_recv() {
+            Ok(Packet::EntityRelativeMove(id, delta, false)) => {
+                assert_eq!(id, client.id());
+                assert_eq!(delta, Coord::new(32, 0, 0));
+            }
+            _ => panic!("Expected entity relative move packet")
+        }
+
+        client.handle_move(Some(Coord::new(1.0, 65.0, 10.0)), None, false);
+        client.tick();
+        match other_rx.try_recv() {
+            Ok(Packet::EntityTeleport(_, pos, _, _, _)) => assert_eq!(pos, Coord::new(1.0, 65.0, 10.0)),
+            _ => panic!("Expected entity teleport packet")
+        }
+    }
+
+    #[test]
+    fn movement_is_only_sent_to_players_in_range() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        player.write().unwrap().set_flying(true);
+        let (other, other_rx) = test_player(&server, GameMode::Creative);
+        other.write().unwrap().set_pos(Coord::new(300.0, 65.0, 0.0));
+        let world = server.default_world();
+        server.update_tracking(&world);
+        assert!(rx.try_recv().is_err());
+        assert!(other_rx.try_recv().is_err());
+
+        let client = player.read().unwrap().client();
+        let client = client.read().unwrap();
+        client.handle_move(Some(Coord::new(1.0, 65.0, 0.0)), None, false);
+        client.tick();
+        assert!(other_rx.try_recv().is_err());
+
+        // Walk up to the other player, it has to see the player spawn before it moves
+        client.handle_move(Some(Coord::new(250.0, 65.0, 0.0)), None, false);
+        server.update_tracking(&world);
+        assert!(matches!(other_rx.try_recv(), Ok(Packet::SpawnPlayer(_))));
+        assert!(matches!(rx.try_recv(), Ok(Packet::SpawnPlayer(_))));
+        client.tick();
+        assert!(matches!(other_rx.try_recv(), Ok(Packet::EntityTeleport(id, ..)) if id == client.id()));
+
+        // Walking away destroys the player for the other player
+        client.handle_move(Some(Coord::new(0.0, 65.0, 0.0)), None, false);
+        server.update_tracking(&world);
+        assert!(matches!(other_rx.try_recv(), Ok(Packet::DestroyEntities(ids)) if ids == [client.id()]));
+    }
+
+    #[test]
+    fn relative_teleport() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        player.write().unwrap().set_rotation(90.0, 0.0);
+        let old = player.read().unwrap().pos();
+        let client = player.read().unwrap().client();
+
+        let flags = TeleportFlags::Y | TeleportFlags::YAW;
+        client.read().unwrap().teleport_relative(Coord::new(old.x, 10.0, 3.5), 45.0, 20.0, flags);
+        let p = player.read().unwrap();
+        assert_eq!(p.pos(), Coord::new(old.x, old.y + 10.0, 3.5));
+        assert_eq!((p.yaw(), p.pitch()), (135.0, 20.0));
+        assert!(matches!(rx.try_iter().last(), Some(Packet::Teleport(pos, 45.0, 20.0, f, (new, 135.0, 20.0))) if pos.y == 10.0 && f == flags && new == p.pos()));
+    }
+
+    /// Returns the names of the packets, in order
+    fn packet_names(packets: impl IntoIterator<Item = Packet>) -> Vec<&'static str> {
+        packets.into_iter().map(|packet| match packet {
+            Packet::UpdateHealth(..) => "UpdateHealth",
+            Packet::CombatEvent(..) => "CombatEvent",
+            Packet::EntityStatus(_, EntityStatus::Hurt) => "EntityStatus(Hurt)",
+            Packet::EntityVelocity(..) => "EntityVelocity",
+            Packet::EntityStatus(_, EntityStatus::Dead) => "EntityStatus(Dead)",
+            Packet::ChatMessage(..) => "ChatMessage",
+            Packet::SpawnObject(..) => "SpawnObject",
+            Packet::EntityMetadata(..) => "EntityMetadata",
+            Packet::SetSlot(..) => "SetSlot",
+            _ => "Other"
+        }).collect()
+    }
+
+    #[test]
+    fn death_sequence() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let (_observer, observer_rx) = test_player(&server, GameMode::Survival);
+        server.update_tracking(&server.default_world());
+        rx.try_iter().for_each(drop);
+        observer_rx.try_iter().for_each(drop);
+        let client = player.read().unwrap().client();
+        client.write().unwrap().set_username("Steve".to_owned());
+        {
+            let mut p = player.write().unwrap();
+            p.set_health(4.0);
+            p.inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bread, 2)));
+            p.inventory_mut().take_changed();
+        }
+
+        client.read().unwrap().damage(3.0, DamageCause::Fall);
+        assert_eq!(packet_names(rx.try_iter()), ["UpdateHealth", "EntityStatus(Hurt)"]);
+        assert_eq!(packet_names(observer_rx.try_iter()), ["EntityStatus(Hurt)"]);
+
+        client.read().unwrap().damage(3.0, DamageCause::Fall);
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(matches!(&packets[1], Packet::CombatEvent(_, None, msg) if msg == "Steve fell from a high place"));
+        assert_eq!(packet_names(packets), ["UpdateHealth", "CombatEvent", "EntityStatus(Dead)", "ChatMessage", "SpawnObject", "EntityMetadata", "SetSlot"]);
+        assert_eq!(packet_names(observer_rx.try_iter()), ["EntityStatus(Dead)", "ChatMessage", "SpawnObject", "EntityMetadata"]);
+
+        let p = player.read().unwrap();
+        assert_eq!(p.stats().get(stats::DEATHS), 1);
+        assert_eq!(p.inventory().held_item(), None);
+
+        // Dead players don't take damage
+        drop(p);
+        client.read().unwrap().damage(3.0, DamageCause::Fall);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn death_drops_crafting_grid_without_result() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        {
+            let mut p = player.write().unwrap();
+            let inventory = p.inventory_mut();
+            for slot in crafting::grid_slots() {
+                inventory.set_slot(slot, Some(ItemStack::new(BlockType::Planks as i16, 1, 0)));
+            }
+            crafting::update_result(inventory, server.recipes());
+            assert!(inventory.get_slot(crafting::RESULT_SLOT).is_some());
+            inventory.take_changed();
+        }
+
+        let client = player.read().unwrap().client();
+        client.read().unwrap().damage(30.0, DamageCause::Generic);
+        let dropped: Vec<_> = rx.try_iter().filter_map(|packet| match packet {
+            Packet::SpawnObject(entity) => entity.read().unwrap().item().cloned(),
+            _ => None
+        }).collect();
+        assert_eq!(dropped, [ItemStack::new(BlockType::Planks as i16, 4, 0)]);
+        assert!((0..INVENTORY_SIZE).all(|slot| player.read().unwrap().inventory().get_slot(slot).is_none()));
+    }
+
+    #[test]
+    fn keep_inventory() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        server.default_world().write().unwrap().game_rules_mut().set(game_rules::KEEP_INVENTORY, "true");
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bread, 2)));
+        player.write().unwrap().inventory_mut().take_changed();
+
+        let client = player.read().unwrap().client();
+        client.read().unwrap().damage(30.0, DamageCause::Explosion);
+        assert_eq!(packet_names(rx.try_iter()), ["UpdateHealth", "CombatEvent", "EntityStatus(Dead)", "ChatMessage"]);
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::of(ItemType::Bread, 2)));
+    }
+
+    #[test]
+    fn punch_player() {
+        let server = test_server();
+        let (attacker, _rx) = test_player(&server, GameMode::Creative);
+        let (target, target_rx) = test_player(&server, GameMode::Survival);
+        let attacker_client = attacker.read().unwrap().client();
+        let target_id = target.read().unwrap().client().read().unwrap().id();
+
+        attacker_client.read().unwrap().handle_use_entity(target_id, UseEntityAction::Attack);
+        assert_eq!(target.read().unwrap().health(), DEFAULT_HEATH - 1.0);
+        assert_eq!(packet_names(target_rx.try_iter()), ["UpdateHealth", "EntityVelocity", "EntityStatus(Hurt)"]);
+
+        // Creative players can't be hurt
+        let attacker_id = attacker_client.read().unwrap().id();
+        target.read().unwrap().client().read().unwrap().handle_use_entity(attacker_id, UseEntityAction::Attack);
+        assert_eq!(attacker.read().unwrap().health(), DEFAULT_HEATH);
+    }
+
+    #[test]
+    fn attack_knocks_back() {
+        let server = test_server();
+        let (attacker, _rx) = test_player(&server, GameMode::Survival);
+        let (target, target_rx) = test_player(&server, GameMode::Survival);
+        let attacker_id = attacker.read().unwrap().client().read().unwrap().id();
+        let pos = target.read().unwrap().pos();
+        attacker.write().unwrap().set_pos(Coord::new(pos.x, pos.y, pos.z - 2.0));
+
+        let client = target.read().unwrap().client();
+        client.read().unwrap().damage(2.5, DamageCause::Player(attacker_id, "Alex".to_owned()));
+        assert_eq!(target.read().unwrap().health(), DEFAULT_HEATH - 2.5);
+        let packets: Vec<_> = target_rx.try_iter().collect();
+        assert!(matches!(&packets[0], Packet::UpdateHealth(health, ..) if *health == DEFAULT_HEATH - 2.5));
+        assert!(matches!(&packets[1], Packet::EntityVelocity(_, v) if *v == Coord::new(0.0, KNOCKBACK_STRENGTH, KNOCKBACK_STRENGTH)));
+        assert_eq!(packet_names(packets), ["UpdateHealth", "EntityVelocity", "EntityStatus(Hurt)"]);
+
+        // Only attacks knock players back
+        client.read().unwrap().damage(1.0, DamageCause::Fall);
+        assert_eq!(packet_names(target_rx.try_iter()), ["UpdateHealth", "EntityStatus(Hurt)"]);
+
+        assert_eq!(knockback(pos, pos), Coord::new(0.0, KNOCKBACK_STRENGTH, 0.0));
+    }
+
+    #[test]
+    fn team_friendly_fire() {
+        let server = test_server();
+        let (attacker, rx) = test_player(&server, GameMode::Survival);
+        let (target, target_rx) = test_player(&server, GameMode::Survival);
+        let attacker_client = attacker.read().unwrap().client();
+        let target_client = target.read().unwrap().client();
+        attacker_client.write().unwrap().set_username("Alice".to_owned());
+        target_client.write().unwrap().set_username("Bob".to_owned());
+        let target_id = target_client.read().unwrap().id();
+
+        assert_eq!(run_command(&server, &attacker, &rx, "team create red red"), ["Created team red"]);
+        assert_eq!(run_command(&server, &attacker, &rx, "team option red friendlyfire false"), ["Set friendly fire of team red to false"]);
+        run_command(&server, &attacker, &rx, "team join red");
+        run_command(&server, &target, &target_rx, "team join red");
+        assert_eq!(run_command(&server, &attacker, &rx, "team list"), ["red (2): Alice, Bob"]);
+
+        attacker_client.read().unwrap().handle_use_entity(target_id, UseEntityAction::Attack);
+        assert_eq!(target.read().unwrap().health(), DEFAULT_HEATH);
+
+        // Players on different teams can hurt each other
+        assert_eq!(run_command(&server, &target, &target_rx, "team leave"), ["Left team red"]);
+        attacker_client.read().unwrap().handle_use_entity(target_id, UseEntityAction::Attack);
+        assert_eq!(target.read().unwrap().health(), DEFAULT_HEATH - 1.0);
+
+        // The limit is in characters, not bytes
+        assert_eq!(run_command(&server, &attacker, &rx, "team create ÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄ"), ["Created team ÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄ"]);
+        assert_eq!(run_command(&server, &attacker, &rx, "team create ÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄÄ"), ["Team names can't be longer than 16 characters"]);
+    }
+
+    #[test]
+    fn pregen_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        // Generating chunks is up to the admins, like saving them
+        assert_eq!(run_command(&server, &player, &rx, "pregen 1"), ["You do not have permission to use this command"]);
+
+        let client = player.read().unwrap().client();
+        server.set_op_level(client.read().unwrap().uuid(), 4);
+        let too_far = format!("pregen {}", MAX_PREGEN_RADIUS + 1);
+        client.read().unwrap().handle_command(&too_far);
+        assert!(matches!(rx.try_recv(), Ok(Packet::ChatMessage(v)) if v == format!("Invalid radius: {}", MAX_PREGEN_RADIUS + 1)));
+
+        client.read().unwrap().handle_command("pregen 1");
+
+        // Progress messages may come before it
+        let finished = rx.iter().find_map(|packet| match packet {
+            Packet::ChatMessage(v) if v.starts_with("Pregeneration ") => Some(v),
+            _ => None
+        });
+        assert_eq!(finished.unwrap(), "Pregeneration finished, generated 9 chunks");
+    }
+
+    /// Runs the command as an operator and returns the chat messages sent back
+    fn run_command(server: &Arc<Server>, player: &Arc<RwLock<Player>>, rx: &Receiver<Packet>, command: &str) -> Vec<String> {
+        let client = player.read().unwrap().client();
+        server.set_op_level(client.read().unwrap().uuid(), 2);
+        client.read().unwrap().handle_command(command);
+        rx.try_iter().filter_map(|packet| match packet {
+            Packet::ChatMessage(v) => Some(v),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn summon_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let world = server.default_world();
+
+        assert_eq!(run_command(&server, &player, &rx, "summon Zombie ~ ~ ~3"), ["Object successfully summoned"]);
+        let zombie = world.read().unwrap().entities().pop().unwrap();
+        let zombie = zombie.read().unwrap();
+        assert_eq!(zombie.entity_type(), EntityType::Mob(MobType::Zombie));
+        assert_eq!(zombie.health(), Some(20.0));
+        assert_eq!(zombie.pos().z, player.read().unwrap().pos().z.floor() + 3.5);
+        assert!(world.read().unwrap().tracker().is_tracking(player.read().unwrap().client().read().unwrap().id(), zombie.id()));
+
+        assert_eq!(run_command(&server, &player, &rx, "summon Dragon"), ["Unable to summon Dragon"]);
+        assert_eq!(run_command(&server, &player, &rx, "summon Pig 1 2"), ["Usage: /summon <EntityName> [x] [y] [z] [dataTag]"]);
+        assert_eq!(world.read().unwrap().entities().len(), 1);
+    }
+
+    #[test]
+    fn difficulty_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let (_observer, observer_rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+
+        run_command(&server, &player, &rx, "summon Zombie ~ ~ ~3");
+        let zombie = world.read().unwrap().entities().pop().unwrap().read().unwrap().id();
+        run_command(&server, &player, &rx, "summon Pig ~ ~ ~-3");
+        observer_rx.try_iter().for_each(drop);
+
+        assert_eq!(run_command(&server, &player, &rx, "difficulty peaceful"), ["Set game difficulty to Peaceful"]);
+        assert_eq!(world.read().unwrap().difficulty(), Difficulty::Peaceful);
+        let packets: Vec<_> = observer_rx.try_iter().collect();
+        assert!(packets.iter().any(|v| matches!(v, Packet::DestroyEntities(ids) if *ids == [zombie])));
+        assert!(packets.iter().any(|v| matches!(v, Packet::ServerDifficulty(Difficulty::Peaceful))));
+        assert_eq!(world.read().unwrap().entities().len(), 1);
+
+        // Hostile mobs summoned while peaceful don't survive the next tick
+        run_command(&server, &player, &rx, "summon Skeleton");
+        server.tick();
+        assert_eq!(world.read().unwrap().entities().len(), 1);
+
+        assert_eq!(run_command(&server, &player, &rx, "difficulty 2"), ["Set game difficulty to Normal"]);
+        assert_eq!(run_command(&server, &player, &rx, "difficulty h"), ["Set game difficulty to Hard"]);
+        assert_eq!(run_command(&server, &player, &rx, "difficulty impossible"), ["Usage: /difficulty <new difficulty>"]);
+        assert_eq!(world.read().unwrap().difficulty(), Difficulty::Hard);
+    }
+
+    #[test]
+    fn give_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        player.read().unwrap().client().write().unwrap().set_username("Steve".to_owned());
+
+        assert_eq!(run_command(&server, &player, &rx, "give @p minecraft:stone 10"), ["Given [stone] * 10 to Steve"]);
+        assert_eq!(player.read().unwrap().inventory().get_slot(HOTBAR_START), Some(&ItemStack::new(BlockType::Stone as i16, 10, 0)));
+        run_command(&server, &player, &rx, "give Steve bread");
+        let synced = rx.try_iter().any(|packet| matches!(packet, Packet::SetSlot(0, slot, Some(item)) if slot == HOTBAR_START as i16 + 1 && item == ItemStack::of(ItemType::Bread, 1)));
+        assert!(synced);
+
+        // The data tag can have spaces in it
+        let given = run_command(&server, &player, &rx, "give Steve diamond_sword 1 0 {display:{Name:\"Sword of Steve\"},ench:[{id:16s,lvl:5s}]}");
+        assert_eq!(given, ["Given [diamond_sword] * 1 to Steve"]);
+        let sword = player.read().unwrap().inventory().get_slot(HOTBAR_START + 2).cloned().unwrap();
+        let display = sword.nbt.as_ref().and_then(|v| v.get("display")).and_then(Tag::as_compound).unwrap();
+        assert_eq!(display.get("Name").and_then(Tag::as_str), Some("Sword of Steve"));
+        assert_eq!(sword.enchantments(), [(Enchantment::Sharpness as i16, 5)]);
+        assert!(run_command(&server, &player, &rx, "give Steve stone 1 0 {display:")[0].starts_with("Data tag parsing failed: "));
+        assert_eq!(run_command(&server, &player, &rx, "give Steve stone 1 0 [1, 2]"), ["Data tag parsing failed: Not a compound"]);
+
+        assert_eq!(run_command(&server, &player, &rx, "give Steve cheese"), ["There is no such item with name cheese"]);
+        assert_eq!(run_command(&server, &player, &rx, "give Steve stone 65"), ["The number you have entered (65) is too big, it must be at most 64"]);
+
+        // Items that don't fit are dropped at the feet of the player
+        {
+            let mut p = player.write().unwrap();
+            for slot in MAIN_START..INVENTORY_SIZE {
+                p.inventory_mut().set_slot(slot, Some(ItemStack::of(ItemType::Apple, 1)));
+            }
+            p.inventory_mut().take_changed();
+        }
+        assert_eq!(run_command(&server, &player, &rx, "give Steve flint_and_steel 1 5"), ["Given [flint_and_steel] * 1 to Steve"]);
+        let world = server.default_world();
+        let dropped = world.read().unwrap().entities().into_iter()
+            .find(|v| v.read().unwrap().entity_type() == EntityType::Item)
+            .unwrap();
+        assert_eq!(dropped.read().unwrap().item(), Some(&ItemStack::new(ItemType::FlintAndSteel as i16, 1, 5)));
+        assert_eq!(dropped.read().unwrap().pos(), player.read().unwrap().pos());
+    }
+
+    #[test]
+    fn summon_with_data_tag() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let world = server.default_world();
+        let summoned = |entity_type| world.read().unwrap().entities().into_iter()
+            .find(|v| v.read().unwrap().entity_type() == entity_type)
+            .unwrap();
+
+        assert_eq!(run_command(&server, &player, &rx, "summon PrimedTnt ~ ~ ~ {Fuse: 20b, Motion: [0.0d, 0.5d, 0.0d]}"),
+            ["Object successfully summoned"]);
+        let tnt = summoned(EntityType::PrimedTnt);
+        assert_eq!(tnt.read().unwrap().fuse(), Some(20));
+        assert_eq!(tnt.read().unwrap().velocity(), Coord::new(0.0, 0.5, 0.0));
+
+        assert_eq!(run_command(&server, &player, &rx, "summon Item 0 64 0 {Item:{id:260s,Count:3b}}"), ["Object successfully summoned"]);
+        let item = summoned(EntityType::Item);
+        assert_eq!(item.read().unwrap().item(), Some(&ItemStack::of(ItemType::Apple, 3)));
+        assert!(world.read().unwrap().get_entity(item.read().unwrap().id()).is_some());
+
+        assert_eq!(run_command(&server, &player, &rx, "summon Cow ~ ~ ~ {Health:3f,Rotation:[90f,0f]}"), ["Object successfully summoned"]);
+        let cow = summoned(EntityType::Mob(MobType::Cow));
+        assert_eq!(cow.read().unwrap().health(), Some(3.0));
+        assert_eq!(cow.read().unwrap().yaw(), 90.0);
+
+        // Items need a stack and the data tag has to be valid
+        assert_eq!(run_command(&server, &player, &rx, "summon Item ~ ~ ~"), ["Unable to summon Item"]);
+        assert_eq!(run_command(&server, &player, &rx, "summon Pig ~ ~ ~ {Health:"), ["Data tag parsing failed: Expected a value"]);
+        assert_eq!(world.read().unwrap().entities().len(), 3);
+    }
+
+    #[test]
+    fn weather_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let (other, other_rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+
+        assert_eq!(run_command(&server, &player, &rx, "weather rain 60"), ["Changing to rainy weather"]);
+        assert_eq!(world.read().unwrap().weather().weather_type(), WeatherType::Rain);
+        let packets: Vec<_> = other_rx.try_iter().collect();
+        assert!(matches!(packets[0], Packet::ChangeGameState(GameStateReason::BeginRaining, _)));
+        assert!(matches!(packets[1], Packet::ChangeGameState(GameStateReason::RainLevel, v) if v == 1.0));
+
+        // The rain stops after 60 seconds
+        for _ in 0..60 * crate::TPS {
+            server.tick();
+        }
+        assert_eq!(world.read().unwrap().weather().weather_type(), WeatherType::Clear);
+        assert!(other_rx.try_iter().any(|v| matches!(v, Packet::ChangeGameState(GameStateReason::EndRaining, _))));
+
+        assert_eq!(run_command(&server, &other, &other_rx, "toggledownfall"), ["Toggled downfall"]);
+        assert!(world.read().unwrap().weather().is_raining());
+        assert_eq!(run_command(&server, &player, &rx, "weather snow"), ["Usage: /weather <clear|rain|thunder> [duration in seconds]"]);
+        assert_eq!(run_command(&server, &player, &rx, "weather clear 0"), ["The number you have entered (0) is too small, it must be at least 1"]);
+    }
+
+    #[test]
+    fn time_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let (_other, other_rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+
+        assert_eq!(run_command(&server, &player, &rx, "time set night"), ["Set the time to 13000"]);
+        assert_eq!(world.read().unwrap().time_of_day(), 13000);
+        assert!(other_rx.try_iter().any(|v| matches!(v, Packet::TimeUpdate(_))));
+
+        assert_eq!(run_command(&server, &player, &rx, "time add 12000"), ["Added 12000 to the time"]);
+        assert_eq!(run_command(&server, &player, &rx, "time query daytime"), ["Time is 1000"]);
+        assert_eq!(run_command(&server, &player, &rx, "time add 9223372036854775807"), ["Added 9223372036854775807 to the time"]);
+        assert_eq!(run_command(&server, &player, &rx, "time query daytime"), ["Time is 8807"]);
+        assert_eq!(run_command(&server, &player, &rx, "time set day"), ["Set the time to 1000"]);
+        assert_eq!(run_command(&server, &player, &rx, "time set dusk"), ["Usage: /time <set|add|query> <value>"]);
+        assert_eq!(world.read().unwrap().time_of_day(), 1000);
+    }
+
+    #[test]
+    fn auto_save() {
+        let dir = std::env::temp_dir().join(format!("siderite-auto-save-{}", std::process::id()));
+        let (server, _auth) = test_server_with_config(ServerConfig {
+            level_name: dir.to_string_lossy().into_owned(),
+            auto_save_interval: 3,
+            ..test_config()
+        });
+        let (player, _rx) = test_player(&server, GameMode::Survival);
+        let uuid = player.read().unwrap().client().read().unwrap().uuid();
+        let chunk_map = server.default_world().read().unwrap().chunk_map();
+        let chunk = ChunkCoord::from_pos(player.read().unwrap().pos());
+        chunk_map.touch_chunk(chunk);
+        let is_dirty = || {
+            let mut dirty = false;
+            chunk_map.do_with_chunk(chunk, |v| dirty = v.is_dirty());
+            dirty
+        };
+
+        server.tick();
+        server.tick();
+        assert!(is_dirty());
+        assert!(!dir.join("level.dat").exists());
+
+        server.tick();
+        assert!(!is_dirty());
+        assert!(dir.join("level.dat").exists());
+        assert!(dir.join("chunks").join(format!("c.{}.{}.dat", chunk.x, chunk.z)).exists());
+        assert!(dir.join("stats").join(format!("{}.json", uuid.hyphenated())).exists());
+        assert!(dir.join("playerdata").join(format!("{}.dat", uuid.hyphenated())).exists());
+
+        // Changes are saved on the next interval
+        chunk_map.do_with_chunk(chunk, |v| v.mark_dirty());
+        server.tick();
+        server.tick();
+        assert!(is_dirty());
+        server.tick();
+        assert!(!is_dirty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_all_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let client = player.read().unwrap().client();
+        let chunk = ChunkCoord::from_pos(player.read().unwrap().pos());
+        let chunk_map = server.default_world().read().unwrap().chunk_map();
+        chunk_map.touch_chunk(chunk);
+
+        // Operators need the highest level
+        assert_eq!(run_command(&server, &player, &rx, "save-all"), ["You do not have permission to use this command"]);
+        server.set_op_level(client.read().unwrap().uuid(), 4);
+        client.read().unwrap().handle_command("save-all");
+        let messages: Vec<_> = rx.try_iter().filter_map(|packet| match packet {
+            Packet::ChatMessage(v) => Some(v),
+            _ => None
+        }).collect();
+        assert_eq!(messages, ["Saving...", "Saved the world"]);
+        chunk_map.do_with_chunk(chunk, |v| assert!(!v.is_dirty()));
+    }
+
+    #[test]
+    fn broadcast_to_world() {
+        let server = test_server();
+        let (_player, rx) = test_player(&server, GameMode::Survival);
+
+        // A player in a nether the server doesn't tick
+        let nether = Arc::new(RwLock::new(World::new(WorldConfig {
+            name: "nether".to_owned(),
+            dimension: Dimension::Nether,
+            spawn_pos: Coord::new(0, 65, 0),
+            spawn_chunks_radius: 0,
+            difficulty: Difficulty::Easy,
+            generator: GeneratorType::Flat,
+            seed: 0
+        })));
+        let (tx, nether_rx) = crossbeam_channel::unbounded();
+        let id = crate::server::get_next_entity_id();
+        let client = Arc::new(RwLock::new(Client::new(id, server.clone(), tx)));
+        let nether_player = Arc::new(RwLock::new(Player::new(client.clone(), nether.clone(), GameMode::Survival, Coord::new(0.5, 65.0, 0.5))));
+        client.write().unwrap().set_player(nether_player.clone());
+        nether.write().unwrap().add_player(id, nether_player);
+
+        server.broadcast_to_world(&nether, Packet::ChatMessage("Only in the nether".to_owned()));
+        assert!(rx.try_recv().is_err());
+        assert!(matches!(nether_rx.try_recv(), Ok(Packet::ChatMessage(msg)) if msg == "Only in the nether"));
+
+        server.default_world().write().unwrap().weather_mut().toggle_downfall();
+        server.broadcast_weather(&server.default_world());
+        assert!(rx.try_iter().count() > 0);
+        assert!(nether_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn display_name() {
+        let server = test_server();
+        let (player, _rx) = test_player(&server, GameMode::Survival);
+        let (_observer, observer_rx) = test_player(&server, GameMode::Survival);
+
+        server.set_display_name(&player, Some(crate::chat::text("§c[Admin] Steve")));
+        assert_eq!(player.read().unwrap().display_name(), Some(&crate::chat::text("§c[Admin] Steve")));
+        let updated = observer_rx.try_iter().any(|packet| matches!(packet,
+            Packet::PlayerListItem(PlayerListAction::UpdateDisplayName, players) if Arc::ptr_eq(&players[0], &player)));
+        assert!(updated);
+
+        server.set_display_name(&player, None);
+        assert_eq!(player.read().unwrap().display_name(), None);
+    }
+
+    #[test]
+    fn help_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+
+        let pages = commands::COMMANDS.len().div_ceil(commands::HELP_PAGE_SIZE);
+        let help = run_command(&server, &player, &rx, "help");
+        assert_eq!(help[0], format!("§2--- Showing help page 1 of {} (/help <page>) ---", pages));
+        assert_eq!(help.len(), commands::HELP_PAGE_SIZE + 1);
+        assert!(help.contains(&"/effect <player> <effect> [seconds] [amplifier] [hideParticles] - Gives a player an effect, or takes them away with clear".to_owned()));
+        assert_eq!(run_command(&server, &player, &rx, "help 2")[0], format!("§2--- Showing help page 2 of {} (/help <page>) ---", pages));
+        assert_eq!(run_command(&server, &player, &rx, "help 99"), [format!("The number you have entered (99) is too big, it must be at most {}", pages)]);
+        assert_eq!(run_command(&server, &player, &rx, "? page"), ["'page' is not a valid number"]);
+        assert_eq!(run_command(&server, &player, &rx, "help 1 2"), ["Usage: /help [page]"]);
+
+        // Players only see the commands they can use
+        let client = player.read().unwrap().client();
+        server.set_op_level(client.read().unwrap().uuid(), 0);
+        client.read().unwrap().handle_command("help");
+        let help: Vec<_> = rx.try_iter().filter_map(|packet| match packet {
+            Packet::ChatMessage(v) => Some(v),
+            _ => None
+        }).collect();
+        assert_eq!(help, [
+            "§2--- Showing help page 1 of 1 (/help <page>) ---",
+            "/help [page] - Lists the commands you can use",
+            "/tell <player> <private message ...> - Sends a private message to a player"
+        ]);
+    }
+
+    #[test]
+    fn tell_command() {
+        let server = test_server();
+        let named_player = |name: &str| {
+            let (player, rx) = test_player(&server, GameMode::Survival);
+            player.read().unwrap().client().write().unwrap().set_username(name.to_owned());
+            (player, rx)
+        };
+        let (steve, steve_rx) = named_player("Steve");
+        let (_alex, alex_rx) = named_player("Alex");
+        let (_bob, bob_rx) = named_player("Bob");
+        let chat = |rx: &Receiver<Packet>| rx.try_iter().filter_map(|packet| match packet {
+            Packet::ChatMessage(v) => Some(v),
+            _ => None
+        }).collect::<Vec<_>>();
+
+        // Anyone can whisper
+        let client = steve.read().unwrap().client();
+        server.set_op_level(client.read().unwrap().uuid(), 0);
+        client.read().unwrap().handle_command("msg alex meet me at spawn");
+        assert_eq!(chat(&steve_rx), ["§7§oYou whisper to Alex: meet me at spawn"]);
+        assert_eq!(chat(&alex_rx), ["§7§oSteve whispers to you: meet me at spawn"]);
+        assert!(chat(&bob_rx).is_empty());
+
+        client.read().unwrap().handle_command("w Herobrine hi");
+        assert_eq!(chat(&steve_rx), ["That player cannot be found"]);
+        client.read().unwrap().handle_command("tell Steve hi");
+        assert_eq!(chat(&steve_rx), ["You can't send a private message to yourself!"]);
+        client.read().unwrap().handle_command("tell Alex");
+        assert_eq!(chat(&steve_rx), ["Usage: /tell <player> <private message ...>"]);
+        assert!(chat(&alex_rx).is_empty() && chat(&bob_rx).is_empty());
+    }
+
+    #[test]
+    fn setblock_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let world = server.default_world();
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        player.write().unwrap().set_pos(Coord::new(3.5, 16.0, 4.5));
+
+        assert_eq!(run_command(&server, &player, &rx, "setblock ~ ~ ~1 minecraft:obsidian"), ["Block placed"]);
+        assert_eq!(server.get_block_state(&world, Coord::new(3, 16, 5)), (BlockType::Obsidian, 0));
+
+        // Not an operator
+        server.set_op_level(player.read().unwrap().client().read().unwrap().uuid(), 0);
+        player.read().unwrap().client().read().unwrap().handle_command("setblock 0 16 0 stone");
+        assert_eq!(server.get_block_state(&world, Coord::new(0, 16, 0)), (BlockType::Air, 0));
+    }
+
+    #[test]
+    fn tp_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let (alex, _alex_rx) = test_player(&server, GameMode::Survival);
+        player.read().unwrap().client().write().unwrap().set_username("Steve".to_owned());
+        alex.read().unwrap().client().write().unwrap().set_username("Alex".to_owned());
+        alex.write().unwrap().set_pos(Coord::new(-3.5, 20.0, 8.5));
+
+        // Falling players don't take the fall damage at the destination
+        {
+            let mut p = player.write().unwrap();
+            p.set_pos(Coord::new(0.5, 80.0, 0.5));
+            p.update_fall(79.0, false, BlockType::Air);
+        }
+        assert_eq!(run_command(&server, &player, &rx, "tp 40 16 -20"), ["Teleported Steve to 40.5, 16, -19.5"]);
+        assert_eq!(player.write().unwrap().update_fall(16.0, true, BlockType::Dirt), None);
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(packets.iter().any(|v| matches!(v, Packet::PlayerPositionAndLook(_))));
+        // The player moved to another chunk, so the ones out of view are unloaded
+        assert!(packets.iter().any(|v| matches!(v, Packet::ChunkUnload(_))));
+
+        assert_eq!(run_command(&server, &player, &rx, "tp ~-3.5 ~2 ~ ~90 -30"), ["Teleported Steve to 37, 18, -19.5"]);
+        {
+            let p = player.read().unwrap();
+            assert_eq!(p.pos(), Coord::new(37.0, 18.0, -19.5));
+            assert_eq!((p.yaw(), p.pitch()), (90.0, -30.0));
+        }
+
+        assert_eq!(run_command(&server, &player, &rx, "tp alex"), ["Teleported Steve to Alex"]);
+        assert_eq!(player.read().unwrap().pos(), Coord::new(-3.5, 20.0, 8.5));
+        player.write().unwrap().set_pos(Coord::new(0.5, 16.0, 0.5));
+        assert_eq!(run_command(&server, &player, &rx, "tp Alex Steve"), ["Teleported Alex to Steve"]);
+        assert_eq!(alex.read().unwrap().pos(), Coord::new(0.5, 16.0, 0.5));
+
+        assert_eq!(run_command(&server, &player, &rx, "tp Herobrine"), ["That player cannot be found"]);
+        assert_eq!(run_command(&server, &player, &rx, "tp ~1 ~ ~ ~ ~ ~ ~"), [
+            "Usage: /tp [target player] <destination player> OR /tp [target player] <x> <y> <z> [<yaw> <pitch>]"
+        ]);
+    }
+
+    #[test]
+    fn respawn() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let client = player.read().unwrap().client();
+
+        // Alive players can't respawn
+        client.read().unwrap().handle_respawn();
+        assert!(rx.try_recv().is_err());
+
+        {
+            let mut p = player.write().unwrap();
+            p.set_pos(Coord::new(100.0, 16.0, 100.0));
+            p.set_health(0.0);
+            p.set_food_level(3);
+        }
+        client.read().unwrap().handle_respawn();
+
+        let p = player.read().unwrap();
+        assert_eq!(p.health(), DEFAULT_HEATH);
+        assert_eq!(p.food_level(), MAX_FOOD_LEVEL);
+        // The spawn at (0, 65, 0) is in the air, the player ends up on the ground
+        assert_eq!(p.pos(), Coord::new(0.5, 16.0, 0.5));
+        assert!(matches!(rx.try_recv(), Ok(Packet::Respawn(_, GameMode::Survival))));
+    }
+
+    #[test]
+    fn kill_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let (target, target_rx) = test_player(&server, GameMode::Survival);
+        player.read().unwrap().client().write().unwrap().set_username("Steve".to_owned());
+        target.read().unwrap().client().write().unwrap().set_username("Alex".to_owned());
+        target_rx.try_iter().for_each(drop);
+
+        assert_eq!(run_command(&server, &player, &rx, "kill Alex"), ["Alex died", "Killed Alex"]);
+        assert_eq!(target.read().unwrap().health(), 0.0);
+        assert!(target_rx.try_iter().any(|packet| matches!(packet, Packet::CombatEvent(..))));
+
+        // Without a player the command kills whoever runs it, creative or not
+        assert_eq!(run_command(&server, &player, &rx, "kill"), ["Steve died", "Killed Steve"]);
+        assert_eq!(player.read().unwrap().health(), 0.0);
+    }
+
+    #[test]
+    fn clear_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        player.read().unwrap().client().write().unwrap().set_username("Steve".to_owned());
+        {
+            let mut p = player.write().unwrap();
+            p.inventory_mut().set_slot(HOTBAR_START, Some(ItemStack::of(ItemType::Bread, 3)));
+            p.inventory_mut().set_slot(MAIN_START, Some(ItemStack::of(ItemType::Apple, 10)));
+            p.inventory_mut().take_changed();
+        }
+
+        assert_eq!(run_command(&server, &player, &rx, "clear Steve cheese"), ["There is no such item with name cheese"]);
+        run_clear(&server, &player, &rx, "clear Steve apple", "Cleared the inventory of Steve, removing 10 items");
+        assert_eq!(player.read().unwrap().inventory().get_slot(HOTBAR_START), Some(&ItemStack::of(ItemType::Bread, 3)));
+        run_clear(&server, &player, &rx, "clear", "Cleared the inventory of Steve, removing 3 items");
+        assert_eq!(run_command(&server, &player, &rx, "clear"), ["Could not clear the inventory of Steve, no items to remove"]);
+    }
+
+    /// Runs the /clear command and checks the whole inventory is sent again
+    fn run_clear(server: &Arc<Server>, player: &Arc<RwLock<Player>>, rx: &Receiver<Packet>, command: &str, message: &str) {
+        let client = player.read().unwrap().client();
+        server.set_op_level(client.read().unwrap().uuid(), 2);
+        client.read().unwrap().handle_command(command);
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(matches!(&packets[0], Packet::WindowItems(0, items) if items.len() == INVENTORY_SIZE));
+        assert!(matches!(&packets[1], Packet::ChatMessage(v) if v == message));
+    }
+
+    #[test]
+    fn spawnpoint_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        player.read().unwrap().client().write().unwrap().set_username("Steve".to_owned());
+        player.write().unwrap().set_pos(Coord::new(10.3, 16.0, -4.2));
+
+        assert_eq!(run_command(&server, &player, &rx, "spawnpoint"), ["Set Steve's spawn point to (10, 16, -5)"]);
+        assert_eq!(run_command(&server, &player, &rx, "spawnpoint Steve 20 ~ ~1"), ["Set Steve's spawn point to (20, 16, -4)"]);
+        assert_eq!(run_command(&server, &player, &rx, "spawnpoint 1 2"), ["Usage: /spawnpoint [player] [<x> <y> <z>]"]);
+        assert_eq!(run_command(&server, &player, &rx, "spawnpoint Alex"), ["That player cannot be found"]);
+        assert_eq!(player.read().unwrap().spawn_point(), Some(Coord::new(20, 16, -4)));
+
+        // Respawning uses the spawn point instead of the spawn of the world
+        run_command(&server, &player, &rx, "kill");
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_respawn();
+        assert_eq!(player.read().unwrap().pos(), Coord::new(20.5, 16.0, -3.5));
+    }
+
+    #[test]
+    fn gamerule_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+
+        assert_eq!(run_command(&server, &player, &rx, "gamerule doDaylightCycle"), ["doDaylightCycle = true"]);
+        assert_eq!(run_command(&server, &player, &rx, "gamerule commandBlockOutput 1"), ["'1' is not a valid value for commandBlockOutput"]);
+        assert_eq!(run_command(&server, &player, &rx, "gamerule flying true"), ["No game rule called 'flying' is available"]);
+        assert_eq!(run_command(&server, &player, &rx, "gamerule commandBlockOutput false"), ["Game rule commandBlockOutput has been updated to false"]);
+        assert!(!server.default_world().read().unwrap().game_rules().get_bool("commandBlockOutput"));
+    }
+
+    #[test]
+    fn fill_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let world = server.default_world();
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+
+        assert_eq!(run_command(&server, &player, &rx, "fill 1 16 1 2 17 3 sand 0"), ["12 blocks filled"]);
+        for pos in [Coord::new(1, 16, 1), Coord::new(2, 17, 3)] {
+            assert_eq!(server.get_block_state(&world, pos), (BlockType::Sand, 0));
+        }
+        assert_eq!(server.get_block_state(&world, Coord::new(3, 16, 1)), (BlockType::Air, 0));
+
+        assert_eq!(run_command(&server, &player, &rx, "fill 0 0 0 100 100 100 sand"), ["Too many blocks in the specified area (1030301 > 32768)"]);
+        assert_eq!(run_command(&server, &player, &rx, "fill -2147483648 16 0 2147483647 16 0 sand"),
+            ["Too many blocks in the specified area (4294967296 > 32768)"]);
+    }
+
+    #[test]
+    fn effect_expires() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let id = player.read().unwrap().client().read().unwrap().id();
+        let effect = Effect::new(EffectType::Regeneration, 1, 50);
+        player.write().unwrap().set_health(10.0);
+        server.add_effect(&player, effect);
+
+        match rx.try_recv().unwrap() {
+            Packet::EntityEffect(entity_id, v) => assert_eq!((entity_id, v), (id, effect)),
+            _ => panic!("Expected entity effect packet")
+        }
+
+        let client = player.read().unwrap().client();
+        for _ in 0..50 {
+            assert!(player.read().unwrap().get_effect(EffectType::Regeneration).is_some());
+            client.read().unwrap().tick();
+        }
+
+        assert!(player.read().unwrap().get_effect(EffectType::Regeneration).is_none());
+        // Regeneration II heals every 25 ticks
+        assert_eq!(player.read().unwrap().health(), 12.0);
+        let removed = rx.try_iter().any(|packet| matches!(packet, Packet::RemoveEntityEffect(entity_id, EffectType::Regeneration) if entity_id == id));
+        assert!(removed);
+    }
+
+    #[test]
+    fn effect_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let client = player.read().unwrap().client();
+        client.write().unwrap().set_username("Steve".to_owned());
+        let id = client.read().unwrap().id();
+        let speed = || player.read().unwrap().get_effect(EffectType::Speed).copied();
+
+        assert_eq!(run_command(&server, &player, &rx, "effect steve speed 10 1"), ["Given speed (ID 1) * 1 to steve for 10 seconds"]);
+        assert_eq!(speed(), Some(Effect::new(EffectType::Speed, 1, 200)));
+
+        // A longer effect replaces the current one, a shorter one doesn't
+        client.read().unwrap().handle_command("effect Steve speed 20 1");
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::EntityEffect(entity_id, effect) if entity_id == id && effect.duration == 400)));
+        run_command(&server, &player, &rx, "effect Steve minecraft:speed 5 1");
+        assert_eq!(speed().map(|v| v.duration), Some(400));
+
+        assert_eq!(run_command(&server, &player, &rx, "effect Steve speed 0"), ["Took speed from Steve"]);
+        assert_eq!(speed(), None);
+        assert_eq!(run_command(&server, &player, &rx, "effect Alex speed"), ["That player cannot be found"]);
+        assert_eq!(run_command(&server, &player, &rx, "effect Steve flight"), ["Usage: /effect <player> <effect> [seconds] [amplifier] [hideParticles]"]);
+
+        // Effects run out on their own
+        run_command(&server, &player, &rx, "effect Steve 16 1");
+        for _ in 0..crate::TPS {
+            client.read().unwrap().tick();
+        }
+        assert!(player.read().unwrap().get_effect(EffectType::NightVision).is_none());
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::RemoveEntityEffect(entity_id, EffectType::NightVision) if entity_id == id)));
+        assert_eq!(run_command(&server, &player, &rx, "effect Steve clear"), ["Couldn't take any effects from Steve as they do not have any"]);
+    }
+
+    #[test]
+    fn speed_allows_faster_moves() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let client = player.read().unwrap().client();
+        let start = player.read().unwrap().pos();
+        let far = Coord::new(start.x + 12.0, start.y, start.z);
+
+        client.read().unwrap().handle_move(Some(far), None, false);
+        assert_eq!(player.read().unwrap().pos(), start);
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::PlayerPositionAndLook(_))));
+
+        server.add_effect(&player, Effect::new(EffectType::Speed, 1, 100));
+        assert!((player.read().unwrap().speed_multiplier() - 1.4).abs() < 1e-9);
+        client.read().unwrap().handle_move(Some(far), None, false);
+        assert_eq!(player.read().unwrap().pos(), far);
+    }
+
+    #[test]
+    fn movement_speed_properties() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let (_observer, observer_rx) = test_player(&server, GameMode::Survival);
+        server.update_tracking(&server.default_world());
+        rx.try_iter().for_each(drop);
+        observer_rx.try_iter().for_each(drop);
+        let client = player.read().unwrap().client();
+        let id = client.read().unwrap().id();
+        let speed_modifiers = |rx: &Receiver<Packet>| rx.try_iter().filter_map(|packet| match packet {
+            Packet::EntityProperties(entity_id, attributes) if entity_id == id =>
+                Some(attributes.get(AttributeType::MovementSpeed).modifiers.iter().map(|v| v.uuid).collect::<Vec<_>>()),
+            _ => None
+        }).collect::<Vec<_>>();
+
+        client.read().unwrap().handle_entity_action(EntityAction::StartSprinting);
+        assert_eq!(speed_modifiers(&rx), [[attributes::SPRINTING_SPEED_BOOST.uuid]]);
+        assert_eq!(speed_modifiers(&observer_rx), [[attributes::SPRINTING_SPEED_BOOST.uuid]]);
+
+        server.add_effect(&player, Effect::new(EffectType::Speed, 0, 100));
+        assert_eq!(speed_modifiers(&rx), [[attributes::SPRINTING_SPEED_BOOST.uuid, attributes::SPEED_UUID]]);
+        let speed = player.read().unwrap().attributes().value(AttributeType::MovementSpeed);
+        assert!((speed - 0.1 * 1.3 * 1.2).abs() < 1e-6);
+
+        // Effects that don't change the speed don't send the properties
+        server.add_effect(&player, Effect::new(EffectType::NightVision, 0, 100));
+        assert!(speed_modifiers(&rx).is_empty());
+
+        client.read().unwrap().handle_entity_action(EntityAction::StopSprinting);
+        server.remove_effects(&player, &[EffectType::Speed]);
+        assert_eq!(speed_modifiers(&rx), [vec![attributes::SPEED_UUID], vec![]]);
+        assert_eq!(player.read().unwrap().attributes(), &Attributes::default());
+    }
+
+    #[test]
+    fn replace_held_bucket() {
+        let server = test_server();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        let mut player = player.write().unwrap();
+        player.inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bucket, 2)));
+
+        player.replace_held_item(ItemStack::of(ItemType::WaterBucket, 1));
+        assert_eq!(player.inventory().held_item(), Some(&ItemStack::of(ItemType::Bucket, 1)));
+        assert_eq!(player.inventory().get_slot(HOTBAR_START + 1), Some(&ItemStack::of(ItemType::WaterBucket, 1)));
+
+        player.replace_held_item(ItemStack::of(ItemType::LavaBucket, 1));
+        assert_eq!(player.inventory().held_item(), Some(&ItemStack::of(ItemType::LavaBucket, 1)));
+        assert_eq!(player.inventory_mut().take_changed(), [HOTBAR_START, HOTBAR_START + 1]);
+
+        let (player, _) = test_player(&server, GameMode::Creative);
+        let mut player = player.write().unwrap();
+        player.inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Bucket, 1)));
+        player.replace_held_item(ItemStack::of(ItemType::WaterBucket, 1));
+        assert_eq!(player.inventory().held_item(), Some(&ItemStack::of(ItemType::Bucket, 1)));
+    }
+
+    #[test]
+    fn button_powers_wire() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let (button, wire) = (Coord::new(0, 16, 0), Coord::new(1, 16, 0));
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(button, BlockType::StoneButton, 5), (wire, BlockType::RedstoneWire, 0)]);
+        player.write().unwrap().set_pos(wire.bottom_center());
+        rx.try_iter().for_each(drop);
+
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_right_click(BlockPlacement {
+            pos: button,
+            face: Some(BlockFace::YP),
+            held_item: None,
+            cursor: Coord::new(8, 8, 8)
+        });
+        assert_eq!(server.get_block_state(&world, button), (BlockType::StoneButton, 5 | redstone::POWERED));
+        assert_eq!(server.get_block_state(&world, wire), (BlockType::RedstoneWire, 15));
+        assert!(rx.try_iter().any(|packet| matches!(packet, Packet::MultiBlockChange(_, records) if records == [(wire, BlockType::RedstoneWire, 15)])));
+
+        // The button pops back out after a second
+        for _ in 0..redstone::STONE_BUTTON_DELAY - 1 {
+            server.tick();
+        }
+        assert_eq!(server.get_block_state(&world, wire), (BlockType::RedstoneWire, 15));
+        server.tick();
+        assert_eq!(server.get_block_state(&world, button), (BlockType::StoneButton, 5));
+        assert_eq!(server.get_block_state(&world, wire), (BlockType::RedstoneWire, 0));
+    }
+
+    #[test]
+    fn place_sign() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Sign, 1)));
+        player.write().unwrap().set_pos(Coord::new(0.5, 16.0, 0.5));
+
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_right_click(BlockPlacement {
+            pos: Coord::new(2, 15, 2),
+            face: Some(BlockFace::YP),
+            held_item: Some(ItemStack::of(ItemType::Sign, 1)),
+            cursor: Coord::new(8, 15, 8)
+        });
+        let sign = Coord::new(2, 16, 2);
+        assert_eq!(server.get_block_state(&world, sign).0, BlockType::StandingSign);
+        assert!(player.read().unwrap().inventory().held_item().is_none());
+        assert!(rx.try_iter().any(|packet| matches!(packet, Packet::OpenSignEditor(pos) if pos == sign)));
+
+        let lines = [r#"{"text":"Hello","clickEvent":{"action":"run_command","value":"/op"}}"#, r#""§aworld""#, r##, r##];
+        client.read().unwrap().handle_update_sign(sign, lines.map(str::to_owned));
+        let expected = [r#""Hello""#, r#""world""#, r##, r##].map(str::to_owned);
+        assert_eq!(world.read().unwrap().get_tile_entity(sign), Some(TileEntity::Sign { lines: expected.clone() }));
+
+        // The sign can only be written on once
+        client.read().unwrap().handle_update_sign(sign, [r#""Bye""#; 4].map(str::to_owned));
+        assert_eq!(world.read().unwrap().get_tile_entity(sign), Some(TileEntity::Sign { lines: expected }));
+    }
+
+    #[test]
+    fn creative_inventory_action() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        let client = player.read().unwrap().client();
+        let slot = HOTBAR_START as i16;
+
+        client.read().unwrap().handle_creative_inventory_action(slot, Some(ItemStack::of(ItemType::LavaBucket, 64)));
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::of(ItemType::LavaBucket, 1)));
+        client.read().unwrap().handle_creative_inventory_action(slot, Some(ItemStack::new(1, 64, 0)));
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::new(1, 64, 0)));
+
+        // Unknown items are ignored
+        client.read().unwrap().handle_creative_inventory_action(slot, Some(ItemStack::new(5000, 1, 0)));
+        client.read().unwrap().handle_creative_inventory_action(slot, Some(ItemStack::new(0, 1, 0)));
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::new(1, 64, 0)));
+
+        // Slot -1 drops the item
+        rx.try_iter().for_each(drop);
+        client.read().unwrap().handle_creative_inventory_action(-1, Some(ItemStack::of(ItemType::Apple, 3)));
+        assert_eq!(packet_names(rx.try_iter()), ["SpawnObject", "EntityMetadata"]);
+    }
+
+    #[test]
+    fn creative_inventory_action_in_survival() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let client = player.read().unwrap().client();
+
+        client.read().unwrap().handle_creative_inventory_action(HOTBAR_START as i16, Some(ItemStack::of(ItemType::Apple, 64)));
+        assert!(player.read().unwrap().inventory().held_item().is_none());
+        assert!(matches!(rx.try_recv(), Ok(Packet::Disconnect(_))));
+    }
+
+    #[test]
+    fn blocks_out_of_reach_cant_be_used() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let chest = Coord::new(0, 16, 0);
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(chest, BlockType::Chest, 2)]);
+        rx.try_iter().for_each(drop);
+
+        let client = player.read().unwrap().client();
+        let open = || client.read().unwrap().handle_right_click(BlockPlacement {
+            pos: chest,
+            face: Some(BlockFace::YP),
+            held_item: None,
+            cursor: Coord::new(8, 8, 8)
+        });
+        // The eyes are 6.12 blocks above the center of the chest
+        player.write().unwrap().set_pos(Coord::new(0.5, 21.0, 0.5));
+        open();
+        assert!(!rx.try_iter().any(|packet| matches!(packet, Packet::OpenWindow(..))));
+
+        player.write().unwrap().set_pos(Coord::new(0.5, 20.0, 0.5));
+        open();
+        assert!(rx.try_iter().any(|packet| matches!(packet, Packet::OpenWindow(..))));
+    }
+
+    /// Uses the block while standing on top of it
+    fn use_block(player: &Arc<RwLock<Player>>, pos: Coord<i32>) {
+        player.write().unwrap().set_pos(Coord::new(pos.x, pos.y + 1, pos.z).bottom_center());
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_right_click(BlockPlacement {
+            pos,
+            face: Some(BlockFace::YP),
+            held_item: None,
+            cursor: Coord::new(8, 8, 8)
+        });
+    }
+
+    #[test]
+    fn chest_window_closes_when_destroyed() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let chest = Coord::new(0, 16, 0);
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(chest, BlockType::Chest, 2)]);
+        player.write().unwrap().inventory_mut().set_slot(HOTBAR_START, Some(ItemStack::of(ItemType::Apple, 3)));
+        rx.try_iter().for_each(drop);
+
+        use_block(&player, chest);
+        assert!(matches!(rx.try_recv(), Ok(Packet::OpenWindow(1, WindowType::Chest, 27))));
+        assert!(matches!(rx.try_recv(), Ok(Packet::WindowItems(1, items)) if items.len() == CHEST_SIZE + 36));
+
+        // Pick up the apples from the hotbar, the last row of the window
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_click_window(1, (CHEST_SIZE + 27) as i16, 0, 0);
+        assert!(player.read().unwrap().inventory().held_item().is_none());
+
+        server.set_blocks(&world, &[(chest, BlockType::Air, 0)]);
+        assert!(world.read().unwrap().get_tile_entity(chest).is_none());
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(packets.iter().any(|packet| matches!(packet, Packet::CloseWindow(1))));
+        // The apples held by the cursor go back into the inventory
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::of(ItemType::Apple, 3)));
+    }
+
+    #[test]
+    fn destroyed_chest_drops_contents() {
+        let server = test_server();
+        let world = server.default_world();
+        let chest = Coord::new(0, 16, 0);
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(chest, BlockType::Chest, 2)]);
+        let items = world.read().unwrap().get_tile_entity(chest).unwrap().items().unwrap().clone();
+        items.lock().set_slot(4, Some(ItemStack::of(ItemType::Apple, 3)));
+        items.lock().set_slot(20, Some(ItemStack::of(ItemType::Bread, 1)));
+
+        server.set_blocks(&world, &[(chest, BlockType::Air, 0)]);
+        let mut dropped: Vec<_> = world.read().unwrap().entities().iter()
+            .filter_map(|v| v.read().unwrap().item().cloned())
+            .collect();
+        dropped.sort_by_key(|v| v.id);
+        assert_eq!(dropped, [ItemStack::of(ItemType::Apple, 3), ItemStack::of(ItemType::Bread, 1)]);
+    }
+
+    #[test]
+    fn player_data_round_trip() {
+        let server = test_server();
+        let path = std::env::temp_dir().join(format!("siderite-player-data-{}.dat", std::process::id()));
+        let (player, _rx) = test_player(&server, GameMode::Survival);
+        {
+            let mut player = player.write().unwrap();
+            player.inventory_mut().set_slot(MAIN_START + 3, Some(ItemStack::of(ItemType::Bread, 7)));
+            player.ender_chest().lock().set_slot(2, Some(ItemStack::of(ItemType::Diamond, 4)));
+            player.set_pos(Coord::new(10.5, 70.0, -3.25));
+            player.set_rotation(90.0, 12.5);
+            player.set_spawn_point(Some(Coord::new(4, 65, 8)), true);
+            player.save_data(&path).unwrap();
+        }
+
+        let (loaded, _rx) = test_player(&server, GameMode::Survival);
+        let mut loaded = loaded.write().unwrap();
+        loaded.load_data(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.inventory().get_slot(MAIN_START + 3), Some(&ItemStack::of(ItemType::Bread, 7)));
+        assert_eq!(loaded.ender_chest().lock().get_slot(2), Some(&ItemStack::of(ItemType::Diamond, 4)));
+        assert_eq!(loaded.pos(), Coord::new(10.5, 70.0, -3.25));
+        assert_eq!((loaded.yaw(), loaded.pitch()), (90.0, 12.5));
+        assert_eq!((loaded.spawn_point(), loaded.spawn_forced()), (Some(Coord::new(4, 65, 8)), true));
+
+        // Players without data keep where they spawned
+        let (new, _rx) = test_player(&server, GameMode::Survival);
+        let pos = new.read().unwrap().pos();
+        new.write().unwrap().load_data(&path).unwrap();
+        assert_eq!(new.read().unwrap().pos(), pos);
+    }
+
+    #[test]
+    fn ender_chest_follows_player() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let (first, second) = (Coord::new(0, 16, 0), Coord::new(4, 16, 0));
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(first, BlockType::EnderChest, 2), (second, BlockType::EnderChest, 2)]);
+        player.write().unwrap().inventory_mut().set_slot(HOTBAR_START, Some(ItemStack::of(ItemType::Apple, 3)));
+        rx.try_iter().for_each(drop);
+
+        use_block(&player, first);
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_click_window(1, (CHEST_SIZE + 27) as i16, 0, 0);
+        client.read().unwrap().handle_click_window(1, 0, 0, 0);
+        client.read().unwrap().handle_close_window(1);
+        rx.try_iter().for_each(drop);
+
+        use_block(&player, second);
+        assert!(matches!(rx.try_recv(), Ok(Packet::OpenWindow(2, WindowType::EnderChest, 27))));
+        match rx.try_recv() {
+            Ok(Packet::WindowItems(2, items)) => assert_eq!(items[0], Some(ItemStack::of(ItemType::Apple, 3))),
+            _ => panic!("Expected window items packet")
+        }
+        assert!(world.read().unwrap().get_tile_entity(second).is_none());
+    }
+
+    #[test]
+    fn furnace_lights_up() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let pos = Coord::new(0, 16, 0);
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(pos, BlockType::Furnace, 2)]);
+        use_block(&player, pos);
+
+        let items = world.read().unwrap().get_tile_entity(pos).unwrap().items().unwrap().clone();
+        items.lock().set_slot(furnace::INPUT_SLOT, Some(ItemStack::of(ItemType::Porkchop, 1)));
+        items.lock().set_slot(furnace::FUEL_SLOT, Some(ItemStack::of(ItemType::Coal, 1)));
+        rx.try_iter().for_each(drop);
+
+        server.tick();
+        assert_eq!(server.get_block_state(&world, pos), (BlockType::LitFurnace, 2));
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(packets.iter().any(|packet| matches!(packet, Packet::WindowProperty(1, 2, 1))));
+        // The window stays open while the furnace is lit
+        assert!(!packets.iter().any(|packet| matches!(packet, Packet::CloseWindow(_))));
+        assert_eq!(items.lock().get_slot(furnace::FUEL_SLOT), None);
+
+        // Only the flame and arrow move while burning
+        server.tick();
+        let properties: Vec<_> = rx.try_iter()
+            .filter_map(|packet| match packet {
+                Packet::WindowProperty(1, property, _) => Some(property),
+                _ => None
+            })
+            .collect();
+        assert_eq!(properties, [0, 2]);
+    }
+
+    #[test]
+    fn enchanting_table() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let table = Coord::new(0, 16, 0);
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(table, BlockType::EnchantingTable, 0)]);
+        {
+            let mut p = player.write().unwrap();
+            p.inventory_mut().set_slot(HOTBAR_START, Some(ItemStack::of(ItemType::IronSword, 1)));
+            p.inventory_mut().set_slot(HOTBAR_START + 1, Some(ItemStack::new(ItemType::Dye as i16, 5, enchanting::LAPIS_DAMAGE)));
+            p.inventory_mut().take_changed();
+        }
+        rx.try_iter().for_each(drop);
+
+        use_block(&player, table);
+        assert!(matches!(rx.try_recv(), Ok(Packet::OpenWindow(1, WindowType::EnchantingTable, 2))));
+        // Move the sword and the lapis from the hotbar, the last row of the window, into the table
+        let client = player.read().unwrap().client();
+        let hotbar = (enchanting::ENCHANTING_TABLE_SIZE + 27) as i16;
+        for (from, to) in [(hotbar, enchanting::ITEM_SLOT as i16), (hotbar + 1, enchanting::LAPIS_SLOT as i16)] {
+            client.read().unwrap().handle_click_window(1, from, 0, 0);
+            client.read().unwrap().handle_click_window(1, to, 0, 0);
+        }
+        let options = EnchantingOptions::new(Some(&ItemStack::of(ItemType::IronSword, 1)), 0, player.read().unwrap().enchant_seed());
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(packets.iter().any(|v| matches!(v, Packet::WindowProperty(1, 2, cost) if *cost as i32 == options.costs[2])));
+        assert!(packets.iter().any(|v| matches!(v, Packet::WindowProperty(1, 6, 16))));
+
+        // Without enough levels nothing happens
+        client.read().unwrap().handle_enchant_item(1, 2);
+        assert!(rx.try_recv().is_err());
+
+        player.write().unwrap().set_xp_level(30);
+        client.read().unwrap().handle_enchant_item(1, 2);
+        assert!(matches!(rx.try_recv(), Ok(Packet::SetExperience(27))));
+        // The enchanted sword can't be enchanted again
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::WindowProperty(1, 2, 0))));
+
+        client.read().unwrap().handle_close_window(1);
+        let p = player.read().unwrap();
+        let sword = p.inventory().held_item().unwrap();
+        assert_eq!(sword.enchantments()[0].0, Enchantment::Sharpness as i16);
+        assert_eq!(p.inventory().get_slot(HOTBAR_START + 1), Some(&ItemStack::new(ItemType::Dye as i16, 2, enchanting::LAPIS_DAMAGE)));
+    }
+
+    #[test]
+    fn anvil_repair_and_rename() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let pos = Coord::new(0, 16, 0);
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(pos, BlockType::Anvil, 0)]);
+        {
+            let mut p = player.write().unwrap();
+            p.inventory_mut().set_slot(HOTBAR_START, Some(ItemStack::new(ItemType::IronSword as i16, 1, 50)));
+            p.inventory_mut().set_slot(HOTBAR_START + 1, Some(ItemStack::of(ItemType::IronIngot, 3)));
+            p.inventory_mut().take_changed();
+        }
+        rx.try_iter().for_each(drop);
+
+        use_block(&player, pos);
+        assert!(matches!(rx.try_recv(), Ok(Packet::OpenWindow(1, WindowType::Anvil, 3))));
+        let client = player.read().unwrap().client();
+        let hotbar = (anvil::ANVIL_SIZE + 27) as i16;
+        for (from, to) in [(hotbar, anvil::INPUT_SLOT as i16), (hotbar + 1, anvil::MATERIAL_SLOT as i16)] {
+            client.read().unwrap().handle_click_window(1, from, 0, 0);
+            client.read().unwrap().handle_click_window(1, to, 0, 0);
+        }
+        // One ingot repairs the sword
+        let packets: Vec<_> = rx.try_iter().collect();
+        assert!(packets.iter().any(|v| matches!(v, Packet::SetSlot(1, 2, Some(item)) if item.damage == 0)));
+        assert!(packets.iter().any(|v| matches!(v, Packet::WindowProperty(1, 0, 1))));
+
+        // The client sends the name the sword has first, renaming it costs another level
+        client.read().unwrap().handle_item_name("Iron Sword".to_owned());
+        assert!(rx.try_recv().is_err());
+        client.read().unwrap().handle_item_name("Stabby".to_owned());
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::WindowProperty(1, 0, 2))));
+
+        // Without enough levels the output stays in the anvil
+        client.read().unwrap().handle_click_window(1, anvil::OUTPUT_SLOT as i16, 0, 0);
+        assert!(matches!(rx.try_recv(), Ok(Packet::WindowItems(1, _))));
+        rx.try_iter().for_each(drop);
+
+        player.write().unwrap().set_xp_level(5);
+        client.read().unwrap().handle_click_window(1, anvil::OUTPUT_SLOT as i16, 0, 0);
+        assert!(matches!(rx.try_recv(), Ok(Packet::SetExperience(3))));
+        let items = match rx.try_recv() {
+            Ok(Packet::WindowItems(1, v)) => v,
+            _ => panic!("Expected window items packet")
+        };
+        assert_eq!(items[anvil::INPUT_SLOT], None);
+        assert_eq!(items[anvil::MATERIAL_SLOT], Some(ItemStack::of(ItemType::IronIngot, 2)));
+        assert_eq!(items[anvil::OUTPUT_SLOT], None);
+
+        client.read().unwrap().handle_close_window(1);
+        let p = player.read().unwrap();
+        let sword = p.inventory().held_item().unwrap();
+        assert_eq!((sword.damage, sword.custom_name()), (0, Some("Stabby")));
+        assert_eq!(anvil::repair_cost(sword), 1);
+        assert_eq!(p.inventory().get_slot(HOTBAR_START + 1), Some(&ItemStack::of(ItemType::IronIngot, 2)));
+    }
+
+    #[test]
+    fn sleeping_skips_the_night() {
+        let server = test_server();
+        let (steve, steve_rx) = test_player(&server, GameMode::Survival);
+        let (alex, alex_rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        // Two beds with their heads to the south
+        let (first, second) = (Coord::new(0, 16, 0), Coord::new(2, 16, 0));
+        let first_head = Coord::new(0, 16, 1);
+        server.set_blocks(&world, &[
+            (first, BlockType::Bed, 0),
+            (first_head, BlockType::Bed, beds::HEAD),
+            (second, BlockType::Bed, 0),
+            (Coord::new(2, 16, 1), BlockType::Bed, beds::HEAD)
+        ]);
+        {
+            let mut w = world.write().unwrap();
+            w.set_time_of_day(6000);
+            w.weather_mut().set(WeatherType::Rain, 100_000);
+        }
+        steve_rx.try_iter().for_each(drop);
+
+        use_block(&steve, first);
+        assert!(steve_rx.try_iter().any(|v| matches!(v, Packet::ChatMessage(msg) if msg == "You can only sleep at night")));
+        assert_eq!(steve.read().unwrap().bed(), None);
+
+        world.write().unwrap().set_time_of_day(13000);
+        use_block(&steve, first);
+        let id = steve.read().unwrap().client().read().unwrap().id();
+        assert!(steve_rx.try_iter().any(|v| matches!(v, Packet::UseBed(player, pos) if player == id && pos == first_head)));
+        assert_eq!(world.read().unwrap().get_block_state(first), (BlockType::Bed, beds::OCCUPIED));
+        assert_eq!(steve.read().unwrap().spawn_point(), Some(first_head));
+        use_block(&alex, first);
+        assert!(alex_rx.try_iter().any(|v| matches!(v, Packet::ChatMessage(msg) if msg == "This bed is occupied")));
+
+        // The night goes on while only one of the players is asleep
+        for _ in 0..2 * beds::FULLY_ASLEEP_TICKS {
+            server.tick();
+        }
+        assert!(steve.read().unwrap().is_fully_asleep());
+        assert_eq!(world.read().unwrap().time_of_day(), 13200);
+
+        use_block(&alex, second);
+        for _ in 0..beds::FULLY_ASLEEP_TICKS {
+            server.tick();
+        }
+        assert_eq!(world.read().unwrap().time_of_day(), 13300);
+        server.tick();
+        assert_eq!(world.read().unwrap().time_of_day(), DAY_LENGTH);
+        assert_eq!(world.read().unwrap().weather().weather_type(), WeatherType::Clear);
+        for (player, rx) in [(&steve, &steve_rx), (&alex, &alex_rx)] {
+            assert_eq!(player.read().unwrap().bed(), None);
+            assert!(rx.try_iter().any(|v| matches!(v, Packet::Animation(_, Animation::LeaveBed))));
+        }
+        assert_eq!(world.read().unwrap().get_block_state(first), (BlockType::Bed, 0));
+
+        // Without the bed the player respawns at the spawn of the world
+        server.set_blocks(&world, &[(first, BlockType::Air, 0), (first_head, BlockType::Air, 0)]);
+        steve_rx.try_iter().for_each(drop);
+        steve.write().unwrap().set_health(0.0);
+        steve.read().unwrap().client().read().unwrap().handle_respawn();
+        assert!(matches!(steve_rx.try_recv(), Ok(Packet::ChangeGameState(GameStateReason::InvalidBed, _))));
+        assert_eq!(steve.read().unwrap().spawn_point(), None);
+    }
+
+    #[test]
+    fn craft_in_inventory() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let planks = ItemStack::new(BlockType::Planks as i16, 4, 0);
+        player.write().unwrap().inventory_mut().set_slot(MAIN_START, Some(planks.clone()));
+        let client = player.read().unwrap().client();
+
+        // Spread the planks over the grid with right clicks
+        client.read().unwrap().handle_click_window(0, MAIN_START as i16, 0, 0);
+        for slot in crafting::grid_slots() {
+            client.read().unwrap().handle_click_window(0, slot as i16, 1, 0);
+        }
+        let table = ItemStack::new(BlockType::CraftingTable as i16, 1, 0);
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::SetSlot(0, 0, Some(item)) if item == table)));
+
+        client.read().unwrap().handle_click_window(0, crafting::RESULT_SLOT as i16, 0, 1);
+        let p = player.read().unwrap();
+        assert_eq!(p.inventory().get_slot(INVENTORY_SIZE - 1), Some(&table));
+        assert!(crafting::grid_slots().all(|slot| p.inventory().get_slot(slot).is_none()));
+        drop(p);
+
+        // Closing the inventory gives back what's left in the grid
+        player.write().unwrap().inventory_mut().set_slot(crafting::GRID_START, Some(planks.clone()));
+        client.read().unwrap().handle_close_window(0);
+        assert_eq!(player.read().unwrap().inventory().get_slot(crafting::GRID_START), None);
+        assert_eq!(player.read().unwrap().inventory().get_slot(HOTBAR_START), Some(&planks));
+    }
+
+    #[test]
+    fn hold_map() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        server.default_world().read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::EmptyMap, 1)));
+
+        let client = player.read().unwrap().client();
+        client.read().unwrap().handle_right_click(BlockPlacement {
+            pos: Coord::new(-1, -1, -1),
+            face: None,
+            held_item: Some(ItemStack::of(ItemType::EmptyMap, 1)),
+            cursor: Coord::new(0, 0, 0)
+        });
+        let held = player.read().unwrap().inventory().held_item().cloned().unwrap();
+        assert_eq!(held.item_type(), Some(ItemType::FilledMap));
+        let id = held.map_id().unwrap();
+        rx.try_iter().for_each(drop);
+
+        // The whole map is sent right away, what's drawn on it once a second
+        server.tick();
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::Map(map, 0, _, Some(update)) if map == id && update.data.len() == MAP_SIZE * MAP_SIZE)));
+        for _ in 0..crate::TPS {
+            server.tick();
+        }
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::Map(map, 0, _, Some(_)) if map == id)));
+        // The spawn is at the center of the map, the flat world is dirt
+        let mut color = 0;
+        server.do_with_map_mut(id, |map| color = map.get_color(MAP_SIZE / 2, MAP_SIZE / 2));
+        assert_eq!(color, map::block_color(BlockType::Dirt) * 4 + 1);
+    }
+
+    #[test]
+    fn item_frame_interactions() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let wall = Coord::new(0, 16, 0);
+        world.read().unwrap().chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        server.set_blocks(&world, &[(wall, BlockType::Stone, 0)]);
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::ItemFrame, 2)));
+        player.write().unwrap().set_pos(Coord::new(2.5, 16.0, 0.5));
+        rx.try_iter().for_each(drop);
+
+        let client = player.read().unwrap().client();
+        let place = || client.read().unwrap().handle_right_click(BlockPlacement {
+            pos: wall,
+            face: Some(BlockFace::XP),
+            held_item: None,
+            cursor: Coord::new(15, 8, 8)
+        });
+        place();
+        let frame = match rx.try_iter().find(|packet| matches!(packet, Packet::SpawnObject(_))) {
+            Some(Packet::SpawnObject(v)) => v,
+            _ => panic!("Expected spawn object packet")
+        };
+        let id = frame.read().unwrap().id();
+        assert_eq!(frame.read().unwrap().hanging_pos(), Some(Coord::new(1, 16, 0)));
+
+        // Only one frame fits on the side of a block
+        place();
+        assert!(!rx.try_iter().any(|packet| matches!(packet, Packet::SpawnObject(_))));
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::of(ItemType::ItemFrame, 1)));
+
+        // The first click puts a held item in, the next ones rotate it
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::Apple, 3)));
+        client.read().unwrap().handle_use_entity(id, UseEntityAction::Interact);
+        client.read().unwrap().handle_use_entity(id, UseEntityAction::Interact);
+        assert_eq!(frame.read().unwrap().item(), Some(&ItemStack::of(ItemType::Apple, 1)));
+        assert_eq!(frame.read().unwrap().rotation(), 1);
+        assert_eq!(player.read().unwrap().inventory().held_item(), Some(&ItemStack::of(ItemType::Apple, 2)));
+        rx.try_iter().for_each(drop);
+
+        // Punching knocks the item out first, then the frame off the wall
+        client.read().unwrap().handle_use_entity(id, UseEntityAction::Attack);
+        assert_eq!(frame.read().unwrap().item(), None);
+        assert_eq!(frame.read().unwrap().rotation(), 0);
+        assert!(world.read().unwrap().get_entity(id).is_some());
+        client.read().unwrap().handle_use_entity(id, UseEntityAction::Attack);
+        assert!(world.read().unwrap().get_entity(id).is_none());
+        assert_eq!(packet_names(rx.try_iter()), ["EntityMetadata", "SpawnObject", "EntityMetadata", "Other", "SpawnObject", "EntityMetadata"]);
+    }
+
+    #[test]
+    fn steered_minecart_moves() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Survival);
+        let world = server.default_world();
+        let client = player.read().unwrap().client();
+
+        run_command(&server, &player, &rx, "summon MinecartRideable");
+        let minecart = world.read().unwrap().entities().pop().unwrap();
+        let (id, start) = {
+            let m = minecart.read().unwrap();
+            (m.id(), m.pos())
+        };
+        client.read().unwrap().handle_use_entity(id, UseEntityAction::Interact);
+        assert_eq!(player.read().unwrap().vehicle(), Some(id));
+
+        // Looking along +z pushes the minecart that way, the rider comes along
+        player.write().unwrap().set_rotation(0.0, 0.0);
+        client.read().unwrap().handle_steer_vehicle(0.0, 1.0, false, false);
+        rx.try_iter().for_each(drop);
+        server.tick();
+        let pos = minecart.read().unwrap().pos();
+        assert!(pos.z > start.z);
+        assert_eq!(pos.x, start.x);
+        assert_eq!(player.read().unwrap().pos(), pos);
+        assert!(rx.try_iter().any(|packet| matches!(packet, Packet::EntityTeleport(entity_id, ..) if entity_id == id)));
+
+        // Friction brings it to a stop
+        for _ in 0..200 {
+            server.tick();
+        }
+        let stopped = minecart.read().unwrap().pos();
+        server.tick();
+        assert_eq!(minecart.read().unwrap().pos(), stopped);
+    }
+
+    #[test]
+    fn boss_bar_command() {
+        let server = test_server();
+        let (player, rx) = test_player(&server, GameMode::Creative);
+        player.write().unwrap().set_flying(true);
+        let client = player.read().unwrap().client();
+        server.set_op_level(client.read().unwrap().uuid(), 2);
+        let command = |command: &str| client.read().unwrap().handle_command(command);
+
+        command("bossbar Welcome to the server");
+        let eye = player.read().unwrap().eye_pos();
+        let id = match rx.try_recv() {
+            Ok(Packet::SpawnMob(id, boss_bar::WITHER_TYPE, pos, metadata)) => {
+                // The player is looking south, towards positive z
+                assert!((pos.z - eye.z - boss_bar::DISTANCE).abs() < 1e-9);
+                assert!(metadata.contains(&(metadata::FLAGS_INDEX, MetadataValue::Byte(EntityFlags::INVISIBLE.bits() as i8))));
+                assert!(metadata.contains(&(metadata::CUSTOM_NAME_INDEX, MetadataValue::String("Welcome to the server".to_owned()))));
+                id
+            }
+            _ => panic!("Expected spawn mob packet")
+        };
+
+        command("bossbar progress 0.5");
+        match rx.try_recv() {
+            Ok(Packet::EntityMetadata(entity_id, metadata)) => {
+                assert_eq!(entity_id, id);
+                assert_eq!(metadata, [(metadata::HEALTH_INDEX, MetadataValue::Float(150.0))]);
+            }
+            _ => panic!("Expected entity metadata packet")
+        }
+
+        // The wither stays put while the player does
+        client.read().unwrap().tick();
+        assert!(!rx.try_iter().any(|packet| matches!(packet, Packet::EntityTeleport(entity_id, ..) if entity_id == id)));
+
+        // The wither follows the player around
+        client.read().unwrap().handle_move(Some(Coord::new(eye.x + 1.0, 65.0, eye.z)), None, false);
+        client.read().unwrap().tick();
+        let teleport = rx.try_iter().find_map(|packet| match packet {
+            Packet::EntityTeleport(entity_id, pos, ..) if entity_id == id => Some(pos),
+            _ => None
+        });
+        assert_eq!(teleport.map(|v| v.x), Some(eye.x + 1.0));
+
+        command("bossbar remove");
+        assert!(matches!(rx.try_recv(), Ok(Packet::DestroyEntities(ids)) if ids == [id]));
+        command("bossbar progress 1");
+        assert!(rx.try_recv().is_err());
+    }
 }
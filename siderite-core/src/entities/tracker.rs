@@ -0,0 +1,197 @@
+//! Keeps track of which entities each player can see, so entity packets are only sent to the players near them
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use crate::coord::Coord;
+use crate::entities::{Entity, EntityType};
+use crate::entities::metadata::{self, MetadataValue};
+use crate::protocol::packets::Packet;
+
+/// Range in blocks players see other players from, like in vanilla it's limited by the view distance
+pub const PLAYER_TRACKING_RANGE: i32 = 512;
+
+/// An entity or player and the range it can be seen from
+#[derive(Copy, Clone, Debug)]
+pub struct Tracked {
+    pub id: u32,
+    pub pos: Coord<f64>,
+    pub range: i32
+}
+
+/// The entities a player started and stopped tracking
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackingChange {
+    pub viewer: u32,
+    pub added: Vec<u32>,
+    pub removed: Vec<u32>
+}
+
+/// Which entities the players of a world track, players are both viewers and tracked entities
+#[derive(Default)]
+pub struct EntityTracker {
+    /// Entities tracked by each player, by the id of the player
+    tracking: HashMap<u32, HashSet<u32>>
+}
+
+impl EntityType {
+    /// Returns the range in blocks the entity can be seen from, the same as vanilla
+    pub fn tracking_range(self) -> i32 {
+        match self {
+            EntityType::Item => 64,
+            EntityType::Minecart | EntityType::Mob(_) => 80,
+            EntityType::PrimedTnt | EntityType::ItemFrame | EntityType::Painting => 160
+        }
+    }
+}
+
+/// Like vanilla, the range is a square around the viewer and the height doesn't matter
+fn in_range(viewer: Coord<f64>, entity: &Tracked) -> bool {
+    let range = entity.range as f64;
+    (viewer.x - entity.pos.x).abs() <= range && (viewer.z - entity.pos.z).abs() <= range
+}
+
+impl EntityTracker {
+    pub fn is_tracking(&self, viewer: u32, entity: u32) -> bool {
+        self.tracking.get(&viewer).is_some_and(|v| v.contains(&entity))
+    }
+
+    /// Returns the players tracking the entity
+    pub fn trackers(&self, entity: u32) -> Vec<u32> {
+        self.tracking.iter()
+            .filter(|(_, tracked)| tracked.contains(&entity))
+            .map(|(viewer, _)| *viewer)
+            .collect()
+    }
+
+    /// Returns the entities and players the viewer tracks
+    pub fn tracked(&self, viewer: u32) -> Vec<u32> {
+        self.tracking.get(&viewer).map(|v| v.iter().copied().collect()).unwrap_or_default()
+    }
+
+    /// Updates which entities the viewers track.
+    /// Viewers and entities that aren't passed anymore are forgotten.
+    pub fn update(&mut self, viewers: &[(u32, Coord<f64>)], entities: &[Tracked]) -> Vec<TrackingChange> {
+        self.tracking.retain(|id, _| viewers.iter().any(|(v, _)| v == id));
+        let ids: HashSet<u32> = entities.iter().map(|v| v.id).collect();
+
+        let mut changes = Vec::new();
+        for &(viewer, pos) in viewers {
+            let tracked = self.tracking.entry(viewer).or_default();
+            let mut change = TrackingChange { viewer, ..Default::default() };
+            tracked.retain(|id| {
+                let exists = ids.contains(id);
+                if !exists {
+                    change.removed.push(*id);
+                }
+
+                exists
+            });
+
+            for entity in entities.iter().filter(|v| v.id != viewer) {
+                if in_range(pos, entity) {
+                    if tracked.insert(entity.id) {
+                        change.added.push(entity.id);
+                    }
+                } else if tracked.remove(&entity.id) {
+                    change.removed.push(entity.id);
+                }
+            }
+
+            if !change.added.is_empty() || !change.removed.is_empty() {
+                changes.push(change);
+            }
+        }
+
+        changes
+    }
+
+    /// Starts tracking a new entity for the viewers in range of it, returns those viewers
+    pub fn track(&mut self, viewers: &[(u32, Coord<f64>)], entity: &Tracked) -> Vec<u32> {
+        let mut added = Vec::new();
+        for &(viewer, pos) in viewers {
+            if viewer != entity.id && in_range(pos, entity) && self.tracking.entry(viewer).or_default().insert(entity.id) {
+                added.push(viewer);
+            }
+        }
+
+        added
+    }
+
+    /// Forgets the entity, and the entities it tracked if it's a player.
+    /// Returns the players that tracked it.
+    pub fn untrack(&mut self, entity: u32) -> Vec<u32> {
+        self.tracking.remove(&entity);
+        self.tracking.iter_mut()
+            .filter_map(|(viewer, tracked)| tracked.remove(&entity).then_some(*viewer))
+            .collect()
+    }
+}
+
+/// Returns the packets that show the entity to a player that starts tracking it
+pub fn spawn_packets(entity: &Arc<RwLock<Entity>>) -> Vec<Packet> {
+    let (id, entity_type, item, rotation) = {
+        let e = entity.read().unwrap();
+        (e.id(), e.entity_type(), e.item().cloned(), e.rotation())
+    };
+
+    let mut packets = vec![Packet::SpawnObject(entity.clone())];
+    match (entity_type, item) {
+        (EntityType::Item, item) => packets.push(Packet::EntityMetadata(id, vec![(metadata::ITEM_INDEX, MetadataValue::Slot(item))])),
+        (EntityType::ItemFrame, Some(item)) => packets.push(Packet::EntityMetadata(id, vec![
+            (metadata::ITEM_FRAME_ITEM_INDEX, MetadataValue::Slot(Some(item))),
+            (metadata::ITEM_FRAME_ROTATION_INDEX, MetadataValue::Byte(rotation as i8))
+        ])),
+        _ => ()
+    }
+
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracked(id: u32, x: f64, range: i32) -> Tracked {
+        Tracked { id, pos: Coord::new(x, 64.0, 0.0), range }
+    }
+
+    #[test]
+    fn enter_and_leave_range() {
+        let mut tracker = EntityTracker::default();
+        let item = tracked(10, 100.0, EntityType::Item.tracking_range());
+        let tnt = tracked(11, 100.0, EntityType::PrimedTnt.tracking_range());
+
+        let changes = tracker.update(&[(1, Coord::new(0.0, 64.0, 0.0))], &[item, tnt]);
+        assert_eq!(changes, [TrackingChange { viewer: 1, added: vec![11], removed: Vec::new() }]);
+        assert!(tracker.is_tracking(1, 11));
+
+        let changes = tracker.update(&[(1, Coord::new(50.0, 64.0, 0.0))], &[item, tnt]);
+        assert_eq!(changes, [TrackingChange { viewer: 1, added: vec![10], removed: Vec::new() }]);
+        assert_eq!(tracker.trackers(10), [1]);
+
+        // The TNT exploded and the player walked away from the item
+        let changes = tracker.update(&[(1, Coord::new(-50.0, 64.0, 0.0))], &[item]);
+        assert_eq!(changes, [TrackingChange { viewer: 1, added: Vec::new(), removed: vec![11, 10] }]);
+        assert!(tracker.update(&[(1, Coord::new(-50.0, 64.0, 0.0))], &[item]).is_empty());
+    }
+
+    #[test]
+    fn players_track_each_other() {
+        let mut tracker = EntityTracker::default();
+        let viewers = [(1, Coord::new(0.0, 64.0, 0.0)), (2, Coord::new(20.0, 64.0, 0.0))];
+        let players = [tracked(1, 0.0, 128), tracked(2, 20.0, 128)];
+        tracker.update(&viewers, &players);
+        assert!(tracker.is_tracking(1, 2) && tracker.is_tracking(2, 1));
+        assert!(!tracker.is_tracking(1, 1));
+
+        let frame = tracked(3, 10.0, EntityType::ItemFrame.tracking_range());
+        let mut added = tracker.track(&viewers, &frame);
+        added.sort_unstable();
+        assert_eq!(added, [1, 2]);
+
+        // Player 2 left
+        assert_eq!(tracker.untrack(2), [1]);
+        assert_eq!(tracker.trackers(3), [1]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Hooks plugins can register to change what happens when players join or leave
+
+use std::sync::RwLock;
+
+use uuid::Uuid;
+
+/// A player finished logging in
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerJoin {
+    pub username: String,
+    pub uuid: Uuid,
+    /// Message broadcast to everyone, None doesn't broadcast anything
+    pub message: Option<String>
+}
+
+impl PlayerJoin {
+    pub fn new(username: &str, uuid: Uuid) -> Self {
+        Self {
+            username: username.to_owned(),
+            uuid,
+            message: Some(format!("{} joined the game", username))
+        }
+    }
+}
+
+/// A player left the game
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerQuit {
+    pub username: String,
+    pub uuid: Uuid,
+    /// Message broadcast to everyone, None doesn't broadcast anything
+    pub message: Option<String>
+}
+
+impl PlayerQuit {
+    pub fn new(username: &str, uuid: Uuid) -> Self {
+        Self {
+            username: username.to_owned(),
+            uuid,
+            message: Some(format!("{} left the game", username))
+        }
+    }
+}
+
+/// Gets called when things happen on the server, every event does nothing by default
+pub trait Listener: Send + Sync {
+    fn on_player_join(&self, _event: &mut PlayerJoin) {}
+
+    fn on_player_quit(&self, _event: &mut PlayerQuit) {}
+}
+
+/// The registered listeners, they get the events in the order they were registered
+#[derive(Default)]
+pub struct EventBus {
+    listeners: RwLock<Vec<Box<dyn Listener>>>
+}
+
+impl EventBus {
+    pub fn register(&self, listener: Box<dyn Listener>) {
+        self.listeners.write().unwrap().push(listener);
+    }
+
+    pub fn player_join(&self, mut event: PlayerJoin) -> PlayerJoin {
+        for listener in self.listeners.read().unwrap().iter() {
+            listener.on_player_join(&mut event);
+        }
+
+        event
+    }
+
+    pub fn player_quit(&self, mut event: PlayerQuit) -> PlayerQuit {
+        for listener in self.listeners.read().unwrap().iter() {
+            listener.on_player_quit(&mut event);
+        }
+
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Prefix(&'static str);
+
+    impl Listener for Prefix {
+        fn on_player_join(&self, event: &mut PlayerJoin) {
+            event.message = event.message.take().map(|v| format!("{}{}", self.0, v));
+        }
+    }
+
+    struct Silent;
+
+    impl Listener for Silent {
+        fn on_player_quit(&self, event: &mut PlayerQuit) {
+            event.message = None;
+        }
+    }
+
+    #[test]
+    fn listeners_run_in_order() {
+        let events = EventBus::default();
+        let uuid = Uuid::from_u128(1);
+        assert_eq!(events.player_join(PlayerJoin::new("Steve", uuid)).message.as_deref(), Some("Steve joined the game"));
+        assert_eq!(events.player_quit(PlayerQuit::new("Steve", uuid)).message.as_deref(), Some("Steve left the game"));
+
+        events.register(Box::new(Prefix("[+] ")));
+        events.register(Box::new(Silent));
+        events.register(Box::new(Prefix("§e")));
+        assert_eq!(events.player_join(PlayerJoin::new("Steve", uuid)).message.as_deref(), Some("§e[+] Steve joined the game"));
+        assert_eq!(events.player_quit(PlayerQuit::new("Steve", uuid)).message, None);
+    }
+}
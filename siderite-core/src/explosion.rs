@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+
+use crate::blocks::BlockType;
+use crate::coord::Coord;
+use crate::storage::world::World;
+
+/// Radius of a TNT explosion
+pub const TNT_RADIUS: f32 = 4.0;
+
+/// Number of points along each edge of the cube the rays are cast towards
+const RAY_GRID: i32 = 16;
+
+/// Distance between the points checked along a ray
+const RAY_STEP: f64 = 0.3;
+
+/// Strength a ray loses for each step, on top of the resistance of the blocks it passes
+const RAY_FALLOFF: f32 = 0.225;
+
+/// Returns the blocks destroyed by an explosion.
+/// `random` has to return values in [0, 1), it scales the strength of each ray.
+pub fn affected_blocks(world: &World, center: Coord<f64>, radius: f32, mut random: impl FnMut() -> f32) -> Vec<Coord<i32>> {
+    let mut affected = Vec::new();
+    let mut seen = HashSet::new();
+    let max = (RAY_GRID - 1) as f64;
+    for x in 0..RAY_GRID {
+        for y in 0..RAY_GRID {
+            for z in 0..RAY_GRID {
+                // Only cast rays towards the surface of the cube
+                if x != 0 && x != RAY_GRID - 1 && y != 0 && y != RAY_GRID - 1 && z != 0 && z != RAY_GRID - 1 {
+                    continue;
+                }
+
+                let (dx, dy, dz) = (x as f64 / max * 2.0 - 1.0, y as f64 / max * 2.0 - 1.0, z as f64 / max * 2.0 - 1.0);
+                let length = (dx * dx + dy * dy + dz * dz).sqrt();
+                let (dx, dy, dz) = (dx / length * RAY_STEP, dy / length * RAY_STEP, dz / length * RAY_STEP);
+
+                let mut intensity = radius * (0.7 + random() * 0.6);
+                let mut pos = center;
+                while intensity > 0.0 {
+                    let block = Coord::new(pos.x.floor() as i32, pos.y.floor() as i32, pos.z.floor() as i32);
+                    let (block_type, _) = world.get_block_state(block);
+                    if block_type != BlockType::Air {
+                        intensity -= (block_type.blast_resistance() + 0.3) * 0.3;
+                        if intensity > 0.0 && seen.insert(block) {
+                            affected.push(block);
+                        }
+                    }
+
+                    pos = Coord::new(pos.x + dx, pos.y + dy, pos.z + dz);
+                    intensity -= RAY_FALLOFF;
+                }
+            }
+        }
+    }
+
+    affected
+}
+
+/// Returns the knockback and damage for an entity at `pos`, None if it's out of range
+pub fn impact(center: Coord<f64>, radius: f32, pos: Coord<f64>) -> Option<(Coord<f64>, f32)> {
+    let reach = radius as f64 * 2.0;
+    let (dx, dy, dz) = (pos.x - center.x, pos.y - center.y, pos.z - center.z);
+    let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+    if distance == 0.0 || distance > reach {
+        return None;
+    }
+
+    // TODO: blocks in between should shield the entity
+    let exposure = 1.0;
+    let impact = (1.0 - distance / reach) * exposure;
+    let knockback = Coord::new(dx / distance * impact, dy / distance * impact, dz / distance * impact);
+    let damage = ((impact * impact + impact) / 2.0 * 8.0 * reach + 1.0) as f32;
+    Some((knockback, damage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::world::Dimension;
+    use crate::test_utils::test_world;
+
+    #[test]
+    fn explosion_in_air() {
+        let world = test_world(Dimension::Overworld);
+        assert!(affected_blocks(&world, Coord::new(0.5, 40.5, 0.5), TNT_RADIUS, || 0.99).is_empty());
+    }
+
+    #[test]
+    fn crater_in_dirt() {
+        let world = test_world(Dimension::Overworld);
+        let affected: HashSet<_> = affected_blocks(&world, Coord::new(0.5, 8.5, 0.5), TNT_RADIUS, || 0.5)
+            .into_iter().collect();
+
+        // At full strength a ray loses 0.465 per step through dirt,
+        // so it breaks blocks up to 2.4 blocks away from the center
+        for (inside, outside) in [((2, 8, 0), (3, 8, 0)), ((-2, 8, 0), (-3, 8, 0)), ((0, 10, 0), (0, 11, 0)), ((0, 6, 0), (0, 5, 0))] {
+            assert!(affected.contains(&Coord::new(inside.0, inside.1, inside.2)));
+            assert!(!affected.contains(&Coord::new(outside.0, outside.1, outside.2)));
+        }
+
+        // The crater is symmetric around the center
+        for pos in &affected {
+            assert!(affected.contains(&Coord::new(-pos.x, pos.y, pos.z)));
+            assert!(affected.contains(&Coord::new(pos.z, pos.y, pos.x)));
+            assert!(affected.contains(&Coord::new(pos.x, 16 - pos.y, pos.z)));
+        }
+    }
+
+    #[test]
+    fn obsidian_survives() {
+        let world = test_world(Dimension::Overworld);
+        world.set_block(Coord::new(1, 8, 0), BlockType::Obsidian, 0);
+        let affected = affected_blocks(&world, Coord::new(0.5, 8.5, 0.5), TNT_RADIUS, || 0.99);
+        assert!(affected.contains(&Coord::new(0, 8, 0)));
+        assert!(!affected.contains(&Coord::new(1, 8, 0)));
+    }
+
+    #[test]
+    fn impact_falls_off() {
+        let center = Coord::new(0.0, 0.0, 0.0);
+        assert!(impact(center, TNT_RADIUS, Coord::new(8.5, 0.0, 0.0)).is_none());
+
+        let (near, near_damage) = impact(center, TNT_RADIUS, Coord::new(2.0, 0.0, 0.0)).unwrap();
+        let (far, far_damage) = impact(center, TNT_RADIUS, Coord::new(6.0, 0.0, 0.0)).unwrap();
+        assert!(near.x > far.x && far.x > 0.0);
+        assert!(near_damage > far_damage);
+    }
+}
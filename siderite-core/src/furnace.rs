@@ -0,0 +1,285 @@
+//! Furnaces smelt the item in their input slot while burning the fuel in their fuel slot
+
+use num_traits::FromPrimitive;
+
+use crate::blocks::BlockType;
+use crate::coord::{ChunkCoord, Coord};
+use crate::items::{Inventory, ItemStack, ItemType};
+use crate::storage::world::World;
+use crate::tile_entities::TileEntity;
+use crate::window::{SharedInventory, WindowPropertySink};
+
+/// Slot of the item being smelted
+pub const INPUT_SLOT: usize = 0;
+
+/// Slot of the fuel
+pub const FUEL_SLOT: usize = 1;
+
+/// Slot the smelted items end up in
+pub const OUTPUT_SLOT: usize = 2;
+
+/// Number of slots in a furnace
+pub const FURNACE_SIZE: usize = 3;
+
+/// Ticks it takes to smelt one item
+pub const SMELT_TIME: i16 = 200;
+
+/// Damage value of charcoal, coal with damage 0 is regular coal
+const CHARCOAL: i16 = 1;
+
+/// How far a furnace is with burning its fuel and smelting its input
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FurnaceProgress {
+    /// Ticks the current fuel keeps burning
+    pub burn_time: i16,
+    /// Ticks the current fuel burns in total, used to draw the flame
+    pub fuel_time: i16,
+    /// Ticks the current input has been smelting
+    pub cook_time: i16
+}
+
+impl FurnaceProgress {
+    pub fn is_burning(&self) -> bool {
+        self.burn_time > 0
+    }
+}
+
+/// The flame and arrow of the furnace window
+impl WindowPropertySink for FurnaceProgress {
+    fn window_properties(&self) -> Vec<(i16, i16)> {
+        vec![(0, self.burn_time), (1, self.fuel_time), (2, self.cook_time), (3, SMELT_TIME)]
+    }
+}
+
+/// A furnace that changed during a tick
+#[derive(Debug)]
+pub struct FurnaceUpdate {
+    pub pos: Coord<i32>,
+    pub progress: FurnaceProgress,
+    pub items: SharedInventory,
+    /// The contents of the slots changed
+    pub items_changed: bool,
+    /// The furnace got lit or went out
+    pub lit_changed: bool
+}
+
+/// Returns the item the input smelts into
+pub fn smelting_result(input: &ItemStack) -> Option<ItemStack> {
+    let result = match input.item_type() {
+        Some(ItemType::Porkchop) => ItemType::CookedPorkchop,
+        Some(ItemType::Beef) => ItemType::CookedBeef,
+        Some(ItemType::Chicken) => ItemType::CookedChicken,
+        Some(ItemType::Potato) => ItemType::BakedPotato,
+        Some(_) => return None,
+        None => {
+            return match BlockType::from_u8(u8::try_from(input.id).ok()?)? {
+                BlockType::IronOre => Some(ItemStack::of(ItemType::IronIngot, 1)),
+                BlockType::GoldOre => Some(ItemStack::of(ItemType::GoldIngot, 1)),
+                BlockType::CobbleStone => Some(ItemStack::new(BlockType::Stone as i16, 1, 0)),
+                BlockType::Sand => Some(ItemStack::new(BlockType::Glass as i16, 1, 0)),
+                BlockType::Log => Some(ItemStack::new(ItemType::Coal as i16, 1, CHARCOAL)),
+                _ => None
+            };
+        }
+    };
+
+    Some(ItemStack::of(result, 1))
+}
+
+/// Returns the number of ticks the item burns for, None if it isn't fuel
+pub fn burn_time(fuel: &ItemStack) -> Option<i16> {
+    match fuel.item_type() {
+        Some(ItemType::Coal) => Some(1600),
+        Some(ItemType::LavaBucket) => Some(20000),
+        Some(_) => None,
+        None => (fuel.id == BlockType::Log as i16).then_some(300)
+    }
+}
+
+/// Returns what's smelted from the input if the result fits in the output slot
+fn smeltable(items: &Inventory) -> Option<ItemStack> {
+    let result = smelting_result(items.get_slot(INPUT_SLOT)?)?;
+    match items.get_slot(OUTPUT_SLOT) {
+        None => Some(result),
+        Some(output) if output.id == result.id && output.damage == result.damage
+            && output.count + result.count <= output.max_stack_size() => Some(result),
+        Some(_) => None
+    }
+}
+
+/// Advances the furnace by one tick, returns true if the contents of its slots changed
+pub fn tick(progress: &mut FurnaceProgress, items: &mut Inventory) -> bool {
+    let mut changed = false;
+    if progress.burn_time > 0 {
+        progress.burn_time -= 1;
+    }
+
+    let result = smeltable(items);
+    // Fuel is only used up while there's something to smelt
+    if progress.burn_time == 0 && result.is_some() {
+        if let Some(fuel) = items.get_slot(FUEL_SLOT).cloned() {
+            if let Some(time) = burn_time(&fuel) {
+                progress.burn_time = time;
+                progress.fuel_time = time;
+                // The bucket stays behind when lava is burned
+                let rest = if fuel.item_type() == Some(ItemType::LavaBucket) {
+                    Some(ItemStack::of(ItemType::Bucket, 1))
+                } else {
+                    Some(ItemStack { count: fuel.count - 1, ..fuel })
+                };
+                items.set_slot(FUEL_SLOT, rest);
+                changed = true;
+            }
+        }
+    }
+
+    match result {
+        Some(result) if progress.is_burning() => {
+            progress.cook_time += 1;
+            if progress.cook_time >= SMELT_TIME {
+                progress.cook_time = 0;
+                let mut input = items.get_slot(INPUT_SLOT).cloned().unwrap();
+                input.count -= 1;
+                items.set_slot(INPUT_SLOT, Some(input));
+                let output = match items.get_slot(OUTPUT_SLOT).cloned() {
+                    Some(mut v) => {
+                        v.count += result.count;
+                        v
+                    }
+                    None => result
+                };
+                items.set_slot(OUTPUT_SLOT, Some(output));
+                changed = true;
+            }
+        }
+        // Without fuel the progress cools down again
+        Some(_) => progress.cook_time = (progress.cook_time - 2).max(0),
+        // Without a valid input the progress is lost
+        None => progress.cook_time = 0
+    }
+
+    changed
+}
+
+/// Ticks the furnaces in the chunks and returns the ones that changed
+pub fn tick_furnaces(world: &World, chunks: impl IntoIterator<Item = ChunkCoord>) -> Vec<FurnaceUpdate> {
+    let chunk_map = world.chunk_map();
+    let mut updates = Vec::new();
+    for coord in chunks {
+        let mut has_furnace = false;
+        chunk_map.do_with_chunk(coord, |chunk| {
+            has_furnace = chunk.tile_entities.values().any(|v| matches!(v, TileEntity::Furnace { .. }));
+        });
+        if !has_furnace {
+            continue;
+        }
+
+        chunk_map.do_with_chunk_mut(coord, |chunk| {
+            let mut changed = false;
+            for (pos, tile_entity) in &mut chunk.tile_entities {
+                if let TileEntity::Furnace { items, progress } = tile_entity {
+                    let before = *progress;
+                    let items_changed = tick(progress, &mut items.lock());
+                    if *progress != before || items_changed {
+                        changed = true;
+                        updates.push(FurnaceUpdate {
+                            pos: *pos,
+                            progress: *progress,
+                            items: items.clone(),
+                            items_changed,
+                            lit_changed: before.is_burning() != progress.is_burning()
+                        });
+                    }
+                }
+            }
+
+            if changed {
+                chunk.mark_dirty();
+            }
+        });
+    }
+
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn furnace(input: Option<ItemStack>, fuel: Option<ItemStack>) -> Inventory {
+        let mut items = Inventory::with_size(FURNACE_SIZE);
+        items.set_slot(INPUT_SLOT, input);
+        items.set_slot(FUEL_SLOT, fuel);
+        items
+    }
+
+    #[test]
+    fn smelts_with_fuel() {
+        let mut items = furnace(Some(ItemStack::of(ItemType::Beef, 2)), Some(ItemStack::of(ItemType::Coal, 2)));
+        let mut progress = FurnaceProgress::default();
+
+        assert!(tick(&mut progress, &mut items));
+        assert_eq!(items.get_slot(FUEL_SLOT), Some(&ItemStack::of(ItemType::Coal, 1)));
+        assert_eq!(progress, FurnaceProgress { burn_time: 1600, fuel_time: 1600, cook_time: 1 });
+
+        for _ in 1..SMELT_TIME - 1 {
+            assert!(!tick(&mut progress, &mut items));
+        }
+        assert!(tick(&mut progress, &mut items));
+        assert_eq!(items.get_slot(INPUT_SLOT), Some(&ItemStack::of(ItemType::Beef, 1)));
+        assert_eq!(items.get_slot(OUTPUT_SLOT), Some(&ItemStack::of(ItemType::CookedBeef, 1)));
+        assert_eq!(progress.cook_time, 0);
+        assert_eq!(progress.burn_time, 1600 - (SMELT_TIME - 1));
+    }
+
+    #[test]
+    fn pauses_without_valid_input() {
+        let mut items = furnace(Some(ItemStack::of(ItemType::Apple, 1)), Some(ItemStack::of(ItemType::Coal, 1)));
+        let mut progress = FurnaceProgress::default();
+
+        // Fuel isn't used up for an input that can't be smelted
+        assert!(!tick(&mut progress, &mut items));
+        assert_eq!(progress, FurnaceProgress::default());
+        assert_eq!(items.get_slot(FUEL_SLOT), Some(&ItemStack::of(ItemType::Coal, 1)));
+
+        // A full output slot stops the smelting but the fuel keeps burning
+        items.set_slot(INPUT_SLOT, Some(ItemStack::new(BlockType::IronOre as i16, 1, 0)));
+        tick(&mut progress, &mut items);
+        assert_eq!(progress.cook_time, 1);
+        items.set_slot(OUTPUT_SLOT, Some(ItemStack::of(ItemType::IronIngot, 64)));
+        tick(&mut progress, &mut items);
+        assert_eq!(progress.cook_time, 0);
+        assert_eq!(progress.burn_time, 1599);
+    }
+
+    #[test]
+    fn cools_down_when_fuel_runs_out() {
+        let mut items = furnace(Some(ItemStack::new(BlockType::Sand as i16, 2, 0)), None);
+        let mut progress = FurnaceProgress { burn_time: 2, fuel_time: 300, cook_time: 50 };
+
+        tick(&mut progress, &mut items);
+        assert_eq!(progress.cook_time, 51);
+        tick(&mut progress, &mut items);
+        assert!(!progress.is_burning());
+        assert_eq!(progress.cook_time, 49);
+    }
+
+    #[test]
+    fn lava_leaves_bucket() {
+        let mut items = furnace(Some(ItemStack::of(ItemType::Potato, 1)), Some(ItemStack::of(ItemType::LavaBucket, 1)));
+        let mut progress = FurnaceProgress::default();
+
+        tick(&mut progress, &mut items);
+        assert_eq!(progress.burn_time, 20000);
+        assert_eq!(items.get_slot(FUEL_SLOT), Some(&ItemStack::of(ItemType::Bucket, 1)));
+    }
+
+    #[test]
+    fn smelting_results() {
+        assert_eq!(smelting_result(&ItemStack::of(ItemType::Chicken, 3)), Some(ItemStack::of(ItemType::CookedChicken, 1)));
+        assert_eq!(smelting_result(&ItemStack::new(BlockType::Log as i16, 1, 0)), Some(ItemStack::new(ItemType::Coal as i16, 1, CHARCOAL)));
+        assert_eq!(smelting_result(&ItemStack::new(BlockType::Dirt as i16, 1, 0)), None);
+        assert_eq!(burn_time(&ItemStack::new(BlockType::Log as i16, 1, 0)), Some(300));
+        assert_eq!(burn_time(&ItemStack::of(ItemType::Apple, 1)), None);
+    }
+}
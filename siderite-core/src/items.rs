@@ -0,0 +1,696 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use siderite_nbt::{Compound, Tag};
+
+use crate::blocks::BlockType;
+
+/// Number of slots in the player inventory window, including crafting and armor slots
+pub const INVENTORY_SIZE: usize = 45;
+
+/// Index of the first hotbar slot in the player inventory window
+pub const HOTBAR_START: usize = 36;
+
+/// Number of slots in the hotbar
+pub const HOTBAR_SIZE: usize = 9;
+
+/// Index of the first slot of the main inventory, the hotbar follows right after it
+pub const MAIN_START: usize = 9;
+
+/// Index of the helmet slot in the player inventory window, the chestplate, leggings and boots follow it
+pub const ARMOR_START: usize = 5;
+
+/// Maximum number of pages in a book
+const MAX_BOOK_PAGES: usize = 50;
+
+/// Maximum number of characters on a page of a book
+const MAX_BOOK_PAGE_LENGTH: usize = 256;
+
+/// Maximum number of characters in the title of a book
+pub const MAX_BOOK_TITLE_LENGTH: usize = 32;
+
+#[repr(i16)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ItemType {
+    IronShovel = 256,
+    IronPickaxe = 257,
+    IronAxe = 258,
+    FlintAndSteel = 259,
+    Apple = 260,
+    Coal = 263,
+    Diamond = 264,
+    IronIngot = 265,
+    GoldIngot = 266,
+    IronSword = 267,
+    DiamondSword = 276,
+    DiamondShovel = 277,
+    DiamondPickaxe = 278,
+    DiamondAxe = 279,
+    Stick = 280,
+    Bread = 297,
+    IronHelmet = 306,
+    IronChestplate = 307,
+    IronLeggings = 308,
+    IronBoots = 309,
+    DiamondHelmet = 310,
+    DiamondChestplate = 311,
+    DiamondLeggings = 312,
+    DiamondBoots = 313,
+    Porkchop = 319,
+    CookedPorkchop = 320,
+    Painting = 321,
+    Sign = 323,
+    Bucket = 325,
+    WaterBucket = 326,
+    LavaBucket = 327,
+    /// The damage value picks the color, 4 is lapis lazuli
+    Dye = 351,
+    Cookie = 357,
+    /// The damage value is the id of the map
+    FilledMap = 358,
+    Beef = 363,
+    CookedBeef = 364,
+    Chicken = 365,
+    CookedChicken = 366,
+    WritableBook = 386,
+    WrittenBook = 387,
+    ItemFrame = 389,
+    Carrot = 391,
+    Potato = 392,
+    BakedPotato = 393,
+    EmptyMap = 395,
+    // TODO: Add more
+}
+
+/// Vanilla names of the items, without the `minecraft:` namespace
+const ITEM_NAMES: &[(ItemType, &str)] = &[
+    (ItemType::IronShovel, "iron_shovel"),
+    (ItemType::IronPickaxe, "iron_pickaxe"),
+    (ItemType::IronAxe, "iron_axe"),
+    (ItemType::FlintAndSteel, "flint_and_steel"),
+    (ItemType::Apple, "apple"),
+    (ItemType::Coal, "coal"),
+    (ItemType::Diamond, "diamond"),
+    (ItemType::IronIngot, "iron_ingot"),
+    (ItemType::GoldIngot, "gold_ingot"),
+    (ItemType::IronSword, "iron_sword"),
+    (ItemType::DiamondSword, "diamond_sword"),
+    (ItemType::DiamondShovel, "diamond_shovel"),
+    (ItemType::DiamondPickaxe, "diamond_pickaxe"),
+    (ItemType::DiamondAxe, "diamond_axe"),
+    (ItemType::Stick, "stick"),
+    (ItemType::Bread, "bread"),
+    (ItemType::IronHelmet, "iron_helmet"),
+    (ItemType::IronChestplate, "iron_chestplate"),
+    (ItemType::IronLeggings, "iron_leggings"),
+    (ItemType::IronBoots, "iron_boots"),
+    (ItemType::DiamondHelmet, "diamond_helmet"),
+    (ItemType::DiamondChestplate, "diamond_chestplate"),
+    (ItemType::DiamondLeggings, "diamond_leggings"),
+    (ItemType::DiamondBoots, "diamond_boots"),
+    (ItemType::Porkchop, "porkchop"),
+    (ItemType::CookedPorkchop, "cooked_porkchop"),
+    (ItemType::Painting, "painting"),
+    (ItemType::Sign, "sign"),
+    (ItemType::Bucket, "bucket"),
+    (ItemType::WaterBucket, "water_bucket"),
+    (ItemType::LavaBucket, "lava_bucket"),
+    (ItemType::Dye, "dye"),
+    (ItemType::Cookie, "cookie"),
+    (ItemType::FilledMap, "filled_map"),
+    (ItemType::Beef, "beef"),
+    (ItemType::CookedBeef, "cooked_beef"),
+    (ItemType::Chicken, "chicken"),
+    (ItemType::CookedChicken, "cooked_chicken"),
+    (ItemType::WritableBook, "writable_book"),
+    (ItemType::WrittenBook, "written_book"),
+    (ItemType::ItemFrame, "item_frame"),
+    (ItemType::Carrot, "carrot"),
+    (ItemType::Potato, "potato"),
+    (ItemType::BakedPotato, "baked_potato"),
+    (ItemType::EmptyMap, "map")
+];
+
+impl ItemType {
+    /// Returns the item with the given vanilla name, without the `minecraft:` namespace
+    pub fn from_name(name: &str) -> Option<Self> {
+        ITEM_NAMES.iter().find(|(_, v)| *v == name).map(|(item_type, _)| *item_type)
+    }
+
+    /// Returns the vanilla name of the item, without the `minecraft:` namespace
+    pub fn name(self) -> &'static str {
+        ITEM_NAMES.iter().find(|(v, _)| *v == self).map(|(_, name)| *name).expect("item without a name")
+    }
+
+    /// Returns the hunger points and saturation modifier restored by eating this item
+    pub fn food(self) -> Option<(i32, f32)> {
+        match self {
+            ItemType::Apple => Some((4, 0.3)),
+            ItemType::Bread => Some((5, 0.6)),
+            ItemType::Porkchop => Some((3, 0.3)),
+            ItemType::CookedPorkchop => Some((8, 0.8)),
+            ItemType::Cookie => Some((2, 0.1)),
+            ItemType::Beef => Some((3, 0.3)),
+            ItemType::CookedBeef => Some((8, 0.8)),
+            ItemType::Chicken => Some((2, 0.3)),
+            ItemType::CookedChicken => Some((6, 0.6)),
+            ItemType::Carrot => Some((3, 0.6)),
+            ItemType::Potato => Some((1, 0.3)),
+            ItemType::BakedPotato => Some((5, 0.6)),
+            _ => None
+        }
+    }
+
+    /// Returns the number of uses before the item breaks, None if the item can't be damaged
+    pub fn max_damage(self) -> Option<i16> {
+        match self {
+            ItemType::FlintAndSteel => Some(64),
+            ItemType::IronShovel | ItemType::IronPickaxe | ItemType::IronAxe | ItemType::IronSword => Some(250),
+            ItemType::DiamondShovel | ItemType::DiamondPickaxe | ItemType::DiamondAxe | ItemType::DiamondSword => Some(1561),
+            ItemType::IronHelmet => Some(165),
+            ItemType::IronChestplate => Some(240),
+            ItemType::IronLeggings => Some(225),
+            ItemType::IronBoots => Some(195),
+            ItemType::DiamondHelmet => Some(363),
+            ItemType::DiamondChestplate => Some(528),
+            ItemType::DiamondLeggings => Some(495),
+            ItemType::DiamondBoots => Some(429),
+            _ => None
+        }
+    }
+
+    pub fn is_sword(self) -> bool {
+        matches!(self, ItemType::IronSword | ItemType::DiamondSword)
+    }
+
+    /// Returns true for shovels, pickaxes and axes
+    pub fn is_digging_tool(self) -> bool {
+        matches!(self, ItemType::IronShovel | ItemType::IronPickaxe | ItemType::IronAxe
+            | ItemType::DiamondShovel | ItemType::DiamondPickaxe | ItemType::DiamondAxe)
+    }
+
+    pub fn is_armor(self) -> bool {
+        self.armor_type().is_some()
+    }
+
+    /// Returns the armor slot the item goes in, 0 for helmets up to 3 for boots
+    pub fn armor_type(self) -> Option<usize> {
+        match self {
+            ItemType::IronHelmet | ItemType::DiamondHelmet => Some(0),
+            ItemType::IronChestplate | ItemType::DiamondChestplate => Some(1),
+            ItemType::IronLeggings | ItemType::DiamondLeggings => Some(2),
+            ItemType::IronBoots | ItemType::DiamondBoots => Some(3),
+            _ => None
+        }
+    }
+
+    /// Returns the maximum number of items in one stack
+    pub fn max_stack_size(self) -> i8 {
+        match self {
+            _ if self.max_damage().is_some() => 1,
+            ItemType::WaterBucket | ItemType::LavaBucket | ItemType::WritableBook => 1,
+            ItemType::Bucket | ItemType::Sign | ItemType::WrittenBook => 16,
+            _ => 64
+        }
+    }
+}
+
+/// Returns the id of the item or block with the vanilla name, with or without the `minecraft:` namespace,
+/// or the numeric id of a known item or block. Items are picked over blocks with the same name.
+pub fn id_from_name(name: &str) -> Option<i16> {
+    if let Ok(id) = name.parse::<i16>() {
+        return Some(id).filter(|v| ItemStack::new(*v, 1, 0).is_known());
+    }
+
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    ItemType::from_name(name).map(|v| v as i16)
+        .or_else(|| BlockType::from_name(name).filter(|v| *v != BlockType::Air).map(|v| v as i16))
+}
+
+/// Returns the vanilla name of the item or block with the id, without the `minecraft:` namespace
+pub fn name_from_id(id: i16) -> Option<&'static str> {
+    match ItemType::from_i16(id) {
+        Some(v) => Some(v.name()),
+        None => u8::try_from(id).ok().and_then(BlockType::from_u8).map(BlockType::name)
+    }
+}
+
+/// A stack of items, as stored in a slot
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemStack {
+    pub id: i16,
+    pub count: i8,
+    pub damage: i16,
+    pub nbt: Option<Compound>
+}
+
+impl ItemStack {
+    pub fn new(id: i16, count: i8, damage: i16) -> Self {
+        Self {
+            id,
+            count,
+            damage,
+            nbt: None
+        }
+    }
+
+    pub fn of(item_type: ItemType, count: i8) -> Self {
+        Self::new(item_type as i16, count, 0)
+    }
+
+    /// Returns the type of the item, None for blocks and unknown items
+    pub fn item_type(&self) -> Option<ItemType> {
+        ItemType::from_i16(self.id)
+    }
+
+    /// Returns true if the id is a known item or block
+    pub fn is_known(&self) -> bool {
+        self.item_type().is_some() || u8::try_from(self.id).ok().and_then(BlockType::from_u8).is_some_and(|v| v != BlockType::Air)
+    }
+
+    /// Returns the id of the map if the item is a filled map
+    pub fn map_id(&self) -> Option<i32> {
+        (self.item_type() == Some(ItemType::FilledMap)).then_some(self.damage as i32)
+    }
+
+    /// Returns the maximum number of items in one stack of this item
+    pub fn max_stack_size(&self) -> i8 {
+        self.item_type().map_or(64, ItemType::max_stack_size)
+    }
+
+    /// Returns the item as it's stored in inventories and containers, with its own tag under `tag`
+    pub fn to_nbt(&self) -> Compound {
+        let mut nbt = Compound::new();
+        nbt.insert("id", Tag::Short(self.id));
+        nbt.insert("Count", Tag::Byte(self.count));
+        nbt.insert("Damage", Tag::Short(self.damage));
+        if let Some(tag) = &self.nbt {
+            nbt.insert("tag", Tag::Compound(tag.clone()));
+        }
+
+        nbt
+    }
+
+    /// Reads an item stored by `to_nbt`, the id can also be a name like in vanilla saves.
+    /// Returns None for unknown items and empty stacks.
+    pub fn from_nbt(nbt: &Compound) -> Option<Self> {
+        let number = |tag: &Tag| match tag {
+            Tag::Byte(v) => Some(*v as f64),
+            Tag::Short(v) => Some(*v as f64),
+            Tag::Int(v) => Some(*v as f64),
+            Tag::Long(v) => Some(*v as f64),
+            Tag::Float(v) => Some(*v as f64),
+            Tag::Double(v) => Some(*v),
+            _ => None
+        };
+
+        let id = match nbt.get("id")? {
+            Tag::String(name) => id_from_name(name)?,
+            tag => number(tag)? as i16
+        };
+        let item = Self {
+            id,
+            count: nbt.get("Count").and_then(number).unwrap_or(1.0) as i8,
+            damage: nbt.get("Damage").and_then(number).unwrap_or_default() as i16,
+            nbt: nbt.get("tag").and_then(Tag::as_compound).cloned()
+        };
+        Some(item).filter(|v| v.count > 0 && v.is_known())
+    }
+
+    /// Returns the pages of a book, None if there are too many or they aren't valid
+    pub fn book_pages(&self) -> Option<Vec<String>> {
+        let pages = match self.nbt.as_ref().and_then(|v| v.get("pages")) {
+            Some(v) => v.as_list()?,
+            None => return Some(Vec::new())
+        };
+
+        if pages.len() > MAX_BOOK_PAGES {
+            return None;
+        }
+
+        pages.iter()
+            .map(|page| page.as_str().filter(|v| v.chars().count() <= MAX_BOOK_PAGE_LENGTH).map(str::to_owned))
+            .collect()
+    }
+
+    /// Returns the title of a signed book
+    pub fn book_title(&self) -> Option<&str> {
+        self.nbt.as_ref()?.get("title")?.as_str()
+    }
+
+    /// Replaces the pages of a book
+    pub fn set_book_pages(&mut self, pages: Vec<String>) {
+        let pages = pages.into_iter().map(Tag::String).collect();
+        self.nbt.get_or_insert_with(Compound::new).insert("pages", Tag::List(pages));
+    }
+
+    /// Returns the enchantments in the `ench` list of the item as id and level
+    pub fn enchantments(&self) -> Vec<(i16, i16)> {
+        let list = match self.nbt.as_ref().and_then(|v| v.get("ench")).and_then(Tag::as_list) {
+            Some(v) => v,
+            None => return Vec::new()
+        };
+
+        // Vanilla reads the numbers as shorts whatever their type, data tags in commands are ints by default
+        let number = |tag: &Tag| tag.as_short().or(tag.as_byte().map(i16::from)).or(tag.as_int().map(|v| v as i16));
+        list.iter()
+            .filter_map(Tag::as_compound)
+            .filter_map(|v| Some((number(v.get("id")?)?, number(v.get("lvl")?)?)))
+            .collect()
+    }
+
+    pub fn is_enchanted(&self) -> bool {
+        self.nbt.as_ref().is_some_and(|v| v.get("ench").is_some())
+    }
+
+    /// Adds the enchantment to the item or changes its level, the client draws a glint on enchanted items
+    pub fn set_enchantment(&mut self, id: i16, level: i16) {
+        let mut enchantments = self.enchantments();
+        match enchantments.iter_mut().find(|(v, _)| *v == id) {
+            Some(v) => v.1 = level,
+            None => enchantments.push((id, level))
+        }
+
+        let list = enchantments.into_iter().map(|(id, level)| {
+            let mut entry = Compound::new();
+            entry.insert("id", Tag::Short(id));
+            entry.insert("lvl", Tag::Short(level));
+            Tag::Compound(entry)
+        }).collect();
+        self.nbt.get_or_insert_with(Compound::new).insert("ench", Tag::List(list));
+    }
+
+    /// Returns the name the item was given in an anvil
+    pub fn custom_name(&self) -> Option<&str> {
+        self.nbt.as_ref()?.get("display")?.as_compound()?.get("Name")?.as_str()
+    }
+
+    /// Names the item or removes its name, tags left empty get removed as well
+    pub fn set_custom_name(&mut self, name: Option<String>) {
+        let nbt = self.nbt.get_or_insert_with(Compound::new);
+        if nbt.get("display").and_then(Tag::as_compound).is_none() {
+            nbt.insert("display", Tag::Compound(Compound::new()));
+        }
+
+        let display = nbt.get_mut("display").and_then(Tag::as_compound_mut).unwrap();
+        match name {
+            Some(name) => display.insert("Name", Tag::String(name)),
+            None => {
+                display.remove("Name");
+            }
+        }
+
+        if display.is_empty() {
+            nbt.remove("display");
+        }
+        if nbt.is_empty() {
+            self.nbt = None;
+        }
+    }
+}
+
+/// The slots of the player inventory window, or of a container
+#[derive(Clone, Debug)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    /// Selected hotbar slot, 0 - 8
+    held_slot: usize,
+    /// Slots that changed since the last call to `take_changed`
+    changed: Vec<usize>,
+    /// True if a slot changed since the inventory was last saved
+    unsaved: bool
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::with_size(INVENTORY_SIZE)
+    }
+
+    /// Returns an empty inventory with the given number of slots,
+    /// `add` and the held slot only work for the player inventory
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            slots: vec![None; size],
+            held_slot: 0,
+            changed: Vec::new(),
+            unsaved: false
+        }
+    }
+
+    /// Returns the number of slots
+    pub fn size(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn get_slot(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub fn set_slot(&mut self, slot: usize, item: Option<ItemStack>) {
+        // Empty stacks are removed
+        let item = item.filter(|v| v.count > 0);
+        if self.slots[slot] != item {
+            self.slots[slot] = item;
+            self.unsaved = true;
+            if !self.changed.contains(&slot) {
+                self.changed.push(slot);
+            }
+        }
+    }
+
+    /// Returns the index of the selected hotbar slot in the inventory window
+    pub fn held_slot(&self) -> usize {
+        HOTBAR_START + self.held_slot
+    }
+
+    pub fn set_held_slot(&mut self, hotbar_slot: usize) {
+        debug_assert!(hotbar_slot < HOTBAR_SIZE);
+        self.held_slot = hotbar_slot;
+    }
+
+    pub fn held_item(&self) -> Option<&ItemStack> {
+        self.get_slot(self.held_slot())
+    }
+
+    pub fn set_held_item(&mut self, item: Option<ItemStack>) {
+        self.set_slot(self.held_slot(), item);
+    }
+
+    /// Adds the items to the stacks of the same item, then to the empty slots, hotbar first.
+    /// Stacks don't grow past the maximum stack size of the item, returns the items that didn't fit.
+    pub fn add(&mut self, mut item: ItemStack) -> Option<ItemStack> {
+        let max = item.max_stack_size();
+        let order = || (HOTBAR_START..INVENTORY_SIZE).chain(MAIN_START..HOTBAR_START);
+        for slot in order() {
+            match &self.slots[slot] {
+                Some(v) if v.id == item.id && v.damage == item.damage && v.nbt == item.nbt && v.count < max => {
+                    let added = item.count.min(max - v.count);
+                    let mut stack = v.clone();
+                    stack.count += added;
+                    self.set_slot(slot, Some(stack));
+                    item.count -= added;
+                }
+                _ => ()
+            }
+
+            if item.count <= 0 {
+                return None;
+            }
+        }
+
+        for slot in order() {
+            if self.slots[slot].is_none() {
+                let added = item.count.min(max);
+                self.set_slot(slot, Some(ItemStack { count: added, ..item.clone() }));
+                item.count -= added;
+            }
+
+            if item.count <= 0 {
+                return None;
+            }
+        }
+
+        Some(item)
+    }
+
+    /// Empties every slot and returns the items that were in them
+    pub fn take_all(&mut self) -> Vec<ItemStack> {
+        let mut items = Vec::new();
+        for slot in 0..self.slots.len() {
+            if let Some(item) = self.slots[slot].clone() {
+                self.set_slot(slot, None);
+                items.push(item);
+            }
+        }
+
+        items
+    }
+
+    /// Empties the slots with the item id, or every slot if there's no id, and returns the number of items removed
+    pub fn clear(&mut self, id: Option<i16>) -> i32 {
+        let mut removed = 0;
+        for slot in 0..self.slots.len() {
+            match &self.slots[slot] {
+                Some(item) if id.is_none() || id == Some(item.id) => {
+                    removed += item.count as i32;
+                    self.set_slot(slot, None);
+                }
+                _ => ()
+            }
+        }
+
+        removed
+    }
+
+    /// Returns the slots that changed since the last call and clears the list
+    pub fn take_changed(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Returns true if a slot changed since `mark_saved` was last called
+    pub fn is_unsaved(&self) -> bool {
+        self.unsaved
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.unsaved = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(id_from_name("minecraft:stone"), Some(BlockType::Stone as i16));
+        assert_eq!(id_from_name("stone"), Some(BlockType::Stone as i16));
+        assert_eq!(id_from_name("minecraft:baked_potato"), Some(ItemType::BakedPotato as i16));
+        assert_eq!(id_from_name("lava_bucket"), Some(ItemType::LavaBucket as i16));
+        assert_eq!(id_from_name("259"), Some(ItemType::FlintAndSteel as i16));
+        assert_eq!(id_from_name("air"), None);
+        assert_eq!(id_from_name("0"), None);
+        assert_eq!(id_from_name("cheese"), None);
+        assert_eq!(id_from_name("stone:minecraft"), None);
+
+        assert_eq!(name_from_id(ItemType::ItemFrame as i16), Some("item_frame"));
+        assert_eq!(name_from_id(BlockType::RedstoneTorchOff as i16), Some("unlit_redstone_torch"));
+        assert_eq!(name_from_id(-1), None);
+    }
+
+    #[test]
+    fn clear() {
+        let mut inventory = Inventory::new();
+        inventory.add(ItemStack::of(ItemType::Apple, 5));
+        inventory.add(ItemStack::of(ItemType::Bread, 2));
+        inventory.set_slot(MAIN_START, Some(ItemStack::of(ItemType::Apple, 64)));
+        inventory.take_changed();
+
+        assert_eq!(inventory.clear(Some(ItemType::Apple as i16)), 69);
+        assert_eq!(inventory.take_changed(), [MAIN_START, HOTBAR_START]);
+        assert_eq!(inventory.clear(Some(ItemType::Apple as i16)), 0);
+        assert_eq!(inventory.clear(None), 2);
+        assert!((0..INVENTORY_SIZE).all(|slot| inventory.get_slot(slot).is_none()));
+    }
+
+    #[test]
+    fn add_respects_max_stack_size() {
+        let mut inventory = Inventory::new();
+        assert_eq!(inventory.add(ItemStack::of(ItemType::Apple, 64)), None);
+        assert_eq!(inventory.add(ItemStack::of(ItemType::Apple, 64)), None);
+        assert_eq!(inventory.get_slot(HOTBAR_START), Some(&ItemStack::of(ItemType::Apple, 64)));
+        assert_eq!(inventory.get_slot(HOTBAR_START + 1), Some(&ItemStack::of(ItemType::Apple, 64)));
+
+        // Fills up the existing stack before taking an empty slot
+        inventory.set_slot(HOTBAR_START, Some(ItemStack::of(ItemType::Apple, 60)));
+        assert_eq!(inventory.add(ItemStack::of(ItemType::Apple, 10)), None);
+        assert_eq!(inventory.get_slot(HOTBAR_START).unwrap().count, 64);
+        assert_eq!(inventory.get_slot(HOTBAR_START + 2), Some(&ItemStack::of(ItemType::Apple, 6)));
+
+        // Buckets don't stack
+        assert_eq!(inventory.add(ItemStack::of(ItemType::WaterBucket, 1)), None);
+        assert_eq!(inventory.add(ItemStack::of(ItemType::WaterBucket, 1)), None);
+        assert_eq!(inventory.get_slot(HOTBAR_START + 4).unwrap().count, 1);
+
+        // What doesn't fit is given back
+        let mut full = Inventory::new();
+        for slot in MAIN_START..INVENTORY_SIZE {
+            full.set_slot(slot, Some(ItemStack::of(ItemType::Apple, 63)));
+        }
+        let rest = full.add(ItemStack::of(ItemType::Apple, 64)).unwrap();
+        assert_eq!(rest.count, 64 - (INVENTORY_SIZE - MAIN_START) as i8);
+    }
+
+    #[test]
+    fn every_type_has_a_name() {
+        for id in 0..=i16::MAX {
+            if let Some(item_type) = ItemType::from_i16(id) {
+                assert_eq!(ItemType::from_name(item_type.name()), Some(item_type));
+            }
+            if let Some(block_type) = u8::try_from(id).ok().and_then(BlockType::from_u8) {
+                assert_eq!(BlockType::from_name(block_type.name()), Some(block_type));
+            }
+        }
+    }
+
+    #[test]
+    fn nbt_round_trip() {
+        let mut display = Compound::new();
+        display.insert("Name", Tag::String("Sword of Steve".to_owned()));
+        let mut sharpness = Compound::new();
+        sharpness.insert("id", Tag::Short(16));
+        sharpness.insert("lvl", Tag::Short(5));
+        let mut tag = Compound::new();
+        tag.insert("display", Tag::Compound(display));
+        tag.insert("ench", Tag::List(vec![Tag::Compound(sharpness)]));
+        let sword = ItemStack { nbt: Some(tag), ..ItemStack::new(ItemType::DiamondSword as i16, 1, 12) };
+
+        let nbt = sword.to_nbt();
+        assert_eq!(nbt.get("id"), Some(&Tag::Short(ItemType::DiamondSword as i16)));
+        assert_eq!(nbt.get("Count"), Some(&Tag::Byte(1)));
+        assert_eq!(nbt.get("Damage"), Some(&Tag::Short(12)));
+        assert_eq!(ItemStack::from_nbt(&nbt), Some(sword));
+
+        // Vanilla saves the id as a name, and leaves out what's default
+        let mut named = Compound::new();
+        named.insert("id", Tag::String("minecraft:bread".to_owned()));
+        assert_eq!(ItemStack::from_nbt(&named), Some(ItemStack::of(ItemType::Bread, 1)));
+        named.insert("Count", Tag::Byte(0));
+        assert_eq!(ItemStack::from_nbt(&named), None);
+        assert_eq!(ItemStack::from_nbt(&ItemStack::new(4000, 1, 0).to_nbt()), None);
+    }
+
+    #[test]
+    fn enchantments() {
+        let mut sword = ItemStack::of(ItemType::DiamondSword, 1);
+        assert!(!sword.is_enchanted());
+        assert!(sword.enchantments().is_empty());
+
+        // Sharpness V
+        sword.set_enchantment(16, 5);
+        assert!(sword.is_enchanted());
+        assert_eq!(sword.enchantments(), [(16, 5)]);
+        let list = sword.nbt.as_ref().unwrap().get("ench").and_then(Tag::as_list).unwrap();
+        let entry = list[0].as_compound().unwrap();
+        assert_eq!((entry.get("id"), entry.get("lvl")), (Some(&Tag::Short(16)), Some(&Tag::Short(5))));
+
+        // Setting it again changes the level instead of adding it twice
+        sword.set_enchantment(34, 3);
+        sword.set_enchantment(16, 2);
+        assert_eq!(sword.enchantments(), [(16, 2), (34, 3)]);
+
+        // Data tags from commands use ints
+        let mut entry = Compound::new();
+        entry.insert("id", Tag::Int(16));
+        entry.insert("lvl", Tag::Int(5));
+        let mut tag = Compound::new();
+        tag.insert("ench", Tag::List(vec![Tag::Compound(entry)]));
+        let given = ItemStack { nbt: Some(tag), ..ItemStack::of(ItemType::DiamondSword, 1) };
+        assert_eq!(given.enchantments(), [(16, 5)]);
+    }
+}
@@ -0,0 +1,48 @@
+//! Reasons players get kicked for, worded like vanilla 1.8.
+//! They're sent as plain text, as the 1.7 and 1.8 clients don't know the translation keys later versions use for them.
+
+use serde_json::Value;
+
+use crate::chat;
+
+pub const SERVER_FULL: &str = "The server is full!";
+pub const IDLING: &str = "You have been idle for too long!";
+pub const DUPLICATE_LOGIN: &str = "You logged in from another location";
+pub const NOT_WHITELISTED: &str = "You are not white-listed on this server!";
+pub const BANNED: &str = "You are banned from this server!";
+pub const TIMED_OUT: &str = "Timed out";
+
+/// Returns the component of a reason without arguments
+pub fn reason(reason: &str) -> Value {
+    chat::text(reason)
+}
+
+/// The client uses an older protocol than the server, `version` is the version the server runs
+pub fn outdated_client(version: &str) -> Value {
+    chat::text(&format!("Outdated client! Please use {}", version))
+}
+
+/// The client uses a newer protocol than the server, `version` is the version the server runs
+pub fn outdated_server(version: &str) -> Value {
+    chat::text(&format!("Outdated server! I'm still on {}", version))
+}
+
+pub fn banned(reason: Option<&str>) -> Value {
+    match reason {
+        Some(reason) => chat::text(&format!("{}\nReason: {}", BANNED, reason)),
+        None => self::reason(BANNED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn banned_with_reason() {
+        assert_eq!(banned(Some("Griefing")), json!({ "text": "You are banned from this server!\nReason: Griefing" }));
+        assert_eq!(banned(None), json!({ "text": "You are banned from this server!" }));
+    }
+}
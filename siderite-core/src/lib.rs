@@ -1,15 +1,42 @@
+pub mod anvil;
 pub mod auth;
+pub mod beds;
+pub mod biomes;
+pub mod block_ticks;
 pub mod blocks;
+pub mod boss_bar;
+pub mod chat;
+pub mod commands;
 pub mod coord;
+pub mod crafting;
+pub mod enchanting;
 pub mod entities;
+pub mod events;
+pub mod explosion;
+pub mod furnace;
+pub mod items;
+pub mod kick;
+pub mod map;
+pub mod redstone;
 pub mod server;
+pub mod skins;
+pub mod stats;
 pub mod storage;
+pub mod teams;
+pub mod throttle;
+pub mod tile_entities;
+pub mod weather;
+pub mod window;
 
 mod client;
 mod protocol;
+#[cfg(test)]
+mod test_utils;
 
 use std::time::Duration;
 
+pub use protocol::v47::write_block_info;
+
 /// Number of ticks per second
 pub const TPS: i32 = 20;
 
@@ -0,0 +1,469 @@
+//! Filled maps show the top blocks of the world around their center, drawn while a player holds them.
+//! Every map is saved in its own `map_<id>.dat` file like vanilla does.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, ErrorKind};
+use std::path::Path;
+
+use num_traits::FromPrimitive;
+use siderite_nbt::{Compound, Tag};
+
+use crate::blocks::BlockType;
+use crate::coord::{ChunkCoord, Coord};
+use crate::storage::chunk::Chunk;
+use crate::storage::chunk::chunk_map::ChunkMap;
+use crate::storage::world::{Dimension, World};
+
+/// Width and height of a map in pixels
+pub const MAP_SIZE: usize = 128;
+
+/// Largest scale of a map, a pixel covers 2^scale blocks
+pub const MAX_SCALE: u8 = 4;
+
+/// Map colors of the blocks, each one comes in four shades
+const AIR: u8 = 0;
+const GRASS: u8 = 1;
+const SAND: u8 = 2;
+const CLOTH: u8 = 3;
+const TNT: u8 = 4;
+const ICE: u8 = 5;
+const IRON: u8 = 6;
+const FOLIAGE: u8 = 7;
+const SNOW: u8 = 8;
+const DIRT: u8 = 10;
+const STONE: u8 = 11;
+const WATER: u8 = 12;
+const WOOD: u8 = 13;
+const RED: u8 = 28;
+const BLACK: u8 = 29;
+
+/// Icon drawn on top of a map
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MapIcon {
+    /// 0 - 15, rotation in 1/16th of a full turn
+    pub direction: u8,
+    /// 0 - 15, 0 is a white player arrow
+    pub icon_type: u8,
+    /// Position relative to the center of the map, in half pixels
+    pub x: i8,
+    pub z: i8
+}
+
+/// Rectangular area of a map's colors
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapUpdate {
+    pub columns: u8,
+    pub rows: u8,
+    pub x: u8,
+    pub z: u8,
+    /// Colors of the area, row by row
+    pub data: Vec<u8>
+}
+
+/// The contents of a filled map
+pub struct MapState {
+    scale: u8,
+    dimension: Dimension,
+    x_center: i32,
+    z_center: i32,
+    colors: Box<[u8; MAP_SIZE * MAP_SIZE]>,
+    icons: Vec<MapIcon>,
+    /// Min x, min z, max x, max z (inclusive) of the changed pixels
+    dirty: Option<(usize, usize, usize, usize)>
+}
+
+impl MapState {
+    pub fn new(scale: u8, dimension: Dimension, x_center: i32, z_center: i32) -> Self {
+        Self {
+            scale,
+            dimension,
+            x_center,
+            z_center,
+            colors: Box::new([0u8; MAP_SIZE * MAP_SIZE]),
+            icons: Vec::new(),
+            dirty: None
+        }
+    }
+
+    /// Creates an empty map of the area the position is in, like vanilla the areas of the maps line up in a grid
+    pub fn centered_on(scale: u8, dimension: Dimension, x: i32, z: i32) -> Self {
+        let size = (MAP_SIZE as i32) << scale;
+        let center = |v: i32| (v + 64).div_euclid(size) * size + size / 2 - 64;
+        Self::new(scale, dimension, center(x), center(z))
+    }
+
+    /// Reads the map from the data compound of a map file, None if it isn't a valid map
+    pub fn from_nbt(data: &Compound) -> Option<Self> {
+        let scale = data.get("scale").and_then(Tag::as_byte).and_then(|v| u8::try_from(v).ok()).filter(|v| *v <= MAX_SCALE)?;
+        let dimension = data.get("dimension").and_then(Tag::as_byte).and_then(Dimension::from_i8)?;
+        let x_center = data.get("xCenter").and_then(Tag::as_int)?;
+        let z_center = data.get("zCenter").and_then(Tag::as_int)?;
+        let mut map = Self::new(scale, dimension, x_center, z_center);
+        // Vanilla only writes 128 by 128 maps
+        let colors = data.get("colors").and_then(Tag::as_byte_array).filter(|v| v.len() == MAP_SIZE * MAP_SIZE)?;
+        for (color, v) in map.colors.iter_mut().zip(colors) {
+            *color = *v as u8;
+        }
+
+        Some(map)
+    }
+
+    pub fn to_nbt(&self) -> Compound {
+        let mut data = Compound::new();
+        data.insert("scale", Tag::Byte(self.scale as i8));
+        data.insert("dimension", Tag::Byte(self.dimension as i8));
+        data.insert("width", Tag::Short(MAP_SIZE as i16));
+        data.insert("height", Tag::Short(MAP_SIZE as i16));
+        data.insert("xCenter", Tag::Int(self.x_center));
+        data.insert("zCenter", Tag::Int(self.z_center));
+        data.insert("colors", Tag::ByteArray(self.colors.iter().map(|v| *v as i8).collect()));
+        data
+    }
+
+    /// Loads the map from its file, None if the map was never saved
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+
+        let (_, root) = siderite_nbt::read_gzip(BufReader::new(file))?;
+        match root.as_compound().and_then(|v| v.get("data")).and_then(Tag::as_compound).and_then(Self::from_nbt) {
+            Some(map) => Ok(Some(map)),
+            None => Err(io::Error::new(ErrorKind::InvalidData, "invalid map data"))
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut root = Compound::new();
+        root.insert("data", Tag::Compound(self.to_nbt()));
+        siderite_nbt::write_gzip(BufWriter::new(File::create(path)?), "", &Tag::Compound(root))
+    }
+
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        (self.x_center, self.z_center)
+    }
+
+    pub fn icons(&self) -> &[MapIcon] {
+        &self.icons
+    }
+
+    pub fn set_icons(&mut self, icons: Vec<MapIcon>) {
+        self.icons = icons;
+    }
+
+    pub fn get_color(&self, x: usize, z: usize) -> u8 {
+        self.colors[x + z * MAP_SIZE]
+    }
+
+    pub fn set_color(&mut self, x: usize, z: usize, color: u8) {
+        let index = x + z * MAP_SIZE;
+        if self.colors[index] == color {
+            return;
+        }
+
+        self.colors[index] = color;
+        self.dirty = Some(match self.dirty {
+            Some((min_x, min_z, max_x, max_z)) => (min_x.min(x), min_z.min(z), max_x.max(x), max_z.max(z)),
+            None => (x, z, x, z)
+        });
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// Returns the area that changed since the last call and marks the map as clean
+    pub fn take_update(&mut self) -> Option<MapUpdate> {
+        let (min_x, min_z, max_x, max_z) = self.dirty.take()?;
+        Some(self.area(min_x, min_z, max_x - min_x + 1, max_z - min_z + 1))
+    }
+
+    /// Returns the whole map
+    pub fn full_update(&self) -> MapUpdate {
+        self.area(0, 0, MAP_SIZE, MAP_SIZE)
+    }
+
+    fn area(&self, x: usize, z: usize, columns: usize, rows: usize) -> MapUpdate {
+        let mut data = Vec::with_capacity(columns * rows);
+        for row in z..(z + rows) {
+            data.extend_from_slice(&self.colors[(x + row * MAP_SIZE)..(x + columns + row * MAP_SIZE)]);
+        }
+
+        MapUpdate {
+            // A full map has 128 columns, which still fits in an unsigned byte
+            columns: columns as u8,
+            rows: rows as u8,
+            x: x as u8,
+            z: z as u8,
+            data
+        }
+    }
+}
+
+/// The top block of a column of the world as drawn on a map
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Column {
+    color: u8,
+    height: i32,
+    /// Number of water blocks from the surface down
+    depth: i32
+}
+
+impl Column {
+    /// Reads the column from the chunk it's in, None if the chunk isn't loaded
+    fn read(chunk_map: &ChunkMap, x: i32, z: i32) -> Option<Self> {
+        let coord = ChunkCoord::from_block_pos(Coord::new(x, 0, z));
+        let block = |chunk: &Chunk, y: i32| chunk.data.get_block_type_meta(Chunk::abs_to_rel(Coord::new(x, y, z), coord)).0;
+
+        let mut column = None;
+        chunk_map.do_with_chunk(coord, |chunk| {
+            // Start at the highest section with blocks in it
+            let mut y = 16 * (16 - chunk.data.get_primary_bit_mask().leading_zeros() as i32) - 1;
+            while y > 0 && block_color(block(chunk, y)) == AIR {
+                y -= 1;
+            }
+
+            let mut depth = 0;
+            while y - depth >= 0 && matches!(block(chunk, y - depth), BlockType::Water | BlockType::FlowingWater) {
+                depth += 1;
+            }
+
+            column = Some(Self { color: block_color(block(chunk, y.max(0))), height: y, depth });
+        });
+
+        column
+    }
+
+    /// Returns the color of the pixel, shaded by how much higher the column is than the one to the north like in vanilla.
+    /// Water gets darker the deeper it is instead.
+    fn shade(&self, north: Option<&Column>, pixel_x: i32, pixel_z: i32, scale: u8) -> u8 {
+        let checker = ((pixel_x + pixel_z) & 1) as f64;
+        let shade = if self.color == WATER {
+            let depth = self.depth as f64 * 0.1 + checker * 0.2;
+            if depth < 0.5 { 2 } else if depth > 0.9 { 0 } else { 1 }
+        } else {
+            let north = north.map_or(self.height, |v| v.height);
+            let slope = (self.height - north) as f64 * 4.0 / ((1 << scale) + 4) as f64 + (checker - 0.5) * 0.4;
+            if slope > 0.6 { 2 } else if slope < -0.6 { 0 } else { 1 }
+        };
+
+        self.color * 4 + shade
+    }
+}
+
+/// Returns the map color of the block, the blocks maps see through are air
+pub fn block_color(block_type: BlockType) -> u8 {
+    match block_type {
+        BlockType::Air | BlockType::Glass | BlockType::Torch | BlockType::Fire | BlockType::RedstoneWire | BlockType::Lever
+            | BlockType::RedstoneTorchOff | BlockType::RedstoneTorchOn | BlockType::StoneButton => AIR,
+        BlockType::Grass | BlockType::SlimeBlock => GRASS,
+        BlockType::Sand | BlockType::Sandstone | BlockType::Glowstone => SAND,
+        BlockType::Bed => CLOTH,
+        BlockType::Tnt | BlockType::FlowingLava | BlockType::Lava => TNT,
+        BlockType::Ice => ICE,
+        BlockType::IronDoor | BlockType::Anvil => IRON,
+        BlockType::Leaves | BlockType::Wheat | BlockType::Carrots | BlockType::Potatoes => FOLIAGE,
+        BlockType::SnowLayer => SNOW,
+        BlockType::Dirt => DIRT,
+        BlockType::Stone | BlockType::CobbleStone | BlockType::Bedrock | BlockType::Gravel | BlockType::GoldOre | BlockType::IronOre
+            | BlockType::CoalOre | BlockType::DiamondOre | BlockType::RedstoneOre | BlockType::Furnace | BlockType::LitFurnace
+            | BlockType::EnderChest => STONE,
+        BlockType::FlowingWater | BlockType::Water => WATER,
+        BlockType::Log | BlockType::Planks | BlockType::CraftingTable | BlockType::Bookshelf | BlockType::Chest | BlockType::StandingSign | BlockType::WallSign
+            | BlockType::WoodenDoor => WOOD,
+        BlockType::EnchantingTable => RED,
+        BlockType::Obsidian => BLACK
+    }
+}
+
+/// Draws the top blocks of the loaded chunks around the position onto the map, like vanilla in a circle around the holder.
+/// A pixel shows the block at its north west corner, vanilla picks the most common color of the blocks it covers.
+pub fn render(map: &mut MapState, world: &World, pos: Coord<f64>) {
+    if map.dimension != world.dimension() {
+        return;
+    }
+
+    let chunk_map = world.chunk_map();
+    let block_size = 1 << map.scale;
+    let half = MAP_SIZE as i32 / 2;
+    let holder_x = (pos.x.floor() as i32 - map.x_center).div_euclid(block_size) + half;
+    let holder_z = (pos.z.floor() as i32 - map.z_center).div_euclid(block_size) + half;
+    let radius = MAP_SIZE as i32 / block_size - 2;
+
+    for x in 0..MAP_SIZE as i32 {
+        let block_x = map.x_center + (x - half) * block_size;
+        let mut north = None;
+        for z in 0..MAP_SIZE as i32 {
+            let (dx, dz) = (x - holder_x, z - holder_z);
+            if dx * dx + dz * dz >= radius * radius {
+                north = None;
+                continue;
+            }
+
+            let block_z = map.z_center + (z - half) * block_size;
+            if north.is_none() {
+                north = Column::read(&chunk_map, block_x, block_z - block_size);
+            }
+
+            let column = Column::read(&chunk_map, block_x, block_z);
+            if let Some(column) = &column {
+                map.set_color(x as usize, z as usize, column.shade(north.as_ref(), x, z, map.scale));
+            }
+            north = column;
+        }
+    }
+}
+
+/// Loads the id of the last created map from `idcounts.dat`, None if no map was created yet
+pub fn load_last_id(path: &Path) -> io::Result<Option<i32>> {
+    let file = match File::open(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e)
+    };
+
+    let (_, root) = siderite_nbt::read(BufReader::new(file))?;
+    Ok(root.as_compound().and_then(|v| v.get("map")).and_then(Tag::as_short).map(i32::from))
+}
+
+/// Saves the id of the last created map, unlike the maps themselves the file isn't compressed
+pub fn save_last_id(path: &Path, id: i32) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut root = Compound::new();
+    root.insert("map", Tag::Short(id as i16));
+    siderite_nbt::write(BufWriter::new(File::create(path)?), "", &Tag::Compound(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::storage::chunk::generator::GeneratorType;
+    use crate::storage::world::{Difficulty, WorldConfig};
+
+    #[test]
+    fn take_update_covers_changed_area() {
+        let mut map = MapState::new(0, Dimension::Overworld, 0, 0);
+        assert!(map.take_update().is_none());
+
+        map.set_color(5, 10, 1);
+        map.set_color(7, 12, 2);
+        let update = map.take_update().unwrap();
+        assert_eq!((update.columns, update.rows, update.x, update.z), (3, 3, 5, 10));
+        assert_eq!(update.data, [1, 0, 0, 0, 0, 0, 0, 0, 2]);
+        assert!(!map.is_dirty());
+    }
+    #[test]
+    fn full_update() {
+        let mut map = MapState::new(0, Dimension::Overworld, 0, 0);
+        map.set_color(127, 127, 5);
+        let update = map.full_update();
+        assert_eq!((update.columns, update.rows, update.x, update.z), (128, 128, 0, 0));
+        assert_eq!(update.data.len(), MAP_SIZE * MAP_SIZE);
+        assert_eq!(update.data[MAP_SIZE * MAP_SIZE - 1], 5);
+    }
+
+    #[test]
+    fn centers_line_up() {
+        assert_eq!(MapState::centered_on(0, Dimension::Overworld, 10, -10).center(), (0, 0));
+        assert_eq!(MapState::centered_on(0, Dimension::Overworld, 63, -65).center(), (0, -128));
+        assert_eq!(MapState::centered_on(0, Dimension::Overworld, 64, 200).center(), (128, 256));
+        assert_eq!(MapState::centered_on(1, Dimension::Overworld, 100, 0).center(), (64, 64));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(block_color(BlockType::Grass), 1);
+        assert_eq!(block_color(BlockType::Stone), 11);
+        assert_eq!(block_color(BlockType::Water), 12);
+        assert_eq!(block_color(BlockType::Log), 13);
+        assert_eq!(block_color(BlockType::Glass), 0);
+
+        // Reference pixels from vanilla maps: flat ground uses the second shade, slopes up the brightest and slopes down the darkest
+        let column = |color, height, depth| Column { color, height, depth };
+        let grass = column(GRASS, 64, 0);
+        for (x, z) in [(0, 0), (0, 1)] {
+            assert_eq!(grass.shade(Some(&column(GRASS, 64, 0)), x, z, 0), 5);
+            assert_eq!(grass.shade(Some(&column(STONE, 62, 0)), x, z, 0), 6);
+            assert_eq!(grass.shade(Some(&column(STONE, 66, 0)), x, z, 0), 4);
+        }
+        assert_eq!(column(STONE, 70, 0).shade(None, 3, 4, 0), 45);
+        // A slope of two blocks is too gentle to shade on the most zoomed out maps
+        assert_eq!(grass.shade(Some(&column(STONE, 62, 0)), 0, 0, MAX_SCALE), 5);
+
+        // Shallow water is bright and deep water dark, the shade in between alternates
+        assert_eq!(column(WATER, 62, 1).shade(None, 0, 0, 0), 50);
+        assert_eq!(column(WATER, 62, 5).shade(None, 0, 0, 0), 49);
+        assert_eq!(column(WATER, 62, 5).shade(None, 0, 1, 0), 49);
+        assert_eq!(column(WATER, 62, 4).shade(None, 0, 0, 0), 50);
+        assert_eq!(column(WATER, 62, 4).shade(None, 0, 1, 0), 49);
+        assert_eq!(column(WATER, 62, 10).shade(None, 0, 0, 0), 48);
+    }
+
+    #[test]
+    fn render_loaded_chunks() {
+        let world = World::new(WorldConfig {
+            name: "world".to_owned(),
+            dimension: Dimension::Overworld,
+            spawn_pos: Coord::new(0, 65, 0),
+            spawn_chunks_radius: 0,
+            difficulty: Difficulty::Easy,
+            generator: GeneratorType::Flat,
+            seed: 0
+        });
+        world.chunk_map().touch_chunk(ChunkCoord { x: 0, z: 0 });
+        world.set_block(Coord::new(3, 16, 3), BlockType::Stone, 0);
+        world.set_block(Coord::new(3, 17, 3), BlockType::Stone, 0);
+        world.set_block(Coord::new(5, 15, 5), BlockType::Water, 0);
+
+        let mut map = MapState::new(0, Dimension::Overworld, 64, 64);
+        render(&mut map, &world, Coord::new(8.0, 16.0, 8.0));
+        // The flat world is dirt up to y 15, the stone sticks out of it
+        assert_eq!(map.get_color(0, 0), DIRT * 4 + 1);
+        assert_eq!(map.get_color(3, 3), STONE * 4 + 2);
+        assert_eq!(map.get_color(3, 4), DIRT * 4);
+        assert_eq!(map.get_color(5, 5), WATER * 4 + 2);
+        // Nothing is drawn outside of the loaded chunk
+        assert_eq!(map.get_color(16, 0), 0);
+        assert_eq!(map.get_color(0, 16), 0);
+
+        let mut other = MapState::new(0, Dimension::Nether, 64, 64);
+        render(&mut other, &world, Coord::new(8.0, 16.0, 8.0));
+        assert!(!other.is_dirty());
+    }
+
+    #[test]
+    fn save_load() {
+        let dir = env::temp_dir().join(format!("siderite-maps-{}", std::process::id()));
+        let mut map = MapState::new(2, Dimension::Nether, 512, -1024);
+        map.set_color(7, 9, 200);
+        map.save(&dir.join("map_3.dat")).unwrap();
+        save_last_id(&dir.join("idcounts.dat"), 3).unwrap();
+
+        let loaded = MapState::load(&dir.join("map_3.dat")).unwrap().unwrap();
+        assert_eq!(load_last_id(&dir.join("idcounts.dat")).unwrap(), Some(3));
+        assert!(MapState::load(&dir.join("map_4.dat")).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((loaded.scale(), loaded.dimension(), loaded.center()), (2, Dimension::Nether, (512, -1024)));
+        assert_eq!(loaded.get_color(7, 9), 200);
+        assert_eq!(loaded.to_nbt(), map.to_nbt());
+    }
+}
@@ -0,0 +1,74 @@
+use std::{error, fmt, io, result};
+use std::io::ErrorKind;
+
+pub type Result<T> = result::Result<T, ProtocolError>;
+
+/// Why handling a connection failed
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// Reading from or writing to the connection failed
+    Io(io::Error),
+    /// The client sent a packet that couldn't be read
+    Parse(String),
+    /// The client sent a packet it isn't allowed to send, or values that are out of range
+    Protocol(String),
+    /// The client sent a packet the server doesn't handle in the current state,
+    /// named if the id belongs to a packet of that state
+    UnknownPacket { id: i32, name: Option<String> },
+    /// The client closed the connection
+    Disconnected
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Io(e) => write!(f, "IO error: {}", e),
+            ProtocolError::Parse(msg) => write!(f, "Invalid packet: {}", msg),
+            ProtocolError::Protocol(msg) => write!(f, "Protocol violation: {}", msg),
+            ProtocolError::UnknownPacket { id, name: Some(name) } => write!(f, "Protocol violation: unsupported packet {} ({:#04X})", name, id),
+            ProtocolError::UnknownPacket { id, name: None } => write!(f, "Protocol violation: unknown packet {:#04X}", id),
+            ProtocolError::Disconnected => f.write_str("Client disconnected")
+        }
+    }
+}
+
+impl error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ProtocolError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// Packets are read from byte slices, so running out of data or reading invalid values
+/// means the packet is malformed rather than that the connection broke
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::UnexpectedEof | ErrorKind::InvalidData | ErrorKind::InvalidInput => ProtocolError::Parse(e.to_string()),
+            kind if is_disconnection_error(kind) => ProtocolError::Disconnected,
+            _ => ProtocolError::Io(e)
+        }
+    }
+}
+
+pub fn is_disconnection_error(e: ErrorKind) -> bool {
+    e == ErrorKind::NotConnected
+        || e == ErrorKind::ConnectionAborted
+        || e == ErrorKind::ConnectionRefused
+        || e == ErrorKind::ConnectionReset
+        || e == ErrorKind::BrokenPipe
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_error_kinds() {
+        assert!(matches!(ProtocolError::from(io::Error::from(ErrorKind::UnexpectedEof)), ProtocolError::Parse(_)));
+        assert!(matches!(ProtocolError::from(io::Error::from(ErrorKind::ConnectionReset)), ProtocolError::Disconnected));
+        assert!(matches!(ProtocolError::from(io::Error::from(ErrorKind::PermissionDenied)), ProtocolError::Io(_)));
+    }
+}
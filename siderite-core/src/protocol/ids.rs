@@ -0,0 +1,182 @@
+//! Packet ids of protocol version 47 (Minecraft 1.8) per state and direction.
+//! The ids are the discriminants, so the compiler rejects two packets with the same id in one state and direction.
+
+use num_derive::FromPrimitive;
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ServerboundHandshake {
+    Handshake = 0x00
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ServerboundStatus {
+    Request = 0x00,
+    Ping = 0x01
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ClientboundStatus {
+    Response = 0x00,
+    Pong = 0x01
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ServerboundLogin {
+    LoginStart = 0x00,
+    EncryptionResponse = 0x01
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ClientboundLogin {
+    Disconnect = 0x00,
+    EncryptionRequest = 0x01,
+    LoginSuccess = 0x02,
+    SetCompression = 0x03
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ServerboundPlay {
+    KeepAlive = 0x00,
+    ChatMessage = 0x01,
+    UseEntity = 0x02,
+    Player = 0x03,
+    PlayerPosition = 0x04,
+    PlayerLook = 0x05,
+    PlayerPositionAndLook = 0x06,
+    PlayerDigging = 0x07,
+    PlayerBlockPlacement = 0x08,
+    HeldItemChange = 0x09,
+    Animation = 0x0A,
+    EntityAction = 0x0B,
+    SteerVehicle = 0x0C,
+    CloseWindow = 0x0D,
+    ClickWindow = 0x0E,
+    ConfirmTransaction = 0x0F,
+    CreativeInventoryAction = 0x10,
+    EnchantItem = 0x11,
+    UpdateSign = 0x12,
+    PlayerAbilities = 0x13,
+    TabComplete = 0x14,
+    ClientSettings = 0x15,
+    ClientStatus = 0x16,
+    PluginMessage = 0x17,
+    Spectate = 0x18,
+    ResourcePackStatus = 0x19
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ClientboundPlay {
+    KeepAlive = 0x00,
+    JoinGame = 0x01,
+    ChatMessage = 0x02,
+    TimeUpdate = 0x03,
+    EntityEquipment = 0x04,
+    SpawnPosition = 0x05,
+    UpdateHealth = 0x06,
+    Respawn = 0x07,
+    PlayerPositionAndLook = 0x08,
+    HeldItemChange = 0x09,
+    UseBed = 0x0A,
+    Animation = 0x0B,
+    SpawnPlayer = 0x0C,
+    CollectItem = 0x0D,
+    SpawnObject = 0x0E,
+    SpawnMob = 0x0F,
+    SpawnPainting = 0x10,
+    SpawnExperienceOrb = 0x11,
+    EntityVelocity = 0x12,
+    DestroyEntities = 0x13,
+    Entity = 0x14,
+    EntityRelativeMove = 0x15,
+    EntityLook = 0x16,
+    EntityLookAndRelativeMove = 0x17,
+    EntityTeleport = 0x18,
+    EntityHeadLook = 0x19,
+    EntityStatus = 0x1A,
+    AttachEntity = 0x1B,
+    EntityMetadata = 0x1C,
+    EntityEffect = 0x1D,
+    RemoveEntityEffect = 0x1E,
+    SetExperience = 0x1F,
+    EntityProperties = 0x20,
+    ChunkData = 0x21,
+    MultiBlockChange = 0x22,
+    BlockChange = 0x23,
+    BlockAction = 0x24,
+    BlockBreakAnimation = 0x25,
+    MapChunkBulk = 0x26,
+    Explosion = 0x27,
+    Effect = 0x28,
+    SoundEffect = 0x29,
+    Particle = 0x2A,
+    ChangeGameState = 0x2B,
+    SpawnGlobalEntity = 0x2C,
+    OpenWindow = 0x2D,
+    CloseWindow = 0x2E,
+    SetSlot = 0x2F,
+    WindowItems = 0x30,
+    WindowProperty = 0x31,
+    ConfirmTransaction = 0x32,
+    UpdateSign = 0x33,
+    Map = 0x34,
+    UpdateBlockEntity = 0x35,
+    OpenSignEditor = 0x36,
+    Statistics = 0x37,
+    PlayerListItem = 0x38,
+    PlayerAbilities = 0x39,
+    TabComplete = 0x3A,
+    ScoreboardObjective = 0x3B,
+    UpdateScore = 0x3C,
+    DisplayScoreboard = 0x3D,
+    Teams = 0x3E,
+    PluginMessage = 0x3F,
+    Disconnect = 0x40,
+    ServerDifficulty = 0x41,
+    CombatEvent = 0x42,
+    Camera = 0x43,
+    WorldBorder = 0x44,
+    Title = 0x45,
+    SetCompression = 0x46,
+    PlayerListHeaderAndFooter = 0x47,
+    ResourcePackSend = 0x48,
+    UpdateEntityNbt = 0x49
+}
+
+#[cfg(test)]
+mod tests {
+    use num_traits::FromPrimitive;
+
+    use super::*;
+
+    /// Counts the ids below 0x100 that resolve to a packet, checking each resolves to the packet with that id
+    macro_rules! count_ids {
+        ($t:ty) => {
+            (0..0x100).filter(|&id| match <$t>::from_i32(id) {
+                Some(packet) => {
+                    assert_eq!(packet as i32, id);
+                    true
+                }
+                None => false
+            }).count()
+        };
+    }
+
+    #[test]
+    fn ids_are_contiguous() {
+        assert_eq!(count_ids!(ServerboundHandshake), 1);
+        assert_eq!(count_ids!(ServerboundStatus), 2);
+        assert_eq!(count_ids!(ClientboundStatus), 2);
+        assert_eq!(count_ids!(ServerboundLogin), 2);
+        assert_eq!(count_ids!(ClientboundLogin), 4);
+        // Ids start at 0 and leave no gaps, together with the unique discriminants every id is one packet
+        assert_eq!(count_ids!(ServerboundPlay), ServerboundPlay::ResourcePackStatus as usize + 1);
+        assert_eq!(count_ids!(ClientboundPlay), ClientboundPlay::UpdateEntityNbt as usize + 1);
+    }
+}
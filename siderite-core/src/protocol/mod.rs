@@ -1,12 +1,18 @@
+pub mod error;
+pub mod ids;
+pub mod out_queue;
 pub mod packets;
 pub mod thread;
-mod v47;
+pub mod v5;
+pub mod v47;
+pub mod version;
 
-use std::io::{ErrorKind, Read, Write, Result};
+use std::io::{self, ErrorKind, Read, Write};
 use std::net::{Shutdown, TcpStream};
 use std::sync::{Arc, RwLock};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant};
 
+use bitflags::bitflags;
 use bytebufrs::RingBuf;
 use crossbeam_channel::Receiver;
 use flate2::Compression;
@@ -20,20 +26,40 @@ use openssl::rsa::Padding;
 use openssl::sha::Sha1;
 use openssl::symm::{Cipher, Crypter, Mode};
 use rand::{thread_rng, Rng};
-use serde_json::json;
+use serde_json::{json, Value};
+use siderite_nbt::Tag;
 
 use crate::auth;
-use crate::blocks::BlockFace;
+use crate::blocks::{BlockFace, BlockType};
+use crate::chat;
 use crate::coord::{ChunkCoord, Coord};
 use crate::client::Client;
-use crate::entities::player::{Abilities, Player, SkinFlags};
+use crate::entities::{Entity, EntityType, Motive};
+use crate::entities::attributes::Attributes;
+use crate::entities::effects::{Effect, EffectType};
+use crate::entities::metadata::{self, MetadataValue};
+use crate::entities::player::{Abilities, GameMode, Player, SkinFlags};
+use crate::items::ItemStack;
+use crate::kick;
+use crate::map::{MapIcon, MapUpdate};
 use crate::server;
 use crate::server::Server;
-use crate::storage::world::{Difficulty, World};
-use crate::storage::chunk::{Chunk, SerializeChunk};
+use crate::storage::game_rules;
+use crate::storage::world::{Difficulty, Dimension, World};
+use crate::storage::chunk::{Chunk, SerializeContext};
 use crate::storage::chunk::chunk_map::ChunkMap;
+use crate::teams::{Team, TeamAction};
+use crate::throttle;
+use crate::tile_entities::TileEntity;
+use crate::window::WindowType;
 
+use self::error::{ProtocolError, Result};
+use self::ids::{ClientboundLogin, ClientboundPlay, ClientboundStatus, ServerboundHandshake, ServerboundLogin, ServerboundPlay, ServerboundStatus};
+
+use self::out_queue::{OutQueue, Priority};
 use self::packets::{Packet, PlayerListAction};
+use self::v47::{PROTOCOL_VERSION, VERSION_NAME};
+use self::version::{JoinGame, ProtocolVersion};
 
 /// The length of the verify token
 const VERIFY_TOKEN_LEN: usize = 4;
@@ -46,6 +72,40 @@ const PADDING: Padding = Padding::PKCS1;
 /// Maximum duration in between keep alive packets from the client
 const KEEP_ALIVE_MAX: Duration = Duration::from_secs(30);
 
+/// Time after entering the play state before the first keep alive, the client is busy with the chunks sent on join
+const KEEP_ALIVE_GRACE: Duration = Duration::from_secs(5);
+
+/// How long a kicked connection gets to accept the queued packets and the reason before it's closed
+const DISCONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Forge clients append this marker to the server address in the handshake
+const FML_MARKER: &str = "\0FML\0";
+
+/// Size the buffer holding the received data that hasn't been handled yet starts at, it grows for larger packets
+const RECEIVE_BUF_LEN: usize = (32 * 1024) - 1;
+
+/// Max length of a packet, the most a 3 byte var int holds like vanilla. Applies to decompressed data as well
+pub const MAX_PACKET_LEN: usize = (1 << 21) - 1;
+
+/// Max number of bytes read from a connection per tick,
+/// so a client flooding data can't starve the others sharing the protocol thread
+const READ_BUDGET: usize = 8 * 1024;
+
+/// Max number of packets handled per connection per tick, the rest stay buffered for the next tick
+const PACKET_BUDGET: usize = 64;
+
+/// Max speed of entities sent to clients, in blocks per tick
+const MAX_VELOCITY: f64 = 3.9;
+
+/// Logs a packet going in or out with its state, id and length including the id.
+/// Only built with the `packet_trace` feature, otherwise this compiles to nothing.
+#[inline(always)]
+fn trace_packet(direction: &str, state: State, id: i32, length: usize) {
+    if cfg!(feature = "packet_trace") {
+        trace!(target: "siderite_core::packets", "{} state: {:?}, id: {:#04X}, len: {}", direction, state, id, length);
+    }
+}
+
 #[repr(i32)]
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
 enum State {
@@ -61,8 +121,9 @@ enum State {
 pub enum GameStateReason {
     /// Bed can't be used as a spawn point
     InvalidBed = 0,
-    EndRaining = 1,
-    BeginRaining = 2,
+    /// The names in the protocol documentation are swapped, the client starts raining when it gets 1
+    BeginRaining = 1,
+    EndRaining = 2,
     /// 0: Survival, 1: Creative, 2: Adventure, 3: Spectator
     ChangeGameMode = 3,
     EnterCredits = 4,
@@ -73,15 +134,47 @@ pub enum GameStateReason {
     DemoMessage = 5,
     /// Appears to be played when an arrow strikes another player in Multiplayer
     ArrowHittingPlayer = 6,
-    /// The current darkness value. 1 = Dark, 0 = Bright,
+    /// How heavy it rains, from 0 to 1.
     /// Setting the value higher causes the game to change color and freeze
-    FadeValue = 7,
-    /// Time in ticks for the sky to fade
-    FadeTime = 8,
+    RainLevel = 7,
+    /// How dark the sky is during a thunderstorm, from 0 to 1
+    ThunderLevel = 8,
     /// Unknown
     PlayMobAppearance = 10,
 }
 
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Animation {
+    SwingArm = 0,
+    TakeDamage = 1,
+    LeaveBed = 2,
+    EatFood = 3,
+    CriticalEffect = 4,
+    MagicCriticalEffect = 5
+}
+
+#[repr(i8)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum EntityStatus {
+    /// Plays the hurt animation and sound
+    Hurt = 2,
+    /// Plays the death animation and sound
+    Dead = 3
+}
+
+bitflags! {
+    /// Fields of a Player Position And Look packet that are relative to the current position and rotation of the player
+    #[derive(Default, Clone, Copy, Debug, PartialEq)]
+    pub struct TeleportFlags: u8 {
+        const X = 0x01;
+        const Y = 0x02;
+        const Z = 0x04;
+        const YAW = 0x08;
+        const PITCH = 0x10;
+    }
+}
+
 #[repr(i8)]
 #[derive(Copy, Clone, Debug, FromPrimitive)]
 pub enum DigStatus {
@@ -93,6 +186,37 @@ pub enum DigStatus {
     ShootArrowFinishEating = 5
 }
 
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum EntityAction {
+    StartSneaking = 0,
+    StopSneaking = 1,
+    LeaveBed = 2,
+    StartSprinting = 3,
+    StopSprinting = 4,
+    JumpWithHorse = 5,
+    OpenHorseInventory = 6
+}
+
+/// A right click with an item, on a block or in the air
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockPlacement {
+    pub pos: Coord<i32>,
+    /// None if the player didn't click on a block
+    pub face: Option<BlockFace>,
+    pub held_item: Option<ItemStack>,
+    /// Position of the crosshair on the block, from 0 to 15
+    pub cursor: Coord<u8>
+}
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum UseEntityAction {
+    Interact = 0,
+    Attack = 1,
+    InteractAt = 2
+}
+
 pub struct Protocol {
     server: Arc<Server>,
     client_id: u32,
@@ -101,14 +225,32 @@ pub struct Protocol {
 
     stream: TcpStream,
     state: State,
+    /// Version of the protocol the client speaks, the newest one until the handshake tells otherwise
+    version: &'static dyn ProtocolVersion,
     received_data: RingBuf,
+    /// Capacity of `received_data`
+    receive_buf_len: usize,
+    out_queue: OutQueue,
     compressed: bool,
+    /// The client is running Forge Mod Loader
+    forge_client: bool,
 
-    last_keep_alive: SystemTime,
+    /// Last time the client answered a keep alive, or entered the play state
+    last_keep_alive: Instant,
+    /// When to send the next keep alive, None outside of the play state
+    next_keep_alive: Option<Instant>,
+    /// When a kicked connection is closed even if the queued packets aren't written yet,
+    /// None unless the connection is waiting for the reason to be written
+    close_deadline: Option<Instant>,
 
     verify_token: [u8; VERIFY_TOKEN_LEN],
     encryption_key: [u8; ENCRYPTION_KEY_LEN],
-    crypter: Option<(Crypter, Crypter)>
+    crypter: Option<(Crypter, Crypter)>,
+
+    /// Holds the data of one read from the connection, sized by the server config
+    read_buf: Box<[u8]>,
+    /// AES/CFB8 is a stream cipher, so the decrypted data is as long as the data read
+    decrypt_buf: Box<[u8]>
 }
 
 impl Protocol {
@@ -119,6 +261,8 @@ impl Protocol {
         let (tx, rx) = crossbeam_channel::unbounded();
         // The player will get the same ID as the client
         let client_id = server::get_next_entity_id();
+        // Reading more than the budget at once is of no use
+        let read_buf_len = server.read_buffer_size().clamp(1, READ_BUDGET);
         Self {
             server: server.clone(),
             client_id,
@@ -127,14 +271,23 @@ impl Protocol {
 
             stream,
             state: State::HandShaking,
-            received_data: RingBuf::with_capacity((32 * 1024) - 1),
+            version: &v47::V47,
+            received_data: RingBuf::with_capacity(RECEIVE_BUF_LEN),
+            receive_buf_len: RECEIVE_BUF_LEN,
+            out_queue: OutQueue::default(),
             compressed: false,
+            forge_client: false,
 
-            last_keep_alive: SystemTime::now(),
+            last_keep_alive: Instant::now(),
+            next_keep_alive: None,
+            close_deadline: None,
 
             verify_token: arr,
             encryption_key: [0u8; ENCRYPTION_KEY_LEN],
-            crypter: None
+            crypter: None,
+
+            read_buf: vec![0u8; read_buf_len].into_boxed_slice(),
+            decrypt_buf: vec![0u8; read_buf_len].into_boxed_slice()
         }
     }
 
@@ -143,12 +296,34 @@ impl Protocol {
     }
 
     pub fn is_disconnected(&self) -> bool {
-        self.state == State::Disconnected
+        self.state == State::Disconnected && self.close_deadline.is_none()
+    }
+
+    /// Returns true if the connection was kicked and is still writing the queued packets
+    pub fn is_closing(&self) -> bool {
+        self.close_deadline.is_some()
+    }
+
+    /// Writes what the kicked connection accepts without blocking,
+    /// then closes it once the queue is empty or the deadline passed
+    pub fn tick_close(&mut self, now: Instant) {
+        let deadline = match self.close_deadline {
+            Some(v) => v,
+            None => return
+        };
+
+        let written = match self.flush() {
+            Ok(()) => self.out_queue.is_empty(),
+            Err(_) => true
+        };
+        if written || now >= deadline {
+            let _ = self.shutdown();
+        }
     }
 
     /// Checks if the first packet is a legacy ping packet (MC v1.4 - 1.6)
     /// If it is, handles it and returns true
-    pub fn legacy_ping(mut stream: &mut TcpStream) -> bool {
+    pub fn legacy_ping(stream: &mut TcpStream) -> bool {
         // This packet uses a nonstandard format. It is never length-prefixed
         // and the packet ID is an Unsigned Byte instead of a VarInt.
         // Legacy clients may send this packet to initiate Server List Ping
@@ -156,7 +331,7 @@ impl Protocol {
         let len = stream.peek(&mut tbuf).unwrap();
         if len == 1 && tbuf[0] == 0xFE {
             stream.read_exact(&mut tbuf).unwrap();
-            Protocol::handle_legacy_ping(&mut stream);
+            Protocol::handle_legacy_ping(stream);
             stream.shutdown(Shutdown::Both).expect("shutdown call failed");
             return true;
         }
@@ -194,74 +369,108 @@ impl Protocol {
 
     // In
 
+    /// Reads the data the client sent, up to the read budget, and handles the buffered packets
     pub fn process_data(&mut self) {
-        let mut tmp = [0u8; 512];
-        let len = match self.stream.peek(&mut tmp) {
-            Ok(v) => v,
-            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                // return, we don't want to block the protocols thread
-                return;
+        if let Err(e) = self.read_data().and_then(|_| self.handle_in_packets()) {
+            self.handle_error(e);
+        }
+    }
+
+    /// Moves data from the connection to the receive buffer, returns the number of bytes read.
+    /// Reads until the socket is drained, the buffer is full or the read budget is used up.
+    fn read_data(&mut self) -> Result<usize> {
+        if self.receive_buf_len > RECEIVE_BUF_LEN && self.received_data.is_empty() {
+            // The large packets are handled, give the memory back
+            self.received_data = RingBuf::with_capacity(RECEIVE_BUF_LEN);
+            self.receive_buf_len = RECEIVE_BUF_LEN;
+        }
+
+        let mut read = 0;
+        loop {
+            // Data that doesn't fit the budget or the buffer is left in the socket for the next tick
+            let max = self.read_buf.len()
+                .min(READ_BUDGET - read)
+                .min(self.receive_buf_len - self.received_data.len());
+            if max == 0 {
+                return Ok(read);
             }
-            Err(ref e) if Protocol::is_disconnection_error(e.kind()) => {
+
+            let len = match self.stream.read(&mut self.read_buf[..max]) {
                 // Connection closed
-                self.state = State::Disconnected;
-                return;
+                Ok(0) => return Err(ProtocolError::Disconnected),
+                Ok(v) => v,
+                // Nothing left to read, we don't want to block the protocols thread
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(read),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into())
+            };
+
+            let data = &self.read_buf[..len];
+            match &mut self.crypter {
+                Some((_, de)) => {
+                    let dlen = de.update(data, &mut self.decrypt_buf[..len]).unwrap();
+                    self.received_data.write_all(&self.decrypt_buf[..dlen]).unwrap();
+                },
+                None => self.received_data.write_all(data).unwrap()
             }
-            Err(e) => {
-                warn!("Encountered IO error: {}", e);
-                self.shutdown().unwrap();
-                return;
+
+            read += len;
+            if len < max {
+                // The socket is drained
+                return Ok(read);
             }
-        };
+        }
+    }
 
-        if len == 0 {
-            // Connection closed
-            if let Err(e) = self.shutdown() {
-                if !Protocol::is_disconnection_error(e.kind()) {
-                    warn!("Error while shutting down connection: {}", e);
+    /// Logs the error and closes the connection.
+    /// Clients that sent an invalid packet get kicked, disconnects are only logged when debugging.
+    fn handle_error(&mut self, error: ProtocolError) {
+        match &error {
+            ProtocolError::Disconnected => debug!("Client {} disconnected", self.client_id),
+            ProtocolError::Io(_) => warn!("Error while handling connection: {}", error),
+            ProtocolError::Parse(_) | ProtocolError::Protocol(_) | ProtocolError::UnknownPacket { .. } => {
+                error!("{}, state: {:?}", error, self.state);
+                if self.state == State::Login || self.state == State::Play {
+                    // We don't care about the result, the connection is closed either way
+                    let _ = self.disconnect(&chat::text(&error.to_string()));
                 }
             }
-
-            return;
         }
 
-        let mut vec = vec![0u8; len];
-        self.stream.read_exact(&mut vec).unwrap();
-
-        match &mut self.crypter {
-            Some((_, de)) => {
-                let mut dvec = vec![0u8; len];
-                let dlen = de.update(&vec, &mut dvec).unwrap();
-                self.received_data.write_all(&dvec[..dlen]).unwrap();
-            },
-            None => self.received_data.write_all(&vec).unwrap()
+        if self.state != State::Disconnected {
+            let _ = self.shutdown();
         }
-
-        self.handle_in_packets();
     }
 
-    fn handle_in_packets(&mut self) {
+    /// Handles the buffered packets, up to the packet budget
+    fn handle_in_packets(&mut self) -> Result<()> {
         let mut buf = [0u8; mcrw::VAR_INT_MAX_SIZE];
-        while self.state != State::Disconnected {
-            let length = match self.received_data.peek(&mut buf) {
-                Ok(0) | Err(_) => {
-                    return; // Not enough data
-                }
-                Ok(read) => {
-                    match (&buf[..read]).read_var_int() {
-                        Ok(v) => v as usize,
-                        Err(_) => {
-                            return; // Not enough data
-                        }
-                    }
-                }
+        let mut handled = 0;
+        while self.state != State::Disconnected && handled < PACKET_BUDGET {
+            handled += 1;
+
+            // The length prefix may not have arrived completely, it's only consumed once the whole packet is there
+            let read = self.received_data.peek(&mut buf).unwrap_or(0);
+            let (length, length_size) = match peek_var_int(&buf[..read])? {
+                Some((v, _)) if v < 0 => return Err(ProtocolError::Protocol(format!("Invalid packet length: {}", v))),
+                Some((v, size)) => (v as usize, size),
+                None => return Ok(()) // Not enough data
             };
 
-            if self.received_data.len() < length {
-                return; // Not enough data
+            if length > MAX_PACKET_LEN {
+                return Err(ProtocolError::Protocol(format!("Packet too large: {} bytes", length)));
+            }
+
+            // A packet that can't fit the buffer would never arrive completely
+            if length_size + length > self.receive_buf_len {
+                self.grow_receive_buf(length_size + length);
+            }
+
+            if self.received_data.len() < length_size + length {
+                return Ok(()); // Not enough data
             }
 
-            self.received_data.advance_read_pos(mcrw::var_int_size(length as i32)).unwrap();
+            self.received_data.advance_read_pos(length_size).unwrap();
 
             debug!("Packet length: {}", length);
 
@@ -271,97 +480,120 @@ impl Protocol {
             let mut rslice = rbuf.as_slice();
 
             if self.compressed {
-                let data_length = rslice.read_var_int().unwrap();
+                let data_length = rslice.read_var_int()?;
                 debug!("Data length: {}", length);
+                if data_length < 0 || data_length as usize > MAX_PACKET_LEN {
+                    return Err(ProtocolError::Protocol(format!("Invalid data length: {}", data_length)));
+                }
+
                 if data_length != 0 {
                     let mut d = ZlibDecoder::new(rslice);
                     let mut vec = vec!(0u8; data_length as usize);
-                    d.read_exact(&mut vec).unwrap();
+                    d.read_exact(&mut vec)?;
                     let mut slice = vec.as_slice();
-                    let id = slice.read_var_int().unwrap();
-                    self.handle_packet(slice, id);
-                    return;
+                    let id = slice.read_var_int()?;
+                    self.handle_packet(slice, id)?;
+                    continue;
                 }
             }
 
-            let id = rslice.read_var_int().unwrap();
-            self.handle_packet(rslice, id);
+            let id = rslice.read_var_int()?;
+            self.handle_packet(rslice, id)?;
         }
+
+        Ok(())
+    }
+
+    /// Moves the received data to a buffer that holds at least `len` bytes
+    fn grow_receive_buf(&mut self, len: usize) {
+        let capacity = len.max(self.receive_buf_len * 2).min(MAX_PACKET_LEN + mcrw::VAR_INT_MAX_SIZE);
+        debug!("Growing the receive buffer of client {} to {} bytes", self.client_id, capacity);
+        let mut grown = RingBuf::with_capacity(capacity);
+        io::copy(&mut self.received_data, &mut grown).unwrap();
+        self.received_data = grown;
+        self.receive_buf_len = capacity;
     }
 
-    fn handle_packet(&mut self, rbuf: &[u8], id: i32) {
+    fn handle_packet(&mut self, rbuf: &[u8], id: i32) -> Result<()> {
+        trace_packet("Read", self.state, id, mcrw::var_int_size(id) + rbuf.len());
         match self.state {
             State::HandShaking => {
-                match id {
-                    0x00 => self.handle_handshake(rbuf),
-                    _ => {
-                        self.unknown_packet(id);
-                        self.shutdown().unwrap();
-                    }
+                match ServerboundHandshake::from_i32(id) {
+                    Some(ServerboundHandshake::Handshake) => self.handle_handshake(rbuf),
+                    None => Err(self.unknown_packet(id))
                 }
             }
             State::Status => {
-                let res = match id {
-                    0x00 => self.handle_request(),
-                    0x01 => self.handle_ping(rbuf),
-                    _ => {
-                        self.unknown_packet(id);
-                        self.shutdown()
-                    }
-                };
-
-                if let Err(e) = res {
-                    error!("Error while handling packets: {}", e);
-                    self.state = State::Disconnected;
+                match ServerboundStatus::from_i32(id) {
+                    Some(ServerboundStatus::Request) => self.handle_request(),
+                    Some(ServerboundStatus::Ping) => self.handle_ping(rbuf),
+                    None => Err(self.unknown_packet(id))
                 }
             }
             State::Login => {
-                let res = match id {
-                    0x00 => self.handle_login_start(rbuf),
-                    0x01 => self.handle_encryption_response(rbuf),
-                    _ => {
-                        self.unknown_packet(id);
-                        self.disconnect(&format!("Unknown packet: {:#X}", id))
+                match ServerboundLogin::from_i32(id) {
+                    Some(ServerboundLogin::LoginStart) => self.handle_login_start(rbuf),
+                    Some(ServerboundLogin::EncryptionResponse) => self.handle_encryption_response(rbuf),
+                    None if self.server.strict_protocol() => Err(self.unknown_packet(id)),
+                    None => {
+                        // Modded clients can send extra packets during login, skip them
+                        warn!("Skipping unknown packet: {:#X}, state: {:?}", id, self.state);
+                        Ok(())
                     }
-                };
-
-                if let Err(e) = res {
-                    error!("Error while handling packets: {}", e);
-                    self.state = State::Disconnected;
                 }
             }
             State::Play => {
-                match id {
-                    0x00 => self.handle_keep_alive(rbuf),
-                    0x01 => self.handle_chat_message(rbuf),
-                    0x03 => self.handle_player(rbuf),
-                    0x04 => self.handle_player_pos(rbuf),
-                    0x05 => self.handle_player_look(rbuf),
-                    0x06 => self.handle_player_pos_look(rbuf),
-                    0x07 => self.handle_player_digging(rbuf),
-                    0x08 => self.handle_player_block_placement(rbuf),
-                    0x09 => self.handle_held_item_change(rbuf),
-                    0x0A => (), // Sent when the player's arm swings
-                    0x0B => self.handle_entity_action(rbuf),
-                    0x0D => self.handle_close_window(rbuf),
-                    0x0E => self.handle_click_window(rbuf),
-                    0x10 => self.handle_creative_inventory_action(rbuf),
-                    0x13 => self.handle_player_abilities(rbuf),
-                    0x15 => self.handle_client_settings(rbuf),
-                    0x16 => self.handle_client_status(rbuf),
-                    0x17 => self.handle_plugin_message(rbuf),
-                    _ => {
-                        self.unknown_packet(id);
-                        self.disconnect(&format!("Unknown packet: {:#X}", id)).unwrap();
-                    }
+                let packet = ServerboundPlay::from_i32(id);
+                let translated = match packet {
+                    Some(packet) => self.version.translate_play_packet(packet, rbuf)?,
+                    None => return Err(self.unknown_packet(id))
+                };
+                let rbuf = match &translated {
+                    Some(v) => v.as_ref(),
+                    None => return Ok(()) // Ignored for this version
+                };
+
+                match packet {
+                    Some(ServerboundPlay::KeepAlive) => self.handle_keep_alive(rbuf),
+                    Some(ServerboundPlay::ChatMessage) => self.handle_chat_message(rbuf),
+                    Some(ServerboundPlay::UseEntity) => self.handle_use_entity(rbuf),
+                    Some(ServerboundPlay::Player) => self.handle_player(rbuf),
+                    Some(ServerboundPlay::PlayerPosition) => self.handle_player_pos(rbuf),
+                    Some(ServerboundPlay::PlayerLook) => self.handle_player_look(rbuf),
+                    Some(ServerboundPlay::PlayerPositionAndLook) => self.handle_player_pos_look(rbuf),
+                    Some(ServerboundPlay::PlayerDigging) => self.handle_player_digging(rbuf),
+                    Some(ServerboundPlay::PlayerBlockPlacement) => self.handle_player_block_placement(rbuf),
+                    Some(ServerboundPlay::HeldItemChange) => self.handle_held_item_change(rbuf),
+                    Some(ServerboundPlay::Animation) => Ok(()), // Sent when the player's arm swings
+                    Some(ServerboundPlay::EntityAction) => self.handle_entity_action(rbuf),
+                    Some(ServerboundPlay::SteerVehicle) => self.handle_steer_vehicle(rbuf),
+                    Some(ServerboundPlay::CloseWindow) => self.handle_close_window(rbuf),
+                    Some(ServerboundPlay::ClickWindow) => self.handle_click_window(rbuf),
+                    Some(ServerboundPlay::CreativeInventoryAction) => self.handle_creative_inventory_action(rbuf),
+                    Some(ServerboundPlay::EnchantItem) => self.handle_enchant_item(rbuf),
+                    Some(ServerboundPlay::UpdateSign) => self.handle_update_sign(rbuf),
+                    Some(ServerboundPlay::PlayerAbilities) => self.handle_player_abilities(rbuf),
+                    Some(ServerboundPlay::ClientSettings) => self.handle_client_settings(rbuf),
+                    Some(ServerboundPlay::ClientStatus) => self.handle_client_status(rbuf),
+                    Some(ServerboundPlay::PluginMessage) => self.handle_plugin_message(rbuf),
+                    _ => Err(self.unknown_packet(id))
                 }
             }
-            State::Disconnected => {} // Ignore all packets
+            State::Disconnected => Ok(()) // Ignore all packets
         }
     }
 
-    fn unknown_packet(&self, id: i32) {
-        error!("Unknown packet: {:#X}, state: {:?}", id, self.state);
+    /// Returns the error for a packet the server doesn't handle in the current state,
+    /// with the name of the packet if the id exists in that state
+    fn unknown_packet(&self, id: i32) -> ProtocolError {
+        let name = match self.state {
+            State::HandShaking => ServerboundHandshake::from_i32(id).map(|v| format!("{:?}", v)),
+            State::Status => ServerboundStatus::from_i32(id).map(|v| format!("{:?}", v)),
+            State::Login => ServerboundLogin::from_i32(id).map(|v| format!("{:?}", v)),
+            State::Play => ServerboundPlay::from_i32(id).map(|v| format!("{:?}", v)),
+            State::Disconnected => None
+        };
+        ProtocolError::UnknownPacket { id, name }
     }
 
     // Out:
@@ -380,9 +612,20 @@ impl Protocol {
         for p in packets {
             self.send_packet(p);
         }
+
+        // Write what the connection couldn't accept earlier
+        if self.state != State::Disconnected {
+            if let Err(e) = self.flush() {
+                self.handle_error(e);
+            }
+        }
     }
 
     fn send_packet(&mut self, packet: Packet) {
+        if !self.version.supports(&packet) {
+            return;
+        }
+
         let res = match packet {
             Packet::LoginSuccess() => self.login_success(),
 
@@ -390,83 +633,149 @@ impl Protocol {
             Packet::JoinGame(player, world) => self.join_game(player, world),
             Packet::TimeUpdate(world) => self.time_update(world),
             Packet::SpawnPosition(world) => self.spawn_position(world),
+            Packet::Respawn(world, gamemode) => self.respawn(world, gamemode),
+            Packet::UpdateHealth(health, food, saturation) => self.update_health(health, food, saturation),
+            Packet::SetExperience(level) => self.set_experience(level),
             Packet::PlayerPositionAndLook(player) => self.player_pos_look(player),
+            Packet::Teleport(pos, yaw, pitch, flags, absolute) => self.teleport(pos, yaw, pitch, flags, absolute),
             Packet::SpawnPlayer(player) => self.spawn_player(player),
+            Packet::SpawnObject(entity) => self.spawn_object(entity),
+            Packet::SpawnMob(entity_id, mob_type, pos, metadata) => self.spawn_mob(entity_id, mob_type, pos, (0.0, 0.0), Coord::new(0.0, 0.0, 0.0), &metadata),
+            Packet::DestroyEntities(entity_ids) => self.destroy_entities(&entity_ids),
+            Packet::EntityRelativeMove(entity_id, delta, on_ground) => self.entity_relative_move(entity_id, delta, on_ground),
+            Packet::EntityLook(entity_id, yaw, pitch, on_ground) => self.entity_look(entity_id, yaw, pitch, on_ground),
+            Packet::EntityLookAndRelativeMove(entity_id, delta, yaw, pitch, on_ground) =>
+                self.entity_look_relative_move(entity_id, delta, yaw, pitch, on_ground),
+            Packet::EntityTeleport(entity_id, pos, yaw, pitch, on_ground) => self.entity_teleport(entity_id, pos, yaw, pitch, on_ground),
+            Packet::EntityHeadLook(entity_id, yaw) => self.entity_head_look(entity_id, yaw),
+            Packet::EntityStatus(entity_id, status) => self.entity_status(entity_id, status),
+            Packet::EntityVelocity(entity_id, velocity) => self.entity_velocity(entity_id, velocity),
+            Packet::UseBed(entity_id, pos) => self.use_bed(entity_id, pos),
+            Packet::Animation(entity_id, animation) => self.animation(entity_id, animation),
+            Packet::CombatEvent(player_id, killer_id, message) => self.combat_event(player_id, killer_id, &message),
+            Packet::AttachEntity(entity_id, vehicle_id, leash) => self.attach_entity(entity_id, vehicle_id, leash),
+            Packet::EntityMetadata(entity_id, metadata) => self.entity_metadata(entity_id, &metadata),
+            Packet::EntityEffect(entity_id, effect) => self.entity_effect(entity_id, effect),
+            Packet::RemoveEntityEffect(entity_id, effect_type) => self.remove_entity_effect(entity_id, effect_type),
+            Packet::EntityProperties(entity_id, attributes) => self.entity_properties(entity_id, &attributes),
+            Packet::MultiBlockChange(coord, records) => self.multi_block_change(coord, &records),
+            Packet::BlockChange(pos, block_type, meta) => self.block_change(pos, block_type, meta),
+            Packet::Explosion(center, radius, records, motion) => self.explosion(center, radius, &records, motion),
             Packet::ChangeGameState(reason, value) => self.change_game_state(reason, value),
-            Packet::PlayerListItem(action, players) => self.player_list_item(action, players),
+            Packet::OpenWindow(window_id, window_type, slots) => self.open_window(window_id, window_type, slots),
+            Packet::CloseWindow(window_id) => self.close_window(window_id),
+            Packet::SetSlot(window_id, slot, item) => self.set_slot(window_id, slot, item.as_ref()),
+            Packet::WindowItems(window_id, items) => self.window_items(window_id, &items),
+            Packet::WindowProperty(window_id, property, value) => self.window_property(window_id, property, value),
+            Packet::PlayerListItem(action, players) => self.player_list_item(action, &players),
             Packet::PlayerAbilities(player) => self.player_abilities(player),
-            Packet::ChunkData(coord, chunk_map) => self.chunk_data(coord, chunk_map),
+            Packet::ChunkData(coord, chunk_map, dimension) => self.chunk_data(coord, chunk_map, dimension),
+            Packet::ChunkUnload(coord) => self.chunk_data_unload(coord),
+            Packet::MapChunkBulk(coords, chunk_map, dimension) => self.map_chunk_bulk(&coords, chunk_map, dimension),
+            Packet::Map(id, scale, icons, update) => self.map(id, scale, &icons, update.as_ref()),
+            Packet::Statistics(stats) => self.statistics(&stats),
+            // 1.8 clients only read the text of signs from Update Sign
+            Packet::UpdateBlockEntity(pos, TileEntity::Sign { lines }) => self.update_sign(pos, &lines),
+            Packet::UpdateBlockEntity(pos, tile_entity) => self.update_block_entity(pos, &tile_entity),
+            Packet::OpenSignEditor(pos) => self.open_sign_editor(pos),
             Packet::ServerDifficulty(difficulty) => self.server_difficulty(difficulty),
             Packet::ResourcePackSend(url, hash) => self.resource_pack_send(&url, &hash),
+            Packet::Teams(name, action) => self.teams(&name, &action),
 
             Packet::Disconnect(reason) => self.disconnect(&reason)
         };
 
-        if res.is_err() {
-            // We don't care about the result
-            self.shutdown().unwrap();
+        if let Err(e) = res {
+            self.handle_error(e);
         }
     }
 
+    /// Frames the packet and queues it, then writes as much of the queue as the connection accepts
     fn write_packet(&mut self, rbuf: &[u8]) -> Result<()> {
         let length = rbuf.len() as i32;
-        debug!("Write packet: state: {:?}, len {}, id: {:#X}", self.state, length, rbuf[0]);
-
-        // REVIEW: duplicate code + multiple writes to self.stream
-        match &mut self.crypter {
-            Some((en, _)) => {
-                let mut buf = vec!(0; rbuf.len() + 10);
-                if !self.compressed {
-                    buf.write_var_int(length)?; // Write packet length
-                    buf.write_all(&rbuf)?; // Write packet data
-                } else if length < self.server.compression_threshold().unwrap() {
-                    buf.write_var_int(length + 1)?; // Write packet length
-                    buf.write_var_int(0)?;
-                    buf.write_all(&rbuf)?;
-                } else {
-                    let mut zen = ZlibEncoder::new(Vec::with_capacity(rbuf.len()), Compression::default());
-                    zen.write_all(rbuf)?;
-                    let comp_buf = zen.finish()?;
-                    buf.write_var_int((mcrw::var_int_size(length) + comp_buf.len()) as i32)?;
-                    buf.write_var_int(length)?;
-                    buf.write_all(&comp_buf)?;
-                }
+        trace_packet("Write", self.state, rbuf[0] as i32, rbuf.len());
+
+        let mut frame = Vec::with_capacity(rbuf.len() + 10);
+        if !self.compressed {
+            frame.write_var_int(length)?; // Write packet length
+            frame.write_all(rbuf)?; // Write packet data
+        } else if length < self.server.compression_threshold().unwrap() {
+            // Like vanilla, only packets below the threshold are left uncompressed
+            frame.write_var_int(length + 1)?; // Write packet length
+            frame.write_var_int(0)?;
+            frame.write_all(rbuf)?;
+        } else {
+            let mut zen = ZlibEncoder::new(Vec::with_capacity(rbuf.len()), Compression::default());
+            zen.write_all(rbuf)?;
+            let comp_buf = zen.finish()?;
+            frame.write_var_int((mcrw::var_int_size(length) + comp_buf.len()) as i32)?;
+            frame.write_var_int(length)?;
+            frame.write_all(&comp_buf)?;
+        }
 
-                let mut enc_buf = vec![0; buf.len() + 128];
-                let enc_len = en.update(&buf, &mut enc_buf).unwrap();
-                self.stream.write_all(&enc_buf[..enc_len])?;
-            },
-            None => {
-                if !self.compressed {
-                    self.stream.write_var_int(length)?; // Write packet length
-                    self.stream.write_all(&rbuf)?; // Write packet data
-                } else if length < self.server.compression_threshold().unwrap() {
-                    self.stream.write_var_int(length + 1)?; // Write packet length
-                    self.stream.write_var_int(0)?;
-                    self.stream.write_all(&rbuf)?;
-                } else {
-                    let mut zen = ZlibEncoder::new(Vec::with_capacity(rbuf.len()), Compression::default());
-                    zen.write_all(rbuf)?;
-                    let comp_buf = zen.finish()?;
-                    self.stream.write_var_int((mcrw::var_int_size(length) + comp_buf.len()) as i32)?;
-                    self.stream.write_var_int(length)?;
-                    self.stream.write_all(&comp_buf)?;
-                }
-            }
+        if !self.out_queue.push(frame, self.priority(rbuf[0])) {
+            return Err(ProtocolError::Protocol("Too many packets waiting to be sent".to_owned()));
+        }
+        self.flush()
+    }
+
+    /// Only packets the connection depends on skip the queued bulk packets, like chunks.
+    /// Everything else keeps its order, a Respawn can't overtake the chunks of the world it leaves.
+    fn priority(&self, id: u8) -> Priority {
+        match (self.state, ClientboundPlay::from_u8(id)) {
+            (State::Play, Some(ClientboundPlay::KeepAlive | ClientboundPlay::Disconnect)) => Priority::Control,
+            (State::Play, _) => Priority::Bulk,
+            _ => Priority::Control
         }
+    }
 
+    /// Writes the queued packets until the connection would block
+    fn flush(&mut self) -> Result<()> {
+        self.out_queue.flush(&mut self.stream, self.crypter.as_mut().map(|(en, _)| en))?;
         Ok(())
     }
 
     // HandShaking packets:
 
-    fn handle_handshake(&mut self, mut rbuf: &[u8]) {
-        let _proto_v = rbuf.read_var_int().unwrap();
-        let _server_address = rbuf.read_string().unwrap();
-        let _server_port = rbuf.read_ushort().unwrap();
-        let next_state = rbuf.read_var_int().unwrap();
-        self.state = State::from_i32(next_state).unwrap();
+    fn handle_handshake(&mut self, mut rbuf: &[u8]) -> Result<()> {
+        let protocol_version = rbuf.read_var_int()?;
+        let server_address = rbuf.read_string()?;
+        if server_address.ends_with(FML_MARKER) {
+            debug!("Client is running Forge");
+            self.forge_client = true;
+        }
+
+        let _server_port = rbuf.read_ushort()?;
+        let next_state = rbuf.read_var_int()?;
+        self.state = match State::from_i32(next_state) {
+            Some(v @ (State::Status | State::Login)) => v,
+            _ => return Err(ProtocolError::Protocol(format!("Invalid next state: {}", next_state)))
+        };
         debug!("Changed State to {:?}", self.state);
+
+        let version = version::for_protocol(protocol_version);
+        if let Some(version) = version {
+            self.version = version;
+        }
+
+        // Only logins are throttled, pinging the server list doesn't count
+        if self.state == State::Login {
+            let ip = self.stream.peer_addr()?.ip();
+            if !self.server.allow_login(ip) {
+                return self.disconnect(&chat::text(throttle::THROTTLED_MESSAGE));
+            }
+
+            if version.is_none() {
+                let reason = if protocol_version < PROTOCOL_VERSION {
+                    kick::outdated_client(VERSION_NAME)
+                } else {
+                    kick::outdated_server(VERSION_NAME)
+                };
+                return self.disconnect(&reason);
+            }
+        }
+
+        Ok(())
     }
 
     // Status packets:
@@ -475,11 +784,12 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Status);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x00).unwrap();
+        wbuf.write_var_int(ClientboundStatus::Response as i32).unwrap();
         let mut response = json!({
+            // Clients of every supported version see the server as compatible
             "version": {
-                "name": "1.8.9",
-                "protocol": 47
+                "name": self.version.name(),
+                "protocol": self.version.protocol()
             },
             "players": {
                 "max": self.server.max_players(),
@@ -491,9 +801,7 @@ impl Protocol {
                     }
                 ]
             },
-            "description": {
-                "text": self.server.motd(),
-            },
+            "description": chat::motd_component(self.server.motd()),
         });
         if let Some(favicon) = self.server.favicon()
         {
@@ -512,8 +820,8 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Status);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x01).unwrap();
-        let payload = rbuf.read_long().unwrap();
+        wbuf.write_var_int(ClientboundStatus::Pong as i32).unwrap();
+        let payload = rbuf.read_long()?;
         debug!("Ping payload: {}", payload);
         wbuf.write_long(payload).unwrap();
         self.write_packet(&wbuf)
@@ -522,7 +830,7 @@ impl Protocol {
     // Login packets:
 
     fn handle_login_start(&mut self, mut rbuf: &[u8]) -> Result<()> {
-        let username = rbuf.read_string().unwrap();
+        let username = rbuf.read_string()?;
         self.client.write().unwrap().set_username(username);
 
         if self.server.encryption() {
@@ -535,38 +843,35 @@ impl Protocol {
         Ok(())
     }
 
-    fn handle_encryption_response(&mut self, mut rbuf: &[u8]) -> Result<()> {
-        let ss_len = rbuf.read_var_int().unwrap() as usize; // Shared Secret Key Length
-        let mut ssarr = vec![0u8; ss_len];
-        rbuf.read_exact(&mut ssarr).unwrap(); // Shared Secret
-
-        let vt_len = rbuf.read_var_int().unwrap() as usize; // Verify Token Length
-        let mut vtarr = vec![0u8; vt_len];
-        rbuf.read_exact(&mut vtarr).unwrap(); // Verify Token
+    fn handle_encryption_response(&mut self, rbuf: &[u8]) -> Result<()> {
+        let (ssarr, vtarr) = self.version.read_encryption_response(rbuf)?;
+        let (ss_len, vt_len) = (ssarr.len(), vtarr.len());
 
         let private_key = self.server.private_key();
 
         // Decrypt the and verify the Verify Token
         let mut vtdvec = vec![0; vt_len];
-        let vtd_len = private_key.private_decrypt(&vtarr, &mut vtdvec, PADDING).unwrap();
+        let vtd_len = private_key.private_decrypt(&vtarr, &mut vtdvec, PADDING)
+            .map_err(|e| ProtocolError::Protocol(format!("Couldn't decrypt the Verify Token: {}", e)))?;
         if vtd_len != VERIFY_TOKEN_LEN {
             debug!("Verify Token is the wrong length: expected {}, got {}", VERIFY_TOKEN_LEN, vtd_len);
-            self.disconnect("Hacked client")?;
+            self.disconnect(&chat::text("Hacked client"))?;
             return Ok(());
         }
 
         if vtdvec[..VERIFY_TOKEN_LEN] != self.verify_token[..] {
             debug!("Verify Token is not the same");
-            self.disconnect("Hacked client")?;
+            self.disconnect(&chat::text("Hacked client"))?;
             return Ok(());
         }
 
         // Decrypt Shared Secret Key
         let mut ssdvec = vec![0; ss_len];
-        let ssd_len = private_key.private_decrypt(&ssarr, &mut ssdvec, PADDING).unwrap();
+        let ssd_len = private_key.private_decrypt(&ssarr, &mut ssdvec, PADDING)
+            .map_err(|e| ProtocolError::Protocol(format!("Couldn't decrypt the Shared Secret Key: {}", e)))?;
         if ssd_len != ENCRYPTION_KEY_LEN {
             debug!("Shared Secret Key is the wrong length: expected {}, got {}", ENCRYPTION_KEY_LEN, ssd_len);
-            self.disconnect("Hacked client")?;
+            self.disconnect(&chat::text("Hacked client"))?;
             return Ok(());
         }
 
@@ -584,12 +889,14 @@ impl Protocol {
             Mode::Decrypt,
             &self.encryption_key,
             Some(&self.encryption_key)).unwrap();
+        // Queued packets are encrypted when they're written, the client only answers
+        // the Encryption Request after receiving it, so nothing is queued that should go out unencrypted
         self.crypter = Some((encrypter, decrypter));
 
         let mut hasher = Sha1::new();
         hasher.update(self.server.id().as_bytes());
         hasher.update(&self.encryption_key);
-        hasher.update(&self.server.public_key_der());
+        hasher.update(self.server.public_key_der());
         let hash = hasher.finish();
         let server_id = auth::java_hex_digest(hash);
         self.client.read().unwrap().handle_login(Some(server_id));
@@ -601,15 +908,9 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Login);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x01).unwrap(); // Encryption Request packet
-        wbuf.write_string(&self.server.id()).unwrap();
-        // Public Key
-        let public_key_der = self.server.public_key_der();
-        wbuf.write_var_int(public_key_der.len() as i32).unwrap();
-        wbuf.write_all(public_key_der).unwrap();
-        // Verify Token
-        wbuf.write_var_int(self.verify_token.len() as i32).unwrap();
-        wbuf.write_all(&self.verify_token).unwrap();
+        wbuf.write_var_int(ClientboundLogin::EncryptionRequest as i32).unwrap();
+        wbuf.write_string(self.server.id()).unwrap(); // Server ID
+        self.version.write_encryption_request(&mut wbuf, self.server.public_key_der(), &self.verify_token);
 
         self.write_packet(&wbuf)
     }
@@ -618,15 +919,16 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Login);
 
         // Enable compression
-        if let Some(compression_threshold) = self.server.compression_threshold() {
+        if let Some(compression_threshold) = self.server.compression_threshold().filter(|_| self.version.supports_compression()) {
             self.set_compression(compression_threshold)?;
         }
 
         self.state = State::Play;
         debug!("Changed State to {:?}", self.state);
+        self.start_keep_alive(Instant::now());
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x02).unwrap(); // Login Success packet
+        wbuf.write_var_int(ClientboundLogin::LoginSuccess as i32).unwrap();
 
         {
             let client = self.client.read().unwrap();
@@ -637,7 +939,7 @@ impl Protocol {
             debug!("name: {}", username);
 
             wbuf.write_string(&uuid).unwrap();
-            wbuf.write_string(&username).unwrap();
+            wbuf.write_string(username).unwrap();
         }
 
         self.write_packet(&wbuf)
@@ -647,7 +949,7 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Login);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x03).unwrap(); // Login Success packet
+        wbuf.write_var_int(ClientboundLogin::SetCompression as i32).unwrap();
 
         // Maximum size of a packet before its compressed
         wbuf.write_var_int(threshold).unwrap(); // Threshold
@@ -662,200 +964,331 @@ impl Protocol {
 
     /// The server will frequently send out a keep-alive, each containing a random ID.
     /// The client must respond with the same packet.
-    fn handle_keep_alive(&mut self, mut rbuf: &[u8]) {
+    fn handle_keep_alive(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let _id = rbuf.read_var_int().unwrap();
-        if self.last_keep_alive.elapsed().unwrap() >= KEEP_ALIVE_MAX {
-            self.disconnect("Timed out!").unwrap();
-            return;
+        let _id = rbuf.read_var_int()?;
+        if self.last_keep_alive.elapsed() >= KEEP_ALIVE_MAX {
+            return self.disconnect(&kick::reason(kick::TIMED_OUT));
         }
 
-        self.last_keep_alive = SystemTime::now();
+        self.last_keep_alive = Instant::now();
+
+        Ok(())
     }
 
     /// Check the message to see if it begins with a '/'.
     /// If it does, the server assumes it to be a command and attempts to process it.
     /// If it doesn't, the username of the sender is prepended and sent to all clients.
-    fn handle_chat_message(&mut self, mut rbuf: &[u8]) {
+    fn handle_chat_message(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let msg = rbuf.read_string().unwrap();
-        if msg.starts_with('/') {
-            // TODO: exec cmd
-            return;
+        let msg = rbuf.read_string()?;
+        let client = self.client.read().unwrap();
+        if let Some(command) = msg.strip_prefix('/') {
+            client.handle_command(command);
+            return Ok(());
         }
 
-        let client = self.client.read().unwrap();
         let username = client.get_username().unwrap();
         self.server.broadcast_chat(username, &msg);
+
+        Ok(())
+    }
+
+    /// This packet is sent from the client to the server when the client attacks or right-clicks another entity.
+    fn handle_use_entity(&mut self, mut rbuf: &[u8]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let target = rbuf.read_var_int()?; // Target
+        let action = rbuf.read_var_int()?; // Type
+        let action = match UseEntityAction::from_i32(action) {
+            Some(v) => v,
+            None => return Err(ProtocolError::Protocol(format!("Use entity type is out of range (0..2), got {}", action)))
+        };
+
+        if action == UseEntityAction::InteractAt {
+            let _target_x = rbuf.read_float()?;
+            let _target_y = rbuf.read_float()?;
+            let _target_z = rbuf.read_float()?;
+        }
+
+        let client = self.client.read().unwrap();
+        client.handle_use_entity(target as u32, action);
+
+        Ok(())
     }
 
     /// This packet is used to indicate whether the player is on ground (walking/swimming),
     /// or airborne (jumping/falling).
-    fn handle_player(&mut self, mut rbuf: &[u8]) {
+    fn handle_player(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let _on_ground = rbuf.read_bool().unwrap();
+        let on_ground = rbuf.read_bool()?;
+
+        let client = self.client.read().unwrap();
+        client.handle_move(None, None, on_ground);
+
+        Ok(())
     }
 
     /// Updates the player's XYZ position on the server.
-    fn handle_player_pos(&mut self, mut rbuf: &[u8]) {
+    fn handle_player_pos(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         // Feet pos
-        let _x = rbuf.read_double().unwrap();
-        let _y = rbuf.read_double().unwrap();
-        let _z = rbuf.read_double().unwrap();
-        let _on_ground = rbuf.read_bool().unwrap();
+        let x = rbuf.read_double()?;
+        let y = rbuf.read_double()?;
+        let z = rbuf.read_double()?;
+        let on_ground = rbuf.read_bool()?;
+
+        let client = self.client.read().unwrap();
+        client.handle_move(Some(Coord::new(x, y, z)), None, on_ground);
+
+        Ok(())
     }
 
     /// Updates the direction the player is looking in.
-    fn handle_player_look(&mut self, mut rbuf: &[u8]) {
+    fn handle_player_look(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let _yaw = rbuf.read_float().unwrap();
-        let _pitch = rbuf.read_float().unwrap();
-        let _on_ground = rbuf.read_bool().unwrap();
+        let yaw = rbuf.read_float()?;
+        let pitch = rbuf.read_float()?;
+        let on_ground = rbuf.read_bool()?;
+
+        let client = self.client.read().unwrap();
+        client.handle_move(None, Some((yaw, pitch)), on_ground);
+
+        Ok(())
     }
 
     /// A combination of Player Look and Player Position.
-    fn handle_player_pos_look(&mut self, mut rbuf: &[u8]) {
+    fn handle_player_pos_look(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        // TODO: Do something
         // Feet pos
-        let _x = rbuf.read_double().unwrap();
-        let _y = rbuf.read_double().unwrap();
-        let _z = rbuf.read_double().unwrap();
+        let x = rbuf.read_double()?;
+        let y = rbuf.read_double()?;
+        let z = rbuf.read_double()?;
+
+        let yaw = rbuf.read_float()?;
+        let pitch = rbuf.read_float()?;
+        let on_ground = rbuf.read_bool()?;
 
-        let _yaw = rbuf.read_float().unwrap();
-        let _pitch = rbuf.read_float().unwrap();
-        let _on_ground = rbuf.read_bool().unwrap();
+        let client = self.client.read().unwrap();
+        client.handle_move(Some(Coord::new(x, y, z)), Some((yaw, pitch)), on_ground);
+
+        Ok(())
     }
 
     /// Sent when the player mines a block. A Notchian server only accepts
     /// digging packets with coordinates within a 6-unit radius of the player's position.
-    fn handle_player_digging(&mut self, mut rbuf: &[u8]) {
+    fn handle_player_digging(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let status = rbuf.read_byte().unwrap();
-        let (x, y, z) = rbuf.read_position().unwrap();
+        let status = rbuf.read_byte()?;
+        let (x, y, z) = rbuf.read_position()?;
 
-        let face = rbuf.read_byte().unwrap();
-        debug_assert!(face >= 0 && face < 6);
+        let face = rbuf.read_byte()?;
+        let (face, status) = match (BlockFace::from_i8(face), DigStatus::from_i8(status)) {
+            (Some(face), Some(status)) => (face, status),
+            _ => return Err(ProtocolError::Protocol(format!("Invalid digging status {} or face {}", status, face)))
+        };
 
         let client = self.client.read().unwrap();
-        client.handle_left_click(
-            Coord {
-                x: x as i32,
-                y: y as i32,
-                z: z as i32
-            },
-            BlockFace::from_i8(face).unwrap(),
-            DigStatus::from_i8(status).unwrap());
+        client.handle_left_click(Coord { x, y, z }, face, status);
+
+        Ok(())
     }
 
-    /// Sent when the player changes the slot selection
-    fn handle_player_block_placement(&mut self, mut rbuf: &[u8]) {
+    /// Sent when the player right clicks while holding an item or looking at a block
+    fn handle_player_block_placement(&mut self, rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let (_x, _y, _z) = rbuf.read_position().unwrap();
-        // See packet above for explanation
-        let _face = rbuf.read_byte().unwrap();
-        // TODO read slot
+        let placement = match read_block_placement(rbuf) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Invalid block placement packet: {}", e);
+                return Ok(());
+            }
+        };
 
-        // let _cursor_x = rbuf.read_byte().unwrap();
-        // let _cursor_y = rbuf.read_byte().unwrap();
-        // let _cursor_z = rbuf.read_byte().unwrap();
+        let client = self.client.read().unwrap();
+        client.handle_right_click(placement);
+
+        Ok(())
     }
 
     /// Sent when the player changes the slot selection
-    fn handle_held_item_change(&mut self, mut rbuf: &[u8]) {
+    fn handle_held_item_change(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let slot = rbuf.read_short().unwrap();
-        debug_assert!(slot >= 0 && slot < 9, "Invalid slot number");
+        let slot = rbuf.read_short()?;
+        if !(0..9).contains(&slot) {
+            warn!("Invalid held item slot: {}", slot);
+            return Ok(());
+        }
+
+        let client = self.client.read().unwrap();
+        client.handle_held_item_change(slot as usize);
+
+        Ok(())
     }
 
     /// Sent by the client to indicate that it has performed certain actions:
     /// sneaking (crouching), sprinting, exiting a bed, jumping with a horse,
     /// and opening a horse's inventory while riding it.
-    fn handle_entity_action(&mut self, mut rbuf: &[u8]) {
+    fn handle_entity_action(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        // TODO: Do something
-
-        let _entity_id = rbuf.read_var_int().unwrap(); // Entity ID
-        let _action_id = rbuf.read_var_int().unwrap(); // Action ID
+        let _entity_id = rbuf.read_var_int()?; // Entity ID
+        let action = rbuf.read_var_int()?; // Action ID
+        let action = match EntityAction::from_i32(action) {
+            Some(v) => v,
+            None => return Err(ProtocolError::Protocol(format!("Entity action is out of range (0..6), got {}", action)))
+        };
         // Only used by Horse Jump Boost, in which case it ranges from 0 to 100. In all other cases it is 0.
-        let _action_par = rbuf.read_var_int().unwrap(); // Action Parameter
+        let _action_par = rbuf.read_var_int()?; // Action Parameter
+
+        let client = self.client.read().unwrap();
+        client.handle_entity_action(action);
+
+        Ok(())
+    }
+
+    /// Sent by the client while riding an entity to pass on its movement input.
+    fn handle_steer_vehicle(&mut self, mut rbuf: &[u8]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let sideways = rbuf.read_float()?; // Positive to the left of the player
+        let forward = rbuf.read_float()?; // Positive forward
+        // Bit      | Meaning
+        // ----------------------------------
+        // 0 (0x01) | Jump
+        // 1 (0x02) | Unmount
+        let flags = rbuf.read_ubyte()?;
+
+        let client = self.client.read().unwrap();
+        client.handle_steer_vehicle(sideways, forward, flags & 0x01 != 0, flags & 0x02 != 0);
 
-        // ID | Action
-        // --------------------------------
-        // 0  | Start sneaking
-        // 1  | Stop sneaking
-        // 2  | Leave bed
-        // 3  | Start sprinting
-        // 4  | Stop sprinting
-        // 5  | Jump with horse
-        // 6  | Open ridden horse inventory
+        Ok(())
     }
 
     /// This packet is sent by the client when closing a window.
     /// Notchian clients send a Close Window packet with Window ID 0 to close their inventory
     /// even though there is never an Open Window packet for the inventory.
-    fn handle_close_window(&mut self, mut rbuf: &[u8]) {
+    fn handle_close_window(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let _window_id = rbuf.read_ubyte().unwrap(); // Window ID
+        let window_id = rbuf.read_ubyte()?; // Window ID
+
+        let client = self.client.read().unwrap();
+        client.handle_close_window(window_id);
+
+        Ok(())
     }
 
     /// This packet is sent by the player when it clicks on a slot in a window.
-    fn handle_click_window(&mut self, mut rbuf: &[u8]) {
+    fn handle_click_window(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let _window_id = rbuf.read_ubyte().unwrap(); // Window ID
-        let _slot = rbuf.read_short().unwrap(); // Slot
-        let _button = rbuf.read_byte().unwrap(); // Button
-        let _action = rbuf.read_short().unwrap(); // Action Number
-        let _mode = rbuf.read_ubyte().unwrap(); // Inventory operation mode
-        // TODO: Read slot
+        let window_id = rbuf.read_ubyte()?; // Window ID
+        let slot = rbuf.read_short()?; // Slot
+        let button = rbuf.read_byte()?; // Button
+        let _action = rbuf.read_short()?; // Action Number
+        let mode = rbuf.read_ubyte()?; // Inventory operation mode
+        // The clicked item isn't read, the server keeps track of the contents of the window itself
+
+        let client = self.client.read().unwrap();
+        client.handle_click_window(window_id, slot, button, mode);
+
+        Ok(())
+    }
+
+    /// Sent when the player picks one of the enchantments offered by an enchanting table.
+    fn handle_enchant_item(&mut self, mut rbuf: &[u8]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let window_id = rbuf.read_ubyte()?; // Window ID
+        let option = rbuf.read_byte()?; // Enchantment, the position of the option starting at 0
+
+        let client = self.client.read().unwrap();
+        client.handle_enchant_item(window_id, option);
+
+        Ok(())
     }
 
     /// While the user is in the standard inventory (i.e., not a crafting bench) in Creative mode,
     /// the player will send this packet.
-    fn handle_creative_inventory_action(&mut self, mut rbuf: &[u8]) {
+    fn handle_creative_inventory_action(&mut self, mut rbuf: &[u8]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let slot = rbuf.read_short()?; // Slot
+        let item = match read_slot(&mut rbuf) { // Clicked Item
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Invalid creative inventory action packet: {}", e);
+                return Ok(());
+            }
+        };
+
+        let client = self.client.read().unwrap();
+        client.handle_creative_inventory_action(slot, item);
+
+        Ok(())
+    }
+
+    /// Sent when the player is done editing a sign, the lines are JSON chat components
+    fn handle_update_sign(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let _slot = rbuf.read_short().unwrap();
-        // TODO: handle slot data
+        let (x, y, z) = rbuf.read_position()?; // Location
+        let mut lines: [String; 4] = Default::default();
+        for line in &mut lines {
+            *line = match rbuf.read_string() { // Line 1-4
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Invalid update sign packet: {}", e);
+                    return Ok(());
+                }
+            };
+        }
+
+        let client = self.client.read().unwrap();
+        client.handle_update_sign(Coord::new(x, y, z), lines);
+
+        Ok(())
     }
 
     /// The latter 2 values are used to indicate the walking and flying speeds respectively,
     /// while the first byte is used to determine the value of 4 booleans.
     /// The vanilla client sends this packet when the player starts/stops flying
     /// with the Flags parameter changed accordingly. All other parameters are ignored by the vanilla server.
-    fn handle_player_abilities(&mut self, mut rbuf: &[u8]) {
+    fn handle_player_abilities(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let _abilities = Abilities::from_bits_truncate(rbuf.read_ubyte().unwrap());
-        let _flying_speed = rbuf.read_float().unwrap();
-        let _walking_speed = rbuf.read_float().unwrap();
+        let abilities = Abilities::from_bits_truncate(rbuf.read_ubyte()?);
+        let _flying_speed = rbuf.read_float()?;
+        let _walking_speed = rbuf.read_float()?;
+
+        let client = self.client.read().unwrap();
+        client.handle_player_abilities(abilities.contains(Abilities::FLYING));
+
+        Ok(())
     }
 
     /// Sent when the player connects, or when settings are changed.
-    fn handle_client_settings(&mut self, mut rbuf: &[u8]) {
+    fn handle_client_settings(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         // TODO: Do something with the settings
-        let locale = rbuf.read_string().unwrap();
+        let locale = rbuf.read_string()?;
         debug!("Locale: {}", locale);
-        let view_distance = rbuf.read_byte().unwrap();
+        let view_distance = rbuf.read_byte()?;
         debug!("View Distance: {}", view_distance);
         // TODO: create an enum
-        let _bchat_mode = rbuf.read_byte().unwrap();
-        let _chat_colors = rbuf.read_bool().unwrap();
+        let _bchat_mode = rbuf.read_byte()?;
+        let _chat_colors = rbuf.read_bool()?;
         // Bit      | Meaning
         // ----------------------------------
         // 0 (0x01) | Cape enabled
@@ -866,14 +1299,16 @@ impl Protocol {
         // 5 (0x20) | Right Pants Leg enabled
         // 6 (0x40) | Hat enabled
         // 7 (0x80) | !Unused
-        let _skin_parts = SkinFlags::from_bits_truncate(rbuf.read_ubyte().unwrap());
+        let _skin_parts = SkinFlags::from_bits_truncate(rbuf.read_ubyte()?);
+
+        Ok(())
     }
 
     /// Sent when the client is ready to complete login and when the client is ready to respawn after death.
-    fn handle_client_status(&mut self, mut rbuf: &[u8]) {
+    fn handle_client_status(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        let action_id = rbuf.read_var_int().unwrap(); // Action ID
+        let action_id = rbuf.read_var_int()?; // Action ID
 
         // Action ID | Action
         // ----------------------------------------
@@ -882,41 +1317,84 @@ impl Protocol {
         // 2         | Taking Inventory achievement
 
         match action_id {
-            0 => (), // TODO: respawn
-            1 => (), // TODO: Stats
+            0 => self.client.read().unwrap().handle_respawn(),
+            1 => self.client.read().unwrap().handle_request_stats(),
             2 => (), // TODO // Taking Inventory achievement
-            _ => {
-                error!("Action ID is out of range (0..2), got {}", action_id);
-                self.disconnect("Hacked client").unwrap();
-            }
+            _ => return Err(ProtocolError::Protocol(format!("Action ID is out of range (0..2), got {}", action_id)))
         }
+
+        Ok(())
     }
 
     /// Mods and plugins can use this to send their data.
     /// Minecraft's internal channels are prefixed with MC|.
-    fn handle_plugin_message(&mut self, mut rbuf: &[u8]) {
+    fn handle_plugin_message(&mut self, mut rbuf: &[u8]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
-        // TODO: Do something
-        let channel = rbuf.read_string().unwrap();
+        let channel = rbuf.read_string()?;
         debug!("Channel: {}", channel);
         let mut data = Vec::new();
-        rbuf.read_to_end(&mut data).unwrap();
+        rbuf.read_to_end(&mut data)?;
+
+        if channel == "MC|BEdit" || channel == "MC|BSign" {
+            let book = match read_slot(&mut data.as_slice()) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Invalid book in {} message: {}", channel, e);
+                    return Ok(());
+                }
+            };
+
+            let client = self.client.read().unwrap();
+            client.handle_edit_book(book, channel == "MC|BSign");
+        } else if channel == "MC|ItemName" {
+            // An empty message clears the name
+            let name = if data.is_empty() {
+                String::new()
+            } else {
+                data.as_slice().read_string()?
+            };
+
+            // Like vanilla the formatting character and control characters are left out
+            let name = name.chars().filter(|c| *c != '\u{a7}' && !c.is_control()).collect();
+            self.client.read().unwrap().handle_item_name(name);
+        } else if channel.starts_with("FML") || channel == "FORGE" {
+            // Like a vanilla server we never answer the Forge handshake.
+            // Forge falls back to vanilla compatibility mode after receiving Join Game
+            // without a server hello, so replying with anything would only confuse it.
+            debug!("Ignoring Forge message, forge client: {}", self.forge_client);
+        }
+
+        Ok(())
+    }
+
+    /// Restarts the keep alive timeout, logging in may have taken longer than it allows
+    fn start_keep_alive(&mut self, now: Instant) {
+        self.last_keep_alive = now;
+        self.next_keep_alive = Some(now + KEEP_ALIVE_GRACE);
+    }
+
+    /// Sends a keep alive if one is due at `now`
+    pub fn tick_keep_alive(&mut self, now: Instant, id: i32) {
+        match self.next_keep_alive {
+            Some(v) if now >= v => self.next_keep_alive = Some(now + self.server.keep_alive_interval()),
+            _ => return
+        }
+
+        self.keep_alive(id);
     }
 
-    pub fn keep_alive(&mut self, id: i32) {
+    fn keep_alive(&mut self, id: i32) {
         if self.state != State::Play {
             return;
         }
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x00).unwrap(); // Keep Alive packet
-        wbuf.write_var_int(id).unwrap(); // Keep Alive ID
+        wbuf.write_var_int(ClientboundPlay::KeepAlive as i32).unwrap();
+        self.version.write_keep_alive(&mut wbuf, id);
 
         if let Err(e) = self.write_packet(&wbuf) {
-            if Protocol::is_disconnection_error(e.kind()) {
-                self.state = State::Disconnected;
-            }
+            self.handle_error(e);
         }
     }
 
@@ -924,26 +1402,26 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x01).unwrap(); // Join Game packet
+        wbuf.write_var_int(ClientboundPlay::JoinGame as i32).unwrap();
 
-        {
+        let (entity_id, gamemode) = {
             let p = player.read().unwrap();
-            {
-                let client_lock = p.client();
-                let c = client_lock.read().unwrap();
-                wbuf.write_int(c.id() as i32).unwrap(); // The player's Entity ID
-            }
-            wbuf.write_ubyte(p.gamemode() as u8).unwrap(); // Gamemode
-        }
-        {
+            let client_lock = p.client();
+            let c = client_lock.read().unwrap();
+            (c.id() as i32, p.gamemode() as u8)
+        };
+        let (dimension, difficulty) = {
             let w = world.read().unwrap();
-            wbuf.write_byte(w.dimension() as i8).unwrap(); // Dimension
-        }
-
-        wbuf.write_ubyte(self.server.difficulty() as u8).unwrap(); // Difficulty
-        wbuf.write_ubyte(self.server.max_players() as u8).unwrap(); // Max players
-        wbuf.write_string(self.server.level_type()).unwrap(); // Level Type? (default, flat, largeBiomes, amplified, default_1_1)
-        wbuf.write_bool(false).unwrap(); // Reduced debug info?
+            (w.dimension() as i8, w.difficulty() as u8)
+        };
+        self.version.write_join_game(&mut wbuf, &JoinGame {
+            entity_id,
+            gamemode,
+            dimension,
+            difficulty,
+            max_players: self.server.max_players() as u8,
+            level_type: self.server.level_type()
+        });
 
         self.write_packet(&wbuf)
     }
@@ -952,24 +1430,28 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x02).unwrap(); // Chat Message packet
+        wbuf.write_var_int(ClientboundPlay::ChatMessage as i32).unwrap();
 
         // TODO:
-        wbuf.write_string(&format!("{{ \"text\": \"{}\" }}", raw_msg)).unwrap(); // JSON Data
-        wbuf.write_ubyte(0).unwrap(); // Position: 0: chat (chat box), 1: system message (chat box), 2: above hotbar
+        self.version.write_chat_message(&mut wbuf, &format!("{{ \"text\": \"{}\" }}", raw_msg));
 
         self.write_packet(&wbuf)
     }
 
-    fn time_update(&mut self, _world: Arc<RwLock<World>>) -> Result<()> {
+    fn time_update(&mut self, world: Arc<RwLock<World>>) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x03).unwrap(); // Time Update packet
-
-        // TODO: write actual values
-        wbuf.write_long(0).unwrap(); // World Age
-        wbuf.write_long(0).unwrap(); // Time of day
+        wbuf.write_var_int(ClientboundPlay::TimeUpdate as i32).unwrap();
+
+        let w = world.read().unwrap();
+        wbuf.write_long(w.age()).unwrap(); // World Age
+        // A negative time of day stops the client from advancing the sun on its own
+        let mut time_of_day = w.time_of_day();
+        if !w.game_rules().get_bool(game_rules::DO_DAYLIGHT_CYCLE) {
+            time_of_day = -time_of_day.max(1);
+        }
+        wbuf.write_long(time_of_day).unwrap(); // Time of day
 
         self.write_packet(&wbuf)
     }
@@ -978,31 +1460,74 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x05).unwrap(); // Spawn Position packet
+        wbuf.write_var_int(ClientboundPlay::SpawnPosition as i32).unwrap();
 
         let spawn_pos = world.read().unwrap().spawn_pos();
-        wbuf.write_position(spawn_pos.x, spawn_pos.y, spawn_pos.z).unwrap(); // Spawn location
+        self.version.write_spawn_position(&mut wbuf, spawn_pos);
 
         self.write_packet(&wbuf)
     }
 
-    fn player_pos_look(&mut self, player: Arc<RwLock<Player>>) -> Result<()> {
+    /// Sent by the server to respawn a dead player or to move the player to another dimension.
+    fn respawn(&mut self, world: Arc<RwLock<World>>, gamemode: GameMode) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x08).unwrap(); // Player Position And Look packet
+        wbuf.write_var_int(ClientboundPlay::Respawn as i32).unwrap();
 
         {
-            let p = player.read().unwrap();
-            let pos = p.pos();
-            wbuf.write_double(pos.x).unwrap(); // X
-            wbuf.write_double(pos.y).unwrap(); // y
-            wbuf.write_double(pos.z).unwrap(); // z
-            wbuf.write_float(p.yaw()).unwrap(); // Yaw
-            wbuf.write_float(p.pitch()).unwrap(); // Pitch
-            wbuf.write_byte(0).unwrap(); // Flags
+            let w = world.read().unwrap();
+            wbuf.write_int(w.dimension() as i32).unwrap(); // Dimension
+            wbuf.write_ubyte(w.difficulty() as u8).unwrap(); // Difficulty
         }
 
+        wbuf.write_ubyte(gamemode as u8).unwrap(); // Gamemode
+        wbuf.write_string(self.server.level_type()).unwrap(); // Level Type
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sent by the server to update/set the health of the player it is sent to.
+    fn update_health(&mut self, health: f32, food: i32, saturation: f32) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::UpdateHealth as i32).unwrap();
+        self.version.write_update_health(&mut wbuf, health, food, saturation);
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sets the experience level shown above the hotbar, players only have whole levels.
+    fn set_experience(&mut self, level: i32) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::SetExperience as i32).unwrap();
+
+        wbuf.write_float(0.0).unwrap(); // Experience bar
+        wbuf.write_var_int(level).unwrap(); // Level
+        wbuf.write_var_int(0).unwrap(); // Total Experience
+
+        self.write_packet(&wbuf)
+    }
+
+    fn player_pos_look(&mut self, player: Arc<RwLock<Player>>) -> Result<()> {
+        let (pos, yaw, pitch) = {
+            let p = player.read().unwrap();
+            (p.pos(), p.yaw(), p.pitch())
+        };
+
+        self.teleport(pos, yaw, pitch, TeleportFlags::empty(), (pos, yaw, pitch))
+    }
+
+    fn teleport(&mut self, pos: Coord<f64>, yaw: f32, pitch: f32, flags: TeleportFlags, absolute: (Coord<f64>, f32, f32)) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::PlayerPositionAndLook as i32).unwrap();
+        self.version.write_player_pos_look(&mut wbuf, pos, yaw, pitch, flags, absolute);
+
         self.write_packet(&wbuf)
     }
 
@@ -1010,7 +1535,7 @@ impl Protocol {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x0C).unwrap(); // Player Spawn packet
+        wbuf.write_var_int(ClientboundPlay::SpawnPlayer as i32).unwrap();
 
         {
             let p = player.read().unwrap();
@@ -1056,184 +1581,2238 @@ impl Protocol {
         self.write_packet(&wbuf)
     }
 
-    /// Chunks are not unloaded by the client automatically.
-    /// To unload chunks, send this packet with Ground-Up Continuous=true and no 16^3 chunks (eg. Primary Bit Mask=0).
-    /// The server does not send skylight information for nether-chunks,
-    /// it's up to the client to know if the player is currently in the nether.
-    /// You can also infer this information from the primary bitmask and the amount of uncompressed bytes sent.
-    fn chunk_data(&mut self, coord: ChunkCoord, chunk_map: Arc<ChunkMap>) -> Result<()> {
+    /// Sent by the server when a vehicle or other object is created.
+    fn spawn_object(&mut self, entity: Arc<RwLock<Entity>>) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x21).unwrap(); // Chunk Data packet
+        wbuf.write_var_int(ClientboundPlay::SpawnObject as i32).unwrap();
 
-        // TODO: write actual values
-        wbuf.write_int(coord.x).unwrap(); // Chunk X
-        wbuf.write_int(coord.z).unwrap(); // Chunk Z
+        let entity = entity.read().unwrap();
+        if let EntityType::Mob(mob_type) = entity.entity_type() {
+            let metadata = [(metadata::HEALTH_INDEX, MetadataValue::Float(entity.health().unwrap_or_default()))];
+            return self.spawn_mob(entity.id(), mob_type as u8, entity.pos(), (entity.yaw(), entity.pitch()), entity.velocity(), &metadata);
+        }
 
-        // This is true if the packet represents all sections in this vertical column,
-        // where the Primary Bit Mask specifies exactly which sections are included, and which are air
-        wbuf.write_bool(true).unwrap(); // Ground-Up Continuous
+        let object_id = match entity.entity_type().object_id() {
+            Some(v) => v,
+            None => return self.spawn_painting(&entity)
+        };
+        wbuf.write_var_int(entity.id() as i32).unwrap(); // Entity ID
+        wbuf.write_ubyte(object_id).unwrap(); // Type
+
+        let pos = entity.pos();
+        // Fixed-point numbers with 5 fraction bits
+        wbuf.write_int((pos.x * 32f64).floor() as i32).unwrap(); // X
+        wbuf.write_int((pos.y * 32f64).floor() as i32).unwrap(); // Y
+        wbuf.write_int((pos.z * 32f64).floor() as i32).unwrap(); // Z
+        wbuf.write_byte(to_angle(entity.pitch())).unwrap(); // Pitch
+        wbuf.write_byte(to_angle(entity.yaw())).unwrap(); // Yaw
+
+        // Meaning depends on the type, the velocity is only sent if it isn't 0
+        let data = entity.object_data();
+        wbuf.write_int(data).unwrap(); // Data
+        if data != 0 {
+            let velocity = entity.velocity();
+            wbuf.write_short(to_velocity(velocity.x)).unwrap(); // Velocity X
+            wbuf.write_short(to_velocity(velocity.y)).unwrap(); // Velocity Y
+            wbuf.write_short(to_velocity(velocity.z)).unwrap(); // Velocity Z
+        }
 
-        chunk_map.do_with_chunk(coord, |chunk: &Chunk| {
-            let bit_mask = chunk.data.get_primary_bit_mask();
-            wbuf.write_ushort(bit_mask).unwrap(); // Primary Bit Mask
+        self.write_packet(&wbuf)
+    }
 
-            chunk.serialize(&mut wbuf).unwrap();
-        });
+    /// Sent by the server when a mob entity is spawned, `rotation` is the yaw and pitch
+    fn spawn_mob(&mut self, entity_id: u32, mob_type: u8, pos: Coord<f64>, rotation: (f32, f32), velocity: Coord<f64>, metadata: &[(u8, MetadataValue)]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::SpawnMob as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_ubyte(mob_type).unwrap(); // Type
+        // Fixed-point numbers with 5 fraction bits
+        wbuf.write_int((pos.x * 32f64).floor() as i32).unwrap(); // X
+        wbuf.write_int((pos.y * 32f64).floor() as i32).unwrap(); // Y
+        wbuf.write_int((pos.z * 32f64).floor() as i32).unwrap(); // Z
+        wbuf.write_byte(to_angle(rotation.0)).unwrap(); // Yaw
+        wbuf.write_byte(to_angle(rotation.1)).unwrap(); // Pitch
+        // The head looks the same way as the body
+        wbuf.write_byte(to_angle(rotation.0)).unwrap(); // Head Pitch
+        wbuf.write_short(to_velocity(velocity.x)).unwrap(); // Velocity X
+        wbuf.write_short(to_velocity(velocity.y)).unwrap(); // Velocity Y
+        wbuf.write_short(to_velocity(velocity.z)).unwrap(); // Velocity Z
+        write_metadata(&mut wbuf, metadata)?; // Metadata
 
         self.write_packet(&wbuf)
     }
 
-    /// https://wiki.vg/index.php?title=Protocol&oldid=7368#Change_Game_State
-    fn change_game_state(&mut self, reason: GameStateReason, value: f32) -> Result<()> {
+    /// Sent by the server when a painting is created.
+    fn spawn_painting(&mut self, entity: &Entity) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x2B).unwrap(); // Change Game State packet
+        wbuf.write_var_int(ClientboundPlay::SpawnPainting as i32).unwrap();
 
-        wbuf.write_ubyte(reason as u8).unwrap(); // Reason
-        wbuf.write_float(value).unwrap(); // Value
+        wbuf.write_var_int(entity.id() as i32).unwrap(); // Entity ID
+        wbuf.write_string(entity.motive().map_or("Kebab", Motive::name)).unwrap(); // Title
+        let pos = entity.pos();
+        wbuf.write_position(pos.x.floor() as i32, pos.y.floor() as i32, pos.z.floor() as i32).unwrap(); // Location
+        let direction = entity.facing().and_then(BlockFace::horizontal_index).unwrap_or_default();
+        wbuf.write_ubyte(direction).unwrap(); // Direction
 
         self.write_packet(&wbuf)
     }
 
-    fn player_list_item(&mut self, action: PlayerListAction, players: Box<[Arc<RwLock<Player>>]>) -> Result<()> {
+    /// Sent by the server when a list of entities is to be destroyed on the client.
+    fn destroy_entities(&mut self, entity_ids: &[u32]) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x38).unwrap(); // Player List Item packet
+        wbuf.write_var_int(ClientboundPlay::DestroyEntities as i32).unwrap();
 
-        wbuf.write_var_int(action as i32).unwrap(); // Action
-        wbuf.write_var_int(players.len() as i32).unwrap(); // Number Of Players
+        wbuf.write_var_int(entity_ids.len() as i32).unwrap(); // Count
+        for id in entity_ids {
+            wbuf.write_var_int(*id as i32).unwrap(); // Entity IDs
+        }
 
-        for player in players.iter() {
-            let player = player.read().unwrap();
-            let client = player.client();
-            let client = client.read().unwrap();
+        self.write_packet(&wbuf)
+    }
 
-            wbuf.write_all(client.uuid().as_bytes()).unwrap(); // UUID
+    /// Sent when an entity moved less than 4 blocks on every axis.
+    fn entity_relative_move(&mut self, entity_id: u32, delta: Coord<i8>, on_ground: bool) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
 
-            match action {
-                PlayerListAction::AddPlayer => {
-                    wbuf.write_string(client.get_username().unwrap()).unwrap();
-                    if let Some(properties) = client.properties().as_array()
-                    {
-                        wbuf.write_var_int(properties.len() as i32).unwrap();
-                        for prop in properties {
-                            wbuf.write_string(prop["name"].as_str().unwrap()).unwrap();
-                            wbuf.write_string(prop["value"].as_str().unwrap()).unwrap();
-                            let signature = prop.get("signature");
-                            wbuf.write_bool(signature.is_some()).unwrap();
-                            if let Some(signature) = signature {
-                                wbuf.write_string(signature.as_str().unwrap()).unwrap();
-                            }
-                        }
-                    }
-                    else {
-                        wbuf.write_var_int(0).unwrap();
-                    }
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::EntityRelativeMove as i32).unwrap();
 
-                    wbuf.write_var_int(player.gamemode() as i32).unwrap(); // Gamemode
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_byte(delta.x).unwrap(); // Delta X
+        wbuf.write_byte(delta.y).unwrap(); // Delta Y
+        wbuf.write_byte(delta.z).unwrap(); // Delta Z
+        wbuf.write_bool(on_ground).unwrap(); // On Ground
 
-                    // TODO: calculate actual ping
-                    wbuf.write_var_int(250).unwrap(); // Ping
+        self.write_packet(&wbuf)
+    }
 
-                    wbuf.write_bool(false).unwrap(); // Has Display Name
-                }
-                PlayerListAction::UpdateGamemode => wbuf.write_var_int(player.gamemode() as i32).unwrap(), // Gamemode
-                PlayerListAction::UpdateLatency => wbuf.write_var_int(250).unwrap(), // Ping
-                PlayerListAction::UpdateDisplayName => wbuf.write_bool(false).unwrap(), // Has Display Name,
-                PlayerListAction::RemovePlayer => ()
-            }
-        }
+    fn entity_look(&mut self, entity_id: u32, yaw: f32, pitch: f32, on_ground: bool) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::EntityLook as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_byte(to_angle(yaw)).unwrap(); // Yaw
+        wbuf.write_byte(to_angle(pitch)).unwrap(); // Pitch
+        wbuf.write_bool(on_ground).unwrap(); // On Ground
 
         self.write_packet(&wbuf)
     }
 
-    fn player_abilities(&mut self, player: Arc<RwLock<Player>>) -> Result<()> {
+    fn entity_look_relative_move(&mut self, entity_id: u32, delta: Coord<i8>, yaw: f32, pitch: f32, on_ground: bool) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x39).unwrap(); // Player Abilities packet
+        wbuf.write_var_int(ClientboundPlay::EntityLookAndRelativeMove as i32).unwrap();
 
-        {
-            let p = player.read().unwrap();
-            wbuf.write_ubyte(p.abilities().bits()).unwrap();
-        }
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_byte(delta.x).unwrap(); // Delta X
+        wbuf.write_byte(delta.y).unwrap(); // Delta Y
+        wbuf.write_byte(delta.z).unwrap(); // Delta Z
+        wbuf.write_byte(to_angle(yaw)).unwrap(); // Yaw
+        wbuf.write_byte(to_angle(pitch)).unwrap(); // Pitch
+        wbuf.write_bool(on_ground).unwrap(); // On Ground
 
-        wbuf.write_float(0.05 * 1.0).unwrap(); // Flying Speed
-        // Modifies the field of view, like a speed potion.
-        // A Notchian server will use the same value as the movement speed
-        wbuf.write_float(0.1 * 1.0).unwrap(); // Field of View Modifier
+        self.write_packet(&wbuf)
+    }
+
+    /// Sent when an entity moved 4 blocks or more.
+    fn entity_teleport(&mut self, entity_id: u32, pos: Coord<f64>, yaw: f32, pitch: f32, on_ground: bool) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::EntityTeleport as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_int((pos.x * 32f64).floor() as i32).unwrap(); // X
+        wbuf.write_int((pos.y * 32f64).floor() as i32).unwrap(); // Y
+        wbuf.write_int((pos.z * 32f64).floor() as i32).unwrap(); // Z
+        wbuf.write_byte(to_angle(yaw)).unwrap(); // Yaw
+        wbuf.write_byte(to_angle(pitch)).unwrap(); // Pitch
+        wbuf.write_bool(on_ground).unwrap(); // On Ground
 
         self.write_packet(&wbuf)
     }
 
-    /// Changes the difficulty setting in the client's option menu
-    fn server_difficulty(&mut self, difficulty: Difficulty) -> Result<()> {
+    /// Changes the direction an entity's head is facing.
+    fn entity_head_look(&mut self, entity_id: u32, yaw: f32) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x41).unwrap(); // Server Difficulty packet
+        wbuf.write_var_int(ClientboundPlay::EntityHeadLook as i32).unwrap();
 
-        wbuf.write_ubyte(difficulty as u8).unwrap(); // Difficulty
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_byte(to_angle(yaw)).unwrap(); // Head Yaw
 
         self.write_packet(&wbuf)
     }
 
-    fn resource_pack_send(&mut self, url: &str, hash: &str) -> Result<()> {
+    fn entity_status(&mut self, entity_id: u32, status: EntityStatus) -> Result<()> {
         debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(0x48).unwrap(); // Resource Pack Send packet
+        wbuf.write_var_int(ClientboundPlay::EntityStatus as i32).unwrap();
 
-        wbuf.write_string(url).unwrap(); // URL
-        wbuf.write_string(hash).unwrap(); // Hash
+        wbuf.write_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_byte(status as i8).unwrap(); // Entity Status
 
         self.write_packet(&wbuf)
     }
 
-    // Other packets:
-    fn disconnect(&mut self, reason: &str) -> Result<()> {
-        debug_assert!(self.state == State::Login || self.state == State::Play);
+    fn entity_velocity(&mut self, entity_id: u32, velocity: Coord<f64>) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
 
         let mut wbuf = Vec::new();
-        wbuf.write_var_int(
-            match self.state {
-                State::Login => 0x00,
-                State::Play => 0x40,
-                _ => panic!("Unknown state for Disconnect Packet: {:?}", self.state)
+        wbuf.write_var_int(ClientboundPlay::EntityVelocity as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_short(to_velocity(velocity.x)).unwrap(); // Velocity X
+        wbuf.write_short(to_velocity(velocity.y)).unwrap(); // Velocity Y
+        wbuf.write_short(to_velocity(velocity.z)).unwrap(); // Velocity Z
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Shows the player lying in the bed
+    fn use_bed(&mut self, entity_id: u32, pos: Coord<i32>) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::UseBed as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_position(pos.x, pos.y, pos.z).unwrap(); // Location
+
+        self.write_packet(&wbuf)
+    }
+
+    fn animation(&mut self, entity_id: u32, animation: Animation) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::Animation as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_ubyte(animation as u8).unwrap(); // Animation
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Tells the player it died, this opens the death screen.
+    fn combat_event(&mut self, player_id: u32, killer_id: Option<u32>, message: &str) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::CombatEvent as i32).unwrap();
+
+        // 0: enter combat, 1: end combat, 2: entity dead
+        wbuf.write_var_int(2).unwrap(); // Event
+        wbuf.write_var_int(player_id as i32).unwrap(); // Player ID
+        // -1 if the player wasn't killed by an entity
+        wbuf.write_int(killer_id.map_or(-1, |x| x as i32)).unwrap(); // Entity ID
+        wbuf.write_string(&json!({ "text": message }).to_string()).unwrap(); // Message
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sent when an entity starts or stops riding another entity, or gets (un)leashed.
+    fn attach_entity(&mut self, entity_id: u32, vehicle_id: Option<u32>, leash: bool) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::AttachEntity as i32).unwrap();
+
+        wbuf.write_int(entity_id as i32).unwrap(); // Entity ID
+        // -1 detaches the entity
+        wbuf.write_int(vehicle_id.map_or(-1, |x| x as i32)).unwrap(); // Vehicle ID
+        // If true leashes the entity to the vehicle
+        wbuf.write_bool(leash).unwrap(); // Leash
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Updates one or more metadata properties for an existing entity.
+    fn entity_metadata(&mut self, entity_id: u32, metadata: &[(u8, MetadataValue)]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::EntityMetadata as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        write_metadata(&mut wbuf, metadata)?; // Metadata
+
+        self.write_packet(&wbuf)
+    }
+
+    fn entity_effect(&mut self, entity_id: u32, effect: Effect) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::EntityEffect as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_ubyte(effect.effect_type as u8).unwrap(); // Effect ID
+        wbuf.write_ubyte(effect.amplifier).unwrap(); // Amplifier
+        wbuf.write_var_int(effect.duration).unwrap(); // Duration
+        wbuf.write_bool(effect.hide_particles).unwrap(); // Hide Particles
+
+        self.write_packet(&wbuf)
+    }
+
+    fn remove_entity_effect(&mut self, entity_id: u32, effect_type: EffectType) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::RemoveEntityEffect as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_ubyte(effect_type as u8).unwrap(); // Effect ID
+
+        self.write_packet(&wbuf)
+    }
+
+    fn entity_properties(&mut self, entity_id: u32, attributes: &Attributes) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::EntityProperties as i32).unwrap();
+
+        wbuf.write_var_int(entity_id as i32).unwrap(); // Entity ID
+        wbuf.write_int(attributes.iter().count() as i32).unwrap(); // Number Of Properties
+        for attribute in attributes.iter() {
+            wbuf.write_string(attribute.attribute_type.key()).unwrap(); // Key
+            wbuf.write_double(attribute.base).unwrap(); // Value
+            wbuf.write_var_int(attribute.modifiers.len() as i32).unwrap(); // Number Of Modifiers
+            for modifier in &attribute.modifiers {
+                wbuf.write_all(modifier.uuid.as_bytes()).unwrap(); // UUID
+                wbuf.write_double(modifier.amount).unwrap(); // Amount
+                wbuf.write_ubyte(modifier.operation as u8).unwrap(); // Operation
             }
-        )?; // Disconnect packet
+        }
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Chunks are not unloaded by the client automatically.
+    /// To unload chunks, send this packet with Ground-Up Continuous=true and no 16^3 chunks (eg. Primary Bit Mask=0).
+    /// The server does not send skylight information for nether-chunks,
+    /// it's up to the client to know if the player is currently in the nether.
+    /// You can also infer this information from the primary bitmask and the amount of uncompressed bytes sent.
+    fn chunk_data(&mut self, coord: ChunkCoord, chunk_map: Arc<ChunkMap>, dimension: Dimension) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
 
-        info!("Kicking with reason: '{}'", reason);
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::ChunkData as i32).unwrap();
 
-        let reason = json!({
-            "text": reason
+        let context = SerializeContext::new(dimension);
+        let mut loaded = false;
+        chunk_map.do_with_chunk(coord, |chunk: &Chunk| {
+            self.version.write_chunk_data(&mut wbuf, coord, Some(chunk), &context);
+            loaded = true;
         });
-        wbuf.write_string(&reason.to_string())?;
-        self.write_packet(&wbuf)?;
-        self.shutdown()
+        // A chunk that isn't loaded anymore shouldn't stay loaded on the client either
+        if !loaded {
+            self.version.write_chunk_data(&mut wbuf, coord, None, &context);
+        }
+
+        self.write_packet(&wbuf)
     }
 
-    fn shutdown(&mut self) -> Result<()> {
-        self.state = State::Disconnected;
-        self.stream.shutdown(Shutdown::Both)?;
-        Ok(())
+    /// Chunk Data without any sections, which makes the client unload the chunk.
+    /// The chunk may already be gone from the chunk map, so this doesn't look at it.
+    fn chunk_data_unload(&mut self, coord: ChunkCoord) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::ChunkData as i32).unwrap();
+        self.version.write_chunk_data(&mut wbuf, coord, None, &SerializeContext::default());
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Fired whenever 2 or more blocks are changed within the same chunk on the same tick.
+    fn multi_block_change(&mut self, coord: ChunkCoord, records: &[(Coord<i32>, BlockType, u8)]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::MultiBlockChange as i32).unwrap();
+        self.version.write_multi_block_change(&mut wbuf, coord, records);
+
+        self.write_packet(&wbuf)
     }
 
-    fn is_disconnection_error(e: ErrorKind) -> bool {
-        e == ErrorKind::NotConnected
-            || e == ErrorKind::ConnectionAborted
-            || e == ErrorKind::ConnectionRefused
-            || e == ErrorKind::ConnectionReset
-            || e == ErrorKind::BrokenPipe
+    /// Fired whenever a block is changed within the render distance.
+    fn block_change(&mut self, pos: Coord<i32>, block_type: BlockType, meta: u8) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::BlockChange as i32).unwrap();
+        self.version.write_block_change(&mut wbuf, pos, block_type, meta);
+
+        self.write_packet(&wbuf)
     }
-}
 
-impl Drop for Protocol {
-    fn drop(&mut self) {
-        self.server.remove_client(self.client_id);
+    /// Sends several chunk columns at once, the chunks have to be loaded.
+    fn map_chunk_bulk(&mut self, coords: &[ChunkCoord], chunk_map: Arc<ChunkMap>, dimension: Dimension) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::MapChunkBulk as i32).unwrap();
+        self.version.write_map_chunk_bulk(&mut wbuf, coords, &chunk_map, &SerializeContext::new(dimension));
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sent when an explosion occurs (creepers, TNT, and ghast fireballs).
+    fn explosion(&mut self, center: Coord<f64>, radius: f32, records: &[Coord<i32>], motion: Coord<f64>) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::Explosion as i32).unwrap();
+
+        wbuf.write_float(center.x as f32).unwrap(); // X
+        wbuf.write_float(center.y as f32).unwrap(); // Y
+        wbuf.write_float(center.z as f32).unwrap(); // Z
+        wbuf.write_float(radius).unwrap(); // Radius
+        wbuf.write_int(records.len() as i32).unwrap(); // Record Count
+        let (x, y, z) = (center.x.floor() as i32, center.y.floor() as i32, center.z.floor() as i32);
+        for pos in records {
+            // Offsets from the block the explosion happened in
+            wbuf.write_byte((pos.x - x) as i8).unwrap();
+            wbuf.write_byte((pos.y - y) as i8).unwrap();
+            wbuf.write_byte((pos.z - z) as i8).unwrap();
+        }
+
+        // Velocity of the player being pushed by the explosion
+        wbuf.write_float(motion.x as f32).unwrap(); // Player Motion X
+        wbuf.write_float(motion.y as f32).unwrap(); // Player Motion Y
+        wbuf.write_float(motion.z as f32).unwrap(); // Player Motion Z
+
+        self.write_packet(&wbuf)
+    }
+
+    /// https://wiki.vg/index.php?title=Protocol&oldid=7368#Change_Game_State
+    fn change_game_state(&mut self, reason: GameStateReason, value: f32) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::ChangeGameState as i32).unwrap();
+
+        wbuf.write_ubyte(reason as u8).unwrap(); // Reason
+        wbuf.write_float(value).unwrap(); // Value
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sent to the client when it should open an inventory, such as a chest.
+    fn open_window(&mut self, window_id: u8, window_type: WindowType, slots: u8) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::OpenWindow as i32).unwrap();
+
+        wbuf.write_ubyte(window_id).unwrap(); // Window ID
+        wbuf.write_string(window_type.id()).unwrap(); // Window Type
+        wbuf.write_string(window_type.title()).unwrap(); // Window Title
+        // Not counting the player inventory
+        wbuf.write_ubyte(slots).unwrap(); // Number Of Slots
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Forces the client to close the window, like when the chest it shows is destroyed.
+    fn close_window(&mut self, window_id: u8) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::CloseWindow as i32).unwrap();
+
+        wbuf.write_ubyte(window_id).unwrap(); // Window ID
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sent by the server when an item in a slot (in a window) is added/removed.
+    fn set_slot(&mut self, window_id: i8, slot: i16, item: Option<&ItemStack>) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::SetSlot as i32).unwrap();
+
+        // 0 is the player inventory, -1 with slot -1 sets the item held by the cursor
+        wbuf.write_byte(window_id).unwrap(); // Window ID
+        wbuf.write_short(slot).unwrap(); // Slot
+        write_slot(&mut wbuf, item)?; // Slot Data
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sets the contents of every slot of the window.
+    fn window_items(&mut self, window_id: u8, items: &[Option<ItemStack>]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::WindowItems as i32).unwrap();
+
+        wbuf.write_ubyte(window_id).unwrap(); // Window ID
+        wbuf.write_short(items.len() as i16).unwrap(); // Count
+        for item in items {
+            write_slot(&mut wbuf, item.as_ref())?; // Slot Data
+        }
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Updates a property of the window, like the progress arrow of a furnace.
+    fn window_property(&mut self, window_id: u8, property: i16, value: i16) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::WindowProperty as i32).unwrap();
+
+        wbuf.write_ubyte(window_id).unwrap(); // Window ID
+        wbuf.write_short(property).unwrap(); // Property
+        wbuf.write_short(value).unwrap(); // Value
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Updates a rectangular area on a map item.
+    fn map(&mut self, id: i32, scale: u8, icons: &[MapIcon], update: Option<&MapUpdate>) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::Map as i32).unwrap();
+
+        wbuf.write_var_int(id).unwrap(); // Item Damage
+        wbuf.write_byte(scale as i8).unwrap(); // Scale
+        wbuf.write_var_int(icons.len() as i32).unwrap(); // Icon Count
+        for icon in icons {
+            wbuf.write_byte(((icon.direction << 4) | (icon.icon_type & 0x0f)) as i8).unwrap(); // Direction And Type
+            wbuf.write_byte(icon.x).unwrap(); // X
+            wbuf.write_byte(icon.z).unwrap(); // Z
+        }
+
+        match update {
+            Some(update) => {
+                wbuf.write_ubyte(update.columns).unwrap(); // Columns
+                wbuf.write_ubyte(update.rows).unwrap(); // Rows
+                wbuf.write_ubyte(update.x).unwrap(); // X
+                wbuf.write_ubyte(update.z).unwrap(); // Z
+                wbuf.write_var_int(update.data.len() as i32).unwrap(); // Length
+                wbuf.write_all(&update.data).unwrap(); // Data
+            }
+            // Only the icons are updated
+            None => wbuf.write_ubyte(0).unwrap() // Columns
+        }
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sent as a response to Client Status 0x16 (Request stats).
+    fn statistics(&mut self, stats: &[(String, i32)]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::Statistics as i32).unwrap();
+
+        wbuf.write_var_int(stats.len() as i32).unwrap(); // Count
+        for (name, value) in stats {
+            wbuf.write_string(name).unwrap(); // Name
+            wbuf.write_var_int(*value).unwrap(); // Value
+        }
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sets the tile entity associated with the block at the given location.
+    fn update_block_entity(&mut self, pos: Coord<i32>, tile_entity: &TileEntity) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::UpdateBlockEntity as i32).unwrap();
+
+        let action = match tile_entity.update_action() {
+            Some(v) => v,
+            // The client doesn't need this tile entity
+            None => return Ok(())
+        };
+
+        wbuf.write_position(pos.x, pos.y, pos.z).unwrap(); // Location
+        wbuf.write_ubyte(action).unwrap(); // Action
+        // Unlike 1.7, the NBT data isn't compressed
+        siderite_nbt::write(&mut wbuf, "", &Tag::Compound(tile_entity.to_nbt(pos)))?; // NBT Data
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Sets the text of the sign at the given location
+    fn update_sign(&mut self, pos: Coord<i32>, lines: &[String; 4]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::UpdateSign as i32).unwrap();
+
+        wbuf.write_position(pos.x, pos.y, pos.z).unwrap(); // Location
+        for line in lines {
+            wbuf.write_string(line).unwrap(); // Line 1-4
+        }
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Opens the sign editor for the sign at the given location, the sign has to be placed already
+    fn open_sign_editor(&mut self, pos: Coord<i32>) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::OpenSignEditor as i32).unwrap();
+
+        wbuf.write_position(pos.x, pos.y, pos.z).unwrap(); // Location
+
+        self.write_packet(&wbuf)
+    }
+
+    fn player_list_item(&mut self, action: PlayerListAction, players: &[Arc<RwLock<Player>>]) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::PlayerListItem as i32).unwrap();
+
+        wbuf.write_var_int(action as i32).unwrap(); // Action
+        wbuf.write_var_int(players.len() as i32).unwrap(); // Number Of Players
+
+        for player in players.iter() {
+            let player = player.read().unwrap();
+            let client = player.client();
+            let client = client.read().unwrap();
+
+            wbuf.write_all(client.uuid().as_bytes()).unwrap(); // UUID
+
+            match action {
+                PlayerListAction::AddPlayer => {
+                    wbuf.write_string(client.get_username().unwrap()).unwrap();
+                    if let Some(properties) = client.properties().as_array()
+                    {
+                        wbuf.write_var_int(properties.len() as i32).unwrap();
+                        for prop in properties {
+                            wbuf.write_string(prop["name"].as_str().unwrap()).unwrap();
+                            wbuf.write_string(prop["value"].as_str().unwrap()).unwrap();
+                            let signature = prop.get("signature");
+                            wbuf.write_bool(signature.is_some()).unwrap();
+                            if let Some(signature) = signature {
+                                wbuf.write_string(signature.as_str().unwrap()).unwrap();
+                            }
+                        }
+                    }
+                    else {
+                        wbuf.write_var_int(0).unwrap();
+                    }
+
+                    wbuf.write_var_int(player.gamemode() as i32).unwrap(); // Gamemode
+
+                    // TODO: calculate actual ping
+                    wbuf.write_var_int(250).unwrap(); // Ping
+
+                    write_display_name(&mut wbuf, player.display_name());
+                }
+                PlayerListAction::UpdateGamemode => wbuf.write_var_int(player.gamemode() as i32).unwrap(), // Gamemode
+                PlayerListAction::UpdateLatency => wbuf.write_var_int(250).unwrap(), // Ping
+                PlayerListAction::UpdateDisplayName => write_display_name(&mut wbuf, player.display_name()),
+                PlayerListAction::RemovePlayer => ()
+            }
+        }
+
+        self.write_packet(&wbuf)
+    }
+
+    fn player_abilities(&mut self, player: Arc<RwLock<Player>>) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::PlayerAbilities as i32).unwrap();
+
+        {
+            let p = player.read().unwrap();
+            wbuf.write_ubyte(p.abilities().bits()).unwrap();
+        }
+
+        wbuf.write_float(0.05 * 1.0).unwrap(); // Flying Speed
+        // Modifies the field of view, like a speed potion.
+        // A Notchian server will use the same value as the movement speed
+        wbuf.write_float(0.1 * 1.0).unwrap(); // Field of View Modifier
+
+        self.write_packet(&wbuf)
+    }
+
+    fn teams(&mut self, name: &str, action: &TeamAction) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::Teams as i32).unwrap();
+
+        wbuf.write_string(name).unwrap(); // Team Name
+        wbuf.write_ubyte(action.mode()).unwrap(); // Mode
+
+        match action {
+            TeamAction::Create(team) => {
+                write_team_info(&mut wbuf, team);
+                write_team_players(&mut wbuf, team.players());
+            }
+            TeamAction::Remove => (),
+            TeamAction::UpdateInfo(team) => write_team_info(&mut wbuf, team),
+            TeamAction::AddPlayers(players) | TeamAction::RemovePlayers(players) => write_team_players(&mut wbuf, players)
+        }
+
+        self.write_packet(&wbuf)
+    }
+
+    /// Changes the difficulty setting in the client's option menu
+    fn server_difficulty(&mut self, difficulty: Difficulty) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::ServerDifficulty as i32).unwrap();
+
+        wbuf.write_ubyte(difficulty as u8).unwrap(); // Difficulty
+
+        self.write_packet(&wbuf)
+    }
+
+    fn resource_pack_send(&mut self, url: &str, hash: &str) -> Result<()> {
+        debug_assert_eq!(self.state, State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(ClientboundPlay::ResourcePackSend as i32).unwrap();
+
+        wbuf.write_string(url).unwrap(); // URL
+        wbuf.write_string(hash).unwrap(); // Hash
+
+        self.write_packet(&wbuf)
+    }
+
+    // Other packets:
+    fn disconnect(&mut self, reason: &Value) -> Result<()> {
+        debug_assert!(self.state == State::Login || self.state == State::Play);
+
+        let mut wbuf = Vec::new();
+        wbuf.write_var_int(
+            match self.state {
+                State::Login => ClientboundLogin::Disconnect as i32,
+                State::Play => ClientboundPlay::Disconnect as i32,
+                _ => panic!("Unknown state for Disconnect Packet: {:?}", self.state)
+            }
+        )?; // Disconnect packet
+
+        info!("Kicking with reason: {}", reason);
+
+        wbuf.write_string(&reason.to_string())?; // Reason
+        // The queued chunks don't matter anymore, but the reason has to arrive before the connection closes.
+        // What the connection can't accept right away is written on the next ticks, the protocol thread is shared.
+        self.out_queue.clear_bulk();
+        let written = self.write_packet(&wbuf);
+        self.state = State::Disconnected;
+        if written.is_ok() && !self.out_queue.is_empty() {
+            self.close_deadline = Some(Instant::now() + DISCONNECT_TIMEOUT);
+            return Ok(());
+        }
+
+        self.shutdown()?;
+        written
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.state = State::Disconnected;
+        self.close_deadline = None;
+        self.stream.shutdown(Shutdown::Both)?;
+        Ok(())
+    }
+}
+
+impl Drop for Protocol {
+    fn drop(&mut self) {
+        self.server.remove_client(self.client_id);
+    }
+}
+
+/// Decodes the VarInt at the start of the data, which doesn't have to hold all of it.
+/// Returns the value and the number of bytes it takes up, None if the rest of the VarInt hasn't arrived yet.
+fn peek_var_int(data: &[u8]) -> Result<Option<(i32, usize)>> {
+    let mut value = 0;
+    for (i, byte) in data.iter().take(mcrw::VAR_INT_MAX_SIZE).enumerate() {
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+
+    if data.len() >= mcrw::VAR_INT_MAX_SIZE {
+        return Err(ProtocolError::Protocol("VarInt is too big".to_owned()));
+    }
+
+    Ok(None)
+}
+
+/// Reads a byte array of `len` bytes, a length past the end of the packet is an error
+fn read_byte_array(rbuf: &mut &[u8], len: i32) -> Result<Vec<u8>> {
+    let len = usize::try_from(len).ok().filter(|&v| v <= rbuf.len())
+        .ok_or_else(|| ProtocolError::Parse(format!("Invalid array length {}", len)))?;
+    let (array, rest) = rbuf.split_at(len);
+    *rbuf = rest;
+    Ok(array.to_vec())
+}
+
+fn read_slot(rbuf: &mut &[u8]) -> Result<Option<ItemStack>> {
+    let id = rbuf.read_short()?; // Block ID
+    if id == -1 {
+        return Ok(None);
+    }
+
+    let count = rbuf.read_byte()?; // Item Count
+    let damage = rbuf.read_short()?; // Item Damage
+    // The lengths in the NBT can't claim more than what's left of the packet
+    let limit = rbuf.len() as u64;
+    let nbt = match siderite_nbt::read_limited(&mut *rbuf, limit)? { // NBT
+        (_, Tag::Compound(v)) => Some(v),
+        _ => None
+    };
+
+    Ok(Some(ItemStack { id, count, damage, nbt }))
+}
+
+/// Converts degrees to steps of 1/256 of a full turn
+fn to_angle(degrees: f32) -> i8 {
+    (degrees * 256.0 / 360.0).rem_euclid(256.0) as u8 as i8
+}
+
+/// Converts blocks per tick to units of 1/8000 of a block per tick,
+/// clamped to the same 3.9 blocks per tick as vanilla so it fits in a short
+fn to_velocity(blocks_per_tick: f64) -> i16 {
+    (blocks_per_tick.clamp(-MAX_VELOCITY, MAX_VELOCITY) * 8000.0) as i16
+}
+
+fn write_slot(wbuf: &mut Vec<u8>, item: Option<&ItemStack>) -> io::Result<()> {
+    let item = match item {
+        Some(v) => v,
+        None => return wbuf.write_short(-1) // Block ID
+    };
+
+    wbuf.write_short(item.id)?; // Block ID
+    wbuf.write_byte(item.count)?; // Item Count
+    wbuf.write_short(item.damage)?; // Item Damage
+    match &item.nbt {
+        Some(nbt) => siderite_nbt::write(&mut *wbuf, "", &Tag::Compound(nbt.clone())), // NBT
+        None => wbuf.write_ubyte(0) // No NBT
+    }
+}
+
+/// Writes the team info fields of the Teams packet
+fn write_team_info(wbuf: &mut Vec<u8>, team: &Team) {
+    wbuf.write_string(team.display_name()).unwrap(); // Team Display Name
+    wbuf.write_string(team.prefix()).unwrap(); // Team Prefix
+    wbuf.write_string(team.suffix()).unwrap(); // Team Suffix
+    // Bit 1 lets players see their invisible teammates, which is always on like in vanilla
+    wbuf.write_ubyte(team.friendly_fire() as u8 | 0x02).unwrap(); // Friendly Fire
+    wbuf.write_string(team.name_tag_visibility().name()).unwrap(); // Name Tag Visibility
+    wbuf.write_byte(team.color().map_or(-1, |v| v as i8)).unwrap(); // Color
+}
+
+/// Writes the player list of the Teams packet
+fn write_team_players(wbuf: &mut Vec<u8>, players: &[String]) {
+    wbuf.write_var_int(players.len() as i32).unwrap(); // Player Count
+    for player in players {
+        wbuf.write_string(player).unwrap(); // Players
+    }
+}
+
+/// Writes the optional display name of the Player List Item packet
+fn write_display_name(wbuf: &mut Vec<u8>, display_name: Option<&Value>) {
+    wbuf.write_bool(display_name.is_some()).unwrap(); // Has Display Name
+    if let Some(display_name) = display_name {
+        wbuf.write_string(&display_name.to_string()).unwrap(); // Display Name
+    }
+}
+
+fn write_metadata(wbuf: &mut Vec<u8>, metadata: &[(u8, MetadataValue)]) -> io::Result<()> {
+    for (index, value) in metadata {
+        wbuf.write_ubyte((value.type_id() << 5) | (index & 0x1f))?; // Type And Index
+        match value {
+            MetadataValue::Byte(v) => wbuf.write_byte(*v)?,
+            MetadataValue::Short(v) => wbuf.write_short(*v)?,
+            MetadataValue::Int(v) => wbuf.write_int(*v)?,
+            MetadataValue::Float(v) => wbuf.write_float(*v)?,
+            MetadataValue::String(v) => wbuf.write_string(v)?,
+            MetadataValue::Slot(v) => write_slot(wbuf, v.as_ref())?,
+            MetadataValue::Position(v) => {
+                wbuf.write_int(v.x)?;
+                wbuf.write_int(v.y)?;
+                wbuf.write_int(v.z)?;
+            }
+            MetadataValue::Rotation(pitch, yaw, roll) => {
+                wbuf.write_float(*pitch)?;
+                wbuf.write_float(*yaw)?;
+                wbuf.write_float(*roll)?;
+            }
+        }
+    }
+
+    wbuf.write_ubyte(0x7f) // End of metadata
+}
+
+fn read_block_placement(mut rbuf: &[u8]) -> Result<BlockPlacement> {
+    let (x, y, z) = rbuf.read_position()?; // Location
+    // -1 means the player isn't looking at a block, the location is -1, -1, -1 in that case
+    let face = rbuf.read_byte()?; // Face
+    let held_item = read_slot(&mut rbuf)?; // Held Item
+    let cursor_x = rbuf.read_ubyte()?; // Cursor Position X
+    let cursor_y = rbuf.read_ubyte()?; // Cursor Position Y
+    let cursor_z = rbuf.read_ubyte()?; // Cursor Position Z
+
+    Ok(BlockPlacement {
+        pos: Coord::new(x, y, z),
+        face: BlockFace::from_i8(face),
+        held_item,
+        cursor: Coord::new(cursor_x, cursor_y, cursor_z)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::thread;
+
+    use openssl::rsa::Rsa;
+    use siderite_nbt::Compound;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::enchanting::Enchantment;
+    use crate::entities::MobType;
+    use crate::entities::attributes::{self, AttributeType};
+    use crate::events::{Listener, PlayerJoin, PlayerQuit};
+    use crate::items::{HOTBAR_START, ItemType};
+    use crate::stats;
+    use crate::server::ServerConfig;
+    use crate::storage::chunk::SerializeChunk;
+    use crate::test_utils::{MockSkinFetcher, test_config, test_player, test_server, test_server_with_auth, test_server_with_config};
+    use crate::tile_entities::SkullType;
+
+    /// Returns a protocol in the handshaking state, the client side of its connection
+    /// and the receiving end of the authenticator
+    fn test_handshake_protocol(strict_protocol: bool) -> (Protocol, TcpStream, Receiver<auth::AuthInfo>) {
+        let (server, rx) = test_server_with_auth(strict_protocol);
+        let (prot, client) = connect(server);
+        (prot, client, rx)
+    }
+
+    /// Returns a new connection to the server in the handshaking state and the client side of it
+    fn connect(server: Arc<Server>) -> (Protocol, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (Protocol::new(server, stream), client)
+    }
+
+    /// Returns a protocol in the play state and the client side of its connection
+    fn test_protocol() -> (Protocol, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut prot = Protocol::new(test_server(), stream);
+        prot.state = State::Play;
+        (prot, client)
+    }
+
+    /// Reads an uncompressed, unencrypted packet and returns its id and data
+    fn read_packet(stream: &mut TcpStream) -> (i32, Vec<u8>) {
+        let length = stream.read_var_int().unwrap() as usize;
+        let mut buf = vec![0u8; length];
+        stream.read_exact(&mut buf).unwrap();
+        let mut slice = buf.as_slice();
+        let id = slice.read_var_int().unwrap();
+        (id, slice.to_vec())
+    }
+
+    #[test]
+    fn block_placement() {
+        let mut data = Vec::new();
+        data.write_position(10, 64, -3).unwrap();
+        data.write_byte(1).unwrap();
+        data.write_short(35).unwrap();
+        data.write_byte(2).unwrap();
+        data.write_short(14).unwrap();
+        data.write_ubyte(0).unwrap();
+        data.write_all(&[8, 15, 0]).unwrap();
+
+        let placement = read_block_placement(&data).unwrap();
+        assert_eq!(placement, BlockPlacement {
+            pos: Coord::new(10, 64, -3),
+            face: Some(BlockFace::YP),
+            held_item: Some(ItemStack::new(35, 2, 14)),
+            cursor: Coord::new(8, 15, 0)
+        });
+    }
+
+    #[test]
+    fn block_placement_empty_hand() {
+        let mut data = Vec::new();
+        data.write_position(-1, -1, -1).unwrap();
+        data.write_byte(-1).unwrap();
+        data.write_short(-1).unwrap();
+        data.write_all(&[0, 0, 0]).unwrap();
+
+        let placement = read_block_placement(&data).unwrap();
+        assert_eq!(placement.face, None);
+        assert_eq!(placement.held_item, None);
+    }
+
+    #[test]
+    fn suppressed_join_message() {
+        struct Silent;
+
+        impl Listener for Silent {
+            fn on_player_join(&self, event: &mut PlayerJoin) {
+                event.message = None;
+            }
+
+            fn on_player_quit(&self, event: &mut PlayerQuit) {
+                event.message = Some(format!("{} ragequit", event.username));
+            }
+        }
+
+        let (prot, _client) = test_protocol();
+        let server = prot.server.clone();
+        server.events().register(Box::new(Silent));
+        let (_observer, rx) = test_player(&server, GameMode::Survival);
+        let (client_id, client) = prot.get_client();
+        server.add_client(client_id, client.clone());
+        server.auth_user(client_id, "Alex".to_owned(), Uuid::from_u128(thread_rng().gen()), Value::Null);
+        assert!(!rx.try_iter().any(|v| matches!(v, Packet::ChatMessage(_))));
+
+        server.remove_client(client_id);
+        assert!(rx.try_iter().any(|v| matches!(v, Packet::ChatMessage(msg) if msg == "Alex ragequit")));
+    }
+
+    #[test]
+    fn request_stats() {
+        let (mut prot, _client) = test_protocol();
+        let server = prot.server.clone();
+        let (client_id, client) = prot.get_client();
+        server.add_client(client_id, client.clone());
+        // Use a new uuid so no statistics are loaded from a previous run
+        let uuid = Uuid::from_u128(thread_rng().gen());
+        server.auth_user(client_id, "Steve".to_owned(), uuid, serde_json::Value::Null);
+        // Ignore the packets sent while joining
+        while prot.receiver.try_recv().is_ok() {}
+
+        // The player spawns on the ground at (0.5, 16, 0.5)
+        client.read().unwrap().handle_move(Some(Coord::new(3.5, 16.0, 4.5)), None, true);
+
+        let mut rbuf = Vec::new();
+        rbuf.write_var_int(1).unwrap();
+        prot.handle_client_status(&rbuf).unwrap();
+
+        match prot.receiver.try_recv().unwrap() {
+            Packet::Statistics(stats) => assert_eq!(stats, [(stats::WALK_ONE_CM.to_owned(), 500)]),
+            _ => panic!("Expected statistics packet")
+        }
+    }
+
+    #[test]
+    fn offline_skins() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(test_config(), None, tx);
+        let calls = Arc::new(AtomicU32::new(0));
+        server.set_skin_fetcher(Box::new(MockSkinFetcher { calls: calls.clone(), fail: false }));
+        server.load_worlds().unwrap();
+        let (mut prot, mut client_stream) = connect(Arc::new(server));
+        prot.state = State::Play;
+        let server = prot.server.clone();
+        let (client_id, client) = prot.get_client();
+        server.add_client(client_id, client.clone());
+        server.auth_user(client_id, "Alex".to_owned(), Uuid::from_u128(thread_rng().gen()), Value::Null);
+        assert_eq!(client.read().unwrap().properties(), &json!([{ "name": "textures", "value": "Alex" }]));
+
+        // The other players get the skin from the tab list
+        let packet = prot.receiver.try_iter().find(|v| matches!(v, Packet::PlayerListItem(PlayerListAction::AddPlayer, _))).unwrap();
+        prot.send_packet(packet);
+        let (id, data) = read_packet(&mut client_stream);
+        assert_eq!(id, 0x38);
+        let mut data = &data[2 + 16..];
+        assert_eq!(data.read_string().unwrap(), "Alex");
+        assert_eq!(data.read_var_int().unwrap(), 1);
+        assert_eq!(data.read_string().unwrap(), "textures");
+        assert_eq!(data.read_string().unwrap(), "Alex");
+        assert!(!data.read_bool().unwrap());
+
+        // Players the authenticator gave properties keep them
+        let (other_prot, _other_stream) = connect(server.clone());
+        let (client_id, client) = other_prot.get_client();
+        server.add_client(client_id, client.clone());
+        let properties = json!([{ "name": "textures", "value": "bW9qYW5n", "signature": "c2lnbmF0dXJl" }]);
+        server.auth_user(client_id, "Bob".to_owned(), Uuid::from_u128(thread_rng().gen()), properties.clone());
+        assert_eq!(client.read().unwrap().properties(), &properties);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn player_list_display_name() {
+        let (mut prot, mut client) = test_protocol();
+        let server = prot.server.clone();
+        let (player, _) = test_player(&server, GameMode::Survival);
+        prot.send_packet(Packet::PlayerListItem(PlayerListAction::UpdateDisplayName, Box::new([player.clone()])));
+        let (_, data) = read_packet(&mut client);
+        assert_eq!(&data[data.len() - 1..], [0]);
+
+        player.write().unwrap().set_display_name(Some(chat::text("[Admin] Steve")));
+        prot.send_packet(Packet::PlayerListItem(PlayerListAction::UpdateDisplayName, Box::new([player])));
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x38);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), PlayerListAction::UpdateDisplayName as i32);
+        assert_eq!(data.read_var_int().unwrap(), 1);
+        data = &data[16..];
+        assert!(data.read_bool().unwrap());
+        assert_eq!(serde_json::from_str::<Value>(&data.read_string().unwrap()).unwrap(), chat::text("[Admin] Steve"));
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn chunk_unload() {
+        let (mut prot, mut client) = test_protocol();
+        prot.send_packet(Packet::ChunkUnload(ChunkCoord {x: 3, z: -2}));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x21);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_int().unwrap(), 3);
+        assert_eq!(data.read_int().unwrap(), -2);
+        assert!(data.read_bool().unwrap());
+        assert_eq!(data.read_ushort().unwrap(), 0);
+        assert_eq!(data.read_var_int().unwrap(), 0);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn chunk_unload_is_byte_exact() {
+        let (mut prot, mut client) = test_protocol();
+        // Unloading doesn't need the chunk to be loaded
+        prot.send_packet(Packet::ChunkUnload(ChunkCoord {x: -1, z: 256}));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x21);
+        assert_eq!(data, [
+            0xff, 0xff, 0xff, 0xff, // Chunk X
+            0x00, 0x00, 0x01, 0x00, // Chunk Z
+            0x01, // Ground-Up Continuous
+            0x00, 0x00, // Primary Bit Mask
+            0x00 // Size
+        ]);
+    }
+
+    #[test]
+    fn entity_properties() {
+        let (mut prot, mut client) = test_protocol();
+        let mut attributes = Attributes::default();
+        attributes.set_modifier(AttributeType::MovementSpeed, attributes::SPRINTING_SPEED_BOOST);
+        prot.send_packet(Packet::EntityProperties(300, attributes));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x20);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), 300);
+        assert_eq!(data.read_int().unwrap(), 2);
+
+        assert_eq!(data.read_string().unwrap(), "generic.maxHealth");
+        assert_eq!(data.read_double().unwrap(), 20.0);
+        assert_eq!(data.read_var_int().unwrap(), 0);
+
+        assert_eq!(data.read_string().unwrap(), "generic.movementSpeed");
+        assert_eq!(data.read_double().unwrap(), 0.1);
+        assert_eq!(data.read_var_int().unwrap(), 1);
+        let mut uuid = [0; 16];
+        data.read_exact(&mut uuid).unwrap();
+        assert_eq!(Uuid::from_bytes(uuid).as_hyphenated().to_string(), "662a6b8d-da3e-4c1c-8813-96ea6097278d");
+        assert_eq!(data.read_double().unwrap(), 0.30000001192092896);
+        assert_eq!(data.read_ubyte().unwrap(), 2);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn map_chunk_bulk() {
+        let (mut prot, mut client) = test_protocol();
+        let chunk_map = Arc::new(ChunkMap::new());
+        let coords = vec![ChunkCoord {x: 0, z: 0}, ChunkCoord {x: 1, z: -1}];
+        for coord in &coords {
+            chunk_map.touch_chunk(*coord);
+        }
+        chunk_map.do_with_chunk_mut(coords[1], |chunk| chunk.set_block(Coord::new(1, 40, 2), BlockType::Stone));
+        prot.send_packet(Packet::MapChunkBulk(coords.clone(), chunk_map.clone(), Dimension::Overworld));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x26);
+        let mut data = data.as_slice();
+        assert!(data.read_bool().unwrap());
+        assert_eq!(data.read_var_int().unwrap(), 2);
+        let mut expected = Vec::new();
+        for coord in &coords {
+            assert_eq!(data.read_int().unwrap(), coord.x);
+            assert_eq!(data.read_int().unwrap(), coord.z);
+            chunk_map.do_with_chunk(*coord, |chunk| {
+                assert_eq!(data.read_ushort().unwrap(), chunk.data.get_primary_bit_mask());
+                chunk.serialize_data(&mut expected, &SerializeContext::default()).unwrap();
+            });
+        }
+
+        assert_eq!(data, expected.as_slice());
+    }
+
+    #[test]
+    fn entity_teleport() {
+        let (mut prot, mut client) = test_protocol();
+        prot.send_packet(Packet::EntityTeleport(7, Coord::new(1.5, 16.0, -0.25), 90.0, -45.0, true));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x18);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), 7);
+        assert_eq!(data.read_int().unwrap(), 48);
+        assert_eq!(data.read_int().unwrap(), 512);
+        assert_eq!(data.read_int().unwrap(), -8);
+        assert_eq!(data.read_byte().unwrap(), 64);
+        assert_eq!(data.read_byte().unwrap(), -32);
+        assert!(data.read_bool().unwrap());
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn relative_teleport() {
+        let (mut prot, mut client) = test_protocol();
+        let flags = TeleportFlags::X | TeleportFlags::Y | TeleportFlags::YAW;
+        prot.send_packet(Packet::Teleport(Coord::new(0.0, 10.0, 64.5), 0.0, 30.0, flags, (Coord::new(3.0, 26.0, 64.5), 90.0, 30.0)));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x08);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_double().unwrap(), 0.0);
+        assert_eq!(data.read_double().unwrap(), 10.0);
+        assert_eq!(data.read_double().unwrap(), 64.5);
+        assert_eq!(data.read_float().unwrap(), 0.0);
+        assert_eq!(data.read_float().unwrap(), 30.0);
+        assert_eq!(data.read_ubyte().unwrap(), 0x0B);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn combat_event() {
+        let (mut prot, mut client) = test_protocol();
+        prot.send_packet(Packet::CombatEvent(7, None, "Steve blew up".to_owned()));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x42);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), 2);
+        assert_eq!(data.read_var_int().unwrap(), 7);
+        assert_eq!(data.read_int().unwrap(), -1);
+        assert_eq!(serde_json::from_str::<Value>(&data.read_string().unwrap()).unwrap(), json!({ "text": "Steve blew up" }));
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn attach_entity() {
+        let (mut prot, mut client) = test_protocol();
+        prot.send_packet(Packet::AttachEntity(7, Some(42), true));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x1B);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_int().unwrap(), 7);
+        assert_eq!(data.read_int().unwrap(), 42);
+        assert!(data.read_bool().unwrap());
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn detach_entity() {
+        let (mut prot, mut client) = test_protocol();
+        prot.send_packet(Packet::AttachEntity(7, None, false));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x1B);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_int().unwrap(), 7);
+        assert_eq!(data.read_int().unwrap(), -1);
+        assert!(!data.read_bool().unwrap());
+        assert!(data.is_empty());
+    }
+
+    /// Handshake and Login Start of a Forge 1.8.9 client, which marks the server address
+    const FML_LOGIN: &[u8] = &[
+        // Handshake: protocol 47, "localhost\0FML\0", port 25565, next state login
+        0x14, 0x00, 0x2f, 0x0e, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't',
+        0x00, b'F', b'M', b'L', 0x00, 0x63, 0xdd, 0x02,
+        // Login Start: "Bond_009"
+        0x0a, 0x00, 0x08, b'B', b'o', b'n', b'd', b'_', b'0', b'0', b'9'
+    ];
+
+    /// Login packet 0x02 with a 2 byte payload, unknown to 1.8. Later versions use the id for Login Plugin Response,
+    /// which is what mod loaders of those versions answer with.
+    const UNKNOWN_LOGIN_PACKET: &[u8] = &[0x03, 0x02, 0x00, 0x00];
+
+    #[test]
+    fn forge_login_completes() {
+        let (mut prot, mut client, auth) = test_handshake_protocol(false);
+        client.write_all(FML_LOGIN).unwrap();
+        client.write_all(UNKNOWN_LOGIN_PACKET).unwrap();
+        prot.process_data();
+
+        assert!(prot.forge_client);
+        assert_eq!(prot.state, State::Login);
+        assert_eq!(auth.try_recv().unwrap().username, "Bond_009");
+    }
+
+    #[test]
+    fn strict_login_kicks_unknown_packet() {
+        let (mut prot, mut client, _auth) = test_handshake_protocol(true);
+        client.write_all(FML_LOGIN).unwrap();
+        client.write_all(UNKNOWN_LOGIN_PACKET).unwrap();
+        prot.process_data();
+
+        assert_eq!(prot.state, State::Disconnected);
+        let (id, _) = read_packet(&mut client);
+        assert_eq!(id, 0x00); // Disconnect
+    }
+
+    #[test]
+    fn forge_play_handshake_is_ignored() {
+        let (mut prot, mut client) = test_protocol();
+        prot.stream.set_nonblocking(true).unwrap();
+        prot.forge_client = true;
+
+        // FML|HS Client Hello with FML protocol 2 and the Mod List of a client without mods
+        let mut mod_list = vec![0x02];
+        mod_list.write_var_int(3).unwrap();
+        for (id, version) in [("mcp", "9.19"), ("FML", "8.0.99.99"), ("Forge", "11.15.1.1722")] {
+            mod_list.write_string(id).unwrap();
+            mod_list.write_string(version).unwrap();
+        }
+        for message in [vec![0x01, 0x02], mod_list] {
+            let mut packet = Vec::new();
+            packet.write_var_int(ServerboundPlay::PluginMessage as i32).unwrap();
+            packet.write_string("FML|HS").unwrap();
+            packet.extend(message);
+            client.write_var_int(packet.len() as i32).unwrap();
+            client.write_all(&packet).unwrap();
+        }
+        prot.process_data();
+
+        // Like vanilla the server doesn't answer, so Forge falls back to vanilla mode
+        assert_eq!(prot.state, State::Play);
+        assert!(prot.received_data.is_empty());
+        client.set_nonblocking(true).unwrap();
+        assert_eq!(client.read(&mut [0; 1]).unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn rapid_logins_are_throttled() {
+        let (server, _auth) = test_server_with_config(ServerConfig {
+            connection_throttle: Duration::from_secs(60),
+            connection_throttle_limit: 2,
+            ..test_config()
+        });
+        // The Handshake of FML_LOGIN, without its length and packet id
+        let handshake = &FML_LOGIN[2..21];
+
+        for _ in 0..2 {
+            let (mut prot, _client) = connect(server.clone());
+            prot.handle_handshake(handshake).unwrap();
+            assert_eq!(prot.state, State::Login);
+        }
+
+        let (mut prot, mut client) = connect(server);
+        prot.handle_handshake(handshake).unwrap();
+        assert_eq!(prot.state, State::Disconnected);
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x00); // Disconnect
+        assert!(data.as_slice().read_string().unwrap().contains(throttle::THROTTLED_MESSAGE));
+    }
+
+    #[test]
+    fn truncated_packet_is_parse_error() {
+        let (mut prot, _client) = test_protocol();
+        // Player Position without its On Ground field
+        let mut rbuf = Vec::new();
+        for coord in [1.0, 65.0, -3.5] {
+            rbuf.write_double(coord).unwrap();
+        }
+
+        assert!(matches!(prot.handle_packet(&rbuf, 0x04), Err(ProtocolError::Parse(_))));
+        assert!(matches!(prot.handle_packet(&[], 0x7F), Err(ProtocolError::UnknownPacket { id: 0x7F, name: None })));
+        // Packets that exist but aren't handled are named in the error
+        let error = prot.handle_packet(&[], 0x18).unwrap_err();
+        assert_eq!(error.to_string(), "Protocol violation: unsupported packet Spectate (0x18)");
+    }
+
+    #[test]
+    fn decrypts_over_multiple_reads() {
+        let (mut prot, mut client) = test_protocol();
+        let key = [7u8; ENCRYPTION_KEY_LEN];
+        let cipher = Cipher::aes_128_cfb8();
+        let decrypter = Crypter::new(cipher, Mode::Decrypt, &key, Some(&key)).unwrap();
+        let encrypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&key)).unwrap();
+        prot.crypter = Some((encrypter, decrypter));
+
+        // The start of a packet that takes a few reads to arrive, so it stays in the buffer
+        let mut data = Vec::new();
+        data.write_var_int(4000).unwrap();
+        data.extend((0..1500).map(|i| (i % 251) as u8));
+
+        let mut en = Crypter::new(cipher, Mode::Encrypt, &key, Some(&key)).unwrap();
+        let mut encrypted = vec![0u8; data.len() + cipher.block_size()];
+        let len = en.update(&data, &mut encrypted).unwrap();
+
+        // Sent in parts, so the decrypter has to continue where the last read stopped
+        let mut sent = 0;
+        for part in encrypted[..len].chunks(700) {
+            client.write_all(part).unwrap();
+            sent += part.len();
+            while prot.received_data.len() < sent {
+                prot.process_data();
+            }
+        }
+
+        let mut received = vec![0u8; data.len()];
+        prot.received_data.read_exact(&mut received).unwrap();
+        assert_eq!(received, data);
+    }
+
+    /// Reports how fast a small and the default read buffer move data to the receive buffer,
+    /// run with `cargo test --release -p siderite-core read_throughput -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn read_throughput() {
+        const TOTAL: usize = 256 * 1024 * 1024;
+
+        for read_buffer_size in [512, 8192] {
+            let (server, _) = test_server_with_config(ServerConfig { read_buffer_size, ..test_config() });
+            let (mut prot, mut client) = connect(server);
+            prot.stream.set_nonblocking(true).unwrap();
+            let writer = thread::spawn(move || {
+                let data = vec![0u8; 64 * 1024];
+                for _ in 0..TOTAL / data.len() {
+                    client.write_all(&data).unwrap();
+                }
+            });
+
+            let start = Instant::now();
+            let mut read = 0;
+            while read < TOTAL {
+                read += prot.read_data().unwrap();
+                // Only the reading is measured, the data gets thrown away
+                let len = prot.received_data.len();
+                prot.received_data.advance_read_pos(len).unwrap();
+            }
+
+            let elapsed = start.elapsed();
+            writer.join().unwrap();
+            println!("{} byte buffer: {:.0} bytes/sec", read_buffer_size, TOTAL as f64 / elapsed.as_secs_f64());
+        }
+    }
+
+    #[test]
+    fn flood_is_handled_over_multiple_ticks() {
+        let (mut prot, mut client) = test_protocol();
+        prot.stream.set_nonblocking(true).unwrap();
+
+        // Player packets, which don't do anything without a player
+        let packets = 6000;
+        let burst = [0x02, 0x03, 0x01].repeat(packets);
+        client.write_all(&burst).unwrap();
+
+        prot.process_data();
+        assert!(prot.received_data.len() <= READ_BUDGET - PACKET_BUDGET * 3);
+
+        let mut ticks = 1;
+        while !prot.received_data.is_empty() {
+            prot.process_data();
+            ticks += 1;
+        }
+        assert!(ticks >= packets / PACKET_BUDGET);
+        assert_eq!(prot.state, State::Play);
+    }
+
+    #[test]
+    fn invalid_packet_kicks_client() {
+        let (mut prot, mut client) = test_protocol();
+        // Held Item Change with only one byte of its slot
+        client.write_all(&[0x02, 0x09, 0x00]).unwrap();
+        prot.process_data();
+
+        assert_eq!(prot.state, State::Disconnected);
+        let (id, _) = read_packet(&mut client);
+        assert_eq!(id, 0x40); // Disconnect
+    }
+
+    #[test]
+    fn compression_threshold_is_inclusive() {
+        let (server, _auth) = test_server_with_config(ServerConfig { compression_threshold: Some(64), ..test_config() });
+        let (mut prot, mut client) = connect(server);
+        prot.compressed = true;
+        for length in [63, 64, 65] {
+            let packet: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            prot.write_packet(&packet).unwrap();
+
+            // Decoded like the client does
+            let frame_length = client.read_var_int().unwrap() as usize;
+            let mut frame = vec![0u8; frame_length];
+            client.read_exact(&mut frame).unwrap();
+            let mut frame = frame.as_slice();
+            let data_length = frame.read_var_int().unwrap();
+            let data = if data_length == 0 {
+                frame.to_vec()
+            } else {
+                let mut data = Vec::new();
+                ZlibDecoder::new(frame).read_to_end(&mut data).unwrap();
+                assert_eq!(data.len(), data_length as usize);
+                data
+            };
+            assert_eq!(data_length == 0, length < 64);
+            assert_eq!(data, packet);
+        }
+    }
+
+    #[test]
+    fn packet_too_large_kicks_client() {
+        let (mut prot, mut client) = test_protocol();
+        let mut data = Vec::new();
+        data.write_var_int(MAX_PACKET_LEN as i32 + 1).unwrap();
+        data.extend([0x17; 100]);
+        client.write_all(&data).unwrap();
+        prot.process_data();
+
+        assert_eq!(prot.state, State::Disconnected);
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x40); // Disconnect
+        let reason: Value = serde_json::from_str(&data.as_slice().read_string().unwrap()).unwrap();
+        assert!(reason.to_string().contains("Packet too large"));
+    }
+
+    #[test]
+    fn kick_reason_follows_queued_chunks() {
+        let (mut prot, client) = test_protocol();
+        prot.stream.set_nonblocking(true).unwrap();
+        // More chunk data than the socket buffers take while the client isn't reading
+        let mut chunk = vec![0u8; 1024 * 1024];
+        chunk[0] = ClientboundPlay::ChunkData as u8;
+        for _ in 0..12 {
+            prot.write_packet(&chunk).unwrap();
+        }
+
+        let reader = thread::spawn(move || {
+            let mut client = client;
+            let mut data = Vec::new();
+            client.read_to_end(&mut data).unwrap();
+            data
+        });
+        // The deadline never passes at this time, the connection closes once everything is written
+        let now = Instant::now();
+        prot.disconnect(&chat::text("Bye")).unwrap();
+        assert_eq!(prot.state, State::Disconnected);
+        while !prot.is_disconnected() {
+            prot.tick_close(now);
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let data = reader.join().unwrap();
+        let mut data = data.as_slice();
+        let mut last = None;
+        while !data.is_empty() {
+            let length = data.read_var_int().unwrap() as usize;
+            let (packet, rest) = data.split_at(length);
+            last = Some(packet);
+            data = rest;
+        }
+        let mut last = last.unwrap();
+        assert_eq!(last.read_var_int().unwrap(), ClientboundPlay::Disconnect as i32);
+        assert_eq!(last.read_string().unwrap(), chat::text("Bye").to_string());
+    }
+
+    #[test]
+    fn kick_does_not_wait_for_slow_clients() {
+        let (mut prot, _client) = test_protocol();
+        prot.stream.set_nonblocking(true).unwrap();
+        let mut chunk = vec![0u8; 1024 * 1024];
+        chunk[0] = ClientboundPlay::ChunkData as u8;
+        for _ in 0..12 {
+            prot.write_packet(&chunk).unwrap();
+        }
+
+        // The client never reads, so the kick stays queued without blocking until the deadline
+        let start = Instant::now();
+        prot.disconnect(&chat::text("Bye")).unwrap();
+        assert!(prot.is_closing());
+        prot.tick_close(start);
+        assert!(prot.is_closing());
+        assert!(!prot.is_disconnected());
+
+        prot.tick_close(start + DISCONNECT_TIMEOUT);
+        assert!(prot.is_disconnected());
+    }
+
+    #[test]
+    fn client_too_far_behind_is_kicked() {
+        let (mut prot, _client) = test_protocol();
+        prot.stream.set_nonblocking(true).unwrap();
+        let mut chunk = vec![0u8; 1024 * 1024];
+        chunk[0] = ClientboundPlay::ChunkData as u8;
+        for _ in 0..64 {
+            if let Err(e) = prot.write_packet(&chunk) {
+                prot.handle_error(e);
+                break;
+            }
+        }
+
+        assert_eq!(prot.state, State::Disconnected);
+    }
+
+    #[test]
+    fn respawn_keeps_its_order() {
+        let (prot, _client) = test_protocol();
+        assert_eq!(prot.priority(ClientboundPlay::KeepAlive as u8), Priority::Control);
+        assert_eq!(prot.priority(ClientboundPlay::Respawn as u8), Priority::Bulk);
+        assert_eq!(prot.priority(ClientboundPlay::PlayerPositionAndLook as u8), Priority::Bulk);
+    }
+
+    #[test]
+    fn large_packet_grows_receive_buffer() {
+        let (mut prot, client) = test_protocol();
+        prot.stream.set_nonblocking(true).unwrap();
+
+        let mut payload = Vec::new();
+        payload.write_var_int(ServerboundPlay::PluginMessage as i32).unwrap();
+        payload.write_string("siderite:test").unwrap();
+        payload.extend((0..64 * 1024).map(|i| (i % 251) as u8));
+        let mut data = Vec::new();
+        data.write_var_int(payload.len() as i32).unwrap();
+        data.extend(payload);
+        // Followed by a keep alive, which shows the large packet got handled
+        data.extend([0x02, 0x00, 0x07]);
+
+        let writer = thread::spawn(move || {
+            let mut client = client;
+            client.write_all(&data).unwrap();
+            client
+        });
+
+        let last_keep_alive = Instant::now() - Duration::from_secs(1);
+        prot.last_keep_alive = last_keep_alive;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while prot.last_keep_alive == last_keep_alive {
+            assert!(Instant::now() < deadline, "The packets weren't handled");
+            prot.process_data();
+            assert_eq!(prot.state, State::Play);
+        }
+
+        assert!(prot.receive_buf_len > 64 * 1024);
+        assert!(prot.received_data.is_empty());
+        let _client = writer.join().unwrap();
+
+        // Shrinks back once the data is handled
+        prot.process_data();
+        assert_eq!(prot.receive_buf_len, RECEIVE_BUF_LEN);
+    }
+
+    #[test]
+    fn kick_banned_with_reason() {
+        let (mut prot, mut client) = test_protocol();
+        prot.send_packet(Packet::Disconnect(kick::banned(Some("Griefing"))));
+
+        assert_eq!(prot.state, State::Disconnected);
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x40);
+        let reason: Value = serde_json::from_str(&data.as_slice().read_string().unwrap()).unwrap();
+        assert_eq!(reason, json!({ "text": "You are banned from this server!\nReason: Griefing" }));
+    }
+
+    #[test]
+    fn outdated_client_is_kicked() {
+        let (server, _auth) = test_server_with_auth(false);
+        let (mut prot, mut client) = connect(server);
+        // Handshake: protocol 4 (1.7.5), "localhost", port 25565, next state login
+        let mut handshake = Vec::new();
+        handshake.write_var_int(4).unwrap();
+        handshake.write_string("localhost").unwrap();
+        handshake.write_ushort(25565).unwrap();
+        handshake.write_var_int(2).unwrap();
+        prot.handle_handshake(&handshake).unwrap();
+
+        assert_eq!(prot.state, State::Disconnected);
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x00);
+        let reason: Value = serde_json::from_str(&data.as_slice().read_string().unwrap()).unwrap();
+        assert_eq!(reason, kick::outdated_client(VERSION_NAME));
+    }
+
+    /// Returns the Handshake of a 1.7.10 client, with its length and packet id
+    fn legacy_handshake(next_state: i32) -> Vec<u8> {
+        // Protocol 5 (1.7.10), "localhost", port 25565
+        let mut handshake = vec![ServerboundHandshake::Handshake as u8];
+        handshake.write_var_int(5).unwrap();
+        handshake.write_string("localhost").unwrap();
+        handshake.write_ushort(25565).unwrap();
+        handshake.write_var_int(next_state).unwrap();
+
+        let mut frame = Vec::new();
+        frame.write_var_int(handshake.len() as i32).unwrap();
+        frame.extend(handshake);
+        frame
+    }
+
+    #[test]
+    fn legacy_status_shows_client_version() {
+        let (mut prot, mut client, _auth) = test_handshake_protocol(false);
+        client.write_all(&legacy_handshake(1)).unwrap();
+        // Request
+        client.write_all(&[0x01, 0x00]).unwrap();
+        prot.process_data();
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x00);
+        let response: Value = serde_json::from_str(&data.as_slice().read_string().unwrap()).unwrap();
+        assert_eq!(response["version"], json!({ "name": "1.7.10", "protocol": 5 }));
+    }
+
+    #[test]
+    fn legacy_client_logs_in() {
+        let (server, auth) = test_server_with_config(ServerConfig { compression_threshold: Some(64), ..test_config() });
+        let (mut prot, mut client) = connect(server);
+        client.write_all(&legacy_handshake(2)).unwrap();
+        // Login Start: "Bond_009"
+        client.write_all(&[0x0a, 0x00, 0x08, b'B', b'o', b'n', b'd', b'_', b'0', b'0', b'9']).unwrap();
+        prot.process_data();
+        assert_eq!(auth.try_recv().unwrap().username, "Bond_009");
+
+        // 1.7 has no compression
+        prot.send_packet(Packet::LoginSuccess());
+        assert!(!prot.compressed);
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x02);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_string().unwrap().len(), 36); // UUID with dashes
+        assert_eq!(data.read_string().unwrap(), "Bond_009");
+
+        let chunk_map = Arc::new(ChunkMap::new());
+        let coord = ChunkCoord { x: 2, z: -1 };
+        chunk_map.touch_chunk(coord);
+        prot.send_packet(Packet::ChunkData(coord, chunk_map.clone(), Dimension::Overworld));
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x21);
+        let mut data = data.as_slice();
+        assert_eq!((data.read_int().unwrap(), data.read_int().unwrap()), (2, -1));
+        assert!(data.read_bool().unwrap());
+        chunk_map.do_with_chunk(coord, |chunk| assert_eq!(data.read_ushort().unwrap(), chunk.data.get_primary_bit_mask()));
+        assert_eq!(data.read_ushort().unwrap(), 0);
+        assert_eq!(data.read_int().unwrap() as usize, data.len());
+
+        // Packets 1.7 doesn't have are left out, Keep Alive has an int id
+        prot.send_packet(Packet::ServerDifficulty(Difficulty::Hard));
+        prot.keep_alive(7);
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x00);
+        assert_eq!(data.as_slice().read_int().unwrap(), 7);
+
+        assert_eq!(prot.state, State::Play);
+    }
+
+    #[test]
+    fn legacy_client_logs_in_online() {
+        let (server, auth) = test_server_with_config(ServerConfig { encryption: true, ..test_config() });
+        let (mut prot, mut client) = connect(server.clone());
+        client.write_all(&legacy_handshake(2)).unwrap();
+        // Login Start: "Bond_009"
+        client.write_all(&[0x0a, 0x00, 0x08, b'B', b'o', b'n', b'd', b'_', b'0', b'0', b'9']).unwrap();
+        prot.process_data();
+
+        // 1.7 prefixes the arrays with shorts
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, ClientboundLogin::EncryptionRequest as i32);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_string().unwrap(), server.id());
+        let len = data.read_short().unwrap() as usize;
+        let (public_key, mut data) = data.split_at(len);
+        assert_eq!(public_key, server.public_key_der());
+        let len = data.read_short().unwrap() as usize;
+        assert_eq!(data.len(), len);
+        let verify_token = data;
+
+        let public_key = Rsa::public_key_from_der(public_key).unwrap();
+        let encrypt = |v: &[u8]| {
+            let mut encrypted = vec![0u8; public_key.size() as usize];
+            let len = public_key.public_encrypt(v, &mut encrypted, PADDING).unwrap();
+            encrypted.truncate(len);
+            encrypted
+        };
+        let key = [7u8; ENCRYPTION_KEY_LEN];
+        let (shared_secret, verify_token) = (encrypt(&key), encrypt(verify_token));
+        let mut response = vec![ServerboundLogin::EncryptionResponse as u8];
+        response.write_short(shared_secret.len() as i16).unwrap();
+        response.extend(&shared_secret);
+        response.write_short(verify_token.len() as i16).unwrap();
+        response.extend(&verify_token);
+        let mut frame = Vec::new();
+        frame.write_var_int(response.len() as i32).unwrap();
+        frame.extend(response);
+        client.write_all(&frame).unwrap();
+        prot.process_data();
+
+        let mut hasher = Sha1::new();
+        hasher.update(server.id().as_bytes());
+        hasher.update(&key);
+        hasher.update(server.public_key_der());
+        let info = auth.try_recv().unwrap();
+        assert_eq!(info.username, "Bond_009");
+        assert_eq!(info.server_id, Some(auth::java_hex_digest(hasher.finish())));
+        assert!(prot.crypter.is_some());
+    }
+
+    #[test]
+    fn keep_alive_grace_period() {
+        let (mut prot, mut client) = test_protocol();
+        let interval = prot.server.keep_alive_interval();
+        let now = Instant::now();
+
+        // Not scheduled before entering the play state
+        prot.tick_keep_alive(now + KEEP_ALIVE_GRACE, 1);
+
+        prot.last_keep_alive = now - Duration::from_secs(1);
+        prot.start_keep_alive(now);
+        assert_eq!(prot.last_keep_alive, now);
+        prot.tick_keep_alive(now, 2);
+        prot.tick_keep_alive(now + KEEP_ALIVE_GRACE - Duration::from_millis(1), 3);
+        prot.tick_keep_alive(now + KEEP_ALIVE_GRACE, 4);
+        prot.tick_keep_alive(now + KEEP_ALIVE_GRACE + interval - Duration::from_millis(1), 5);
+        prot.tick_keep_alive(now + KEEP_ALIVE_GRACE + interval, 6);
+
+        for id in [4, 6] {
+            let (packet_id, data) = read_packet(&mut client);
+            assert_eq!(packet_id, ClientboundPlay::KeepAlive as i32);
+            assert_eq!(data.as_slice().read_var_int().unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn map_single_column() {
+        let (mut prot, mut client) = test_protocol();
+        let icon = MapIcon { direction: 8, icon_type: 1, x: -10, z: 20 };
+        let update = MapUpdate { columns: 1, rows: 3, x: 64, z: 2, data: vec![4, 5, 6] };
+        prot.send_packet(Packet::Map(3, 2, vec![icon], Some(update)));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x34);
+        assert_eq!(data, [0x03, 0x02, 0x01, 0x81, 0xf6, 0x14, 0x01, 0x03, 0x40, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    }
+
+    #[test]
+    fn update_block_entity_skull() {
+        let (mut prot, mut client) = test_protocol();
+        let skull = TileEntity::Skull {
+            skull_type: SkullType::Player,
+            rotation: 4,
+            owner: Some("Bond_009".to_owned())
+        };
+        prot.send_packet(Packet::UpdateBlockEntity(Coord::new(-5, 64, 300), skull));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x35);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_position().unwrap(), (-5, 64, 300));
+        assert_eq!(data.read_ubyte().unwrap(), 4);
+
+        let (_, nbt) = siderite_nbt::read(&mut data).unwrap();
+        assert!(data.is_empty());
+        let nbt = nbt.as_compound().unwrap();
+        assert_eq!(nbt.get("id").unwrap().as_str(), Some("Skull"));
+        assert_eq!(nbt.get("x").unwrap().as_int(), Some(-5));
+        assert_eq!(nbt.get("SkullType").unwrap().as_byte(), Some(3));
+        assert_eq!(nbt.get("Rot").unwrap().as_byte(), Some(4));
+        let owner = nbt.get("Owner").unwrap().as_compound().unwrap();
+        assert_eq!(owner.get("Name").unwrap().as_str(), Some("Bond_009"));
+    }
+
+    #[test]
+    fn open_sign_editor() {
+        let (mut prot, mut client) = test_protocol();
+        prot.send_packet(Packet::OpenSignEditor(Coord::new(1, 2, 3)));
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x36);
+        // x in the top 26 bits, then 12 bits of y and 26 bits of z
+        assert_eq!(data, [0x00, 0x00, 0x00, 0x40, 0x08, 0x00, 0x00, 0x03]);
+
+        prot.send_packet(Packet::OpenSignEditor(Coord::new(-30, 255, -1)));
+        let (_, data) = read_packet(&mut client);
+        assert_eq!(data.as_slice().read_position().unwrap(), (-30, 255, -1));
+    }
+
+    #[test]
+    fn spawn_painting() {
+        let (mut prot, mut client) = test_protocol();
+        let painting = Entity::painting(12, Coord::new(-3, 70, 5), BlockFace::XP, Motive::Plant);
+        prot.send_packet(Packet::SpawnObject(Arc::new(RwLock::new(painting))));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x10);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), 12);
+        assert_eq!(data.read_string().unwrap(), "Plant");
+        assert_eq!(data.read_position().unwrap(), (-3, 70, 5));
+        assert_eq!(data.read_ubyte().unwrap(), 3);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn teams() {
+        let (mut prot, mut client) = test_protocol();
+        let mut team = Team::new("red".to_owned(), Some(0xc));
+        team.set_friendly_fire(false);
+        let mut teams = crate::teams::TeamManager::default();
+        teams.create(team);
+        teams.join("red", "Alice");
+        let team = teams.get("red").unwrap().clone();
+
+        let read_info = |data: &mut &[u8]| {
+            assert_eq!(data.read_string().unwrap(), "red"); // Display Name
+            assert_eq!(data.read_string().unwrap(), "§c"); // Prefix
+            assert_eq!(data.read_string().unwrap(), ""); // Suffix
+            assert_eq!(data.read_ubyte().unwrap(), 0x02); // Friendly Fire
+            assert_eq!(data.read_string().unwrap(), "always"); // Name Tag Visibility
+            assert_eq!(data.read_byte().unwrap(), 0xc); // Color
+        };
+        let read_players = |data: &mut &[u8]| {
+            let count = data.read_var_int().unwrap();
+            (0..count).map(|_| data.read_string().unwrap()).collect::<Vec<_>>()
+        };
+
+        let actions = [
+            TeamAction::Create(team.clone()),
+            TeamAction::Remove,
+            TeamAction::UpdateInfo(team),
+            TeamAction::AddPlayers(vec!["Bob".to_owned(), "Carol".to_owned()]),
+            TeamAction::RemovePlayers(vec!["Bob".to_owned()])
+        ];
+        for (mode, action) in actions.into_iter().enumerate() {
+            prot.send_packet(Packet::Teams("red".to_owned(), action));
+
+            let (id, data) = read_packet(&mut client);
+            assert_eq!(id, 0x3E);
+            let mut data = data.as_slice();
+            assert_eq!(data.read_string().unwrap(), "red");
+            assert_eq!(data.read_ubyte().unwrap() as usize, mode);
+            match mode {
+                0 => {
+                    read_info(&mut data);
+                    assert_eq!(read_players(&mut data), ["Alice"]);
+                }
+                1 => (),
+                2 => read_info(&mut data),
+                3 => assert_eq!(read_players(&mut data), ["Bob", "Carol"]),
+                _ => assert_eq!(read_players(&mut data), ["Bob"])
+            }
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn spawn_item() {
+        let (mut prot, mut client) = test_protocol();
+        let mut item = Entity::dropped_item(7, Coord::new(0.5, 65.0, -2.25), ItemStack::of(ItemType::Apple, 1));
+        item.set_velocity(Coord::new(0.1, 0.2, -5.0));
+        prot.send_packet(Packet::SpawnObject(Arc::new(RwLock::new(item))));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x0E);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), 7);
+        assert_eq!(data.read_ubyte().unwrap(), 2);
+        assert_eq!(data.read_int().unwrap(), 16);
+        assert_eq!(data.read_int().unwrap(), 65 * 32);
+        assert_eq!(data.read_int().unwrap(), -72);
+        assert_eq!(data.read_byte().unwrap(), 0);
+        assert_eq!(data.read_byte().unwrap(), 0);
+        // Items always have data 1, so the velocity follows
+        assert_eq!(data.read_int().unwrap(), 1);
+        assert_eq!(data.read_short().unwrap(), 800);
+        assert_eq!(data.read_short().unwrap(), 1600);
+        // Too fast, clamped like vanilla
+        assert_eq!(data.read_short().unwrap(), -31200);
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn spawn_mob() {
+        let (mut prot, mut client) = test_protocol();
+        let mut zombie = Entity::mob(7, MobType::Zombie, Coord::new(0.5, 16.0, -1.5));
+        zombie.set_rotation(90.0, 0.0);
+        prot.send_packet(Packet::SpawnObject(Arc::new(RwLock::new(zombie))));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x0F);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), 7);
+        assert_eq!(data.read_ubyte().unwrap(), 54);
+        assert_eq!(data.read_int().unwrap(), 16);
+        assert_eq!(data.read_int().unwrap(), 16 * 32);
+        assert_eq!(data.read_int().unwrap(), -48);
+        assert_eq!(data.read_byte().unwrap(), 64);
+        assert_eq!(data.read_byte().unwrap(), 0);
+        assert_eq!(data.read_byte().unwrap(), 64);
+        assert_eq!(data, [
+            0, 0, 0, 0, 0, 0,
+            // Health, a float at index 6
+            0x66, 0x41, 0xa0, 0x00, 0x00,
+            0x7f
+        ]);
+    }
+
+    #[test]
+    fn spawn_item_frame() {
+        let (mut prot, mut client) = test_protocol();
+        let frame = Entity::item_frame(7, Coord::new(2, 16, -1), BlockFace::ZM);
+        prot.send_packet(Packet::SpawnObject(Arc::new(RwLock::new(frame))));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x0E);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_var_int().unwrap(), 7);
+        assert_eq!(data.read_ubyte().unwrap(), 71);
+        assert_eq!(data.read_int().unwrap(), 2 * 32);
+        assert_eq!(data.read_int().unwrap(), 16 * 32);
+        assert_eq!(data.read_int().unwrap(), -32);
+        assert_eq!(data.read_byte().unwrap(), 0);
+        // Facing north is half a turn
+        assert_eq!(data.read_byte().unwrap(), -128);
+        // The direction is sent as the data, followed by the velocity
+        assert_eq!(data.read_int().unwrap(), 2);
+        assert_eq!(data, [0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn update_sign() {
+        let (mut prot, mut client) = test_protocol();
+        let mut sign = TileEntity::empty_sign();
+        if let TileEntity::Sign { lines } = &mut sign {
+            lines[1] = "\"Hello\"".to_owned();
+        }
+        prot.send_packet(Packet::UpdateBlockEntity(Coord::new(4, 70, -8), sign));
+
+        let (id, data) = read_packet(&mut client);
+        assert_eq!(id, 0x33);
+        let mut data = data.as_slice();
+        assert_eq!(data.read_position().unwrap(), (4, 70, -8));
+        for line in ["\"\"", "\"Hello\"", "\"\"", "\"\""] {
+            assert_eq!(data.read_string().unwrap(), line);
+        }
+        assert!(data.is_empty());
+    }
+
+    /// Returns a protocol of a player called Steve holding an empty writable book
+    fn book_protocol() -> (Protocol, TcpStream) {
+        let (prot, client_stream) = test_protocol();
+        let server = prot.server.clone();
+        let (client_id, client) = prot.get_client();
+        server.add_client(client_id, client.clone());
+        server.auth_user(client_id, "Steve".to_owned(), Uuid::from_u128(thread_rng().gen()), serde_json::Value::Null);
+        let player = server.default_world().read().unwrap().get_player(client_id).unwrap();
+        player.write().unwrap().inventory_mut().set_held_item(Some(ItemStack::of(ItemType::WritableBook, 1)));
+        player.write().unwrap().inventory_mut().take_changed();
+        while prot.receiver.try_recv().is_ok() {}
+        (prot, client_stream)
+    }
+
+    #[test]
+    fn edit_and_sign_book() {
+        let (mut prot, _client) = book_protocol();
+
+        let book_message = |channel: &str, book: &ItemStack| {
+            let mut rbuf = Vec::new();
+            rbuf.write_string(channel).unwrap();
+            write_slot(&mut rbuf, Some(book)).unwrap();
+            rbuf
+        };
+
+        let mut book = ItemStack::of(ItemType::WritableBook, 1);
+        book.set_book_pages(vec!["First page".to_owned(), "Second page".to_owned()]);
+        prot.handle_plugin_message(&book_message("MC|BEdit", &book)).unwrap();
+        match prot.receiver.try_recv().unwrap() {
+            Packet::SetSlot(0, slot, Some(item)) => {
+                assert_eq!(slot, HOTBAR_START as i16);
+                assert_eq!(item, book);
+                assert_eq!(item.book_pages().unwrap(), ["First page", "Second page"]);
+            }
+            _ => panic!("Expected set slot packet")
+        }
+
+        // Books over the vanilla limits of 50 pages of 256 characters are thrown away
+        let mut too_many_pages = ItemStack::of(ItemType::WritableBook, 1);
+        too_many_pages.set_book_pages(vec!["Page".to_owned(); 51]);
+        let mut page_too_long = ItemStack::of(ItemType::WritableBook, 1);
+        page_too_long.set_book_pages(vec!["a".repeat(257)]);
+        for book in [too_many_pages, page_too_long] {
+            prot.handle_plugin_message(&book_message("MC|BEdit", &book)).unwrap();
+            assert!(prot.receiver.try_recv().is_err());
+        }
+        let mut full = ItemStack::of(ItemType::WritableBook, 1);
+        full.set_book_pages(vec!["\u{e9}".repeat(256); 50]);
+        prot.handle_plugin_message(&book_message("MC|BEdit", &full)).unwrap();
+        assert!(matches!(prot.receiver.try_recv().unwrap(), Packet::SetSlot(0, _, Some(item)) if item == full));
+
+        // The author is always the player signing the book
+        let mut signed = ItemStack::of(ItemType::WrittenBook, 1);
+        signed.nbt = Some(Compound::new());
+        signed.nbt.as_mut().unwrap().insert("author", Tag::String("Alex".to_owned()));
+        signed.nbt.as_mut().unwrap().insert("title", Tag::String("Diary".to_owned()));
+        // 1.8 clients turn the pages into chat components when signing
+        signed.set_book_pages(vec!["\"First page\"".to_owned(), "\"Second page\"".to_owned()]);
+        prot.handle_plugin_message(&book_message("MC|BSign", &signed)).unwrap();
+        match prot.receiver.try_recv().unwrap() {
+            Packet::SetSlot(0, _, Some(item)) => {
+                assert_eq!(item.item_type(), Some(ItemType::WrittenBook));
+                let nbt = item.nbt.as_ref().unwrap();
+                assert_eq!(nbt.get("author").unwrap().as_str(), Some("Steve"));
+                assert_eq!(item.book_title(), Some("Diary"));
+                assert_eq!(item.book_pages().unwrap(), ["\"First page\"", "\"Second page\""]);
+            }
+            _ => panic!("Expected set slot packet")
+        }
+
+        // Written books can't be edited anymore
+        prot.handle_plugin_message(&book_message("MC|BEdit", &book)).unwrap();
+        assert!(prot.receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn sign_book_sent_by_vanilla_client() {
+        let (mut prot, _client) = book_protocol();
+        // Plugin Message of a 1.8 client signing a two page book, the compound keys are in the order of its HashMap
+        let payload = b"\x08MC|BSign\x01\x83\x01\x00\x00\x0a\x00\x00\
+            \x09\x00\x05pages\x08\x00\x00\x00\x02\x00\x0d\"Dear diary,\"\x00\x0c\"It rained.\"\
+            \x08\x00\x06author\x00\x05Steve\x08\x00\x05title\x00\x05Diary\x00";
+        prot.handle_plugin_message(payload).unwrap();
+        match prot.receiver.try_recv().unwrap() {
+            Packet::SetSlot(0, slot, Some(item)) => {
+                assert_eq!(slot, HOTBAR_START as i16);
+                assert_eq!(item.item_type(), Some(ItemType::WrittenBook));
+                assert_eq!(item.nbt.as_ref().unwrap().get("author").unwrap().as_str(), Some("Steve"));
+                assert_eq!(item.book_title(), Some("Diary"));
+                assert_eq!(item.book_pages().unwrap(), ["\"Dear diary,\"", "\"It rained.\""]);
+            }
+            _ => panic!("Expected set slot packet")
+        }
+    }
+
+    #[test]
+    fn slot_nbt_round_trip() {
+        let mut sword = ItemStack::of(ItemType::DiamondSword, 1);
+        sword.set_enchantment(Enchantment::Sharpness as i16, 3);
+        let mut wbuf = Vec::new();
+        write_slot(&mut wbuf, Some(&sword)).unwrap();
+        assert_eq!(read_slot(&mut wbuf.as_slice()).unwrap(), Some(sword));
+
+        wbuf.clear();
+        write_slot(&mut wbuf, None).unwrap();
+        assert_eq!(wbuf, [0xFF, 0xFF]);
+        assert_eq!(read_slot(&mut wbuf.as_slice()).unwrap(), None);
+    }
+
+    #[test]
+    fn var_int_split_across_reads() {
+        // 300_000 takes up three bytes
+        let mut encoded = Vec::new();
+        encoded.write_var_int(300_000).unwrap();
+        assert_eq!(encoded.len(), 3);
+
+        let mut received = RingBuf::with_capacity(RECEIVE_BUF_LEN);
+        let mut buf = [0u8; mcrw::VAR_INT_MAX_SIZE];
+        for (i, byte) in encoded.iter().enumerate() {
+            received.write_all(&[*byte]).unwrap();
+            let read = received.peek(&mut buf).unwrap();
+            let decoded = peek_var_int(&buf[..read]).unwrap();
+            if i + 1 < encoded.len() {
+                assert_eq!(decoded, None);
+            } else {
+                assert_eq!(decoded, Some((300_000, 3)));
+            }
+
+            // Nothing gets consumed by peeking
+            assert_eq!(received.len(), i + 1);
+        }
+
+        assert_eq!(peek_var_int(&[]).unwrap(), None);
+        assert_eq!(peek_var_int(&[0x80, 0x00]).unwrap(), Some((0, 2)));
+        assert!(peek_var_int(&[0xFF; 5]).is_err());
+    }
+
+    #[test]
+    fn creative_slot_with_nbt() {
+        let (server, _) = test_server_with_config(ServerConfig {
+            default_gamemode: GameMode::Creative,
+            ..test_config()
+        });
+        let (mut prot, _client) = connect(server.clone());
+        prot.state = State::Play;
+        let (client_id, client) = prot.get_client();
+        server.add_client(client_id, client.clone());
+        server.auth_user(client_id, "Steve".to_owned(), Uuid::from_u128(thread_rng().gen()), serde_json::Value::Null);
+        let player = server.default_world().read().unwrap().get_player(client_id).unwrap();
+
+        // A renamed diamond sword with Sharpness V and lore put in the first hotbar slot, like a vanilla client sends it
+        let mut payload = vec![0x00, 0x24, 0x01, 0x14, 0x01, 0x00, 0x00];
+        payload.extend([0x0A, 0x00, 0x00]);
+        payload.extend([0x09, 0x00, 0x04]);
+        payload.extend(b"ench");
+        payload.extend([0x0A, 0x00, 0x00, 0x00, 0x01]);
+        payload.extend([0x02, 0x00, 0x03]);
+        payload.extend(b"lvl");
+        payload.extend([0x00, 0x05, 0x02, 0x00, 0x02]);
+        payload.extend(b"id");
+        payload.extend([0x00, 0x10, 0x00]);
+        payload.extend([0x0A, 0x00, 0x07]);
+        payload.extend(b"display");
+        payload.extend([0x08, 0x00, 0x04]);
+        payload.extend(b"Name");
+        payload.extend([0x00, 0x0A]);
+        payload.extend(b"Excalibur!");
+        payload.extend([0x09, 0x00, 0x04]);
+        payload.extend(b"Lore");
+        payload.extend([0x08, 0x00, 0x00, 0x00, 0x01, 0x00, 0x09]);
+        payload.extend(b"Legendary");
+        payload.extend([0x00, 0x00]);
+        prot.handle_creative_inventory_action(&payload).unwrap();
+
+        let item = player.read().unwrap().inventory().get_slot(HOTBAR_START).cloned().unwrap();
+        assert_eq!(item.item_type(), Some(ItemType::DiamondSword));
+        let nbt = item.nbt.as_ref().unwrap();
+        let display = nbt.get("display").and_then(Tag::as_compound).unwrap();
+        assert_eq!(display.get("Name").and_then(Tag::as_str), Some("Excalibur!"));
+        assert_eq!(display.get("Lore"), Some(&Tag::List(vec![Tag::String("Legendary".to_owned())])));
+        let ench = nbt.get("ench").and_then(Tag::as_list).unwrap()[0].as_compound().unwrap();
+        assert_eq!(ench.get("id"), Some(&Tag::Short(Enchantment::Sharpness as i16)));
+        assert_eq!(ench.get("lvl"), Some(&Tag::Short(5)));
+
+        // Sending the item back gives the client the same bytes
+        let mut wbuf = Vec::new();
+        write_slot(&mut wbuf, Some(&item)).unwrap();
+        assert_eq!(wbuf, payload[2..]);
     }
 }
@@ -0,0 +1,199 @@
+//! Packets waiting for the connection to accept them
+
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Write};
+
+use openssl::symm::Crypter;
+
+/// Most bytes waiting to be written, a client falling further behind gets kicked
+pub const MAX_QUEUED_LEN: usize = 16 * 1024 * 1024;
+
+/// Which queue a packet waits in
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Priority {
+    /// Packets the connection depends on, like Keep Alive
+    Control,
+    /// Everything else, like chunk data
+    Bulk
+}
+
+/// Control packets are written before bulk packets, so they only wait for the packet being written
+/// instead of for every chunk queued before them
+#[derive(Default)]
+pub struct OutQueue {
+    control: VecDeque<Vec<u8>>,
+    bulk: VecDeque<Vec<u8>>,
+    /// Data of the packet being written and how much of it is written
+    current: Option<(Vec<u8>, usize)>,
+    /// Length of the packets in the queues, without the packet being written
+    queued_len: usize
+}
+
+impl OutQueue {
+    /// Queues a framed packet, returns false without queuing it if that would exceed `MAX_QUEUED_LEN`
+    pub fn push(&mut self, frame: Vec<u8>, priority: Priority) -> bool {
+        if self.queued_len + frame.len() > MAX_QUEUED_LEN {
+            return false;
+        }
+
+        self.queued_len += frame.len();
+        match priority {
+            Priority::Control => self.control.push_back(frame),
+            Priority::Bulk => self.bulk.push_back(frame)
+        }
+        true
+    }
+
+    /// Drops the bulk packets which aren't being written yet
+    pub fn clear_bulk(&mut self) {
+        self.queued_len -= self.bulk.iter().map(Vec::len).sum::<usize>();
+        self.bulk.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_none() && self.control.is_empty() && self.bulk.is_empty()
+    }
+
+    /// Writes the queued packets until the writer would block.
+    /// Packets are encrypted right before they're written, as the cipher has to see the data in the order it's sent.
+    pub fn flush<W: Write>(&mut self, writer: &mut W, mut encrypter: Option<&mut Crypter>) -> io::Result<()> {
+        loop {
+            if self.current.is_none() {
+                let frame = match self.control.pop_front().or_else(|| self.bulk.pop_front()) {
+                    Some(v) => v,
+                    None => return Ok(())
+                };
+                self.queued_len -= frame.len();
+
+                let data = match encrypter.as_deref_mut() {
+                    Some(en) => {
+                        let mut enc_buf = vec![0; frame.len() + 128];
+                        let enc_len = en.update(&frame, &mut enc_buf).unwrap();
+                        enc_buf.truncate(enc_len);
+                        enc_buf
+                    }
+                    None => frame
+                };
+                self.current = Some((data, 0));
+            }
+
+            let (data, written) = self.current.as_mut().unwrap();
+            while *written < data.len() {
+                match writer.write(&data[*written..]) {
+                    Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                    Ok(n) => *written += n,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                    Err(e) => return Err(e)
+                }
+            }
+
+            self.current = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::symm::{Cipher, Mode};
+
+    use super::*;
+
+    /// Connection to a slow client, which accepts a few bytes per tick
+    struct ThrottledWriter {
+        data: Vec<u8>,
+        /// Bytes left to accept this tick
+        budget: usize
+    }
+
+    impl Write for ThrottledWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.budget == 0 {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+
+            let len = buf.len().min(self.budget).min(64);
+            self.data.extend_from_slice(&buf[..len]);
+            self.budget -= len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Pushes two chunk sized packets, then a keep alive while the first one is being written
+    /// and returns what got written after flushing every tick until the queue is empty
+    fn write_with_keep_alive(mut encrypter: Option<Crypter>) -> Vec<u8> {
+        let mut queue = OutQueue::default();
+        let mut writer = ThrottledWriter { data: Vec::new(), budget: 300 };
+        assert!(queue.push(vec![1; 1000], Priority::Bulk));
+        assert!(queue.push(vec![2; 1000], Priority::Bulk));
+        queue.flush(&mut writer, encrypter.as_mut()).unwrap();
+        assert_eq!(writer.data.len(), 300);
+
+        assert!(queue.push(vec![0, 7], Priority::Control));
+        while !queue.is_empty() {
+            writer.budget = 300;
+            queue.flush(&mut writer, encrypter.as_mut()).unwrap();
+        }
+
+        writer.data
+    }
+
+    fn expected() -> Vec<u8> {
+        [vec![1; 1000], vec![0, 7], vec![2; 1000]].concat()
+    }
+
+    #[test]
+    fn control_before_bulk() {
+        assert_eq!(write_with_keep_alive(None), expected());
+    }
+
+    #[test]
+    fn encrypted_in_write_order() {
+        let key = [3u8; 16];
+        let cipher = Cipher::aes_128_cfb8();
+        let encrypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&key)).unwrap();
+        let data = write_with_keep_alive(Some(encrypter));
+
+        let mut decrypter = Crypter::new(cipher, Mode::Decrypt, &key, Some(&key)).unwrap();
+        let mut decrypted = vec![0; data.len() + cipher.block_size()];
+        let len = decrypter.update(&data, &mut decrypted).unwrap();
+        decrypted.truncate(len);
+        assert_eq!(decrypted, expected());
+    }
+
+    #[test]
+    fn push_past_max_len_fails() {
+        let mut queue = OutQueue::default();
+        let mut writer = ThrottledWriter { data: Vec::new(), budget: 0 };
+        assert!(queue.push(vec![1; MAX_QUEUED_LEN - 10], Priority::Bulk));
+        assert!(!queue.push(vec![2; 11], Priority::Control));
+        assert!(queue.push(vec![2; 10], Priority::Control));
+
+        // Packets that started being written no longer count
+        writer.budget = 64;
+        queue.flush(&mut writer, None).unwrap();
+        assert!(queue.push(vec![3; 11], Priority::Bulk));
+    }
+
+    #[test]
+    fn clear_bulk_keeps_control_and_current() {
+        let mut queue = OutQueue::default();
+        let mut writer = ThrottledWriter { data: Vec::new(), budget: 64 };
+        assert!(queue.push(vec![1; 100], Priority::Bulk));
+        assert!(queue.push(vec![2; 100], Priority::Bulk));
+        queue.flush(&mut writer, None).unwrap();
+        assert!(queue.push(vec![0, 7], Priority::Control));
+        queue.clear_bulk();
+        assert!(queue.push(vec![3; MAX_QUEUED_LEN - 2], Priority::Bulk));
+
+        while !queue.is_empty() {
+            writer.budget = MAX_QUEUED_LEN;
+            queue.flush(&mut writer, None).unwrap();
+        }
+        assert_eq!(writer.data, [vec![1; 100], vec![0, 7], vec![3; MAX_QUEUED_LEN - 2]].concat());
+    }
+}
@@ -1,15 +1,26 @@
 use std::sync::{Arc, RwLock};
 
-use crate::coord::ChunkCoord;
-use crate::entities::player::Player;
-use crate::protocol::GameStateReason;
+use serde_json::Value;
+
+use crate::blocks::BlockType;
+use crate::coord::{ChunkCoord, Coord};
+use crate::entities::Entity;
+use crate::entities::attributes::Attributes;
+use crate::entities::effects::{Effect, EffectType};
+use crate::entities::metadata::Metadata;
+use crate::entities::player::{GameMode, Player};
+use crate::items::ItemStack;
+use crate::map::{MapIcon, MapUpdate};
+use crate::protocol::{Animation, EntityStatus, GameStateReason, TeleportFlags};
 use crate::storage::chunk::chunk_map::ChunkMap;
-use crate::storage::world::{Difficulty, World};
+use crate::storage::world::{Difficulty, Dimension, World};
+use crate::teams::TeamAction;
+use crate::tile_entities::TileEntity;
+use crate::window::WindowType;
 
 #[derive(Clone)]
 pub enum Packet {
     // Login state
-    ///
     LoginSuccess(),
 
     // Play state
@@ -21,26 +32,101 @@ pub enum Packet {
     TimeUpdate(Arc<RwLock<World>>),
     /// World
     SpawnPosition(Arc<RwLock<World>>),
+    /// World, Gamemode
+    Respawn(Arc<RwLock<World>>, GameMode),
+    /// Health, Food, Food Saturation
+    UpdateHealth(f32, i32, f32),
+    /// Level
+    SetExperience(i32),
     /// Player
     PlayerPositionAndLook(Arc<RwLock<Player>>),
+    /// Position, Yaw, Pitch, Flags, then the absolute Position, Yaw and Pitch they result in
+    /// for clients without relative teleports, sent as Player Position And Look
+    Teleport(Coord<f64>, f32, f32, TeleportFlags, (Coord<f64>, f32, f32)),
     /// Player
     SpawnPlayer(Arc<RwLock<Player>>),
+    /// Entity, paintings are sent as Spawn Painting
+    SpawnObject(Arc<RwLock<Entity>>),
+    /// Entity ID, Type, Position, Metadata
+    SpawnMob(u32, u8, Coord<f64>, Metadata),
+    /// Entity IDs
+    DestroyEntities(Vec<u32>),
+    /// Entity ID, Change in position in 1/32 blocks, On ground
+    EntityRelativeMove(u32, Coord<i8>, bool),
+    /// Entity ID, Yaw, Pitch, On ground
+    EntityLook(u32, f32, f32, bool),
+    /// Entity ID, Change in position in 1/32 blocks, Yaw, Pitch, On ground
+    EntityLookAndRelativeMove(u32, Coord<i8>, f32, f32, bool),
+    /// Entity ID, Position, Yaw, Pitch, On ground
+    EntityTeleport(u32, Coord<f64>, f32, f32, bool),
+    /// Entity ID, Head yaw
+    EntityHeadLook(u32, f32),
+    /// Entity ID, Status
+    EntityStatus(u32, EntityStatus),
+    /// Entity ID, Velocity in blocks per tick
+    EntityVelocity(u32, Coord<f64>),
+    /// Player ID, Location of the head of the bed
+    UseBed(u32, Coord<i32>),
+    /// Entity ID, Animation
+    Animation(u32, Animation),
+    /// Player ID, Killer ID, Death message
+    CombatEvent(u32, Option<u32>, String),
+    /// Entity ID, Vehicle ID (None detaches the entity), Leash
+    AttachEntity(u32, Option<u32>, bool),
+    /// Entity ID, Metadata
+    EntityMetadata(u32, Metadata),
+    /// Entity ID, Effect
+    EntityEffect(u32, Effect),
+    /// Entity ID, Effect ID
+    RemoveEntityEffect(u32, EffectType),
+    /// Entity ID, Attributes
+    EntityProperties(u32, Attributes),
     /// PlayerListAction, Players
     PlayerListItem(PlayerListAction, Box<[Arc<RwLock<Player>>]>),
     /// Player
     PlayerAbilities(Arc<RwLock<Player>>),
-    /// Primary Bit Mask, Chunk Data
-    ChunkData(ChunkCoord, Arc<ChunkMap>),
+    /// Chunk and the dimension it's in
+    ChunkData(ChunkCoord, Arc<ChunkMap>, Dimension),
+    /// Empty Chunk Data telling the client to unload the chunk
+    ChunkUnload(ChunkCoord),
+    /// Chunks to send at once and the dimension they're in
+    MapChunkBulk(Vec<ChunkCoord>, Arc<ChunkMap>, Dimension),
+    /// Chunk, Location, Block Type and Block Meta of each changed block
+    MultiBlockChange(ChunkCoord, Vec<(Coord<i32>, BlockType, u8)>),
+    /// Location, Block Type, Block Meta
+    BlockChange(Coord<i32>, BlockType, u8),
+    /// Center, Radius, Destroyed blocks, Player Motion
+    Explosion(Coord<f64>, f32, Vec<Coord<i32>>, Coord<f64>),
+    /// Map ID, Scale, Icons, Updated area
+    Map(i32, u8, Vec<MapIcon>, Option<MapUpdate>),
+    /// Statistic names and their values
+    Statistics(Vec<(String, i32)>),
+    /// Position, Tile Entity
+    UpdateBlockEntity(Coord<i32>, TileEntity),
+    /// Location of the sign
+    OpenSignEditor(Coord<i32>),
     /// Difficulty
     ServerDifficulty(Difficulty),
-    ///
+    /// Reason, Value
     ChangeGameState(GameStateReason, f32),
-    ///
+    /// Window ID, Window Type, Number of slots
+    OpenWindow(u8, WindowType, u8),
+    /// Window ID
+    CloseWindow(u8),
+    /// Window ID, Slot, Slot Data
+    SetSlot(i8, i16, Option<ItemStack>),
+    /// Window ID, Slot Data of every slot
+    WindowItems(u8, Vec<Option<ItemStack>>),
+    /// Window ID, Property, Value
+    WindowProperty(u8, i16, i16),
+    /// URL, Hash
     ResourcePackSend(String, String),
+    /// Team name, Action
+    Teams(String, TeamAction),
 
     // Other
-    /// Reason
-    Disconnect(String),
+    /// Reason, as a chat component
+    Disconnect(Value),
 }
 
 #[repr(i32)]
@@ -1,17 +1,14 @@
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Instant, SystemTime};
 
 use crossbeam_channel::{Receiver, Sender};
 
 use crate::TICK_DURATION;
 use crate::protocol::Protocol;
 
-const KEEP_ALIVE_INTERVAL: Duration = Duration::from_millis(1000);
-
 pub struct ProtocolThread {
     rx: Receiver<Protocol>,
-    prots: Vec<Protocol>,
-    last_keep_alive: SystemTime
+    prots: Vec<Protocol>
 }
 
 impl ProtocolThread {
@@ -21,8 +18,7 @@ impl ProtocolThread {
         thread::spawn(move || {
             let mut thread = ProtocolThread {
                 rx,
-                prots: Vec::new(),
-                last_keep_alive: SystemTime::now()
+                prots: Vec::new()
             };
 
             loop {
@@ -41,12 +37,9 @@ impl ProtocolThread {
             self.prots.push(prot);
         }
 
-        let send_keep_alive = self.last_keep_alive.elapsed().unwrap() >= KEEP_ALIVE_INTERVAL;
-        let millis = if send_keep_alive {
-            SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as i32
-        } else {
-            0
-        };
+        // Each protocol keeps its own keep alive schedule, they share the id
+        let now = Instant::now();
+        let millis = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as i32;
 
         for prot in self.prots.iter_mut() {
             if prot.is_disconnected() {
@@ -54,11 +47,15 @@ impl ProtocolThread {
                 continue;
             }
 
-            prot.process_data();
-            if send_keep_alive {
-                prot.keep_alive(millis);
+            if prot.is_closing() {
+                // Kicked, only the queued packets are left to write
+                prot.tick_close(now);
+                continue;
             }
 
+            prot.process_data();
+            prot.tick_keep_alive(now, millis);
+
             prot.handle_out_packets();
         }
     }
@@ -1,24 +1,203 @@
+//! Protocol version 47 (Minecraft 1.8), the packets that differ from other versions and chunk serialization
+
+use std::borrow::Cow;
 use std::io::{Result, Write};
 use std::mem::size_of;
+use std::sync::{Arc, OnceLock};
 
 #[cfg(target_arch = "x86")]
 use std::arch::x86::*;
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-use mcrw::MCWriteExt;
-
-use crate::storage::chunk::{AREA, SECTION_BLOCK_COUNT, SECTION_COUNT, SerializeChunk, Chunk};
+use mcrw::{MCReadExt, MCWriteExt};
+
+use crate::blocks::BlockType;
+use crate::coord::{ChunkCoord, Coord};
+use crate::protocol::{TeleportFlags, read_byte_array};
+use crate::protocol::error;
+use crate::protocol::ids::ServerboundPlay;
+use crate::protocol::packets::Packet;
+use crate::protocol::version::{JoinGame, ProtocolVersion};
+use crate::storage::chunk::{AREA, SECTION_BLOCK_COUNT, SECTION_COUNT, SerializeChunk, SerializeContext, Chunk};
+use crate::storage::chunk::chunk_map::ChunkMap;
 use crate::storage::chunk::section::Section;
 
+pub const PROTOCOL_VERSION: i32 = 47;
+
+/// Name of the Minecraft version of the protocol
+pub const VERSION_NAME: &str = "1.8.9";
+
+pub struct V47;
+
+impl ProtocolVersion for V47 {
+    fn protocol(&self) -> i32 {
+        PROTOCOL_VERSION
+    }
+
+    fn name(&self) -> &'static str {
+        VERSION_NAME
+    }
+
+    fn supports_compression(&self) -> bool {
+        true
+    }
+
+    fn supports(&self, _packet: &Packet) -> bool {
+        true
+    }
+
+    fn translate_play_packet<'a>(&self, _packet: ServerboundPlay, rbuf: &'a [u8]) -> error::Result<Option<Cow<'a, [u8]>>> {
+        Ok(Some(Cow::Borrowed(rbuf)))
+    }
+
+    fn write_encryption_request(&self, wbuf: &mut Vec<u8>, public_key: &[u8], verify_token: &[u8]) {
+        wbuf.write_var_int(public_key.len() as i32).unwrap(); // Public Key Length
+        wbuf.write_all(public_key).unwrap(); // Public Key
+        wbuf.write_var_int(verify_token.len() as i32).unwrap(); // Verify Token Length
+        wbuf.write_all(verify_token).unwrap(); // Verify Token
+    }
+
+    fn read_encryption_response(&self, mut rbuf: &[u8]) -> error::Result<(Vec<u8>, Vec<u8>)> {
+        let len = rbuf.read_var_int()?; // Shared Secret Length
+        let shared_secret = read_byte_array(&mut rbuf, len)?; // Shared Secret
+        let len = rbuf.read_var_int()?; // Verify Token Length
+        let verify_token = read_byte_array(&mut rbuf, len)?; // Verify Token
+        Ok((shared_secret, verify_token))
+    }
+
+    fn write_keep_alive(&self, wbuf: &mut Vec<u8>, id: i32) {
+        wbuf.write_var_int(id).unwrap(); // Keep Alive ID
+    }
+
+    fn write_join_game(&self, wbuf: &mut Vec<u8>, join: &JoinGame) {
+        wbuf.write_int(join.entity_id).unwrap(); // The player's Entity ID
+        wbuf.write_ubyte(join.gamemode).unwrap(); // Gamemode
+        wbuf.write_byte(join.dimension).unwrap(); // Dimension
+        wbuf.write_ubyte(join.difficulty).unwrap(); // Difficulty
+        wbuf.write_ubyte(join.max_players).unwrap(); // Max players
+        wbuf.write_string(join.level_type).unwrap(); // Level Type? (default, flat, largeBiomes, amplified, default_1_1)
+        wbuf.write_bool(false).unwrap(); // Reduced debug info?
+    }
+
+    fn write_chat_message(&self, wbuf: &mut Vec<u8>, json: &str) {
+        wbuf.write_string(json).unwrap(); // JSON Data
+        wbuf.write_ubyte(0).unwrap(); // Position: 0: chat (chat box), 1: system message (chat box), 2: above hotbar
+    }
+
+    fn write_spawn_position(&self, wbuf: &mut Vec<u8>, pos: Coord<i32>) {
+        wbuf.write_position(pos.x, pos.y, pos.z).unwrap(); // Spawn location
+    }
+
+    fn write_update_health(&self, wbuf: &mut Vec<u8>, health: f32, food: i32, saturation: f32) {
+        // 0 or less = dead, 20 = full HP
+        wbuf.write_float(health).unwrap(); // Health
+        wbuf.write_var_int(food).unwrap(); // Food
+        wbuf.write_float(saturation).unwrap(); // Food Saturation
+    }
+
+    fn write_player_pos_look(&self, wbuf: &mut Vec<u8>, pos: Coord<f64>, yaw: f32, pitch: f32, flags: TeleportFlags, _absolute: (Coord<f64>, f32, f32)) {
+        wbuf.write_double(pos.x).unwrap(); // X
+        wbuf.write_double(pos.y).unwrap(); // y
+        wbuf.write_double(pos.z).unwrap(); // z
+        wbuf.write_float(yaw).unwrap(); // Yaw
+        wbuf.write_float(pitch).unwrap(); // Pitch
+        wbuf.write_ubyte(flags.bits()).unwrap(); // Flags
+    }
+
+    fn write_chunk_data(&self, wbuf: &mut Vec<u8>, coord: ChunkCoord, chunk: Option<&Chunk>, context: &SerializeContext) {
+        wbuf.write_int(coord.x).unwrap(); // Chunk X
+        wbuf.write_int(coord.z).unwrap(); // Chunk Z
+
+        // This is true if the packet represents all sections in this vertical column,
+        // where the Primary Bit Mask specifies exactly which sections are included, and which are air
+        wbuf.write_bool(true).unwrap(); // Ground-Up Continuous
+
+        match chunk {
+            Some(chunk) => {
+                let data = serialized_data(chunk, context);
+                wbuf.write_ushort(chunk.data.get_primary_bit_mask()).unwrap(); // Primary Bit Mask
+                chunk.serialize(&mut *wbuf, &SerializeContext { cache: Some(&data), ..*context }).unwrap();
+            }
+            None => {
+                wbuf.write_ushort(0).unwrap(); // Primary Bit Mask
+                // The client unloads the chunk as soon as it sees the empty bit mask and never reads any data,
+                // so unlike other ground-up continuous chunks there's no biome array
+                wbuf.write_var_int(0).unwrap(); // Size
+            }
+        }
+    }
+
+    fn write_multi_block_change(&self, wbuf: &mut Vec<u8>, coord: ChunkCoord, records: &[(Coord<i32>, BlockType, u8)]) {
+        wbuf.write_int(coord.x).unwrap(); // Chunk X
+        wbuf.write_int(coord.z).unwrap(); // Chunk Z
+        wbuf.write_var_int(records.len() as i32).unwrap(); // Record Count
+        for (pos, block_type, meta) in records {
+            let rel_pos = Chunk::abs_to_rel(*pos, coord);
+            wbuf.write_ubyte(((rel_pos.x << 4) | rel_pos.z) as u8).unwrap(); // Horizontal Position
+            wbuf.write_ubyte(rel_pos.y as u8).unwrap(); // Y Coordinate
+            wbuf.write_var_int(((*block_type as i32) << 4) | (meta & 0x0f) as i32).unwrap(); // Block ID
+        }
+    }
+
+    fn write_block_change(&self, wbuf: &mut Vec<u8>, pos: Coord<i32>, block_type: BlockType, meta: u8) {
+        wbuf.write_position(pos.x, pos.y, pos.z).unwrap(); // Location
+        // The new block state ID for the block as given in the global palette (type << 4 | meta)
+        wbuf.write_var_int(((block_type as i32) << 4) | (meta & 0x0f) as i32).unwrap(); // Block ID
+    }
+
+    fn write_map_chunk_bulk(&self, wbuf: &mut Vec<u8>, coords: &[ChunkCoord], chunk_map: &ChunkMap, context: &SerializeContext) {
+        wbuf.write_bool(context.sky_light).unwrap(); // Sky Light Sent
+        wbuf.write_var_int(coords.len() as i32).unwrap(); // Chunk Column Count
+
+        let mut data = Vec::new();
+        for coord in coords {
+            wbuf.write_int(coord.x).unwrap(); // Chunk X
+            wbuf.write_int(coord.z).unwrap(); // Chunk Z
+            let mut bit_mask = 0;
+            chunk_map.do_with_chunk(*coord, |chunk: &Chunk| {
+                bit_mask = chunk.data.get_primary_bit_mask();
+                // Players loading the same area share the serialized data
+                data.extend_from_slice(&serialized_data(chunk, context));
+            });
+            wbuf.write_ushort(bit_mask).unwrap(); // Primary Bit Mask
+        }
+
+        wbuf.write_all(&data).unwrap(); // Chunk Data
+    }
+}
+
+/// Returns the data of the chunk in the 1.8 format without the size, shared with the other 1.8 players it's sent to
+pub fn serialized_data(chunk: &Chunk, context: &SerializeContext) -> Arc<[u8]> {
+    chunk.serialized_data(PROTOCOL_VERSION, context.sky_light, |data| {
+        let context = SerializeContext { cache: None, ..*context };
+        data.reserve(chunk.serialized_size(&context));
+        chunk.serialize_data(data, &context).unwrap();
+    })
+}
+
 impl SerializeChunk for Chunk {
-    fn serialized_size(&self) -> usize {
-        self.data.get_num_sections() * SECTION_BLOCK_COUNT * 3 + AREA as usize
+    fn serialized_size(&self, context: &SerializeContext) -> usize {
+        if let Some(cache) = context.cache {
+            return cache.len();
+        }
+
+        // 2 bytes of block info and half a byte of each light array per block
+        let per_section = if context.sky_light { SECTION_BLOCK_COUNT * 3 } else { SECTION_BLOCK_COUNT * 5 / 2 };
+        self.data.get_num_sections() * per_section + AREA as usize
     }
 
-    fn serialize<W>(&self, mut buf: W) -> Result<()>
+    fn serialize<W>(&self, mut buf: W, context: &SerializeContext) -> Result<()>
         where W: Write {
-        buf.write_var_int(self.serialized_size() as i32)?;
+        buf.write_var_int(self.serialized_size(context) as i32)?;
+        self.serialize_data(buf, context)
+    }
+
+    fn serialize_data<W>(&self, mut buf: W, context: &SerializeContext) -> Result<()>
+        where W: Write {
+        if let Some(cache) = context.cache {
+            return buf.write_all(cache);
+        }
 
         write_block_info(&self.data.sections, &mut buf)?;
 
@@ -26,29 +205,58 @@ impl SerializeChunk for Chunk {
             buf.write_all(&section.block_light)?;
         }
 
-        for section in self.data.sections.iter().filter_map(|x| x.as_ref()) {
-            buf.write_all(&section.block_sky_light)?;
+        if context.sky_light {
+            for section in self.data.sections.iter().filter_map(|x| x.as_ref()) {
+                buf.write_all(&section.block_sky_light)?;
+            }
         }
 
         buf.write_all(&self.biome_map)
     }
 }
 
-fn write_block_info<W>(sections: &[Option<Box<Section>>; SECTION_COUNT], mut buf: W) -> Result<()>
-    where W : Write {
+type WriteBlockInfoFn = fn(&[Option<Box<Section>>; SECTION_COUNT], &mut dyn Write) -> Result<()>;
+
+/// Implementation picked for the CPU the first time block info is written
+static WRITE_BLOCK_INFO: OnceLock<WriteBlockInfoFn> = OnceLock::new();
 
+fn detect_write_block_info() -> WriteBlockInfoFn {
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     {
+        if is_x86_feature_detected!("avx512bw") {
+            return |sections, buf| unsafe { write_block_info_avx512bw(sections, buf) };
+        }
+
         if is_x86_feature_detected!("avx2") {
-            return unsafe { write_block_info_avx2(sections, &mut buf) };
+            return |sections, buf| unsafe { write_block_info_avx2(sections, buf) };
         }
 
         if is_x86_feature_detected!("sse2") {
-            return unsafe { write_block_info_sse2(sections, &mut buf) };
+            return |sections, buf| unsafe { write_block_info_sse2(sections, buf) };
         }
     }
 
-    write_block_info_fallback(sections, &mut buf)
+    |sections, buf| write_block_info_fallback(sections, buf)
+}
+
+/// Writes the block types and metas of the present sections in the 1.8 chunk format,
+/// 2 bytes per block: `type << 4 | meta` as a little endian short.
+///
+/// Uses AVX-512BW, AVX2 or SSE2 when the CPU supports it, the check is only done once per process.
+///
+/// ```
+/// use siderite_core::write_block_info;
+/// use siderite_core::storage::chunk::SECTION_COUNT;
+/// use siderite_core::storage::chunk::section::Section;
+///
+/// let sections: [Option<Box<Section>>; SECTION_COUNT] = Default::default();
+/// let mut buf = Vec::new();
+/// write_block_info(&sections, &mut buf).unwrap();
+/// assert!(buf.is_empty());
+/// ```
+pub fn write_block_info<W>(sections: &[Option<Box<Section>>; SECTION_COUNT], mut buf: W) -> Result<()>
+    where W : Write {
+    WRITE_BLOCK_INFO.get_or_init(detect_write_block_info)(sections, &mut buf)
 }
 
 fn write_block_info_fallback<W>(sections: &[Option<Box<Section>>; SECTION_COUNT], mut buf: W) -> Result<()>
@@ -194,6 +402,61 @@ unsafe fn write_block_info_avx2<W>(sections: &[Option<Box<Section>>; SECTION_COU
     Ok(())
 }
 
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx512bw")]
+unsafe fn write_block_info_avx512bw<W>(sections: &[Option<Box<Section>>; SECTION_COUNT], mut buf: W) -> Result<()>
+    where W : Write {
+
+    const VECTOR_SIZE: usize = size_of::<__m512i>();
+    const STEP_SIZE: usize = 2 * VECTOR_SIZE;
+    const BUF_SIZE: usize = 2 * STEP_SIZE;
+
+    let low_mask = _mm512_set1_epi8(0x0f);
+    // Unpacking works within 128-bit lanes, these put the lanes of the low and high halves back in order
+    let first_half = _mm512_set_epi64(11, 10, 3, 2, 9, 8, 1, 0);
+    let second_half = _mm512_set_epi64(15, 14, 7, 6, 13, 12, 5, 4);
+
+    // Sections are only 32-byte aligned, so unaligned loads and stores are used
+    let mut write_buf = [0u8; BUF_SIZE];
+
+    for section in sections.iter().filter_map(|x| x.as_ref()) {
+        for i in 0..(SECTION_BLOCK_COUNT / STEP_SIZE) {
+
+            let in_types1 = _mm512_loadu_si512(section.block_types[i * STEP_SIZE..].as_ptr().cast());
+            let in_types2 = _mm512_loadu_si512(section.block_types[i * STEP_SIZE + VECTOR_SIZE..].as_ptr().cast());
+
+            let in_metas = _mm512_loadu_si512(section.block_metas[i * (STEP_SIZE / 2)..].as_ptr().cast());
+            let in_metas_shifted = _mm512_srli_epi16::<4>(in_metas);
+
+            let metas_low = _mm512_unpacklo_epi8(in_metas, in_metas_shifted);
+            let metas_high = _mm512_unpackhi_epi8(in_metas, in_metas_shifted);
+            let metas1 = _mm512_and_si512(_mm512_permutex2var_epi64(metas_low, first_half, metas_high), low_mask);
+            let metas2 = _mm512_and_si512(_mm512_permutex2var_epi64(metas_low, second_half, metas_high), low_mask);
+
+            let types_shift_right1 = _mm512_and_si512(low_mask, _mm512_srli_epi16::<4>(in_types1));
+            let types_shift_left1 = _mm512_andnot_si512(low_mask, _mm512_slli_epi16::<4>(in_types1));
+            let types_with_metas1 = _mm512_or_si512(types_shift_left1, metas1);
+            let types_shift_right2 = _mm512_and_si512(low_mask, _mm512_srli_epi16::<4>(in_types2));
+            let types_shift_left2 = _mm512_andnot_si512(low_mask, _mm512_slli_epi16::<4>(in_types2));
+            let types_with_metas2 = _mm512_or_si512(types_shift_left2, metas2);
+
+            let first = _mm512_unpacklo_epi8(types_with_metas1, types_shift_right1);
+            let second = _mm512_unpackhi_epi8(types_with_metas1, types_shift_right1);
+            let third = _mm512_unpacklo_epi8(types_with_metas2, types_shift_right2);
+            let fourth = _mm512_unpackhi_epi8(types_with_metas2, types_shift_right2);
+
+            _mm512_storeu_si512(write_buf.as_mut_ptr().cast(), _mm512_permutex2var_epi64(first, first_half, second));
+            _mm512_storeu_si512(write_buf[VECTOR_SIZE..].as_mut_ptr().cast(), _mm512_permutex2var_epi64(first, second_half, second));
+            _mm512_storeu_si512(write_buf[2 * VECTOR_SIZE..].as_mut_ptr().cast(), _mm512_permutex2var_epi64(third, first_half, fourth));
+            _mm512_storeu_si512(write_buf[3 * VECTOR_SIZE..].as_mut_ptr().cast(), _mm512_permutex2var_epi64(third, second_half, fourth));
+
+            buf.write_all(&write_buf)?;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::array;
@@ -203,6 +466,7 @@ mod tests {
     use super::*;
 
     use crate::storage::chunk::ChunkColumn;
+    use crate::storage::world::Dimension;
 
     impl Arbitrary for Section {
         fn arbitrary(g: &mut Gen) -> Section {
@@ -255,4 +519,46 @@ mod tests {
         write_block_info_fallback(&data.sections, buf2.as_mut_slice()).unwrap();
         buf1 == buf2
     }
+
+    #[quickcheck]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn write_block_info_avx512bw_matches_fallback(data: ChunkColumn) -> bool {
+        // Only checked when the CPU running the tests supports it
+        if !is_x86_feature_detected!("avx512bw") {
+            return true;
+        }
+
+        let mut buf1 = create_output_buf!();
+        let mut buf2 = create_output_buf!();
+        unsafe { write_block_info_avx512bw(&data.sections, buf1.as_mut_slice()).unwrap(); }
+        write_block_info_fallback(&data.sections, buf2.as_mut_slice()).unwrap();
+        buf1 == buf2
+    }
+
+    #[quickcheck]
+    fn nether_chunks_skip_sky_light(data: ChunkColumn) -> bool {
+        let chunk = Chunk::new(data, [0u8; AREA as usize]);
+        let sky_light_size = chunk.data.get_num_sections() * SECTION_BLOCK_COUNT / 2;
+
+        let (overworld_context, nether_context) = (SerializeContext::default(), SerializeContext::new(Dimension::Nether));
+        let mut overworld = Vec::new();
+        let mut nether = Vec::new();
+        chunk.serialize(&mut overworld, &overworld_context).unwrap();
+        chunk.serialize(&mut nether, &nether_context).unwrap();
+
+        chunk.serialized_size(&overworld_context) - chunk.serialized_size(&nether_context) == sky_light_size
+            && overworld.len() - nether.len() == sky_light_size
+    }
+
+    #[quickcheck]
+    fn cached_data_is_written_as_is(data: ChunkColumn) -> bool {
+        let chunk = Chunk::new(data, [0u8; AREA as usize]);
+        let mut cache = Vec::new();
+        chunk.serialize_data(&mut cache, &SerializeContext::default()).unwrap();
+
+        let context = SerializeContext { cache: Some(&cache), ..Default::default() };
+        let mut cached = Vec::new();
+        chunk.serialize_data(&mut cached, &context).unwrap();
+        cached == cache && chunk.serialized_size(&context) == cache.len()
+    }
 }
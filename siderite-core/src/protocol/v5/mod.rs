@@ -0,0 +1,376 @@
+//! Protocol version 5 (Minecraft 1.7.10), still used by a lot of modpacks.
+//! Clients can log in, move around and change the world, packets without a 1.7 format yet aren't sent to them,
+//! like the ones spawning entities or filling inventories.
+
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use mcrw::{MCReadExt, MCWriteExt};
+
+use crate::blocks::BlockType;
+use crate::coord::{ChunkCoord, Coord};
+use crate::protocol::{TeleportFlags, read_byte_array};
+use crate::protocol::error::Result;
+use crate::protocol::ids::ServerboundPlay;
+use crate::protocol::packets::Packet;
+use crate::protocol::version::{JoinGame, ProtocolVersion};
+use crate::storage::chunk::{Chunk, SerializeContext};
+use crate::storage::chunk::chunk_map::ChunkMap;
+
+pub const PROTOCOL_VERSION: i32 = 5;
+
+/// Name of the Minecraft version of the protocol
+pub const VERSION_NAME: &str = "1.7.10";
+
+/// 1.7 clients send and expect the position of the eyes instead of the feet in Player Position And Look
+const EYE_HEIGHT: f64 = 1.62;
+
+pub struct V5;
+
+impl ProtocolVersion for V5 {
+    fn protocol(&self) -> i32 {
+        PROTOCOL_VERSION
+    }
+
+    fn name(&self) -> &'static str {
+        VERSION_NAME
+    }
+
+    fn supports_compression(&self) -> bool {
+        false
+    }
+
+    fn supports(&self, packet: &Packet) -> bool {
+        match packet {
+            Packet::LoginSuccess()
+            | Packet::ChatMessage(_)
+            | Packet::JoinGame(..)
+            | Packet::TimeUpdate(_)
+            | Packet::SpawnPosition(_)
+            | Packet::Respawn(..)
+            | Packet::UpdateHealth(..)
+            | Packet::PlayerPositionAndLook(_)
+            | Packet::Teleport(..)
+            | Packet::ChangeGameState(..)
+            | Packet::PlayerAbilities(_)
+            | Packet::ChunkData(..)
+            | Packet::ChunkUnload(_)
+            | Packet::MapChunkBulk(..)
+            | Packet::MultiBlockChange(..)
+            | Packet::BlockChange(..)
+            | Packet::Disconnect(_) => true,
+            _ => false
+        }
+    }
+
+    fn translate_play_packet<'a>(&self, packet: ServerboundPlay, mut rbuf: &'a [u8]) -> Result<Option<Cow<'a, [u8]>>> {
+        let mut wbuf = Vec::new();
+        match packet {
+            // Same as in 1.8, Client Status only has action ids that fit a single byte VarInt
+            ServerboundPlay::ChatMessage
+            | ServerboundPlay::Player
+            | ServerboundPlay::PlayerLook
+            | ServerboundPlay::HeldItemChange
+            | ServerboundPlay::CloseWindow
+            | ServerboundPlay::EnchantItem
+            | ServerboundPlay::PlayerAbilities
+            | ServerboundPlay::ClientStatus => return Ok(Some(Cow::Borrowed(rbuf))),
+            ServerboundPlay::KeepAlive => {
+                wbuf.write_var_int(rbuf.read_int()?)?; // Keep Alive ID
+            }
+            ServerboundPlay::PlayerPosition | ServerboundPlay::PlayerPositionAndLook => {
+                wbuf.write_double(rbuf.read_double()?)?; // X
+                wbuf.write_double(rbuf.read_double()?)?; // Feet Y
+                let _head_y = rbuf.read_double()?;
+                wbuf.write_double(rbuf.read_double()?)?; // Z
+                // The rest is the look and On Ground, which didn't change
+                wbuf.write_all(rbuf)?;
+            }
+            ServerboundPlay::PlayerDigging => {
+                wbuf.write_byte(rbuf.read_byte()?)?; // Status
+                translate_position(&mut wbuf, &mut rbuf)?; // Location
+                wbuf.write_byte(rbuf.read_byte()?)?; // Face
+            }
+            ServerboundPlay::PlayerBlockPlacement => {
+                let (x, y, z) = (rbuf.read_int()?, rbuf.read_ubyte()?, rbuf.read_int()?);
+                let face = rbuf.read_byte()?;
+                if face == -1 {
+                    wbuf.write_position(-1, -1, -1)?; // Location
+                } else {
+                    wbuf.write_position(x, y as i32, z)?; // Location
+                }
+                wbuf.write_byte(face)?; // Face
+                translate_slot(&mut wbuf, &mut rbuf)?; // Held Item
+                wbuf.write_all(rbuf)?; // Cursor Position
+            }
+            ServerboundPlay::ClientSettings => {
+                wbuf.write_string(&rbuf.read_string()?)?; // Locale
+                wbuf.write_byte(rbuf.read_byte()?)?; // View Distance
+                wbuf.write_byte(rbuf.read_byte()?)?; // Chat Mode
+                wbuf.write_bool(rbuf.read_bool()?)?; // Chat Colors
+                let _difficulty = rbuf.read_byte()?;
+                // 1.7 only has the cape, it's the first skin part
+                wbuf.write_ubyte(rbuf.read_bool()? as u8)?; // Displayed Skin Parts
+            }
+            // Formats that differ and aren't converted yet
+            _ => return Ok(None)
+        }
+
+        Ok(Some(Cow::Owned(wbuf)))
+    }
+
+    fn write_encryption_request(&self, wbuf: &mut Vec<u8>, public_key: &[u8], verify_token: &[u8]) {
+        // 1.7 prefixes the arrays with a short instead of a VarInt
+        wbuf.write_short(public_key.len() as i16).unwrap(); // Public Key Length
+        wbuf.write_all(public_key).unwrap(); // Public Key
+        wbuf.write_short(verify_token.len() as i16).unwrap(); // Verify Token Length
+        wbuf.write_all(verify_token).unwrap(); // Verify Token
+    }
+
+    fn read_encryption_response(&self, mut rbuf: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let len = rbuf.read_short()?; // Shared Secret Length
+        let shared_secret = read_byte_array(&mut rbuf, len as i32)?; // Shared Secret
+        let len = rbuf.read_short()?; // Verify Token Length
+        let verify_token = read_byte_array(&mut rbuf, len as i32)?; // Verify Token
+        Ok((shared_secret, verify_token))
+    }
+
+    fn write_keep_alive(&self, wbuf: &mut Vec<u8>, id: i32) {
+        wbuf.write_int(id).unwrap(); // Keep Alive ID
+    }
+
+    fn write_join_game(&self, wbuf: &mut Vec<u8>, join: &JoinGame) {
+        wbuf.write_int(join.entity_id).unwrap(); // The player's Entity ID
+        wbuf.write_ubyte(join.gamemode).unwrap(); // Gamemode
+        wbuf.write_byte(join.dimension).unwrap(); // Dimension
+        wbuf.write_ubyte(join.difficulty).unwrap(); // Difficulty
+        wbuf.write_ubyte(join.max_players).unwrap(); // Max players
+        wbuf.write_string(join.level_type).unwrap(); // Level Type
+    }
+
+    fn write_chat_message(&self, wbuf: &mut Vec<u8>, json: &str) {
+        wbuf.write_string(json).unwrap(); // JSON Data
+    }
+
+    fn write_spawn_position(&self, wbuf: &mut Vec<u8>, pos: Coord<i32>) {
+        wbuf.write_int(pos.x).unwrap(); // X
+        wbuf.write_int(pos.y).unwrap(); // Y
+        wbuf.write_int(pos.z).unwrap(); // Z
+    }
+
+    fn write_update_health(&self, wbuf: &mut Vec<u8>, health: f32, food: i32, saturation: f32) {
+        wbuf.write_float(health).unwrap(); // Health
+        wbuf.write_short(food as i16).unwrap(); // Food
+        wbuf.write_float(saturation).unwrap(); // Food Saturation
+    }
+
+    fn write_player_pos_look(&self, wbuf: &mut Vec<u8>, _pos: Coord<f64>, _yaw: f32, _pitch: f32, _flags: TeleportFlags, absolute: (Coord<f64>, f32, f32)) {
+        // Relative teleports came with 1.8
+        let (pos, yaw, pitch) = absolute;
+        wbuf.write_double(pos.x).unwrap(); // X
+        wbuf.write_double(pos.y + EYE_HEIGHT).unwrap(); // Y
+        wbuf.write_double(pos.z).unwrap(); // Z
+        wbuf.write_float(yaw).unwrap(); // Yaw
+        wbuf.write_float(pitch).unwrap(); // Pitch
+        wbuf.write_bool(false).unwrap(); // On Ground
+    }
+
+    fn write_chunk_data(&self, wbuf: &mut Vec<u8>, coord: ChunkCoord, chunk: Option<&Chunk>, context: &SerializeContext) {
+        wbuf.write_int(coord.x).unwrap(); // Chunk X
+        wbuf.write_int(coord.z).unwrap(); // Chunk Z
+        wbuf.write_bool(true).unwrap(); // Ground-Up Continuous
+
+        // The client unloads the chunk when the bit mask is empty, the data is still read
+        let (bit_mask, data) = match chunk {
+            Some(chunk) => (chunk.data.get_primary_bit_mask(), compress(&serialized_column(chunk, context.sky_light))),
+            None => (0, compress(&[]))
+        };
+        wbuf.write_ushort(bit_mask).unwrap(); // Primary Bit Mask
+        wbuf.write_ushort(0).unwrap(); // Add Bit Mask
+        wbuf.write_int(data.len() as i32).unwrap(); // Compressed Size
+        wbuf.write_all(&data).unwrap(); // Compressed Data
+    }
+
+    fn write_multi_block_change(&self, wbuf: &mut Vec<u8>, coord: ChunkCoord, records: &[(Coord<i32>, BlockType, u8)]) {
+        wbuf.write_int(coord.x).unwrap(); // Chunk X
+        wbuf.write_int(coord.z).unwrap(); // Chunk Z
+        wbuf.write_short(records.len() as i16).unwrap(); // Record Count
+        wbuf.write_int(records.len() as i32 * 4).unwrap(); // Data Size
+        for (pos, block_type, meta) in records {
+            let rel_pos = Chunk::abs_to_rel(*pos, coord);
+            wbuf.write_ushort(((rel_pos.x << 12) | (rel_pos.z << 8) | rel_pos.y) as u16).unwrap(); // Position
+            wbuf.write_ushort(((*block_type as u16) << 4) | (meta & 0x0f) as u16).unwrap(); // Block ID and Meta
+        }
+    }
+
+    fn write_block_change(&self, wbuf: &mut Vec<u8>, pos: Coord<i32>, block_type: BlockType, meta: u8) {
+        wbuf.write_int(pos.x).unwrap(); // X
+        wbuf.write_ubyte(pos.y as u8).unwrap(); // Y
+        wbuf.write_int(pos.z).unwrap(); // Z
+        wbuf.write_var_int(block_type as i32).unwrap(); // Block Type
+        wbuf.write_ubyte(meta & 0x0f).unwrap(); // Block Metadata
+    }
+
+    fn write_map_chunk_bulk(&self, wbuf: &mut Vec<u8>, coords: &[ChunkCoord], chunk_map: &ChunkMap, context: &SerializeContext) {
+        let mut data = Vec::new();
+        let mut bit_masks = Vec::with_capacity(coords.len());
+        for coord in coords {
+            let mut bit_mask = 0;
+            chunk_map.do_with_chunk(*coord, |chunk: &Chunk| {
+                bit_mask = chunk.data.get_primary_bit_mask();
+                data.extend_from_slice(&serialized_column(chunk, context.sky_light));
+            });
+            bit_masks.push(bit_mask);
+        }
+
+        let data = compress(&data);
+        wbuf.write_short(coords.len() as i16).unwrap(); // Chunk Column Count
+        wbuf.write_int(data.len() as i32).unwrap(); // Data Length
+        wbuf.write_bool(context.sky_light).unwrap(); // Sky Light Sent
+        wbuf.write_all(&data).unwrap(); // Compressed Data
+        for (coord, bit_mask) in coords.iter().zip(bit_masks) {
+            wbuf.write_int(coord.x).unwrap(); // Chunk X
+            wbuf.write_int(coord.z).unwrap(); // Chunk Z
+            wbuf.write_ushort(bit_mask).unwrap(); // Primary Bit Mask
+            wbuf.write_ushort(0).unwrap(); // Add Bit Mask
+        }
+    }
+}
+
+/// Returns the column in the 1.7 format, shared with the other 1.7 players it's sent to
+fn serialized_column(chunk: &Chunk, sky_light: bool) -> Arc<[u8]> {
+    chunk.serialized_data(PROTOCOL_VERSION, sky_light, |data| write_column(data, chunk, sky_light))
+}
+
+/// Writes a chunk column in the 1.7 format, the arrays are grouped by type instead of by section.
+/// Block types fit a byte, so the column never has the add arrays for the upper bits of the types.
+fn write_column(wbuf: &mut Vec<u8>, chunk: &Chunk, sky_light: bool) {
+    let sections: Vec<_> = chunk.data.sections.iter().flatten().collect();
+    for section in &sections {
+        wbuf.extend_from_slice(&section.block_types);
+    }
+    for section in &sections {
+        wbuf.extend_from_slice(&section.block_metas);
+    }
+    for section in &sections {
+        wbuf.extend_from_slice(&section.block_light);
+    }
+    if sky_light {
+        for section in &sections {
+            wbuf.extend_from_slice(&section.block_sky_light);
+        }
+    }
+
+    wbuf.extend_from_slice(&chunk.biome_map);
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut zen = ZlibEncoder::new(Vec::new(), Compression::default());
+    zen.write_all(data).unwrap();
+    zen.finish().unwrap()
+}
+
+/// Converts the x, y and z ints of a 1.7 location to a 1.8 position
+fn translate_position(wbuf: &mut Vec<u8>, rbuf: &mut &[u8]) -> Result<()> {
+    let (x, y, z) = (rbuf.read_int()?, rbuf.read_ubyte()?, rbuf.read_int()?);
+    wbuf.write_position(x, y as i32, z)?;
+    Ok(())
+}
+
+/// Converts a 1.7 slot to a 1.8 slot, the gzipped NBT of 1.7 is left out
+fn translate_slot(wbuf: &mut Vec<u8>, rbuf: &mut &[u8]) -> Result<()> {
+    let id = rbuf.read_short()?;
+    wbuf.write_short(id)?; // Block ID
+    if id == -1 {
+        return Ok(());
+    }
+
+    wbuf.write_byte(rbuf.read_byte()?)?; // Item Count
+    wbuf.write_short(rbuf.read_short()?)?; // Item Damage
+    let nbt_length = rbuf.read_short()?;
+    if nbt_length > 0 {
+        let mut nbt = vec![0u8; nbt_length as usize];
+        rbuf.read_exact(&mut nbt)?;
+    }
+    wbuf.write_ubyte(0)?; // NBT: TAG_End, no NBT
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+    use crate::protocol::v47;
+    use crate::storage::chunk::{AREA, SECTION_BLOCK_COUNT};
+
+    #[test]
+    fn chunk_format() {
+        let chunk_map = ChunkMap::new();
+        let coord = ChunkCoord { x: 0, z: 0 };
+        chunk_map.touch_chunk(coord);
+        chunk_map.do_with_chunk_mut(coord, |chunk| chunk.set_block(Coord::new(1, 40, 2), BlockType::Stone));
+        // A 1.8 player loading the chunk first doesn't change what 1.7 players get
+        chunk_map.do_with_chunk(coord, |chunk| { v47::serialized_data(chunk, &SerializeContext::default()); });
+
+        let mut wbuf = Vec::new();
+        chunk_map.do_with_chunk(coord, |chunk| V5.write_chunk_data(&mut wbuf, coord, Some(chunk), &SerializeContext::default()));
+        let mut rbuf = wbuf.as_slice();
+        assert_eq!((rbuf.read_int().unwrap(), rbuf.read_int().unwrap()), (0, 0));
+        assert!(rbuf.read_bool().unwrap());
+        let bit_mask = rbuf.read_ushort().unwrap();
+        assert_eq!(rbuf.read_ushort().unwrap(), 0);
+        assert_eq!(rbuf.read_int().unwrap() as usize, rbuf.len());
+
+        let mut data = Vec::new();
+        ZlibDecoder::new(rbuf).read_to_end(&mut data).unwrap();
+        let sections = bit_mask.count_ones() as usize;
+        // Types, metas, block light and sky light of every section, then the biomes
+        assert_eq!(data.len(), sections * SECTION_BLOCK_COUNT * 5 / 2 + AREA as usize);
+        // The stone is in the third section, which comes after all the sections below it
+        let section = (bit_mask & 0b11).count_ones() as usize;
+        assert_eq!(bit_mask & 0b100, 0b100);
+        assert_eq!(data[section * SECTION_BLOCK_COUNT + (1 + 2 * 16 + 8 * 256)], BlockType::Stone as u8);
+    }
+
+    #[test]
+    fn relative_teleports_are_absolute() {
+        let packet = Packet::Teleport(Coord::new(0.0, 2.0, 0.0), 90.0, 0.0, TeleportFlags::all(), (Coord::new(10.5, 66.0, -3.5), 180.0, 20.0));
+        assert!(V5.supports(&packet));
+
+        let mut wbuf = Vec::new();
+        V5.write_player_pos_look(&mut wbuf, Coord::new(0.0, 2.0, 0.0), 90.0, 0.0, TeleportFlags::all(), (Coord::new(10.5, 66.0, -3.5), 180.0, 20.0));
+        let mut data = wbuf.as_slice();
+        assert_eq!(data.read_double().unwrap(), 10.5);
+        assert_eq!(data.read_double().unwrap(), 66.0 + EYE_HEIGHT);
+        assert_eq!(data.read_double().unwrap(), -3.5);
+        assert_eq!(data.read_float().unwrap(), 180.0);
+        assert_eq!(data.read_float().unwrap(), 20.0);
+        assert!(!data.read_bool().unwrap());
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn positions_are_translated() {
+        let mut rbuf = Vec::new();
+        for v in [1.5, 64.0, 65.62, -3.5] {
+            rbuf.write_double(v).unwrap();
+        }
+        rbuf.write_bool(true).unwrap();
+
+        let data = V5.translate_play_packet(ServerboundPlay::PlayerPosition, &rbuf).unwrap().unwrap();
+        let mut data = &data[..];
+        for v in [1.5, 64.0, -3.5] {
+            assert_eq!(data.read_double().unwrap(), v);
+        }
+        assert!(data.read_bool().unwrap());
+        assert!(data.is_empty());
+
+        // Packets that can't be converted yet are ignored
+        assert!(V5.translate_play_packet(ServerboundPlay::UseEntity, &[]).unwrap().is_none());
+    }
+}
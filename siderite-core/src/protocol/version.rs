@@ -0,0 +1,83 @@
+//! The packets that differ between the protocol versions the server supports.
+//! The packet ids and everything else are shared, handlers read serverbound packets in the format of protocol 47.
+
+use std::borrow::Cow;
+
+use crate::blocks::BlockType;
+use crate::coord::{ChunkCoord, Coord};
+use crate::storage::chunk::{Chunk, SerializeContext};
+use crate::storage::chunk::chunk_map::ChunkMap;
+
+use super::{TeleportFlags, v5, v47};
+use super::error::Result;
+use super::ids::ServerboundPlay;
+use super::packets::Packet;
+
+/// The fields of Join Game
+#[derive(Clone, Debug, PartialEq)]
+pub struct JoinGame<'a> {
+    pub entity_id: i32,
+    pub gamemode: u8,
+    pub dimension: i8,
+    pub difficulty: u8,
+    pub max_players: u8,
+    pub level_type: &'a str
+}
+
+/// Writes the packets whose format differs between versions, the methods write the fields following the packet id
+pub trait ProtocolVersion: Sync {
+    /// Protocol number the client sends in the handshake
+    fn protocol(&self) -> i32;
+
+    /// Name of the Minecraft version, shown in the server list
+    fn name(&self) -> &'static str;
+
+    /// False for versions from before packets could be compressed
+    fn supports_compression(&self) -> bool;
+
+    /// Returns false for packets that can't be sent to clients of this version, they're left out
+    fn supports(&self, packet: &Packet) -> bool;
+
+    /// Returns the data of the serverbound play packet in the format of protocol 47,
+    /// None for packets the server ignores from clients of this version
+    fn translate_play_packet<'a>(&self, packet: ServerboundPlay, rbuf: &'a [u8]) -> Result<Option<Cow<'a, [u8]>>>;
+
+    /// Writes the public key and verify token of Encryption Request, they follow the server id
+    fn write_encryption_request(&self, wbuf: &mut Vec<u8>, public_key: &[u8], verify_token: &[u8]);
+
+    /// Reads Encryption Response, returns the encrypted shared secret and verify token
+    fn read_encryption_response(&self, rbuf: &[u8]) -> Result<(Vec<u8>, Vec<u8>)>;
+
+    fn write_keep_alive(&self, wbuf: &mut Vec<u8>, id: i32);
+
+    fn write_join_game(&self, wbuf: &mut Vec<u8>, join: &JoinGame);
+
+    fn write_chat_message(&self, wbuf: &mut Vec<u8>, json: &str);
+
+    fn write_spawn_position(&self, wbuf: &mut Vec<u8>, pos: Coord<i32>);
+
+    fn write_update_health(&self, wbuf: &mut Vec<u8>, health: f32, food: i32, saturation: f32);
+
+    /// `pos` is the position of the feet of the player, the fields with their flag set are relative.
+    /// `absolute` is the position, yaw and pitch the player ends up with.
+    fn write_player_pos_look(&self, wbuf: &mut Vec<u8>, pos: Coord<f64>, yaw: f32, pitch: f32, flags: TeleportFlags, absolute: (Coord<f64>, f32, f32));
+
+    /// Writes a whole chunk column, or makes the client unload the column when `chunk` is None
+    fn write_chunk_data(&self, wbuf: &mut Vec<u8>, coord: ChunkCoord, chunk: Option<&Chunk>, context: &SerializeContext);
+
+    fn write_multi_block_change(&self, wbuf: &mut Vec<u8>, coord: ChunkCoord, records: &[(Coord<i32>, BlockType, u8)]);
+
+    fn write_block_change(&self, wbuf: &mut Vec<u8>, pos: Coord<i32>, block_type: BlockType, meta: u8);
+
+    /// The chunks have to be loaded
+    fn write_map_chunk_bulk(&self, wbuf: &mut Vec<u8>, coords: &[ChunkCoord], chunk_map: &ChunkMap, context: &SerializeContext);
+}
+
+/// Returns the version for the protocol number of the handshake, None if the server doesn't support it
+pub fn for_protocol(protocol: i32) -> Option<&'static dyn ProtocolVersion> {
+    match protocol {
+        v47::PROTOCOL_VERSION => Some(&v47::V47),
+        v5::PROTOCOL_VERSION => Some(&v5::V5),
+        _ => None
+    }
+}
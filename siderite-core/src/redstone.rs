@@ -0,0 +1,537 @@
+//! Redstone power: levers, buttons and torches power wire and the blocks around them,
+//! powered doors open and powered TNT gets ignited
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::blocks::{BlockFace, BlockType};
+use crate::coord::Coord;
+use crate::storage::world::World;
+
+/// Power of a source, wire loses one level for every block it runs
+pub const MAX_POWER: u8 = 15;
+
+/// Meta bit set for pulled levers and pressed buttons
+pub const POWERED: u8 = 0x8;
+
+/// Ticks a stone button stays pressed
+pub const STONE_BUTTON_DELAY: i64 = 20;
+
+/// Meta bit set on the lower half of open doors
+const DOOR_OPEN: u8 = 0x4;
+
+/// Meta bit set on the upper half of doors
+const DOOR_UPPER: u8 = 0x8;
+
+/// Meta bit set on the upper half of powered doors
+const DOOR_POWERED: u8 = 0x2;
+
+/// Torches that toggle more often than this during one update burn out and stay as they are,
+/// this stops clocks built from torches from updating forever
+const TORCH_MAX_TOGGLES: usize = 8;
+
+const HORIZONTAL_FACES: [BlockFace; 4] = [BlockFace::ZM, BlockFace::ZP, BlockFace::XM, BlockFace::XP];
+
+const FACES: [BlockFace; 6] = [BlockFace::YM, BlockFace::YP, BlockFace::ZM, BlockFace::ZP, BlockFace::XM, BlockFace::XP];
+
+/// Blocks changed by a redstone update and the TNT that got powered
+#[derive(Debug, Default, PartialEq)]
+pub struct RedstoneChanges {
+    pub blocks: Vec<(Coord<i32>, BlockType, u8)>,
+    pub ignited: Vec<Coord<i32>>
+}
+
+/// Returns true for blocks that give off or react to power
+pub fn is_redstone(block_type: BlockType) -> bool {
+    matches!(block_type, BlockType::RedstoneWire | BlockType::Lever | BlockType::StoneButton
+        | BlockType::RedstoneTorchOff | BlockType::RedstoneTorchOn
+        | BlockType::WoodenDoor | BlockType::IronDoor | BlockType::Tnt)
+}
+
+/// Returns the meta of the lever or button after a player used it, None if it can't be used
+pub fn use_component(block_type: BlockType, meta: u8) -> Option<u8> {
+    match block_type {
+        BlockType::Lever => Some(meta ^ POWERED),
+        BlockType::StoneButton if meta & POWERED == 0 => Some(meta | POWERED),
+        _ => None
+    }
+}
+
+/// Handles a scheduled tick of the block, releases pressed buttons
+pub fn scheduled_tick(world: &World, pos: Coord<i32>) -> Option<(Coord<i32>, BlockType, u8)> {
+    match world.get_block(pos)? {
+        (BlockType::StoneButton, meta) if meta & POWERED != 0 => Some((pos, BlockType::StoneButton, meta & !POWERED)),
+        _ => None
+    }
+}
+
+/// Returns the face of the lever, button or torch that touches the block it's attached to
+pub fn attached_face(block_type: BlockType, meta: u8) -> Option<BlockFace> {
+    match block_type {
+        BlockType::RedstoneTorchOff | BlockType::RedstoneTorchOn => Some(match meta {
+            1 => BlockFace::XM,
+            2 => BlockFace::XP,
+            3 => BlockFace::ZM,
+            4 => BlockFace::ZP,
+            _ => BlockFace::YM
+        }),
+        BlockType::StoneButton => Some(match meta & !POWERED {
+            0 => BlockFace::YP,
+            1 => BlockFace::XM,
+            2 => BlockFace::XP,
+            3 => BlockFace::ZM,
+            4 => BlockFace::ZP,
+            _ => BlockFace::YM
+        }),
+        BlockType::Lever => Some(match meta & !POWERED {
+            0 | 7 => BlockFace::YP,
+            1 => BlockFace::XM,
+            2 => BlockFace::XP,
+            3 => BlockFace::ZM,
+            4 => BlockFace::ZP,
+            _ => BlockFace::YM
+        }),
+        _ => None
+    }
+}
+
+fn attached_pos(pos: Coord<i32>, block_type: BlockType, meta: u8) -> Option<Coord<i32>> {
+    attached_face(block_type, meta).map(|face| face.adjacent(pos))
+}
+
+/// Updates the redstone around the changed blocks and applies the changes to the world.
+/// The same world always gets the same changes.
+pub fn update(world: &World, changed: &[Coord<i32>]) -> RedstoneChanges {
+    let mut changes = RedstoneChanges::default();
+    let mut toggles: HashMap<Coord<i32>, usize> = HashMap::new();
+    let mut queued: HashSet<Coord<i32>> = changed.iter().copied().collect();
+    let mut queue: VecDeque<Coord<i32>> = changed.iter().copied().collect();
+    while let Some(pos) = queue.pop_front() {
+        queued.remove(&pos);
+        let nearby = nearby(pos);
+
+        let wire: Vec<_> = nearby.iter().copied().filter(|v| world.get_block_state(*v).0 == BlockType::RedstoneWire).collect();
+        let mut updated: Vec<_> = wire_power(world, &wire).into_iter()
+            .map(|(pos, power)| (pos, BlockType::RedstoneWire, power))
+            .collect();
+        for &(pos, block_type, meta) in &updated {
+            world.set_block(pos, block_type, meta);
+        }
+
+        for pos in nearby {
+            let (block_type, meta) = world.get_block_state(pos);
+            match block_type {
+                BlockType::RedstoneTorchOff | BlockType::RedstoneTorchOn => {
+                    let lit = is_torch_lit(world, pos, block_type, meta);
+                    let toggled = toggles.entry(pos).or_insert(0);
+                    if lit != (block_type == BlockType::RedstoneTorchOn) && *toggled < TORCH_MAX_TOGGLES {
+                        *toggled += 1;
+                        let block_type = if lit { BlockType::RedstoneTorchOn } else { BlockType::RedstoneTorchOff };
+                        world.set_block(pos, block_type, meta);
+                        updated.push((pos, block_type, meta));
+                    }
+                }
+                BlockType::WoodenDoor | BlockType::IronDoor => updated.extend(update_door(world, pos, block_type, meta)),
+                BlockType::Tnt => {
+                    if is_receiving_power(world, pos) && !changes.ignited.contains(&pos) {
+                        changes.ignited.push(pos);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for &(pos, _, _) in &updated {
+            if queued.insert(pos) {
+                queue.push_back(pos);
+            }
+        }
+
+        changes.blocks.extend(updated);
+    }
+
+    // Only the last change of every block matters
+    let mut seen = HashSet::new();
+    changes.blocks = changes.blocks.into_iter().rev().filter(|(pos, _, _)| seen.insert(*pos)).collect();
+    changes.blocks.reverse();
+    changes
+}
+
+/// Returns the blocks within two steps of `pos`, a change at `pos` can affect these directly
+fn nearby(pos: Coord<i32>) -> Vec<Coord<i32>> {
+    let mut blocks = Vec::new();
+    for dy in -2i32..=2 {
+        for dz in -2i32..=2 {
+            for dx in -2i32..=2 {
+                if dx.abs() + dy.abs() + dz.abs() <= 2 {
+                    blocks.push(Coord::new(pos.x + dx, pos.y + dy, pos.z + dz));
+                }
+            }
+        }
+    }
+
+    blocks
+}
+
+/// Returns true if the lever, button or torch at `source` powers the block at `pos`
+fn powers_directly(world: &World, source: Coord<i32>, pos: Coord<i32>) -> bool {
+    let (block_type, meta) = world.get_block_state(source);
+    match block_type {
+        BlockType::Lever | BlockType::StoneButton => meta & POWERED != 0,
+        // Torches don't power the block they're attached to
+        BlockType::RedstoneTorchOn => attached_pos(source, block_type, meta) != Some(pos),
+        _ => false
+    }
+}
+
+/// Returns true if the opaque block at `pos` is powered.
+/// Power from wire only counts if `through_wire` is set, wire can't power itself through a block.
+fn is_block_powered(world: &World, pos: Coord<i32>, through_wire: bool) -> bool {
+    if !world.get_block_state(pos).0.is_opaque() {
+        return false;
+    }
+
+    FACES.iter().any(|&face| {
+        let neighbour = face.adjacent(pos);
+        let (block_type, meta) = world.get_block_state(neighbour);
+        match block_type {
+            BlockType::Lever | BlockType::StoneButton => meta & POWERED != 0 && attached_pos(neighbour, block_type, meta) == Some(pos),
+            // Torches power the block above them
+            BlockType::RedstoneTorchOn => face == BlockFace::YM,
+            BlockType::RedstoneWire => through_wire && meta > 0 && wire_powers(world, neighbour, face.opposite()),
+            _ => false
+        }
+    })
+}
+
+/// Returns true if any block around `pos` powers it, used for doors and TNT
+fn is_receiving_power(world: &World, pos: Coord<i32>) -> bool {
+    FACES.iter().any(|&face| {
+        let neighbour = face.adjacent(pos);
+        let (block_type, meta) = world.get_block_state(neighbour);
+        match block_type {
+            BlockType::RedstoneWire => meta > 0 && wire_powers(world, neighbour, face.opposite()),
+            _ => powers_directly(world, neighbour, pos) || is_block_powered(world, neighbour, true)
+        }
+    })
+}
+
+/// A torch is lit unless the block it's attached to is powered
+fn is_torch_lit(world: &World, pos: Coord<i32>, block_type: BlockType, meta: u8) -> bool {
+    attached_pos(pos, block_type, meta).map_or(true, |attached| !is_block_powered(world, attached, true))
+}
+
+/// Opens powered doors and closes them again when they lose power
+fn update_door(world: &World, pos: Coord<i32>, block_type: BlockType, meta: u8) -> Vec<(Coord<i32>, BlockType, u8)> {
+    let (lower, upper) = if meta & DOOR_UPPER == 0 {
+        (pos, BlockFace::YP.adjacent(pos))
+    }
+    else {
+        (BlockFace::YM.adjacent(pos), pos)
+    };
+
+    let (lower_meta, upper_meta) = match (world.get_block_state(lower), world.get_block_state(upper)) {
+        ((lower_type, lower_meta), (upper_type, upper_meta)) if lower_type == block_type && upper_type == block_type
+            && lower_meta & DOOR_UPPER == 0 && upper_meta & DOOR_UPPER != 0 => (lower_meta, upper_meta),
+        _ => return Vec::new()
+    };
+
+    let powered = is_receiving_power(world, lower) || is_receiving_power(world, upper);
+    if powered == (upper_meta & DOOR_POWERED != 0) {
+        return Vec::new();
+    }
+
+    let (lower_meta, upper_meta) = if powered {
+        (lower_meta | DOOR_OPEN, upper_meta | DOOR_POWERED)
+    }
+    else {
+        (lower_meta & !DOOR_OPEN, upper_meta & !DOOR_POWERED)
+    };
+
+    world.set_block(lower, block_type, lower_meta);
+    world.set_block(upper, block_type, upper_meta);
+    vec![(lower, block_type, lower_meta), (upper, block_type, upper_meta)]
+}
+
+/// Returns the wire connected to the wire at `pos` and the face it's on,
+/// wire connects on the same level and one block up or down
+fn connected_wire(world: &World, pos: Coord<i32>) -> Vec<(BlockFace, Coord<i32>)> {
+    let mut wire = Vec::new();
+    let covered = world.get_block_state(BlockFace::YP.adjacent(pos)).0.is_opaque();
+    for face in HORIZONTAL_FACES {
+        let side = face.adjacent(pos);
+        let side_opaque = world.get_block_state(side).0.is_opaque();
+        for (candidate, reachable) in [(side, true), (BlockFace::YP.adjacent(side), !covered), (BlockFace::YM.adjacent(side), !side_opaque)] {
+            if reachable && world.get_block_state(candidate).0 == BlockType::RedstoneWire {
+                wire.push((face, candidate));
+            }
+        }
+    }
+
+    wire
+}
+
+/// Returns true if the wire at `pos` powers the block next to the face
+fn wire_powers(world: &World, pos: Coord<i32>, face: BlockFace) -> bool {
+    match face {
+        BlockFace::YM => true,
+        BlockFace::YP => false,
+        _ => {
+            let wire = connected_wire(world, pos);
+            let connected: Vec<_> = HORIZONTAL_FACES.into_iter().filter(|&face| {
+                let side = world.get_block_state(face.adjacent(pos)).0;
+                matches!(side, BlockType::Lever | BlockType::StoneButton | BlockType::RedstoneTorchOff | BlockType::RedstoneTorchOn)
+                    || wire.iter().any(|(v, _)| *v == face)
+            }).collect();
+
+            // Wire that doesn't lead anywhere powers all sides, a dead end points straight ahead
+            match connected[..] {
+                [] => true,
+                [single] => face == single || face == single.opposite(),
+                _ => connected.contains(&face)
+            }
+        }
+    }
+}
+
+/// Returns true if the wire at `pos` gets full power from something else than wire
+fn is_wire_source(world: &World, pos: Coord<i32>) -> bool {
+    FACES.iter().any(|&face| {
+        let neighbour = face.adjacent(pos);
+        powers_directly(world, neighbour, pos) || is_block_powered(world, neighbour, false)
+    })
+}
+
+/// Recomputes the power of the wire networks `seeds` are part of.
+/// Returns the wire whose power changed together with its new power.
+fn wire_power(world: &World, seeds: &[Coord<i32>]) -> Vec<(Coord<i32>, u8)> {
+    let mut network = Vec::new();
+    let mut seen: HashSet<Coord<i32>> = HashSet::new();
+    let mut queue = VecDeque::new();
+    for &seed in seeds {
+        if seen.insert(seed) {
+            queue.push_back(seed);
+        }
+    }
+
+    while let Some(pos) = queue.pop_front() {
+        network.push(pos);
+        for (_, next) in connected_wire(world, pos) {
+            if seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    // Spread the power from the strongest wire down, so every wire ends up with the highest power that reaches it
+    let mut power: HashMap<Coord<i32>, u8> = HashMap::new();
+    let mut levels: Vec<Vec<Coord<i32>>> = vec![Vec::new(); MAX_POWER as usize + 1];
+    for &pos in &network {
+        if is_wire_source(world, pos) {
+            power.insert(pos, MAX_POWER);
+            levels[MAX_POWER as usize].push(pos);
+        }
+    }
+
+    for level in (2..=MAX_POWER).rev() {
+        for pos in std::mem::take(&mut levels[level as usize]) {
+            for (_, next) in connected_wire(world, pos) {
+                if power.get(&next).map_or(true, |&v| v < level - 1) {
+                    power.insert(next, level - 1);
+                    levels[level as usize - 1].push(next);
+                }
+            }
+        }
+    }
+
+    network.into_iter()
+        .map(|pos| (pos, power.get(&pos).copied().unwrap_or(0)))
+        .filter(|&(pos, power)| world.get_block_state(pos).1 != power)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::world::Dimension;
+    use crate::test_utils::test_world;
+
+    fn power(world: &World, x: i32, z: i32) -> u8 {
+        let (block_type, meta) = world.get_block_state(Coord::new(x, 16, z));
+        assert_eq!(block_type, BlockType::RedstoneWire);
+        meta
+    }
+
+    /// Places a lever on the ground at the position and updates the blocks around it
+    fn set_lever(world: &World, pos: Coord<i32>, on: bool) -> RedstoneChanges {
+        world.set_block(pos, BlockType::Lever, if on { 5 | POWERED } else { 5 });
+        update(world, &[pos])
+    }
+
+    #[test]
+    fn straight_line() {
+        let world = test_world(Dimension::Overworld);
+        for x in 1..=16 {
+            world.set_block(Coord::new(x, 16, 0), BlockType::RedstoneWire, 0);
+        }
+
+        let lever = Coord::new(0, 16, 0);
+        let changes = set_lever(&world, lever, true);
+        assert_eq!(changes.blocks.len(), 15);
+        for x in 1..=16 {
+            assert_eq!(power(&world, x, 0), 16 - x as u8);
+        }
+
+        // Nothing changes when nothing changed
+        assert_eq!(update(&world, &[lever]), RedstoneChanges::default());
+
+        set_lever(&world, lever, false);
+        for x in 1..=16 {
+            assert_eq!(power(&world, x, 0), 0);
+        }
+    }
+
+    #[test]
+    fn t_junction() {
+        let world = test_world(Dimension::Overworld);
+        for x in 1..=5 {
+            world.set_block(Coord::new(x, 16, 0), BlockType::RedstoneWire, 0);
+        }
+
+        for z in 1..=4 {
+            world.set_block(Coord::new(3, 16, z), BlockType::RedstoneWire, 0);
+        }
+
+        set_lever(&world, Coord::new(0, 16, 0), true);
+        assert_eq!((1..=5).map(|x| power(&world, x, 0)).collect::<Vec<_>>(), [15, 14, 13, 12, 11]);
+        assert_eq!((1..=4).map(|z| power(&world, 3, z)).collect::<Vec<_>>(), [12, 11, 10, 9]);
+
+        // Powering the end of the branch as well
+        set_lever(&world, Coord::new(3, 16, 5), true);
+        assert_eq!((1..=5).map(|x| power(&world, x, 0)).collect::<Vec<_>>(), [15, 14, 13, 12, 11]);
+        assert_eq!((1..=4).map(|z| power(&world, 3, z)).collect::<Vec<_>>(), [12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn wire_loop() {
+        let world = test_world(Dimension::Overworld);
+        let ring = [(1, 0), (2, 0), (3, 0), (3, 1), (3, 2), (2, 2), (1, 2), (1, 1)];
+        for (x, z) in ring {
+            world.set_block(Coord::new(x, 16, z), BlockType::RedstoneWire, 0);
+        }
+
+        let lever = Coord::new(0, 16, 0);
+        set_lever(&world, lever, true);
+        assert_eq!(ring.iter().map(|&(x, z)| power(&world, x, z)).collect::<Vec<_>>(), [15, 14, 13, 12, 11, 12, 13, 14]);
+        assert_eq!(update(&world, &[Coord::new(3, 16, 2)]), RedstoneChanges::default());
+
+        // The loop doesn't keep itself powered
+        set_lever(&world, lever, false);
+        for (x, z) in ring {
+            assert_eq!(power(&world, x, z), 0);
+        }
+    }
+
+    #[test]
+    fn wire_steps() {
+        let world = test_world(Dimension::Overworld);
+        world.set_block(Coord::new(2, 16, 0), BlockType::Dirt, 0);
+        for pos in [Coord::new(1, 16, 0), Coord::new(2, 17, 0), Coord::new(3, 16, 0)] {
+            world.set_block(pos, BlockType::RedstoneWire, 0);
+        }
+
+        set_lever(&world, Coord::new(0, 16, 0), true);
+        assert_eq!(world.get_block_state(Coord::new(2, 17, 0)), (BlockType::RedstoneWire, 14));
+        assert_eq!(power(&world, 3, 0), 13);
+
+        // A block on top of the lower wire cuts the connection up
+        world.set_block(Coord::new(1, 17, 0), BlockType::Dirt, 0);
+        update(&world, &[Coord::new(1, 17, 0)]);
+        assert_eq!(world.get_block_state(Coord::new(2, 17, 0)), (BlockType::RedstoneWire, 0));
+        assert_eq!(power(&world, 3, 0), 0);
+    }
+
+    #[test]
+    fn torch_inverts() {
+        let world = test_world(Dimension::Overworld);
+        // A torch standing on top of a block with wire next to it
+        let (torch, wire) = (Coord::new(0, 17, 0), Coord::new(1, 17, 0));
+        world.set_block(Coord::new(0, 16, 0), BlockType::Dirt, 0);
+        world.set_block(torch, BlockType::RedstoneTorchOff, 5);
+        world.set_block(Coord::new(1, 16, 0), BlockType::Dirt, 0);
+        world.set_block(wire, BlockType::RedstoneWire, 0);
+
+        update(&world, &[torch]);
+        assert_eq!(world.get_block_state(torch), (BlockType::RedstoneTorchOn, 5));
+        assert_eq!(world.get_block_state(wire), (BlockType::RedstoneWire, 15));
+
+        // A lever on the side of the block turns the torch off
+        let lever = Coord::new(0, 16, -1);
+        world.set_block(lever, BlockType::Lever, 4 | POWERED);
+        let changes = update(&world, &[lever]);
+        assert_eq!(changes.blocks, [(torch, BlockType::RedstoneTorchOff, 5), (wire, BlockType::RedstoneWire, 0)]);
+    }
+
+    #[test]
+    fn torch_clock_burns_out() {
+        let world = test_world(Dimension::Overworld);
+        // The torch powers the block above it, the wire next to that block powers the block the torch is attached to
+        let (torch, wire) = (Coord::new(1, 16, 0), Coord::new(0, 17, 0));
+        world.set_block(Coord::new(0, 16, 0), BlockType::Stone, 0);
+        world.set_block(torch, BlockType::RedstoneTorchOn, 1);
+        world.set_block(Coord::new(1, 17, 0), BlockType::Stone, 0);
+        world.set_block(wire, BlockType::RedstoneWire, 0);
+
+        let changes = update(&world, &[torch]);
+        assert_eq!(changes.blocks, [(torch, BlockType::RedstoneTorchOn, 1), (wire, BlockType::RedstoneWire, 15)]);
+        assert_eq!(world.get_block_state(torch), (BlockType::RedstoneTorchOn, 1));
+        assert_eq!(world.get_block_state(wire), (BlockType::RedstoneWire, 15));
+    }
+
+    #[test]
+    fn doors_open() {
+        let world = test_world(Dimension::Overworld);
+        let (lower, upper) = (Coord::new(3, 16, 3), Coord::new(3, 17, 3));
+        world.set_block(lower, BlockType::WoodenDoor, 1);
+        world.set_block(upper, BlockType::WoodenDoor, DOOR_UPPER);
+
+        let lever = Coord::new(4, 17, 3);
+        world.set_block(Coord::new(4, 16, 3), BlockType::Dirt, 0);
+        world.set_block(lever, BlockType::Lever, 5 | POWERED);
+        update(&world, &[lever]);
+        assert_eq!(world.get_block_state(lower), (BlockType::WoodenDoor, 1 | DOOR_OPEN));
+        assert_eq!(world.get_block_state(upper), (BlockType::WoodenDoor, DOOR_UPPER | DOOR_POWERED));
+
+        world.set_block(lever, BlockType::Lever, 5);
+        update(&world, &[lever]);
+        assert_eq!(world.get_block_state(lower), (BlockType::WoodenDoor, 1));
+        assert_eq!(world.get_block_state(upper), (BlockType::WoodenDoor, DOOR_UPPER));
+    }
+
+    #[test]
+    fn tnt_ignites() {
+        let world = test_world(Dimension::Overworld);
+        let tnt = Coord::new(0, 16, 3);
+        world.set_block(tnt, BlockType::Tnt, 0);
+        for x in 1..=3 {
+            world.set_block(Coord::new(x, 16, 3), BlockType::RedstoneWire, 0);
+        }
+
+        assert!(set_lever(&world, Coord::new(4, 16, 3), false).ignited.is_empty());
+        assert_eq!(set_lever(&world, Coord::new(4, 16, 3), true).ignited, [tnt]);
+    }
+
+    #[test]
+    fn buttons() {
+        let world = test_world(Dimension::Overworld);
+        let button = Coord::new(0, 16, 0);
+        assert_eq!(use_component(BlockType::StoneButton, 5), Some(5 | POWERED));
+        assert_eq!(use_component(BlockType::StoneButton, 5 | POWERED), None);
+        assert_eq!(use_component(BlockType::Lever, 5 | POWERED), Some(5));
+        assert_eq!(use_component(BlockType::Dirt, 0), None);
+
+        world.set_block(button, BlockType::StoneButton, 5);
+        assert_eq!(scheduled_tick(&world, button), None);
+        world.set_block(button, BlockType::StoneButton, 5 | POWERED);
+        assert_eq!(scheduled_tick(&world, button), Some((button, BlockType::StoneButton, 5)));
+    }
+}
@@ -1,23 +1,59 @@
-use std::collections::HashMap;
-use std::net::{SocketAddr, TcpListener};
-use std::sync::{Arc, RwLock};
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use log::*;
 use openssl::pkey::Private;
 use openssl::rsa::Rsa;
+use rand::{thread_rng, Rng};
 use serde_json as json;
 use uuid::Uuid;
 
+use crate::{TICK_DURATION, TPS};
 use crate::auth::*;
+use crate::block_ticks;
+use crate::blocks::BlockType;
 use crate::client::Client;
-use crate::coord::Coord;
-use crate::entities::player::{GameMode, Player};
+use crate::coord::{ChunkCoord, Coord};
+use crate::crafting::RecipeRegistry;
+use crate::entities::{Entity, TNT_FUSE};
+use crate::entities::effects::{Effect, EffectType};
+use crate::entities::player::{DamageCause, GameMode, Player};
+use crate::entities::tracker::{self, PLAYER_TRACKING_RANGE, Tracked};
+use crate::events::{EventBus, PlayerJoin, PlayerQuit};
+use crate::explosion;
+use crate::furnace::{self, FurnaceUpdate};
+use crate::items::ItemStack;
+use crate::kick;
+use crate::map::{self, MapState};
 use crate::protocol::Protocol;
 use crate::protocol::packets::{Packet, PlayerListAction};
 use crate::protocol::thread::ProtocolThread;
+use crate::redstone;
+use crate::skins::{self, SkinCache, SkinFetcher};
+use crate::stats::{self, Statistics};
+use crate::storage::chunk::generator::GeneratorType;
+use crate::storage::versioning::UnknownFields;
 use crate::storage::world::*;
+use crate::teams::{Team, TeamAction, TeamManager};
+use crate::throttle::ConnectionThrottle;
+use crate::tile_entities::TileEntity;
+
+/// Players further away from an explosion than this don't get notified about it
+const EXPLOSION_RANGE: f64 = 64.0;
+
+/// Time between progress messages while pregenerating chunks
+const PREGEN_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of ticks between the Time Update packets that keep the clients in sync
+const TIME_UPDATE_INTERVAL: i64 = TPS as i64;
 
 static ENTITY_ID_COUNTER: AtomicU32 = AtomicU32::new(0);
 
@@ -25,6 +61,45 @@ pub fn get_next_entity_id() -> u32 {
     ENTITY_ID_COUNTER.fetch_add(1, Ordering::Relaxed)
 }
 
+/// Binds the listeners for the address, the last one is the listener for the address itself.
+/// With `dual_stack` an unspecified IPv6 address gets IPv4 clients as well: platforms where IPV6_V6ONLY is off
+/// by default (Linux) give them to the IPv6 socket, the others (Windows, the BSDs) get a second listener for IPv4.
+/// Without it IPV6_V6ONLY is left as the platform has it
+fn bind(address: SocketAddr, dual_stack: bool) -> io::Result<Vec<TcpListener>> {
+    let listener = TcpListener::bind(address)?;
+    let mut listeners = Vec::with_capacity(2);
+    if dual_stack && address.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        // The port is taken for IPv4 too if the IPv6 socket is dual-stack, but it could also be another program using it
+        let port = listener.local_addr()?.port();
+        match TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)) {
+            Ok(v) => listeners.push(v),
+            Err(e) if e.kind() == ErrorKind::AddrInUse => match accepts_ipv4(&listener, port) {
+                Ok(true) => debug!("The IPv6 listener accepts IPv4 clients"),
+                Ok(false) => warn!("IPv4 port {} is used by another program, IPv4 clients can't connect", port),
+                Err(e) => warn!("Couldn't check if IPv4 clients can connect: {}", e)
+            },
+            Err(e) => return Err(e)
+        }
+    }
+
+    listeners.push(listener);
+    Ok(listeners)
+}
+
+/// Returns true if a connection to the IPv4 loopback address on the port ends up at the listener
+fn accepts_ipv4(listener: &TcpListener, port: u16) -> io::Result<bool> {
+    let _probe = TcpStream::connect_timeout(&SocketAddr::from((Ipv4Addr::LOCALHOST, port)), Duration::from_secs(1))?;
+    // The kernel has queued the connection by the time connect returns
+    listener.set_nonblocking(true)?;
+    let accepted = match listener.accept() {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e)
+    };
+    listener.set_nonblocking(false)?;
+    accepted
+}
+
 pub struct ServerConfig {
     pub view_distance: u8,
     pub default_gamemode: GameMode,
@@ -33,8 +108,27 @@ pub struct ServerConfig {
     pub difficulty: Difficulty,
     pub compression_threshold: Option<i32>,
     pub level_type: String,
+    /// Seed of the terrain of new worlds, a random one if None
+    pub level_seed: Option<i64>,
     pub max_players: i32,
-    pub encryption: bool
+    pub encryption: bool,
+    pub spawn_chunks_radius: i32,
+    /// Kick clients sending unknown packets during login
+    pub strict_protocol: bool,
+    /// Window in which an address can only log in a limited number of times, zero turns the throttle off
+    pub connection_throttle: Duration,
+    /// Max number of logins from one address within the window
+    pub connection_throttle_limit: usize,
+    /// Fail to load saved data with fields we don't know, instead of keeping them
+    pub strict_save_format: bool,
+    /// Number of ticks between saving the worlds and the data of the players, 0 turns auto-saving off
+    pub auto_save_interval: u64,
+    /// Also accept IPv4 clients when listening on the unspecified IPv6 address
+    pub dual_stack: bool,
+    /// Time between the keep alives sent to each client
+    pub keep_alive_interval: Duration,
+    /// Max number of bytes read from a connection at once
+    pub read_buffer_size: usize
 }
 
 pub struct Server {
@@ -45,18 +139,51 @@ pub struct Server {
     // Clients that aren't assigned a world yet
     clients: RwLock<HashMap<u32, Arc<RwLock<Client>>>>,
 
+    maps: RwLock<HashMap<i32, MapState>>,
+    /// Ids of the maps that couldn't be loaded, so their files aren't read again every time they're drawn
+    missing_maps: Mutex<HashSet<i32>>,
+    next_map_id: AtomicI32,
+
+    /// Permission level of the operators
+    ops: RwLock<HashMap<Uuid, u8>>,
+
+    teams: RwLock<TeamManager>,
+
+    connection_throttle: Mutex<ConnectionThrottle>,
+
+    /// Cancel flag and thread of the running chunk pregeneration
+    pregen: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>>,
+
+    view_distance: u8,
+    spawn_chunks_radius: i32,
     default_gamemode: GameMode,
     level_name: String,
     motd: String,
+    /// Difficulty new worlds start with
     difficulty: Difficulty,
     compression_threshold: Option<i32>,
     level_type: String,
+    generator: GeneratorType,
+    level_seed: Option<i64>,
     max_players: i32,
     favicon: Option<String>,
 
     encryption: bool,
+    strict_protocol: bool,
+    /// What to do with unknown fields in saved data
+    unknown_fields: UnknownFields,
+    auto_save_interval: u64,
+    dual_stack: bool,
+    keep_alive_interval: Duration,
+    read_buffer_size: usize,
+    /// Number of ticks the server has run for
+    ticks: AtomicU64,
 
     pub authenticator: Sender<AuthInfo>,
+    /// Looks up the skins of players the authenticator didn't give any properties
+    skins: Option<SkinCache>,
+    events: EventBus,
+    recipes: RecipeRegistry,
 
     public_key_der: Vec<u8>,
     private_key: Rsa<Private>,
@@ -64,6 +191,10 @@ pub struct Server {
 
 impl Server {
 
+    pub fn view_distance(&self) -> u8 {
+        self.view_distance
+    }
+
     /// Returns the default gamemode.
     pub fn default_gamemode(&self) -> GameMode {
         self.default_gamemode
@@ -73,6 +204,7 @@ impl Server {
         &self.motd
     }
 
+    /// Returns the difficulty new worlds start with, each world has its own difficulty
     pub fn difficulty(&self) -> Difficulty {
         self.difficulty
     }
@@ -81,6 +213,14 @@ impl Server {
         self.compression_threshold
     }
 
+    pub fn keep_alive_interval(&self) -> Duration {
+        self.keep_alive_interval
+    }
+
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size
+    }
+
     pub fn level_type(&self) -> &str {
         &self.level_type
     }
@@ -97,6 +237,10 @@ impl Server {
         self.encryption
     }
 
+    pub fn strict_protocol(&self) -> bool {
+        self.strict_protocol
+    }
+
     pub fn private_key(&self) -> &Rsa<Private> {
         &self.private_key
     }
@@ -109,6 +253,16 @@ impl Server {
         &self.public_key_der
     }
 
+    /// Returns the listeners of the server, plugins register theirs here
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Returns the crafting recipes, plugins can register more
+    pub fn recipes(&self) -> &RecipeRegistry {
+        &self.recipes
+    }
+
     pub fn new(config: ServerConfig, favicon: Option<String>, authenticator: Sender<AuthInfo>) -> Server {
         let rsa = Rsa::generate(1024).unwrap();
         Server {
@@ -119,18 +273,44 @@ impl Server {
             worlds: Vec::new(),
             clients: RwLock::new(HashMap::new()),
 
+            maps: RwLock::new(HashMap::new()),
+            missing_maps: Mutex::new(HashSet::new()),
+            next_map_id: AtomicI32::new(0),
+
+            ops: RwLock::new(HashMap::new()),
+
+            teams: RwLock::new(TeamManager::default()),
+
+            connection_throttle: Mutex::new(ConnectionThrottle::new(config.connection_throttle, config.connection_throttle_limit)),
+
+            pregen: Mutex::new(None),
+
+            view_distance: config.view_distance,
+            spawn_chunks_radius: config.spawn_chunks_radius,
             default_gamemode: config.default_gamemode,
             level_name: config.level_name,
             motd: config.motd,
             difficulty: config.difficulty,
             compression_threshold: config.compression_threshold,
+            generator: GeneratorType::from_level_type(&config.level_type),
             level_type: config.level_type,
+            level_seed: config.level_seed,
             max_players: config.max_players,
             encryption: config.encryption,
+            strict_protocol: config.strict_protocol,
+            unknown_fields: if config.strict_save_format { UnknownFields::Reject } else { UnknownFields::Preserve },
+            auto_save_interval: config.auto_save_interval,
+            dual_stack: config.dual_stack,
+            keep_alive_interval: config.keep_alive_interval,
+            read_buffer_size: config.read_buffer_size,
+            ticks: AtomicU64::new(0),
 
             favicon,
 
             authenticator,
+            skins: None,
+            events: EventBus::default(),
+            recipes: RecipeRegistry::new(),
 
             public_key_der: rsa.public_key_to_der().unwrap(),
             private_key: rsa
@@ -142,7 +322,26 @@ impl Server {
 
         let ps = ProtocolThread::start();
 
-        let listener = TcpListener::bind(address).unwrap();
+        let tick_svr = svr.clone();
+        thread::spawn(move || {
+            loop {
+                tick_svr.tick();
+                thread::sleep(TICK_DURATION);
+            }
+        });
+
+        let mut listeners = bind(address, svr.dual_stack).unwrap();
+        let listener = listeners.pop().unwrap();
+        for other in listeners {
+            let svr = svr.clone();
+            let ps = ps.clone();
+            thread::spawn(move || Server::accept(&svr, &ps, other));
+        }
+
+        Server::accept(&svr, &ps, listener);
+    }
+
+    fn accept(svr: &Arc<Server>, ps: &Sender<Protocol>, listener: TcpListener) {
         for connection in listener.incoming() {
             let mut stream = connection.unwrap();
             if Protocol::legacy_ping(&mut stream) {
@@ -156,12 +355,21 @@ impl Server {
             let (client_id, client) = prot.get_client();
             ps.send(prot).unwrap();
 
-            let mut clients = svr.clients.write().unwrap();
-            clients.insert(client_id, client);
-            debug!("Added client with id: {}", client_id);
+            svr.add_client(client_id, client);
         }
     }
 
+    /// Records a login from the address, returns false if it logged in too often recently
+    pub fn allow_login(&self, ip: IpAddr) -> bool {
+        self.connection_throttle.lock().unwrap().allow(ip, Instant::now())
+    }
+
+    pub(crate) fn add_client(&self, client_id: u32, client: Arc<RwLock<Client>>) {
+        let mut clients = self.clients.write().unwrap();
+        clients.insert(client_id, client);
+        debug!("Added client with id: {}", client_id);
+    }
+
     pub fn remove_client(&self, id: u32) {
         let mut clients = self.clients.write().unwrap();
         if clients.remove(&id).is_some() {
@@ -176,22 +384,425 @@ impl Server {
         }
 
         if let Some(player) = player {
+            // Get off whatever the player was riding
+            let (vehicle, world) = {
+                let p = player.read().unwrap();
+                (p.vehicle(), p.world())
+            };
+            if let Some(vehicle) = vehicle {
+                if let Some(entity) = world.read().unwrap().get_entity(vehicle) {
+                    entity.write().unwrap().set_passenger(None);
+                }
+
+                self.send_to_tracking(&world, id, Packet::AttachEntity(id, None, false));
+            }
+
+            self.untrack_entities(&world, &[id]);
+
             let client = player.read().unwrap().client();
             let client = client.read().unwrap();
-            let msg = format!("{} left the game", client.get_username().unwrap());
-            info!("{}", msg);
-            self.broadcast(Packet::ChatMessage(msg));
+            client.close_windows();
+
+            {
+                let mut player = player.write().unwrap();
+                player.stats_mut().increment(stats::LEAVE_GAME, 1);
+                if let Err(e) = player.stats().save(&self.stats_path(client.uuid())) {
+                    error!("Failed to save the statistics of {}: {}", client.uuid(), e);
+                }
+                if let Err(e) = player.save_data(&self.player_data_path(client.uuid())) {
+                    error!("Failed to save the data of {}: {}", client.uuid(), e);
+                }
+            }
+
+            let event = self.events.player_quit(PlayerQuit::new(client.get_username().unwrap(), client.uuid()));
+            if let Some(msg) = event.message {
+                info!("{}", msg);
+                self.broadcast(Packet::ChatMessage(msg));
+            }
             self.broadcast(Packet::PlayerListItem(PlayerListAction::RemovePlayer, Box::new([player])));
         }
     }
 
-    pub fn load_worlds(&mut self) {
+    /// Loads the operators from a vanilla ops.json file, a missing file means there are no operators
+    pub fn load_ops(&mut self, path: &Path) -> io::Result<()> {
+        let data = match fs::read(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e)
+        };
+
+        let entries: Vec<json::Value> = json::from_slice(&data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let ops = self.ops.get_mut().unwrap();
+        for entry in entries {
+            let uuid = entry["uuid"].as_str().and_then(|v| Uuid::parse_str(v).ok());
+            let level = entry["level"].as_u64().unwrap_or(4);
+            match uuid {
+                Some(uuid) => {
+                    ops.insert(uuid, level as u8);
+                }
+                None => warn!("Skipping operator without a valid uuid: {}", entry)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the skins of players that join without properties with the fetcher, like players of offline servers
+    pub fn set_skin_fetcher(&mut self, fetcher: Box<dyn SkinFetcher>) {
+        self.skins = Some(SkinCache::new(fetcher, skins::SKIN_CACHE_TTL));
+    }
+
+    /// Returns the permission level of the player, 0 for players that aren't operators
+    pub fn op_level(&self, uuid: Uuid) -> u8 {
+        self.ops.read().unwrap().get(&uuid).copied().unwrap_or(0)
+    }
+
+    pub fn set_op_level(&self, uuid: Uuid, level: u8) {
+        let mut ops = self.ops.write().unwrap();
+        if level == 0 {
+            ops.remove(&uuid);
+        } else {
+            ops.insert(uuid, level);
+        }
+    }
+
+    /// Returns the names of the teams and their players
+    pub fn list_teams(&self) -> Vec<(String, Vec<String>)> {
+        self.teams.read().unwrap().teams().map(|team| (team.name().to_owned(), team.players().to_vec())).collect()
+    }
+
+    /// Returns the packets creating all teams, for players that just joined
+    pub fn team_packets(&self) -> Vec<Packet> {
+        self.teams.read().unwrap().teams()
+            .map(|team| Packet::Teams(team.name().to_owned(), TeamAction::Create(team.clone())))
+            .collect()
+    }
+
+    /// Adds the team, returns false if there already is a team with the name
+    pub fn create_team(&self, team: Team) -> bool {
+        let packet = Packet::Teams(team.name().to_owned(), TeamAction::Create(team.clone()));
+        if !self.teams.write().unwrap().create(team) {
+            return false;
+        }
+
+        self.broadcast(packet);
+        true
+    }
+
+    pub fn remove_team(&self, name: &str) -> bool {
+        if self.teams.write().unwrap().remove(name).is_none() {
+            return false;
+        }
+
+        self.broadcast(Packet::Teams(name.to_owned(), TeamAction::Remove));
+        true
+    }
+
+    /// Changes the team with the function and sends its new info
+    pub fn update_team(&self, name: &str, function: impl FnOnce(&mut Team)) -> bool {
+        let team = {
+            let mut teams = self.teams.write().unwrap();
+            match teams.get_mut(name) {
+                Some(team) => {
+                    function(team);
+                    team.clone()
+                }
+                None => return false
+            }
+        };
+
+        self.broadcast(Packet::Teams(name.to_owned(), TeamAction::UpdateInfo(team)));
+        true
+    }
+
+    /// Moves the player to the team, returns false if there is no team with the name
+    pub fn join_team(&self, name: &str, player: &str) -> bool {
+        let old = {
+            let mut teams = self.teams.write().unwrap();
+            if teams.get(name).is_none() {
+                return false;
+            }
+
+            let old = teams.leave(player);
+            teams.join(name, player);
+            old
+        };
+
+        if let Some(old) = old {
+            self.broadcast(Packet::Teams(old, TeamAction::RemovePlayers(vec![player.to_owned()])));
+        }
+
+        self.broadcast(Packet::Teams(name.to_owned(), TeamAction::AddPlayers(vec![player.to_owned()])));
+        true
+    }
+
+    /// Removes the player from its team, returns the name of the team it left
+    pub fn leave_team(&self, player: &str) -> Option<String> {
+        let old = self.teams.write().unwrap().leave(player)?;
+        self.broadcast(Packet::Teams(old.clone(), TeamAction::RemovePlayers(vec![player.to_owned()])));
+        Some(old)
+    }
+
+    /// Returns false if both players are on a team without friendly fire
+    pub fn can_hurt(&self, attacker: &str, target: &str) -> bool {
+        self.teams.read().unwrap().can_hurt(attacker, target)
+    }
+
+    /// Loads the worlds. Fails if level.dat can't be read, the server would overwrite it when saving otherwise.
+    pub fn load_worlds(&mut self) -> io::Result<()> {
+        let level_dat_error = |e: Error| Error::new(e.kind(), format!("Failed to load level.dat of {}: {}", self.level_name, e));
+        // Existing worlds keep the seed they were created with
+        let seed = World::saved_seed(&self.level_name).map_err(level_dat_error)?
+            .or(self.level_seed)
+            .unwrap_or_else(|| thread_rng().gen());
+
         // TODO: change
-        self.worlds.push(Arc::new(RwLock::new(World::new(WorldConfig {
+        let mut world = World::new(WorldConfig {
             name: self.level_name.clone(),
             dimension: Dimension::Overworld,
-            spawn_pos: Coord::<i32>::new(0, 65, 0)
-        }))));
+            spawn_pos: Coord::<i32>::new(0, 65, 0),
+            spawn_chunks_radius: self.spawn_chunks_radius,
+            difficulty: self.difficulty,
+            generator: self.generator,
+            seed
+        });
+        world.load_level_dat(self.unknown_fields).map_err(level_dat_error)?;
+
+        match map::load_last_id(&self.map_ids_path()) {
+            Ok(last) => *self.next_map_id.get_mut() = last.map_or(0, |v| v + 1),
+            Err(e) => error!("Failed to load the map ids of {}: {}", self.level_name, e)
+        }
+
+        self.worlds.push(Arc::new(RwLock::new(world)));
+        Ok(())
+    }
+
+    pub fn tick(&self) {
+        for world in &self.worlds {
+            let (detonated, weather_changed) = {
+                let mut w = world.write().unwrap();
+                let weather = w.weather().weather_type();
+                let detonated = w.tick(self.view_distance as i32);
+                (detonated, w.weather().weather_type() != weather)
+            };
+            if weather_changed {
+                self.broadcast_weather(world);
+            }
+
+            // Everyone in the world slept through the night
+            if world.write().unwrap().skip_night() {
+                let players = world.read().unwrap().players();
+                for player in players {
+                    let client = player.read().unwrap().client();
+                    client.read().unwrap().wake_up();
+                }
+                self.broadcast_to_world(world, Packet::TimeUpdate(world.clone()));
+                self.broadcast_weather(world);
+            }
+
+            // Hostile mobs disappear right away on peaceful, summoned ones too
+            let despawned = {
+                let mut w = world.write().unwrap();
+                if w.difficulty() == Difficulty::Peaceful { w.remove_hostile_mobs() } else { Vec::new() }
+            };
+            if !despawned.is_empty() {
+                self.untrack_entities(world, &despawned);
+            }
+
+            if !detonated.is_empty() {
+                let ids: Vec<_> = detonated.iter().map(|v| v.read().unwrap().id()).collect();
+                self.untrack_entities(world, &ids);
+                for tnt in detonated {
+                    let pos = tnt.read().unwrap().pos();
+                    // Explode from the center of the TNT
+                    let center = Coord::new(pos.x, pos.y + 0.49, pos.z);
+                    self.explode(world, center, explosion::TNT_RADIUS);
+                }
+            }
+
+            let moved = world.read().unwrap().move_entities();
+            for entity in moved {
+                let (id, pos, yaw, pitch) = {
+                    let e = entity.read().unwrap();
+                    (e.id(), e.pos(), e.yaw(), e.pitch())
+                };
+                self.send_to_tracking(world, id, Packet::EntityTeleport(id, pos, yaw, pitch, false));
+            }
+
+            let ticked = {
+                let w = world.read().unwrap();
+                block_ticks::random_ticks(&w, w.ticking_chunks(self.view_distance as i32), &mut thread_rng())
+            };
+            if !ticked.is_empty() {
+                self.set_blocks(world, &ticked);
+            }
+
+            let scheduled: Vec<_> = {
+                let mut w = world.write().unwrap();
+                let due = w.take_due_ticks();
+                due.into_iter().filter_map(|pos| redstone::scheduled_tick(&w, pos)).collect()
+            };
+            if !scheduled.is_empty() {
+                self.set_blocks(world, &scheduled);
+            }
+
+            let furnaces = {
+                let w = world.read().unwrap();
+                furnace::tick_furnaces(&w, w.ticking_chunks(self.view_distance as i32))
+            };
+            self.update_furnaces(world, &furnaces);
+
+            self.update_tracking(world);
+
+            // Don't hold the world lock, the clients need to lock it themselves
+            let (players, send_time) = {
+                let w = world.read().unwrap();
+                (w.players(), w.age() % TIME_UPDATE_INTERVAL == 0)
+            };
+            for player in players {
+                let client = player.read().unwrap().client();
+                client.read().unwrap().tick();
+            }
+
+            if send_time {
+                self.broadcast_to_world(world, Packet::TimeUpdate(world.clone()));
+            }
+        }
+
+        self.send_map_updates();
+
+        let ticks = self.ticks.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.auto_save_interval > 0 && ticks % self.auto_save_interval == 0 {
+            self.save_all();
+        }
+    }
+
+    /// Saves the changed chunks and level.dat of every world and the data of the online players
+    pub fn save_all(&self) {
+        let (mut chunks, mut players) = (0, 0);
+        for world in &self.worlds {
+            let w = world.read().unwrap();
+            match w.save_chunks() {
+                Ok(v) => chunks += v,
+                Err(e) => error!("Failed to save the chunks of {}: {}", w.name(), e)
+            }
+            if let Err(e) = w.save_level_dat() {
+                error!("Failed to save level.dat of {}: {}", w.name(), e);
+            }
+
+            for player in w.players() {
+                let player = player.read().unwrap();
+                let uuid = player.client().read().unwrap().uuid();
+                let saved = player.stats().save(&self.stats_path(uuid))
+                    .and_then(|()| player.save_data(&self.player_data_path(uuid)));
+                match saved {
+                    Ok(()) => players += 1,
+                    Err(e) => error!("Failed to save the data of {}: {}", uuid, e)
+                }
+            }
+        }
+
+        let maps = self.maps.read().unwrap();
+        for (id, map) in maps.iter() {
+            if let Err(e) = map.save(&self.map_path(*id)) {
+                error!("Failed to save map {}: {}", id, e);
+            }
+        }
+        let next_map_id = self.next_map_id.load(Ordering::Relaxed);
+        if next_map_id > 0 {
+            if let Err(e) = map::save_last_id(&self.map_ids_path(), next_map_id - 1) {
+                error!("Failed to save the map ids: {}", e);
+            }
+        }
+
+        info!("Saved {} chunks, {} maps and the data of {} players", chunks, maps.len(), players);
+    }
+
+    /// Returns the path of the file the map is saved in
+    fn map_path(&self, id: i32) -> PathBuf {
+        PathBuf::from(&self.level_name).join("data").join(format!("map_{}.dat", id))
+    }
+
+    /// Returns the path of the file storing the id of the last created map
+    fn map_ids_path(&self) -> PathBuf {
+        PathBuf::from(&self.level_name).join("data").join("idcounts.dat")
+    }
+
+    /// Creates a new map of the area around the position and returns its id.
+    /// Returns None when all ids are taken, the id of a map item is a short.
+    pub fn create_map(&self, scale: u8, dimension: Dimension, x: i32, z: i32) -> Option<i32> {
+        let id = self.next_map_id.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| (v <= i16::MAX as i32).then_some(v + 1)).ok()?;
+        self.missing_maps.lock().unwrap().remove(&id);
+        self.maps.write().unwrap().insert(id, MapState::centered_on(scale, dimension, x, z));
+        Some(id)
+    }
+
+    /// Calls the function with the map, maps that aren't in memory yet are loaded from their file first.
+    /// Returns false if there's no map with the id, a map that failed to load isn't tried again.
+    pub fn do_with_map_mut(&self, id: i32, function: impl FnOnce(&mut MapState)) -> bool {
+        let mut maps = self.maps.write().unwrap();
+        if !maps.contains_key(&id) && !self.missing_maps.lock().unwrap().contains(&id) {
+            match MapState::load(&self.map_path(id)) {
+                Ok(Some(map)) => {
+                    maps.insert(id, map);
+                }
+                Ok(None) => {
+                    self.missing_maps.lock().unwrap().insert(id);
+                }
+                Err(e) => {
+                    error!("Failed to load map {}: {}", id, e);
+                    self.missing_maps.lock().unwrap().insert(id);
+                }
+            }
+        }
+
+        match maps.get_mut(&id) {
+            Some(map) => {
+                function(map);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Draws the world around the position onto the map
+    pub fn render_map(&self, id: i32, world: &Arc<RwLock<World>>, pos: Coord<f64>) {
+        let w = world.read().unwrap();
+        self.do_with_map_mut(id, |v| map::render(v, &w, pos));
+    }
+
+    /// Returns the packet with the whole map, for players that start holding it
+    pub fn full_map(&self, id: i32) -> Option<Packet> {
+        let mut packet = None;
+        self.do_with_map_mut(id, |map| {
+            packet = Some(Packet::Map(id, map.scale(), map.icons().to_vec(), Some(map.full_update())));
+        });
+        packet
+    }
+
+    /// Sends the changed areas of all maps to the players holding them
+    fn send_map_updates(&self) {
+        let mut updates = HashMap::new();
+        for (id, map) in self.maps.write().unwrap().iter_mut() {
+            if let Some(update) = map.take_update() {
+                updates.insert(*id, Packet::Map(*id, map.scale(), map.icons().to_vec(), Some(update)));
+            }
+        }
+
+        if updates.is_empty() {
+            return;
+        }
+
+        self.foreach_player(&|player| {
+            let (map_id, client) = {
+                let p = player.read().unwrap();
+                (p.inventory().held_item().and_then(ItemStack::map_id), p.client())
+            };
+            if let Some(packet) = map_id.and_then(|v| updates.get(&v)) {
+                client.read().unwrap().send(packet.clone());
+            }
+        });
     }
 
     pub fn default_world(&self) -> Arc<RwLock<World>> {
@@ -235,42 +846,482 @@ impl Server {
 
     pub fn auth_user(&self, client_id: u32, username: String, uuid: Uuid, properties: json::Value) {
         if self.online_players() >= self.max_players {
-            self.kick_user(client_id, "The server is currently full.");
+            self.kick_user(client_id, kick::reason(kick::SERVER_FULL));
             return;
         }
 
+        // Look the skin up before the player is added to the tab list, that's where other players get it from
+        let properties = match &self.skins {
+            Some(skins) if properties.is_null() => skins.get(&username),
+            _ => properties
+        };
+
         let client_arc = self.get_client(client_id).unwrap();
         let client_arc2 = client_arc.clone();
 
         let mut client = client_arc.write().unwrap();
-        let join_message = format!("{} joined the game", username);
+        let join = self.events.player_join(PlayerJoin::new(&username, uuid));
         client.auth(username, uuid, properties);
         // TODO: get correct world for player
         let world = self.default_world();
         let spawn = {
             let w = world.read().unwrap();
-            w.spawn_pos()
+            w.find_safe_spawn(w.spawn_pos())
         };
-        let player = Player::new(client_arc2, world.clone(), self.default_gamemode(), spawn.into());
+        let mut player = Player::new(client_arc2, world.clone(), self.default_gamemode(), spawn.bottom_center());
+        match Statistics::load(&self.stats_path(uuid)) {
+            Ok(v) => player.set_stats(v),
+            Err(e) => error!("Failed to load the statistics of {}: {}", uuid, e)
+        }
+        if let Err(e) = player.load_data(&self.player_data_path(uuid)) {
+            error!("Failed to load the data of {}: {}", uuid, e);
+        }
         let player_arc = Arc::new(RwLock::new(player));
 
-        info!("{}", join_message);
-        self.broadcast(Packet::ChatMessage(join_message));
+        if let Some(msg) = join.message {
+            info!("{}", msg);
+            self.broadcast(Packet::ChatMessage(msg));
+        }
         client.finish_auth(player_arc.clone());
+        drop(client);
 
         self.remove_client(client_id);
         world.write().unwrap().add_player(client_id, player_arc);
+        self.update_tracking(&world);
+    }
+
+    /// Returns the path of the file the statistics of a player are stored in
+    fn stats_path(&self, uuid: Uuid) -> PathBuf {
+        PathBuf::from(&self.level_name).join("stats").join(format!("{}.json", uuid.hyphenated()))
     }
 
-    pub fn kick_user(&self, client_id: u32, reason: &str) {
+    /// Returns the path of the player.dat file with the inventory and position of a player
+    fn player_data_path(&self, uuid: Uuid) -> PathBuf {
+        PathBuf::from(&self.level_name).join("playerdata").join(format!("{}.dat", uuid.hyphenated()))
+    }
+
+    pub fn kick_user(&self, client_id: u32, reason: json::Value) {
         self.do_with_client(client_id, &|client: &Arc<RwLock<Client>>| {
-            client.read().unwrap().kick(reason);
+            client.read().unwrap().kick(reason.clone());
             true
         });
     }
 
+    /// Returns the type and meta of the block in the world, unloaded chunks are treated like air
+    pub fn get_block_state(&self, world: &Arc<RwLock<World>>, pos: Coord<i32>) -> (BlockType, u8) {
+        world.read().unwrap().get_block_state(pos)
+    }
+
+    /// Sets the blocks and sends the changes to the players, one Multi Block Change per chunk.
+    /// Blocks in unloaded chunks are skipped. Redstone around the changed blocks gets updated.
+    /// Returns the number of changed blocks.
+    pub fn set_blocks(&self, world: &Arc<RwLock<World>>, blocks: &[(Coord<i32>, BlockType, u8)]) -> usize {
+        let mut changed = Vec::new();
+        let mut powered = Vec::new();
+        let mut removed_containers = Vec::new();
+        {
+            let w = world.read().unwrap();
+            for &(pos, block_type, meta) in blocks {
+                let old = w.get_block_state(pos).0;
+                if w.set_block(pos, block_type, meta) {
+                    changed.push((pos, block_type, meta));
+                    if redstone::is_redstone(old) || redstone::is_redstone(block_type) {
+                        powered.push(pos);
+                    }
+                    // Furnaces keep their contents when they get lit or go out
+                    let same_container = old.is_furnace() && block_type.is_furnace();
+                    if old != block_type && old.is_container() && !same_container {
+                        removed_containers.push((pos, w.get_tile_entity(pos)));
+                        w.set_tile_entity(pos, None);
+                    }
+                }
+            }
+        }
+
+        self.broadcast_block_changes(world, &changed);
+        self.remove_containers(world, removed_containers);
+        if !powered.is_empty() {
+            self.update_redstone(world, &powered);
+        }
+
+        changed.len()
+    }
+
+    /// Closes the windows players have open for the destroyed containers
+    /// and drops their contents where they were, the tile entities are already removed
+    fn remove_containers(&self, world: &Arc<RwLock<World>>, containers: Vec<(Coord<i32>, Option<TileEntity>)>) {
+        if containers.is_empty() {
+            return;
+        }
+
+        let players = world.read().unwrap().players();
+        for player in players {
+            let client = player.read().unwrap().client();
+            let client = client.read().unwrap();
+            for (pos, _) in &containers {
+                client.close_window_at(*pos);
+            }
+        }
+
+        for (pos, tile_entity) in containers {
+            let items = match tile_entity.as_ref().and_then(TileEntity::items) {
+                Some(v) => v.lock().take_all(),
+                None => continue
+            };
+
+            let pos = Coord::new(pos.x as f64 + 0.5, pos.y as f64 + 0.5, pos.z as f64 + 0.5);
+            for item in items {
+                let entity = world.write().unwrap().add_entity(Entity::dropped_item(get_next_entity_id(), pos, item));
+                self.track_entity(world, &entity);
+            }
+        }
+    }
+
+    /// Swaps furnaces that got lit or went out and shows their progress to the players looking into them
+    fn update_furnaces(&self, world: &Arc<RwLock<World>>, furnaces: &[FurnaceUpdate]) {
+        if furnaces.is_empty() {
+            return;
+        }
+
+        let lit: Vec<_> = furnaces.iter()
+            .filter(|v| v.lit_changed)
+            .filter_map(|v| {
+                let (block_type, meta) = world.read().unwrap().get_block_state(v.pos);
+                if !block_type.is_furnace() {
+                    return None;
+                }
+
+                let block_type = if v.progress.is_burning() { BlockType::LitFurnace } else { BlockType::Furnace };
+                Some((v.pos, block_type, meta))
+            })
+            .collect();
+        if !lit.is_empty() {
+            self.set_blocks(world, &lit);
+        }
+
+        let players = world.read().unwrap().players();
+        for player in players {
+            let client = player.read().unwrap().client();
+            let client = client.read().unwrap();
+            for furnace in furnaces {
+                client.update_furnace_window(furnace);
+            }
+        }
+    }
+
+    /// Sends the changed blocks to the players, grouped per chunk
+    fn broadcast_block_changes(&self, world: &Arc<RwLock<World>>, blocks: &[(Coord<i32>, BlockType, u8)]) {
+        let mut changes: HashMap<ChunkCoord, Vec<(Coord<i32>, BlockType, u8)>> = HashMap::new();
+        for &(pos, block_type, meta) in blocks {
+            changes.entry(ChunkCoord::from_block_pos(pos)).or_default().push((pos, block_type, meta));
+        }
+
+        for (coord, records) in changes {
+            self.broadcast_to_world(world, Packet::MultiBlockChange(coord, records));
+        }
+    }
+
+    /// Updates the redstone around the changed blocks, sends the blocks it changed and ignites powered TNT
+    fn update_redstone(&self, world: &Arc<RwLock<World>>, changed: &[Coord<i32>]) {
+        let changes = redstone::update(&world.read().unwrap(), changed);
+        self.broadcast_block_changes(world, &changes.blocks);
+        for pos in changes.ignited {
+            let tnt = world.write().unwrap().prime_tnt(pos, TNT_FUSE);
+            if let Some(tnt) = tnt {
+                self.track_entity(world, &tnt);
+                self.broadcast_to_world(world, Packet::BlockChange(pos, BlockType::Air, 0));
+            }
+        }
+    }
+
+    /// Sets the tile entity of a block and sends it to the players
+    pub fn set_tile_entity(&self, world: &Arc<RwLock<World>>, pos: Coord<i32>, tile_entity: TileEntity) {
+        if world.read().unwrap().set_tile_entity(pos, Some(tile_entity.clone())) {
+            self.broadcast_to_world(world, Packet::UpdateBlockEntity(pos, tile_entity));
+        }
+    }
+
+    /// Destroys the blocks around the center and damages and pushes away players nearby
+    pub fn explode(&self, world: &Arc<RwLock<World>>, center: Coord<f64>, radius: f32) {
+        let mut rng = thread_rng();
+        let affected = explosion::affected_blocks(&world.read().unwrap(), center, radius, || rng.gen());
+
+        let mut changes: HashMap<ChunkCoord, Vec<(Coord<i32>, BlockType, u8)>> = HashMap::new();
+        let mut primed = Vec::new();
+        let mut removed_containers = Vec::new();
+        {
+            let mut w = world.write().unwrap();
+            for pos in &affected {
+                if w.get_block_state(*pos).0.is_container() {
+                    removed_containers.push((*pos, w.get_tile_entity(*pos)));
+                    w.set_tile_entity(*pos, None);
+                }
+
+
+                // Caught TNT goes off a bit sooner
+                match w.prime_tnt(*pos, rng.gen_range(TNT_FUSE / 8..TNT_FUSE / 4 + TNT_FUSE / 8)) {
+                    Some(tnt) => primed.push(tnt),
+                    None => {
+                        w.set_block(*pos, BlockType::Air, 0);
+                    }
+                }
+
+                changes.entry(ChunkCoord::from_block_pos(*pos)).or_default().push((*pos, BlockType::Air, 0));
+            }
+        }
+
+        let players = world.read().unwrap().players();
+        for player in players {
+            let (pos, gamemode, client) = {
+                let p = player.read().unwrap();
+                (p.pos(), p.gamemode(), p.client())
+            };
+
+            let (dx, dy, dz) = (pos.x - center.x, pos.y - center.y, pos.z - center.z);
+            if dx * dx + dy * dy + dz * dz >= EXPLOSION_RANGE * EXPLOSION_RANGE {
+                continue;
+            }
+
+            let client = client.read().unwrap();
+            let mut motion = Coord::new(0f64, 0f64, 0f64);
+            if gamemode == GameMode::Survival || gamemode == GameMode::Adventure {
+                if let Some((knockback, damage)) = explosion::impact(center, radius, pos) {
+                    motion = knockback;
+                    client.damage(damage, DamageCause::Explosion);
+                }
+            }
+
+            client.send(Packet::Explosion(center, radius, affected.clone(), motion));
+        }
+
+        for tnt in primed {
+            self.track_entity(world, &tnt);
+        }
+
+        for (coord, records) in changes {
+            self.broadcast_to_world(world, Packet::MultiBlockChange(coord, records));
+        }
+
+        self.remove_containers(world, removed_containers);
+    }
+
+    /// Generates the chunks within `radius` of `center` in the background,
+    /// posting the progress to `notify` every few seconds.
+    /// Returns false if a pregeneration is already running.
+    pub fn start_pregen(&self, world: &Arc<RwLock<World>>, center: ChunkCoord, radius: i32, notify: Sender<Packet>) -> bool {
+        let mut pregen = self.pregen.lock().unwrap();
+        if pregen.as_ref().is_some_and(|(_, handle)| !handle.is_finished()) {
+            return false;
+        }
+
+        let chunk_map = world.read().unwrap().chunk_map();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = cancel.clone();
+        let handle = thread::spawn(move || {
+            let parallelism = thread::available_parallelism().map_or(1, |v| v.get());
+            let last_report = Mutex::new(Instant::now());
+            let generated = chunk_map.pregenerate(center, radius, parallelism, |done, total| {
+                let mut last_report = last_report.lock().unwrap();
+                if last_report.elapsed() >= PREGEN_PROGRESS_INTERVAL {
+                    *last_report = Instant::now();
+                    // The player might have left in the meantime
+                    let _ = notify.send(Packet::ChatMessage(format!("Pregenerating chunks: {}/{}", done, total)));
+                }
+            }, &thread_cancel);
+
+            let msg = if thread_cancel.load(Ordering::Relaxed) {
+                format!("Pregeneration cancelled after generating {} chunks", generated)
+            } else {
+                format!("Pregeneration finished, generated {} chunks", generated)
+            };
+            info!("{}", msg);
+            let _ = notify.send(Packet::ChatMessage(msg));
+        });
+
+        *pregen = Some((cancel, handle));
+        true
+    }
+
+    /// Stops the running chunk pregeneration. Returns false if none is running.
+    pub fn cancel_pregen(&self) -> bool {
+        match self.pregen.lock().unwrap().as_ref() {
+            Some((cancel, handle)) if !handle.is_finished() => {
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            _ => false
+        }
+    }
+
+    /// Adds the effect to the player and lets it and the players tracking it know
+    pub fn add_effect(&self, player: &Arc<RwLock<Player>>, effect: Effect) {
+        let (client, world, effect, attributes) = {
+            let mut player = player.write().unwrap();
+            let attributes = player.attributes().clone();
+            let effect = player.add_effect(effect);
+            let attributes = (*player.attributes() != attributes).then(|| player.attributes().clone());
+            (player.client(), player.world(), effect, attributes)
+        };
+
+        let entity_id = client.read().unwrap().id();
+        self.send_to_tracking(&world, entity_id, Packet::EntityEffect(entity_id, effect));
+        client.read().unwrap().send(Packet::EntityEffect(entity_id, effect));
+        if let Some(attributes) = attributes {
+            self.send_to_tracking(&world, entity_id, Packet::EntityProperties(entity_id, attributes.clone()));
+            client.read().unwrap().send(Packet::EntityProperties(entity_id, attributes));
+        }
+    }
+
+    /// Removes the effects from the player, returns the types it had
+    pub fn remove_effects(&self, player: &Arc<RwLock<Player>>, effect_types: &[EffectType]) -> Vec<EffectType> {
+        let (client, world, removed, attributes) = {
+            let mut player = player.write().unwrap();
+            let attributes = player.attributes().clone();
+            let removed: Vec<_> = effect_types.iter().filter_map(|v| player.remove_effect(*v)).map(|v| v.effect_type).collect();
+            let attributes = (*player.attributes() != attributes).then(|| player.attributes().clone());
+            (player.client(), player.world(), removed, attributes)
+        };
+
+        let entity_id = client.read().unwrap().id();
+        for effect_type in &removed {
+            self.send_to_tracking(&world, entity_id, Packet::RemoveEntityEffect(entity_id, *effect_type));
+            client.read().unwrap().send(Packet::RemoveEntityEffect(entity_id, *effect_type));
+        }
+        if let Some(attributes) = attributes {
+            self.send_to_tracking(&world, entity_id, Packet::EntityProperties(entity_id, attributes.clone()));
+            client.read().unwrap().send(Packet::EntityProperties(entity_id, attributes));
+        }
+
+        removed
+    }
+
+    /// Returns the online player with the name, the case doesn't matter
+    pub fn find_player(&self, username: &str) -> Option<Arc<RwLock<Player>>> {
+        self.worlds.iter()
+            .flat_map(|v| v.read().unwrap().players())
+            .find(|player| {
+                let client = player.read().unwrap().client();
+                let found = client.read().unwrap().get_username().is_some_and(|v| v.eq_ignore_ascii_case(username));
+                found
+            })
+    }
+
+    /// Returns the names and positions of the online players
+    pub fn player_positions(&self) -> Vec<(String, Coord<f64>)> {
+        self.worlds.iter()
+            .flat_map(|v| v.read().unwrap().players())
+            .filter_map(|player| {
+                let player = player.read().unwrap();
+                let client = player.client();
+                let name = client.read().unwrap().get_username().map(|v| (v.to_owned(), player.pos()));
+                name
+            })
+            .collect()
+    }
+
+    /// Returns the range entities can be tracked from at most,
+    /// like in vanilla players don't see entities in chunks they don't have
+    fn max_tracking_range(&self) -> i32 {
+        ((self.view_distance as i32 - 1) * 16).max(16)
+    }
+
+    /// Starts and stops tracking the entities and players that came in or went out of range of the players of the world,
+    /// and sends the packets spawning or destroying them
+    pub fn update_tracking(&self, world: &Arc<RwLock<World>>) {
+        let (viewers, entities) = {
+            let w = world.read().unwrap();
+            (w.player_positions(), w.entities())
+        };
+
+        let max_range = self.max_tracking_range();
+        let mut tracked: Vec<_> = viewers.iter()
+            .map(|&(id, pos)| Tracked { id, pos, range: PLAYER_TRACKING_RANGE.min(max_range) })
+            .collect();
+        tracked.extend(entities.iter().map(|entity| {
+            let e = entity.read().unwrap();
+            Tracked { id: e.id(), pos: e.pos(), range: e.entity_type().tracking_range().min(max_range) }
+        }));
+
+        let changes = world.write().unwrap().tracker_mut().update(&viewers, &tracked);
+        let w = world.read().unwrap();
+        for change in changes {
+            let client = match w.get_player(change.viewer) {
+                Some(v) => v.read().unwrap().client(),
+                None => continue
+            };
+            let client = client.read().unwrap();
+
+            if !change.removed.is_empty() {
+                client.send(Packet::DestroyEntities(change.removed));
+            }
+
+            for id in change.added {
+                if let Some(player) = w.get_player(id) {
+                    client.send(Packet::SpawnPlayer(player));
+                } else if let Some(entity) = w.get_entity(id) {
+                    tracker::spawn_packets(&entity).into_iter().for_each(|packet| client.send(packet));
+                }
+            }
+        }
+    }
+
+    /// Shows a new entity to the players in range of it
+    pub fn track_entity(&self, world: &Arc<RwLock<World>>, entity: &Arc<RwLock<Entity>>) {
+        let tracked = {
+            let e = entity.read().unwrap();
+            Tracked { id: e.id(), pos: e.pos(), range: e.entity_type().tracking_range().min(self.max_tracking_range()) }
+        };
+
+        let viewers = world.read().unwrap().player_positions();
+        let added = world.write().unwrap().tracker_mut().track(&viewers, &tracked);
+        let packets = tracker::spawn_packets(entity);
+        let w = world.read().unwrap();
+        for player in added.into_iter().filter_map(|id| w.get_player(id)) {
+            let client = player.read().unwrap().client();
+            let client = client.read().unwrap();
+            for packet in &packets {
+                client.send(packet.clone());
+            }
+        }
+    }
+
+    /// Destroys the entities or players for the players tracking them
+    pub fn untrack_entities(&self, world: &Arc<RwLock<World>>, ids: &[u32]) {
+        let mut destroyed: HashMap<u32, Vec<u32>> = HashMap::new();
+        {
+            let mut w = world.write().unwrap();
+            for &id in ids {
+                for viewer in w.tracker_mut().untrack(id) {
+                    destroyed.entry(viewer).or_default().push(id);
+                }
+            }
+        }
+
+        let w = world.read().unwrap();
+        for (viewer, ids) in destroyed {
+            if let Some(player) = w.get_player(viewer) {
+                player.read().unwrap().client().read().unwrap().send(Packet::DestroyEntities(ids));
+            }
+        }
+    }
+
+    /// Sends the packet to the players tracking the entity or player
+    pub fn send_to_tracking(&self, world: &Arc<RwLock<World>>, entity_id: u32, packet: Packet) {
+        let w = world.read().unwrap();
+        for viewer in w.tracker().trackers(entity_id) {
+            if let Some(player) = w.get_player(viewer) {
+                player.read().unwrap().client().read().unwrap().send(packet.clone());
+            }
+        }
+    }
+
+    /// Sends the message to all players, the name of the sender is colored by its team
     pub fn broadcast_chat(&self, username: &str, msg: &str) {
-        let raw_msg = format!("<{}>: {}", username, msg);
+        let name = match self.teams.read().unwrap().team_of(username) {
+            Some(team) => format!("{}§r", team.format_name(username)),
+            None => username.to_owned()
+        };
+        let raw_msg = format!("<{}>: {}", name, msg);
         info!("{}", raw_msg);
         self.broadcast(Packet::ChatMessage(raw_msg));
     }
@@ -280,4 +1331,143 @@ impl Server {
             player.read().unwrap().client().read().unwrap().send(packet.clone());
         });
     }
+
+    /// Changes the name the player has in the tab list, None shows the username
+    pub fn set_display_name(&self, player: &Arc<RwLock<Player>>, display_name: Option<json::Value>) {
+        player.write().unwrap().set_display_name(display_name);
+        self.broadcast(Packet::PlayerListItem(PlayerListAction::UpdateDisplayName, Box::new([player.clone()])));
+    }
+
+    /// Sends the packet to the players in the world
+    pub fn broadcast_to_world(&self, world: &Arc<RwLock<World>>, packet: Packet) {
+        world.read().unwrap().foreach_player(&|player| {
+            player.read().unwrap().client().read().unwrap().send(packet.clone());
+        });
+    }
+
+    /// Changes the difficulty of the world and tells the players in it,
+    /// the hostile mobs in it despawn when it becomes peaceful
+    pub fn set_difficulty(&self, world: &Arc<RwLock<World>>, difficulty: Difficulty) {
+        let despawned = {
+            let mut w = world.write().unwrap();
+            w.set_difficulty(difficulty);
+            if difficulty == Difficulty::Peaceful { w.remove_hostile_mobs() } else { Vec::new() }
+        };
+        if !despawned.is_empty() {
+            self.untrack_entities(world, &despawned);
+        }
+
+        self.broadcast_to_world(world, Packet::ServerDifficulty(difficulty));
+    }
+
+    /// Sends the weather of the world to the players in it
+    pub fn broadcast_weather(&self, world: &Arc<RwLock<World>>) {
+        let packets = world.read().unwrap().weather().packets();
+        for packet in packets {
+            self.broadcast_to_world(world, packet);
+        }
+    }
+
+    /// Sends the packet to every player except the one of the client
+    pub fn broadcast_except(&self, client_id: u32, packet: Packet) {
+        self.foreach_player(&|player| {
+            let client = player.read().unwrap().client();
+            let client = client.read().unwrap();
+            if client.id() != client_id {
+                client.send(packet.clone());
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use siderite_nbt::{Compound, Tag};
+
+    use super::*;
+    use crate::test_utils::{test_config, test_server};
+
+    #[test]
+    fn dual_stack_accepts_ipv4() {
+        let listeners = match bind(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0), true) {
+            Ok(v) => v,
+            // No IPv6 on this machine
+            Err(_) => return
+        };
+        let port = listeners.last().unwrap().local_addr().unwrap().port();
+        assert!(listeners.iter().all(|v| v.local_addr().unwrap().port() == port));
+
+        // The connections only have to be accepted by the kernel, they wait in the backlog
+        TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        TcpStream::connect((Ipv6Addr::LOCALHOST, port)).unwrap();
+    }
+
+    #[test]
+    fn ipv4_address_binds_one_listener() {
+        let listeners = bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0), true).unwrap();
+        assert_eq!(listeners.len(), 1);
+    }
+
+    #[test]
+    fn unreadable_level_dat_stops_loading() {
+        let dir = std::env::temp_dir().join(format!("siderite-strict-level-dat-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut data = Compound::new();
+        data.insert("LevelName", Tag::String("old".to_owned()));
+        let mut root = Compound::new();
+        root.insert("Data", Tag::Compound(data));
+        siderite_nbt::write_gzip(fs::File::create(dir.join("level.dat")).unwrap(), "", &Tag::Compound(root)).unwrap();
+
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let config = || ServerConfig {
+            level_name: dir.to_string_lossy().into_owned(),
+            ..test_config()
+        };
+        let mut server = Server::new(ServerConfig { strict_save_format: true, ..config() }, None, tx.clone());
+        assert_eq!(server.load_worlds().unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // Unknown fields are kept when they aren't rejected
+        let mut server = Server::new(config(), None, tx);
+        server.load_worlds().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn worlds_keep_their_seed() {
+        let dir = std::env::temp_dir().join(format!("siderite-seed-{}", std::process::id()));
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let config = |level_seed| ServerConfig {
+            level_name: dir.to_string_lossy().into_owned(),
+            level_type: "DEFAULT".to_owned(),
+            level_seed,
+            ..test_config()
+        };
+        let mut server = Server::new(config(Some(42)), None, tx.clone());
+        server.load_worlds().unwrap();
+        assert_eq!(server.default_world().read().unwrap().seed(), 42);
+        server.save_all();
+
+        // Changing the property only affects new worlds
+        let mut server = Server::new(config(Some(43)), None, tx);
+        server.load_worlds().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(server.default_world().read().unwrap().seed(), 42);
+    }
+
+    #[test]
+    fn map_ids_fit_in_a_short() {
+        let server = test_server();
+        server.next_map_id.store(i16::MAX as i32, Ordering::Relaxed);
+        assert_eq!(server.create_map(0, Dimension::Overworld, 0, 0), Some(i16::MAX as i32));
+        assert_eq!(server.create_map(0, Dimension::Overworld, 0, 0), None);
+        assert!(server.do_with_map_mut(i16::MAX as i32, |_| ()));
+    }
+
+    #[test]
+    fn missing_map_is_remembered() {
+        let server = test_server();
+        assert!(!server.do_with_map_mut(1000, |_| ()));
+        assert!(server.missing_maps.lock().unwrap().contains(&1000));
+        assert!(!server.do_with_map_mut(1000, |_| ()));
+    }
 }
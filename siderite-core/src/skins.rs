@@ -0,0 +1,169 @@
+//! Skins for offline mode, where the authenticator doesn't give players any properties and everyone looks like Steve
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::*;
+use serde_json as json;
+
+/// How long a looked up skin is used before it's looked up again
+pub const SKIN_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Looks up the skins of players by name
+pub trait SkinFetcher: Send + Sync {
+    /// Returns the properties of the player in the same format as the Mojang session server,
+    /// an array with a textures property. None if the player doesn't have a skin.
+    fn fetch(&self, username: &str) -> io::Result<Option<json::Value>>;
+}
+
+/// Skins from a skins.json file, an object with the textures property of each player by name:
+/// `{"Bond_009": {"value": "...", "signature": "..."}}`
+#[derive(Default)]
+pub struct LocalSkins {
+    /// Properties of each player, by lowercase name
+    skins: HashMap<String, json::Value>
+}
+
+impl LocalSkins {
+    /// Loads the skins from the file, a missing file means there are no skins
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(v) => Self::from_slice(&v),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn from_slice(data: &[u8]) -> io::Result<Self> {
+        let entries: json::Map<String, json::Value> = json::from_slice(data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        let mut skins = HashMap::new();
+        for (name, textures) in entries {
+            let value = textures["value"].as_str();
+            let signature = textures.get("signature").map(|v| v.as_str());
+            match (value, signature) {
+                (Some(value), None) => skins.insert(name.to_lowercase(), json::json!([{ "name": "textures", "value": value }])),
+                (Some(value), Some(Some(signature))) => skins.insert(name.to_lowercase(), json::json!([{
+                    "name": "textures",
+                    "value": value,
+                    "signature": signature
+                }])),
+                _ => {
+                    warn!("Skipping the skin of {} without a valid textures value", name);
+                    continue;
+                }
+            };
+        }
+
+        Ok(Self { skins })
+    }
+}
+
+impl SkinFetcher for LocalSkins {
+    fn fetch(&self, username: &str) -> io::Result<Option<json::Value>> {
+        Ok(self.skins.get(&username.to_lowercase()).cloned())
+    }
+}
+
+/// Remembers the skins a fetcher found, so players that rejoin don't have to be looked up again
+pub struct SkinCache {
+    fetcher: Box<dyn SkinFetcher>,
+    ttl: Duration,
+    /// When each player was looked up and the properties that were found, by lowercase name
+    entries: Mutex<HashMap<String, (Instant, json::Value)>>
+}
+
+impl SkinCache {
+    pub fn new(fetcher: Box<dyn SkinFetcher>, ttl: Duration) -> Self {
+        Self {
+            fetcher,
+            ttl,
+            entries: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// Returns the properties of the player, Null if it doesn't have a skin or it couldn't be looked up.
+    /// Failed lookups aren't remembered, so they're tried again the next time the player joins.
+    pub fn get(&self, username: &str) -> json::Value {
+        let key = username.to_lowercase();
+        if let Some((fetched, properties)) = self.entries.lock().unwrap().get(&key) {
+            if fetched.elapsed() < self.ttl {
+                return properties.clone();
+            }
+        }
+
+        let properties = match self.fetcher.fetch(username) {
+            Ok(v) => v.unwrap_or(json::Value::Null),
+            Err(e) => {
+                warn!("Failed to look up the skin of {}: {}", username, e);
+                return json::Value::Null;
+            }
+        };
+
+        self.entries.lock().unwrap().insert(key, (Instant::now(), properties.clone()));
+        properties
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::test_utils::MockSkinFetcher;
+
+    fn mock_cache(ttl: Duration, fail: bool) -> (SkinCache, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        (SkinCache::new(Box::new(MockSkinFetcher { calls: calls.clone(), fail }), ttl), calls)
+    }
+
+    #[test]
+    fn caches_skins() {
+        let (cache, calls) = mock_cache(SKIN_CACHE_TTL, false);
+        assert_eq!(cache.get("Alex"), json::json!([{ "name": "textures", "value": "Alex" }]));
+        assert_eq!(cache.get("alex"), json::json!([{ "name": "textures", "value": "Alex" }]));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // Players without a skin are remembered too
+        assert!(cache.get("Steve").is_null());
+        assert!(cache.get("Steve").is_null());
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn expired_skins_are_fetched_again() {
+        let (cache, calls) = mock_cache(Duration::ZERO, false);
+        cache.get("Alex");
+        cache.get("Alex");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn failures_fall_back_to_no_skin() {
+        let (cache, calls) = mock_cache(SKIN_CACHE_TTL, true);
+        assert!(cache.get("Alex").is_null());
+        assert!(cache.get("Alex").is_null());
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn local_skins() {
+        let skins = LocalSkins::from_slice(br#"{
+            "Bond_009": {"value": "dGV4dHVyZXM=", "signature": "c2lnbmF0dXJl"},
+            "Alex": {"value": "YWxleA=="},
+            "Broken": {"signature": "c2lnbmF0dXJl"}
+        }"#).unwrap();
+
+        let properties = skins.fetch("bond_009").unwrap().unwrap();
+        assert_eq!(properties, json::json!([{ "name": "textures", "value": "dGV4dHVyZXM=", "signature": "c2lnbmF0dXJl" }]));
+        assert_eq!(skins.fetch("Alex").unwrap().unwrap()[0].get("signature"), None);
+        assert_eq!(skins.fetch("Broken").unwrap(), None);
+        assert!(LocalSkins::from_slice(b"[]").is_err());
+    }
+}
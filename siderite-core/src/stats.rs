@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use serde_json as json;
+
+/// Distance walked on the ground, in centimeters
+pub const WALK_ONE_CM: &str = "stat.walkOneCm";
+/// Distance fallen, in centimeters
+pub const FALL_ONE_CM: &str = "stat.fallOneCm";
+/// Number of times the player died
+pub const DEATHS: &str = "stat.deaths";
+/// Number of times the player left the game
+pub const LEAVE_GAME: &str = "stat.leaveGame";
+
+/// Returns the name of the statistic counting the mined blocks of the given type
+pub fn mine_block(block_id: u8) -> String {
+    format!("stat.mineBlock.{}", block_id)
+}
+
+/// Per-player statistics, stored in the same format vanilla uses for stats/<uuid>.json
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Statistics {
+    values: HashMap<String, i32>
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Loads the statistics from a file, returns empty statistics if the file doesn't exist
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = match fs::read(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e)
+        };
+
+        let object: json::Map<String, json::Value> = json::from_slice(&data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        // Achievements with progress are stored as objects, we don't track those
+        let values = object.into_iter()
+            .filter_map(|(name, value)| value.as_i64().map(|v| (name, v as i32)))
+            .collect();
+        Ok(Self { values })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let data = json::to_vec(&self.values).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        fs::write(path, data)
+    }
+
+    pub fn get(&self, name: &str) -> i32 {
+        self.values.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, name: &str, value: i32) {
+        self.values.insert(name.to_owned(), value);
+    }
+
+    pub fn increment(&mut self, name: &str, amount: i32) {
+        let value = self.values.entry(name.to_owned()).or_insert(0);
+        *value = value.saturating_add(amount);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn save_load() {
+        let path = env::temp_dir().join(format!("siderite-stats-{}", std::process::id())).join("stats.json");
+        let mut stats = Statistics::new();
+        stats.increment(WALK_ONE_CM, 150);
+        stats.increment(WALK_ONE_CM, 50);
+        stats.increment(&mine_block(1), 3);
+        stats.save(&path).unwrap();
+
+        let loaded = Statistics::load(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded.get(WALK_ONE_CM), 200);
+        assert_eq!(loaded.get("stat.mineBlock.1"), 3);
+        assert_eq!(loaded, stats);
+    }
+}
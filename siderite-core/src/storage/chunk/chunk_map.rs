@@ -1,38 +1,260 @@
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::sync::{Condvar, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use log::*;
 
 use crate::storage::chunk::*;
+use crate::storage::chunk::generator::{ChunkGenerator, FlatGenerator};
+
+/// Default number of loaded chunks that aren't used by any player
+pub const DEFAULT_CACHE_SIZE: usize = 1024;
+
+/// Ticks between trims of the serialized chunk data, chunks sent less than twice in that time drop it
+pub const SERIALIZED_TRIM_INTERVAL: i64 = 600;
+
+/// Largest radius `pregenerate` handles, keeps a single /pregen at about 250k chunks
+pub const MAX_PREGEN_RADIUS: i32 = 250;
+
+/// Where chunks are loaded from, and written to before they're unloaded
+pub trait ChunkStorage: Send + Sync {
+    /// Returns None if the chunk was never saved
+    fn load(&self, coord: ChunkCoord) -> io::Result<Option<Chunk>>;
+    fn save(&self, coord: ChunkCoord, chunk: &Chunk) -> io::Result<()>;
+}
+
+struct CachedChunk {
+    chunk: Chunk,
+    /// Value of the access counter when the chunk was last used
+    last_access: AtomicU64
+}
 
-#[derive(Default)]
 pub struct ChunkMap {
     // REVIEW: currently we box up the chunks because
     // without they overflow the stack when inserting to the hashmap in debug mode
-    chunks: RwLock<HashMap<ChunkCoord, Chunk>>
+    chunks: RwLock<HashMap<ChunkCoord, CachedChunk>>,
+    /// Chunks that never get unloaded, even when no player is nearby
+    keep_loaded: RwLock<HashSet<ChunkCoord>>,
+    /// Chunks in view of a player, as of the last unload
+    in_use: RwLock<HashSet<ChunkCoord>>,
+    /// Counts chunk accesses, to find the least recently used chunk
+    access_counter: AtomicU64,
+    /// Loaded chunks by the access count they had when they were put in, oldest first.
+    /// Accesses don't move a chunk right away, eviction moves the ones used since to the back.
+    lru: Mutex<BTreeMap<u64, ChunkCoord>>,
+    /// Evicted chunks that are being saved, loading them waits for that so it doesn't read the old data
+    unloading: Mutex<HashSet<ChunkCoord>>,
+    unloaded: Condvar,
+    /// Maximum number of loaded chunks that aren't in use or kept loaded,
+    /// the least recently used one is unloaded when a new chunk exceeds it
+    cache_size: usize,
+    generator: Box<dyn ChunkGenerator>,
+    /// Without storage the changes to unloaded chunks are lost
+    storage: Option<Box<dyn ChunkStorage>>
+}
+
+impl Default for ChunkMap {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChunkMap {
     pub fn new() -> Self {
+        Self::with_generator(FlatGenerator)
+    }
+
+    pub fn with_generator(generator: impl ChunkGenerator + 'static) -> Self {
         Self {
-            chunks: RwLock::new(HashMap::new())
+            chunks: RwLock::new(HashMap::new()),
+            keep_loaded: RwLock::new(HashSet::new()),
+            in_use: RwLock::new(HashSet::new()),
+            access_counter: AtomicU64::new(0),
+            lru: Mutex::new(BTreeMap::new()),
+            unloading: Mutex::new(HashSet::new()),
+            unloaded: Condvar::new(),
+            cache_size: DEFAULT_CACHE_SIZE,
+            generator: Box::new(generator),
+            storage: None
         }
     }
 
+    pub fn set_cache_size(&mut self, cache_size: usize) {
+        self.cache_size = cache_size;
+    }
+
+    pub fn set_storage(&mut self, storage: impl ChunkStorage + 'static) {
+        self.storage = Some(Box::new(storage));
+    }
+
     pub fn do_with_chunk(&self, coord: ChunkCoord, function: impl FnOnce(&Chunk)) {
         let chunks = self.chunks.read().unwrap();
 
-        if let Some(chunk) = chunks.get(&coord) {
-            function(chunk);
+        if let Some(cached) = chunks.get(&coord) {
+            self.mark_accessed(cached);
+            function(&cached.chunk);
         }
     }
 
     pub fn do_with_chunk_mut(&self, coord: ChunkCoord, function: impl FnOnce(&mut Chunk)) {
         let mut chunks = self.chunks.write().unwrap();
 
-        if let Some(chunk) = chunks.get_mut(&coord) {
-            function(chunk);
+        if let Some(cached) = chunks.get_mut(&coord) {
+            self.mark_accessed(cached);
+            function(&mut cached.chunk);
+        }
+    }
+
+    fn mark_accessed(&self, cached: &CachedChunk) {
+        cached.last_access.store(self.access_counter.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// Returns the number of loaded chunks
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.read().unwrap().len()
+    }
+
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool {
+        self.chunks.read().unwrap().contains_key(&coord)
+    }
+
+    /// Loads the chunks in the square area around `center` and keeps them loaded.
+    /// The area spans from `center - radius` (inclusive) to `center + radius` (exclusive) on both axes.
+    pub fn keep_loaded_area(&self, center: ChunkCoord, radius: i32) {
+        for x in (center.x - radius)..(center.x + radius) {
+            for z in (center.z - radius)..(center.z + radius) {
+                self.keep_loaded(ChunkCoord { x, z });
+            }
+        }
+    }
+
+    /// Loads the chunk and excludes it from unloading
+    pub fn keep_loaded(&self, coord: ChunkCoord) {
+        self.touch_chunk(coord);
+        self.keep_loaded.write().unwrap().insert(coord);
+    }
+
+    pub fn is_keep_loaded(&self, coord: ChunkCoord) -> bool {
+        self.keep_loaded.read().unwrap().contains(&coord)
+    }
+
+    /// Returns the coordinates of all chunks that are kept loaded
+    pub fn keep_loaded_chunks(&self) -> Vec<ChunkCoord> {
+        self.keep_loaded.read().unwrap().iter().copied().collect()
+    }
+
+    /// Updates the chunks in view of a player. The others stay loaded as long as they fit in the cache,
+    /// the least recently used ones beyond it are unloaded. Returns the number of unloaded chunks.
+    pub fn unload_unused(&self, in_use: &HashSet<ChunkCoord>) -> usize {
+        self.in_use.write().unwrap().clone_from(in_use);
+        let evicted = self.evict(&mut self.chunks.write().unwrap());
+        self.save_evicted(evicted)
+    }
+
+    /// Drops the serialized data of the chunks that weren't sent to at least two players since the last trim.
+    /// Returns the number of chunks that dropped it.
+    pub fn trim_serialized(&self) -> usize {
+        self.chunks.read().unwrap().values().filter(|cached| cached.chunk.trim_serialized()).count()
+    }
+
+    /// Removes the least recently used chunks that aren't in use or kept loaded
+    /// until there are no more of them than the cache size, and returns them to be saved with `save_evicted`.
+    /// Changed chunks are marked as unloading until then.
+    fn evict(&self, chunks: &mut HashMap<ChunkCoord, CachedChunk>) -> Vec<(ChunkCoord, CachedChunk)> {
+        if chunks.len() <= self.cache_size {
+            return Vec::new();
+        }
+
+        let keep_loaded = self.keep_loaded.read().unwrap();
+        let in_use = self.in_use.read().unwrap();
+        let pinned = |coord: &ChunkCoord| in_use.contains(coord) || keep_loaded.contains(coord);
+        let pinned_count = in_use.iter().filter(|v| chunks.contains_key(v)).count()
+            + keep_loaded.iter().filter(|v| !in_use.contains(v) && chunks.contains_key(v)).count();
+        let excess = match (chunks.len() - pinned_count).checked_sub(self.cache_size) {
+            Some(v) if v > 0 => v,
+            _ => return Vec::new()
+        };
+
+        let mut lru = self.lru.lock().unwrap();
+        let mut skipped = Vec::new();
+        let mut evicted = Vec::with_capacity(excess);
+        while evicted.len() < excess {
+            let (access, coord) = match lru.pop_first() {
+                Some(v) => v,
+                None => break
+            };
+            let last_access = match chunks.get(&coord) {
+                Some(v) => v.last_access.load(Ordering::Relaxed),
+                None => continue
+            };
+
+            if last_access != access {
+                // Used since it was put in, it goes to the back
+                lru.insert(last_access, coord);
+            } else if pinned(&coord) {
+                skipped.push((access, coord));
+            } else {
+                evicted.push((coord, chunks.remove(&coord).unwrap()));
+            }
+        }
+        lru.extend(skipped);
+
+        if self.storage.is_some() {
+            let mut unloading = self.unloading.lock().unwrap();
+            unloading.extend(evicted.iter().filter(|(_, cached)| cached.chunk.is_dirty()).map(|(coord, _)| *coord));
+        }
+
+        evicted
+    }
+
+    /// Saves the changed chunks `evict` removed, without holding the lock on the chunks.
+    /// Those that fail to save are put back. Returns the number of unloaded chunks.
+    fn save_evicted(&self, evicted: Vec<(ChunkCoord, CachedChunk)>) -> usize {
+        let mut unloaded = 0;
+        for (coord, cached) in evicted {
+            if let Err(e) = self.save_changed(coord, &cached.chunk) {
+                error!("Failed to save chunk {}, {} before unloading it: {}", coord.x, coord.z, e);
+                // Nothing loaded it meanwhile, that waits for the unloading to finish
+                let mut chunks = self.chunks.write().unwrap();
+                self.lru.lock().unwrap().insert(cached.last_access.load(Ordering::Relaxed), coord);
+                chunks.insert(coord, cached);
+            } else {
+                unloaded += 1;
+            }
+
+            if self.unloading.lock().unwrap().remove(&coord) {
+                self.unloaded.notify_all();
+            }
+        }
+
+        unloaded
+    }
+
+    /// Reads the chunk from the storage, None if it was never saved or can't be read
+    fn load(&self, coord: ChunkCoord) -> Option<Chunk> {
+        match self.storage.as_ref()?.load(coord) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Failed to load chunk {}, {}, generating it again: {}", coord.x, coord.z, e);
+                None
+            }
         }
     }
 
+    /// Writes the chunk to the storage if it changed since it was last saved
+    fn save_changed(&self, coord: ChunkCoord, chunk: &Chunk) -> io::Result<()> {
+        if let Some(storage) = &self.storage {
+            if chunk.is_dirty() {
+                storage.save(coord, chunk)?;
+                chunk.clear_dirty();
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn touch_chunk(&self, coord: ChunkCoord) {
         {
             let chunks = self.chunks.read().unwrap();
@@ -41,36 +263,395 @@ impl ChunkMap {
             }
         }
 
-        // TODO: load/generate chunk
-        let chunk = Chunk {
-            data: ChunkColumn {
-                sections: [
-                    Some(Box::new(Section {
-                        block_types: [3; SECTION_BLOCK_COUNT],
-                        block_metas: [0; SECTION_BLOCK_COUNT / 2],
-                        block_light: [0; SECTION_BLOCK_COUNT / 2],
-                        block_sky_light: [0xff; SECTION_BLOCK_COUNT / 2]
-                    })),
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None
-                ]},
-            biome_map: [1; AREA as usize]
+        {
+            let mut unloading = self.unloading.lock().unwrap();
+            while unloading.contains(&coord) {
+                unloading = self.unloaded.wait(unloading).unwrap();
+            }
+        }
+
+        let chunk = match self.load(coord) {
+            Some(v) => v,
+            None => {
+                let mut chunk = self.generator.generate(coord);
+                chunk.data.compute_light();
+                chunk
+            }
+        };
+
+        let evicted = {
+            let mut chunks = self.chunks.write().unwrap();
+            if chunks.contains_key(&coord) {
+                return;
+            }
+
+            let cached = CachedChunk { chunk, last_access: AtomicU64::new(0) };
+            self.mark_accessed(&cached);
+            self.lru.lock().unwrap().insert(cached.last_access.load(Ordering::Relaxed), coord);
+            chunks.insert(coord, cached);
+            self.evict(&mut chunks)
         };
+        self.save_evicted(evicted);
+    }
 
-        let mut chunks = self.chunks.write().unwrap();
-        chunks.insert(coord, chunk);
+    /// Writes the chunks that changed since they were last saved with `save`,
+    /// a chunk stays dirty if writing it fails. Returns the number of saved chunks.
+    pub fn save_dirty(&self, mut save: impl FnMut(ChunkCoord, &Chunk) -> io::Result<()>) -> io::Result<usize> {
+        let chunks = self.chunks.read().unwrap();
+        let mut saved = 0;
+        for (coord, cached) in chunks.iter() {
+            if cached.chunk.is_dirty() {
+                save(*coord, &cached.chunk)?;
+                cached.chunk.clear_dirty();
+                saved += 1;
+            }
+        }
+
+        Ok(saved)
+    }
+
+    /// Writes the changed chunks to the storage, see `save_dirty`
+    pub fn save(&self) -> io::Result<usize> {
+        match &self.storage {
+            Some(storage) => self.save_dirty(|coord, chunk| storage.save(coord, chunk)),
+            None => Ok(0)
+        }
+    }
+
+    /// Generates the chunks from `center - radius` to `center + radius` (inclusive) on `parallelism` threads
+    /// and saves them right away, the radius is clamped to `MAX_PREGEN_RADIUS`.
+    /// `progress` is called with the number of handled and total chunks after each chunk.
+    /// Stops early when `cancel` is set. Returns the number of newly generated chunks.
+    pub fn pregenerate(&self, center: ChunkCoord, radius: i32, parallelism: usize,
+                        progress: impl Fn(usize, usize) + Sync, cancel: &AtomicBool) -> usize {
+        let radius = radius.clamp(0, MAX_PREGEN_RADIUS);
+        let side = 2 * radius + 1;
+        let total = (side * side) as usize;
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let generated = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..parallelism.max(1) {
+                s.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= total || cancel.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        let coord = ChunkCoord {
+                            x: center.x - radius + i as i32 % side,
+                            z: center.z - radius + i as i32 / side
+                        };
+                        if !self.is_loaded(coord) {
+                            self.touch_chunk(coord);
+                            // The regular unloading can drop the chunks out of view any time after this,
+                            // saving them keeps the work when the server stops before that
+                            self.do_with_chunk(coord, |chunk| {
+                                if let Err(e) = self.save_changed(coord, chunk) {
+                                    error!("Failed to save pregenerated chunk {}, {}: {}", coord.x, coord.z, e);
+                                }
+                            });
+                            generated.fetch_add(1, Ordering::Relaxed);
+                        }
+
+                        progress(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+                    }
+                });
+            }
+        });
+
+        generated.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, OnceLock};
+
+    use super::*;
+    use crate::protocol::v47;
+
+    #[test]
+    fn unload_unused_skips_keep_loaded() {
+        let mut map = ChunkMap::new();
+        map.keep_loaded_area(ChunkCoord { x: 0, z: 0 }, 1);
+        map.touch_chunk(ChunkCoord { x: 10, z: 10 });
+        map.touch_chunk(ChunkCoord { x: 11, z: 10 });
+        assert_eq!(map.num_chunks(), 6);
+        map.set_cache_size(0);
+
+        let in_use = HashSet::from([ChunkCoord { x: 11, z: 10 }]);
+        assert_eq!(map.unload_unused(&in_use), 1);
+        assert!(!map.is_loaded(ChunkCoord { x: 10, z: 10 }));
+        assert!(map.is_loaded(ChunkCoord { x: 11, z: 10 }));
+        for x in -1..1 {
+            for z in -1..1 {
+                assert!(map.is_loaded(ChunkCoord { x, z }));
+            }
+        }
+    }
+
+    #[test]
+    fn unused_chunks_stay_cached() {
+        let map = ChunkMap::new();
+        map.touch_chunk(ChunkCoord { x: 3, z: 4 });
+        assert_eq!(map.unload_unused(&HashSet::new()), 0);
+        assert!(map.is_loaded(ChunkCoord { x: 3, z: 4 }));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut map = ChunkMap::new();
+        map.set_cache_size(2);
+        let coords = [ChunkCoord { x: 0, z: 0 }, ChunkCoord { x: 1, z: 0 }, ChunkCoord { x: 2, z: 0 }, ChunkCoord { x: 3, z: 0 }];
+        map.touch_chunk(coords[0]);
+        map.unload_unused(&HashSet::from([coords[0]]));
+
+        map.touch_chunk(coords[1]);
+        map.touch_chunk(coords[2]);
+        // Using the older chunk makes the other one the least recently used
+        map.do_with_chunk(coords[1], |_| ());
+        map.touch_chunk(coords[3]);
+
+        assert_eq!(map.num_chunks(), 3);
+        assert!(map.is_loaded(coords[0]));
+        assert!(map.is_loaded(coords[1]));
+        assert!(!map.is_loaded(coords[2]));
+        assert!(map.is_loaded(coords[3]));
+    }
+
+    /// Storage in memory that remembers which chunks were saved, or fails while `fail` is set
+    #[derive(Clone, Default)]
+    struct TestStorage {
+        chunks: Arc<Mutex<HashMap<ChunkCoord, Compound>>>,
+        saved: Arc<Mutex<Vec<ChunkCoord>>>,
+        fail: Arc<AtomicBool>
+    }
+
+    impl ChunkStorage for TestStorage {
+        fn load(&self, coord: ChunkCoord) -> io::Result<Option<Chunk>> {
+            Ok(self.chunks.lock().unwrap().get(&coord).and_then(Chunk::from_nbt))
+        }
+
+        fn save(&self, coord: ChunkCoord, chunk: &Chunk) -> io::Result<()> {
+            if self.fail.load(Ordering::Relaxed) {
+                return Err(io::Error::other("disk full"));
+            }
+
+            self.chunks.lock().unwrap().insert(coord, chunk.to_nbt(coord));
+            self.saved.lock().unwrap().push(coord);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn evict_saves_changed_chunks() {
+        let storage = TestStorage::default();
+        let mut map = ChunkMap::new();
+        map.set_storage(storage.clone());
+        let (changed, unchanged) = (ChunkCoord { x: 0, z: 0 }, ChunkCoord { x: 1, z: 0 });
+        map.touch_chunk(changed);
+        map.touch_chunk(unchanged);
+        map.save().unwrap();
+        map.do_with_chunk_mut(changed, |chunk| chunk.set_block(Coord::new(1, 40, 2), BlockType::Stone));
+        storage.saved.lock().unwrap().clear();
+
+        // A chunk that fails to save isn't dropped
+        map.set_cache_size(0);
+        storage.fail.store(true, Ordering::Relaxed);
+        assert_eq!(map.unload_unused(&HashSet::new()), 1);
+        assert!(map.is_loaded(changed));
+        assert!(!map.is_loaded(unchanged));
+
+        storage.fail.store(false, Ordering::Relaxed);
+        assert_eq!(map.unload_unused(&HashSet::new()), 1);
+        assert!(!map.is_loaded(changed));
+        assert_eq!(*storage.saved.lock().unwrap(), [changed]);
+    }
+
+    /// Checks that nothing holds the lock on the chunks while one of them is saved
+    struct LockCheckStorage(Arc<OnceLock<Arc<ChunkMap>>>);
+
+    impl ChunkStorage for LockCheckStorage {
+        fn load(&self, _coord: ChunkCoord) -> io::Result<Option<Chunk>> {
+            Ok(None)
+        }
+
+        fn save(&self, _coord: ChunkCoord, _chunk: &Chunk) -> io::Result<()> {
+            assert!(self.0.get().unwrap().chunks.try_write().is_ok());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn evicted_chunks_save_without_the_lock() {
+        let cell = Arc::new(OnceLock::new());
+        let mut map = ChunkMap::new();
+        map.set_storage(LockCheckStorage(cell.clone()));
+        map.set_cache_size(1);
+        let map = Arc::new(map);
+        assert!(cell.set(map.clone()).is_ok());
+
+        let (changed, other) = (ChunkCoord { x: 0, z: 0 }, ChunkCoord { x: 1, z: 0 });
+        map.touch_chunk(changed);
+        map.do_with_chunk_mut(changed, |chunk| chunk.set_block(Coord::new(1, 40, 2), BlockType::Stone));
+        map.touch_chunk(other);
+        assert!(!map.is_loaded(changed));
+        assert!(map.is_loaded(other));
+    }
+
+    #[test]
+    fn loads_saved_chunks() {
+        let mut map = ChunkMap::new();
+        map.set_storage(TestStorage::default());
+        let coord = ChunkCoord { x: -2, z: 5 };
+        map.touch_chunk(coord);
+        map.do_with_chunk_mut(coord, |chunk| chunk.set_block(Coord::new(1, 40, 2), BlockType::Stone));
+        map.set_cache_size(0);
+        assert_eq!(map.unload_unused(&HashSet::new()), 1);
+
+        map.set_cache_size(1);
+        map.touch_chunk(coord);
+        map.do_with_chunk(coord, |chunk| {
+            assert_eq!(chunk.data.get_block(Coord::new(1, 40, 2)), BlockType::Stone);
+            assert!(!chunk.is_dirty());
+        });
+    }
+
+    #[test]
+    fn save_dirty_chunks() {
+        let map = ChunkMap::new();
+        let (first, second) = (ChunkCoord { x: 0, z: 0 }, ChunkCoord { x: 1, z: 0 });
+        map.touch_chunk(first);
+        map.touch_chunk(second);
+        // New chunks haven't been saved yet
+        assert_eq!(map.save_dirty(|_, _| Ok(())).unwrap(), 2);
+        assert_eq!(map.save_dirty(|_, _| Ok(())).unwrap(), 0);
+
+        map.do_with_chunk_mut(second, |chunk| chunk.set_block(Coord::new(1, 40, 2), BlockType::Stone));
+        map.do_with_chunk(second, |chunk| assert!(chunk.is_dirty()));
+        let result = map.save_dirty(|_, _| Err(io::Error::other("disk full")));
+        assert!(result.is_err());
+
+        let mut saved = Vec::new();
+        assert_eq!(map.save_dirty(|coord, _| {
+            saved.push(coord);
+            Ok(())
+        }).unwrap(), 1);
+        assert_eq!(saved, [second]);
+        map.do_with_chunk(second, |chunk| assert!(!chunk.is_dirty()));
+    }
+
+    #[test]
+    fn serialized_data_is_shared() {
+        let map = ChunkMap::new();
+        let coords: Vec<_> = (0..4).map(|x| ChunkCoord { x, z: 0 }).collect();
+        for coord in &coords {
+            map.touch_chunk(*coord);
+        }
+
+        // 50 players loading the same chunks only serialize each of them once
+        let mut serialized: Vec<Arc<[u8]>> = Vec::new();
+        for _ in 0..50 {
+            for coord in &coords {
+                map.do_with_chunk(*coord, |chunk| {
+                    let data = v47::serialized_data(chunk, &SerializeContext::default());
+                    if !serialized.iter().any(|v| Arc::ptr_eq(v, &data)) {
+                        serialized.push(data);
+                    }
+                });
+            }
+        }
+        assert_eq!(serialized.len(), coords.len());
+
+        // Changing a block serializes the chunk again, with the change
+        let pos = Coord::new(1, 40, 2);
+        map.do_with_chunk_mut(coords[0], |chunk| chunk.set_block(pos, BlockType::Stone));
+        map.do_with_chunk(coords[0], |chunk| {
+            let data = v47::serialized_data(chunk, &SerializeContext::default());
+            assert!(!Arc::ptr_eq(&data, &serialized[0]));
+            let mut expected = Vec::new();
+            chunk.serialize_data(&mut expected, &SerializeContext::default()).unwrap();
+            assert_eq!(*data, *expected);
+        });
+    }
+
+    #[test]
+    fn trim_serialized() {
+        let map = ChunkMap::new();
+        let (shared, single) = (ChunkCoord { x: 0, z: 0 }, ChunkCoord { x: 1, z: 0 });
+        map.touch_chunk(shared);
+        map.touch_chunk(single);
+        let send = |coord, times| for _ in 0..times {
+            map.do_with_chunk(coord, |chunk| { v47::serialized_data(chunk, &SerializeContext::default()); });
+        };
+        send(shared, 2);
+        send(single, 1);
+        assert_eq!(map.trim_serialized(), 1);
+
+        // The views are counted again after each trim
+        assert_eq!(map.trim_serialized(), 1);
+        assert_eq!(map.trim_serialized(), 0);
+    }
+
+    #[test]
+    fn zero_radius_keeps_nothing_loaded() {
+        let map = ChunkMap::new();
+        map.keep_loaded_area(ChunkCoord { x: 0, z: 0 }, 0);
+        assert_eq!(map.num_chunks(), 0);
+        assert!(map.keep_loaded_chunks().is_empty());
+    }
+
+    /// Flat generator that counts how many chunks it generated
+    struct CountingGenerator(Arc<AtomicUsize>);
+
+    impl ChunkGenerator for CountingGenerator {
+        fn generate(&self, coord: ChunkCoord) -> Chunk {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            FlatGenerator.generate(coord)
+        }
+    }
+
+    #[test]
+    fn pregenerate() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let map = ChunkMap::with_generator(CountingGenerator(count.clone()));
+        map.touch_chunk(ChunkCoord { x: 5, z: 5 });
+
+        let last = AtomicUsize::new(0);
+        let generated = map.pregenerate(ChunkCoord { x: 5, z: 5 }, 2, 3, |done, total| {
+            assert_eq!(total, 25);
+            last.fetch_max(done, Ordering::Relaxed);
+        }, &AtomicBool::new(false));
+        assert_eq!(generated, 24);
+        assert_eq!(count.load(Ordering::Relaxed), 25);
+        assert_eq!(last.into_inner(), 25);
+        assert!(map.is_loaded(ChunkCoord { x: 3, z: 7 }));
+        assert!(!map.is_loaded(ChunkCoord { x: 2, z: 5 }));
+
+        // Everything already exists the second time around
+        assert_eq!(map.pregenerate(ChunkCoord { x: 5, z: 5 }, 2, 3, |_, _| (), &AtomicBool::new(false)), 0);
+        assert_eq!(count.load(Ordering::Relaxed), 25);
+    }
+
+    #[test]
+    fn pregenerate_cancelled() {
+        let map = ChunkMap::new();
+        assert_eq!(map.pregenerate(ChunkCoord { x: 0, z: 0 }, 4, 2, |_, _| (), &AtomicBool::new(true)), 0);
+        // The area of huge radii doesn't overflow
+        assert_eq!(map.pregenerate(ChunkCoord { x: 0, z: 0 }, i32::MAX, 2, |_, _| (), &AtomicBool::new(true)), 0);
+        assert_eq!(map.num_chunks(), 0);
+    }
+
+    #[test]
+    fn pregenerate_saves_chunks() {
+        let storage = TestStorage::default();
+        let mut map = ChunkMap::new();
+        map.set_storage(storage.clone());
+        assert_eq!(map.pregenerate(ChunkCoord { x: 0, z: 0 }, 1, 2, |_, _| (), &AtomicBool::new(false)), 9);
+        assert_eq!(storage.chunks.lock().unwrap().len(), 9);
+        assert_eq!(map.save().unwrap(), 0);
     }
 }
@@ -0,0 +1,396 @@
+use std::f64::consts::TAU;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::biomes::Biome;
+use crate::storage::chunk::*;
+
+/// Height of the water surface in the generated terrain
+pub const SEA_LEVEL: i32 = 62;
+
+/// Width of the square cells of the same biome, in blocks
+const BIOME_CELL_SIZE: i32 = 64;
+
+/// Distance between the points the terrain height is interpolated between, in blocks
+const HEIGHT_CELL_SIZE: i32 = 16;
+
+/// Lowest and highest surface generated
+const MIN_HEIGHT: i32 = 56;
+const MAX_HEIGHT: i32 = 68;
+
+/// Number of filler blocks (dirt, sandstone) below the top block
+const FILLER_DEPTH: i32 = 3;
+
+/// Highest block carved out by caves, low enough to never reach the surface or lakes
+const MAX_CAVE_Y: i32 = MIN_HEIGHT - 8;
+
+/// Caves are filled with lava up to this height
+const CAVE_LAVA_LEVEL: i32 = 10;
+
+/// One in this many chunks has caves
+const CAVE_CHANCE: u32 = 7;
+
+/// Ore, veins per chunk, blocks per vein and the y range of the veins, the same as vanilla
+const ORES: [(BlockType, u32, u32, i32, i32); 5] = [
+    (BlockType::CoalOre, 20, 17, 0, 128),
+    (BlockType::IronOre, 20, 9, 0, 64),
+    (BlockType::GoldOre, 2, 9, 0, 32),
+    (BlockType::RedstoneOre, 8, 8, 0, 16),
+    (BlockType::DiamondOre, 1, 8, 0, 16)
+];
+
+const BIOMES: [Biome; 5] = [Biome::Plains, Biome::Desert, Biome::Taiga, Biome::Swampland, Biome::IcePlains];
+
+/// Which generator creates the new chunks of a world
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GeneratorType {
+    /// A layer of dirt, the `FLAT` level type
+    Flat,
+    /// Terrain with biomes, caves and ores
+    Biomes
+}
+
+impl GeneratorType {
+    /// Picks the generator of the level type, like vanilla unknown types get the default terrain
+    pub fn from_level_type(level_type: &str) -> Self {
+        if level_type.eq_ignore_ascii_case("FLAT") {
+            GeneratorType::Flat
+        } else {
+            GeneratorType::Biomes
+        }
+    }
+}
+
+/// Creates the contents of chunks that haven't been generated yet
+pub trait ChunkGenerator: Send + Sync {
+    fn generate(&self, coord: ChunkCoord) -> Chunk;
+}
+
+fn empty_chunk(biome: Biome) -> Chunk {
+    Chunk::new(ChunkColumn { sections: Default::default() }, [biome as u8; AREA as usize])
+}
+
+/// Generates a layer of dirt from y 0 to 15
+pub struct FlatGenerator;
+
+impl ChunkGenerator for FlatGenerator {
+    fn generate(&self, _coord: ChunkCoord) -> Chunk {
+        let mut chunk = empty_chunk(Biome::Plains);
+        chunk.data.fill(Coord::new(0, 0, 0), Coord::new(WIDTH - 1, WIDTH - 1, WIDTH - 1), BlockType::Dirt, 0);
+        chunk
+    }
+}
+
+/// Generates rolling terrain with lakes, the surface blocks depend on the biome of the column.
+/// The same seed always generates the same terrain.
+pub struct BiomeGenerator {
+    seed: u64
+}
+
+impl BiomeGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let cell = self.hash(x.div_euclid(BIOME_CELL_SIZE), z.div_euclid(BIOME_CELL_SIZE), 0);
+        BIOMES[(cell % BIOMES.len() as u64) as usize]
+    }
+
+    /// Returns the y of the highest solid block of the column
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let height = self.terrain_height(x, z);
+        // Swamps sink down into the water around them
+        if self.biome_at(x, z) == Biome::Swampland && height >= SEA_LEVEL {
+            let near_water = (-1..=1).any(|dx| (-1..=1).any(|dz| self.terrain_height(x + dx, z + dz) < SEA_LEVEL));
+            if near_water {
+                return height - 1;
+            }
+        }
+
+        height
+    }
+
+    fn terrain_height(&self, x: i32, z: i32) -> i32 {
+        let noise = self.value_noise(x, z, HEIGHT_CELL_SIZE, 1);
+        MIN_HEIGHT + (noise * (MAX_HEIGHT - MIN_HEIGHT + 1) as f64) as i32
+    }
+
+    /// Smoothly interpolated random value in [0, 1)
+    fn value_noise(&self, x: i32, z: i32, cell_size: i32, salt: u64) -> f64 {
+        let (cell_x, cell_z) = (x.div_euclid(cell_size), z.div_euclid(cell_size));
+        let smooth = |v: f64| v * v * (3.0 - 2.0 * v);
+        let fx = smooth(x.rem_euclid(cell_size) as f64 / cell_size as f64);
+        let fz = smooth(z.rem_euclid(cell_size) as f64 / cell_size as f64);
+        let corner = |dx: i32, dz: i32| (self.hash(cell_x + dx, cell_z + dz, salt) >> 11) as f64 / (1u64 << 53) as f64;
+
+        let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * fx;
+        let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * fx;
+        top + (bottom - top) * fz
+    }
+
+    /// Carves worm-like caves through the chunk. Caves stop at the chunk border,
+    /// so generating a chunk never touches its neighbours.
+    fn carve_caves(&self, chunk: &mut Chunk, rng: &mut StdRng) {
+        if rng.gen_range(0..CAVE_CHANCE) != 0 {
+            return;
+        }
+
+        for _ in 0..rng.gen_range(1..=3) {
+            let (mut x, mut y, mut z) = (rng.gen_range(0.0..WIDTH as f64), rng.gen_range(8.0..MAX_CAVE_Y as f64), rng.gen_range(0.0..WIDTH as f64));
+            let (mut yaw, mut pitch): (f64, f64) = (rng.gen_range(0.0..TAU), rng.gen_range(-0.5..0.5));
+            let radius: f64 = rng.gen_range(1.5..3.0);
+            for _ in 0..rng.gen_range(20..60) {
+                if x < 0.0 || x >= WIDTH as f64 || z < 0.0 || z >= WIDTH as f64 {
+                    break;
+                }
+
+                carve_sphere(chunk, x, y, z, radius);
+                x += yaw.cos() * pitch.cos();
+                y += pitch.sin();
+                z += yaw.sin() * pitch.cos();
+                yaw += rng.gen_range(-0.3..0.3);
+                pitch = pitch * 0.7 + rng.gen_range(-0.2..0.2);
+            }
+        }
+    }
+
+    /// Replaces stone with ore veins
+    fn place_ores(&self, chunk: &mut Chunk, rng: &mut StdRng) {
+        for (ore, veins, size, min_y, max_y) in ORES {
+            for _ in 0..veins {
+                let mut pos = Coord::new(rng.gen_range(0..WIDTH), rng.gen_range(min_y..max_y), rng.gen_range(0..WIDTH));
+                for _ in 0..size {
+                    if chunk.data.get_block(pos) == BlockType::Stone {
+                        chunk.data.set_block(pos, ore);
+                    }
+
+                    // Wander to a neighbouring block, staying inside the chunk
+                    let step = if rng.gen() { 1 } else { -1 };
+                    match rng.gen_range(0..3) {
+                        0 => pos.x = (pos.x + step).clamp(0, WIDTH - 1),
+                        1 => pos.y = (pos.y + step).clamp(min_y, max_y - 1),
+                        _ => pos.z = (pos.z + step).clamp(0, WIDTH - 1)
+                    }
+                }
+            }
+        }
+    }
+
+    fn hash(&self, x: i32, z: i32, salt: u64) -> u64 {
+        // SplitMix64 finalizer
+        let mut v = self.seed
+            ^ salt.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (x as i64 as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9)
+            ^ (z as i64 as u64).wrapping_mul(0x94d0_49bb_1331_11eb);
+        v = (v ^ (v >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        v = (v ^ (v >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        v ^ (v >> 31)
+    }
+}
+
+impl ChunkGenerator for BiomeGenerator {
+    fn generate(&self, coord: ChunkCoord) -> Chunk {
+        let mut chunk = empty_chunk(Biome::Plains);
+        for z in 0..WIDTH {
+            for x in 0..WIDTH {
+                let abs = Chunk::rel_to_abs(Coord::new(x, 0, z), coord);
+                let biome = self.biome_at(abs.x, abs.z);
+                let height = self.surface_height(abs.x, abs.z);
+                chunk.biome_map[(z * WIDTH + x) as usize] = biome as u8;
+
+                let (top, filler) = match biome {
+                    Biome::Desert => (BlockType::Sand, BlockType::Sandstone),
+                    _ if height < SEA_LEVEL => (BlockType::Dirt, BlockType::Dirt),
+                    _ => (BlockType::Grass, BlockType::Dirt)
+                };
+
+                let data = &mut chunk.data;
+                data.fill(Coord::new(x, 0, z), Coord::new(x, 0, z), BlockType::Bedrock, 0);
+                data.fill(Coord::new(x, 1, z), Coord::new(x, height - FILLER_DEPTH - 1, z), BlockType::Stone, 0);
+                data.fill(Coord::new(x, height - FILLER_DEPTH, z), Coord::new(x, height - 1, z), filler, 0);
+                data.set_block(Coord::new(x, height, z), top);
+
+                if height < SEA_LEVEL {
+                    data.fill(Coord::new(x, height + 1, z), Coord::new(x, SEA_LEVEL, z), BlockType::Water, 0);
+                    if biome.is_snowy() {
+                        data.set_block(Coord::new(x, SEA_LEVEL, z), BlockType::Ice);
+                    }
+                } else if biome.is_snowy() {
+                    data.set_block(Coord::new(x, height + 1, z), BlockType::SnowLayer);
+                }
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.hash(coord.x, coord.z, 2));
+        self.carve_caves(&mut chunk, &mut rng);
+        self.place_ores(&mut chunk, &mut rng);
+        chunk
+    }
+}
+
+fn carve_sphere(chunk: &mut Chunk, x: f64, y: f64, z: f64, radius: f64) {
+    let min = |v: f64| (v - radius).floor() as i32;
+    let max = |v: f64| (v + radius).ceil() as i32;
+    for block_y in min(y).max(1)..=max(y).min(MAX_CAVE_Y) {
+        for block_z in min(z).max(0)..=max(z).min(WIDTH - 1) {
+            for block_x in min(x).max(0)..=max(x).min(WIDTH - 1) {
+                let (dx, dy, dz) = (block_x as f64 + 0.5 - x, block_y as f64 + 0.5 - y, block_z as f64 + 0.5 - z);
+                if dx * dx + dy * dy + dz * dz > radius * radius {
+                    continue;
+                }
+
+                let pos = Coord::new(block_x, block_y, block_z);
+                if matches!(chunk.data.get_block(pos), BlockType::Bedrock | BlockType::Water) {
+                    continue;
+                }
+
+                let fill = if block_y <= CAVE_LAVA_LEVEL { BlockType::Lava } else { BlockType::Air };
+                chunk.data.set_block(pos, fill);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use num_traits::FromPrimitive;
+
+    use super::*;
+
+    /// Returns a few columns of each biome found around the origin
+    fn sample_columns(generator: &BiomeGenerator) -> HashMap<u8, Vec<Coord<i32>>> {
+        let mut columns: HashMap<u8, Vec<Coord<i32>>> = HashMap::new();
+        for x in (-1024..1024).step_by(7) {
+            for z in (-1024..1024).step_by(7) {
+                let samples = columns.entry(generator.biome_at(x, z) as u8).or_default();
+                if samples.len() < 5 {
+                    samples.push(Coord::new(x, 0, z));
+                }
+            }
+        }
+
+        columns
+    }
+
+    /// Returns the block at the absolute position and the biome of its column
+    fn block_at(generator: &BiomeGenerator, pos: Coord<i32>) -> (BlockType, Biome) {
+        let coord = ChunkCoord::from_block_pos(pos);
+        let chunk = generator.generate(coord);
+        let rel_pos = Chunk::abs_to_rel(pos, coord);
+        let biome = chunk.biome_map[(rel_pos.z * WIDTH + rel_pos.x) as usize];
+        (chunk.data.get_block(rel_pos), Biome::from_u8(biome).unwrap())
+    }
+
+    #[test]
+    fn surface_depends_on_biome() {
+        let generator = BiomeGenerator::new(12345);
+        let columns = sample_columns(&generator);
+        assert_eq!(columns.len(), BIOMES.len());
+
+        for (biome, samples) in columns {
+            let biome = Biome::from_u8(biome).unwrap();
+            for column in samples {
+                let height = generator.surface_height(column.x, column.z);
+                let (top, map_biome) = block_at(&generator, Coord::new(column.x, height, column.z));
+                let (filler, _) = block_at(&generator, Coord::new(column.x, height - 1, column.z));
+                let (above, _) = block_at(&generator, Coord::new(column.x, height + 1, column.z));
+                let (water_surface, _) = block_at(&generator, Coord::new(column.x, SEA_LEVEL, column.z));
+                assert_eq!(map_biome, biome);
+
+                match biome {
+                    Biome::Desert => assert_eq!((top, filler), (BlockType::Sand, BlockType::Sandstone)),
+                    _ if height < SEA_LEVEL => assert_eq!((top, filler), (BlockType::Dirt, BlockType::Dirt)),
+                    _ => assert_eq!((top, filler), (BlockType::Grass, BlockType::Dirt))
+                }
+
+                if height < SEA_LEVEL {
+                    let expected = if biome.is_snowy() { BlockType::Ice } else { BlockType::Water };
+                    assert_eq!(water_surface, expected);
+                } else {
+                    let expected = if biome.is_snowy() { BlockType::SnowLayer } else { BlockType::Air };
+                    assert_eq!(above, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn swamps_sink_near_water() {
+        let generator = BiomeGenerator::new(12345);
+        let mut sunk = 0;
+        for x in -256..256 {
+            for z in -256..256 {
+                let height = generator.terrain_height(x, z);
+                if generator.biome_at(x, z) != Biome::Swampland || height < SEA_LEVEL {
+                    continue;
+                }
+
+                let near_water = (-1..=1).any(|dx| (-1..=1).any(|dz| generator.terrain_height(x + dx, z + dz) < SEA_LEVEL));
+                let expected = if near_water { height - 1 } else { height };
+                assert_eq!(generator.surface_height(x, z), expected);
+                if near_water {
+                    sunk += 1;
+                }
+            }
+        }
+
+        assert!(sunk > 0);
+    }
+
+    #[test]
+    fn ores_and_caves() {
+        let generator = BiomeGenerator::new(12345);
+        let mut ore_blocks: HashMap<u8, usize> = HashMap::new();
+        let mut caves = 0;
+        for i in 0..100 {
+            let chunk = generator.generate(ChunkCoord { x: i % 10, z: i / 10 });
+            let mut air = false;
+            for y in 0..MIN_HEIGHT {
+                for z in 0..WIDTH {
+                    for x in 0..WIDTH {
+                        let block_type = chunk.data.get_block(Coord::new(x, y, z));
+                        if let Some((_, _, _, min_y, max_y)) = ORES.iter().find(|v| v.0 == block_type) {
+                            assert!((*min_y..*max_y).contains(&y));
+                            *ore_blocks.entry(block_type as u8).or_default() += 1;
+                        }
+
+                        air |= block_type == BlockType::Air && y <= MAX_CAVE_Y;
+                    }
+                }
+            }
+
+            if air {
+                caves += 1;
+            }
+        }
+
+        // Every vein places at least one block, at most its size
+        for (ore, veins, size, _, _) in ORES {
+            let average = ore_blocks[&(ore as u8)] as f64 / 100.0;
+            assert!(average >= veins as f64 * 0.9 && average <= (veins * size) as f64, "{:?}: {}", ore, average);
+        }
+
+        // One in 7 chunks has caves
+        assert!((5..=30).contains(&caves), "{}", caves);
+    }
+
+    #[test]
+    fn same_seed_same_terrain() {
+        let coord = ChunkCoord { x: 7, z: -3 };
+        let first = BiomeGenerator::new(42).generate(coord);
+        let second = BiomeGenerator::new(42).generate(coord);
+        let other = BiomeGenerator::new(43).generate(coord);
+
+        let (mut first_data, mut second_data, mut other_data) = (Vec::new(), Vec::new(), Vec::new());
+        first.serialize_data(&mut first_data, &SerializeContext::default()).unwrap();
+        second.serialize_data(&mut second_data, &SerializeContext::default()).unwrap();
+        other.serialize_data(&mut other_data, &SerializeContext::default()).unwrap();
+        assert_eq!(first_data, second_data);
+        assert_ne!(first_data, other_data);
+        assert_eq!(first.biome_map, second.biome_map);
+    }
+}
@@ -1,12 +1,19 @@
 pub mod section;
 pub mod chunk_map;
+pub mod generator;
 
+use std::collections::HashMap;
 use std::io::{Result, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use num_traits::FromPrimitive;
+use siderite_nbt::{Compound, Tag};
 
 use crate::coord::{ChunkCoord, Coord};
 use crate::blocks::BlockType;
+use crate::storage::world::{Dimension, MAX_LIGHT};
+use crate::tile_entities::TileEntity;
 
 use self::section::Section;
 
@@ -25,9 +32,40 @@ pub const SECTION_COUNT: usize = 16;
 /// Number of blocks in one section
 pub const SECTION_BLOCK_COUNT: usize = (AREA * WIDTH) as usize;
 
+/// How a chunk is serialized for the client
+#[derive(Copy, Clone, Debug)]
+pub struct SerializeContext<'a> {
+    pub dimension: Dimension,
+    /// False for dimensions without sky light (the Nether and the End),
+    /// the sky light arrays are left out in that case
+    pub sky_light: bool,
+    /// Previously serialized data of the chunk, without the size,
+    /// written as is instead of serializing the chunk again
+    pub cache: Option<&'a [u8]>
+}
+
+impl SerializeContext<'_> {
+    pub fn new(dimension: Dimension) -> Self {
+        Self {
+            dimension,
+            sky_light: dimension == Dimension::Overworld,
+            cache: None
+        }
+    }
+}
+
+impl Default for SerializeContext<'_> {
+    fn default() -> Self {
+        Self::new(Dimension::Overworld)
+    }
+}
+
 pub trait SerializeChunk {
-    fn serialized_size(&self) -> usize;
-    fn serialize<W: Write>(&self, w: W) -> Result<()>;
+    fn serialized_size(&self, context: &SerializeContext) -> usize;
+    /// Writes the size followed by the data
+    fn serialize<W: Write>(&self, w: W, context: &SerializeContext) -> Result<()>;
+    /// Writes the data without the size, as used in Map Chunk Bulk
+    fn serialize_data<W: Write>(&self, w: W, context: &SerializeContext) -> Result<()>;
 }
 
 #[derive(Clone, Debug)]
@@ -53,6 +91,33 @@ impl ChunkColumn {
         self.sections.iter().filter(|x| x.is_some()).count()
     }
 
+    /// Lights the column on its own: full sky light down to the first opaque block
+    /// and block light only where a block gives off light, without spreading it sideways
+    pub fn compute_light(&mut self) {
+        let mut open = [true; AREA as usize];
+        let mut block_light = [0u8; SECTION_BLOCK_COUNT];
+        let mut sky_light = [0u8; SECTION_BLOCK_COUNT];
+        for section in self.sections.iter_mut().rev() {
+            let section = match section {
+                Some(v) => v,
+                None => continue
+            };
+
+            for index in (0..SECTION_BLOCK_COUNT).rev() {
+                let block_type = BlockType::from_u8(section.block_types[index]).unwrap();
+                let column = index % AREA as usize;
+                if block_type.is_opaque() {
+                    open[column] = false;
+                }
+
+                sky_light[index] = if open[column] { MAX_LIGHT } else { 0 };
+                block_light[index] = block_type.light_emission();
+            }
+
+            section.set_light(&block_light, &sky_light);
+        }
+    }
+
     pub fn get_block(&self, rel_pos: Coord<i32>) -> BlockType {
         let (section, index) = ChunkColumn::get_indices_from_rel_pos(rel_pos);
 
@@ -82,6 +147,10 @@ impl ChunkColumn {
             Some(v) => v.block_types[index] = block_type as u8,
             None => panic!("Dunno")
         }
+
+        if block_type == BlockType::Air {
+            self.prune_section(section);
+        }
     }
 
     pub fn get_meta(&self, rel_pos: Coord<i32>) -> u8 {
@@ -116,6 +185,90 @@ impl ChunkColumn {
             }
             None => panic!("Dunno")
         }
+
+        if (block_meta & 0xf) == 0x00 {
+            self.prune_section(section);
+        }
+    }
+
+    /// Sets all blocks in the cuboid from `min` to `max` (inclusive), both relative to the chunk
+    pub fn fill(&mut self, min: Coord<i32>, max: Coord<i32>, block_type: BlockType, block_meta: u8) {
+        debug_assert!(Chunk::is_valid_rel_pos(min) && Chunk::is_valid_rel_pos(max));
+
+        let block_meta = block_meta & 0x0f;
+        let full_layer = min.x == 0 && max.x == WIDTH - 1 && min.z == 0 && max.z == WIDTH - 1;
+        for section in (min.y / WIDTH) as usize..=(max.y / WIDTH) as usize {
+            if self.sections[section].is_none() {
+                if block_type == BlockType::Air && block_meta == 0 {
+                    continue;
+                }
+
+                self.sections[section] = Some(Box::new(Section {
+                    block_types: [0; SECTION_BLOCK_COUNT],
+                    block_metas: [0; SECTION_BLOCK_COUNT / 2],
+                    block_light: [0; SECTION_BLOCK_COUNT / 2],
+                    block_sky_light: [0xff; SECTION_BLOCK_COUNT / 2]
+                }));
+            }
+
+            let v = self.sections[section].as_mut().unwrap();
+            let section_y = section as i32 * WIDTH;
+            let min_y = (min.y - section_y).max(0);
+            let max_y = (max.y - section_y).min(WIDTH - 1);
+
+            if full_layer {
+                // The layers are contiguous, fill them all at once
+                let start = (min_y * AREA) as usize;
+                let end = ((max_y + 1) * AREA) as usize;
+                v.block_types[start..end].fill(block_type as u8);
+                v.block_metas[(start / 2)..(end / 2)].fill(block_meta | (block_meta << 4));
+                continue;
+            }
+
+            for y in min_y..=max_y {
+                for z in min.z..=max.z {
+                    let row = (z * WIDTH + y * AREA) as usize;
+                    v.block_types[(row + min.x as usize)..=(row + max.x as usize)].fill(block_type as u8);
+                    for x in min.x..=max.x {
+                        let index = row + x as usize;
+                        v.block_metas[index / 2] = (v.block_metas[index / 2] & (0xf0 >> ((index & 1) * 4)))
+                                                    | block_meta << ((index & 1) * 4);
+                    }
+                }
+            }
+        }
+
+        if block_type == BlockType::Air && block_meta == 0 {
+            for section in (min.y / WIDTH) as usize..=(max.y / WIDTH) as usize {
+                self.prune_section(section);
+            }
+        }
+    }
+
+    /// Sets the meta of every block in a 16^3 section, one meta per byte in block index order
+    pub fn set_section_metas(&mut self, section: usize, metas: &[u8; SECTION_BLOCK_COUNT]) {
+        if self.sections[section].is_none() {
+            if metas.iter().all(|&v| (v & 0xf) == 0x00) {
+                return;
+            }
+
+            self.sections[section] = Some(Box::new(Section {
+                block_types: [0; SECTION_BLOCK_COUNT],
+                block_metas: [0; SECTION_BLOCK_COUNT / 2],
+                block_light: [0; SECTION_BLOCK_COUNT / 2],
+                block_sky_light: [0xff; SECTION_BLOCK_COUNT / 2]
+            }));
+        }
+
+        self.sections[section].as_mut().unwrap().set_metas(metas);
+        self.prune_section(section);
+    }
+
+    /// Frees the section if it only contains air, so it isn't sent to clients
+    fn prune_section(&mut self, section: usize) {
+        if self.sections[section].as_ref().is_some_and(|v| v.is_empty()) {
+            self.sections[section] = None;
+        }
     }
 
     pub fn get_block_type_meta(&self, rel_pos: Coord<i32>) -> (BlockType, u8) {
@@ -129,18 +282,175 @@ impl ChunkColumn {
     }
 
     const fn get_indices_from_rel_pos(rel_pos: Coord<i32>) -> (usize, usize) {
-        assert!(!Chunk::is_valid_rel_pos(rel_pos));
+        assert!(Chunk::is_valid_rel_pos(rel_pos));
 
-        ((rel_pos.y / WIDTH) as usize, (rel_pos.x + rel_pos.z * WIDTH + rel_pos.y * AREA) as usize)
+        ((rel_pos.y / WIDTH) as usize, (rel_pos.x + rel_pos.z * WIDTH + (rel_pos.y % WIDTH) * AREA) as usize)
     }
 }
 
 pub struct Chunk {
     pub data: ChunkColumn,
-    pub biome_map: [u8; AREA as usize]
+    pub biome_map: [u8; AREA as usize],
+    /// Tile entities in this chunk by absolute block position
+    pub tile_entities: HashMap<Coord<i32>, TileEntity>,
+    /// Set when the chunk changed since it was last saved.
+    /// Atomic so saving can clear it while other threads read the chunk.
+    dirty: AtomicBool,
+    serialize_cache: Mutex<SerializeCache>
+}
+
+/// Serialized data of a chunk, shared by every player the chunk is sent to until a block changes
+#[derive(Default)]
+struct SerializeCache {
+    /// The data without the size, by protocol version and whether it includes sky light
+    data: HashMap<(i32, bool), Arc<[u8]>>,
+    /// Number of times the chunk was sent since the cache was last trimmed
+    views: u32
 }
 
 impl Chunk {
+    /// Returns a chunk without tile entities, it's dirty as it hasn't been saved yet
+    pub fn new(data: ChunkColumn, biome_map: [u8; AREA as usize]) -> Self {
+        Self {
+            data,
+            biome_map,
+            tile_entities: HashMap::new(),
+            dirty: AtomicBool::new(true),
+            serialize_cache: Mutex::default()
+        }
+    }
+
+    pub fn set_block(&mut self, rel_pos: Coord<i32>, block_type: BlockType) {
+        self.data.set_block(rel_pos, block_type);
+        self.mark_dirty();
+        self.invalidate_serialized();
+    }
+
+    pub fn set_meta(&mut self, rel_pos: Coord<i32>, meta: u8) {
+        self.data.set_meta(rel_pos, meta);
+        self.mark_dirty();
+        self.invalidate_serialized();
+    }
+
+    /// Returns the data of the chunk as sent to clients with the protocol version, without the size.
+    /// `serialize` writes it when it isn't cached for the version and sky light yet.
+    /// The chunk is only serialized again after a block changed through `set_block` or `set_meta`,
+    /// changes made to `data` directly aren't noticed.
+    pub fn serialized_data(&self, protocol_version: i32, sky_light: bool, serialize: impl FnOnce(&mut Vec<u8>)) -> Arc<[u8]> {
+        let mut cache = self.serialize_cache.lock().unwrap();
+        cache.views += 1;
+        if let Some(data) = cache.data.get(&(protocol_version, sky_light)) {
+            return data.clone();
+        }
+
+        let mut data = Vec::new();
+        serialize(&mut data);
+        let data: Arc<[u8]> = data.into();
+        cache.data.insert((protocol_version, sky_light), data.clone());
+        data
+    }
+
+    /// Drops the serialized data unless the chunk was sent at least twice since the last trim,
+    /// so only chunks several players load stay in memory twice. Returns true if the data was dropped.
+    pub fn trim_serialized(&self) -> bool {
+        let mut cache = self.serialize_cache.lock().unwrap();
+        let trimmed = cache.views < 2 && !cache.data.is_empty();
+        if trimmed {
+            cache.data.clear();
+        }
+        cache.views = 0;
+        trimmed
+    }
+
+    fn invalidate_serialized(&mut self) {
+        self.serialize_cache.get_mut().unwrap().data.clear();
+    }
+
+    /// Sets or removes the tile entity at the absolute position
+    pub fn set_tile_entity(&mut self, pos: Coord<i32>, tile_entity: Option<TileEntity>) {
+        match tile_entity {
+            Some(v) => self.tile_entities.insert(pos, v),
+            None => self.tile_entities.remove(&pos)
+        };
+        self.mark_dirty();
+    }
+
+    /// Returns true if the chunk or the contents of one of its containers changed since it was last saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+            || self.tile_entities.values().filter_map(TileEntity::items).any(|v| v.lock().is_unsaved())
+    }
+
+    pub fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Marks the chunk and its containers as saved
+    pub fn clear_dirty(&self) {
+        self.dirty.store(false, Ordering::Release);
+        for items in self.tile_entities.values().filter_map(TileEntity::items) {
+            items.lock().mark_saved();
+        }
+    }
+
+    /// Returns the Level compound of the chunk in the vanilla chunk format, empty sections are left out
+    pub fn to_nbt(&self, coord: ChunkCoord) -> Compound {
+        let bytes = |v: &[u8]| Tag::ByteArray(v.iter().map(|&b| b as i8).collect());
+        let sections = self.data.sections.iter().enumerate()
+            .filter_map(|(y, section)| {
+                let section = section.as_ref()?;
+                let mut nbt = Compound::new();
+                nbt.insert("Y", Tag::Byte(y as i8));
+                nbt.insert("Blocks", bytes(&section.block_types));
+                nbt.insert("Data", bytes(&section.block_metas));
+                nbt.insert("BlockLight", bytes(&section.block_light));
+                nbt.insert("SkyLight", bytes(&section.block_sky_light));
+                Some(Tag::Compound(nbt))
+            })
+            .collect();
+
+        let mut level = Compound::new();
+        level.insert("xPos", Tag::Int(coord.x));
+        level.insert("zPos", Tag::Int(coord.z));
+        level.insert("Sections", Tag::List(sections));
+        level.insert("Biomes", bytes(&self.biome_map));
+        level.insert("TileEntities", Tag::List(self.tile_entities.iter().map(|(pos, v)| Tag::Compound(v.to_nbt(*pos))).collect()));
+        level
+    }
+
+    /// Reads the Level compound written by `to_nbt`, the chunk isn't dirty.
+    /// Returns None if a section or the biomes don't have the right size or there are unknown blocks.
+    pub fn from_nbt(level: &Compound) -> Option<Self> {
+        fn bytes<const N: usize>(tag: Option<&Tag>) -> Option<[u8; N]> {
+            let bytes = tag?.as_byte_array()?;
+            (bytes.len() == N).then(|| std::array::from_fn(|i| bytes[i] as u8))
+        }
+
+        let mut data = ChunkColumn { sections: Default::default() };
+        for section in level.get("Sections").and_then(Tag::as_list).unwrap_or_default() {
+            let section = section.as_compound()?;
+            let y = usize::try_from(section.get("Y")?.as_byte()?).ok().filter(|&v| v < SECTION_COUNT)?;
+            let block_types: [u8; SECTION_BLOCK_COUNT] = bytes(section.get("Blocks"))?;
+            if block_types.iter().any(|&v| BlockType::from_u8(v).is_none()) {
+                return None;
+            }
+
+            data.sections[y] = Some(Box::new(Section {
+                block_types,
+                block_metas: bytes(section.get("Data"))?,
+                block_light: bytes(section.get("BlockLight"))?,
+                block_sky_light: bytes(section.get("SkyLight"))?
+            }));
+        }
+
+        let mut chunk = Chunk::new(data, bytes(level.get("Biomes"))?);
+        chunk.tile_entities = level.get("TileEntities").and_then(Tag::as_list).unwrap_or_default().iter()
+            .filter_map(|v| TileEntity::from_nbt(v.as_compound()?))
+            .collect();
+        chunk.clear_dirty();
+        Some(chunk)
+    }
+
     #[inline]
     pub const fn abs_to_rel(pos: Coord<i32>, chunk_coord: ChunkCoord) -> Coord<i32> {
         Coord {
@@ -176,3 +486,143 @@ impl Chunk {
             && Chunk::is_valid_width(rel_pos.z)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::ItemStack;
+
+    fn empty_column() -> ChunkColumn {
+        ChunkColumn {
+            sections: Default::default()
+        }
+    }
+
+    #[test]
+    fn compute_light_stops_at_opaque_blocks() {
+        let mut column = empty_column();
+        column.set_block(Coord::new(2, 40, 3), BlockType::Stone);
+        column.set_block(Coord::new(5, 40, 5), BlockType::Glass);
+        column.set_block(Coord::new(7, 10, 7), BlockType::Glowstone);
+        column.compute_light();
+
+        let sky_light = |pos: Coord<i32>| {
+            let (section, index) = ChunkColumn::get_indices_from_rel_pos(pos);
+            column.sections[section].as_ref().unwrap().block_sky_light[index / 2] >> ((index & 1) * 4) & 0x0f
+        };
+        assert_eq!(sky_light(Coord::new(2, 41, 3)), MAX_LIGHT);
+        assert_eq!(sky_light(Coord::new(2, 40, 3)), 0);
+        assert_eq!(sky_light(Coord::new(2, 33, 3)), 0);
+        assert_eq!(sky_light(Coord::new(5, 39, 5)), MAX_LIGHT);
+        assert_eq!(sky_light(Coord::new(7, 9, 7)), 0);
+
+        let (section, index) = ChunkColumn::get_indices_from_rel_pos(Coord::new(7, 10, 7));
+        let block_light = column.sections[section].as_ref().unwrap().block_light[index / 2] >> ((index & 1) * 4) & 0x0f;
+        assert_eq!(block_light, 15);
+    }
+
+    #[test]
+    fn clearing_section_clears_bitmask() {
+        let mut column = empty_column();
+        for x in 0..WIDTH {
+            for y in 32..34 {
+                column.set_block(Coord::new(x, y, 3), BlockType::Stone);
+                column.set_meta(Coord::new(x, y, 3), 2);
+            }
+        }
+
+        assert_eq!(column.get_primary_bit_mask(), 0b100);
+
+        for x in 0..WIDTH {
+            for y in 32..34 {
+                column.set_block(Coord::new(x, y, 3), BlockType::Air);
+            }
+        }
+
+        // There's still meta left
+        assert_eq!(column.get_primary_bit_mask(), 0b100);
+
+        for x in 0..WIDTH {
+            for y in 32..34 {
+                column.set_meta(Coord::new(x, y, 3), 0);
+            }
+        }
+
+        assert_eq!(column.get_primary_bit_mask(), 0);
+        assert_eq!(column.get_num_sections(), 0);
+    }
+
+    #[test]
+    fn fill() {
+        let mut column = empty_column();
+        column.fill(Coord::new(0, 30, 0), Coord::new(15, 33, 15), BlockType::Stone, 1);
+        assert_eq!(column.get_primary_bit_mask(), 0b110);
+        for pos in [Coord::new(0, 30, 0), Coord::new(15, 33, 15), Coord::new(7, 32, 9)] {
+            assert_eq!(column.get_block_type_meta(pos), (BlockType::Stone, 1));
+        }
+
+        for pos in [Coord::new(0, 29, 0), Coord::new(15, 34, 15)] {
+            assert_eq!(column.get_block_type_meta(pos), (BlockType::Air, 0));
+        }
+
+        column.fill(Coord::new(3, 31, 5), Coord::new(4, 31, 6), BlockType::Dirt, 0);
+        for pos in [Coord::new(3, 31, 5), Coord::new(4, 31, 6)] {
+            assert_eq!(column.get_block_type_meta(pos), (BlockType::Dirt, 0));
+        }
+
+        for pos in [Coord::new(2, 31, 5), Coord::new(5, 31, 6), Coord::new(3, 31, 4), Coord::new(4, 31, 7), Coord::new(3, 30, 5), Coord::new(3, 32, 5)] {
+            assert_eq!(column.get_block_type_meta(pos), (BlockType::Stone, 1));
+        }
+
+        column.fill(Coord::new(0, 0, 0), Coord::new(15, 255, 15), BlockType::Air, 0);
+        assert_eq!(column.get_primary_bit_mask(), 0);
+    }
+
+    #[test]
+    fn set_section_metas() {
+        let mut column = empty_column();
+        let metas = std::array::from_fn(|i| (i % 16) as u8);
+        column.set_section_metas(1, &metas);
+        assert_eq!(column.get_primary_bit_mask(), 0b10);
+        assert_eq!(column.get_meta(Coord::new(5, 16, 0)), 5);
+        assert_eq!(column.get_meta(Coord::new(15, 31, 15)), 15);
+
+        column.set_section_metas(1, &[0u8; SECTION_BLOCK_COUNT]);
+        assert_eq!(column.get_primary_bit_mask(), 0);
+    }
+
+    #[test]
+    fn nbt_round_trip() {
+        let coord = ChunkCoord { x: 3, z: -2 };
+        let mut chunk = Chunk::new(empty_column(), [4; AREA as usize]);
+        chunk.set_block(Coord::new(1, 40, 2), BlockType::Chest);
+        chunk.set_meta(Coord::new(1, 40, 2), 3);
+        let chest = TileEntity::for_container(BlockType::Chest).unwrap();
+        chest.items().unwrap().lock().set_slot(5, Some(ItemStack::new(1, 12, 0)));
+        chunk.set_tile_entity(Coord::new(49, 40, -30), Some(chest));
+        chunk.set_tile_entity(Coord::new(50, 41, -30), Some(TileEntity::empty_sign()));
+
+        let loaded = Chunk::from_nbt(&chunk.to_nbt(coord)).unwrap();
+        assert!(!loaded.is_dirty());
+        assert_eq!(loaded.data.get_block_type_meta(Coord::new(1, 40, 2)), (BlockType::Chest, 3));
+        assert_eq!(loaded.data.get_primary_bit_mask(), chunk.data.get_primary_bit_mask());
+        assert_eq!(loaded.biome_map, chunk.biome_map);
+        let items = loaded.tile_entities[&Coord::new(49, 40, -30)].items().unwrap().lock();
+        assert_eq!(items.get_slot(5), Some(&ItemStack::new(1, 12, 0)));
+        assert_eq!(loaded.tile_entities[&Coord::new(50, 41, -30)], TileEntity::empty_sign());
+    }
+
+    #[test]
+    fn container_changes_make_chunk_dirty() {
+        let mut chunk = Chunk::new(empty_column(), [1; AREA as usize]);
+        let chest = TileEntity::for_container(BlockType::Chest).unwrap();
+        chunk.set_tile_entity(Coord::new(1, 40, 2), Some(chest.clone()));
+        chunk.clear_dirty();
+        assert!(!chunk.is_dirty());
+
+        chest.items().unwrap().lock().set_slot(0, Some(ItemStack::new(1, 1, 0)));
+        assert!(chunk.is_dirty());
+        chunk.clear_dirty();
+        assert!(!chunk.is_dirty());
+    }
+}
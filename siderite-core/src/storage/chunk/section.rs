@@ -1,3 +1,11 @@
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+use std::mem::size_of;
+
 use super::*;
 
 #[derive(Clone, Debug)]
@@ -8,3 +16,277 @@ pub struct Section {
     pub block_light: [u8; SECTION_BLOCK_COUNT / 2],
     pub block_sky_light: [u8; SECTION_BLOCK_COUNT / 2],
 }
+
+impl Section {
+    /// Returns true if the section contains only air with zero meta
+    pub fn is_empty(&self) -> bool {
+        self.block_types.iter().all(|&v| v == 0) && self.block_metas.iter().all(|&v| v == 0)
+    }
+
+    /// Sets the meta of every block in the section, one meta per byte in block index order.
+    /// Only the low nibble of each meta is used.
+    pub fn set_metas(&mut self, metas: &[u8; SECTION_BLOCK_COUNT]) {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return unsafe { pack_metas_avx2(metas, &mut self.block_metas) };
+            }
+
+            if is_x86_feature_detected!("sse2") {
+                return unsafe { pack_metas_sse2(metas, &mut self.block_metas) };
+            }
+        }
+
+        pack_metas_fallback(metas, &mut self.block_metas)
+    }
+
+    /// Sets the block and sky light of every block in the section, one light level per byte in block index order.
+    /// Only the low nibble of each level is used.
+    pub fn set_light(&mut self, block_light: &[u8; SECTION_BLOCK_COUNT], sky_light: &[u8; SECTION_BLOCK_COUNT]) {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return unsafe { pack_light_avx2(block_light, sky_light, &mut self.block_light, &mut self.block_sky_light) };
+            }
+
+            if is_x86_feature_detected!("sse2") {
+                return unsafe { pack_light_sse2(block_light, sky_light, &mut self.block_light, &mut self.block_sky_light) };
+            }
+        }
+
+        // NEON is always there on aarch64
+        #[cfg(target_arch = "aarch64")]
+        unsafe { pack_light_neon(block_light, sky_light, &mut self.block_light, &mut self.block_sky_light); }
+        #[cfg(not(target_arch = "aarch64"))]
+        pack_light_fallback(block_light, sky_light, &mut self.block_light, &mut self.block_sky_light);
+    }
+}
+
+fn pack_metas_fallback(metas: &[u8; SECTION_BLOCK_COUNT], out: &mut [u8; SECTION_BLOCK_COUNT / 2]) {
+    for (i, v) in out.iter_mut().enumerate() {
+        *v = (metas[i * 2] & 0x0f) | (metas[i * 2 + 1] << 4);
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn pack_metas_sse2(metas: &[u8; SECTION_BLOCK_COUNT], out: &mut [u8; SECTION_BLOCK_COUNT / 2]) {
+    const VECTOR_SIZE: usize = size_of::<__m128i>();
+
+    let low_mask = _mm_set1_epi16(0x000f);
+    let high_mask = _mm_set1_epi16(0x00f0);
+
+    for i in 0..(SECTION_BLOCK_COUNT / (2 * VECTOR_SIZE)) {
+        let in1 = _mm_loadu_si128(metas[i * 2 * VECTOR_SIZE..].as_ptr().cast());
+        let in2 = _mm_loadu_si128(metas[i * 2 * VECTOR_SIZE + VECTOR_SIZE..].as_ptr().cast());
+
+        // Each 16-bit lane holds the metas of 2 blocks, move the second one into the high nibble of the low byte
+        let packed1 = _mm_or_si128(_mm_and_si128(in1, low_mask), _mm_and_si128(_mm_srli_epi16::<4>(in1), high_mask));
+        let packed2 = _mm_or_si128(_mm_and_si128(in2, low_mask), _mm_and_si128(_mm_srli_epi16::<4>(in2), high_mask));
+
+        _mm_storeu_si128(out[i * VECTOR_SIZE..].as_mut_ptr().cast(), _mm_packus_epi16(packed1, packed2));
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn pack_metas_avx2(metas: &[u8; SECTION_BLOCK_COUNT], out: &mut [u8; SECTION_BLOCK_COUNT / 2]) {
+    const VECTOR_SIZE: usize = size_of::<__m256i>();
+
+    let low_mask = _mm256_set1_epi16(0x000f);
+    let high_mask = _mm256_set1_epi16(0x00f0);
+
+    for i in 0..(SECTION_BLOCK_COUNT / (2 * VECTOR_SIZE)) {
+        let in1 = _mm256_loadu_si256(metas[i * 2 * VECTOR_SIZE..].as_ptr().cast());
+        let in2 = _mm256_loadu_si256(metas[i * 2 * VECTOR_SIZE + VECTOR_SIZE..].as_ptr().cast());
+
+        let packed1 = _mm256_or_si256(_mm256_and_si256(in1, low_mask), _mm256_and_si256(_mm256_srli_epi16::<4>(in1), high_mask));
+        let packed2 = _mm256_or_si256(_mm256_and_si256(in2, low_mask), _mm256_and_si256(_mm256_srli_epi16::<4>(in2), high_mask));
+
+        // Packing works within 128-bit lanes, put them back in order
+        let packed = _mm256_permute4x64_epi64::<0b11011000>(_mm256_packus_epi16(packed1, packed2));
+        _mm256_storeu_si256(out[i * VECTOR_SIZE..].as_mut_ptr().cast(), packed);
+    }
+}
+
+#[cfg_attr(target_arch = "aarch64", allow(dead_code))]
+fn pack_light_fallback(block_light: &[u8; SECTION_BLOCK_COUNT], sky_light: &[u8; SECTION_BLOCK_COUNT],
+        block_out: &mut [u8; SECTION_BLOCK_COUNT / 2], sky_out: &mut [u8; SECTION_BLOCK_COUNT / 2]) {
+    for (light, out) in [(block_light, block_out), (sky_light, sky_out)] {
+        for (i, v) in out.iter_mut().enumerate() {
+            *v = (light[i * 2] & 0x0f) | (light[i * 2 + 1] << 4);
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn pack_light_sse2(block_light: &[u8; SECTION_BLOCK_COUNT], sky_light: &[u8; SECTION_BLOCK_COUNT],
+        block_out: &mut [u8; SECTION_BLOCK_COUNT / 2], sky_out: &mut [u8; SECTION_BLOCK_COUNT / 2]) {
+    const VECTOR_SIZE: usize = size_of::<__m128i>();
+
+    let low_mask = _mm_set1_epi16(0x000f);
+    let high_mask = _mm_set1_epi16(0x00f0);
+
+    for (light, out) in [(block_light, block_out), (sky_light, sky_out)] {
+        for i in 0..(SECTION_BLOCK_COUNT / (2 * VECTOR_SIZE)) {
+            let in1 = _mm_loadu_si128(light[i * 2 * VECTOR_SIZE..].as_ptr().cast());
+            let in2 = _mm_loadu_si128(light[i * 2 * VECTOR_SIZE + VECTOR_SIZE..].as_ptr().cast());
+
+            // Same as packing metas, the levels of 2 blocks end up in the nibbles of one byte
+            let packed1 = _mm_or_si128(_mm_and_si128(in1, low_mask), _mm_and_si128(_mm_srli_epi16::<4>(in1), high_mask));
+            let packed2 = _mm_or_si128(_mm_and_si128(in2, low_mask), _mm_and_si128(_mm_srli_epi16::<4>(in2), high_mask));
+
+            _mm_storeu_si128(out[i * VECTOR_SIZE..].as_mut_ptr().cast(), _mm_packus_epi16(packed1, packed2));
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn pack_light_avx2(block_light: &[u8; SECTION_BLOCK_COUNT], sky_light: &[u8; SECTION_BLOCK_COUNT],
+        block_out: &mut [u8; SECTION_BLOCK_COUNT / 2], sky_out: &mut [u8; SECTION_BLOCK_COUNT / 2]) {
+    const VECTOR_SIZE: usize = size_of::<__m256i>();
+
+    let low_mask = _mm256_set1_epi16(0x000f);
+    let high_mask = _mm256_set1_epi16(0x00f0);
+
+    for (light, out) in [(block_light, block_out), (sky_light, sky_out)] {
+        for i in 0..(SECTION_BLOCK_COUNT / (2 * VECTOR_SIZE)) {
+            let in1 = _mm256_loadu_si256(light[i * 2 * VECTOR_SIZE..].as_ptr().cast());
+            let in2 = _mm256_loadu_si256(light[i * 2 * VECTOR_SIZE + VECTOR_SIZE..].as_ptr().cast());
+
+            let packed1 = _mm256_or_si256(_mm256_and_si256(in1, low_mask), _mm256_and_si256(_mm256_srli_epi16::<4>(in1), high_mask));
+            let packed2 = _mm256_or_si256(_mm256_and_si256(in2, low_mask), _mm256_and_si256(_mm256_srli_epi16::<4>(in2), high_mask));
+
+            // Packing works within 128-bit lanes, put them back in order
+            let packed = _mm256_permute4x64_epi64::<0b11011000>(_mm256_packus_epi16(packed1, packed2));
+            _mm256_storeu_si256(out[i * VECTOR_SIZE..].as_mut_ptr().cast(), packed);
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn pack_light_neon(block_light: &[u8; SECTION_BLOCK_COUNT], sky_light: &[u8; SECTION_BLOCK_COUNT],
+        block_out: &mut [u8; SECTION_BLOCK_COUNT / 2], sky_out: &mut [u8; SECTION_BLOCK_COUNT / 2]) {
+    const VECTOR_SIZE: usize = size_of::<uint8x16_t>();
+
+    let low_mask = vdupq_n_u8(0x0f);
+
+    for (light, out) in [(block_light, block_out), (sky_light, sky_out)] {
+        for i in 0..(SECTION_BLOCK_COUNT / (2 * VECTOR_SIZE)) {
+            // Deinterleaving splits the levels of even and odd blocks,
+            // the odd ones are shifted into the high nibbles of the even ones
+            let levels = vld2q_u8(light[i * 2 * VECTOR_SIZE..].as_ptr());
+            vst1q_u8(out[i * VECTOR_SIZE..].as_mut_ptr(), vsliq_n_u8::<4>(vandq_u8(levels.0, low_mask), levels.1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck_macros::quickcheck;
+
+    use super::*;
+
+    fn random_metas(seed: Vec<u8>) -> [u8; SECTION_BLOCK_COUNT] {
+        std::array::from_fn(|i| if seed.is_empty() { 0 } else { seed[i % seed.len()].wrapping_add(i as u8) })
+    }
+
+    #[test]
+    fn set_metas_packs_nibbles() {
+        let mut metas = [0u8; SECTION_BLOCK_COUNT];
+        metas[0] = 0x3;
+        metas[1] = 0xf5;
+        metas[SECTION_BLOCK_COUNT - 1] = 0x7;
+
+        let mut out = [0u8; SECTION_BLOCK_COUNT / 2];
+        pack_metas_fallback(&metas, &mut out);
+        assert_eq!(out[0], 0x53);
+        assert_eq!(out[SECTION_BLOCK_COUNT / 2 - 1], 0x70);
+    }
+
+    #[quickcheck]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn pack_metas_sse2_matches_fallback(seed: Vec<u8>) -> bool {
+        let metas = random_metas(seed);
+        let mut out1 = [0u8; SECTION_BLOCK_COUNT / 2];
+        let mut out2 = [0u8; SECTION_BLOCK_COUNT / 2];
+        unsafe { pack_metas_sse2(&metas, &mut out1); }
+        pack_metas_fallback(&metas, &mut out2);
+        out1 == out2
+    }
+
+    #[quickcheck]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn pack_metas_avx2_matches_fallback(seed: Vec<u8>) -> bool {
+        // Only checked when the CPU running the tests supports it
+        if !is_x86_feature_detected!("avx2") {
+            return true;
+        }
+
+        let metas = random_metas(seed);
+        let mut out1 = [0u8; SECTION_BLOCK_COUNT / 2];
+        let mut out2 = [0u8; SECTION_BLOCK_COUNT / 2];
+        unsafe { pack_metas_avx2(&metas, &mut out1); }
+        pack_metas_fallback(&metas, &mut out2);
+        out1 == out2
+    }
+
+    type PackLightFn = unsafe fn(&[u8; SECTION_BLOCK_COUNT], &[u8; SECTION_BLOCK_COUNT], &mut [u8; SECTION_BLOCK_COUNT / 2], &mut [u8; SECTION_BLOCK_COUNT / 2]);
+
+    /// Packs random light levels with the implementation and the fallback and compares the results
+    fn pack_light_matches_fallback(pack_light: PackLightFn, block_seed: Vec<u8>, sky_seed: Vec<u8>) -> bool {
+        let (block_light, sky_light) = (random_metas(block_seed), random_metas(sky_seed));
+        let mut out1 = ([0u8; SECTION_BLOCK_COUNT / 2], [0u8; SECTION_BLOCK_COUNT / 2]);
+        let mut out2 = ([0u8; SECTION_BLOCK_COUNT / 2], [0u8; SECTION_BLOCK_COUNT / 2]);
+        unsafe { pack_light(&block_light, &sky_light, &mut out1.0, &mut out1.1); }
+        pack_light_fallback(&block_light, &sky_light, &mut out2.0, &mut out2.1);
+        out1 == out2
+    }
+
+    #[test]
+    fn set_light_packs_nibbles() {
+        let mut block_light = [0u8; SECTION_BLOCK_COUNT];
+        block_light[0] = 0xf;
+        block_light[1] = 0x4;
+        let mut sky_light = [0xffu8; SECTION_BLOCK_COUNT];
+        sky_light[SECTION_BLOCK_COUNT - 1] = 0x2;
+
+        let mut section = Section {
+            block_types: [0; SECTION_BLOCK_COUNT],
+            block_metas: [0; SECTION_BLOCK_COUNT / 2],
+            block_light: [0; SECTION_BLOCK_COUNT / 2],
+            block_sky_light: [0; SECTION_BLOCK_COUNT / 2]
+        };
+        section.set_light(&block_light, &sky_light);
+        assert_eq!(section.block_light[0], 0x4f);
+        assert_eq!(section.block_light[1], 0x00);
+        assert_eq!(section.block_sky_light[0], 0xff);
+        assert_eq!(section.block_sky_light[SECTION_BLOCK_COUNT / 2 - 1], 0x2f);
+    }
+
+    #[quickcheck]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn pack_light_sse2_matches_fallback(block_seed: Vec<u8>, sky_seed: Vec<u8>) -> bool {
+        pack_light_matches_fallback(pack_light_sse2, block_seed, sky_seed)
+    }
+
+    #[quickcheck]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn pack_light_avx2_matches_fallback(block_seed: Vec<u8>, sky_seed: Vec<u8>) -> bool {
+        // Only checked when the CPU running the tests supports it
+        if !is_x86_feature_detected!("avx2") {
+            return true;
+        }
+
+        pack_light_matches_fallback(pack_light_avx2, block_seed, sky_seed)
+    }
+
+    #[quickcheck]
+    #[cfg(target_arch = "aarch64")]
+    fn pack_light_neon_matches_fallback(block_seed: Vec<u8>, sky_seed: Vec<u8>) -> bool {
+        pack_light_matches_fallback(pack_light_neon, block_seed, sky_seed)
+    }
+}
@@ -0,0 +1,176 @@
+//! Vanilla game rules, stored as strings in the GameRules compound of level.dat
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use log::*;
+use siderite_nbt::{Compound, Tag};
+
+/// Whether the time of day advances
+pub const DO_DAYLIGHT_CYCLE: &str = "doDaylightCycle";
+/// Whether mobs spawn naturally
+pub const DO_MOB_SPAWNING: &str = "doMobSpawning";
+/// Whether players keep their inventory when they die
+pub const KEEP_INVENTORY: &str = "keepInventory";
+/// Whether mobs can change blocks
+pub const MOB_GRIEFING: &str = "mobGriefing";
+/// Number of blocks per chunk section that get a random tick every tick
+pub const RANDOM_TICK_SPEED: &str = "randomTickSpeed";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameRuleValue {
+    Bool(bool),
+    Int(i32)
+}
+
+impl GameRuleValue {
+    /// Parses the value as the same type as `self`
+    fn parse_as(self, value: &str) -> Option<Self> {
+        match self {
+            GameRuleValue::Bool(_) => match value {
+                "true" => Some(GameRuleValue::Bool(true)),
+                "false" => Some(GameRuleValue::Bool(false)),
+                _ => None
+            },
+            GameRuleValue::Int(_) => value.parse().ok().map(GameRuleValue::Int)
+        }
+    }
+}
+
+impl fmt::Display for GameRuleValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameRuleValue::Bool(v) => write!(f, "{}", v),
+            GameRuleValue::Int(v) => write!(f, "{}", v)
+        }
+    }
+}
+
+/// The game rules of vanilla 1.8 and their default values
+const DEFAULTS: &[(&str, GameRuleValue)] = &[
+    ("commandBlockOutput", GameRuleValue::Bool(true)),
+    (DO_DAYLIGHT_CYCLE, GameRuleValue::Bool(true)),
+    ("doEntityDrops", GameRuleValue::Bool(true)),
+    ("doFireTick", GameRuleValue::Bool(true)),
+    ("doMobLoot", GameRuleValue::Bool(true)),
+    (DO_MOB_SPAWNING, GameRuleValue::Bool(true)),
+    ("doTileDrops", GameRuleValue::Bool(true)),
+    (KEEP_INVENTORY, GameRuleValue::Bool(false)),
+    ("logAdminCommands", GameRuleValue::Bool(true)),
+    (MOB_GRIEFING, GameRuleValue::Bool(true)),
+    ("naturalRegeneration", GameRuleValue::Bool(true)),
+    (RANDOM_TICK_SPEED, GameRuleValue::Int(3)),
+    ("reducedDebugInfo", GameRuleValue::Bool(false)),
+    ("sendCommandFeedback", GameRuleValue::Bool(true)),
+    ("showDeathMessages", GameRuleValue::Bool(true))
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRules {
+    values: BTreeMap<&'static str, GameRuleValue>
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            values: DEFAULTS.iter().copied().collect()
+        }
+    }
+}
+
+impl GameRules {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the value of the rule, None if there's no rule with that name
+    pub fn get(&self, name: &str) -> Option<GameRuleValue> {
+        self.values.get(name).copied()
+    }
+
+    /// Returns the value of a boolean rule, false for unknown rules
+    pub fn get_bool(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(GameRuleValue::Bool(true)))
+    }
+
+    /// Returns the value of an integer rule, 0 for unknown rules
+    pub fn get_int(&self, name: &str) -> i32 {
+        match self.get(name) {
+            Some(GameRuleValue::Int(v)) => v,
+            _ => 0
+        }
+    }
+
+    /// Parses and sets the value of the rule.
+    /// Returns None if there's no rule with that name or the value has the wrong type.
+    pub fn set(&mut self, name: &str, value: &str) -> Option<GameRuleValue> {
+        let current = self.values.get_mut(name)?;
+        *current = current.parse_as(value)?;
+        Some(*current)
+    }
+
+    /// Returns the names of all rules in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().copied()
+    }
+
+    /// Returns the rules as the GameRules compound of level.dat
+    pub fn to_nbt(&self) -> Compound {
+        let mut nbt = Compound::new();
+        for (name, value) in &self.values {
+            nbt.insert(name, Tag::String(value.to_string()));
+        }
+
+        nbt
+    }
+
+    /// Reads the rules from the GameRules compound of level.dat,
+    /// unknown rules and invalid values are skipped
+    pub fn load_nbt(&mut self, nbt: &Compound) {
+        for (name, tag) in nbt.iter() {
+            if let Some(value) = tag.as_str() {
+                if self.set(name, value).is_none() {
+                    warn!("Ignoring game rule {} = {}", name, value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        let mut rules = GameRules::new();
+        assert!(!rules.get_bool(KEEP_INVENTORY));
+        assert_eq!(rules.set(KEEP_INVENTORY, "true"), Some(GameRuleValue::Bool(true)));
+        assert!(rules.get_bool(KEEP_INVENTORY));
+        assert_eq!(rules.set(KEEP_INVENTORY, "1"), None);
+        assert_eq!(rules.set(KEEP_INVENTORY, "TRUE"), None);
+
+        assert_eq!(rules.get_int(RANDOM_TICK_SPEED), 3);
+        assert_eq!(rules.set(RANDOM_TICK_SPEED, "10"), Some(GameRuleValue::Int(10)));
+        assert_eq!(rules.set(RANDOM_TICK_SPEED, "fast"), None);
+        assert_eq!(rules.set(RANDOM_TICK_SPEED, "true"), None);
+        assert_eq!(rules.get_int(RANDOM_TICK_SPEED), 10);
+
+        assert_eq!(rules.set("doNothing", "true"), None);
+        assert_eq!(rules.get("doNothing"), None);
+    }
+
+    #[test]
+    fn nbt_roundtrip() {
+        let mut rules = GameRules::new();
+        rules.set(DO_DAYLIGHT_CYCLE, "false");
+        rules.set(RANDOM_TICK_SPEED, "0");
+        let mut nbt = rules.to_nbt();
+        assert_eq!(nbt.get(DO_DAYLIGHT_CYCLE).and_then(Tag::as_str), Some("false"));
+
+        nbt.insert("doNothing", Tag::String("true".to_owned()));
+        let mut loaded = GameRules::new();
+        loaded.load_nbt(&nbt);
+        assert_eq!(loaded, rules);
+    }
+}
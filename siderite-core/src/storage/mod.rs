@@ -1,2 +1,4 @@
 pub mod chunk;
+pub mod game_rules;
+pub mod versioning;
 pub mod world;
@@ -0,0 +1,164 @@
+//! Versions of the data we save, so files written by older versions of siderite or by vanilla
+//! get upgraded to the current layout when they're loaded
+
+use std::io::{self, ErrorKind};
+
+use siderite_nbt::{Compound, Tag};
+
+/// Name of the tag the version of the data is stored in, data without it is version 0
+pub const DATA_VERSION_TAG: &str = "DataVersion";
+
+/// Version of the data we write, has to be increased with every new migration
+pub const DATA_VERSION: i32 = 1;
+
+/// What to do with fields we don't know when loading data
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnknownFields {
+    /// Keep them, so they're written back when the data is saved
+    Preserve,
+    /// Fail to load the data
+    Reject
+}
+
+/// Upgrades data from one version to the next
+pub struct Migration {
+    /// Version of the data the migration upgrades, it's `from + 1` after the migration ran
+    pub from: i32,
+    pub migrate: fn(&mut Compound) -> io::Result<()>
+}
+
+/// Migrations of the Data compound of level.dat, ordered by version
+pub const LEVEL_DAT: &[Migration] = &[
+    Migration { from: 0, migrate: game_rules_to_strings }
+];
+
+/// Returns the version of the data
+pub fn version_of(data: &Compound) -> i32 {
+    data.get(DATA_VERSION_TAG).and_then(Tag::as_int).unwrap_or(0)
+}
+
+/// Runs the migrations the data needs to get to the current version in order, then tags it with the current version.
+/// Returns the version the data had, data from a newer version than ours can't be loaded.
+pub fn migrate(migrations: &[Migration], data: &mut Compound) -> io::Result<i32> {
+    let version = version_of(data);
+    if version > DATA_VERSION {
+        return Err(io::Error::new(ErrorKind::InvalidData,
+            format!("Data version {} is newer than the supported version {}", version, DATA_VERSION)));
+    }
+
+    for migration in migrations.iter().filter(|v| v.from >= version) {
+        (migration.migrate)(data)?;
+    }
+
+    data.insert(DATA_VERSION_TAG, Tag::Int(DATA_VERSION));
+    Ok(version)
+}
+
+/// Checks the data only has the known fields, the version tag is always known.
+/// Unknown fields are an error when they're rejected.
+pub fn check_fields(data: &Compound, known: &[&str], unknown_fields: UnknownFields) -> io::Result<()> {
+    if unknown_fields == UnknownFields::Preserve {
+        return Ok(());
+    }
+
+    match data.iter().find(|(name, _)| *name != DATA_VERSION_TAG && !known.contains(name)) {
+        Some((name, _)) => Err(io::Error::new(ErrorKind::InvalidData, format!("Unknown field {}", name))),
+        None => Ok(())
+    }
+}
+
+/// Version 0 to 1: vanilla stores the value of every game rule as a string,
+/// but some tools write them as byte or int tags which we'd skip when loading them
+fn game_rules_to_strings(data: &mut Compound) -> io::Result<()> {
+    let rules = match data.get_mut("GameRules") {
+        Some(Tag::Compound(v)) => v,
+        Some(_) => return Err(io::Error::new(ErrorKind::InvalidData, "GameRules isn't a compound")),
+        None => return Ok(())
+    };
+
+    let converted: Vec<_> = rules.iter()
+        .filter_map(|(name, tag)| match tag {
+            Tag::Byte(v) => Some((name.to_owned(), (*v != 0).to_string())),
+            Tag::Int(v) => Some((name.to_owned(), v.to_string())),
+            _ => None
+        })
+        .collect();
+    for (name, value) in converted {
+        rules.insert(&name, Tag::String(value));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_rules(rules: &[(&str, Tag)]) -> Compound {
+        let mut compound = Compound::new();
+        for (name, tag) in rules {
+            compound.insert(name, tag.clone());
+        }
+
+        compound
+    }
+
+    /// Data compound of a level.dat written before data versions
+    fn version_0() -> Compound {
+        let mut data = Compound::new();
+        data.insert("GameRules", Tag::Compound(game_rules(&[
+            ("keepInventory", Tag::Byte(1)),
+            ("randomTickSpeed", Tag::Int(10)),
+            ("doDaylightCycle", Tag::String("false".to_owned()))
+        ])));
+        data.insert("Time", Tag::Long(100));
+        data.insert("LevelName", Tag::String("world".to_owned()));
+        data
+    }
+
+    #[test]
+    fn migrate_version_0() {
+        let mut data = version_0();
+        assert_eq!(migrate(LEVEL_DAT, &mut data).unwrap(), 0);
+        assert_eq!(version_of(&data), DATA_VERSION);
+
+        let rules = data.get("GameRules").and_then(Tag::as_compound).unwrap();
+        assert_eq!(rules.get("keepInventory").and_then(Tag::as_str), Some("true"));
+        assert_eq!(rules.get("randomTickSpeed").and_then(Tag::as_str), Some("10"));
+        assert_eq!(rules.get("doDaylightCycle").and_then(Tag::as_str), Some("false"));
+        assert_eq!(data.get("Time").and_then(Tag::as_long), Some(100));
+        // Fields we don't know are kept
+        assert_eq!(data.get("LevelName").and_then(Tag::as_str), Some("world"));
+    }
+
+    #[test]
+    fn current_version_is_unchanged() {
+        let mut data = version_0();
+        data.insert(DATA_VERSION_TAG, Tag::Int(DATA_VERSION));
+        let expected = data.clone();
+        assert_eq!(migrate(LEVEL_DAT, &mut data).unwrap(), DATA_VERSION);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn newer_version_fails() {
+        let mut data = version_0();
+        data.insert(DATA_VERSION_TAG, Tag::Int(DATA_VERSION + 1));
+        assert_eq!(migrate(LEVEL_DAT, &mut data).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn strict_fields() {
+        let data = version_0();
+        let known = ["GameRules", "Time"];
+        assert!(check_fields(&data, &known, UnknownFields::Preserve).is_ok());
+        assert!(check_fields(&data, &known, UnknownFields::Reject).is_err());
+        assert!(check_fields(&data, &["GameRules", "Time", "LevelName"], UnknownFields::Reject).is_ok());
+    }
+
+    #[test]
+    fn migrations_are_ordered() {
+        assert!(LEVEL_DAT.windows(2).all(|v| v[0].from < v[1].from));
+        assert!(LEVEL_DAT.iter().all(|v| v.from < DATA_VERSION));
+    }
+}
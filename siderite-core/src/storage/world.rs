@@ -1,11 +1,47 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, ErrorKind};
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
 use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use rand::thread_rng;
+use siderite_nbt::{Compound, Tag};
 
-use crate::coord::Coord;
+use crate::beds;
+use crate::blocks::{BlockFace, BlockType};
+use crate::coord::{self, ChunkCoord, Coord};
+use crate::entities::{Entity, EntityType};
 use crate::entities::player::Player;
-use crate::storage::chunk::chunk_map::ChunkMap;
+use crate::entities::tracker::EntityTracker;
+use crate::items::ItemType;
+use crate::server;
+use crate::storage::chunk::{Chunk, HEIGHT, WIDTH};
+use crate::storage::chunk::chunk_map::{ChunkMap, ChunkStorage, SERIALIZED_TRIM_INTERVAL};
+use crate::storage::chunk::generator::{BiomeGenerator, FlatGenerator, GeneratorType};
+use crate::storage::game_rules::{self, GameRules};
+use crate::storage::versioning::{self, UnknownFields};
+use crate::tile_entities::TileEntity;
+use crate::weather::Weather;
+
+/// Distance between the points checked along a line of sight
+const RAYCAST_STEP: f64 = 0.05;
+
+/// Maximum distance a player can reach blocks from
+const REACH: f64 = 5.0;
+
+/// Columns further away than this from the requested spawn aren't searched for a safe spot
+const SAFE_SPAWN_RADIUS: i32 = 8;
+
+/// Highest light level
+pub const MAX_LIGHT: u8 = 15;
+
+/// Number of ticks in a day
+pub const DAY_LENGTH: i64 = 24000;
+
+/// Fields of the Data compound of level.dat we read and write
+const LEVEL_DAT_FIELDS: &[&str] = &["Difficulty", "GameRules", "RandomSeed", "Time", "DayTime", "raining", "rainTime", "thundering", "thunderTime", "clearWeatherTime"];
 
 #[repr(i8)]
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
@@ -24,38 +60,263 @@ pub enum Difficulty {
     Hard = 3
 }
 
+impl Difficulty {
+    /// Returns the name shown in the game, like `Peaceful`
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Peaceful => "Peaceful",
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard"
+        }
+    }
+
+    /// Parses the difficulty the way /difficulty does: the name, its first letter or the number
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "peaceful" | "p" | "0" => Some(Difficulty::Peaceful),
+            "easy" | "e" | "1" => Some(Difficulty::Easy),
+            "normal" | "n" | "2" => Some(Difficulty::Normal),
+            "hard" | "h" | "3" => Some(Difficulty::Hard),
+            _ => None
+        }
+    }
+}
+
 pub struct WorldConfig {
     pub name: String,
     pub dimension: Dimension,
-    pub spawn_pos: Coord<i32>
+    pub spawn_pos: Coord<i32>,
+    /// Radius in chunks around the spawn that is always kept loaded, 0 disables
+    pub spawn_chunks_radius: i32,
+    pub difficulty: Difficulty,
+    pub generator: GeneratorType,
+    pub seed: i64
+}
+
+/// Stores each chunk in its own file in the chunks directory of the world
+struct ChunkFiles {
+    dir: PathBuf
+}
+
+impl ChunkFiles {
+    /// Returns the path of the file the chunk is saved in
+    fn path(&self, coord: ChunkCoord) -> PathBuf {
+        self.dir.join(format!("c.{}.{}.dat", coord.x, coord.z))
+    }
+}
+
+impl ChunkStorage for ChunkFiles {
+    fn load(&self, coord: ChunkCoord) -> io::Result<Option<Chunk>> {
+        let file = match File::open(self.path(coord)) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+
+        let (_, mut root) = siderite_nbt::read_gzip(BufReader::new(file))?;
+        let root = root.as_compound_mut().ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "chunk isn't a compound"))?;
+        versioning::migrate(&[], root)?;
+        match root.get("Level").and_then(Tag::as_compound).and_then(Chunk::from_nbt) {
+            Some(v) => Ok(Some(v)),
+            None => Err(io::Error::new(ErrorKind::InvalidData, "invalid chunk data"))
+        }
+    }
+
+    fn save(&self, coord: ChunkCoord, chunk: &Chunk) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut root = Compound::new();
+        root.insert(versioning::DATA_VERSION_TAG, Tag::Int(versioning::DATA_VERSION));
+        root.insert("Level", Tag::Compound(chunk.to_nbt(coord)));
+        siderite_nbt::write_gzip(BufWriter::new(File::create(self.path(coord))?), "", &Tag::Compound(root))
+    }
 }
 
 pub struct World {
-    _name: String,
+    name: String,
     dimension: Dimension,
 
     players: HashMap<u32, Arc<RwLock<Player>>>,
+    entities: HashMap<u32, Arc<RwLock<Entity>>>,
+    /// Which entities and players each player can see
+    tracker: EntityTracker,
     chunk_map: Arc<ChunkMap>,
 
-    spawn_pos: Coord<i32>
+    spawn_pos: Coord<i32>,
+    /// Seed of the terrain generator, saved so the terrain continues the same way
+    seed: i64,
+    game_rules: GameRules,
+    difficulty: Difficulty,
+
+    /// Number of ticks the world has existed for
+    age: i64,
+    /// Ticks since the first day started, a day is 24000 ticks
+    time_of_day: i64,
+    weather: Weather,
+
+    /// Blocks waiting for a scheduled tick, with the age of the world they get it at
+    scheduled_ticks: Vec<(i64, Coord<i32>)>
 }
 
 impl World {
     pub fn new(config: WorldConfig) -> Self {
+        let mut chunk_map = match config.generator {
+            GeneratorType::Flat => ChunkMap::with_generator(FlatGenerator),
+            GeneratorType::Biomes => ChunkMap::with_generator(BiomeGenerator::new(config.seed as u64))
+        };
+        chunk_map.set_storage(ChunkFiles { dir: PathBuf::from(&config.name).join("chunks") });
+        chunk_map.keep_loaded_area(ChunkCoord::from_pos(config.spawn_pos.into()), config.spawn_chunks_radius);
+
         Self {
-            _name: config.name,
+            name: config.name,
             dimension: config.dimension,
             spawn_pos: config.spawn_pos,
+            seed: config.seed,
+            game_rules: GameRules::new(),
+            difficulty: config.difficulty,
+
+            age: 0,
+            time_of_day: 0,
+            weather: Weather::default(),
+            scheduled_ticks: Vec::new(),
 
             players: HashMap::new(),
-            chunk_map: Arc::new(ChunkMap::new())
+            entities: HashMap::new(),
+            tracker: EntityTracker::default(),
+            chunk_map: Arc::new(chunk_map)
+        }
+    }
+
+    /// Advances the time and weather, unloads unused chunks, trims their serialized data and burns the fuses of primed TNT.
+    /// Returns the entities that detonated this tick, they're already removed from the world.
+    pub fn tick(&mut self, view_distance: i32) -> Vec<Arc<RwLock<Entity>>> {
+        self.age += 1;
+        if self.game_rules.get_bool(game_rules::DO_DAYLIGHT_CYCLE) {
+            self.time_of_day += 1;
+        }
+        // Like vanilla, there's no weather without a sky
+        if self.has_sky_light() {
+            self.weather.tick(&mut thread_rng());
+        }
+
+        let in_use = self.chunks_in_view(view_distance);
+        self.chunk_map.unload_unused(&in_use);
+        if self.age % SERIALIZED_TRIM_INTERVAL == 0 {
+            self.chunk_map.trim_serialized();
+        }
+
+        let detonated: Vec<u32> = self.entities.iter()
+            .filter(|(_, entity)| entity.write().unwrap().tick_fuse())
+            .map(|(id, _)| *id)
+            .collect();
+        detonated.into_iter().filter_map(|id| self.entities.remove(&id)).collect()
+    }
+
+    /// Moves the entities with a velocity the server simulates, their riders move along.
+    /// Solid blocks and unloaded chunks stop them. Returns the entities that moved.
+    pub fn move_entities(&self) -> Vec<Arc<RwLock<Entity>>> {
+        let blocked = |pos: Coord<f64>| {
+            let block_pos = Coord::new(pos.x.floor() as i32, pos.y.floor() as i32, pos.z.floor() as i32);
+            self.get_block(block_pos).map_or(true, |(block_type, _)| block_type.is_solid())
+        };
+
+        let mut moved = Vec::new();
+        for entity in self.entities.values() {
+            let (pos, passenger) = {
+                let mut e = entity.write().unwrap();
+                if !e.tick_movement(&blocked) {
+                    continue;
+                }
+                (e.pos(), e.passenger())
+            };
+
+            if let Some(rider) = passenger.and_then(|id| self.players.get(&id)) {
+                rider.write().unwrap().set_pos(pos);
+            }
+            moved.push(entity.clone());
+        }
+
+        moved
+    }
+
+    /// Skips to the next morning and stops the rain if every player in the world is fully asleep, returns true if it did
+    pub fn skip_night(&mut self) -> bool {
+        let asleep = !self.players.is_empty() && self.players.values().all(|v| v.read().unwrap().is_fully_asleep());
+        if asleep {
+            self.time_of_day = beds::next_morning(self.time_of_day);
+            self.weather.clear();
+        }
+
+        asleep
+    }
+
+    /// Replaces the TNT block at the position with primed TNT
+    pub fn prime_tnt(&mut self, pos: Coord<i32>, fuse: i32) -> Option<Arc<RwLock<Entity>>> {
+        if self.get_block(pos)?.0 != BlockType::Tnt {
+            return None;
+        }
+
+        self.set_block(pos, BlockType::Air, 0);
+        let center = Coord::new(pos.x as f64 + 0.5, pos.y as f64, pos.z as f64 + 0.5);
+        Some(self.add_entity(Entity::primed_tnt(server::get_next_entity_id(), center, fuse)))
+    }
+
+    /// Gives the block at the position a tick after `delay` ticks
+    pub fn schedule_tick(&mut self, pos: Coord<i32>, delay: i64) {
+        self.scheduled_ticks.push((self.age + delay, pos));
+    }
+
+    /// Removes and returns the blocks whose scheduled tick is due, in the order they were scheduled
+    pub fn take_due_ticks(&mut self) -> Vec<Coord<i32>> {
+        let age = self.age;
+        let mut due = Vec::new();
+        self.scheduled_ticks.retain(|&(at, pos)| {
+            if at <= age {
+                due.push(pos);
+                return false;
+            }
+
+            true
+        });
+        due
+    }
+
+    /// Returns the chunks within `view_distance` of any player in this world
+    pub fn chunks_in_view(&self, view_distance: i32) -> HashSet<ChunkCoord> {
+        let mut chunks = HashSet::new();
+        for player in self.players.values() {
+            let center = ChunkCoord::from_pos(player.read().unwrap().pos());
+            for x in (center.x - view_distance)..=(center.x + view_distance) {
+                for z in (center.z - view_distance)..=(center.z + view_distance) {
+                    chunks.insert(ChunkCoord { x, z });
+                }
+            }
         }
+
+        chunks
+    }
+
+    /// Returns the chunks that should receive updates this tick,
+    /// those near players and those kept loaded around the spawn
+    pub fn ticking_chunks(&self, view_distance: i32) -> HashSet<ChunkCoord> {
+        let mut chunks = self.chunks_in_view(view_distance);
+        chunks.extend(self.chunk_map.keep_loaded_chunks());
+        chunks
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     pub fn dimension(&self) -> Dimension {
         self.dimension
     }
 
+    /// Only the overworld has a sky, chunks in the Nether and the End are sent without sky light
+    pub fn has_sky_light(&self) -> bool {
+        self.dimension == Dimension::Overworld
+    }
+
     pub fn num_players(&self) -> usize {
         self.players.len()
     }
@@ -69,12 +330,149 @@ impl World {
         self.spawn_pos
     }
 
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = difficulty;
+    }
+
+    pub fn game_rules(&self) -> &GameRules {
+        &self.game_rules
+    }
+
+    pub fn game_rules_mut(&mut self) -> &mut GameRules {
+        &mut self.game_rules
+    }
+
+    pub fn age(&self) -> i64 {
+        self.age
+    }
+
+    pub fn time_of_day(&self) -> i64 {
+        self.time_of_day
+    }
+
+    pub fn set_time_of_day(&mut self, time_of_day: i64) {
+        self.time_of_day = time_of_day;
+    }
+
+    pub fn weather(&self) -> &Weather {
+        &self.weather
+    }
+
+    pub fn weather_mut(&mut self) -> &mut Weather {
+        &mut self.weather
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// Returns the path of the level.dat file of this world
+    fn level_dat_path(&self) -> PathBuf {
+        PathBuf::from(&self.name).join("level.dat")
+    }
+
+    /// Returns the seed in level.dat of the world with the name, None if the world has no level.dat yet
+    pub fn saved_seed(name: &str) -> io::Result<Option<i64>> {
+        let file = match File::open(PathBuf::from(name).join("level.dat")) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+
+        let (_, root) = siderite_nbt::read_gzip(BufReader::new(file))?;
+        Ok(root.as_compound().and_then(|v| v.get("Data")).and_then(Tag::as_compound)
+            .and_then(|v| v.get("RandomSeed")).and_then(Tag::as_long))
+    }
+
+    /// Loads the difficulty, game rules, time and weather from level.dat, a missing file keeps the defaults.
+    /// Files written by older versions are migrated first.
+    pub fn load_level_dat(&mut self, unknown_fields: UnknownFields) -> io::Result<()> {
+        let file = match File::open(self.level_dat_path()) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e)
+        };
+
+        let (_, mut root) = siderite_nbt::read_gzip(BufReader::new(file))?;
+        let data = match root.as_compound_mut().and_then(|v| v.get_mut("Data")).and_then(Tag::as_compound_mut) {
+            Some(v) => v,
+            None => return Err(io::Error::new(ErrorKind::InvalidData, "level.dat has no Data compound"))
+        };
+
+        versioning::migrate(versioning::LEVEL_DAT, data)?;
+        versioning::check_fields(data, LEVEL_DAT_FIELDS, unknown_fields)?;
+
+        if let Some(difficulty) = data.get("Difficulty").and_then(Tag::as_byte).and_then(Difficulty::from_i8) {
+            self.difficulty = difficulty;
+        }
+        if let Some(rules) = data.get("GameRules").and_then(Tag::as_compound) {
+            self.game_rules.load_nbt(rules);
+        }
+
+        self.age = data.get("Time").and_then(Tag::as_long).unwrap_or(0);
+        self.time_of_day = data.get("DayTime").and_then(Tag::as_long).unwrap_or(0);
+        self.weather.load_nbt(data);
+        Ok(())
+    }
+
+    /// Saves the difficulty, game rules, time and weather to level.dat, keeping the other values in the file
+    pub fn save_level_dat(&self) -> io::Result<()> {
+        let path = self.level_dat_path();
+        let mut root = match File::open(&path) {
+            Ok(file) => match siderite_nbt::read_gzip(BufReader::new(file))? {
+                (_, Tag::Compound(v)) => v,
+                _ => return Err(io::Error::new(ErrorKind::InvalidData, "level.dat isn't a compound"))
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => Compound::new(),
+            Err(e) => return Err(e)
+        };
+
+        if root.get("Data").and_then(Tag::as_compound).is_none() {
+            root.insert("Data", Tag::Compound(Compound::new()));
+        }
+
+        let data = root.get_mut("Data").and_then(Tag::as_compound_mut).unwrap();
+        versioning::migrate(versioning::LEVEL_DAT, data)?;
+        data.insert("Difficulty", Tag::Byte(self.difficulty as i8));
+        data.insert("GameRules", Tag::Compound(self.game_rules.to_nbt()));
+        data.insert("RandomSeed", Tag::Long(self.seed));
+        data.insert("Time", Tag::Long(self.age));
+        data.insert("DayTime", Tag::Long(self.time_of_day));
+        self.weather.write_nbt(data);
+
+        fs::create_dir_all(&self.name)?;
+        siderite_nbt::write_gzip(BufWriter::new(File::create(&path)?), "", &Tag::Compound(root))
+    }
+
+    /// Saves the chunks that changed since they were last saved, one file per chunk.
+    /// Returns the number of saved chunks.
+    pub fn save_chunks(&self) -> io::Result<usize> {
+        self.chunk_map.save()
+    }
+
     pub fn foreach_player(&self, function: &dyn Fn(&Arc<RwLock<Player>>)) {
         for player in self.players.values() {
-            function(&player);
+            function(player);
         }
     }
 
+    pub fn players(&self) -> Vec<Arc<RwLock<Player>>> {
+        self.players.values().cloned().collect()
+    }
+
+    /// Returns the ids of the players and where they are
+    pub fn player_positions(&self) -> Vec<(u32, Coord<f64>)> {
+        self.players.iter().map(|(id, player)| (*id, player.read().unwrap().pos())).collect()
+    }
+
+    pub fn get_player(&self, id: u32) -> Option<Arc<RwLock<Player>>> {
+        self.players.get(&id).cloned()
+    }
+
     pub fn add_player(&mut self, id: u32, player: Arc<RwLock<Player>>) {
         self.players.insert(id, player);
     }
@@ -82,4 +480,529 @@ impl World {
     pub fn remove_player(&mut self, id: u32) -> Option<Arc<RwLock<Player>>> {
         self.players.remove(&id)
     }
+
+    /// Returns the type and meta of the block at the position, or None if the chunk isn't loaded
+    pub fn get_block(&self, pos: Coord<i32>) -> Option<(BlockType, u8)> {
+        if !Chunk::is_valid_height(pos.y) {
+            return Some((BlockType::Air, 0));
+        }
+
+        let coord = ChunkCoord::from_block_pos(pos);
+        let mut block = None;
+        self.chunk_map.do_with_chunk(coord, |chunk| {
+            block = Some(chunk.data.get_block_type_meta(Chunk::abs_to_rel(pos, coord)));
+        });
+
+        block
+    }
+
+    /// Returns the type and meta of the block at the position, unloaded chunks are treated like air
+    pub fn get_block_state(&self, pos: Coord<i32>) -> (BlockType, u8) {
+        self.get_block(pos).unwrap_or((BlockType::Air, 0))
+    }
+
+    /// Returns the sky light at the position: full if no opaque block is at or above it.
+    /// Light isn't stored in the chunks yet, so light doesn't spread sideways under overhangs.
+    pub fn sky_light(&self, pos: Coord<i32>) -> u8 {
+        if !self.has_sky_light() {
+            return 0;
+        }
+
+        let coord = ChunkCoord::from_block_pos(pos);
+        let mut covered = false;
+        self.chunk_map.do_with_chunk(coord, |chunk| {
+            let rel_pos = Chunk::abs_to_rel(pos, coord);
+            covered = (pos.y.max(0)..HEIGHT).any(|y| chunk.data.get_block(Coord::new(rel_pos.x, y, rel_pos.z)).is_opaque());
+        });
+
+        if covered { 0 } else { MAX_LIGHT }
+    }
+
+    /// Returns the light given off by blocks nearby, which drops by one for every block of distance.
+    /// Blocks in between don't shield the light.
+    pub fn block_light(&self, pos: Coord<i32>) -> u8 {
+        let range = MAX_LIGHT as i32 - 1;
+        let min = ChunkCoord::from_block_pos(Coord::new(pos.x - range, 0, pos.z - range));
+        let max = ChunkCoord::from_block_pos(Coord::new(pos.x + range, 0, pos.z + range));
+        let mut light = 0;
+        for cx in min.x..=max.x {
+            for cz in min.z..=max.z {
+                let coord = ChunkCoord { x: cx, z: cz };
+                self.chunk_map.do_with_chunk(coord, |chunk| {
+                    for x in (pos.x - range).max(cx * WIDTH)..=(pos.x + range).min(cx * WIDTH + WIDTH - 1) {
+                        for z in (pos.z - range).max(cz * WIDTH)..=(pos.z + range).min(cz * WIDTH + WIDTH - 1) {
+                            let horizontal = (x - pos.x).abs() + (z - pos.z).abs();
+                            let vertical = range - horizontal;
+                            for y in (pos.y - vertical).max(0)..=(pos.y + vertical).min(HEIGHT - 1) {
+                                let distance = horizontal + (y - pos.y).abs();
+                                let block = Chunk::abs_to_rel(Coord::new(x, y, z), coord);
+                                let emitted = chunk.data.get_block(block).light_emission() as i32 - distance;
+                                light = light.max(emitted);
+                            }
+                        }
+                    }
+                });
+            }
+        }
+
+        light as u8
+    }
+
+    /// Returns the brightest of the sky and block light at the position
+    pub fn light(&self, pos: Coord<i32>) -> u8 {
+        let sky_light = self.sky_light(pos);
+        if sky_light == MAX_LIGHT {
+            return sky_light;
+        }
+
+        sky_light.max(self.block_light(pos))
+    }
+
+    /// Returns a position close to `around` with solid ground below it and room for a player to stand.
+    /// The columns are searched in growing squares around `around`, each column upwards first.
+    /// Returns `around` if there's no such position within SAFE_SPAWN_RADIUS.
+    pub fn find_safe_spawn(&self, around: Coord<i32>) -> Coord<i32> {
+        let start_y = around.y.clamp(1, HEIGHT - 1);
+        for radius in 0..=SAFE_SPAWN_RADIUS {
+            for x in (around.x - radius)..=(around.x + radius) {
+                for z in (around.z - radius)..=(around.z + radius) {
+                    // The inside of the square was searched already
+                    if (x - around.x).abs() != radius && (z - around.z).abs() != radius {
+                        continue;
+                    }
+
+                    self.chunk_map.touch_chunk(ChunkCoord::from_block_pos(Coord::new(x, 0, z)));
+                    let is_safe = |y: &i32| self.is_safe_spawn(Coord::new(x, *y, z));
+                    let found = (start_y..HEIGHT).find(is_safe).or_else(|| (1..start_y).rev().find(is_safe));
+                    if let Some(y) = found {
+                        return Coord::new(x, y, z);
+                    }
+                }
+            }
+        }
+
+        around
+    }
+
+    /// Returns true if a player standing at `feet` is on solid ground and not stuck in a block
+    fn is_safe_spawn(&self, feet: Coord<i32>) -> bool {
+        let is_free = |y: i32| {
+            let (block_type, _) = self.get_block_state(Coord::new(feet.x, y, feet.z));
+            !block_type.is_solid() && !block_type.is_liquid() && block_type != BlockType::Fire
+        };
+
+        self.get_block_state(Coord::new(feet.x, feet.y - 1, feet.z)).0.is_solid() && is_free(feet.y) && is_free(feet.y + 1)
+    }
+
+    /// Sets the block at the position.
+    /// Returns false if the position is outside the world or the chunk containing it isn't loaded.
+    pub fn set_block(&self, pos: Coord<i32>, block_type: BlockType, meta: u8) -> bool {
+        if !Chunk::is_valid_height(pos.y) {
+            return false;
+        }
+
+        let coord = ChunkCoord::from_block_pos(pos);
+        let mut loaded = false;
+        self.chunk_map.do_with_chunk_mut(coord, |chunk| {
+            loaded = true;
+            let rel_pos = Chunk::abs_to_rel(pos, coord);
+            chunk.set_block(rel_pos, block_type);
+            chunk.set_meta(rel_pos, meta);
+        });
+
+        loaded
+    }
+
+    /// Follows the line of sight from `eye` and returns the first block for which `hit` returns true,
+    /// together with the position just in front of it
+    pub fn raycast(
+        &self,
+        eye: Coord<f64>,
+        yaw: f32,
+        pitch: f32,
+        max_distance: f64,
+        hit: impl Fn(BlockType, u8) -> bool) -> Option<(Coord<i32>, Coord<i32>)>
+    {
+        let dir = coord::look_direction(yaw, pitch);
+
+        let block_at = |distance: f64| Coord::new(
+            (eye.x + dir.x * distance).floor() as i32,
+            (eye.y + dir.y * distance).floor() as i32,
+            (eye.z + dir.z * distance).floor() as i32);
+
+        let mut previous = block_at(0.0);
+        let mut distance = 0.0;
+        while distance <= max_distance {
+            let pos = block_at(distance);
+            if pos != previous || distance == 0.0 {
+                let (block_type, meta) = self.get_block(pos)?;
+                if hit(block_type, meta) {
+                    return Some((pos, previous));
+                }
+
+                previous = pos;
+            }
+
+            distance += RAYCAST_STEP;
+        }
+
+        None
+    }
+
+    /// Uses a bucket in the line of sight, picking up or placing a liquid source.
+    /// Returns the changed block, if any, and the item the bucket turns into.
+    pub fn use_bucket(&self, eye: Coord<f64>, yaw: f32, pitch: f32, bucket: ItemType) -> Option<(Option<Coord<i32>>, ItemType)> {
+        match bucket {
+            ItemType::Bucket => {
+                let (pos, _) = self.raycast(eye, yaw, pitch, REACH, |block_type, _| block_type != BlockType::Air)?;
+                let filled = match self.get_block(pos)? {
+                    (BlockType::Water | BlockType::FlowingWater, 0) => ItemType::WaterBucket,
+                    (BlockType::Lava | BlockType::FlowingLava, 0) => ItemType::LavaBucket,
+                    _ => return None
+                };
+
+                self.set_block(pos, BlockType::Air, 0);
+                Some((Some(pos), filled))
+            }
+            ItemType::WaterBucket | ItemType::LavaBucket => {
+                let (_, pos) = self.raycast(eye, yaw, pitch, REACH, |block_type, _| !block_type.is_replaceable())?;
+                let (block_type, meta) = self.get_block(pos)?;
+                if !block_type.is_replaceable() || (block_type.is_liquid() && meta == 0) {
+                    return None;
+                }
+
+                // Water evaporates in the nether
+                if bucket == ItemType::WaterBucket && self.dimension == Dimension::Nether {
+                    return Some((None, ItemType::Bucket));
+                }
+
+                let liquid = if bucket == ItemType::WaterBucket { BlockType::Water } else { BlockType::Lava };
+                self.set_block(pos, liquid, 0);
+                Some((Some(pos), ItemType::Bucket))
+            }
+            _ => None
+        }
+    }
+
+    /// Sets fire to the block next to the given face.
+    /// Returns the position of the fire or None if there's no room for it.
+    pub fn ignite(&self, pos: Coord<i32>, face: BlockFace) -> Option<Coord<i32>> {
+        let target = face.adjacent(pos);
+        if self.get_block(target)?.0 != BlockType::Air {
+            return None;
+        }
+
+        self.set_block(target, BlockType::Fire, 0);
+        Some(target)
+    }
+
+    pub fn get_tile_entity(&self, pos: Coord<i32>) -> Option<TileEntity> {
+        let mut tile_entity = None;
+        self.chunk_map.do_with_chunk(ChunkCoord::from_block_pos(pos), |chunk| {
+            tile_entity = chunk.tile_entities.get(&pos).cloned();
+        });
+
+        tile_entity
+    }
+
+    /// Sets or removes the tile entity at the position.
+    /// Returns false if the chunk containing the position isn't loaded.
+    pub fn set_tile_entity(&self, pos: Coord<i32>, tile_entity: Option<TileEntity>) -> bool {
+        let mut loaded = false;
+        self.chunk_map.do_with_chunk_mut(ChunkCoord::from_block_pos(pos), |chunk| {
+            loaded = true;
+            chunk.set_tile_entity(pos, tile_entity);
+        });
+
+        loaded
+    }
+
+    pub fn entities(&self) -> Vec<Arc<RwLock<Entity>>> {
+        self.entities.values().cloned().collect()
+    }
+
+    pub fn tracker(&self) -> &EntityTracker {
+        &self.tracker
+    }
+
+    pub fn tracker_mut(&mut self) -> &mut EntityTracker {
+        &mut self.tracker
+    }
+
+    pub fn get_entity(&self, id: u32) -> Option<Arc<RwLock<Entity>>> {
+        self.entities.get(&id).cloned()
+    }
+
+    pub fn add_entity(&mut self, entity: Entity) -> Arc<RwLock<Entity>> {
+        let id = entity.id();
+        let entity = Arc::new(RwLock::new(entity));
+        self.entities.insert(id, entity.clone());
+        entity
+    }
+
+    pub fn remove_entity(&mut self, id: u32) -> Option<Arc<RwLock<Entity>>> {
+        self.entities.remove(&id)
+    }
+
+    /// Removes the hostile mobs, which can't exist on peaceful difficulty. Returns their ids.
+    pub fn remove_hostile_mobs(&mut self) -> Vec<u32> {
+        let hostile: Vec<u32> = self.entities.iter()
+            .filter(|(_, entity)| matches!(entity.read().unwrap().entity_type(), EntityType::Mob(v) if v.is_hostile()))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &hostile {
+            self.entities.remove(id);
+        }
+
+        hostile
+    }
+
+    /// Returns true if an item frame or painting hangs in the block, facing the direction
+    pub fn has_hanging_entity(&self, pos: Coord<i32>, facing: BlockFace) -> bool {
+        self.entities.values().any(|v| {
+            let entity = v.read().unwrap();
+            entity.facing() == Some(facing) && entity.hanging_pos() == Some(pos)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::test_utils::test_world;
+
+    #[test]
+    fn terrain_follows_the_seed() {
+        let underground = |seed| {
+            let world = World::new(WorldConfig {
+                name: "world".to_owned(),
+                dimension: Dimension::Overworld,
+                spawn_pos: Coord::new(0, 16, 0),
+                spawn_chunks_radius: 1,
+                difficulty: Difficulty::Easy,
+                generator: GeneratorType::Biomes,
+                seed
+            });
+
+            // The caves and ores of the chunks kept loaded around the spawn
+            let mut blocks = Vec::new();
+            for y in 0..48 {
+                for z in -16..16 {
+                    for x in -16..16 {
+                        blocks.push(world.get_block(Coord::new(x, y, z)).unwrap().0);
+                    }
+                }
+            }
+            blocks
+        };
+
+        let blocks = underground(42);
+        assert!(blocks.contains(&BlockType::CoalOre));
+        assert_eq!(blocks, underground(42));
+        assert_ne!(blocks, underground(43));
+    }
+
+    #[test]
+    fn daylight_cycle() {
+        let mut world = test_world(Dimension::Overworld);
+        world.tick(0);
+        world.tick(0);
+        assert_eq!((world.age(), world.time_of_day()), (2, 2));
+
+        world.game_rules_mut().set(game_rules::DO_DAYLIGHT_CYCLE, "false");
+        world.tick(0);
+        assert_eq!((world.age(), world.time_of_day()), (3, 2));
+    }
+
+    #[test]
+    fn level_dat_roundtrip() {
+        let dir = env::temp_dir().join(format!("siderite-level-dat-{}", std::process::id()));
+        let config = || WorldConfig {
+            name: dir.to_string_lossy().into_owned(),
+            dimension: Dimension::Overworld,
+            spawn_pos: Coord::new(0, 16, 0),
+            spawn_chunks_radius: 0,
+            difficulty: Difficulty::Easy,
+            generator: GeneratorType::Flat,
+            seed: 7
+        };
+
+        let mut world = World::new(config());
+        world.game_rules_mut().set(game_rules::KEEP_INVENTORY, "true");
+        world.set_difficulty(Difficulty::Hard);
+        world.tick(0);
+        world.save_level_dat().unwrap();
+
+        let mut loaded = World::new(config());
+        loaded.load_level_dat(UnknownFields::Reject).unwrap();
+        assert_eq!(World::saved_seed(&loaded.name).unwrap(), Some(7));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.difficulty(), Difficulty::Hard);
+        assert_eq!(loaded.game_rules(), world.game_rules());
+        assert_eq!((loaded.age(), loaded.time_of_day()), (1, 1));
+        assert_eq!(loaded.weather(), world.weather());
+    }
+
+    #[test]
+    fn load_old_level_dat() {
+        let dir = env::temp_dir().join(format!("siderite-old-level-dat-{}", std::process::id()));
+        let mut world = World::new(WorldConfig {
+            name: dir.to_string_lossy().into_owned(),
+            dimension: Dimension::Overworld,
+            spawn_pos: Coord::new(0, 16, 0),
+            spawn_chunks_radius: 0,
+            difficulty: Difficulty::Easy,
+            generator: GeneratorType::Flat,
+            seed: 0
+        });
+
+        // Written without a data version, with the game rules as typed tags
+        let mut rules = Compound::new();
+        rules.insert(game_rules::KEEP_INVENTORY, Tag::Byte(1));
+        rules.insert(game_rules::RANDOM_TICK_SPEED, Tag::Int(7));
+        let mut data = Compound::new();
+        data.insert("GameRules", Tag::Compound(rules));
+        data.insert("DayTime", Tag::Long(6000));
+        data.insert("LevelName", Tag::String("old".to_owned()));
+        let mut root = Compound::new();
+        root.insert("Data", Tag::Compound(data));
+        fs::create_dir_all(&dir).unwrap();
+        siderite_nbt::write_gzip(File::create(world.level_dat_path()).unwrap(), "", &Tag::Compound(root)).unwrap();
+
+        assert_eq!(world.load_level_dat(UnknownFields::Reject).unwrap_err().kind(), ErrorKind::InvalidData);
+        world.load_level_dat(UnknownFields::Preserve).unwrap();
+        assert!(world.game_rules().get_bool(game_rules::KEEP_INVENTORY));
+        assert_eq!(world.game_rules().get_int(game_rules::RANDOM_TICK_SPEED), 7);
+        assert_eq!(world.time_of_day(), 6000);
+
+        // Saving tags the file with the current version and keeps the unknown fields
+        world.save_level_dat().unwrap();
+        let (_, root) = siderite_nbt::read_gzip(BufReader::new(File::open(world.level_dat_path()).unwrap())).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let data = root.as_compound().and_then(|v| v.get("Data")).and_then(Tag::as_compound).unwrap();
+        assert_eq!(versioning::version_of(data), versioning::DATA_VERSION);
+        assert_eq!(data.get("LevelName").and_then(Tag::as_str), Some("old"));
+    }
+
+    #[test]
+    fn sky_light_only_in_overworld() {
+        assert!(test_world(Dimension::Overworld).has_sky_light());
+        assert!(!test_world(Dimension::Nether).has_sky_light());
+        assert!(!test_world(Dimension::End).has_sky_light());
+    }
+
+    #[test]
+    fn get_block_state() {
+        let world = test_world(Dimension::Overworld);
+        world.chunk_map().touch_chunk(ChunkCoord { x: -1, z: 2 });
+        world.set_block(Coord::new(-3, 20, 40), BlockType::Obsidian, 0);
+        assert_eq!(world.get_block_state(Coord::new(-3, 20, 40)), (BlockType::Obsidian, 0));
+        assert_eq!(world.get_block_state(Coord::new(-3, 15, 40)), (BlockType::Dirt, 0));
+        // Not loaded
+        assert_eq!(world.get_block_state(Coord::new(100, 15, 100)), (BlockType::Air, 0));
+    }
+
+    #[test]
+    fn safe_spawn() {
+        let world = test_world(Dimension::Overworld);
+        // The ground is at y 15, spawning in the air or in the ground moves the player onto it
+        assert_eq!(world.find_safe_spawn(Coord::new(3, 40, 3)), Coord::new(3, 16, 3));
+        assert_eq!(world.find_safe_spawn(Coord::new(3, 5, 3)), Coord::new(3, 16, 3));
+
+        // A low ceiling pushes the player to the top of it
+        world.set_block(Coord::new(3, 17, 3), BlockType::Stone, 0);
+        assert_eq!(world.find_safe_spawn(Coord::new(3, 16, 3)), Coord::new(3, 18, 3));
+
+        // Lava and water aren't safe, the closest column next to them is used
+        for y in 16..HEIGHT {
+            world.set_block(Coord::new(-5, y, 0), BlockType::Lava, 0);
+        }
+        assert_eq!(world.find_safe_spawn(Coord::new(-5, 16, 0)), Coord::new(-6, 16, -1));
+
+        // Bedrock up to the build limit, then there's no spot at all
+        for x in 1..=2 {
+            for z in 1..=2 {
+                world.chunk_map().touch_chunk(ChunkCoord { x, z });
+            }
+        }
+        for x in 20..=36 {
+            for z in 20..=36 {
+                for y in 0..HEIGHT {
+                    world.set_block(Coord::new(x, y, z), BlockType::Bedrock, 0);
+                }
+            }
+        }
+        assert_eq!(world.find_safe_spawn(Coord::new(28, 300, 28)), Coord::new(28, 300, 28));
+    }
+
+    #[test]
+    fn bucket_fill_and_empty() {
+        let world = test_world(Dimension::Overworld);
+        let water = Coord::new(0, 16, 0);
+        // Looking straight down at the water
+        let eye = Coord::new(0.5, 17.5, 0.5);
+        world.set_block(water, BlockType::Water, 0);
+
+        assert_eq!(world.use_bucket(eye, 0.0, 90.0, ItemType::Bucket), Some((Some(water), ItemType::WaterBucket)));
+        assert_eq!(world.get_block(water), Some((BlockType::Air, 0)));
+
+        // Nothing left to pick up, the dirt below isn't a liquid
+        assert_eq!(world.use_bucket(eye, 0.0, 90.0, ItemType::Bucket), None);
+
+        assert_eq!(world.use_bucket(eye, 0.0, 90.0, ItemType::LavaBucket), Some((Some(water), ItemType::Bucket)));
+        assert_eq!(world.get_block(water), Some((BlockType::Lava, 0)));
+    }
+
+    #[test]
+    fn water_evaporates_in_nether() {
+        let world = test_world(Dimension::Nether);
+        let eye = Coord::new(0.5, 17.5, 0.5);
+
+        assert_eq!(world.use_bucket(eye, 0.0, 90.0, ItemType::WaterBucket), Some((None, ItemType::Bucket)));
+        assert_eq!(world.get_block(Coord::new(0, 16, 0)), Some((BlockType::Air, 0)));
+    }
+
+    #[test]
+    fn primed_tnt_detonates() {
+        let mut world = test_world(Dimension::Overworld);
+        let pos = Coord::new(2, 16, 2);
+        assert!(world.prime_tnt(pos, 2).is_none());
+
+        world.set_block(pos, BlockType::Tnt, 0);
+        let tnt = world.prime_tnt(pos, 2).unwrap();
+        assert_eq!(world.get_block(pos), Some((BlockType::Air, 0)));
+        assert!(world.tick(0).is_empty());
+        let detonated = world.tick(0);
+        assert_eq!(detonated.len(), 1);
+        assert!(Arc::ptr_eq(&detonated[0], &tnt));
+        assert!(world.get_entity(tnt.read().unwrap().id()).is_none());
+    }
+
+    #[test]
+    fn ignite() {
+        let world = test_world(Dimension::Overworld);
+        let dirt = Coord::new(3, 15, 3);
+
+        assert_eq!(world.ignite(dirt, BlockFace::YP), Some(Coord::new(3, 16, 3)));
+        assert_eq!(world.get_block(Coord::new(3, 16, 3)), Some((BlockType::Fire, 0)));
+        // The dirt next to it is in the way
+        assert_eq!(world.ignite(Coord::new(3, 14, 3), BlockFace::ZP), None);
+    }
+
+    #[test]
+    fn scheduled_ticks() {
+        let mut world = test_world(Dimension::Overworld);
+        let (a, b) = (Coord::new(0, 16, 0), Coord::new(1, 16, 0));
+        world.schedule_tick(a, 2);
+        world.schedule_tick(b, 1);
+        assert!(world.take_due_ticks().is_empty());
+        world.tick(0);
+        assert_eq!(world.take_due_ticks(), [b]);
+        world.tick(0);
+        assert_eq!(world.take_due_ticks(), [a]);
+        world.tick(0);
+        assert!(world.take_due_ticks().is_empty());
+    }
 }
@@ -0,0 +1,246 @@
+//! Scoreboard teams, which color the names of their players and can stop them from hurting each other
+
+use std::collections::HashMap;
+
+/// Max length of a team name
+pub const MAX_NAME_LEN: usize = 16;
+
+/// Who can see the name tags of the players on a team
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NameTagVisibility {
+    Always,
+    HideForOtherTeams,
+    HideForOwnTeam,
+    Never
+}
+
+impl NameTagVisibility {
+    pub fn name(self) -> &'static str {
+        match self {
+            NameTagVisibility::Always => "always",
+            NameTagVisibility::HideForOtherTeams => "hideForOtherTeams",
+            NameTagVisibility::HideForOwnTeam => "hideForOwnTeam",
+            NameTagVisibility::Never => "never"
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Team {
+    name: String,
+    display_name: String,
+    /// Shown before the names of the players, used to color them
+    prefix: String,
+    suffix: String,
+    /// Players on the team can hurt each other
+    friendly_fire: bool,
+    name_tag_visibility: NameTagVisibility,
+    /// 0 - 15, index of the legacy color code of the team
+    color: Option<u8>,
+    /// Names of the players on the team
+    players: Vec<String>
+}
+
+impl Team {
+    /// Returns an empty team, with friendly fire on like in vanilla.
+    /// The color is used as prefix so it colors the names of the players.
+    pub fn new(name: String, color: Option<u8>) -> Self {
+        Self {
+            display_name: name.clone(),
+            name,
+            prefix: color.map(|v| format!("§{:x}", v)).unwrap_or_default(),
+            suffix: String::new(),
+            friendly_fire: true,
+            name_tag_visibility: NameTagVisibility::Always,
+            color,
+            players: Vec::new()
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    pub fn set_display_name(&mut self, display_name: String) {
+        self.display_name = display_name;
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn suffix(&self) -> &str {
+        &self.suffix
+    }
+
+    pub fn set_prefix_suffix(&mut self, prefix: String, suffix: String) {
+        self.prefix = prefix;
+        self.suffix = suffix;
+    }
+
+    pub fn friendly_fire(&self) -> bool {
+        self.friendly_fire
+    }
+
+    pub fn set_friendly_fire(&mut self, friendly_fire: bool) {
+        self.friendly_fire = friendly_fire;
+    }
+
+    pub fn name_tag_visibility(&self) -> NameTagVisibility {
+        self.name_tag_visibility
+    }
+
+    pub fn set_name_tag_visibility(&mut self, visibility: NameTagVisibility) {
+        self.name_tag_visibility = visibility;
+    }
+
+    pub fn color(&self) -> Option<u8> {
+        self.color
+    }
+
+    pub fn players(&self) -> &[String] {
+        &self.players
+    }
+
+    /// Returns the name of the player with the prefix and suffix of the team
+    pub fn format_name(&self, player: &str) -> String {
+        format!("{}{}{}", self.prefix, player, self.suffix)
+    }
+}
+
+/// What a Teams packet changes
+#[derive(Clone, Debug, PartialEq)]
+pub enum TeamAction {
+    /// The team with its info and players
+    Create(Team),
+    Remove,
+    /// The team with its new info
+    UpdateInfo(Team),
+    /// Names of the players
+    AddPlayers(Vec<String>),
+    /// Names of the players
+    RemovePlayers(Vec<String>)
+}
+
+impl TeamAction {
+    /// Returns the mode of the Teams packet
+    pub fn mode(&self) -> u8 {
+        match self {
+            TeamAction::Create(_) => 0,
+            TeamAction::Remove => 1,
+            TeamAction::UpdateInfo(_) => 2,
+            TeamAction::AddPlayers(_) => 3,
+            TeamAction::RemovePlayers(_) => 4
+        }
+    }
+}
+
+/// The teams of the server, a player can be on one team at most
+#[derive(Default)]
+pub struct TeamManager {
+    teams: HashMap<String, Team>
+}
+
+impl TeamManager {
+    pub fn get(&self, name: &str) -> Option<&Team> {
+        self.teams.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Team> {
+        self.teams.get_mut(name)
+    }
+
+    pub fn teams(&self) -> impl Iterator<Item = &Team> {
+        self.teams.values()
+    }
+
+    /// Adds the team, returns false if there already is a team with the name
+    pub fn create(&mut self, team: Team) -> bool {
+        if self.teams.contains_key(&team.name) {
+            return false;
+        }
+
+        self.teams.insert(team.name.clone(), team);
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Team> {
+        self.teams.remove(name)
+    }
+
+    /// Returns the team the player is on
+    pub fn team_of(&self, player: &str) -> Option<&Team> {
+        self.teams.values().find(|team| team.players.iter().any(|v| v == player))
+    }
+
+    /// Adds the player to the team, returns false if there is no team with the name.
+    /// The player has to leave its old team first.
+    pub fn join(&mut self, name: &str, player: &str) -> bool {
+        debug_assert!(self.team_of(player).is_none());
+        match self.teams.get_mut(name) {
+            Some(team) => {
+                team.players.push(player.to_owned());
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Removes the player from its team, returns the name of the team it left
+    pub fn leave(&mut self, player: &str) -> Option<String> {
+        let team = self.teams.values_mut().find(|team| team.players.iter().any(|v| v == player))?;
+        team.players.retain(|v| v != player);
+        Some(team.name.clone())
+    }
+
+    /// Returns false if both players are on a team without friendly fire
+    pub fn can_hurt(&self, attacker: &str, target: &str) -> bool {
+        match self.team_of(attacker) {
+            Some(team) => team.friendly_fire || !team.players.iter().any(|v| v == target),
+            None => true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_and_leave() {
+        let mut teams = TeamManager::default();
+        assert!(teams.create(Team::new("red".to_owned(), Some(0xc))));
+        assert!(!teams.create(Team::new("red".to_owned(), None)));
+        assert!(teams.create(Team::new("blue".to_owned(), Some(0x9))));
+        assert_eq!(teams.get("red").unwrap().prefix(), "§c");
+
+        assert!(teams.join("red", "Alice"));
+        assert!(teams.join("red", "Bob"));
+        assert!(!teams.join("green", "Carol"));
+        assert_eq!(teams.team_of("Alice").map(Team::name), Some("red"));
+        assert_eq!(teams.team_of("Carol"), None);
+
+        assert_eq!(teams.leave("Bob").as_deref(), Some("red"));
+        assert_eq!(teams.leave("Bob"), None);
+        assert!(teams.join("blue", "Bob"));
+        assert_eq!(teams.get("red").unwrap().players(), ["Alice"]);
+    }
+
+    #[test]
+    fn friendly_fire() {
+        let mut teams = TeamManager::default();
+        teams.create(Team::new("red".to_owned(), None));
+        teams.join("red", "Alice");
+        teams.join("red", "Bob");
+
+        assert!(teams.can_hurt("Alice", "Bob"));
+        teams.get_mut("red").unwrap().set_friendly_fire(false);
+        assert!(!teams.can_hurt("Alice", "Bob"));
+        assert!(teams.can_hurt("Alice", "Carol"));
+        assert!(teams.can_hurt("Carol", "Alice"));
+    }
+}
@@ -0,0 +1,120 @@
+//! Helpers shared by the unit tests
+
+use std::env;
+use std::io::{self, Error, ErrorKind};
+use std::process;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, Sender};
+use serde_json as json;
+
+use crate::auth::AuthInfo;
+use crate::client::Client;
+use crate::coord::Coord;
+use crate::entities::player::{GameMode, Player};
+use crate::protocol::packets::Packet;
+use crate::server::{self, Server, ServerConfig};
+use crate::skins::SkinFetcher;
+use crate::storage::chunk::generator::GeneratorType;
+use crate::storage::world::{Difficulty, Dimension, World, WorldConfig};
+
+/// Number of worlds handed out by `test_config`, to give each its own directory
+static TEST_WORLDS: AtomicU32 = AtomicU32::new(0);
+
+pub fn test_server() -> Arc<Server> {
+    test_server_with_auth(false).0
+}
+
+/// Returns a server with its worlds loaded and the receiving end of its authenticator
+pub fn test_server_with_auth(strict_protocol: bool) -> (Arc<Server>, Receiver<AuthInfo>) {
+    test_server_with_config(ServerConfig {
+        strict_protocol,
+        ..test_config()
+    })
+}
+
+/// Returns the config of the test servers, connections aren't throttled as all tests connect from localhost
+pub fn test_config() -> ServerConfig {
+    ServerConfig {
+        view_distance: 10,
+        default_gamemode: GameMode::Survival,
+        // Keep files written by the server, like player statistics, out of the source tree
+        // and out of the way of tests running at the same time
+        level_name: env::temp_dir()
+            .join(format!("siderite-test-world-{}-{}", process::id(), TEST_WORLDS.fetch_add(1, Ordering::Relaxed)))
+            .to_string_lossy()
+            .into_owned(),
+        motd: "A Minecraft Server".to_owned(),
+        difficulty: Difficulty::Easy,
+        compression_threshold: None,
+        // Most tests build on the flat layer of dirt
+        level_type: "FLAT".to_owned(),
+        level_seed: None,
+        max_players: 20,
+        encryption: false,
+        spawn_chunks_radius: 0,
+        strict_protocol: false,
+        connection_throttle: Duration::ZERO,
+        connection_throttle_limit: 0,
+        strict_save_format: false,
+        auto_save_interval: 0,
+        dual_stack: true,
+        keep_alive_interval: Duration::from_secs(1),
+        read_buffer_size: 8 * 1024
+    }
+}
+
+/// Returns a server with the config, its worlds loaded and the receiving end of its authenticator
+pub fn test_server_with_config(config: ServerConfig) -> (Arc<Server>, Receiver<AuthInfo>) {
+    let (tx, rx): (Sender<AuthInfo>, _) = crossbeam_channel::unbounded();
+    let mut server = Server::new(config, None, tx);
+    server.load_worlds().unwrap();
+    (Arc::new(server), rx)
+}
+
+/// Returns a world without a server with dirt from y 0 to 15 around the origin
+pub fn test_world(dimension: Dimension) -> World {
+    World::new(WorldConfig {
+        name: "world".to_owned(),
+        dimension,
+        spawn_pos: Coord::new(0, 16, 0),
+        spawn_chunks_radius: 1,
+        difficulty: Difficulty::Easy,
+        generator: GeneratorType::Flat,
+        seed: 0
+    })
+}
+
+/// Returns a player that joined the default world of the server, without a connection,
+/// and the receiving end of the packets sent to it
+pub fn test_player(server: &Arc<Server>, gamemode: GameMode) -> (Arc<RwLock<Player>>, Receiver<Packet>) {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let id = server::get_next_entity_id();
+    let client = Arc::new(RwLock::new(Client::new(id, server.clone(), tx)));
+    let world = server.default_world();
+    let spawn = world.read().unwrap().spawn_pos();
+    let player = Arc::new(RwLock::new(Player::new(client.clone(), world.clone(), gamemode, spawn.into())));
+    client.write().unwrap().set_player(player.clone());
+    world.write().unwrap().add_player(id, player.clone());
+    (player, rx)
+}
+
+/// Skin fetcher that gives everyone but Steve a skin with their name as the value, or fails,
+/// and counts how often it's called
+pub struct MockSkinFetcher {
+    pub calls: Arc<AtomicU32>,
+    pub fail: bool
+}
+
+impl SkinFetcher for MockSkinFetcher {
+    fn fetch(&self, username: &str) -> io::Result<Option<json::Value>> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if self.fail {
+            return Err(Error::new(ErrorKind::TimedOut, "timed out"));
+        }
+
+        Ok((username != "Steve").then(|| json::json!([{ "name": "textures", "value": username }])))
+    }
+}
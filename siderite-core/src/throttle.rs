@@ -0,0 +1,84 @@
+//! Limits how often an address can log in, so one client can't flood the server with connections
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Kick message for throttled connections, the same as Bukkit's
+pub const THROTTLED_MESSAGE: &str = "Connection throttled! Please wait before reconnecting.";
+
+pub struct ConnectionThrottle {
+    window: Duration,
+    /// Max number of logins from one address within the window
+    limit: usize,
+    /// When the addresses logged in within the window, oldest first
+    logins: HashMap<IpAddr, VecDeque<Instant>>
+}
+
+impl ConnectionThrottle {
+    /// A window of zero turns the throttle off
+    pub fn new(window: Duration, limit: usize) -> Self {
+        Self {
+            window,
+            limit,
+            logins: HashMap::new()
+        }
+    }
+
+    /// Records a login from the address, returns false if it logged in too often within the window.
+    /// Rejected logins don't count, so a client waiting for the window gets in.
+    pub fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if self.window.is_zero() {
+            return true;
+        }
+
+        let window = self.window;
+        self.logins.retain(|_, times| {
+            while times.front().is_some_and(|&v| now.duration_since(v) >= window) {
+                times.pop_front();
+            }
+
+            !times.is_empty()
+        });
+
+        let times = self.logins.entry(ip).or_default();
+        if times.len() >= self.limit {
+            return false;
+        }
+
+        times.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn throttles_within_window() {
+        let mut throttle = ConnectionThrottle::new(Duration::from_secs(4), 2);
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let start = Instant::now();
+
+        assert!(throttle.allow(ip, start));
+        assert!(throttle.allow(ip, start + Duration::from_secs(1)));
+        assert!(!throttle.allow(ip, start + Duration::from_secs(2)));
+        assert!(throttle.allow(other, start + Duration::from_secs(2)));
+
+        // The first login left the window
+        assert!(throttle.allow(ip, start + Duration::from_secs(4)));
+        assert!(!throttle.allow(ip, start + Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn zero_window_disables() {
+        let mut throttle = ConnectionThrottle::new(Duration::ZERO, 0);
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = Instant::now();
+        assert!((0..10).all(|_| throttle.allow(ip, now)));
+    }
+}
@@ -0,0 +1,239 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use siderite_nbt::{Compound, Tag};
+
+use crate::blocks::BlockType;
+use crate::coord::Coord;
+use crate::furnace::{FURNACE_SIZE, FurnaceProgress, SMELT_TIME};
+use crate::items::{Inventory, ItemStack};
+use crate::window::{CHEST_SIZE, SharedInventory};
+
+/// A sign line without any text, as JSON
+const EMPTY_SIGN_LINE: &str = "\"\"";
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum SkullType {
+    Skeleton = 0,
+    WitherSkeleton = 1,
+    Zombie = 2,
+    Player = 3,
+    Creeper = 4
+}
+
+/// Extra data attached to a block
+#[derive(Clone, Debug, PartialEq)]
+pub enum TileEntity {
+    MobSpawner {
+        /// Name of the entity spinning inside the spawner
+        entity_id: String
+    },
+    Skull {
+        skull_type: SkullType,
+        /// Rotation in 1/16th of a full turn, only used for skulls placed on the floor
+        rotation: u8,
+        /// Name of the player whose head this is
+        owner: Option<String>
+    },
+    Banner {
+        /// Base color
+        base: i32,
+        /// Pattern id and color of each layer
+        patterns: Vec<(String, i32)>
+    },
+    Sign {
+        /// Text of each line as a JSON chat component
+        lines: [String; 4]
+    },
+    Chest {
+        /// Contents of the chest, shared with the windows showing it
+        items: SharedInventory
+    },
+    Furnace {
+        /// Input, fuel and output slots, shared with the windows showing them
+        items: SharedInventory,
+        progress: FurnaceProgress
+    }
+}
+
+impl TileEntity {
+    /// Returns a sign without any text
+    pub fn empty_sign() -> Self {
+        TileEntity::Sign {
+            lines: std::array::from_fn(|_| EMPTY_SIGN_LINE.to_owned())
+        }
+    }
+
+    /// Returns the tile entity for a container block, None for other blocks
+    pub fn for_container(block_type: BlockType) -> Option<Self> {
+        match block_type {
+            BlockType::Chest => Some(TileEntity::Chest { items: SharedInventory::new(CHEST_SIZE) }),
+            BlockType::Furnace | BlockType::LitFurnace => Some(TileEntity::Furnace {
+                items: SharedInventory::new(FURNACE_SIZE),
+                progress: FurnaceProgress::default()
+            }),
+            _ => None
+        }
+    }
+
+    /// Returns the contents of a container
+    pub fn items(&self) -> Option<&SharedInventory> {
+        match self {
+            TileEntity::Chest { items } | TileEntity::Furnace { items, .. } => Some(items),
+            _ => None
+        }
+    }
+
+    /// Returns the id used in the NBT data of this tile entity
+    pub fn id(&self) -> &'static str {
+        match self {
+            TileEntity::MobSpawner { .. } => "MobSpawner",
+            TileEntity::Skull { .. } => "Skull",
+            TileEntity::Banner { .. } => "Banner",
+            TileEntity::Sign { .. } => "Sign",
+            TileEntity::Chest { .. } => "Chest",
+            TileEntity::Furnace { .. } => "Furnace"
+        }
+    }
+
+    /// Returns the action of the Update Block Entity packet for this tile entity.
+    /// 1.8 clients don't take signs from Update Block Entity, they get Update Sign instead.
+    /// None if the client doesn't need the tile entity, like the contents of a chest.
+    pub fn update_action(&self) -> Option<u8> {
+        match self {
+            TileEntity::MobSpawner { .. } => Some(1),
+            TileEntity::Skull { .. } => Some(4),
+            TileEntity::Banner { .. } => Some(6),
+            TileEntity::Sign { .. } => Some(9),
+            TileEntity::Chest { .. } | TileEntity::Furnace { .. } => None
+        }
+    }
+
+    pub fn to_nbt(&self, pos: Coord<i32>) -> Compound {
+        let mut nbt = Compound::new();
+        nbt.insert("id", Tag::String(self.id().to_owned()));
+        nbt.insert("x", Tag::Int(pos.x));
+        nbt.insert("y", Tag::Int(pos.y));
+        nbt.insert("z", Tag::Int(pos.z));
+
+        match self {
+            TileEntity::MobSpawner { entity_id } => {
+                nbt.insert("EntityId", Tag::String(entity_id.clone()));
+            }
+            TileEntity::Skull { skull_type, rotation, owner } => {
+                nbt.insert("SkullType", Tag::Byte(*skull_type as i8));
+                nbt.insert("Rot", Tag::Byte(*rotation as i8));
+                if let Some(owner) = owner {
+                    let mut profile = Compound::new();
+                    profile.insert("Name", Tag::String(owner.clone()));
+                    nbt.insert("Owner", Tag::Compound(profile));
+                }
+            }
+            TileEntity::Banner { base, patterns } => {
+                nbt.insert("Base", Tag::Int(*base));
+                let patterns = patterns.iter().map(|(pattern, color)| {
+                    let mut layer = Compound::new();
+                    layer.insert("Pattern", Tag::String(pattern.clone()));
+                    layer.insert("Color", Tag::Int(*color));
+                    Tag::Compound(layer)
+                }).collect();
+                nbt.insert("Patterns", Tag::List(patterns));
+            }
+            TileEntity::Sign { lines } => {
+                for (i, line) in lines.iter().enumerate() {
+                    nbt.insert(&format!("Text{}", i + 1), Tag::String(line.clone()));
+                }
+            }
+            TileEntity::Chest { items } => {
+                nbt.insert("Items", items_nbt(&items.lock()));
+            }
+            TileEntity::Furnace { items, progress } => {
+                nbt.insert("Items", items_nbt(&items.lock()));
+                nbt.insert("BurnTime", Tag::Short(progress.burn_time));
+                nbt.insert("CookTime", Tag::Short(progress.cook_time));
+                nbt.insert("CookTimeTotal", Tag::Short(SMELT_TIME));
+            }
+        }
+
+        nbt
+    }
+
+    /// Reads a tile entity written by `to_nbt`, returns its position and the tile entity.
+    /// None for unknown tile entities and missing fields.
+    pub fn from_nbt(nbt: &Compound) -> Option<(Coord<i32>, Self)> {
+        let int = |name: &str| nbt.get(name).and_then(Tag::as_int);
+        let pos = Coord::new(int("x")?, int("y")?, int("z")?);
+        let tile_entity = match nbt.get("id")?.as_str()? {
+            "MobSpawner" => TileEntity::MobSpawner {
+                entity_id: nbt.get("EntityId")?.as_str()?.to_owned()
+            },
+            "Skull" => TileEntity::Skull {
+                skull_type: SkullType::from_i8(nbt.get("SkullType")?.as_byte()?)?,
+                rotation: nbt.get("Rot").and_then(Tag::as_byte).unwrap_or(0) as u8,
+                owner: nbt.get("Owner").and_then(Tag::as_compound).and_then(|v| v.get("Name")).and_then(Tag::as_str).map(str::to_owned)
+            },
+            "Banner" => TileEntity::Banner {
+                base: int("Base")?,
+                patterns: nbt.get("Patterns").and_then(Tag::as_list).unwrap_or_default().iter()
+                    .filter_map(|layer| {
+                        let layer = layer.as_compound()?;
+                        Some((layer.get("Pattern")?.as_str()?.to_owned(), layer.get("Color")?.as_int()?))
+                    })
+                    .collect()
+            },
+            "Sign" => TileEntity::Sign {
+                lines: std::array::from_fn(|i| nbt.get(&format!("Text{}", i + 1)).and_then(Tag::as_str).unwrap_or(EMPTY_SIGN_LINE).to_owned())
+            },
+            "Chest" => TileEntity::Chest {
+                items: container_from_nbt(nbt, CHEST_SIZE)
+            },
+            "Furnace" => {
+                let burn_time = nbt.get("BurnTime").and_then(Tag::as_short).unwrap_or(0);
+                TileEntity::Furnace {
+                    items: container_from_nbt(nbt, FURNACE_SIZE),
+                    // Like vanilla the total burn time of the fuel isn't saved, the flame starts full again
+                    progress: FurnaceProgress {
+                        burn_time,
+                        fuel_time: burn_time,
+                        cook_time: nbt.get("CookTime").and_then(Tag::as_short).unwrap_or(0)
+                    }
+                }
+            }
+            _ => return None
+        };
+
+        Some((pos, tile_entity))
+    }
+}
+
+/// Returns the items of a container as a list with the slot of each item
+pub fn items_nbt(items: &Inventory) -> Tag {
+    let items = (0..items.size()).filter_map(|slot| {
+        let mut tag = items.get_slot(slot)?.to_nbt();
+        tag.insert("Slot", Tag::Byte(slot as i8));
+        Some(Tag::Compound(tag))
+    }).collect();
+
+    Tag::List(items)
+}
+
+/// Puts the items of a list written by `items_nbt` in their slots,
+/// unknown items and slots outside of the inventory are left out
+pub fn load_items(list: &Tag, items: &mut Inventory) {
+    for tag in list.as_list().unwrap_or_default().iter().filter_map(Tag::as_compound) {
+        let slot = tag.get("Slot").and_then(Tag::as_byte).and_then(|v| usize::try_from(v).ok());
+        if let (Some(slot), Some(item)) = (slot.filter(|&v| v < items.size()), ItemStack::from_nbt(tag)) {
+            items.set_slot(slot, Some(item));
+        }
+    }
+}
+
+/// Returns a container with `size` slots and the items in its Items list
+fn container_from_nbt(nbt: &Compound, size: usize) -> SharedInventory {
+    let items = SharedInventory::new(size);
+    if let Some(list) = nbt.get("Items") {
+        load_items(list, &mut items.lock());
+    }
+
+    items
+}
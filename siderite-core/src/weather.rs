@@ -0,0 +1,202 @@
+//! Rain and thunderstorms, which start and stop after a random number of ticks like in vanilla
+
+use rand::Rng;
+use siderite_nbt::{Compound, Tag};
+
+use crate::protocol::GameStateReason;
+use crate::protocol::packets::Packet;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WeatherType {
+    Clear,
+    Rain,
+    /// Rain with thunder, thunder without rain can't be seen
+    Thunder
+}
+
+impl WeatherType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clear" => Some(WeatherType::Clear),
+            "rain" => Some(WeatherType::Rain),
+            "thunder" => Some(WeatherType::Thunder),
+            _ => None
+        }
+    }
+}
+
+/// The weather of a world, stored in level.dat with the same fields as vanilla
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Weather {
+    raining: bool,
+    thundering: bool,
+    /// Ticks before it starts or stops raining, 0 picks a new random time
+    rain_time: i32,
+    /// Ticks before thunder starts or stops, 0 picks a new random time
+    thunder_time: i32,
+    /// Ticks of clear weather left from /weather clear, the other times don't run out during them
+    clear_time: i32
+}
+
+impl Weather {
+    pub fn is_raining(&self) -> bool {
+        self.raining
+    }
+
+    pub fn is_thundering(&self) -> bool {
+        self.thundering
+    }
+
+    pub fn weather_type(&self) -> WeatherType {
+        match (self.raining, self.thundering) {
+            (false, _) => WeatherType::Clear,
+            (true, false) => WeatherType::Rain,
+            (true, true) => WeatherType::Thunder
+        }
+    }
+
+    /// Sets the weather for `duration` ticks, like /weather
+    pub fn set(&mut self, weather_type: WeatherType, duration: i32) {
+        self.raining = weather_type != WeatherType::Clear;
+        self.thundering = weather_type == WeatherType::Thunder;
+        if weather_type == WeatherType::Clear {
+            self.clear_time = duration;
+            self.rain_time = 0;
+            self.thunder_time = 0;
+        } else {
+            self.clear_time = 0;
+            self.rain_time = duration;
+            self.thunder_time = duration;
+        }
+    }
+
+    /// Starts or stops the rain, like /toggledownfall
+    pub fn toggle_downfall(&mut self) {
+        self.raining = !self.raining;
+        self.rain_time = 0;
+        self.clear_time = 0;
+    }
+
+    /// Stops the rain and thunder, new times for them get picked on the next tick
+    pub fn clear(&mut self) {
+        self.raining = false;
+        self.thundering = false;
+        self.rain_time = 0;
+        self.thunder_time = 0;
+    }
+
+    /// Counts down the times, returns true if the weather changed
+    pub fn tick(&mut self, rng: &mut impl Rng) -> bool {
+        let before = self.weather_type();
+        if self.clear_time > 0 {
+            self.clear_time -= 1;
+            // Keep the times from running out, they start over once the clear weather is over
+            self.thunder_time = if self.thundering { 1 } else { 2 };
+            self.rain_time = if self.raining { 1 } else { 2 };
+        }
+
+        if self.thunder_time <= 0 {
+            self.thunder_time = if self.thundering { rng.gen_range(3600..15600) } else { rng.gen_range(12000..180000) };
+        } else {
+            self.thunder_time -= 1;
+            if self.thunder_time <= 0 {
+                self.thundering = !self.thundering;
+            }
+        }
+
+        if self.rain_time <= 0 {
+            self.rain_time = if self.raining { rng.gen_range(12000..24000) } else { rng.gen_range(12000..180000) };
+        } else {
+            self.rain_time -= 1;
+            if self.rain_time <= 0 {
+                self.raining = !self.raining;
+            }
+        }
+
+        self.weather_type() != before
+    }
+
+    /// Returns the packets that show the weather to a client
+    pub fn packets(&self) -> Vec<Packet> {
+        let (reason, thunder) = match self.weather_type() {
+            WeatherType::Clear => (GameStateReason::EndRaining, 0.0),
+            WeatherType::Rain => (GameStateReason::BeginRaining, 0.0),
+            WeatherType::Thunder => (GameStateReason::BeginRaining, 1.0)
+        };
+
+        vec![
+            Packet::ChangeGameState(reason, 0.0),
+            Packet::ChangeGameState(GameStateReason::RainLevel, if self.raining { 1.0 } else { 0.0 }),
+            Packet::ChangeGameState(GameStateReason::ThunderLevel, thunder)
+        ]
+    }
+
+    pub fn load_nbt(&mut self, data: &Compound) {
+        self.raining = data.get("raining").and_then(Tag::as_byte).unwrap_or(0) != 0;
+        self.thundering = data.get("thundering").and_then(Tag::as_byte).unwrap_or(0) != 0;
+        self.rain_time = data.get("rainTime").and_then(Tag::as_int).unwrap_or(0);
+        self.thunder_time = data.get("thunderTime").and_then(Tag::as_int).unwrap_or(0);
+        self.clear_time = data.get("clearWeatherTime").and_then(Tag::as_int).unwrap_or(0);
+    }
+
+    pub fn write_nbt(&self, data: &mut Compound) {
+        data.insert("raining", Tag::Byte(self.raining as i8));
+        data.insert("thundering", Tag::Byte(self.thundering as i8));
+        data.insert("rainTime", Tag::Int(self.rain_time));
+        data.insert("thunderTime", Tag::Int(self.thunder_time));
+        data.insert("clearWeatherTime", Tag::Int(self.clear_time));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::*;
+
+    #[test]
+    fn rain_stops_after_duration() {
+        let mut weather = Weather::default();
+        weather.set(WeatherType::Rain, 3);
+        assert_eq!(weather.weather_type(), WeatherType::Rain);
+
+        let mut rng = thread_rng();
+        assert!(!weather.tick(&mut rng));
+        assert!(!weather.tick(&mut rng));
+        assert!(weather.tick(&mut rng));
+        assert_eq!(weather.weather_type(), WeatherType::Clear);
+        // The next rain is at least half a day away
+        assert!((0..100).all(|_| !weather.tick(&mut rng)));
+    }
+
+    #[test]
+    fn clear_weather_holds() {
+        let mut weather = Weather::default();
+        weather.set(WeatherType::Thunder, 1000);
+        weather.set(WeatherType::Clear, 10);
+        let mut rng = thread_rng();
+        assert!((0..10).all(|_| !weather.tick(&mut rng)));
+        assert_eq!(weather.weather_type(), WeatherType::Clear);
+    }
+
+    #[test]
+    fn toggle_downfall() {
+        let mut weather = Weather::default();
+        weather.toggle_downfall();
+        assert!(weather.is_raining());
+        weather.toggle_downfall();
+        assert!(!weather.is_raining());
+    }
+
+    #[test]
+    fn nbt_roundtrip() {
+        let mut weather = Weather::default();
+        weather.set(WeatherType::Thunder, 600);
+        let mut data = Compound::new();
+        weather.write_nbt(&mut data);
+
+        let mut loaded = Weather::default();
+        loaded.load_nbt(&data);
+        assert_eq!(loaded, weather);
+    }
+}
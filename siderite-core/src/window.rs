@@ -0,0 +1,813 @@
+//! Container windows: chests, ender chests, furnaces, enchanting tables and anvils show their own slots above the player inventory
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::anvil;
+use crate::coord::Coord;
+use crate::crafting::{self, RecipeRegistry};
+use crate::enchanting;
+use crate::furnace;
+use crate::items::{ARMOR_START, HOTBAR_SIZE, HOTBAR_START, INVENTORY_SIZE, Inventory, ItemStack, ItemType, MAIN_START};
+
+/// Number of slots in a single chest
+pub const CHEST_SIZE: usize = 27;
+
+/// Number of slots in the ender chest of a player
+pub const ENDER_CHEST_SIZE: usize = 27;
+
+/// Id of the player inventory window, it's always open
+pub const PLAYER_WINDOW_ID: u8 = 0;
+
+/// Highest window id, the ids wrap around to 1 after it
+const MAX_WINDOW_ID: u8 = 100;
+
+/// Slot number for clicks outside of the window
+const OUTSIDE_SLOT: i16 = -999;
+
+/// An inventory shared by a container and the windows showing it
+#[derive(Clone, Debug)]
+pub struct SharedInventory(Arc<Mutex<Inventory>>);
+
+impl SharedInventory {
+    pub fn new(size: usize) -> Self {
+        Self(Arc::new(Mutex::new(Inventory::with_size(size))))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Inventory> {
+        self.0.lock().unwrap()
+    }
+}
+
+/// Two shared inventories are only equal when they're the same inventory
+impl PartialEq for SharedInventory {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// A container with state shown by its window through Window Property packets, like the flame of a furnace
+pub trait WindowPropertySink {
+    /// Returns all properties of the window as index and value
+    fn window_properties(&self) -> Vec<(i16, i16)>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WindowType {
+    Chest,
+    EnderChest,
+    Furnace,
+    EnchantingTable,
+    Anvil
+}
+
+impl WindowType {
+    /// Returns the window type sent in Open Window
+    pub fn id(self) -> &'static str {
+        // Ender chests use the chest window with another title
+        match self {
+            WindowType::Chest | WindowType::EnderChest => "minecraft:chest",
+            WindowType::Furnace => "minecraft:furnace",
+            WindowType::EnchantingTable => "minecraft:enchanting_table",
+            WindowType::Anvil => "minecraft:anvil"
+        }
+    }
+
+    /// Returns false if the item can't go in the container slot, like the slots only the container itself puts items in
+    pub fn can_place(self, slot: usize, item: &ItemStack) -> bool {
+        match self {
+            WindowType::Furnace => slot != furnace::OUTPUT_SLOT,
+            WindowType::EnchantingTable => slot != enchanting::LAPIS_SLOT || enchanting::is_lapis(item),
+            WindowType::Anvil => slot != anvil::OUTPUT_SLOT,
+            _ => true
+        }
+    }
+
+    /// Returns the most items the container slot holds
+    fn slot_limit(self, slot: usize) -> i8 {
+        if self == WindowType::EnchantingTable && slot == enchanting::ITEM_SLOT { 1 } else { i8::MAX }
+    }
+
+    /// Returns true for windows whose items belong to the player and go back to it when the window closes
+    pub fn returns_items(self) -> bool {
+        matches!(self, WindowType::EnchantingTable | WindowType::Anvil)
+    }
+
+    /// Returns the title of the window as a JSON chat component
+    pub fn title(self) -> &'static str {
+        match self {
+            WindowType::Chest => r#"{"translate":"container.chest"}"#,
+            WindowType::EnderChest => r#"{"translate":"container.enderchest"}"#,
+            WindowType::Furnace => r#"{"translate":"container.furnace"}"#,
+            WindowType::EnchantingTable => r#"{"translate":"container.enchant"}"#,
+            WindowType::Anvil => r#"{"translate":"container.repair"}"#
+        }
+    }
+}
+
+/// A container window the player has open
+#[derive(Debug)]
+pub struct Window {
+    id: u8,
+    window_type: WindowType,
+    inventory: SharedInventory,
+    /// Block the window belongs to, the window gets closed when the block is destroyed
+    block: Option<Coord<i32>>,
+    /// Values of the window properties the client has, by index
+    properties: HashMap<i16, i16>
+}
+
+impl Window {
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn window_type(&self) -> WindowType {
+        self.window_type
+    }
+
+    pub fn inventory(&self) -> &SharedInventory {
+        &self.inventory
+    }
+
+    pub fn block(&self) -> Option<Coord<i32>> {
+        self.block
+    }
+}
+
+/// Outcome of a click in a window
+#[derive(Debug, PartialEq)]
+pub enum Click {
+    Done,
+    /// The item was thrown out of the window
+    Drop(ItemStack),
+    /// The click wasn't applied, the client has to get the window contents again
+    Rejected
+}
+
+/// A slot of a window, either in the container or in the player inventory
+#[derive(Copy, Clone, PartialEq)]
+enum WindowSlot {
+    Container(usize),
+    Player(usize)
+}
+
+/// The inventories shown by a window, locked while a click moves items around in them
+struct Slots<'a> {
+    window_type: Option<WindowType>,
+    container: Option<MutexGuard<'a, Inventory>>,
+    player: &'a mut Inventory
+}
+
+impl Slots<'_> {
+    fn get(&self, slot: WindowSlot) -> Option<&ItemStack> {
+        match slot {
+            WindowSlot::Container(i) => self.container.as_ref().and_then(|v| v.get_slot(i)),
+            WindowSlot::Player(i) => self.player.get_slot(i)
+        }
+    }
+
+    fn set(&mut self, slot: WindowSlot, item: Option<ItemStack>) {
+        match slot {
+            WindowSlot::Container(i) => self.container.as_mut().expect("container slot without a container").set_slot(i, item),
+            WindowSlot::Player(i) => self.player.set_slot(i, item)
+        }
+    }
+
+    /// Returns true if the item can go in the slot, armor only goes in its own armor slot
+    fn accepts(&self, slot: WindowSlot, item: &ItemStack) -> bool {
+        match slot {
+            WindowSlot::Container(i) => self.window_type.map_or(true, |v| v.can_place(i, item)),
+            WindowSlot::Player(crafting::RESULT_SLOT) => false,
+            WindowSlot::Player(i) if (ARMOR_START..MAIN_START).contains(&i) =>
+                item.item_type().and_then(ItemType::armor_type) == Some(i - ARMOR_START),
+            WindowSlot::Player(_) => true
+        }
+    }
+
+    fn limit(&self, slot: WindowSlot) -> i8 {
+        match slot {
+            WindowSlot::Container(i) => self.window_type.map_or(i8::MAX, |v| v.slot_limit(i)),
+            WindowSlot::Player(i) if (ARMOR_START..MAIN_START).contains(&i) => 1,
+            WindowSlot::Player(_) => i8::MAX
+        }
+    }
+
+    /// Returns how many of the item fit into the slots
+    fn space_for(&self, item: &ItemStack, targets: &[WindowSlot]) -> i32 {
+        targets.iter().filter(|v| self.accepts(**v, item)).map(|&target| {
+            let limit = self.limit(target).min(item.max_stack_size()) as i32;
+            match self.get(target) {
+                Some(v) if WindowManager::stacks_with(v, item) => (limit - v.count as i32).max(0),
+                Some(_) => 0,
+                None => limit
+            }
+        }).sum()
+    }
+
+    /// Moves as much of the item as fits into the slots, like vanilla onto matching stacks first and then into empty slots.
+    /// Returns what's left of the item.
+    fn merge(&mut self, mut item: ItemStack, targets: &[WindowSlot]) -> Option<ItemStack> {
+        for fill_empty in [false, true] {
+            for &target in targets {
+                if item.count <= 0 || !self.accepts(target, &item) {
+                    continue;
+                }
+
+                let limit = self.limit(target).min(item.max_stack_size());
+                let (moved, stack) = match self.get(target) {
+                    Some(v) if !fill_empty && WindowManager::stacks_with(v, &item) => {
+                        let moved = (limit - v.count).clamp(0, item.count);
+                        (moved, ItemStack { count: v.count + moved, ..v.clone() })
+                    }
+                    None if fill_empty => {
+                        let moved = limit.min(item.count);
+                        (moved, ItemStack { count: moved, ..item.clone() })
+                    }
+                    _ => continue
+                };
+
+                if moved > 0 {
+                    self.set(target, Some(stack));
+                    item.count -= moved;
+                }
+            }
+        }
+
+        Some(item).filter(|v| v.count > 0)
+    }
+}
+
+/// Keeps track of the window a player has open and the item held by its cursor
+#[derive(Debug)]
+pub struct WindowManager {
+    /// Id the next opened window gets
+    next_id: u8,
+    open: Option<Window>,
+    /// Item held by the cursor
+    cursor: Option<ItemStack>
+}
+
+impl Default for WindowManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WindowManager {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            open: None,
+            cursor: None
+        }
+    }
+
+    pub fn open_window(&self) -> Option<&Window> {
+        self.open.as_ref()
+    }
+
+    pub fn cursor(&self) -> Option<&ItemStack> {
+        self.cursor.as_ref()
+    }
+
+    /// Returns the item held by the cursor and empties it
+    pub fn take_cursor(&mut self) -> Option<ItemStack> {
+        self.cursor.take()
+    }
+
+    /// Opens a window showing the inventory, replacing the window that was open
+    pub fn open(&mut self, window_type: WindowType, inventory: SharedInventory, block: Option<Coord<i32>>) -> &Window {
+        let id = self.next_id;
+        self.next_id = if id >= MAX_WINDOW_ID { 1 } else { id + 1 };
+        self.open.insert(Window { id, window_type, inventory, block, properties: HashMap::new() })
+    }
+
+    /// Returns the properties of the open window that changed since they were last sent, and remembers them as sent
+    pub fn changed_properties(&mut self, sink: &impl WindowPropertySink) -> Vec<(i16, i16)> {
+        let window = match &mut self.open {
+            Some(v) => v,
+            None => return Vec::new()
+        };
+
+        sink.window_properties().into_iter()
+            .filter(|&(index, value)| window.properties.insert(index, value) != Some(value))
+            .collect()
+    }
+
+    /// Closes the window if it's the open one, returns false otherwise
+    pub fn close(&mut self, window_id: u8) -> bool {
+        if self.open.as_ref().is_some_and(|v| v.id == window_id) {
+            self.open = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Closes the open window if it belongs to the block at `pos`, returns the id of the closed window
+    pub fn close_at(&mut self, pos: Coord<i32>) -> Option<u8> {
+        let id = self.open.as_ref().filter(|v| v.block == Some(pos))?.id;
+        self.open = None;
+        Some(id)
+    }
+
+    /// Returns the items in all slots of the window, the container slots followed by the player inventory
+    pub fn window_items(&self, window_id: u8, player: &Inventory) -> Option<Vec<Option<ItemStack>>> {
+        if window_id == PLAYER_WINDOW_ID {
+            return Some((0..INVENTORY_SIZE).map(|slot| player.get_slot(slot).cloned()).collect());
+        }
+
+        let window = self.open.as_ref().filter(|v| v.id == window_id)?;
+        let container = window.inventory.lock();
+        let mut items: Vec<_> = (0..container.size()).map(|slot| container.get_slot(slot).cloned()).collect();
+        items.extend((MAIN_START..INVENTORY_SIZE).map(|slot| player.get_slot(slot).cloned()));
+        Some(items)
+    }
+
+    /// Applies a click in the window.
+    /// Left and right clicks (mode 0), shift clicks (mode 1) and number keys (mode 2) are supported, everything else gets rejected.
+    pub fn click(&mut self, window_id: u8, slot: i16, button: i8, mode: u8, player: &mut Inventory) -> Click {
+        match mode {
+            0 if (0..=1).contains(&button) => (),
+            1 if (0..=1).contains(&button) => return self.shift_click(window_id, slot, player),
+            2 if (0..HOTBAR_SIZE as i8).contains(&button) => return self.swap_with_hotbar(window_id, slot, button as usize, player),
+            _ => return Click::Rejected
+        }
+
+        if slot == OUTSIDE_SLOT {
+            return match self.cursor.take() {
+                // Right click drops one item
+                Some(mut item) if button == 1 && item.count > 1 => {
+                    let mut dropped = item.clone();
+                    dropped.count = 1;
+                    item.count -= 1;
+                    self.cursor = Some(item);
+                    Click::Drop(dropped)
+                }
+                Some(item) => Click::Drop(item),
+                None => Click::Done
+            };
+        }
+
+        let window_slot = match self.resolve(window_id, slot) {
+            Some(v) => v,
+            None => return Click::Rejected
+        };
+
+        let mut slots = self.slots(window_id, player);
+        if self.cursor.as_ref().is_some_and(|v| !slots.accepts(window_slot, v)) {
+            return Click::Rejected;
+        }
+
+        let item = slots.get(window_slot).cloned();
+        let (item, cursor) = Self::click_slot(item, self.cursor.clone(), button == 1);
+        slots.set(window_slot, item);
+        drop(slots);
+        self.cursor = cursor;
+
+        Click::Done
+    }
+
+    /// Takes the result of the crafting grid of the player inventory, which uses up one of every ingredient.
+    /// Shift clicks craft as often as the ingredients allow and move the results into the inventory.
+    pub fn take_crafting_result(&mut self, button: i8, mode: u8, player: &mut Inventory, recipes: &RecipeRegistry) -> Click {
+        if !(0..=1).contains(&button) {
+            return Click::Rejected;
+        }
+
+        match mode {
+            0 => {
+                let result = match player.get_slot(crafting::RESULT_SLOT) {
+                    Some(v) => v.clone(),
+                    None => return Click::Done
+                };
+
+                let cursor = match self.cursor.take() {
+                    None => result,
+                    // More results pile up on the cursor
+                    Some(v) if Self::stacks_with(&v, &result) && result.count <= v.max_stack_size() - v.count => {
+                        ItemStack { count: v.count + result.count, ..v }
+                    }
+                    cursor => {
+                        self.cursor = cursor;
+                        return Click::Rejected;
+                    }
+                };
+                self.cursor = Some(cursor);
+                crafting::consume_ingredients(player, recipes);
+            }
+            1 => {
+                // Like in vanilla the results fill the hotbar from its end
+                let targets: Vec<_> = (MAIN_START..INVENTORY_SIZE).rev().map(WindowSlot::Player).collect();
+                let mut slots = Slots { window_type: None, container: None, player };
+                while let Some(result) = slots.player.get_slot(crafting::RESULT_SLOT).cloned() {
+                    // Only craft while the whole result fits
+                    if slots.space_for(&result, &targets) < result.count as i32 {
+                        break;
+                    }
+
+                    slots.merge(result, &targets);
+                    crafting::consume_ingredients(slots.player, recipes);
+                }
+            }
+            _ => return Click::Rejected
+        }
+
+        Click::Done
+    }
+
+    /// Moves the stack in the slot to the other part of the window, like from a chest into the player inventory.
+    /// Inside the player inventory items move between the main inventory and the hotbar, armor goes into the armor slots.
+    fn shift_click(&self, window_id: u8, slot: i16, player: &mut Inventory) -> Click {
+        let window_slot = match self.resolve(window_id, slot) {
+            Some(v) => v,
+            None => return Click::Rejected
+        };
+
+        let mut slots = self.slots(window_id, player);
+        let item = match slots.get(window_slot) {
+            Some(v) => v.clone(),
+            None => return Click::Done
+        };
+
+        let targets = Self::shift_targets(&slots, window_slot, &item);
+        let rest = slots.merge(item, &targets);
+        slots.set(window_slot, rest);
+        Click::Done
+    }
+
+    /// Returns the slots a shift click on the slot moves the item to, in the order vanilla fills them
+    fn shift_targets(slots: &Slots, slot: WindowSlot, item: &ItemStack) -> Vec<WindowSlot> {
+        let player = |range: Range<usize>, reverse: bool| -> Vec<WindowSlot> {
+            let iter = range.map(WindowSlot::Player);
+            if reverse { iter.rev().collect() } else { iter.collect() }
+        };
+        // The main inventory and the hotbar move items to each other
+        let other_part = |i: usize| if i < HOTBAR_START { player(HOTBAR_START..INVENTORY_SIZE, false) } else { player(MAIN_START..HOTBAR_START, false) };
+        let size = slots.container.as_ref().map_or(0, |v| v.size());
+
+        let i = match slot {
+            WindowSlot::Container(i) => {
+                // Vanilla fills the player inventory from the end, except with items that didn't come out of the container
+                let reverse = match slots.window_type {
+                    Some(WindowType::Furnace) => i == furnace::OUTPUT_SLOT,
+                    Some(WindowType::Anvil) => i == anvil::OUTPUT_SLOT,
+                    _ => true
+                };
+                return player(MAIN_START..INVENTORY_SIZE, reverse);
+            }
+            WindowSlot::Player(i) => i
+        };
+
+        match slots.window_type {
+            None if i < MAIN_START => player(MAIN_START..INVENTORY_SIZE, false),
+            None => {
+                let mut targets: Vec<_> = item.item_type().and_then(ItemType::armor_type).map(|v| WindowSlot::Player(ARMOR_START + v)).into_iter().collect();
+                targets.extend(other_part(i));
+                targets
+            }
+            Some(WindowType::Chest | WindowType::EnderChest) => (0..size).map(WindowSlot::Container).collect(),
+            Some(WindowType::Furnace) => {
+                if furnace::smelting_result(item).is_some() {
+                    vec![WindowSlot::Container(furnace::INPUT_SLOT)]
+                } else if furnace::burn_time(item).is_some() {
+                    vec![WindowSlot::Container(furnace::FUEL_SLOT)]
+                } else {
+                    other_part(i)
+                }
+            }
+            Some(WindowType::EnchantingTable) if enchanting::is_lapis(item) => vec![WindowSlot::Container(enchanting::LAPIS_SLOT)],
+            Some(WindowType::EnchantingTable) => vec![WindowSlot::Container(enchanting::ITEM_SLOT)],
+            Some(WindowType::Anvil) => other_part(i)
+        }
+    }
+
+    /// Swaps the item in the slot with the one in a hotbar slot, like when pressing a number key while hovering over a slot
+    fn swap_with_hotbar(&self, window_id: u8, slot: i16, hotbar: usize, player: &mut Inventory) -> Click {
+        let window_slot = match self.resolve(window_id, slot) {
+            Some(v) => v,
+            None => return Click::Rejected
+        };
+        let hotbar_slot = WindowSlot::Player(HOTBAR_START + hotbar);
+        if window_slot == hotbar_slot {
+            return Click::Done;
+        }
+
+        let mut slots = self.slots(window_id, player);
+        let item = slots.get(window_slot).cloned();
+        let held = slots.get(hotbar_slot).cloned();
+        match held {
+            // The item from the hotbar can't go in the slot, it moves to the first empty slot of the inventory instead
+            Some(held) if !slots.accepts(window_slot, &held) || held.count > slots.limit(window_slot) => {
+                if item.is_none() {
+                    return Click::Rejected;
+                }
+
+                let empty = (HOTBAR_START..INVENTORY_SIZE).chain(MAIN_START..HOTBAR_START)
+                    .find(|&i| i != HOTBAR_START + hotbar && slots.player.get_slot(i).is_none());
+                let empty = match empty {
+                    Some(v) => v,
+                    None => return Click::Rejected
+                };
+                slots.set(WindowSlot::Player(empty), Some(held));
+                slots.set(hotbar_slot, item);
+                slots.set(window_slot, None);
+            }
+            held => {
+                slots.set(hotbar_slot, item);
+                slots.set(window_slot, held);
+            }
+        }
+
+        Click::Done
+    }
+
+    /// Locks the inventories shown by the window
+    fn slots<'a>(&'a self, window_id: u8, player: &'a mut Inventory) -> Slots<'a> {
+        let window = self.open.as_ref().filter(|v| window_id != PLAYER_WINDOW_ID && v.id == window_id);
+        Slots {
+            window_type: window.map(|v| v.window_type),
+            container: window.map(|v| v.inventory.lock()),
+            player
+        }
+    }
+
+    /// Maps a slot of the window to the inventory it belongs to
+    fn resolve(&self, window_id: u8, slot: i16) -> Option<WindowSlot> {
+        let slot = usize::try_from(slot).ok()?;
+        if window_id == PLAYER_WINDOW_ID {
+            return (slot < INVENTORY_SIZE).then_some(WindowSlot::Player(slot));
+        }
+
+        let window = self.open.as_ref().filter(|v| v.id == window_id)?;
+        let size = window.inventory.lock().size();
+        if slot < size {
+            Some(WindowSlot::Container(slot))
+        } else if slot < size + INVENTORY_SIZE - MAIN_START {
+            Some(WindowSlot::Player(slot - size + MAIN_START))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the new contents of the slot and the cursor after clicking the slot
+    fn click_slot(item: Option<ItemStack>, cursor: Option<ItemStack>, right: bool) -> (Option<ItemStack>, Option<ItemStack>) {
+        match (item, cursor) {
+            // Right click picks up half of the stack, rounded up
+            (Some(mut item), None) if right => {
+                let mut taken = item.clone();
+                taken.count = (item.count + 1) / 2;
+                item.count -= taken.count;
+                (Some(item), Some(taken))
+            }
+            // Right click puts down a single item
+            (None, Some(mut cursor)) if right => {
+                let mut placed = cursor.clone();
+                placed.count = 1;
+                cursor.count -= 1;
+                (Some(placed), Some(cursor).filter(|v| v.count > 0))
+            }
+            (Some(mut item), Some(mut cursor)) if Self::stacks_with(&item, &cursor) => {
+                let space = item.max_stack_size().saturating_sub(item.count).max(0);
+                let moved = if right { 1.min(space) } else { cursor.count.min(space) };
+                item.count += moved;
+                cursor.count -= moved;
+                (Some(item), Some(cursor).filter(|v| v.count > 0))
+            }
+            (item, cursor) => (cursor, item)
+        }
+    }
+
+    fn stacks_with(a: &ItemStack, b: &ItemStack) -> bool {
+        a.id == b.id && a.damage == b.damage && a.nbt == b.nbt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockType;
+
+    #[test]
+    fn window_ids_wrap_around() {
+        let mut windows = WindowManager::new();
+        let inventory = SharedInventory::new(CHEST_SIZE);
+        assert_eq!(windows.open(WindowType::Chest, inventory.clone(), None).id(), 1);
+        for _ in 1..MAX_WINDOW_ID - 1 {
+            windows.open(WindowType::Chest, inventory.clone(), None);
+        }
+
+        assert_eq!(windows.open(WindowType::EnderChest, inventory.clone(), None).id(), MAX_WINDOW_ID);
+        assert_eq!(windows.open(WindowType::Chest, inventory, None).id(), 1);
+        // Closing an old window doesn't close the one that's open now
+        assert!(!windows.close(MAX_WINDOW_ID));
+        assert!(windows.close(1));
+        assert!(windows.open_window().is_none());
+    }
+
+    #[test]
+    fn close_at_block() {
+        let mut windows = WindowManager::new();
+        let pos = Coord::new(1, 16, 1);
+        windows.open(WindowType::Chest, SharedInventory::new(CHEST_SIZE), Some(pos));
+        assert_eq!(windows.close_at(Coord::new(2, 16, 1)), None);
+        assert_eq!(windows.close_at(pos), Some(1));
+        assert!(windows.open_window().is_none());
+    }
+
+    #[test]
+    fn move_items_into_chest() {
+        let mut windows = WindowManager::new();
+        let chest = SharedInventory::new(CHEST_SIZE);
+        let mut player = Inventory::new();
+        player.set_slot(MAIN_START, Some(ItemStack::of(ItemType::Apple, 5)));
+        let id = windows.open(WindowType::Chest, chest.clone(), None).id();
+
+        // The first slot of the main inventory follows right after the chest
+        assert_eq!(windows.click(id, CHEST_SIZE as i16, 0, 0, &mut player), Click::Done);
+        assert_eq!(windows.cursor(), Some(&ItemStack::of(ItemType::Apple, 5)));
+        assert_eq!(windows.click(id, 3, 1, 0, &mut player), Click::Done);
+        assert_eq!(windows.click(id, 4, 0, 0, &mut player), Click::Done);
+
+        assert_eq!(windows.cursor(), None);
+        assert_eq!(player.get_slot(MAIN_START), None);
+        assert_eq!(chest.lock().get_slot(3), Some(&ItemStack::of(ItemType::Apple, 1)));
+        assert_eq!(chest.lock().get_slot(4), Some(&ItemStack::of(ItemType::Apple, 4)));
+        assert_eq!(windows.click(id + 1, 4, 0, 0, &mut player), Click::Rejected);
+
+        // Shift clicking moves the stack back, filling the hotbar from the end
+        assert_eq!(windows.click(id, 4, 0, 1, &mut player), Click::Done);
+        assert_eq!(chest.lock().get_slot(4), None);
+        assert_eq!(player.get_slot(INVENTORY_SIZE - 1), Some(&ItemStack::of(ItemType::Apple, 4)));
+        // and into the chest again, onto the stack already in it
+        assert_eq!(windows.click(id, CHEST_SIZE as i16 + 35, 0, 1, &mut player), Click::Done);
+        assert_eq!(chest.lock().get_slot(3), Some(&ItemStack::of(ItemType::Apple, 5)));
+        assert_eq!(chest.lock().get_slot(0), None);
+    }
+
+    #[test]
+    fn shift_click_armor() {
+        let mut windows = WindowManager::new();
+        let mut player = Inventory::new();
+        player.set_slot(MAIN_START, Some(ItemStack::of(ItemType::IronHelmet, 1)));
+        player.set_slot(MAIN_START + 1, Some(ItemStack::of(ItemType::IronHelmet, 1)));
+        player.set_slot(HOTBAR_START, Some(ItemStack::of(ItemType::DiamondBoots, 1)));
+
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16, 0, 1, &mut player), Click::Done);
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, HOTBAR_START as i16, 0, 1, &mut player), Click::Done);
+        assert_eq!(player.get_slot(ARMOR_START), Some(&ItemStack::of(ItemType::IronHelmet, 1)));
+        assert_eq!(player.get_slot(ARMOR_START + 3), Some(&ItemStack::of(ItemType::DiamondBoots, 1)));
+        assert_eq!(player.get_slot(MAIN_START), None);
+        assert_eq!(player.get_slot(HOTBAR_START), None);
+
+        // With the helmet slot taken the second helmet moves to the hotbar
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16 + 1, 0, 1, &mut player), Click::Done);
+        assert_eq!(player.get_slot(HOTBAR_START), Some(&ItemStack::of(ItemType::IronHelmet, 1)));
+        // Taking armor off puts it in the main inventory
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, ARMOR_START as i16, 0, 1, &mut player), Click::Done);
+        assert_eq!(player.get_slot(MAIN_START), Some(&ItemStack::of(ItemType::IronHelmet, 1)));
+        assert_eq!(player.get_slot(ARMOR_START), None);
+
+        // Only armor of the right kind fits in an armor slot
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, HOTBAR_START as i16, 0, 0, &mut player), Click::Done);
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, ARMOR_START as i16 + 1, 0, 0, &mut player), Click::Rejected);
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, ARMOR_START as i16, 0, 0, &mut player), Click::Done);
+        assert_eq!(player.get_slot(ARMOR_START), Some(&ItemStack::of(ItemType::IronHelmet, 1)));
+    }
+
+    #[test]
+    fn shift_click_merges_stacks() {
+        let mut windows = WindowManager::new();
+        let mut player = Inventory::new();
+        player.set_slot(MAIN_START, Some(ItemStack::of(ItemType::Apple, 40)));
+        player.set_slot(HOTBAR_START + 2, Some(ItemStack::of(ItemType::Apple, 50)));
+
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16, 0, 1, &mut player), Click::Done);
+        assert_eq!(player.get_slot(HOTBAR_START + 2), Some(&ItemStack::of(ItemType::Apple, 64)));
+        assert_eq!(player.get_slot(HOTBAR_START), Some(&ItemStack::of(ItemType::Apple, 26)));
+        assert_eq!(player.get_slot(MAIN_START), None);
+    }
+
+    #[test]
+    fn shift_click_into_furnace() {
+        let mut windows = WindowManager::new();
+        let furnace = SharedInventory::new(furnace::FURNACE_SIZE);
+        let mut player = Inventory::new();
+        player.set_slot(MAIN_START, Some(ItemStack::of(ItemType::Beef, 3)));
+        player.set_slot(MAIN_START + 1, Some(ItemStack::of(ItemType::Coal, 2)));
+        player.set_slot(MAIN_START + 2, Some(ItemStack::of(ItemType::Apple, 1)));
+        let id = windows.open(WindowType::Furnace, furnace.clone(), None).id();
+        let slot = |i: usize| (furnace::FURNACE_SIZE + i - MAIN_START) as i16;
+
+        assert_eq!(windows.click(id, slot(MAIN_START), 0, 1, &mut player), Click::Done);
+        assert_eq!(windows.click(id, slot(MAIN_START + 1), 0, 1, &mut player), Click::Done);
+        assert_eq!(furnace.lock().get_slot(furnace::INPUT_SLOT), Some(&ItemStack::of(ItemType::Beef, 3)));
+        assert_eq!(furnace.lock().get_slot(furnace::FUEL_SLOT), Some(&ItemStack::of(ItemType::Coal, 2)));
+        // Items that can't be smelted or burnt move to the hotbar
+        assert_eq!(windows.click(id, slot(MAIN_START + 2), 0, 1, &mut player), Click::Done);
+        assert_eq!(player.get_slot(HOTBAR_START), Some(&ItemStack::of(ItemType::Apple, 1)));
+    }
+
+    #[test]
+    fn take_crafting_result() {
+        let mut windows = WindowManager::new();
+        let recipes = RecipeRegistry::new();
+        let mut player = Inventory::new();
+        let log = ItemStack::new(BlockType::Log as i16, 3, 0);
+        let planks = |count| ItemStack::new(BlockType::Planks as i16, count, 0);
+        player.set_slot(MAIN_START, Some(log.clone()));
+
+        // Put one log into the grid
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16, 0, 0, &mut player), Click::Done);
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, crafting::GRID_START as i16 + 3, 1, 0, &mut player), Click::Done);
+        crafting::update_result(&mut player, &recipes);
+        assert_eq!(player.get_slot(crafting::RESULT_SLOT), Some(&planks(4)));
+        // Nothing can be put into the result slot
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, crafting::RESULT_SLOT as i16, 0, 0, &mut player), Click::Rejected);
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16, 0, 0, &mut player), Click::Done);
+
+        assert_eq!(windows.take_crafting_result(0, 0, &mut player, &recipes), Click::Done);
+        assert_eq!(windows.cursor(), Some(&planks(4)));
+        assert_eq!(player.get_slot(crafting::GRID_START + 3), None);
+        assert_eq!(player.get_slot(crafting::RESULT_SLOT), None);
+
+        // Shift clicking crafts until the logs run out
+        player.set_slot(crafting::GRID_START, Some(log));
+        crafting::update_result(&mut player, &recipes);
+        assert_eq!(windows.take_crafting_result(0, 1, &mut player, &recipes), Click::Done);
+        assert_eq!(player.get_slot(INVENTORY_SIZE - 1), Some(&planks(12)));
+        assert_eq!(player.get_slot(crafting::GRID_START), None);
+        assert_eq!(player.get_slot(crafting::RESULT_SLOT), None);
+        assert_eq!(windows.take_crafting_result(0, 2, &mut player, &recipes), Click::Rejected);
+    }
+
+    #[test]
+    fn number_keys() {
+        let mut windows = WindowManager::new();
+        let mut player = Inventory::new();
+        player.set_slot(MAIN_START, Some(ItemStack::of(ItemType::Apple, 5)));
+        player.set_slot(HOTBAR_START + 3, Some(ItemStack::of(ItemType::DiamondBoots, 1)));
+
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16, 3, 2, &mut player), Click::Done);
+        assert_eq!(player.get_slot(MAIN_START), Some(&ItemStack::of(ItemType::DiamondBoots, 1)));
+        assert_eq!(player.get_slot(HOTBAR_START + 3), Some(&ItemStack::of(ItemType::Apple, 5)));
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16, 9, 2, &mut player), Click::Rejected);
+
+        // Apples don't go on feet, the boots do
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, ARMOR_START as i16 + 3, 3, 2, &mut player), Click::Rejected);
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, MAIN_START as i16, 0, 2, &mut player), Click::Done);
+        assert_eq!(windows.click(PLAYER_WINDOW_ID, ARMOR_START as i16 + 3, 0, 2, &mut player), Click::Done);
+        assert_eq!(player.get_slot(ARMOR_START + 3), Some(&ItemStack::of(ItemType::DiamondBoots, 1)));
+        assert_eq!(player.get_slot(HOTBAR_START), None);
+
+        // Taking from the furnace output moves the held item out of the way
+        let furnace = SharedInventory::new(furnace::FURNACE_SIZE);
+        furnace.lock().set_slot(furnace::OUTPUT_SLOT, Some(ItemStack::of(ItemType::CookedBeef, 2)));
+        let id = windows.open(WindowType::Furnace, furnace.clone(), None).id();
+        assert_eq!(windows.click(id, furnace::OUTPUT_SLOT as i16, 3, 2, &mut player), Click::Done);
+        assert_eq!(furnace.lock().get_slot(furnace::OUTPUT_SLOT), None);
+        assert_eq!(player.get_slot(HOTBAR_START + 3), Some(&ItemStack::of(ItemType::CookedBeef, 2)));
+        assert_eq!(player.get_slot(HOTBAR_START), Some(&ItemStack::of(ItemType::Apple, 5)));
+    }
+
+    #[test]
+    fn furnace_output_is_take_only() {
+        let mut windows = WindowManager::new();
+        let furnace = SharedInventory::new(furnace::FURNACE_SIZE);
+        furnace.lock().set_slot(furnace::OUTPUT_SLOT, Some(ItemStack::of(ItemType::CookedBeef, 2)));
+        let mut player = Inventory::new();
+        let id = windows.open(WindowType::Furnace, furnace.clone(), None).id();
+
+        assert_eq!(windows.click(id, furnace::OUTPUT_SLOT as i16, 0, 0, &mut player), Click::Done);
+        assert_eq!(windows.click(id, furnace::OUTPUT_SLOT as i16, 0, 0, &mut player), Click::Rejected);
+        assert_eq!(windows.click(id, furnace::INPUT_SLOT as i16, 0, 0, &mut player), Click::Done);
+        assert_eq!(furnace.lock().get_slot(furnace::INPUT_SLOT), Some(&ItemStack::of(ItemType::CookedBeef, 2)));
+    }
+
+    #[test]
+    fn only_changed_properties() {
+        let mut windows = WindowManager::new();
+        let items = SharedInventory::new(furnace::FURNACE_SIZE);
+        items.lock().set_slot(furnace::INPUT_SLOT, Some(ItemStack::of(ItemType::Beef, 1)));
+        items.lock().set_slot(furnace::FUEL_SLOT, Some(ItemStack::of(ItemType::Coal, 1)));
+        let mut progress = furnace::FurnaceProgress::default();
+        assert!(windows.changed_properties(&progress).is_empty());
+
+        // A new window gets all properties
+        windows.open(WindowType::Furnace, items.clone(), None);
+        assert_eq!(windows.changed_properties(&progress), [(0, 0), (1, 0), (2, 0), (3, furnace::SMELT_TIME)]);
+        assert!(windows.changed_properties(&progress).is_empty());
+
+        // Lighting the furnace sets the fuel time, after that only the flame and arrow move
+        furnace::tick(&mut progress, &mut items.lock());
+        assert_eq!(windows.changed_properties(&progress), [(0, 1600), (1, 1600), (2, 1)]);
+        furnace::tick(&mut progress, &mut items.lock());
+        assert_eq!(windows.changed_properties(&progress), [(0, 1599), (2, 2)]);
+
+        // Reopening starts over
+        windows.open(WindowType::Furnace, items, None);
+        assert_eq!(windows.changed_properties(&progress).len(), 4);
+    }
+}
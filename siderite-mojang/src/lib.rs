@@ -18,6 +18,12 @@ impl MojangAuthenticator {
     }
 }
 
+impl Default for MojangAuthenticator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl Authenticator for MojangAuthenticator {
     async fn authenticate(&self, info: AuthInfo) -> Result {
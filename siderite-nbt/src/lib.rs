@@ -0,0 +1,478 @@
+#![forbid(unsafe_code)]
+
+use std::io::{Error, ErrorKind, Read, Result, Take, Write};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use mcrw::{MCReadExt, MCWriteExt};
+
+pub mod snbt;
+
+/// Maximum nesting depth of lists and compounds accepted when reading
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    End,
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>)
+}
+
+impl Tag {
+    /// Returns the type id of the tag
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::End => 0,
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11
+        }
+    }
+
+    pub fn as_byte(&self) -> Option<i8> {
+        match self {
+            Tag::Byte(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    pub fn as_short(&self) -> Option<i16> {
+        match self {
+            Tag::Short(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Tag::Int(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    pub fn as_long(&self) -> Option<i64> {
+        match self {
+            Tag::Long(v) => Some(*v),
+            _ => None
+        }
+    }
+
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match self {
+            Tag::ByteArray(v) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(v) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(v) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(v) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn as_compound_mut(&mut self) -> Option<&mut Compound> {
+        match self {
+            Tag::Compound(v) => Some(v),
+            _ => None
+        }
+    }
+}
+
+/// A compound tag, the entries keep their insertion order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound {
+    entries: Vec<(String, Tag)>
+}
+
+impl Compound {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.entries.iter().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Tag> {
+        self.entries.iter_mut().find(|(k, _)| k == name).map(|(_, v)| v)
+    }
+
+    /// Inserts the tag, replacing the existing tag with the same name
+    pub fn insert(&mut self, name: &str, tag: Tag) {
+        match self.get_mut(name) {
+            Some(v) => *v = tag,
+            None => self.entries.push((name.to_owned(), tag))
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tag> {
+        let index = self.entries.iter().position(|(k, _)| k == name)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tag)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+/// Writes a named root tag
+///```
+/// use siderite_nbt::{Compound, Tag};
+///
+/// let mut compound = Compound::new();
+/// compound.insert("name", Tag::String("Bananrama".to_owned()));
+///
+/// let mut buf = Vec::new();
+/// siderite_nbt::write(&mut buf, "hello world", &Tag::Compound(compound.clone())).unwrap();
+/// let (name, tag) = siderite_nbt::read(buf.as_slice()).unwrap();
+/// assert_eq!(name, "hello world");
+/// assert_eq!(tag, Tag::Compound(compound));
+///```
+pub fn write<W: Write>(mut w: W, name: &str, tag: &Tag) -> Result<()> {
+    w.write_ubyte(tag.id())?;
+    if *tag == Tag::End {
+        return Ok(());
+    }
+
+    write_str(&mut w, name)?;
+    write_payload(&mut w, tag)
+}
+
+/// Writes a named root tag, compressed with gzip
+pub fn write_gzip<W: Write>(w: W, name: &str, tag: &Tag) -> Result<()> {
+    let mut encoder = GzEncoder::new(w, Compression::default());
+    write(&mut encoder, name, tag)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Reads a named root tag
+pub fn read<R: Read>(r: R) -> Result<(String, Tag)> {
+    read_limited(r, u64::MAX)
+}
+
+/// Reads a named root tag from at most `limit` bytes,
+/// lengths that can't fit in the bytes left are rejected before reading the elements
+pub fn read_limited<R: Read>(r: R, limit: u64) -> Result<(String, Tag)> {
+    let mut r = r.take(limit);
+    let id = r.read_ubyte()?;
+    if id == 0 {
+        return Ok((String::new(), Tag::End));
+    }
+
+    let name = read_str(&mut r)?;
+    let tag = read_payload(&mut r, id, 0)?;
+    Ok((name, tag))
+}
+
+/// Reads a named root tag compressed with gzip
+pub fn read_gzip<R: Read>(r: R) -> Result<(String, Tag)> {
+    read(GzDecoder::new(r))
+}
+
+fn write_str<W: Write>(w: &mut W, value: &str) -> Result<()> {
+    // REVIEW: Java uses modified UTF-8, which only differs for '\0' and supplementary characters
+    let bytes = value.as_bytes();
+    if bytes.len() > u16::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, "String is too long"));
+    }
+
+    w.write_ushort(bytes.len() as u16)?;
+    w.write_all(bytes)
+}
+
+fn read_str<R: Read>(r: &mut R) -> Result<String> {
+    let len = r.read_ushort()? as usize;
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+fn write_payload<W: Write>(w: &mut W, tag: &Tag) -> Result<()> {
+    match tag {
+        Tag::End => Ok(()),
+        Tag::Byte(v) => w.write_byte(*v),
+        Tag::Short(v) => w.write_short(*v),
+        Tag::Int(v) => w.write_int(*v),
+        Tag::Long(v) => w.write_long(*v),
+        Tag::Float(v) => w.write_float(*v),
+        Tag::Double(v) => w.write_double(*v),
+        Tag::ByteArray(v) => {
+            w.write_int(v.len() as i32)?;
+            for b in v {
+                w.write_byte(*b)?;
+            }
+
+            Ok(())
+        }
+        Tag::String(v) => write_str(w, v),
+        Tag::List(v) => {
+            let id = v.first().map_or(0, |x| x.id());
+            w.write_ubyte(id)?;
+            w.write_int(v.len() as i32)?;
+            for t in v {
+                if t.id() != id {
+                    return Err(Error::new(ErrorKind::InvalidInput, "List elements have different types"));
+                }
+
+                write_payload(w, t)?;
+            }
+
+            Ok(())
+        }
+        Tag::Compound(v) => {
+            for (name, t) in v.iter() {
+                if *t == Tag::End {
+                    continue;
+                }
+
+                w.write_ubyte(t.id())?;
+                write_str(w, name)?;
+                write_payload(w, t)?;
+            }
+
+            w.write_ubyte(0) // TAG_End
+        }
+        Tag::IntArray(v) => {
+            w.write_int(v.len() as i32)?;
+            for i in v {
+                w.write_int(*i)?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Returns the fewest bytes a payload of the type takes
+fn min_payload_size(id: u8) -> u64 {
+    match id {
+        2 | 8 => 2,
+        3 | 5 | 7 | 11 => 4,
+        4 | 6 => 8,
+        9 => 5,
+        _ => 1
+    }
+}
+
+/// Reads the length of an array or list whose elements take at least `elem_size` bytes each
+fn read_len<R: Read>(r: &mut Take<R>, elem_size: u64) -> Result<usize> {
+    let len = r.read_int()?;
+    if len < 0 {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Negative length: {}", len)));
+    }
+
+    if len as u64 * elem_size > r.limit() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Length doesn't fit in the input: {}", len)));
+    }
+
+    Ok(len as usize)
+}
+
+fn read_payload<R: Read>(r: &mut Take<R>, id: u8, depth: usize) -> Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(Error::new(ErrorKind::InvalidData, "Tag is nested too deep"));
+    }
+
+    Ok(match id {
+        0 => Tag::End,
+        1 => Tag::Byte(r.read_byte()?),
+        2 => Tag::Short(r.read_short()?),
+        3 => Tag::Int(r.read_int()?),
+        4 => Tag::Long(r.read_long()?),
+        5 => Tag::Float(r.read_float()?),
+        6 => Tag::Double(r.read_double()?),
+        7 => {
+            let len = read_len(r, 1)?;
+            // Don't trust the length for preallocation
+            let mut v = Vec::new();
+            for _ in 0..len {
+                v.push(r.read_byte()?);
+            }
+
+            Tag::ByteArray(v)
+        }
+        8 => Tag::String(read_str(r)?),
+        9 => {
+            let elem_id = r.read_ubyte()?;
+            let len = read_len(r, min_payload_size(elem_id))?;
+            // Elements of TAG_End take no bytes at all, so only empty lists can have them, like vanilla
+            if elem_id == 0 && len > 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "List of TAG_End isn't empty"));
+            }
+
+            let mut v = Vec::new();
+            for _ in 0..len {
+                v.push(read_payload(r, elem_id, depth + 1)?);
+            }
+
+            Tag::List(v)
+        }
+        10 => {
+            let mut compound = Compound::new();
+            loop {
+                let elem_id = r.read_ubyte()?;
+                if elem_id == 0 {
+                    break;
+                }
+
+                let name = read_str(r)?;
+                let tag = read_payload(r, elem_id, depth + 1)?;
+                compound.insert(&name, tag);
+            }
+
+            Tag::Compound(compound)
+        }
+        11 => {
+            let len = read_len(r, 4)?;
+            let mut v = Vec::new();
+            for _ in 0..len {
+                v.push(r.read_int()?);
+            }
+
+            Tag::IntArray(v)
+        }
+        _ => return Err(Error::new(ErrorKind::InvalidData, format!("Unknown tag type: {}", id)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_compound() -> Tag {
+        let mut inner = Compound::new();
+        inner.insert("Name", Tag::String("Bond_009".to_owned()));
+
+        let mut compound = Compound::new();
+        compound.insert("byte", Tag::Byte(-1));
+        compound.insert("short", Tag::Short(300));
+        compound.insert("int", Tag::Int(-70000));
+        compound.insert("long", Tag::Long(1 << 40));
+        compound.insert("float", Tag::Float(0.5));
+        compound.insert("double", Tag::Double(-0.25));
+        compound.insert("bytes", Tag::ByteArray(vec![1, 2, 3]));
+        compound.insert("list", Tag::List(vec![Tag::Short(1), Tag::Short(2)]));
+        compound.insert("empty", Tag::List(Vec::new()));
+        compound.insert("ints", Tag::IntArray(vec![i32::MIN, 0, i32::MAX]));
+        compound.insert("Owner", Tag::Compound(inner));
+        Tag::Compound(compound)
+    }
+
+    #[test]
+    fn round_trip() {
+        let tag = test_compound();
+        let mut buf = Vec::new();
+        write(&mut buf, "root", &tag).unwrap();
+        assert_eq!(read(buf.as_slice()).unwrap(), ("root".to_owned(), tag));
+    }
+
+    #[test]
+    fn round_trip_gzip() {
+        let tag = test_compound();
+        let mut buf = Vec::new();
+        write_gzip(&mut buf, "", &tag).unwrap();
+        assert_eq!(read_gzip(buf.as_slice()).unwrap(), (String::new(), tag));
+    }
+
+    #[test]
+    fn write_matches_spec() {
+        let mut compound = Compound::new();
+        compound.insert("name", Tag::String("Bananrama".to_owned()));
+        let mut buf = Vec::new();
+        write(&mut buf, "hello world", &Tag::Compound(compound)).unwrap();
+
+        let mut expected = vec![0x0a, 0x00, 0x0b];
+        expected.extend_from_slice(b"hello world");
+        expected.extend_from_slice(&[0x08, 0x00, 0x04]);
+        expected.extend_from_slice(b"name");
+        expected.extend_from_slice(&[0x00, 0x09]);
+        expected.extend_from_slice(b"Bananrama");
+        expected.push(0x00);
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn write_end() {
+        let mut buf = Vec::new();
+        write(&mut buf, "ignored", &Tag::End).unwrap();
+        assert_eq!(buf, [0x00]);
+    }
+
+    #[test]
+    fn insert_replaces() {
+        let mut compound = Compound::new();
+        compound.insert("a", Tag::Int(1));
+        compound.insert("a", Tag::Int(2));
+        assert_eq!(compound.len(), 1);
+        assert_eq!(compound.get("a"), Some(&Tag::Int(2)));
+    }
+
+    #[test]
+    fn read_rejects_unknown_type() {
+        let buf = [0x0c, 0x00, 0x00];
+        assert!(read(buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn read_rejects_hostile_lists() {
+        // A list of i32::MAX TAG_End in an unnamed compound, as a client could send it
+        let buf = [0x0a, 0x00, 0x00, 0x09, 0x00, 0x01, b'a', 0x00, 0x7f, 0xff, 0xff, 0xff, 0x00];
+        assert!(read(buf.as_slice()).is_err());
+        assert!(read_limited(buf.as_slice(), buf.len() as u64).is_err());
+
+        // Empty lists of TAG_End are what vanilla writes for empty lists
+        let buf = [0x0a, 0x00, 0x00, 0x09, 0x00, 0x01, b'a', 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let (_, tag) = read(buf.as_slice()).unwrap();
+        assert_eq!(tag.as_compound().unwrap().get("a"), Some(&Tag::List(Vec::new())));
+
+        // More compounds than there are bytes left
+        let buf = [0x0a, 0x00, 0x00, 0x09, 0x00, 0x01, b'a', 0x0a, 0x7f, 0xff, 0xff, 0xff, 0x00];
+        assert!(read_limited(buf.as_slice(), buf.len() as u64).is_err());
+        let buf = [0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01];
+        assert!(read_limited(buf.as_slice(), buf.len() as u64).is_err());
+    }
+}
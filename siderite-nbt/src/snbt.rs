@@ -0,0 +1,238 @@
+//! Stringified NBT, the text form of tags used by commands like `{Health:10f,Motion:[0.0d,0.5d,0.0d]}`
+
+use std::io::{Error, ErrorKind, Result};
+
+use crate::{Compound, MAX_DEPTH, Tag};
+
+/// Parses a tag, numbers without a type suffix are ints or doubles and anything else unquoted is a string
+pub fn parse(s: &str) -> Result<Tag> {
+    let mut parser = Parser { s, pos: 0 };
+    let tag = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != s.len() {
+        return Err(invalid(&format!("Unexpected data at {}", parser.pos)));
+    }
+
+    Ok(tag)
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|v| v.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Skips the char if it's next, returns true if it was skipped
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            return true;
+        }
+
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if !self.eat(c) {
+            return Err(invalid(&format!("Expected '{}' at {}", c, self.pos)));
+        }
+
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("Tags are nested too deep"));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.compound(depth),
+            Some('[') => self.list(depth),
+            Some('"' | '\'') => self.quoted().map(Tag::String),
+            Some(_) => self.unquoted().map(primitive),
+            None => Err(invalid("Expected a value"))
+        }
+    }
+
+    fn compound(&mut self, depth: usize) -> Result<Tag> {
+        self.expect('{')?;
+        let mut compound = Compound::new();
+        if self.eat('}') {
+            return Ok(Tag::Compound(compound));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let name = match self.peek() {
+                Some('"' | '\'') => self.quoted()?,
+                _ => self.unquoted()?.to_owned()
+            };
+            self.expect(':')?;
+            let value = self.value(depth + 1)?;
+            compound.insert(&name, value);
+
+            if self.eat('}') {
+                return Ok(Tag::Compound(compound));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    /// Parses a list, or a byte or int array when it starts with `B;` or `I;`
+    fn list(&mut self, depth: usize) -> Result<Tag> {
+        self.expect('[')?;
+        let array_type = ["B;", "I;"].into_iter().find(|v| self.s[self.pos..].starts_with(v));
+        if array_type.is_some() {
+            self.pos += 2;
+        }
+
+        let mut values = Vec::new();
+        if !self.eat(']') {
+            loop {
+                values.push(self.value(depth + 1)?);
+                if self.eat(']') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+
+        match array_type {
+            Some("B;") => values.iter()
+                .map(|v| v.as_byte().ok_or_else(|| invalid("Byte arrays can only hold bytes")))
+                .collect::<Result<_>>()
+                .map(Tag::ByteArray),
+            Some(_) => values.iter()
+                .map(|v| v.as_int().ok_or_else(|| invalid("Int arrays can only hold ints")))
+                .collect::<Result<_>>()
+                .map(Tag::IntArray),
+            None if values.windows(2).any(|v| v[0].id() != v[1].id()) => Err(invalid("The values of a list must have the same type")),
+            None => Ok(Tag::List(values))
+        }
+    }
+
+    /// Parses a string in single or double quotes, a backslash escapes the next char
+    fn quoted(&mut self) -> Result<String> {
+        let quote = self.peek().unwrap_or('"');
+        self.pos += quote.len_utf8();
+
+        let mut value = String::new();
+        let mut chars = self.s[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            if c == quote {
+                self.pos += i + c.len_utf8();
+                return Ok(value);
+            }
+
+            if c == '\\' {
+                match chars.next() {
+                    Some((_, v)) => value.push(v),
+                    None => break
+                }
+            } else {
+                value.push(c);
+            }
+        }
+
+        Err(invalid("Unterminated string"))
+    }
+
+    /// Parses a name or value made of letters, digits and `_-.+`
+    fn unquoted(&mut self) -> Result<&'a str> {
+        let rest = &self.s[self.pos..];
+        let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || "_-.+".contains(c))).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(invalid(&format!("Unexpected character at {}", self.pos)));
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+}
+
+/// Returns the number the unquoted value is, or a string if it isn't a number.
+/// The suffixes b, s, L, f and d set the type of numbers, true and false are bytes.
+fn primitive(value: &str) -> Tag {
+    let (number, suffix) = value.split_at(value.len() - 1);
+    let typed = match suffix {
+        "b" | "B" => number.parse().ok().map(Tag::Byte),
+        "s" | "S" => number.parse().ok().map(Tag::Short),
+        "l" | "L" => number.parse().ok().map(Tag::Long),
+        "f" | "F" => number.parse().ok().map(Tag::Float),
+        "d" | "D" => number.parse().ok().map(Tag::Double),
+        _ => None
+    };
+
+    typed.or_else(|| value.parse().ok().map(Tag::Int))
+        .or_else(|| value.contains('.').then(|| value.parse().ok().map(Tag::Double)).flatten())
+        .unwrap_or_else(|| match value {
+            "true" => Tag::Byte(1),
+            "false" => Tag::Byte(0),
+            _ => Tag::String(value.to_owned())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_compound() {
+        let tag = parse(r#"{Health: 10.5f, Fuse:20b, id:"minecraft:tnt", 'Custom Name':'It\'s TNT',
+            Motion:[0.0d,0.5,-1d], Tags:[], Nested:{Long:9000000000L,Short:-3s,Flag:true}}"#).unwrap();
+        let compound = tag.as_compound().unwrap();
+        assert_eq!(compound.get("Health"), Some(&Tag::Float(10.5)));
+        assert_eq!(compound.get("Fuse"), Some(&Tag::Byte(20)));
+        assert_eq!(compound.get("id").and_then(Tag::as_str), Some("minecraft:tnt"));
+        assert_eq!(compound.get("Custom Name").and_then(Tag::as_str), Some("It's TNT"));
+        assert_eq!(compound.get("Motion"), Some(&Tag::List(vec![Tag::Double(0.0), Tag::Double(0.5), Tag::Double(-1.0)])));
+        assert_eq!(compound.get("Tags"), Some(&Tag::List(Vec::new())));
+
+        let nested = compound.get("Nested").and_then(Tag::as_compound).unwrap();
+        assert_eq!(nested.get("Long"), Some(&Tag::Long(9_000_000_000)));
+        assert_eq!(nested.get("Short"), Some(&Tag::Short(-3)));
+        assert_eq!(nested.get("Flag"), Some(&Tag::Byte(1)));
+    }
+
+    #[test]
+    fn parse_primitives() {
+        assert_eq!(parse("42").unwrap(), Tag::Int(42));
+        assert_eq!(parse("4.5").unwrap(), Tag::Double(4.5));
+        assert_eq!(parse("Zombie").unwrap(), Tag::String("Zombie".to_owned()));
+        // Out of range for a byte, so it's a string like in vanilla
+        assert_eq!(parse("300b").unwrap(), Tag::String("300b".to_owned()));
+    }
+
+    #[test]
+    fn parse_arrays() {
+        assert_eq!(parse("[B;1b,-2b]").unwrap(), Tag::ByteArray(vec![1, -2]));
+        assert_eq!(parse("[I; 1, 2, 3]").unwrap(), Tag::IntArray(vec![1, 2, 3]));
+        assert!(parse("[I;1b]").is_err());
+    }
+
+    #[test]
+    fn invalid_input() {
+        for s in ["", "{", "{Health}", "{Health:1f", "[1,2b]", "{a:1}}", "\"open", "{a:1,}", "{a b:1}"] {
+            assert_eq!(parse(s).unwrap_err().kind(), ErrorKind::InvalidData, "{}", s);
+        }
+
+        let deep = "[".repeat(MAX_DEPTH + 2) + &"]".repeat(MAX_DEPTH + 2);
+        assert!(parse(&deep).is_err());
+    }
+}
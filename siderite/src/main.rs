@@ -6,6 +6,7 @@ use std::error::Error;
 use std::fs;
 use std::io::ErrorKind;
 use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::Path;
 use std::result::Result;
 use std::sync::Arc;
 
@@ -15,12 +16,15 @@ use tokio::task;
 
 use siderite_core::auth::*;
 use siderite_core::server::*;
+use siderite_core::skins::LocalSkins;
 
 use properties::ServerProperties;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const PROPERTIES_FILENAME: &str = "server.properties";
 const FAVICON_FILENAME: &str = "favicon.png";
+const OPS_FILENAME: &str = "ops.json";
+const SKINS_FILENAME: &str = "skins.json";
 
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn Error>> {
@@ -67,7 +71,19 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
         favicon,
         tx);
 
-    server.load_worlds();
+    if let Err(e) = server.load_ops(Path::new(OPS_FILENAME)) {
+        error!("Failed to load {}\n{}", OPS_FILENAME, e);
+    }
+
+    // Offline players don't get their skins from the authenticator
+    if !online {
+        match LocalSkins::load(Path::new(SKINS_FILENAME)) {
+            Ok(v) => server.set_skin_fetcher(Box::new(v)),
+            Err(e) => error!("Failed to load {}\n{}", SKINS_FILENAME, e)
+        }
+    }
+
+    server.load_worlds()?;
 
     let server = Arc::new(server);
     let server_ref = server.clone();
@@ -1,10 +1,17 @@
 use std::net::IpAddr;
 use std::str::FromStr;
+use std::time::Duration;
 
+use siderite_core::TPS;
 use siderite_core::entities::player::GameMode;
 use siderite_core::server::ServerConfig;
 use siderite_core::storage::world::Difficulty;
 
+/// Shortest keep alive interval in milliseconds, 0 would flood the clients
+const MIN_KEEP_ALIVE_INTERVAL: u64 = 1;
+/// Longest keep alive interval in milliseconds, the clients time out after 20 seconds without one
+const MAX_KEEP_ALIVE_INTERVAL: u64 = 15000;
+
 #[derive(Debug, PartialEq)]
 pub struct ServerProperties {
     pub view_distance: u8,
@@ -40,10 +47,24 @@ pub struct ServerProperties {
     pub max_players: i32,
     pub use_native_transport: bool,
     pub spawn_protection: i32,
+    pub spawn_chunks_radius: i32,
+    pub strict_protocol: bool,
+    /// Milliseconds in which an address can only log in `connection_throttle_limit` times, 0 or less turns it off
+    pub connection_throttle: i64,
+    pub connection_throttle_limit: usize,
+    pub strict_save_format: bool,
+    /// Seconds between auto-saves, 0 or less turns auto-saving off
+    pub auto_save_interval: i64,
     pub online_mode: bool,
     pub allow_flight: bool,
     pub resource_pack_hash: Option<String>,
-    pub max_world_size: i64
+    pub max_world_size: i64,
+    /// Also listen for IPv4 clients when server-ip is empty or the unspecified IPv6 address
+    pub dual_stack: bool,
+    /// Milliseconds between the keep alives sent to each client
+    pub keep_alive_interval: u64,
+    /// Max number of bytes read from a connection at once
+    pub network_read_buffer_size: usize
 }
 
 impl Default for ServerProperties {
@@ -82,10 +103,19 @@ impl Default for ServerProperties {
             max_players: 20,
             use_native_transport: true,
             spawn_protection: 16,
+            spawn_chunks_radius: 8,
+            strict_protocol: false,
+            connection_throttle: 4000,
+            connection_throttle_limit: 3,
+            strict_save_format: false,
+            auto_save_interval: 45,
             online_mode: true,
             allow_flight: false,
             resource_pack_hash: None,
-            max_world_size: 29999984
+            max_world_size: 29999984,
+            dual_stack: true,
+            keep_alive_interval: 1000,
+            network_read_buffer_size: 8192
         }
     }
 }
@@ -146,7 +176,7 @@ impl FromStr for ServerProperties {
                 "resource-pack" => parse_optional_str!(value, properties.resource_pack),
                 "player-idle-timeout" => parse!(value, properties.player_idle_timeout),
                 "level-name" => properties.level_name = value.to_owned(),
-                "motd" => properties.motd = value.to_owned(),
+                "motd" => properties.motd = unescape(value),
                 "announce-player-achievements" => parse!(value, properties.announce_player_achievements),
                 "force-gamemode" => parse!(value, properties.force_gamemode),
                 "white-list" => parse!(value, properties.white_list),
@@ -170,10 +200,19 @@ impl FromStr for ServerProperties {
                 "max-tick-time" => parse!(value, properties.max_tick_time),
                 "max-players" => parse!(value, properties.max_players),
                 "use-native-transport" => parse!(value, properties.use_native_transport),
+                "spawn-chunks-radius" => parse!(value, properties.spawn_chunks_radius),
+                "strict-protocol" => parse!(value, properties.strict_protocol),
+                "connection-throttle" => parse!(value, properties.connection_throttle),
+                "connection-throttle-limit" => parse!(value, properties.connection_throttle_limit),
+                "strict-save-format" => parse!(value, properties.strict_save_format),
+                "auto-save-interval" => parse!(value, properties.auto_save_interval),
                 "online-mode" => parse!(value, properties.online_mode),
                 "allow-flight" => parse!(value, properties.allow_flight),
                 "resource-pack-hash" => parse_optional_str!(value, properties.resource_pack_hash),
                 "max-world-size" => parse!(value, properties.max_world_size),
+                "dual-stack" => parse!(value, properties.dual_stack),
+                "keep-alive-interval" => parse!(value, properties.keep_alive_interval),
+                "network-read-buffer-size" => parse!(value, properties.network_read_buffer_size),
                 _ => {}
             }
         }
@@ -182,6 +221,37 @@ impl FromStr for ServerProperties {
     }
 }
 
+/// Resolves the escape sequences Java uses in properties files, like \u00A7 for §
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(v) => unescaped.push(v),
+                    None => {
+                        unescaped.push_str("\\u");
+                        unescaped.push_str(&hex);
+                    }
+                }
+            }
+            Some(v) => unescaped.push(v),
+            None => ()
+        }
+    }
+
+    unescaped
+}
+
 impl From<ServerProperties> for ServerConfig {
     fn from(properties: ServerProperties) -> ServerConfig {
         let compression_threshold = if properties.network_compression_threshold < 0 {
@@ -199,12 +269,27 @@ impl From<ServerProperties> for ServerConfig {
             difficulty: properties.difficulty,
             compression_threshold,
             level_type: properties.level_type,
+            level_seed: properties.level_seed.as_deref().map(parse_seed),
             max_players: properties.max_players,
-            encryption: properties.online_mode
+            encryption: properties.online_mode,
+            spawn_chunks_radius: properties.spawn_chunks_radius.max(0),
+            strict_protocol: properties.strict_protocol,
+            connection_throttle: Duration::from_millis(properties.connection_throttle.max(0) as u64),
+            connection_throttle_limit: properties.connection_throttle_limit,
+            strict_save_format: properties.strict_save_format,
+            auto_save_interval: properties.auto_save_interval.max(0) as u64 * TPS as u64,
+            dual_stack: properties.dual_stack,
+            keep_alive_interval: Duration::from_millis(properties.keep_alive_interval.clamp(MIN_KEEP_ALIVE_INTERVAL, MAX_KEEP_ALIVE_INTERVAL)),
+            read_buffer_size: properties.network_read_buffer_size
         }
     }
 }
 
+/// Like vanilla, a seed that isn't a number uses the Java hash code of the text
+fn parse_seed(seed: &str) -> i64 {
+    seed.parse().unwrap_or_else(|_| seed.encode_utf16().fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32)) as i64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -220,4 +305,59 @@ mod tests {
         let parsed: ServerProperties = "".parse().unwrap();
         assert_eq!(parsed, ServerProperties::default());
     }
+
+    #[test]
+    fn parse_escaped_motd() {
+        let parsed: ServerProperties = "motd=\\u00A7aHello\\nworld \\: \\\\".parse().unwrap();
+        assert_eq!(parsed.motd, "§aHello\nworld : \\");
+    }
+
+    #[test]
+    fn disable_connection_throttle() {
+        let parsed: ServerProperties = "connection-throttle=-1\nconnection-throttle-limit=5".parse().unwrap();
+        assert_eq!(parsed.connection_throttle_limit, 5);
+        let config = ServerConfig::from(parsed);
+        assert!(config.connection_throttle.is_zero());
+    }
+
+    #[test]
+    fn auto_save_interval_in_ticks() {
+        let parsed: ServerProperties = "auto-save-interval=60".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).auto_save_interval, 1200);
+        let parsed: ServerProperties = "auto-save-interval=-1".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).auto_save_interval, 0);
+    }
+
+    #[test]
+    fn parse_spawn_chunks_radius() {
+        let parsed: ServerProperties = "spawn-chunks-radius=0".parse().unwrap();
+        assert_eq!(parsed.spawn_chunks_radius, 0);
+    }
+
+    #[test]
+    fn disable_dual_stack() {
+        let parsed: ServerProperties = "dual-stack=false".parse().unwrap();
+        assert!(!ServerConfig::from(parsed).dual_stack);
+    }
+
+    #[test]
+    fn clamp_keep_alive_interval() {
+        let parsed: ServerProperties = "keep-alive-interval=500".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).keep_alive_interval, Duration::from_millis(500));
+        let parsed: ServerProperties = "keep-alive-interval=0".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).keep_alive_interval, Duration::from_millis(1));
+        let parsed: ServerProperties = "keep-alive-interval=60000".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).keep_alive_interval, Duration::from_millis(15000));
+    }
+
+    #[test]
+    fn parse_level_seed() {
+        let parsed: ServerProperties = "level-seed=-42".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).level_seed, Some(-42));
+        // "Glacier".hashCode() in Java
+        let parsed: ServerProperties = "level-seed=Glacier".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).level_seed, Some(1_772_835_215));
+        let parsed: ServerProperties = "level-seed=".parse().unwrap();
+        assert_eq!(ServerConfig::from(parsed).level_seed, None);
+    }
 }